@@ -0,0 +1,212 @@
+use base::Status;
+use guid::Guid;
+use runtimeservices::{VariableAttributes, EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS,
+                      EFI_VARIABLE_RUNTIME_ACCESS};
+
+/// Name of the variable holding the A/B state, in the vendor namespace given to `AbBoot::new`.
+pub const AB_STATE_VARIABLE: &str = "AbBootState";
+
+const MAGIC: [u8; 2] = [b'A', b'B'];
+const VERSION: u8 = 1;
+const STATE_SIZE: usize = 8;
+
+/// One of the two image slots of an A/B update scheme.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootSlot {
+    A = 0,
+    B = 1,
+}
+
+impl BootSlot {
+    /// The slot that is not this one.
+    pub fn other(self) -> BootSlot {
+        match self {
+            BootSlot::A => BootSlot::B,
+            BootSlot::B => BootSlot::A,
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<BootSlot> {
+        match v {
+            0 => Some(BootSlot::A),
+            1 => Some(BootSlot::B),
+            _ => None,
+        }
+    }
+}
+
+/// The persistent A/B state, as stored in `AB_STATE_VARIABLE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AbState {
+    /// The slot that should be booted.
+    pub active: BootSlot,
+    /// Boot attempts made on `active` since it was activated, without it being marked
+    /// successful.
+    pub attempts: u8,
+    /// Whether each slot has been marked successful since it was last activated.
+    pub successful: [bool; 2],
+}
+
+impl Default for AbState {
+    fn default() -> AbState {
+        AbState {
+            active: BootSlot::A,
+            attempts: 0,
+            successful: [false; 2],
+        }
+    }
+}
+
+impl AbState {
+    fn to_bytes(self) -> [u8; STATE_SIZE] {
+        [MAGIC[0], MAGIC[1], VERSION, self.active as u8, self.attempts,
+         self.successful[0] as u8, self.successful[1] as u8, 0]
+    }
+
+    fn from_bytes(b: &[u8]) -> Option<AbState> {
+        if b.len() != STATE_SIZE || b[0..2] != MAGIC || b[2] != VERSION {
+            return None;
+        }
+
+        Some(AbState {
+            active: BootSlot::from_u8(b[3])?,
+            attempts: b[4],
+            successful: [b[5] != 0, b[6] != 0],
+        })
+    }
+}
+
+/// The state after counting one boot attempt on `state`: the active slot falls back to the other
+/// one once it has used `max_attempts` boots without being marked successful.
+fn next_state(mut state: AbState, max_attempts: u8) -> AbState {
+    let active = state.active;
+
+    if !state.successful[active as usize] {
+        if state.attempts >= max_attempts {
+            // The active slot never came up; go back to the other one and give it a fresh set
+            // of attempts.
+            state.active = active.other();
+            state.attempts = 0;
+        }
+
+        if !state.successful[state.active as usize] {
+            state.attempts = state.attempts.saturating_add(1);
+        }
+    }
+
+    state
+}
+
+/// Boot-attempt counting and automatic fallback between two image slots, for devices doing
+/// image-based updates.
+///
+/// The loader calls `select` on every boot and boots the returned slot. Once the booted system is
+/// known to be healthy, `mark_boot_successful` is called (by the loader, or by the OS through
+/// the runtime variable). If the active slot fails to be marked successful within
+/// `max_attempts` boots, `select` falls back to the other slot.
+pub struct AbBoot {
+    vendor: &'static Guid,
+    max_attempts: u8,
+}
+
+impl AbBoot {
+    /// Keep the state in `AB_STATE_VARIABLE` under `vendor`, and fall back after `max_attempts`
+    /// unsuccessful boots. A `max_attempts` of 0 is treated as 1, so each slot gets at least one
+    /// boot.
+    pub fn new(vendor: &'static Guid, max_attempts: u8) -> AbBoot {
+        AbBoot { vendor, max_attempts: max_attempts.max(1) }
+    }
+
+    /// Read the current state. A missing or unrecognised variable yields the default state,
+    /// with slot A active.
+    pub fn state(&self) -> Result<AbState, Status> {
+        let mut buf = [0u8; STATE_SIZE];
+        let rs = ::get_system_table().runtime_services();
+
+        match rs.get_variable(AB_STATE_VARIABLE, self.vendor, &mut buf) {
+            Ok((size, _)) => Ok(AbState::from_bytes(&buf[..size]).unwrap_or_default()),
+            Err(Status::NotFound) | Err(Status::BufferTooSmall) => Ok(AbState::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn store(&self, state: AbState) -> Result<(), Status> {
+        let attributes: VariableAttributes = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS
+            | EFI_VARIABLE_RUNTIME_ACCESS;
+
+        ::get_system_table()
+            .runtime_services()
+            .set_variable(AB_STATE_VARIABLE, self.vendor, attributes, &state.to_bytes())
+    }
+
+    /// Count a boot attempt and return the slot to boot. The attempt is recorded before
+    /// returning, so a boot that hangs or crashes still counts.
+    pub fn select(&self) -> Result<BootSlot, Status> {
+        let state = self.state()?;
+        let next = next_state(state, self.max_attempts);
+
+        if next != state {
+            self.store(next)?;
+        }
+
+        Ok(next.active)
+    }
+
+    /// Mark the active slot as successfully booted, stopping the attempt counter.
+    pub fn mark_boot_successful(&self) -> Result<(), Status> {
+        let mut state = self.state()?;
+        state.successful[state.active as usize] = true;
+        state.attempts = 0;
+        self.store(state)
+    }
+
+    /// Make `slot` active after installing a new image into it. The slot has to be marked
+    /// successful again within `max_attempts` boots, or the other slot is booted.
+    pub fn set_active(&self, slot: BootSlot) -> Result<(), Status> {
+        let mut state = self.state()?;
+        state.active = slot;
+        state.attempts = 0;
+        state.successful[slot as usize] = false;
+        self.store(state)
+    }
+}
+
+#[test]
+fn ab_state_bytes() {
+    let state = AbState { active: BootSlot::B, attempts: 3, successful: [true, false] };
+    let bytes = state.to_bytes();
+    assert_eq!(bytes, [b'A', b'B', 1, 1, 3, 1, 0, 0]);
+    assert_eq!(AbState::from_bytes(&bytes), Some(state));
+
+    assert_eq!(AbState::from_bytes(&bytes[..7]), None);
+    assert_eq!(AbState::from_bytes(&[b'A', b'B', 2, 1, 3, 1, 0, 0]), None);
+    assert_eq!(AbState::from_bytes(&[b'A', b'B', 1, 2, 3, 1, 0, 0]), None);
+}
+
+#[test]
+fn ab_next_state() {
+    // First boot: slot A gets its first attempt.
+    let first = next_state(AbState::default(), 3);
+    assert_eq!(first, AbState { active: BootSlot::A, attempts: 1, successful: [false, false] });
+
+    // A slot that is never marked successful falls back after `max_attempts` boots. The other
+    // slot was good, so it isn't counted.
+    let mut state = AbState { active: BootSlot::B, attempts: 0, successful: [true, false] };
+    for attempt in 1..4 {
+        state = next_state(state, 3);
+        assert_eq!((state.active, state.attempts), (BootSlot::B, attempt));
+    }
+    state = next_state(state, 3);
+    assert_eq!(state, AbState { active: BootSlot::A, attempts: 0, successful: [true, false] });
+
+    // A successful slot stays put without counting.
+    assert_eq!(next_state(state, 3), state);
+
+    // With neither slot good, each gets its own set of attempts.
+    let state = AbState { active: BootSlot::A, attempts: 1, successful: [false, false] };
+    assert_eq!(next_state(state, 1), AbState { active: BootSlot::B, attempts: 1, successful: [false, false] });
+
+    // 0 attempts is treated as 1, so the active slot always gets booted once.
+    static VENDOR: Guid = Guid(0, 0, 0, [0; 8]);
+    assert_eq!(AbBoot::new(&VENDOR, 0).max_attempts, 1);
+}
@@ -0,0 +1,198 @@
+use core::slice;
+
+use base::Status;
+use bmp::BMP_HEADER_SIZE;
+use guid::Guid;
+use util::wire;
+
+/// GUID of the ACPI 2.0+ RSDP in the system configuration table
+pub static EFI_ACPI_20_TABLE_GUID: Guid = Guid(0x8868E871, 0xE4F1, 0x11D3, [0xBC, 0x22, 0x00, 0x80, 0xC7, 0x3C, 0x88, 0x81]);
+
+/// GUID of the ACPI 1.0 RSDP in the system configuration table
+pub static ACPI_TABLE_GUID: Guid = Guid(0xEB9D2D30, 0x2D88, 0x11D3, [0x9A, 0x16, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]);
+
+/// Size of the header every ACPI system description table starts with.
+pub const ACPI_TABLE_HEADER_SIZE: usize = 36;
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// View the ACPI table at `address`, checking its length and checksum.
+unsafe fn table_at(address: u64) -> Option<&'static [u8]> {
+    if address == 0 {
+        return None;
+    }
+
+    let header = slice::from_raw_parts(address as usize as *const u8, ACPI_TABLE_HEADER_SIZE);
+    let len = wire::read_u32(header, 4).ok()? as usize;
+    if len < ACPI_TABLE_HEADER_SIZE {
+        return None;
+    }
+
+    let table = slice::from_raw_parts(address as usize as *const u8, len);
+    if checksum_ok(table) { Some(table) } else { None }
+}
+
+/// The root table, as (table, entry size): the XSDT with 8-byte entries, or on ACPI 1.0
+/// firmware the RSDT with 4-byte entries.
+fn root_table() -> Option<(&'static [u8], usize)> {
+    let st = ::get_system_table();
+    let rsdp = st.configuration_table(&EFI_ACPI_20_TABLE_GUID)
+        .or_else(|| st.configuration_table(&ACPI_TABLE_GUID))
+        .filter(|p| !p.is_null())?;
+
+    unsafe {
+        let v1 = slice::from_raw_parts(rsdp as *const u8, 20);
+        if &v1[..8] != b"RSD PTR " || !checksum_ok(v1) {
+            return None;
+        }
+
+        if v1[15] >= 2 {
+            let len = wire::read_u32(slice::from_raw_parts(rsdp as *const u8, 24), 20).ok()? as usize;
+            let v2 = slice::from_raw_parts(rsdp as *const u8, len.max(36));
+            if checksum_ok(v2) {
+                if let Some(xsdt) = table_at(wire::read_u64(v2, 24).ok()?) {
+                    return Some((xsdt, 8));
+                }
+            }
+        }
+
+        table_at(wire::read_u32(v1, 16).ok()? as u64).map(|rsdt| (rsdt, 4))
+    }
+}
+
+/// The ACPI tables the firmware publishes, each validated, including the header.
+pub fn acpi_tables() -> impl Iterator<Item = &'static [u8]> {
+    let (root, entry_size) = root_table().unwrap_or((&[], 8));
+    root.get(ACPI_TABLE_HEADER_SIZE..)
+        .unwrap_or(&[])
+        .chunks(entry_size)
+        .filter(move |entry| entry.len() == entry_size)
+        .filter_map(move |entry| {
+            let address = if entry_size == 8 { wire::read_u64(entry, 0).ok()? } else { wire::read_u32(entry, 0).ok()? as u64 };
+            unsafe { table_at(address) }
+        })
+}
+
+/// The first valid ACPI table with `signature`, such as `b"BGRT"`.
+pub fn find_acpi_table(signature: &[u8; 4]) -> Option<&'static [u8]> {
+    acpi_tables().find(|t| &t[..4] == signature)
+}
+
+/// Rotation of the boot logo, from the BGRT status field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogoOrientation {
+    None,
+    Rotated90,
+    Rotated180,
+    Rotated270,
+}
+
+/// The Boot Graphics Resource Table, which describes the OEM logo the firmware drew at boot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bgrt {
+    pub version: u16,
+    pub status: u8,
+    /// 0 for a BMP image; no other type is defined.
+    pub image_type: u8,
+    pub image_address: u64,
+    /// Position of the logo's top left corner in the mode it was drawn in.
+    pub offset_x: u32,
+    pub offset_y: u32,
+}
+
+impl Bgrt {
+    /// The firmware's BGRT, if it publishes a valid one.
+    pub fn find() -> Option<Bgrt> {
+        Bgrt::from_bytes(find_acpi_table(b"BGRT")?).ok()
+    }
+
+    /// Parse a BGRT, including its ACPI header. Fails with `Status::InvalidParameter` if it is
+    /// too short or not a BGRT.
+    pub fn from_bytes(table: &[u8]) -> Result<Bgrt, Status> {
+        let invalid = |_| Status::InvalidParameter;
+        if table.get(..4) != Some(b"BGRT") {
+            return Err(Status::InvalidParameter);
+        }
+
+        Ok(Bgrt {
+            version: wire::read_u16(table, 36).map_err(invalid)?,
+            status: wire::read_u8(table, 38).map_err(invalid)?,
+            image_type: wire::read_u8(table, 39).map_err(invalid)?,
+            image_address: wire::read_u64(table, 40).map_err(invalid)?,
+            offset_x: wire::read_u32(table, 48).map_err(invalid)?,
+            offset_y: wire::read_u32(table, 52).map_err(invalid)?,
+        })
+    }
+
+    /// Whether the logo was still on screen when the table was last updated.
+    pub fn displayed(&self) -> bool {
+        self.status & 0x01 != 0
+    }
+
+    pub fn orientation(&self) -> LogoOrientation {
+        match (self.status >> 1) & 0x03 {
+            1 => LogoOrientation::Rotated90,
+            2 => LogoOrientation::Rotated180,
+            3 => LogoOrientation::Rotated270,
+            _ => LogoOrientation::None,
+        }
+    }
+
+    /// The logo as a BMP file, after checking its headers. The image lives in boot services
+    /// memory, so it is only available before ExitBootServices.
+    pub fn image(&self) -> Option<&'static [u8]> {
+        if self.image_type != 0 || self.image_address == 0 {
+            return None;
+        }
+
+        unsafe {
+            let headers = slice::from_raw_parts(self.image_address as usize as *const u8, BMP_HEADER_SIZE);
+            if &headers[..2] != b"BM" {
+                return None;
+            }
+            let size = wire::read_u32(headers, 2).ok()? as usize;
+            if size < BMP_HEADER_SIZE {
+                return None;
+            }
+            Some(slice::from_raw_parts(self.image_address as usize as *const u8, size))
+        }
+    }
+
+    /// Width and height of the logo in pixels, from its BMP header.
+    pub fn image_size(&self) -> Option<(u32, u32)> {
+        let image = self.image()?;
+        let width = wire::read_u32(image, 18).ok()? as i32;
+        let height = wire::read_u32(image, 22).ok()? as i32;
+        Some((width.unsigned_abs(), height.unsigned_abs()))
+    }
+
+    /// Where to draw the logo in a `to` (width, height) mode so that it keeps the relative
+    /// position it had in the `from` mode it was drawn in, for redrawing it after a mode change.
+    pub fn reposition(&self, from: (u32, u32), to: (u32, u32)) -> Option<(u32, u32)> {
+        let (width, height) = self.image_size()?;
+        let scale = |offset: u32, size: u32, from: u32, to: u32| -> Option<u32> {
+            // Keep the logo's centre at the same fraction of the screen.
+            let centre = (offset as u64 * 2 + size as u64) * to as u64 / (from as u64).max(1);
+            (centre / 2).checked_sub(size as u64 / 2).map(|v| v.min(to.saturating_sub(size) as u64) as u32)
+        };
+        Some((scale(self.offset_x, width, from.0, to.0)?, scale(self.offset_y, height, from.1, to.1)?))
+    }
+}
+
+#[test]
+fn bgrt_parse() {
+    let mut table = [0u8; 56];
+    table[..4].copy_from_slice(b"BGRT");
+    wire::write_u16(&mut table, 36, 1).unwrap();
+    table[38] = 0x03;
+    wire::write_u64(&mut table, 40, 0x1000).unwrap();
+    wire::write_u32(&mut table, 48, 760).unwrap();
+
+    let bgrt = Bgrt::from_bytes(&table).unwrap();
+    assert!(bgrt.displayed());
+    assert_eq!(bgrt.orientation(), LogoOrientation::Rotated90);
+    assert_eq!((bgrt.image_address, bgrt.offset_x), (0x1000, 760));
+    assert_eq!(Bgrt::from_bytes(&table[..40]), Err(Status::InvalidParameter));
+}
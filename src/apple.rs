@@ -0,0 +1,70 @@
+//! Booting on Macs and chainloading macOS: the partition types of Apple volumes, Apple's
+//! variable namespaces and the path and quirks of the macOS loader. Built with the `apple`
+//! feature, along with the Apple protocols.
+
+use core::ptr;
+
+use base::Status;
+use guid::Guid;
+use protocol::{AppleSetOsProtocol, FileProtocol, EFI_FILE_MODE_READ};
+
+/// Namespace of Apple's firmware variables, such as the `boot-args` and `csr-active-config`
+/// variables macOS reads.
+pub static APPLE_BOOT_VARIABLE_GUID: Guid = Guid(0x7C436110, 0xAB2A, 0x4BBB, [0xA8, 0x80, 0xFE, 0x41, 0x99, 0x5C, 0x9F, 0x82]);
+
+/// Namespace of Apple's vendor variables, such as `UIScale` and `BootCampHD`.
+pub static APPLE_VENDOR_VARIABLE_GUID: Guid = Guid(0x4D1EDE05, 0x38C7, 0x4A6A, [0x9C, 0xC6, 0x4B, 0xCC, 0xA8, 0xB3, 0x8C, 0x14]);
+
+/// GPT partition type of an APFS container.
+pub static APFS_PARTITION_TYPE_GUID: Guid = Guid(0x7C3457EF, 0x0000, 0x11AA, [0xAA, 0x11, 0x00, 0x30, 0x65, 0x43, 0xEC, 0xAC]);
+
+/// GPT partition type of an HFS+ volume.
+pub static HFS_PLUS_PARTITION_TYPE_GUID: Guid = Guid(0x48465300, 0x0000, 0x11AA, [0xAA, 0x11, 0x00, 0x30, 0x65, 0x43, 0xEC, 0xAC]);
+
+/// GPT partition type of an Apple boot ("Recovery HD") partition.
+pub static APPLE_BOOT_PARTITION_TYPE_GUID: Guid = Guid(0x426F6F74, 0x0000, 0x11AA, [0xAA, 0x11, 0x00, 0x30, 0x65, 0x43, 0xEC, 0xAC]);
+
+/// The macOS loader, relative to the root of a macOS volume. Load it with `load_image` from its
+/// device path rather than from a buffer, since it finds the volume to boot from the path.
+pub const MACOS_BOOT_FILE: &str = "\\System\\Library\\CoreServices\\boot.efi";
+
+/// The OS version `prepare_macos_boot` reports to the firmware.
+pub const MACOS_OS_VERSION: &str = "Mac OS X 10.15";
+
+/// The OS vendor `prepare_macos_boot` reports to the firmware.
+pub const MACOS_OS_VENDOR: &str = "Apple Inc.";
+
+/// Whether `partition_type` is one of Apple's GPT partition types.
+pub fn is_apple_partition_type(partition_type: &Guid) -> bool {
+    [&APFS_PARTITION_TYPE_GUID, &HFS_PLUS_PARTITION_TYPE_GUID, &APPLE_BOOT_PARTITION_TYPE_GUID].contains(&partition_type)
+}
+
+/// Whether the volume whose root directory is `root` has a macOS loader.
+pub fn has_macos_loader(root: &FileProtocol) -> bool {
+    match root.open_path(MACOS_BOOT_FILE, EFI_FILE_MODE_READ) {
+        Ok(file) => {
+            file.close();
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Tell the firmware macOS is about to start, as Apple's boot picker does, so it leaves the
+/// hardware macOS expects switched on, such as the integrated GPU of dual-GPU MacBook Pros.
+/// Call this before starting `MACOS_BOOT_FILE`. Succeeds without doing anything on firmware
+/// without the set OS protocol.
+pub fn prepare_macos_boot() -> Status {
+    let bs = ::get_system_table().boot_services();
+    let set_os = match bs.locate_protocol::<AppleSetOsProtocol>(ptr::null()) {
+        Ok(set_os) => set_os,
+        Err(Status::NotFound) => return Status::Success,
+        Err(e) => return e,
+    };
+
+    let status = set_os.set_os_vendor(MACOS_OS_VENDOR);
+    if status != Status::Success {
+        return status;
+    }
+    set_os.set_os_version(MACOS_OS_VERSION)
+}
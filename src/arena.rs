@@ -0,0 +1,197 @@
+use core::cell::Cell;
+use core::{array, mem, ptr, slice, str};
+
+use base::{MemoryType, PhysicalAddress, Status};
+use bootservices::AllocateType;
+use memmap::EFI_PAGE_SIZE;
+use util::char_to_ucs2;
+
+/// Most chunks an `Arena` can hold.
+pub const MAX_ARENA_CHUNKS: usize = 32;
+/// Pages in each chunk of an `Arena::new` arena (64 KiB).
+pub const DEFAULT_ARENA_CHUNK_PAGES: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Chunk {
+    address: PhysicalAddress,
+    pages: usize,
+}
+
+impl Chunk {
+    fn size(&self) -> usize {
+        self.pages * EFI_PAGE_SIZE as usize
+    }
+}
+
+/// A bump allocator over large chunks from AllocatePages, for building many small objects
+/// (device paths, strings, file lists) without a pool allocation each. Allocation is a pointer
+/// bump; everything is freed at once when the arena is reset or dropped.
+///
+/// Values are never dropped, so only `Copy` types can be allocated.
+///
+/// ```rust,ignore
+/// let arena = Arena::new();
+/// let name = arena.alloc_ucs2("\\EFI\\BOOT\\BOOTX64.EFI")?;
+/// let nodes = arena.alloc_slice_copy(&path_bytes)?;
+/// ```
+pub struct Arena {
+    chunk_pages: usize,
+    memory_type: MemoryType,
+    chunks: [Cell<Option<Chunk>>; MAX_ARENA_CHUNKS],
+    /// Index of the chunk being allocated from, and the offset of its free space.
+    current: Cell<usize>,
+    offset: Cell<usize>,
+}
+
+impl Default for Arena {
+    fn default() -> Arena {
+        Arena::new()
+    }
+}
+
+impl Arena {
+    /// An arena of `LoaderData` memory in 64 KiB chunks. No memory is allocated until the first
+    /// allocation.
+    pub fn new() -> Arena {
+        Arena::with_chunk_pages(DEFAULT_ARENA_CHUNK_PAGES, MemoryType::LoaderData)
+    }
+
+    /// An arena of `memory_type` memory in chunks of `chunk_pages` pages.
+    pub fn with_chunk_pages(chunk_pages: usize, memory_type: MemoryType) -> Arena {
+        Arena {
+            chunk_pages: chunk_pages.max(1),
+            memory_type,
+            chunks: array::from_fn(|_| Cell::new(None)),
+            current: Cell::new(0),
+            offset: Cell::new(0),
+        }
+    }
+
+    /// Start a new chunk big enough for `size` bytes at any alignment up to a page.
+    fn grow(&self, size: usize) -> Result<(), Status> {
+        let index = self.chunks.iter().position(|c| c.get().is_none()).ok_or(Status::OutOfResources)?;
+
+        let size = size as u64;
+        let pages = (size / EFI_PAGE_SIZE + (size % EFI_PAGE_SIZE != 0) as u64).max(self.chunk_pages as u64) as usize;
+        let address = ::get_system_table()
+            .boot_services()
+            .allocate_pages(AllocateType::AnyPages, self.memory_type, pages)?;
+
+        self.chunks[index].set(Some(Chunk { address, pages }));
+        self.current.set(index);
+        self.offset.set(0);
+        Ok(())
+    }
+
+    /// Allocate `size` bytes aligned to `align`, which must be a power of two no larger than
+    /// the page size. The bytes are zeroed.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_bytes(&self, size: usize, align: usize) -> Result<&mut [u8], Status> {
+        if !align.is_power_of_two() || align as u64 > EFI_PAGE_SIZE {
+            return Err(Status::InvalidParameter);
+        }
+
+        // Chunks are page aligned, so aligning the offset aligns the address.
+        let fits = |chunk: Option<Chunk>| {
+            let chunk = chunk?;
+            let start = self.offset.get().checked_add(align - 1)? & !(align - 1);
+            let end = start.checked_add(size)?;
+            if end <= chunk.size() { Some((chunk, start, end)) } else { None }
+        };
+
+        let (chunk, start, end) = match fits(self.chunks[self.current.get()].get()) {
+            Some(found) => found,
+            None => {
+                self.grow(size)?;
+                fits(self.chunks[self.current.get()].get()).ok_or(Status::OutOfResources)?
+            }
+        };
+
+        self.offset.set(end);
+        // Each range of a chunk is handed out once until the arena is reset, which takes
+        // `&mut self`.
+        unsafe {
+            let p = (chunk.address as usize + start) as *mut u8;
+            ptr::write_bytes(p, 0, size);
+            Ok(slice::from_raw_parts_mut(p, size))
+        }
+    }
+
+    /// Move `value` into the arena.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc<T: Copy>(&self, value: T) -> Result<&mut T, Status> {
+        let bytes = self.alloc_bytes(mem::size_of::<T>(), mem::align_of::<T>())?;
+        let p = bytes.as_mut_ptr() as *mut T;
+        unsafe {
+            p.write(value);
+            Ok(&mut *p)
+        }
+    }
+
+    /// Copy `values` into the arena.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice_copy<T: Copy>(&self, values: &[T]) -> Result<&mut [T], Status> {
+        let size = mem::size_of_val(values);
+        let bytes = self.alloc_bytes(size, mem::align_of::<T>())?;
+        let p = bytes.as_mut_ptr() as *mut T;
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), p, values.len());
+            Ok(slice::from_raw_parts_mut(p, values.len()))
+        }
+    }
+
+    /// Copy `s` into the arena.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_str(&self, s: &str) -> Result<&mut str, Status> {
+        let bytes = self.alloc_slice_copy(s.as_bytes())?;
+        Ok(unsafe { str::from_utf8_unchecked_mut(bytes) })
+    }
+
+    /// Encode `s` as a null-terminated UCS-2 string in the arena, as firmware interfaces take
+    /// them. Characters outside the Basic Multilingual Plane are replaced.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_ucs2(&self, s: &str) -> Result<&mut [u16], Status> {
+        let len = s.chars().count() + 1;
+        let bytes = self.alloc_bytes(len * 2, mem::align_of::<u16>())?;
+        let units = unsafe { slice::from_raw_parts_mut(bytes.as_mut_ptr() as *mut u16, len) };
+        for (unit, c) in units.iter_mut().zip(s.chars()) {
+            *unit = char_to_ucs2(c);
+        }
+        Ok(units)
+    }
+
+    /// Bytes handed out from the current chunk, plus the full size of earlier chunks.
+    pub fn allocated_bytes(&self) -> usize {
+        let full: usize = self.chunks
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != self.current.get())
+            .filter_map(|(_, c)| c.get())
+            .map(|c| c.size())
+            .sum();
+        full + self.offset.get()
+    }
+
+    /// Free everything allocated, keeping the first chunk for reuse.
+    pub fn reset(&mut self) {
+        let bs = ::get_system_table().boot_services();
+        for chunk in self.chunks.iter().skip(1) {
+            if let Some(c) = chunk.take() {
+                bs.free_pages(c.address, c.pages);
+            }
+        }
+        self.current.set(0);
+        self.offset.set(0);
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        let bs = ::get_system_table().boot_services();
+        for chunk in self.chunks.iter() {
+            if let Some(c) = chunk.take() {
+                bs.free_pages(c.address, c.pages);
+            }
+        }
+    }
+}
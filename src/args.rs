@@ -0,0 +1,358 @@
+use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use core::fmt;
+
+use base::Status;
+use protocol::{ShellParametersProtocol, get_current_image, get_current_image_handle};
+use util::{utf16_strlen, wire};
+
+/// Most arguments an `Args` can hold.
+pub const MAX_ARGS: usize = 64;
+/// Total size in bytes of the UTF-8 text of all arguments in an `Args`.
+pub const ARGS_BUFFER_SIZE: usize = 4096;
+
+/// A list of command line arguments, converted to UTF-8 and kept in fixed-size storage.
+pub struct Args {
+    buf: [u8; ARGS_BUFFER_SIZE],
+    used: usize,
+    spans: [(usize, usize); MAX_ARGS],
+    count: usize,
+    // Set while building an argument with `push_char`.
+    open: Option<usize>,
+}
+
+impl Default for Args {
+    fn default() -> Args {
+        Args::new()
+    }
+}
+
+impl Args {
+    pub fn new() -> Args {
+        Args {
+            buf: [0; ARGS_BUFFER_SIZE],
+            used: 0,
+            spans: [(0, 0); MAX_ARGS],
+            count: 0,
+            open: None,
+        }
+    }
+
+    /// The arguments the UEFI shell passed to the current image, without the program name, or
+    /// `None` if the image was not started by the shell.
+    pub fn from_shell() -> Option<Result<Args, Status>> {
+        let params = ::get_system_table()
+            .boot_services()
+            .handle_protocol::<ShellParametersProtocol>(get_current_image_handle())
+            .ok()?;
+
+        let mut args = Args::new();
+        for &arg in params.argv().iter().skip(1) {
+            let ucs2 = unsafe { ::core::slice::from_raw_parts(arg, utf16_strlen(arg)) };
+            if let Err(e) = args.push_ucs2(ucs2) {
+                return Some(Err(e));
+            }
+        }
+        Some(Ok(args))
+    }
+
+    /// The arguments in the current image's load options, which are taken to be a UCS-2 command
+    /// line whose first word is the program name, as the shell and most boot managers pass them.
+    pub fn from_load_options() -> Result<Args, Status> {
+        let options = get_current_image().load_options();
+        let units = wire::read_ucs2(options, 0);
+
+        let mut args = Args::new();
+        args.split(decode_utf16(units).map(|c| c.unwrap_or(REPLACEMENT_CHARACTER)))?;
+        args.remove_first();
+        Ok(args)
+    }
+
+    /// The arguments of the current image: the shell's if it was started from the shell,
+    /// otherwise those in its load options.
+    pub fn current() -> Result<Args, Status> {
+        match Args::from_shell() {
+            Some(args) => args,
+            None => Args::from_load_options(),
+        }
+    }
+
+    /// Split `line` into arguments at whitespace, following the UEFI shell's rules: double quotes
+    /// group words into one argument, and `^` makes the next character literal. Backslashes are
+    /// ordinary characters, since they separate path components.
+    pub fn parse(line: &str) -> Result<Args, Status> {
+        let mut args = Args::new();
+        args.split(line.chars())?;
+        Ok(args)
+    }
+
+    fn split<I: Iterator<Item = char>>(&mut self, chars: I) -> Result<(), Status> {
+        let mut quoted = false;
+        let mut escaped = false;
+
+        for c in chars {
+            if escaped {
+                self.push_char(c)?;
+                escaped = false;
+            } else if c == '^' {
+                escaped = true;
+                self.start()?;
+            } else if c == '"' {
+                quoted = !quoted;
+                // "" is an empty argument.
+                self.start()?;
+            } else if c.is_whitespace() && !quoted {
+                self.finish();
+            } else {
+                self.push_char(c)?;
+            }
+        }
+        self.finish();
+
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Status> {
+        if self.open.is_none() {
+            if self.count == MAX_ARGS {
+                return Err(Status::BufferTooSmall);
+            }
+            self.open = Some(self.used);
+        }
+        Ok(())
+    }
+
+    fn push_char(&mut self, c: char) -> Result<(), Status> {
+        self.start()?;
+        if self.used + c.len_utf8() > ARGS_BUFFER_SIZE {
+            return Err(Status::BufferTooSmall);
+        }
+        self.used += c.encode_utf8(&mut self.buf[self.used..]).len();
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        if let Some(start) = self.open.take() {
+            self.spans[self.count] = (start, self.used);
+            self.count += 1;
+        }
+    }
+
+    fn remove_first(&mut self) {
+        if self.count > 0 {
+            self.spans.copy_within(1..self.count, 0);
+            self.count -= 1;
+        }
+    }
+
+    /// Append one argument.
+    pub fn push(&mut self, arg: &str) -> Result<(), Status> {
+        self.start()?;
+        for c in arg.chars() {
+            self.push_char(c)?;
+        }
+        self.finish();
+        Ok(())
+    }
+
+    /// Append one argument given as UCS-2.
+    pub fn push_ucs2(&mut self, arg: &[u16]) -> Result<(), Status> {
+        self.start()?;
+        for c in decode_utf16(arg.iter().cloned()) {
+            self.push_char(c.unwrap_or(REPLACEMENT_CHARACTER))?;
+        }
+        self.finish();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        if index >= self.count {
+            return None;
+        }
+
+        let (start, end) = self.spans[index];
+        // Only whole characters are ever written to the buffer.
+        Some(unsafe { ::core::str::from_utf8_unchecked(&self.buf[start..end]) })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        (0..self.count).filter_map(move |i| self.get(i))
+    }
+}
+
+/// An option a `GetOpt` parser accepts.
+#[derive(Clone, Copy, Debug)]
+pub struct OptSpec {
+    /// Single-character form, used as `-v`.
+    pub short: Option<char>,
+    /// Long form, used as `--verbose`.
+    pub long: &'static str,
+    /// Whether the option takes a value, as in `-o file`, `-ofile`, `--output file` or
+    /// `--output=file`.
+    pub takes_value: bool,
+}
+
+/// An item produced by `GetOpt`.
+#[derive(Clone, Copy, Debug)]
+pub enum Opt<'a> {
+    /// An option from the spec list, with its value if it takes one.
+    Option(&'a OptSpec, Option<&'a str>),
+    /// An argument which is not an option, such as a file name or subcommand.
+    Positional(&'a str),
+}
+
+/// A command line error, to be reported to the user.
+#[derive(Clone, Copy, Debug)]
+pub enum OptError<'a> {
+    UnknownOption(&'a str),
+    MissingValue(&'a OptSpec),
+    UnexpectedValue(&'a OptSpec),
+}
+
+impl<'a> fmt::Display for OptError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptError::UnknownOption(opt) => write!(f, "unknown option '{}'", opt),
+            OptError::MissingValue(spec) => write!(f, "option '--{}' requires a value", spec.long),
+            OptError::UnexpectedValue(spec) => write!(f, "option '--{}' does not take a value", spec.long),
+        }
+    }
+}
+
+/// A getopt-style parser over `Args`, yielding options and positional arguments in order.
+///
+/// Short options may be grouped (`-vx`), `--` ends option parsing, and a lone `-` is positional.
+/// For subcommands, stop at the first `Opt::Positional` and parse the rest with a new parser
+/// starting at `position()`:
+///
+/// ```rust,ignore
+/// static GLOBAL: [OptSpec; 1] = [OptSpec { short: Some('v'), long: "verbose", takes_value: false }];
+///
+/// let args = Args::current()?;
+/// let mut opts = GetOpt::new(&args, &GLOBAL);
+/// while let Some(opt) = opts.next() {
+///     match opt {
+///         Ok(Opt::Option(spec, _)) if spec.long == "verbose" => verbose = true,
+///         Ok(Opt::Positional("install")) => return install(GetOpt::starting_at(&args, opts.position(), &INSTALL)),
+///         Ok(_) => {}
+///         Err(e) => { writeln!(console, "{}", e); return Err(Status::InvalidParameter); }
+///     }
+/// }
+/// ```
+pub struct GetOpt<'a> {
+    args: &'a Args,
+    specs: &'a [OptSpec],
+    index: usize,
+    // Remaining characters of a group of short options, such as "x" after "-v" in "-vx".
+    short_group: &'a str,
+    options_done: bool,
+}
+
+impl<'a> GetOpt<'a> {
+    pub fn new(args: &'a Args, specs: &'a [OptSpec]) -> GetOpt<'a> {
+        GetOpt::starting_at(args, 0, specs)
+    }
+
+    /// Parse `args` from argument `index` on.
+    pub fn starting_at(args: &'a Args, index: usize, specs: &'a [OptSpec]) -> GetOpt<'a> {
+        GetOpt {
+            args,
+            specs,
+            index,
+            short_group: "",
+            options_done: false,
+        }
+    }
+
+    /// Index of the next argument to be parsed.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+
+    fn next_arg(&mut self) -> Option<&'a str> {
+        let arg = self.args.get(self.index)?;
+        self.index += 1;
+        Some(arg)
+    }
+
+    fn long(&mut self, arg: &'a str) -> Result<Opt<'a>, OptError<'a>> {
+        let (name, value) = match arg.find('=') {
+            Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+            None => (arg, None),
+        };
+
+        let spec = match self.specs.iter().find(|s| s.long == name) {
+            Some(spec) => spec,
+            None => return Err(OptError::UnknownOption(arg)),
+        };
+
+        match (spec.takes_value, value) {
+            (true, Some(value)) => Ok(Opt::Option(spec, Some(value))),
+            (true, None) => match self.next_arg() {
+                Some(value) => Ok(Opt::Option(spec, Some(value))),
+                None => Err(OptError::MissingValue(spec)),
+            },
+            (false, Some(_)) => Err(OptError::UnexpectedValue(spec)),
+            (false, None) => Ok(Opt::Option(spec, None)),
+        }
+    }
+
+    fn short(&mut self) -> Result<Opt<'a>, OptError<'a>> {
+        let group = self.short_group;
+        let c = group.chars().next().unwrap();
+        let rest = &group[c.len_utf8()..];
+        self.short_group = "";
+
+        let spec = match self.specs.iter().find(|s| s.short == Some(c)) {
+            Some(spec) => spec,
+            None => return Err(OptError::UnknownOption(&group[..c.len_utf8()])),
+        };
+
+        if !spec.takes_value {
+            self.short_group = rest;
+            return Ok(Opt::Option(spec, None));
+        }
+
+        if !rest.is_empty() {
+            return Ok(Opt::Option(spec, Some(rest)));
+        }
+        match self.next_arg() {
+            Some(value) => Ok(Opt::Option(spec, Some(value))),
+            None => Err(OptError::MissingValue(spec)),
+        }
+    }
+}
+
+impl<'a> Iterator for GetOpt<'a> {
+    type Item = Result<Opt<'a>, OptError<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.short_group.is_empty() {
+            return Some(self.short());
+        }
+
+        let arg = self.next_arg()?;
+        if self.options_done || arg == "-" || !arg.starts_with('-') {
+            return Some(Ok(Opt::Positional(arg)));
+        }
+
+        if arg == "--" {
+            self.options_done = true;
+            return self.next();
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            return Some(self.long(long));
+        }
+
+        self.short_group = &arg[1..];
+        Some(self.short())
+    }
+}
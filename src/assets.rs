@@ -0,0 +1,169 @@
+//! Files built into the image with `assets!`, read by path like files on a volume, so a single
+//! `.efi` can carry its fonts, images and configuration. Mount the `Assets` in an `Overlay` to
+//! have copies on the ESP take precedence.
+//!
+//! ```rust,ignore
+//! static ASSETS: Assets = assets! {
+//!     "\\fonts\\ter-u16n.psf" => "../assets/ter-u16n.psf",
+//!     "\\logo.bmp" => "../assets/logo.bmp",
+//! };
+//!
+//! let mut logo = ASSETS.open("\\logo.bmp")?;
+//! let len = logo.read(&mut header)?;
+//! ```
+
+use core::fmt;
+
+use base::Status;
+use overlay::FileSource;
+use path::EfiPath;
+
+/// Embed files in the image as `Assets`, each given as the path it is read by, then the file
+/// to include, relative to the source file as for `include_bytes!`.
+///
+/// ```rust,ignore
+/// static ASSETS: Assets = assets! {
+///     "\\config.txt" => "config.txt",
+/// };
+/// ```
+#[macro_export]
+macro_rules! assets {
+    ($($path:expr => $file:expr),* $(,)*) => {
+        $crate::Assets::new(&[$($crate::Asset::new($path, include_bytes!($file))),*])
+    };
+}
+
+/// A file built into the image.
+#[derive(Clone, Copy)]
+pub struct Asset {
+    path: &'static str,
+    data: &'static [u8],
+}
+
+impl Asset {
+    pub const fn new(path: &'static str, data: &'static [u8]) -> Asset {
+        Asset { path, data }
+    }
+
+    /// The path the asset is read by, as given to `assets!`.
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    pub fn data(&self) -> &'static [u8] {
+        self.data
+    }
+
+    /// Open the asset for reading from the start.
+    pub fn open(&self) -> AssetFile {
+        AssetFile { data: self.data, position: 0 }
+    }
+}
+
+impl fmt::Debug for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Asset({:?}, {} bytes)", self.path, self.data.len())
+    }
+}
+
+/// The assets of an image, as made by `assets!`. Paths are matched as `EfiPath`s are
+/// compared, so either slash may be used and case is ignored.
+#[derive(Clone, Copy, Debug)]
+pub struct Assets {
+    assets: &'static [Asset],
+}
+
+impl Assets {
+    pub const fn new(assets: &'static [Asset]) -> Assets {
+        Assets { assets }
+    }
+
+    /// The asset at `path`, if there is one.
+    pub fn get(&self, path: &str) -> Option<&'static Asset> {
+        let path = EfiPath::new(path).ok()?;
+        self.assets.iter().find(|asset| path == asset.path)
+    }
+
+    /// Open the asset at `path`, failing with `Status::NotFound` if there is none.
+    pub fn open(&self, path: &str) -> Result<AssetFile, Status> {
+        self.get(path).map(Asset::open).ok_or(Status::NotFound)
+    }
+
+    pub fn iter(&self) -> ::core::slice::Iter<'static, Asset> {
+        self.assets.iter()
+    }
+}
+
+impl FileSource for Assets {
+    fn file_size(&self, path: &str) -> Result<u64, Status> {
+        self.open(path).map(|file| file.file_size())
+    }
+
+    fn read_file(&self, path: &str, buf: &mut [u8]) -> Result<usize, Status> {
+        let data = self.open(path)?.data;
+        buf.get_mut(..data.len()).ok_or(Status::BufferTooSmall)?.copy_from_slice(data);
+        Ok(data.len())
+    }
+}
+
+/// An asset open for reading, with the reading methods of `FileProtocol`.
+#[derive(Clone, Debug)]
+pub struct AssetFile {
+    data: &'static [u8],
+    position: u64,
+}
+
+impl AssetFile {
+    /// Read from the current position into `buf`, returning how much was read: less than
+    /// `buf.len()` at the end of the asset, and 0 once there.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Status> {
+        let rest = self.data.get(self.position as usize..).unwrap_or(&[]);
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+
+    pub fn get_position(&self) -> Result<u64, Status> {
+        Ok(self.position)
+    }
+
+    /// Move to `position`, where `u64::MAX` means the end, as for `FileProtocol::set_position`.
+    /// Positions past the end are allowed; reading there reads nothing.
+    pub fn set_position(&mut self, position: u64) -> Status {
+        self.position = if position == u64::MAX { self.file_size() } else { position };
+        Status::Success
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// The whole asset, without copying it.
+    pub fn data(&self) -> &'static [u8] {
+        self.data
+    }
+}
+
+#[test]
+fn embedded_assets() {
+    static ASSETS: Assets = assets! {
+        "\\Cargo.toml" => "../Cargo.toml",
+        "/docs/readme.md" => "../README.md",
+    };
+
+    assert_eq!(ASSETS.iter().count(), 2);
+    let readme = ASSETS.get("\\DOCS\\README.md").unwrap();
+    assert_eq!(readme.data(), include_bytes!("../README.md"));
+    assert!(ASSETS.get("\\docs").is_none());
+    assert_eq!(ASSETS.open("\\missing").map(|_| ()), Err(Status::NotFound));
+
+    let mut file = ASSETS.open("\\cargo.TOML").unwrap();
+    let mut buf = [0u8; 16];
+    assert_eq!(file.read(&mut buf), Ok(16));
+    assert_eq!(&buf, &include_bytes!("../Cargo.toml")[..16]);
+    assert_eq!(file.get_position(), Ok(16));
+    assert_eq!(file.set_position(u64::MAX), Status::Success);
+    assert_eq!(file.read(&mut buf), Ok(0));
+    assert_eq!(ASSETS.file_size("/Cargo.toml"), Ok(file.file_size()));
+}
@@ -0,0 +1,567 @@
+#[cfg(feature = "json")]
+use core::{char, fmt};
+use core::{ptr, slice};
+#[cfg(all(test, feature = "json"))]
+use core::str;
+
+#[cfg(any(feature = "json", feature = "cbor"))]
+use base::Status;
+use esrt::{SystemResourceEntry, esrt_entries};
+use protocol::{LoadedImageProtocol, Tcg2Protocol, TPM_PCR_COUNT};
+use runtimeservices::EFI_GLOBAL_VARIABLE_GUID;
+use smbios::{SmbiosTable, SMBIOS_TYPE_BIOS_INFORMATION};
+use util::{sha256, utf16_strlen, SHA256_LEN};
+#[cfg(feature = "json")]
+use protocol::FileProtocol;
+#[cfg(any(feature = "cbor", all(test, feature = "json")))]
+use guid::Guid;
+#[cfg(feature = "cbor")]
+use runtimeservices::{VariableAttributes, EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_BOOTSERVICE_ACCESS};
+
+/// Most loaded images recorded in a report.
+pub const AUDIT_MAX_IMAGES: usize = 64;
+
+/// Most ESRT entries recorded in a report.
+pub const AUDIT_MAX_FIRMWARE: usize = 16;
+
+const MAX_VENDOR: usize = 64;
+
+/// A loaded image and the SHA-256 of its in-memory contents.
+#[derive(Clone, Copy)]
+pub struct ImageRecord {
+    pub base: u64,
+    pub size: u64,
+    pub sha256: [u8; SHA256_LEN],
+}
+
+/// A snapshot of the platform's boot security state, for attestation and provisioning
+/// pipelines. Every part is collected on a best-effort basis: parts the firmware does not
+/// support are left as `None` or empty.
+pub struct AuditReport {
+    vendor: [u16; MAX_VENDOR],
+    vendor_len: usize,
+    pub firmware_revision: u32,
+    /// The BIOS version string of SMBIOS, if the firmware publishes one.
+    pub bios_version: Option<&'static str>,
+    pub secure_boot: Option<bool>,
+    pub setup_mode: Option<bool>,
+    /// SHA-256 bank PCR values, indexed by PCR number.
+    pub pcrs: [Option<[u8; SHA256_LEN]>; TPM_PCR_COUNT as usize],
+    images: [ImageRecord; AUDIT_MAX_IMAGES],
+    image_count: usize,
+    firmware: [Option<SystemResourceEntry>; AUDIT_MAX_FIRMWARE],
+}
+
+fn global_flag(name: &str) -> Option<bool> {
+    let mut value = [0u8; 1];
+    ::get_system_table()
+        .runtime_services()
+        .get_variable(name, &EFI_GLOBAL_VARIABLE_GUID, &mut value)
+        .ok()
+        .map(|_| value[0] != 0)
+}
+
+impl AuditReport {
+    /// Gather the report from the running firmware.
+    pub fn collect() -> AuditReport {
+        let st = ::get_system_table();
+        let bs = st.boot_services();
+
+        let mut report = AuditReport {
+            vendor: [0; MAX_VENDOR],
+            vendor_len: 0,
+            firmware_revision: st.firmware_revision(),
+            bios_version: SmbiosTable::get()
+                .and_then(|table| table.find(SMBIOS_TYPE_BIOS_INFORMATION))
+                .and_then(|bios| bios.string_at(0x05)),
+            secure_boot: global_flag("SecureBoot"),
+            setup_mode: global_flag("SetupMode"),
+            pcrs: [None; TPM_PCR_COUNT as usize],
+            images: [ImageRecord { base: 0, size: 0, sha256: [0; SHA256_LEN] }; AUDIT_MAX_IMAGES],
+            image_count: 0,
+            firmware: [None; AUDIT_MAX_FIRMWARE],
+        };
+
+        if !st.vendor().is_null() {
+            let vendor = unsafe { slice::from_raw_parts(st.vendor(), utf16_strlen(st.vendor())) };
+            report.vendor_len = vendor.len().min(MAX_VENDOR);
+            report.vendor[..report.vendor_len].copy_from_slice(&vendor[..report.vendor_len]);
+        }
+
+        if let Ok(tcg2) = bs.locate_protocol::<Tcg2Protocol>(ptr::null()) {
+            for (i, pcr) in report.pcrs.iter_mut().enumerate() {
+                *pcr = tcg2.pcr_read_sha256(i as u32).ok();
+            }
+        }
+
+        if let Ok(handles) = bs.locate_handle_by_protocol::<LoadedImageProtocol>() {
+            for handle in &handles {
+                if report.image_count == AUDIT_MAX_IMAGES {
+                    break;
+                }
+                if let Ok(image) = bs.handle_protocol::<LoadedImageProtocol>(*handle) {
+                    // Images the firmware reports without a location can't be hashed.
+                    if image.image_base == 0 || image.image_size == 0 {
+                        continue;
+                    }
+                    let data = unsafe { slice::from_raw_parts(image.image_base as *const u8, image.image_size as usize) };
+                    report.images[report.image_count] = ImageRecord {
+                        base: image.image_base as u64,
+                        size: image.image_size,
+                        sha256: sha256(data),
+                    };
+                    report.image_count += 1;
+                }
+            }
+        }
+
+        for (slot, entry) in report.firmware.iter_mut().zip(esrt_entries()) {
+            *slot = Some(*entry);
+        }
+
+        report
+    }
+
+    /// The firmware vendor string from the system table, as UCS-2.
+    pub fn vendor(&self) -> &[u16] {
+        &self.vendor[..self.vendor_len]
+    }
+
+    /// The loaded images, in handle database order.
+    pub fn images(&self) -> &[ImageRecord] {
+        &self.images[..self.image_count]
+    }
+
+    /// The firmware components from the ESRT.
+    pub fn firmware(&self) -> FirmwareIter<'_> {
+        FirmwareIter { entries: self.firmware.iter() }
+    }
+}
+
+pub struct FirmwareIter<'a> {
+    entries: slice::Iter<'a, Option<SystemResourceEntry>>,
+}
+
+impl<'a> Iterator for FirmwareIter<'a> {
+    type Item = &'a SystemResourceEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.entries.next() {
+            Some(Some(entry)) => Some(entry),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn write_hex<W: fmt::Write>(w: &mut W, bytes: &[u8]) -> fmt::Result {
+    w.write_char('"')?;
+    for b in bytes {
+        write!(w, "{:02x}", b)?;
+    }
+    w.write_char('"')
+}
+
+/// Write `chars` as a JSON string, quotes included.
+#[cfg(feature = "json")]
+fn write_string<W: fmt::Write, I: Iterator<Item = char>>(w: &mut W, chars: I) -> fmt::Result {
+    w.write_char('"')?;
+    for c in chars {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+#[cfg(feature = "json")]
+fn write_bool<W: fmt::Write>(w: &mut W, value: Option<bool>) -> fmt::Result {
+    match value {
+        Some(v) => write!(w, "{}", v),
+        None => w.write_str("null"),
+    }
+}
+
+#[cfg(feature = "json")]
+impl AuditReport {
+    /// Serialize the report as a JSON object.
+    pub fn write_json<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("{\"firmware_vendor\":")?;
+        let vendor = char::decode_utf16(self.vendor().iter().cloned());
+        write_string(w, vendor.map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)))?;
+        write!(w, ",\"firmware_revision\":{},\"bios_version\":", self.firmware_revision)?;
+        match self.bios_version {
+            Some(version) => write_string(w, version.chars())?,
+            None => w.write_str("null")?,
+        }
+        w.write_str(",\"secure_boot\":")?;
+        write_bool(w, self.secure_boot)?;
+        w.write_str(",\"setup_mode\":")?;
+        write_bool(w, self.setup_mode)?;
+
+        w.write_str(",\"pcrs\":[")?;
+        let mut first = true;
+        for (i, pcr) in self.pcrs.iter().enumerate() {
+            if let Some(ref digest) = *pcr {
+                if !first {
+                    w.write_char(',')?;
+                }
+                first = false;
+                write!(w, "{{\"index\":{},\"sha256\":", i)?;
+                write_hex(w, digest)?;
+                w.write_char('}')?;
+            }
+        }
+
+        w.write_str("],\"images\":[")?;
+        for (i, image) in self.images().iter().enumerate() {
+            if i > 0 {
+                w.write_char(',')?;
+            }
+            write!(w, "{{\"base\":{},\"size\":{},\"sha256\":", image.base, image.size)?;
+            write_hex(w, &image.sha256)?;
+            w.write_char('}')?;
+        }
+
+        w.write_str("],\"esrt\":[")?;
+        for (i, entry) in self.firmware().enumerate() {
+            if i > 0 {
+                w.write_char(',')?;
+            }
+            write!(w, "{{\"fw_class\":\"{}\",\"fw_type\":{},\"fw_version\":{},\"lowest_supported_fw_version\":{},\"last_attempt_version\":{},\"last_attempt_status\":{}}}",
+                   entry.fw_class, entry.fw_type, entry.fw_version, entry.lowest_supported_fw_version,
+                   entry.last_attempt_version, entry.last_attempt_status)?;
+        }
+        w.write_str("]}")
+    }
+
+    /// Write the report as JSON to an open file.
+    pub fn save_json(&self, file: &FileProtocol) -> Result<(), Status> {
+        let mut out = FileWriter { file, status: Status::Success };
+        match self.write_json(&mut out) {
+            Ok(()) => Ok(()),
+            Err(_) if out.status != Status::Success => Err(out.status),
+            Err(_) => Err(Status::DeviceError),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+struct FileWriter<'a> {
+    file: &'a FileProtocol,
+    status: Status,
+}
+
+#[cfg(feature = "json")]
+impl<'a> fmt::Write for FileWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.file.write(s.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.status = e;
+                Err(fmt::Error)
+            }
+        }
+    }
+}
+
+/// Minimal CBOR encoder writing definite-length items into a byte buffer.
+#[cfg(feature = "cbor")]
+struct Cbor<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+#[cfg(feature = "cbor")]
+impl<'a> Cbor<'a> {
+    fn bytes(&mut self, data: &[u8]) -> Result<(), Status> {
+        if self.buf.len() - self.pos < data.len() {
+            return Err(Status::BufferTooSmall);
+        }
+        self.buf[self.pos..self.pos + data.len()].copy_from_slice(data);
+        self.pos += data.len();
+        Ok(())
+    }
+
+    fn head(&mut self, major: u8, value: u64) -> Result<(), Status> {
+        let major = major << 5;
+        if value < 24 {
+            self.bytes(&[major | value as u8])
+        } else if value <= 0xFF {
+            self.bytes(&[major | 24, value as u8])
+        } else if value <= 0xFFFF {
+            self.bytes(&[major | 25])?;
+            self.bytes(&(value as u16).to_be_bytes())
+        } else if value <= 0xFFFF_FFFF {
+            self.bytes(&[major | 26])?;
+            self.bytes(&(value as u32).to_be_bytes())
+        } else {
+            self.bytes(&[major | 27])?;
+            self.bytes(&value.to_be_bytes())
+        }
+    }
+
+    fn uint(&mut self, value: u64) -> Result<(), Status> {
+        self.head(0, value)
+    }
+
+    fn byte_string(&mut self, data: &[u8]) -> Result<(), Status> {
+        self.head(2, data.len() as u64)?;
+        self.bytes(data)
+    }
+
+    fn text(&mut self, s: &str) -> Result<(), Status> {
+        self.head(3, s.len() as u64)?;
+        self.bytes(s.as_bytes())
+    }
+
+    fn array(&mut self, len: usize) -> Result<(), Status> {
+        self.head(4, len as u64)
+    }
+
+    fn map(&mut self, len: usize) -> Result<(), Status> {
+        self.head(5, len as u64)
+    }
+
+    fn bool_or_null(&mut self, value: Option<bool>) -> Result<(), Status> {
+        match value {
+            Some(false) => self.bytes(&[0xF4]),
+            Some(true) => self.bytes(&[0xF5]),
+            None => self.bytes(&[0xF6]),
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl AuditReport {
+    /// Serialize the report as a CBOR map into `buf`, returning the encoded length. Keys match
+    /// those of the JSON encoding; digests and the vendor string are byte strings (the vendor
+    /// as UCS-2LE).
+    pub fn write_cbor(&self, buf: &mut [u8]) -> Result<usize, Status> {
+        let mut c = Cbor { buf, pos: 0 };
+
+        c.map(8)?;
+        c.text("firmware_vendor")?;
+        c.head(2, (self.vendor_len * 2) as u64)?;
+        for ch in self.vendor() {
+            c.bytes(&ch.to_le_bytes())?;
+        }
+        c.text("firmware_revision")?;
+        c.uint(self.firmware_revision as u64)?;
+        c.text("bios_version")?;
+        match self.bios_version {
+            Some(version) => c.text(version)?,
+            None => c.bytes(&[0xF6])?,
+        }
+        c.text("secure_boot")?;
+        c.bool_or_null(self.secure_boot)?;
+        c.text("setup_mode")?;
+        c.bool_or_null(self.setup_mode)?;
+
+        c.text("pcrs")?;
+        c.array(self.pcrs.iter().filter(|p| p.is_some()).count())?;
+        for (i, pcr) in self.pcrs.iter().enumerate() {
+            if let Some(ref digest) = *pcr {
+                c.map(2)?;
+                c.text("index")?;
+                c.uint(i as u64)?;
+                c.text("sha256")?;
+                c.byte_string(digest)?;
+            }
+        }
+
+        c.text("images")?;
+        c.array(self.image_count)?;
+        for image in self.images() {
+            c.map(3)?;
+            c.text("base")?;
+            c.uint(image.base)?;
+            c.text("size")?;
+            c.uint(image.size)?;
+            c.text("sha256")?;
+            c.byte_string(&image.sha256)?;
+        }
+
+        c.text("esrt")?;
+        c.array(self.firmware().count())?;
+        for entry in self.firmware() {
+            let class = &entry.fw_class;
+            let mut guid = [0u8; 16];
+            guid[0..4].copy_from_slice(&class.0.to_le_bytes());
+            guid[4..6].copy_from_slice(&class.1.to_le_bytes());
+            guid[6..8].copy_from_slice(&class.2.to_le_bytes());
+            guid[8..16].copy_from_slice(&class.3);
+
+            c.map(6)?;
+            c.text("fw_class")?;
+            c.byte_string(&guid)?;
+            c.text("fw_type")?;
+            c.uint(entry.fw_type as u64)?;
+            c.text("fw_version")?;
+            c.uint(entry.fw_version as u64)?;
+            c.text("lowest_supported_fw_version")?;
+            c.uint(entry.lowest_supported_fw_version as u64)?;
+            c.text("last_attempt_version")?;
+            c.uint(entry.last_attempt_version as u64)?;
+            c.text("last_attempt_status")?;
+            c.uint(entry.last_attempt_status as u64)?;
+        }
+
+        Ok(c.pos)
+    }
+
+    /// Serialize the report as CBOR, using `scratch` as the encoding buffer, and store it in the
+    /// non-volatile variable `name` under `vendor`.
+    pub fn save_cbor_variable(&self, name: &str, vendor: &Guid, scratch: &mut [u8]) -> Result<(), Status> {
+        let len = self.write_cbor(scratch)?;
+        let attributes: VariableAttributes = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS;
+
+        ::get_system_table()
+            .runtime_services()
+            .set_variable(name, vendor, attributes, &scratch[..len])
+    }
+}
+
+/// A report with nothing collected, for building test cases.
+#[cfg(all(test, any(feature = "json", feature = "cbor")))]
+fn empty_report(vendor: &[u16]) -> AuditReport {
+    let mut report = AuditReport {
+        vendor: [0; MAX_VENDOR],
+        vendor_len: vendor.len(),
+        firmware_revision: 0,
+        bios_version: None,
+        secure_boot: None,
+        setup_mode: None,
+        pcrs: [None; TPM_PCR_COUNT as usize],
+        images: [ImageRecord { base: 0, size: 0, sha256: [0; SHA256_LEN] }; AUDIT_MAX_IMAGES],
+        image_count: 0,
+        firmware: [None; AUDIT_MAX_FIRMWARE],
+    };
+    report.vendor[..vendor.len()].copy_from_slice(vendor);
+    report
+}
+
+#[cfg(all(test, feature = "json"))]
+struct JsonBuf([u8; 1024], usize);
+
+#[cfg(all(test, feature = "json"))]
+impl fmt::Write for JsonBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0[self.1..self.1 + s.len()].copy_from_slice(s.as_bytes());
+        self.1 += s.len();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn audit_json() {
+    let mut report = empty_report(&[]);
+    report.firmware_revision = 0x10000;
+    report.bios_version = Some("F.10");
+    report.secure_boot = Some(true);
+    report.setup_mode = Some(false);
+    report.pcrs[0] = Some([0x01; SHA256_LEN]);
+    report.pcrs[7] = Some([0xAB; SHA256_LEN]);
+    report.images[0] = ImageRecord { base: 0x1000, size: 0x200, sha256: [0xFF; SHA256_LEN] };
+    report.image_count = 1;
+    report.firmware[0] = Some(SystemResourceEntry {
+        fw_class: Guid(0x8BE4DF61, 0x93CA, 0x11D2, [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C]),
+        fw_type: 1,
+        fw_version: 2,
+        lowest_supported_fw_version: 1,
+        capsule_flags: 0,
+        last_attempt_version: 2,
+        last_attempt_status: 0,
+    });
+
+    let mut out = JsonBuf([0; 1024], 0);
+    report.write_json(&mut out).unwrap();
+    assert_eq!(str::from_utf8(&out.0[..out.1]).unwrap(), concat!(
+        "{\"firmware_vendor\":\"\",\"firmware_revision\":65536,\"bios_version\":\"F.10\",",
+        "\"secure_boot\":true,\"setup_mode\":false,",
+        "\"pcrs\":[{\"index\":0,\"sha256\":\"0101010101010101010101010101010101010101010101010101010101010101\"},",
+        "{\"index\":7,\"sha256\":\"abababababababababababababababababababababababababababababababab\"}],",
+        "\"images\":[{\"base\":4096,\"size\":512,\"sha256\":\"ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff\"}],",
+        "\"esrt\":[{\"fw_class\":\"8BE4DF61-93CA-11D2-AA0D-00E098032B8C\",\"fw_type\":1,\"fw_version\":2,",
+        "\"lowest_supported_fw_version\":1,\"last_attempt_version\":2,\"last_attempt_status\":0}]}"));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn audit_json_escapes_vendor() {
+    // A quote, a backslash, a newline, a non-ASCII character and an unpaired surrogate.
+    let report = empty_report(&[0x41, 0x22, 0x5C, 0x0A, 0xE9, 0xD800, 0x42]);
+    let mut out = JsonBuf([0; 1024], 0);
+    report.write_json(&mut out).unwrap();
+    let json = str::from_utf8(&out.0[..out.1]).unwrap();
+    assert!(json.starts_with("{\"firmware_vendor\":\"A\\\"\\\\\\u000a\u{e9}\u{fffd}B\",\"firmware_revision\":0,\"bios_version\":null,"));
+    assert!(json.ends_with("\"secure_boot\":null,\"setup_mode\":null,\"pcrs\":[],\"images\":[],\"esrt\":[]}"));
+}
+
+/// Check that `out` is the concatenation of `parts`.
+#[cfg(all(test, feature = "cbor"))]
+fn assert_cbor(out: &[u8], parts: &[&[u8]]) {
+    let mut pos = 0;
+    for part in parts {
+        assert_eq!(&out[pos..pos + part.len()], *part, "at offset {}", pos);
+        pos += part.len();
+    }
+    assert_eq!(out.len(), pos);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_heads() {
+    let mut buf = [0u8; 16];
+    for &(value, expected) in &[
+        (0u64, &[0x60u8][..]),
+        (23, &[0x77]),
+        (24, &[0x78, 0x18]),
+        (255, &[0x78, 0xFF]),
+        (256, &[0x79, 0x01, 0x00]),
+        (0xFFFF, &[0x79, 0xFF, 0xFF]),
+        (0x10000, &[0x7A, 0x00, 0x01, 0x00, 0x00]),
+        (0x1_0000_0000, &[0x7B, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]),
+    ] {
+        let mut c = Cbor { buf: &mut buf, pos: 0 };
+        c.head(3, value).unwrap();
+        let len = c.pos;
+        assert_eq!(&buf[..len], expected, "head for {}", value);
+    }
+
+    let mut c = Cbor { buf: &mut buf[..2], pos: 0 };
+    assert_eq!(c.head(2, 256), Err(Status::BufferTooSmall));
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn audit_cbor() {
+    // Twelve characters, so the UCS-2 vendor takes the first two-byte length header.
+    let vendor: [u16; 12] = [0x46, 0x69, 0x72, 0x6D, 0x77, 0x61, 0x72, 0x65, 0x20, 0x49, 0x6E, 0x63];
+    let mut report = empty_report(&vendor);
+    report.firmware_revision = 0x10000;
+    report.secure_boot = Some(true);
+    report.pcrs[7] = Some([0xAB; SHA256_LEN]);
+
+    let mut buf = [0u8; 256];
+    let len = report.write_cbor(&mut buf).unwrap();
+    assert_cbor(&buf[..len], &[
+        b"\xA8",
+        b"\x6Ffirmware_vendor",
+        b"\x58\x18F\0i\0r\0m\0w\0a\0r\0e\0 \0I\0n\0c\0",
+        b"\x71firmware_revision\x1A\x00\x01\x00\x00",
+        b"\x6Cbios_version\xF6",
+        b"\x6Bsecure_boot\xF5",
+        b"\x6Asetup_mode\xF6",
+        b"\x64pcrs\x81\xA2\x65index\x07\x66sha256\x58\x20",
+        &[0xAB; SHA256_LEN],
+        b"\x66images\x80",
+        b"\x64esrt\x80",
+    ]);
+
+    assert_eq!(report.write_cbor(&mut buf[..len - 1]), Err(Status::BufferTooSmall));
+}
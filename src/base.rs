@@ -1,16 +1,46 @@
-use core::{default, fmt, ptr, slice};
+use core::{cmp, default, fmt, ptr, slice};
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::time::Duration;
 
 use void::CVoid;
 
 /// Type for EFI_HANDLE.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub struct Handle(*mut CVoid);
 
+impl Handle {
+    /// The null handle, e.g. for an agent or controller handle that is not used.
+    pub const NULL: Handle = Handle(ptr::null_mut());
+
+    pub fn is_null(&self) -> bool {
+        self.0.is_null()
+    }
+}
+
 impl default::Default for Handle {
     fn default() -> Handle { Handle(ptr::null_mut()) }
 }
 
+/// A `Handle` that can be set through a shared reference, laid out as an EFI_HANDLE, for
+/// structures the firmware reads while the crate may update them.
+#[repr(transparent)]
+pub(crate) struct AtomicHandle(AtomicPtr<CVoid>);
+
+impl AtomicHandle {
+    pub(crate) const fn new(handle: Handle) -> AtomicHandle {
+        AtomicHandle(AtomicPtr::new(handle.0))
+    }
+
+    pub(crate) fn load(&self) -> Handle {
+        Handle(self.0.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn store(&self, handle: Handle) {
+        self.0.store(handle.0, Ordering::Relaxed)
+    }
+}
+
 #[derive(Debug)]
 pub struct Handles(*const Handle, usize);
 
@@ -18,13 +48,55 @@ impl Handles {
     pub fn new(p: *const Handle, len: usize) -> Handles {
         return Handles(p, len);
     }
+
+    /// No handles, as `BootServices::find_handles` returns when nothing supports the protocol.
+    pub fn empty() -> Handles {
+        Handles(ptr::null(), 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_slice(&self) -> &[Handle] {
+        if self.0.is_null() {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.0, self.1) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [Handle] {
+        if self.0.is_null() {
+            return &mut [];
+        }
+        unsafe { slice::from_raw_parts_mut(self.0 as *mut Handle, self.1) }
+    }
+
+    /// Keep only the handles `f` accepts, in order, in the same buffer.
+    pub fn retain<F: FnMut(Handle) -> bool>(&mut self, mut f: F) {
+        let mut kept = 0;
+        for i in 0..self.len() {
+            let handle = self.as_slice()[i];
+            if f(handle) {
+                self.as_mut_slice()[kept] = handle;
+                kept += 1;
+            }
+        }
+        self.1 = kept;
+    }
 }
 
 #[cfg(target_os = "efi")]
 impl ::core::ops::Drop for Handles {
 	fn drop(&mut self) {
-        let bs = systemtable::get_system_table().boot_services();
-        bs.free_pool(self.0);
+        if !self.0.is_null() {
+            let bs = systemtable::get_system_table().boot_services();
+            bs.free_pool(self.0);
+        }
     }
 }
 
@@ -49,8 +121,7 @@ impl<'a> ::core::iter::Iterator for HandlesIterator<'a> {
     type Item = &'a Handle;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let sl = unsafe { slice::from_raw_parts(self.handles.0, self.handles.1) };
-        let item = sl.get(self.offset);
+        let item = self.handles.as_slice().get(self.offset);
         self.offset += 1;
         return item;
     }
@@ -58,27 +129,52 @@ impl<'a> ::core::iter::Iterator for HandlesIterator<'a> {
 
 impl<'a> ::core::iter::ExactSizeIterator for HandlesIterator<'a> {
     fn len(&self) -> usize {
-        self.handles.1
+        self.handles.len().saturating_sub(self.offset)
     }
 }
 
+#[test]
+fn handles_empty() {
+    let handles = Handles::empty();
+    assert!(handles.is_empty());
+    assert_eq!(handles.as_slice(), &[]);
+    assert_eq!((&handles).into_iter().len(), 0);
+    assert_eq!((&handles).into_iter().next(), None);
+}
+
+#[test]
+fn handles_retain() {
+    let mut buf = [Handle(1 as *mut CVoid), Handle(2 as *mut CVoid), Handle(3 as *mut CVoid)];
+    let mut handles = Handles::new(buf.as_mut_ptr(), buf.len());
+    handles.retain(|handle| handle.0 as usize != 2);
+    assert_eq!(handles.as_slice(), &[Handle(1 as *mut CVoid), Handle(3 as *mut CVoid)]);
+}
+
 /// Type for EFI_EVENT.
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Event(pub *mut CVoid);
 
 #[cfg(target_pointer_width = "32")]
-const ERR_FLAG: u32 = 1 << 31;
+pub(crate) const ERR_FLAG: u32 = 1 << 31;
 
 #[cfg(target_pointer_width = "64")]
-const ERR_FLAG: u64 = 1 << 63;
+pub(crate) const ERR_FLAG: u64 = 1 << 63;
 
 /// Type for EFI_STATUS
 #[cfg_attr(target_pointer_width = "32", repr(u32))]
 #[cfg_attr(target_pointer_width = "64", repr(u64))]
 #[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum Status {
     Success = 0,
+    WarnUnknownGlyph = 1,
+    WarnDeleteFailure = 2,
+    WarnWriteFailure = 3,
+    WarnBufferTooSmall = 4,
+    WarnStaleData = 5,
+    WarnFileSystem = 6,
+    WarnResetRequired = 7,
     LoadError = 1 | ERR_FLAG,
     InvalidParameter = 2 | ERR_FLAG,
     Unsupported = 3 | ERR_FLAG,
@@ -108,12 +204,80 @@ pub enum Status {
     CrcError = 27 | ERR_FLAG,
     EndOfMedia = 28 | ERR_FLAG,
     EndOfFile = 31 | ERR_FLAG,
+    InvalidLanguage = 32 | ERR_FLAG,
+    CompromisedData = 33 | ERR_FLAG,
+    IpAddressConflict = 34 | ERR_FLAG,
+    HttpError = 35 | ERR_FLAG,
 }
 
+/// Every defined status, for `Status::from_usize`.
+const STATUSES: [Status; 41] = [
+    Status::Success,
+    Status::WarnUnknownGlyph,
+    Status::WarnDeleteFailure,
+    Status::WarnWriteFailure,
+    Status::WarnBufferTooSmall,
+    Status::WarnStaleData,
+    Status::WarnFileSystem,
+    Status::WarnResetRequired,
+    Status::LoadError,
+    Status::InvalidParameter,
+    Status::Unsupported,
+    Status::BadBufferSize,
+    Status::BufferTooSmall,
+    Status::NotReady,
+    Status::DeviceError,
+    Status::WriteProtected,
+    Status::OutOfResources,
+    Status::VolumeCorrupted,
+    Status::VolumeFull,
+    Status::NoMedia,
+    Status::MediaChanged,
+    Status::NotFound,
+    Status::AccessDenied,
+    Status::NoResponse,
+    Status::NoMapping,
+    Status::Timeout,
+    Status::NotStarted,
+    Status::AlreadyStarted,
+    Status::Aborted,
+    Status::IcmpError,
+    Status::TftpError,
+    Status::ProtocolError,
+    Status::IncompatibleVersion,
+    Status::SecurityViolation,
+    Status::CrcError,
+    Status::EndOfMedia,
+    Status::EndOfFile,
+    Status::InvalidLanguage,
+    Status::CompromisedData,
+    Status::IpAddressConflict,
+    Status::HttpError,
+];
+
 impl Status {
+    /// The status with the EFI_STATUS value `raw`, as returned by function pointers this crate
+    /// doesn't bind, such as those of OEM protocols. `None` for values the UEFI specification
+    /// doesn't define, including OEM-specific errors.
+    pub fn from_usize(raw: usize) -> Option<Status> {
+        STATUSES.iter().cloned().find(|status| status.as_usize() == raw)
+    }
+
+    /// The EFI_STATUS value of the status, with the error flag in the top bit.
+    pub fn as_usize(self) -> usize {
+        self as usize
+    }
+
     pub fn str(&self) -> &'static str {
         match *self {
             Status::Success => "success",
+            Status::WarnUnknownGlyph => "unknown glyph",
+            Status::WarnDeleteFailure => "delete failure",
+            Status::WarnWriteFailure => "write failure",
+            Status::WarnBufferTooSmall => "buffer too small (warning)",
+            Status::WarnStaleData => "stale data",
+            Status::WarnFileSystem => "file system warning",
+            Status::WarnResetRequired => "reset required",
             Status::LoadError => "load error",
             Status::InvalidParameter => "invalid parameter",
             Status::Unsupported => "unsupported",
@@ -143,6 +307,10 @@ impl Status {
             Status::CrcError => "CRC error",
             Status::EndOfMedia => "end of media",
             Status::EndOfFile => "end of file",
+            Status::InvalidLanguage => "invalid language",
+            Status::CompromisedData => "compromised data",
+            Status::IpAddressConflict => "IP address conflict",
+            Status::HttpError => "HTTP error",
         }
     }
 }
@@ -153,13 +321,52 @@ impl fmt::Display for Status {
     }
 }
 
+/// The value of an entry point returning `status`. Entry points are declared as returning
+/// `isize`; this keeps the bit pattern of EFI_STATUS, so errors are negative.
+impl From<Status> for isize {
+    fn from(status: Status) -> isize {
+        status.as_usize() as isize
+    }
+}
+
+/// `Ok` for `Status::Success`, and the status as the error otherwise, for calling EFI
+/// functions directly.
+pub fn status_to_result(status: Status) -> Result<(), Status> {
+    match status {
+        Status::Success => Ok(()),
+        e => Err(e),
+    }
+}
+
 #[test]
 fn status_str() {
     assert_eq!(Status::Success.str(), "success");
 }
 
+#[test]
+fn status_conversions() {
+    for &status in STATUSES.iter() {
+        assert_eq!(Status::from_usize(status.as_usize()), Some(status));
+    }
+    assert_eq!(Status::from_usize(0), Some(Status::Success));
+    assert_eq!(Status::from_usize(ERR_FLAG as usize | 32), Some(Status::InvalidLanguage));
+    assert_eq!(Status::from_usize(ERR_FLAG as usize | 33), Some(Status::CompromisedData));
+    assert_eq!(Status::from_usize(ERR_FLAG as usize | 34), Some(Status::IpAddressConflict));
+    assert_eq!(Status::from_usize(ERR_FLAG as usize | 35), Some(Status::HttpError));
+    assert_eq!(Status::HttpError.str(), "HTTP error");
+    // Codes the specification leaves unassigned.
+    assert_eq!(Status::from_usize(ERR_FLAG as usize | 29), None);
+    assert_eq!(Status::from_usize(ERR_FLAG as usize | 36), None);
+    assert_eq!(Status::from_usize(Status::NotFound.as_usize() + 1000), None);
+    assert!(isize::from(Status::NotFound) < 0);
+    assert_eq!(isize::from(Status::WarnStaleData), 5);
+    assert_eq!(status_to_result(Status::Success), Ok(()));
+    assert_eq!(status_to_result(Status::Aborted), Err(Status::Aborted));
+}
+
 /// Type for EFI_MEMORY_TYPE
 #[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(C)]
 pub enum MemoryType {
     Reserved = 0,
@@ -212,12 +419,318 @@ pub struct Time {
     __pad2: u8,
 }
 
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Time {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("Time", 9)?;
+        s.serialize_field("year", &self.year)?;
+        s.serialize_field("month", &self.month)?;
+        s.serialize_field("day", &self.day)?;
+        s.serialize_field("hour", &self.hour)?;
+        s.serialize_field("minute", &self.minute)?;
+        s.serialize_field("second", &self.second)?;
+        s.serialize_field("nanosecond", &self.nanosecond)?;
+        s.serialize_field("timezone", &self.timezone)?;
+        s.serialize_field("daylight", &self.daylight)?;
+        s.end()
+    }
+}
+
 impl fmt::Display for Time {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
     }
 }
 
+/// Value of `Time::timezone` meaning the time is local time with no known offset from UTC.
+pub const EFI_UNSPECIFIED_TIMEZONE: i16 = 0x07FF;
+
+/// Bit of `Time::daylight`: the time is affected by daylight savings time.
+pub const EFI_TIME_ADJUST_DAYLIGHT: u8 = 0x01;
+
+/// Bit of `Time::daylight`: the time has been adjusted for daylight savings time.
+pub const EFI_TIME_IN_DAYLIGHT: u8 = 0x02;
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// The proleptic Gregorian (year, month, day) of a count of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl Time {
+    /// Create a time, validating each field against the ranges the UEFI specification allows.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8, nanosecond: u32,
+               timezone: i16) -> Result<Time, Status> {
+        let time = Time {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond,
+            timezone,
+            ..Default::default()
+        };
+
+        if !time.is_valid() {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(time)
+    }
+
+    /// Create a UTC time from seconds and nanoseconds since the Unix epoch. Returns `None` if the
+    /// result is outside the years UEFI can represent (1900-9999).
+    pub fn from_unix(seconds: i64, nanosecond: u32) -> Option<Time> {
+        Time::from_local_seconds(seconds, nanosecond, 0, 0)
+    }
+
+    fn from_local_seconds(seconds: i64, nanosecond: u32, timezone: i16, daylight: u8) -> Option<Time> {
+        if nanosecond >= NANOS_PER_SEC {
+            return None;
+        }
+
+        let days = seconds.div_euclid(86400);
+        let secs = seconds.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        if !(1900..=9999).contains(&year) {
+            return None;
+        }
+
+        Some(Time {
+            year: year as u16,
+            month,
+            day,
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+            nanosecond,
+            timezone,
+            daylight,
+            ..Default::default()
+        })
+    }
+
+    /// Check that every field is within the range the UEFI specification allows, including the
+    /// day against the length of the month.
+    pub fn is_valid(&self) -> bool {
+        (1900..=9999).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && self.day >= 1 && self.day <= days_in_month(self.year as i64, self.month)
+            && self.hour <= 23
+            && self.minute <= 59
+            && self.second <= 59
+            && self.nanosecond < NANOS_PER_SEC
+            && ((-1440..=1440).contains(&self.timezone) || self.timezone == EFI_UNSPECIFIED_TIMEZONE)
+            && self.daylight & !(EFI_TIME_ADJUST_DAYLIGHT | EFI_TIME_IN_DAYLIGHT) == 0
+    }
+
+    /// The raw daylight savings bits (`EFI_TIME_ADJUST_DAYLIGHT`, `EFI_TIME_IN_DAYLIGHT`).
+    pub fn daylight(&self) -> u8 {
+        self.daylight
+    }
+
+    pub fn set_daylight(&mut self, daylight: u8) {
+        self.daylight = daylight;
+    }
+
+    /// True if the timezone is `EFI_UNSPECIFIED_TIMEZONE`.
+    pub fn is_local(&self) -> bool {
+        self.timezone == EFI_UNSPECIFIED_TIMEZONE
+    }
+
+    /// Seconds since the Unix epoch of the fields as written, ignoring the timezone.
+    fn local_seconds(&self) -> i64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    /// Seconds to add to the local time to get UTC. Per the specification, local time is UTC
+    /// minus `timezone` minutes, and one hour later again with `EFI_TIME_IN_DAYLIGHT` set. An
+    /// unspecified timezone is treated as UTC, daylight bits and all.
+    fn offset_seconds(&self) -> i64 {
+        if self.is_local() {
+            return 0;
+        }
+        let daylight = if self.daylight & EFI_TIME_IN_DAYLIGHT != 0 { 3600 } else { 0 };
+        self.timezone as i64 * 60 - daylight
+    }
+
+    fn instant(&self) -> (i64, u32) {
+        (self.local_seconds() + self.offset_seconds(), self.nanosecond)
+    }
+
+    /// Seconds since the Unix epoch, in UTC. Times with an unspecified timezone are treated as
+    /// UTC. Returns `None` if the time is not valid.
+    pub fn to_unix(&self) -> Option<i64> {
+        if !self.is_valid() {
+            return None;
+        }
+        Some(self.instant().0)
+    }
+
+    /// The same instant expressed in UTC.
+    pub fn to_utc(&self) -> Option<Time> {
+        let (seconds, nanos) = self.instant();
+        Time::from_unix(seconds, nanos)
+    }
+
+    /// Add `duration`, keeping this time's timezone and daylight bits.
+    pub fn checked_add(&self, duration: Duration) -> Option<Time> {
+        let secs = duration.as_secs();
+        if secs > i64::MAX as u64 {
+            return None;
+        }
+
+        let mut seconds = self.local_seconds().checked_add(secs as i64)?;
+        let mut nanos = self.nanosecond + duration.subsec_nanos();
+        if nanos >= NANOS_PER_SEC {
+            nanos -= NANOS_PER_SEC;
+            seconds = seconds.checked_add(1)?;
+        }
+
+        Time::from_local_seconds(seconds, nanos, self.timezone, self.daylight)
+    }
+
+    /// Subtract `duration`, keeping this time's timezone and daylight bits.
+    pub fn checked_sub(&self, duration: Duration) -> Option<Time> {
+        let secs = duration.as_secs();
+        if secs > i64::MAX as u64 {
+            return None;
+        }
+
+        let mut seconds = self.local_seconds().checked_sub(secs as i64)?;
+        let mut nanos = self.nanosecond;
+        if nanos < duration.subsec_nanos() {
+            nanos += NANOS_PER_SEC;
+            seconds = seconds.checked_sub(1)?;
+        }
+
+        Time::from_local_seconds(seconds, nanos - duration.subsec_nanos(), self.timezone, self.daylight)
+    }
+
+    /// The time elapsed from `earlier` to this time, or `None` if `earlier` is later.
+    pub fn duration_since(&self, earlier: &Time) -> Option<Duration> {
+        let (a_secs, a_nanos) = self.instant();
+        let (b_secs, b_nanos) = earlier.instant();
+
+        let (mut secs, mut nanos) = (a_secs - b_secs, a_nanos as i64 - b_nanos as i64);
+        if nanos < 0 {
+            nanos += NANOS_PER_SEC as i64;
+            secs -= 1;
+        }
+        if secs < 0 {
+            return None;
+        }
+
+        Some(Duration::new(secs as u64, nanos as u32))
+    }
+}
+
+/// Times compare by the instant they represent, so the same moment in two timezones is equal.
+impl PartialEq for Time {
+    fn eq(&self, other: &Time) -> bool {
+        self.instant() == other.instant()
+    }
+}
+
+impl Eq for Time {}
+
+impl PartialOrd for Time {
+    fn partial_cmp(&self, other: &Time) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Time {
+    fn cmp(&self, other: &Time) -> cmp::Ordering {
+        self.instant().cmp(&other.instant())
+    }
+}
+
+#[test]
+fn time_unix_roundtrip() {
+    let t = Time::new(2017, 3, 1, 12, 30, 15, 0, 0).unwrap();
+    assert_eq!(t.to_unix(), Some(1488371415));
+    assert_eq!(Time::from_unix(1488371415, 0), Some(t));
+    assert!(Time::new(2016, 2, 29, 0, 0, 0, 0, 0).is_ok());
+    assert!(Time::new(2017, 2, 29, 0, 0, 0, 0, 0).is_err());
+}
+
+#[test]
+fn time_arithmetic() {
+    // 01:00 in a timezone of -60, an hour ahead of UTC, is midnight UTC.
+    let local = Time::new(2000, 1, 1, 1, 0, 0, 0, -60).unwrap();
+    let utc = Time::new(2000, 1, 1, 0, 0, 0, 0, 0).unwrap();
+    assert_eq!(local, utc);
+
+    let later = utc.checked_add(Duration::new(86400 + 1, 500)).unwrap();
+    assert_eq!((later.day, later.second, later.nanosecond), (2, 1, 500));
+    assert_eq!(later.duration_since(&utc), Some(Duration::new(86401, 500)));
+    assert_eq!(utc.duration_since(&later), None);
+    assert_eq!(later.checked_sub(Duration::new(86401, 500)), Some(utc));
+    assert!(utc < later);
+}
+
+#[test]
+fn time_zones() {
+    // 13:30:15 in Central Europe, UTC+1, is 12:30:15 UTC.
+    let cet = Time::new(2017, 3, 1, 13, 30, 15, 0, -60).unwrap();
+    assert_eq!(cet.to_unix(), Some(1488371415));
+    // 07:30:15 in New York, UTC-5.
+    let est = Time::new(2017, 3, 1, 7, 30, 15, 0, 300).unwrap();
+    assert_eq!(est.to_unix(), Some(1488371415));
+    assert_eq!(est, cet);
+
+    // 14:00 in Central European summer time, UTC+2, is 12:00 UTC.
+    let mut cest = Time::new(2017, 7, 1, 14, 0, 0, 0, -60).unwrap();
+    cest.set_daylight(EFI_TIME_ADJUST_DAYLIGHT | EFI_TIME_IN_DAYLIGHT);
+    assert_eq!(cest.to_unix(), Some(1498910400));
+    let utc = cest.to_utc().unwrap();
+    assert_eq!((utc.hour, utc.timezone, utc.daylight()), (12, 0, 0));
+
+    let unspecified = Time::new(2017, 3, 1, 12, 30, 15, 0, EFI_UNSPECIFIED_TIMEZONE).unwrap();
+    assert_eq!(unspecified.to_unix(), Some(1488371415));
+}
+
 #[repr(C)]
 pub struct TimeCapabilities {
     resolution: u32,
@@ -228,6 +741,34 @@ pub struct TimeCapabilities {
 pub type PhysicalAddress = u64;
 pub type VirtualAddress = u64;
 
+// bitflags 0.9 expands to the deprecated `try!`, so the flags get a module of their own.
+#[allow(deprecated)]
+mod memory_attribute {
+    bitflags! {
+        /// Capabilities and permissions of a memory region, from the memory map or the Memory
+        /// Attributes Table.
+        #[cfg_attr(feature = "serde", derive(Serialize))]
+        pub struct MemoryAttribute: u64 {
+            const EFI_MEMORY_UC = 0x0000000000000001;
+            const EFI_MEMORY_WC = 0x0000000000000002;
+            const EFI_MEMORY_WT = 0x0000000000000004;
+            const EFI_MEMORY_WB = 0x0000000000000008;
+            const EFI_MEMORY_UCE = 0x0000000000000010;
+            const EFI_MEMORY_WP = 0x0000000000001000;
+            const EFI_MEMORY_RP = 0x0000000000002000;
+            const EFI_MEMORY_XP = 0x0000000000004000;
+            const EFI_MEMORY_NV = 0x0000000000008000;
+            const EFI_MEMORY_MORE_RELIABLE = 0x0000000000010000;
+            const EFI_MEMORY_RO = 0x0000000000020000;
+            const EFI_MEMORY_SP = 0x0000000000040000;
+            const EFI_MEMORY_CPU_CRYPTO = 0x0000000000080000;
+            const EFI_MEMORY_RUNTIME = 0x8000000000000000;
+        }
+    }
+}
+pub use self::memory_attribute::*;
+
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(C)]
 pub struct MemoryDescriptor {
     memory_type: MemoryType,
@@ -236,3 +777,25 @@ pub struct MemoryDescriptor {
     number_of_pages: u64,
     attribute: u64
 }
+
+impl MemoryDescriptor {
+    pub fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    pub fn physical_start(&self) -> PhysicalAddress {
+        self.physical_start
+    }
+
+    pub fn virtual_start(&self) -> VirtualAddress {
+        self.virtual_start
+    }
+
+    pub fn number_of_pages(&self) -> u64 {
+        self.number_of_pages
+    }
+
+    pub fn attribute(&self) -> MemoryAttribute {
+        MemoryAttribute::from_bits_truncate(self.attribute)
+    }
+}
@@ -0,0 +1,212 @@
+use core::str;
+
+use base::Status;
+use guid::Guid;
+use protocol::{DevicePathNode, DevicePathTypes, BIOSSubTypes, DEVICE_PATH_NODE_HEADER_SIZE};
+use util::wire;
+
+/// GUID of EFI_LEGACY_BIOS_PROTOCOL, which is only installed by firmware with a Compatibility
+/// Support Module.
+pub static EFI_LEGACY_BIOS_PROTOCOL_GUID: Guid = Guid(0xDB9A1E3D, 0x45CB, 0x4ABB, [0x85, 0x3B, 0xE5, 0x38, 0x7F, 0xDB, 0x2E, 0x2D]);
+
+/// Vendor GUID of the `LegacyDevOrder` variable, in which EDK2-based firmware keeps the order of
+/// legacy boot devices.
+pub static EFI_LEGACY_DEV_ORDER_VARIABLE_GUID: Guid = Guid(0xA56074DB, 0x65FE, 0x45F7, [0xBD, 0x21, 0x2D, 0x2B, 0xDD, 0x8E, 0x96, 0x52]);
+
+/// Name of the legacy boot order variable.
+pub const LEGACY_DEV_ORDER_VARIABLE: &str = "LegacyDevOrder";
+
+/// Device type of a BBS device path node, from the BIOS Boot Specification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub enum BbsDeviceType {
+    Floppy = 0x01,
+    HardDrive = 0x02,
+    CdRom = 0x03,
+    Pcmcia = 0x04,
+    Usb = 0x05,
+    EmbeddedNetwork = 0x06,
+    /// A Boot Entry Vector device, such as a network option ROM.
+    Bev = 0x80,
+    Unknown = 0xFF,
+}
+
+impl BbsDeviceType {
+    pub fn from_u16(v: u16) -> BbsDeviceType {
+        match v {
+            0x01 => BbsDeviceType::Floppy,
+            0x02 => BbsDeviceType::HardDrive,
+            0x03 => BbsDeviceType::CdRom,
+            0x04 => BbsDeviceType::Pcmcia,
+            0x05 => BbsDeviceType::Usb,
+            0x06 => BbsDeviceType::EmbeddedNetwork,
+            0x80 => BbsDeviceType::Bev,
+            _ => BbsDeviceType::Unknown,
+        }
+    }
+}
+
+/// Offset of the description string in a BBS node.
+const BBS_DESCRIPTION_OFFSET: usize = DEVICE_PATH_NODE_HEADER_SIZE + 4;
+
+/// A BIOS Boot Specification device path node, which identifies a legacy boot device in a
+/// `Boot####` load option.
+#[derive(Clone, Copy, Debug)]
+pub struct BbsNode<'a> {
+    pub device_type: BbsDeviceType,
+    pub status_flag: u16,
+    /// The description, as a null-terminated ASCII string in the node.
+    description: &'a [u8],
+}
+
+impl<'a> BbsNode<'a> {
+    /// Parse `node`, failing with `Status::InvalidParameter` if it is not a BBS node or is too
+    /// short.
+    pub fn parse(node: &DevicePathNode<'a>) -> Result<BbsNode<'a>, Status> {
+        if node.node_type() != DevicePathTypes::BIOSBootSpecification.into() ||
+           node.sub_type() != BIOSSubTypes::BIOSBootSpecification.into() {
+            return Err(Status::InvalidParameter);
+        }
+
+        let bytes = node.as_bytes();
+        let device_type = wire::read_u16(bytes, 4).map_err(|_| Status::InvalidParameter)?;
+        let status_flag = wire::read_u16(bytes, 6).map_err(|_| Status::InvalidParameter)?;
+        let description = bytes.get(BBS_DESCRIPTION_OFFSET..).ok_or(Status::InvalidParameter)?;
+        let len = description.iter().position(|&b| b == 0).unwrap_or(description.len());
+
+        Ok(BbsNode {
+            device_type: BbsDeviceType::from_u16(device_type),
+            status_flag,
+            description: &description[..len],
+        })
+    }
+
+    /// The description, such as "SATA: WDC WD5000". Non-ASCII descriptions are returned as
+    /// `None`.
+    pub fn description(&self) -> Option<&'a str> {
+        if !self.description.is_ascii() {
+            return None;
+        }
+        str::from_utf8(self.description).ok()
+    }
+}
+
+/// Write a BBS device path node into the start of `buf`, returning its length.
+/// `description` must be ASCII.
+pub fn write_bbs_node(buf: &mut [u8], device_type: BbsDeviceType, status_flag: u16, description: &str)
+                      -> Result<usize, Status> {
+    if !description.is_ascii() {
+        return Err(Status::InvalidParameter);
+    }
+
+    let len = BBS_DESCRIPTION_OFFSET + description.len() + 1;
+    if len > u16::MAX as usize {
+        return Err(Status::InvalidParameter);
+    }
+    if len > buf.len() {
+        return Err(Status::BufferTooSmall);
+    }
+
+    wire::write_u8(buf, 0, DevicePathTypes::BIOSBootSpecification.into())?;
+    wire::write_u8(buf, 1, BIOSSubTypes::BIOSBootSpecification.into())?;
+    wire::write_u16(buf, 2, len as u16)?;
+    wire::write_u16(buf, 4, device_type as u16)?;
+    wire::write_u16(buf, 6, status_flag)?;
+    buf[BBS_DESCRIPTION_OFFSET..len - 1].copy_from_slice(description.as_bytes());
+    buf[len - 1] = 0;
+
+    Ok(len)
+}
+
+/// Whether the firmware has a Compatibility Support Module, and so can boot legacy devices.
+pub fn csm_present() -> bool {
+    ::get_system_table()
+        .boot_services()
+        .locate_protocol_by_guid(&EFI_LEGACY_BIOS_PROTOCOL_GUID)
+        .is_ok_and(|i| !i.as_ptr().is_null())
+}
+
+/// The legacy boot device order, as read from the `LegacyDevOrder` variable: for each device
+/// type, the BBS table indices of the devices of that type, in boot order.
+pub struct LegacyDevOrder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> LegacyDevOrder<'a> {
+    /// Read the legacy boot order into `buf`. Fails with `Status::Unsupported` if the firmware
+    /// has no CSM, and `Status::NotFound` if it does not keep the variable.
+    pub fn read(buf: &'a mut [u8]) -> Result<LegacyDevOrder<'a>, Status> {
+        if !csm_present() {
+            return Err(Status::Unsupported);
+        }
+
+        let (size, _) = ::get_system_table()
+            .runtime_services()
+            .get_variable(LEGACY_DEV_ORDER_VARIABLE, &EFI_LEGACY_DEV_ORDER_VARIABLE_GUID, buf)?;
+        Ok(LegacyDevOrder { data: &buf[..size] })
+    }
+
+    /// View a `LegacyDevOrder` variable's contents.
+    pub fn from_bytes(data: &'a [u8]) -> LegacyDevOrder<'a> {
+        LegacyDevOrder { data }
+    }
+
+    /// The groups of devices, one per device type. Iteration stops at a malformed group.
+    pub fn groups(&self) -> LegacyDevOrderGroups<'a> {
+        LegacyDevOrderGroups { data: self.data }
+    }
+}
+
+/// Iterator returned by `LegacyDevOrder::groups`.
+pub struct LegacyDevOrderGroups<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for LegacyDevOrderGroups<'a> {
+    type Item = LegacyDevGroup<'a>;
+
+    fn next(&mut self) -> Option<LegacyDevGroup<'a>> {
+        // Each group is a u32 BBS type, then a u16 length which counts itself and the u16
+        // indices that follow.
+        let device_type = wire::read_u32(self.data, 0).ok()?;
+        let len = wire::read_u16(self.data, 4).ok()? as usize;
+        if len < 2 {
+            return None;
+        }
+        let indices = self.data.get(6..4 + len)?;
+        self.data = &self.data[4 + len..];
+
+        Some(LegacyDevGroup {
+            device_type: BbsDeviceType::from_u16(device_type as u16),
+            indices,
+        })
+    }
+}
+
+/// The devices of one type in the legacy boot order.
+pub struct LegacyDevGroup<'a> {
+    pub device_type: BbsDeviceType,
+    indices: &'a [u8],
+}
+
+impl<'a> LegacyDevGroup<'a> {
+    /// The devices, in boot order, as (BBS table index, enabled) pairs. Firmware marks disabled
+    /// devices by setting the high byte of the index.
+    pub fn devices(&self) -> impl Iterator<Item = (u16, bool)> + 'a {
+        self.indices.chunks(2).filter(|c| c.len() == 2).map(|c| {
+            let v = u16::from_le_bytes([c[0], c[1]]);
+            (v & 0xFF, v & 0xFF00 != 0xFF00)
+        })
+    }
+}
+
+#[test]
+fn bbs_node_round_trip() {
+    let mut buf = [0u8; 32];
+    let len = write_bbs_node(&mut buf, BbsDeviceType::CdRom, 0, "ATAPI CD").unwrap();
+    assert_eq!(len, 17);
+
+    let node = BbsNode::parse(&DevicePathNode::from_bytes(&buf[..len]).unwrap()).unwrap();
+    assert_eq!(node.device_type, BbsDeviceType::CdRom);
+    assert_eq!(node.description(), Some("ATAPI CD"));
+}
@@ -0,0 +1,151 @@
+//! Boot Loader Specification entries, the `loader/entries/*.conf` drop-in files in which Linux
+//! distributions describe each installed kernel.
+
+use core::fmt;
+
+/// Most `initrd` lines kept from an entry.
+pub const MAX_BLS_INITRDS: usize = 8;
+
+/// Most `options` lines kept from an entry.
+pub const MAX_BLS_OPTIONS: usize = 16;
+
+/// A parsed BLS type #1 entry, borrowing from the file's text. Unknown keys are ignored, and
+/// for keys which may only appear once the last value wins.
+///
+/// ```rust,ignore
+/// let entry = BlsEntry::parse(text);
+/// if entry.is_bootable() {
+///     menu.add(entry.display_title(), entry);
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlsEntry<'a> {
+    pub title: Option<&'a str>,
+    pub version: Option<&'a str>,
+    pub machine_id: Option<&'a str>,
+    pub sort_key: Option<&'a str>,
+    /// The kernel, as a path on the volume the entry is on, with forward slashes.
+    pub linux: Option<&'a str>,
+    /// An EFI program to start instead of a kernel.
+    pub efi: Option<&'a str>,
+    pub devicetree: Option<&'a str>,
+    pub architecture: Option<&'a str>,
+    initrds: [&'a str; MAX_BLS_INITRDS],
+    initrd_count: usize,
+    options: [&'a str; MAX_BLS_OPTIONS],
+    option_count: usize,
+}
+
+impl<'a> BlsEntry<'a> {
+    pub fn parse(text: &'a str) -> BlsEntry<'a> {
+        let mut entry = BlsEntry::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.find(char::is_whitespace) {
+                Some(i) => (&line[..i], line[i..].trim()),
+                None => (line, ""),
+            };
+
+            match key {
+                "title" => entry.title = Some(value),
+                "version" => entry.version = Some(value),
+                "machine-id" => entry.machine_id = Some(value),
+                "sort-key" => entry.sort_key = Some(value),
+                "linux" => entry.linux = Some(value),
+                "efi" => entry.efi = Some(value),
+                "devicetree" => entry.devicetree = Some(value),
+                "architecture" => entry.architecture = Some(value),
+                "initrd" if entry.initrd_count < MAX_BLS_INITRDS => {
+                    entry.initrds[entry.initrd_count] = value;
+                    entry.initrd_count += 1;
+                }
+                "options" if entry.option_count < MAX_BLS_OPTIONS && !value.is_empty() => {
+                    entry.options[entry.option_count] = value;
+                    entry.option_count += 1;
+                }
+                _ => {}
+            }
+        }
+        entry
+    }
+
+    /// The initrds to load, in order.
+    pub fn initrds(&self) -> &[&'a str] {
+        &self.initrds[..self.initrd_count]
+    }
+
+    /// The `options` lines, in order.
+    pub fn options(&self) -> &[&'a str] {
+        &self.options[..self.option_count]
+    }
+
+    /// The kernel command line: every `options` line, separated by spaces.
+    pub fn cmdline(&self) -> BlsCmdline<'_, 'a> {
+        BlsCmdline(self.options())
+    }
+
+    /// Whether the entry names something to start.
+    pub fn is_bootable(&self) -> bool {
+        self.linux.is_some() || self.efi.is_some()
+    }
+
+    /// The title to show: `title`, or `version` without one, or "Linux".
+    pub fn display_title(&self) -> &'a str {
+        self.title.or(self.version).unwrap_or("Linux")
+    }
+
+    /// Whether the entry is for `arch`, an EFI architecture name such as "x64" or "aa64".
+    /// Entries without an architecture are for any.
+    pub fn is_for_architecture(&self, arch: &str) -> bool {
+        self.architecture.map_or(true, |a| a.eq_ignore_ascii_case(arch))
+    }
+}
+
+/// Displays the options of a `BlsEntry` as one command line.
+pub struct BlsCmdline<'e, 'a>(&'e [&'a str]);
+
+impl<'e, 'a> fmt::Display for BlsCmdline<'e, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, options) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(options)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn bls_entry_parse() {
+    let entry = BlsEntry::parse("# Fedora\n\
+        title Fedora Linux (6.8.9-300.fc40.x86_64) 40\n\
+        version 6.8.9-300.fc40.x86_64\n\
+        linux /vmlinuz-6.8.9-300.fc40.x86_64\n\
+        initrd /intel-ucode.img\n\
+        initrd /initramfs-6.8.9-300.fc40.x86_64.img\n\
+        options root=UUID=1234 ro\n\
+        options\trhgb quiet\n");
+
+    assert_eq!(entry.display_title(), "Fedora Linux (6.8.9-300.fc40.x86_64) 40");
+    assert_eq!(entry.linux, Some("/vmlinuz-6.8.9-300.fc40.x86_64"));
+    assert_eq!(entry.initrds(), &["/intel-ucode.img", "/initramfs-6.8.9-300.fc40.x86_64.img"]);
+
+    struct Buf([u8; 64], usize);
+    impl fmt::Write for Buf {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0[self.1..self.1 + s.len()].copy_from_slice(s.as_bytes());
+            self.1 += s.len();
+            Ok(())
+        }
+    }
+    let mut cmdline = Buf([0; 64], 0);
+    fmt::write(&mut cmdline, format_args!("{}", entry.cmdline())).unwrap();
+    assert_eq!(&cmdline.0[..cmdline.1], b"root=UUID=1234 ro rhgb quiet");
+    assert!(entry.is_bootable() && entry.is_for_architecture("x64"));
+    assert!(!BlsEntry::parse("title Empty\n").is_bootable());
+}
@@ -0,0 +1,138 @@
+use core::{ptr, slice};
+
+use base::Status;
+use protocol::{self, BltPixel, SimpleFileSystemProtocol};
+use util::wire;
+
+/// Size of the BITMAPFILEHEADER and BITMAPINFOHEADER that start a BMP file.
+pub const BMP_HEADER_SIZE: usize = 54;
+
+/// Bytes in each row of a 24-bit BMP, which are padded to a multiple of four.
+fn row_size(width: usize) -> usize {
+    (width * 3 + 3) / 4 * 4
+}
+
+/// Size of a 24-bit BMP file of `width` by `height` pixels, or `None` if it does not fit the
+/// format's 32-bit size field.
+pub fn bmp_size(width: usize, height: usize) -> Option<usize> {
+    let size = row_size(width).checked_mul(height)?.checked_add(BMP_HEADER_SIZE)?;
+    if size <= u32::MAX as usize { Some(size) } else { None }
+}
+
+/// Write the headers of a bottom-up 24-bit BMP of `width` by `height` pixels to the start of
+/// `buf`, which must be at least `BMP_HEADER_SIZE` bytes.
+pub fn write_bmp_header(buf: &mut [u8], width: usize, height: usize) -> Result<(), Status> {
+    let size = bmp_size(width, height).ok_or(Status::InvalidParameter)?;
+    if buf.len() < BMP_HEADER_SIZE {
+        return Err(Status::BufferTooSmall);
+    }
+
+    let header = &mut buf[..BMP_HEADER_SIZE];
+    header.fill(0);
+    header[..2].copy_from_slice(b"BM");
+    wire::write_u32(header, 2, size as u32)?;
+    wire::write_u32(header, 10, BMP_HEADER_SIZE as u32)?;
+    wire::write_u32(header, 14, 40)?;
+    wire::write_u32(header, 18, width as u32)?;
+    wire::write_u32(header, 22, height as u32)?;
+    wire::write_u16(header, 26, 1)?;
+    wire::write_u16(header, 28, 24)?;
+    wire::write_u32(header, 34, (size - BMP_HEADER_SIZE) as u32)?;
+    // 2835 pixels per metre is 72 DPI.
+    wire::write_u32(header, 38, 2835)?;
+    wire::write_u32(header, 42, 2835)?;
+    Ok(())
+}
+
+/// A 24-bit BMP image in pool memory, which is freed when this is dropped.
+pub struct BmpImage {
+    buffer: *mut u8,
+    len: usize,
+    width: usize,
+    height: usize,
+}
+
+impl BmpImage {
+    /// A black image of `width` by `height` pixels.
+    pub fn new(width: usize, height: usize) -> Result<BmpImage, Status> {
+        let len = bmp_size(width, height).ok_or(Status::InvalidParameter)?;
+        let buffer = ::get_system_table().boot_services().allocate_pool::<u8>(len)?;
+        let image = BmpImage { buffer, len, width, height };
+
+        let buf = unsafe {
+            ptr::write_bytes(buffer, 0, len);
+            slice::from_raw_parts_mut(buffer, len)
+        };
+        write_bmp_header(buf, width, height)?;
+        Ok(image)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The whole file, headers included.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.buffer, self.len) }
+    }
+
+    /// Set row `y`, counting from the top, from `pixels`. Extra pixels are ignored, and missing
+    /// ones left as they were.
+    pub fn set_row(&mut self, y: usize, pixels: &[BltPixel]) {
+        if y >= self.height {
+            return;
+        }
+
+        // Rows are stored bottom-up.
+        let size = row_size(self.width);
+        let start = BMP_HEADER_SIZE + (self.height - 1 - y) * size;
+        let row = unsafe { slice::from_raw_parts_mut(self.buffer.add(start), size) };
+        for (out, p) in row.chunks_exact_mut(3).zip(pixels.iter().take(self.width)) {
+            out.copy_from_slice(&[p.blue, p.green, p.red]);
+        }
+    }
+
+    /// Save the image as `path` on the volume the current image was loaded from, which is the
+    /// ESP when it was started from a boot entry. An existing file is replaced.
+    pub fn save<P: AsRef<str>>(&self, path: P) -> Result<(), Status> {
+        let device = protocol::get_current_image().device_handle;
+        let fs: &SimpleFileSystemProtocol = ::get_system_table().boot_services().handle_protocol(device)?;
+        let root = fs.open_volume()?;
+
+        let file = root.create_path(path);
+        root.close();
+
+        let file = file?;
+        let result = file.write_all(self.as_slice());
+        let flushed = file.flush();
+        file.close();
+        result?;
+        match flushed {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+}
+
+impl Drop for BmpImage {
+    fn drop(&mut self) {
+        ::get_system_table().boot_services().free_pool(self.buffer);
+    }
+}
+
+#[test]
+fn bmp_header() {
+    assert_eq!(bmp_size(3, 2), Some(BMP_HEADER_SIZE + 24));
+
+    let mut buf = [0u8; BMP_HEADER_SIZE];
+    write_bmp_header(&mut buf, 3, 2).unwrap();
+    assert_eq!(&buf[..2], b"BM");
+    assert_eq!(wire::read_u32(&buf, 2), Ok(78));
+    assert_eq!(wire::read_u32(&buf, 18), Ok(3));
+    assert_eq!(wire::read_u16(&buf, 28), Ok(24));
+    assert_eq!(write_bmp_header(&mut buf[..40], 3, 2), Err(Status::BufferTooSmall));
+}
@@ -0,0 +1,372 @@
+//! The firmware boot manager's entries: EFI_LOAD_OPTION structures in `Boot####` variables, and
+//! the `BootOrder` list of which to try first. This is what `efibootmgr` edits, for installers
+//! adding an entry for their loader and boot menus offering a one-time choice.
+//!
+//! ```rust,ignore
+//! let number = add_boot_option(LOAD_OPTION_ACTIVE, "My OS", &loader_path, &[], false)?;
+//! for entry in boot_options() {
+//!     let entry = entry?;
+//!     println!("Boot{:04X} {}", entry.number(), entry.option()?.description_string());
+//! }
+//! ```
+
+use core::{char, fmt, str};
+
+use base::Status;
+use protocol::DevicePath;
+use runtimeservices::{EFI_GLOBAL_VARIABLE_GUID, EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_NON_VOLATILE,
+                      EFI_VARIABLE_RUNTIME_ACCESS};
+use util::{char_to_ucs2, wire};
+
+/// Attribute bits of a load option. Inactive options stay in the boot order but are skipped;
+/// hidden ones are not shown in the firmware's boot menu.
+pub const LOAD_OPTION_ACTIVE: u32 = 0x00000001;
+pub const LOAD_OPTION_FORCE_RECONNECT: u32 = 0x00000002;
+pub const LOAD_OPTION_HIDDEN: u32 = 0x00000008;
+pub const LOAD_OPTION_CATEGORY_APP: u32 = 0x00000100;
+
+/// Largest load option read or written.
+pub const MAX_LOAD_OPTION_SIZE: usize = 4096;
+
+/// Most entries of `BootOrder` handled.
+pub const MAX_BOOT_ORDER: usize = 256;
+
+/// Size of the attributes and file path list length before the description.
+const LOAD_OPTION_HEADER_SIZE: usize = 6;
+
+/// A parsed EFI_LOAD_OPTION, borrowing the bytes it was parsed from.
+#[derive(Clone, Copy, Debug)]
+pub struct LoadOption<'a> {
+    attributes: u32,
+    description: &'a [u8],
+    file_path: DevicePath<'a>,
+    optional_data: &'a [u8],
+}
+
+impl<'a> LoadOption<'a> {
+    /// Parse `bytes`, failing with `Status::InvalidParameter` if they are too short for the
+    /// header, the description is not terminated, or the file path list is malformed or runs
+    /// past the end.
+    pub fn parse(bytes: &'a [u8]) -> Result<LoadOption<'a>, Status> {
+        let attributes = wire::read_u32(bytes, 0).map_err(|_| Status::InvalidParameter)?;
+        let list_len = wire::read_u16(bytes, 4).map_err(|_| Status::InvalidParameter)? as usize;
+
+        let mut offset = LOAD_OPTION_HEADER_SIZE;
+        while wire::read_u16(bytes, offset).map_err(|_| Status::InvalidParameter)? != 0 {
+            offset += 2;
+        }
+        let description = &bytes[LOAD_OPTION_HEADER_SIZE..offset];
+        offset += 2;
+
+        let list = bytes.get(offset..offset + list_len).ok_or(Status::InvalidParameter)?;
+        let file_path = DevicePath::from_bytes(list).map_err(|_| Status::InvalidParameter)?;
+        Ok(LoadOption { attributes, description, file_path, optional_data: &bytes[offset + list_len..] })
+    }
+
+    /// The `LOAD_OPTION_*` attribute bits.
+    pub fn attributes(&self) -> u32 {
+        self.attributes
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.attributes & LOAD_OPTION_ACTIVE != 0
+    }
+
+    /// The description shown in boot menus, as UCS-2 units.
+    pub fn description(&self) -> wire::Ucs2Units<'a> {
+        wire::read_ucs2(self.description, 0)
+    }
+
+    /// The description, for display.
+    pub fn description_string(&self) -> Description<'a> {
+        Description(*self)
+    }
+
+    /// The device path of what to boot: usually a partition and a file on it.
+    pub fn file_path(&self) -> DevicePath<'a> {
+        self.file_path
+    }
+
+    /// Data passed to the started image as its load options, such as a command line.
+    pub fn optional_data(&self) -> &'a [u8] {
+        self.optional_data
+    }
+}
+
+/// Displays the description of a `LoadOption`.
+pub struct Description<'a>(LoadOption<'a>);
+
+impl<'a> fmt::Display for Description<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in char::decode_utf16(self.0.description()) {
+            write!(f, "{}", c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
+/// Write an EFI_LOAD_OPTION into `buf`, returning its size. Fails with
+/// `Status::BufferTooSmall` if it doesn't fit.
+pub fn write_load_option(buf: &mut [u8], attributes: u32, description: &str, file_path: &DevicePath,
+                         optional_data: &[u8]) -> Result<usize, Status> {
+    let list = file_path.as_bytes();
+    if list.len() > u16::MAX as usize {
+        return Err(Status::InvalidParameter);
+    }
+    wire::write_u32(buf, 0, attributes).map_err(|_| Status::BufferTooSmall)?;
+    wire::write_u16(buf, 4, list.len() as u16).map_err(|_| Status::BufferTooSmall)?;
+
+    let mut offset = LOAD_OPTION_HEADER_SIZE;
+    offset += wire::write_ucs2(buf, offset, description.chars().map(char_to_ucs2)).map_err(|_| Status::BufferTooSmall)?;
+    for part in &[list, optional_data] {
+        buf.get_mut(offset..offset + part.len()).ok_or(Status::BufferTooSmall)?.copy_from_slice(part);
+        offset += part.len();
+    }
+    Ok(offset)
+}
+
+/// The number of a `Boot####` variable name, with exactly four hex digits. The specification
+/// has them in upper case: `Boot000a` is not a boot option.
+pub fn boot_option_number(name: &str) -> Option<u16> {
+    let digits = name.strip_prefix("Boot")?;
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit() || (b'A'..=b'F').contains(&b)) {
+        return None;
+    }
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// The `Boot####` variable name of option `number`, written into `buf`.
+pub fn boot_option_name(number: u16, buf: &mut [u8; 8]) -> &str {
+    buf[..4].copy_from_slice(b"Boot");
+    for (i, b) in buf[4..].iter_mut().enumerate() {
+        *b = b"0123456789ABCDEF"[(number >> (12 - 4 * i)) as usize & 0xF];
+    }
+    // Only ASCII was written.
+    unsafe { str::from_utf8_unchecked(buf) }
+}
+
+/// A `Boot####` variable, read by `read_boot_option` or `boot_options`.
+#[derive(Clone)]
+pub struct BootOption {
+    number: u16,
+    data: [u8; MAX_LOAD_OPTION_SIZE],
+    len: usize,
+}
+
+impl BootOption {
+    pub fn number(&self) -> u16 {
+        self.number
+    }
+
+    /// The load option, parsed.
+    pub fn option(&self) -> Result<LoadOption<'_>, Status> {
+        LoadOption::parse(self.as_bytes())
+    }
+
+    /// The raw EFI_LOAD_OPTION.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl fmt::Debug for BootOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.option() {
+            Ok(option) => write!(f, "BootOption {{ number: {:#06X}, description: \"{}\" }}", self.number, option.description_string()),
+            Err(e) => write!(f, "BootOption {{ number: {:#06X}, error: {:?} }}", self.number, e),
+        }
+    }
+}
+
+/// Read `Boot####` option `number`.
+pub fn read_boot_option(number: u16) -> Result<BootOption, Status> {
+    let mut name = [0u8; 8];
+    let mut option = BootOption { number, data: [0; MAX_LOAD_OPTION_SIZE], len: 0 };
+    let (len, _) = ::get_system_table().runtime_services()
+        .get_variable(boot_option_name(number, &mut name), &EFI_GLOBAL_VARIABLE_GUID, &mut option.data)?;
+    option.len = len;
+    Ok(option)
+}
+
+/// Create or replace `Boot####` option `number`, without changing the boot order.
+pub fn write_boot_option(number: u16, attributes: u32, description: &str, file_path: &DevicePath,
+                         optional_data: &[u8]) -> Result<(), Status> {
+    let mut data = [0u8; MAX_LOAD_OPTION_SIZE];
+    let len = write_load_option(&mut data, attributes, description, file_path, optional_data)?;
+
+    let mut name = [0u8; 8];
+    let attributes = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+    ::get_system_table().runtime_services()
+        .set_variable(boot_option_name(number, &mut name), &EFI_GLOBAL_VARIABLE_GUID, attributes, &data[..len])
+}
+
+/// Create a boot option under the lowest unused number and add it to the boot order, first if
+/// `first` is set and otherwise last. Returns its number.
+pub fn add_boot_option(attributes: u32, description: &str, file_path: &DevicePath, optional_data: &[u8],
+                       first: bool) -> Result<u16, Status> {
+    let mut used = [0u64; 0x10000 / 64];
+    for (name, _) in ::get_system_table().runtime_services().variables().with_guid(&EFI_GLOBAL_VARIABLE_GUID).with_prefix("Boot") {
+        if let Some(number) = boot_option_number(name.as_str()) {
+            used[number as usize / 64] |= 1 << (number % 64);
+        }
+    }
+    let number = (0..=0xFFFFu32).find(|&n| used[n as usize / 64] & (1 << (n % 64)) == 0).ok_or(Status::OutOfResources)? as u16;
+
+    write_boot_option(number, attributes, description, file_path, optional_data)?;
+    let mut buf = [0u16; MAX_BOOT_ORDER];
+    let order = match read_boot_order(&mut buf) {
+        Ok(order) => order,
+        Err(e) => {
+            let _ = delete_variable(number);
+            return Err(e);
+        }
+    };
+    let mut new_order = [0u16; MAX_BOOT_ORDER + 1];
+    let len = order.len() + 1;
+    if first {
+        new_order[0] = number;
+        new_order[1..len].copy_from_slice(order);
+    } else {
+        new_order[..len - 1].copy_from_slice(order);
+        new_order[len - 1] = number;
+    }
+    if let Err(e) = ::get_system_table().runtime_services().set_boot_order(&new_order[..len]) {
+        let _ = delete_variable(number);
+        return Err(e);
+    }
+    Ok(number)
+}
+
+/// Delete `Boot####` option `number` and remove it from the boot order.
+pub fn delete_boot_option(number: u16) -> Result<(), Status> {
+    let mut buf = [0u16; MAX_BOOT_ORDER];
+    let order = read_boot_order(&mut buf)?;
+    if order.contains(&number) {
+        let mut new_order = [0u16; MAX_BOOT_ORDER];
+        let mut len = 0;
+        for &n in order.iter().filter(|&&n| n != number) {
+            new_order[len] = n;
+            len += 1;
+        }
+        ::get_system_table().runtime_services().set_boot_order(&new_order[..len])?;
+    }
+    delete_variable(number)
+}
+
+/// Move option `number` to `position` in the boot order, adding it if it isn't there. A
+/// position past the end moves it last.
+pub fn move_boot_option(number: u16, position: usize) -> Result<(), Status> {
+    let mut buf = [0u16; MAX_BOOT_ORDER];
+    let order = read_boot_order(&mut buf)?;
+    let mut new_order = [0u16; MAX_BOOT_ORDER + 1];
+    let len = reorder(order, number, position, &mut new_order);
+    ::get_system_table().runtime_services().set_boot_order(&new_order[..len])
+}
+
+/// Write `order` with `number` moved or inserted at `position` into `buf`, returning the length.
+fn reorder(order: &[u16], number: u16, position: usize, buf: &mut [u16; MAX_BOOT_ORDER + 1]) -> usize {
+    let mut len = 0;
+    for &n in order.iter().filter(|&&n| n != number) {
+        buf[len] = n;
+        len += 1;
+    }
+    let position = position.min(len);
+    buf.copy_within(position..len, position + 1);
+    buf[position] = number;
+    len + 1
+}
+
+/// The boot order, or an empty one if the variable doesn't exist yet.
+fn read_boot_order(buf: &mut [u16; MAX_BOOT_ORDER]) -> Result<&[u16], Status> {
+    match ::get_system_table().runtime_services().boot_order(buf) {
+        Ok(order) => Ok(order),
+        Err(Status::NotFound) => Ok(&[]),
+        Err(e) => Err(e),
+    }
+}
+
+fn delete_variable(number: u16) -> Result<(), Status> {
+    let mut name = [0u8; 8];
+    ::get_system_table().runtime_services().delete_variable(boot_option_name(number, &mut name), &EFI_GLOBAL_VARIABLE_GUID)
+}
+
+/// Iterate over the `Boot####` options in the order the firmware lists the variables, which is
+/// not the boot order. Options larger than `MAX_LOAD_OPTION_SIZE` are yielded as errors.
+pub fn boot_options() -> BootOptions {
+    let variables = ::get_system_table().runtime_services().variables().with_guid(&EFI_GLOBAL_VARIABLE_GUID).with_prefix("Boot");
+    BootOptions { variables }
+}
+
+/// Iterator returned by `boot_options`.
+pub struct BootOptions {
+    variables: ::runtimeservices::Variables<'static>,
+}
+
+impl Iterator for BootOptions {
+    type Item = Result<BootOption, Status>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (name, _) in &mut self.variables {
+            if let Some(number) = boot_option_number(name.as_str()) {
+                return Some(read_boot_option(number));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+const TEST_PATH: [u8; 14] = [4, 4, 10, 0, b'\\', 0, b'a', 0, 0, 0, 0x7F, 0xFF, 4, 0];
+
+#[test]
+fn load_option_round_trip() {
+    let path = DevicePath::from_bytes(&TEST_PATH).unwrap();
+    let mut buf = [0u8; 64];
+    let len = write_load_option(&mut buf, LOAD_OPTION_ACTIVE, "Linux", &path, b"quiet").unwrap();
+    assert_eq!(len, LOAD_OPTION_HEADER_SIZE + 12 + path.total_len() + 5);
+    let option = LoadOption::parse(&buf[..len]).unwrap();
+    assert!(option.is_active());
+    assert!(option.description().eq("Linux".encode_utf16()));
+    assert_eq!(option.file_path().as_bytes(), path.as_bytes());
+    assert_eq!(option.optional_data(), b"quiet");
+    assert_eq!(write_load_option(&mut buf[..20], 0, "Linux", &path, &[]), Err(Status::BufferTooSmall));
+}
+
+#[test]
+fn load_option_parse_errors() {
+    let path = DevicePath::from_bytes(&TEST_PATH).unwrap();
+    let mut buf = [0u8; 64];
+    let len = write_load_option(&mut buf, LOAD_OPTION_ACTIVE, "Linux", &path, &[]).unwrap();
+
+    // Cut off in the header, the description and the file path list.
+    for &cut in &[0, 3, 5, 10, LOAD_OPTION_HEADER_SIZE + 12 + 4] {
+        assert_eq!(LoadOption::parse(&buf[..cut]).map(|_| ()), Err(Status::InvalidParameter), "cut at {}", cut);
+    }
+    // A file path list that isn't a device path.
+    let mut bad = buf;
+    bad[LOAD_OPTION_HEADER_SIZE + 12 + 2] = 0xFF;
+    assert_eq!(LoadOption::parse(&bad[..len]).map(|_| ()), Err(Status::InvalidParameter));
+}
+
+#[test]
+fn boot_option_names() {
+    let mut name = [0u8; 8];
+    assert_eq!(boot_option_name(0x1F, &mut name), "Boot001F");
+    assert_eq!(boot_option_number("Boot001F"), Some(0x1F));
+    assert_eq!(boot_option_number("BootFFFF"), Some(0xFFFF));
+    assert_eq!(boot_option_number("Boot001f"), None);
+    assert_eq!(boot_option_number("Boot01F"), None);
+    assert_eq!(boot_option_number("Boot+01F"), None);
+    assert_eq!(boot_option_number("BootOrder"), None);
+}
+
+#[test]
+fn boot_order_moves() {
+    let mut order = [0u16; MAX_BOOT_ORDER + 1];
+    let len = reorder(&[1, 2, 3], 3, 0, &mut order);
+    assert_eq!(&order[..len], &[3, 1, 2]);
+    let len = reorder(&[1, 2, 3], 4, 9, &mut order);
+    assert_eq!(&order[..len], &[1, 2, 3, 4]);
+    let len = reorder(&[], 7, 0, &mut order);
+    assert_eq!(&order[..len], &[7]);
+}
@@ -0,0 +1,507 @@
+//! Finding the loaders installed on every volume, for a fallback boot manager to offer entries
+//! for operating systems the firmware has no Boot#### variable for, or lost them.
+
+use core::str;
+
+use base::{Handle, Status};
+use bootoptions::{boot_options, LoadOption};
+use protocol::{DevicePath, DevicePathProtocol, DevicePathTypes, FileProtocol, MediaSubTypes, SimpleFileSystemProtocol,
+               EFI_FILE_MODE_READ};
+use util::{Sha256, SHA256_LEN};
+
+/// Architecture suffix of loader file names, as in `\EFI\BOOT\BOOTX64.EFI`.
+#[cfg(target_arch = "x86_64")]
+pub const EFI_ARCH: &str = "X64";
+#[cfg(target_arch = "x86")]
+pub const EFI_ARCH: &str = "IA32";
+#[cfg(target_arch = "aarch64")]
+pub const EFI_ARCH: &str = "AA64";
+#[cfg(target_arch = "arm")]
+pub const EFI_ARCH: &str = "ARM";
+#[cfg(target_arch = "riscv64")]
+pub const EFI_ARCH: &str = "RISCV64";
+#[cfg(target_arch = "loongarch64")]
+pub const EFI_ARCH: &str = "LOONGARCH64";
+/// Other architectures have no removable media path, so only loaders named without an
+/// architecture suffix are looked for.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "x86", target_arch = "aarch64", target_arch = "arm",
+              target_arch = "riscv64", target_arch = "loongarch64")))]
+pub const EFI_ARCH: &str = "";
+
+/// Most loaders `BootDiscovery::scan` records.
+pub const MAX_BOOT_CANDIDATES: usize = 32;
+
+const MAX_LOADER_PATH: usize = 64;
+const MAX_TITLE: usize = 64;
+
+/// Largest os-release file read for a title.
+const MAX_OS_RELEASE_SIZE: usize = 2048;
+
+/// The kind of loader a `BootCandidate` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoaderKind {
+    /// The removable media path, `\EFI\BOOT\BOOT{ARCH}.EFI`, which any OS may have installed.
+    Fallback,
+    Windows,
+    Linux,
+}
+
+struct KnownLoader {
+    /// The path up to the architecture suffix, or the whole path without `.efi`.
+    path: &'static str,
+    arch_suffix: bool,
+    kind: LoaderKind,
+    title: &'static str,
+}
+
+/// Loaders looked for on each volume. Where a distribution installs both shim and GRUB, shim
+/// comes first, and only the first one found is offered.
+static KNOWN_LOADERS: &[KnownLoader] = &[
+    KnownLoader { path: "\\EFI\\BOOT\\BOOT", arch_suffix: true, kind: LoaderKind::Fallback, title: "EFI Boot" },
+    KnownLoader { path: "\\EFI\\Microsoft\\Boot\\bootmgfw", arch_suffix: false, kind: LoaderKind::Windows, title: "Windows Boot Manager" },
+    KnownLoader { path: "\\EFI\\systemd\\systemd-boot", arch_suffix: true, kind: LoaderKind::Linux, title: "Linux Boot Manager" },
+    KnownLoader { path: "\\EFI\\fedora\\shim", arch_suffix: true, kind: LoaderKind::Linux, title: "Fedora" },
+    KnownLoader { path: "\\EFI\\fedora\\grub", arch_suffix: true, kind: LoaderKind::Linux, title: "Fedora" },
+    KnownLoader { path: "\\EFI\\redhat\\shim", arch_suffix: true, kind: LoaderKind::Linux, title: "Red Hat Enterprise Linux" },
+    KnownLoader { path: "\\EFI\\centos\\shim", arch_suffix: true, kind: LoaderKind::Linux, title: "CentOS" },
+    KnownLoader { path: "\\EFI\\ubuntu\\shim", arch_suffix: true, kind: LoaderKind::Linux, title: "Ubuntu" },
+    KnownLoader { path: "\\EFI\\ubuntu\\grub", arch_suffix: true, kind: LoaderKind::Linux, title: "Ubuntu" },
+    KnownLoader { path: "\\EFI\\debian\\shim", arch_suffix: true, kind: LoaderKind::Linux, title: "Debian" },
+    KnownLoader { path: "\\EFI\\debian\\grub", arch_suffix: true, kind: LoaderKind::Linux, title: "Debian" },
+    KnownLoader { path: "\\EFI\\opensuse\\shim", arch_suffix: false, kind: LoaderKind::Linux, title: "openSUSE" },
+    KnownLoader { path: "\\EFI\\arch\\grub", arch_suffix: true, kind: LoaderKind::Linux, title: "Arch Linux" },
+];
+
+/// A path or title in a fixed buffer.
+#[derive(Clone, Copy)]
+struct Text<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Text<N> {
+    const EMPTY: Text<N> = Text { buf: [0; N], len: 0 };
+
+    /// Append `s`, truncated at a character boundary if it does not fit.
+    fn push(&mut self, s: &str) {
+        for c in s.chars() {
+            if self.len + c.len_utf8() > N {
+                return;
+            }
+            self.len += c.encode_utf8(&mut self.buf[self.len..]).len();
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only whole characters are ever written.
+        unsafe { str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+}
+
+/// A loader found by `BootDiscovery::scan`.
+#[derive(Clone, Copy)]
+pub struct BootCandidate {
+    /// The handle of the volume, with `SimpleFileSystemProtocol` on it.
+    pub volume: Handle,
+    pub kind: LoaderKind,
+    /// The Boot#### variable that already starts this loader, if any.
+    pub boot_option: Option<u16>,
+    path: Text<MAX_LOADER_PATH>,
+    title: Text<MAX_TITLE>,
+    partition: Option<[u8; 16]>,
+    sha256: [u8; SHA256_LEN],
+}
+
+impl BootCandidate {
+    const EMPTY: BootCandidate = BootCandidate {
+        volume: Handle::NULL,
+        kind: LoaderKind::Fallback,
+        boot_option: None,
+        path: Text::EMPTY,
+        title: Text::EMPTY,
+        partition: None,
+        sha256: [0; SHA256_LEN],
+    };
+
+    /// The loader's path on the volume, such as `\EFI\fedora\shimx64.efi`.
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    /// A title for a menu: the os-release name of the volume if it has one, otherwise the
+    /// distribution for known loaders and the volume label for the fallback loader.
+    pub fn title(&self) -> &str {
+        self.title.as_str()
+    }
+
+    /// The signature of the partition the volume is on, from its hard drive device path node:
+    /// the GPT partition GUID, or the MBR disk signature in the first four bytes.
+    pub fn partition_signature(&self) -> Option<&[u8; 16]> {
+        self.partition.as_ref()
+    }
+
+    /// The SHA-256 of the loader file.
+    pub fn sha256(&self) -> &[u8; SHA256_LEN] {
+        &self.sha256
+    }
+}
+
+/// The loaders found on every volume, with duplicates removed. A fallback loader which is a
+/// copy of another loader on the same volume, as installers often leave, is dropped in favour
+/// of the original.
+///
+/// ```rust,ignore
+/// let discovery = BootDiscovery::scan()?;
+/// for candidate in discovery.new_candidates() {
+///     menu.add(candidate.title(), candidate.volume, candidate.path());
+/// }
+/// ```
+pub struct BootDiscovery {
+    candidates: [BootCandidate; MAX_BOOT_CANDIDATES],
+    len: usize,
+}
+
+impl BootDiscovery {
+    /// Look for known loaders on every volume and match them against the Boot#### variables.
+    /// Volumes that cannot be read are skipped.
+    pub fn scan() -> Result<BootDiscovery, Status> {
+        let bs = ::get_system_table().boot_services();
+        let mut discovery = BootDiscovery { candidates: [BootCandidate::EMPTY; MAX_BOOT_CANDIDATES], len: 0 };
+
+        let handles = bs.find_handles::<SimpleFileSystemProtocol>()?;
+        for &volume in handles.as_slice() {
+            let fs: &SimpleFileSystemProtocol = match bs.handle_protocol(volume) {
+                Ok(fs) => fs,
+                Err(_) => continue,
+            };
+            let root = match fs.open_volume() {
+                Ok(root) => root,
+                Err(_) => continue,
+            };
+            let partition = bs.handle_protocol::<DevicePathProtocol>(volume).ok()
+                .and_then(|dp| unsafe { dp.path() }.ok())
+                .and_then(|path| LoadTarget::from_device_path(path).partition);
+            discovery.scan_volume(root, volume, partition);
+            root.close();
+        }
+
+        discovery.match_boot_options();
+        Ok(discovery)
+    }
+
+    fn scan_volume(&mut self, root: &FileProtocol, volume: Handle, partition: Option<[u8; 16]>) {
+        let first = self.len;
+        let mut os_release = Text::<MAX_TITLE>::EMPTY;
+        read_os_release_name(root, &mut os_release);
+
+        for loader in KNOWN_LOADERS {
+            if self.len == MAX_BOOT_CANDIDATES {
+                return;
+            }
+            if loader.arch_suffix && EFI_ARCH.is_empty() {
+                continue;
+            }
+            // Only offer one loader per distribution directory.
+            let dir = &loader.path[..loader.path.rfind('\\').unwrap_or(0)];
+            if self.candidates[first..self.len].iter().any(|c| in_directory(c.path(), dir) && c.kind != LoaderKind::Fallback) {
+                continue;
+            }
+
+            let mut path = Text::EMPTY;
+            path.push(loader.path);
+            match (loader.arch_suffix, loader.kind) {
+                (true, LoaderKind::Fallback) => {
+                    path.push(EFI_ARCH);
+                    path.push(".EFI");
+                }
+                (true, _) => {
+                    for c in EFI_ARCH.chars() {
+                        path.push(c.to_ascii_lowercase().encode_utf8(&mut [0; 4]));
+                    }
+                    path.push(".efi");
+                }
+                (false, _) => path.push(".efi"),
+            }
+
+            let sha256 = match hash_file(root, path.as_str()) {
+                Some(sha256) => sha256,
+                None => continue,
+            };
+
+            let mut title = Text::EMPTY;
+            if os_release.len > 0 && loader.kind != LoaderKind::Windows {
+                title = os_release;
+            } else if loader.kind == LoaderKind::Fallback {
+                read_volume_label(root, &mut title);
+            }
+            if title.len == 0 {
+                title.push(loader.title);
+            }
+
+            self.candidates[self.len] = BootCandidate {
+                volume,
+                kind: loader.kind,
+                boot_option: None,
+                path,
+                title,
+                partition,
+                sha256,
+            };
+            self.len += 1;
+        }
+
+        // The fallback loader is found first, so it is at `first` if it is there at all.
+        if self.len > first && self.candidates[first].kind == LoaderKind::Fallback {
+            let fallback = self.candidates[first].sha256;
+            if self.candidates[first + 1..self.len].iter().any(|c| c.sha256 == fallback) {
+                self.candidates.copy_within(first + 1..self.len, first);
+                self.len -= 1;
+            }
+        }
+    }
+
+    /// Record which candidates an existing Boot#### variable already starts.
+    fn match_boot_options(&mut self) {
+        for option in boot_options() {
+            let option = match option {
+                Ok(option) => option,
+                Err(_) => continue,
+            };
+            let number = option.number();
+            let target = match LoadTarget::from_load_option(option.as_bytes()) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            for candidate in self.candidates[..self.len].iter_mut().filter(|c| c.boot_option.is_none()) {
+                if target.starts(candidate) {
+                    candidate.boot_option = Some(number);
+                }
+            }
+        }
+    }
+
+    pub fn candidates(&self) -> &[BootCandidate] {
+        &self.candidates[..self.len]
+    }
+
+    /// The candidates no Boot#### variable starts yet.
+    pub fn new_candidates(&self) -> impl Iterator<Item = &BootCandidate> {
+        self.candidates().iter().filter(|c| c.boot_option.is_none())
+    }
+}
+
+/// A volume with the Windows boot manager on it, found by `find_windows_boot_manager`.
+#[derive(Clone, Copy, Debug)]
+pub struct WindowsBootManager {
+    pub volume: Handle,
+    /// Whether the BCD store, which lists the Windows installations to boot, is next to it.
+    pub has_bcd: bool,
+}
+
+/// Path of the Windows boot manager on its ESP.
+const WINDOWS_BOOT_MANAGER: &str = "\\EFI\\Microsoft\\Boot\\bootmgfw.efi";
+
+/// Path of the BCD store the Windows boot manager reads its entries from.
+const WINDOWS_BCD: &str = "\\EFI\\Microsoft\\Boot\\BCD";
+
+/// Find the first volume with the Windows boot manager on it. Nothing is read beyond checking
+/// the files exist: the BCD store is a registry hive which is left to the boot manager, so a
+/// menu offers one "Windows Boot Manager" entry however many installations it lists.
+pub fn find_windows_boot_manager() -> Option<WindowsBootManager> {
+    let bs = ::get_system_table().boot_services();
+    let handles = bs.locate_handle_by_protocol::<SimpleFileSystemProtocol>().ok()?;
+
+    handles.as_slice().iter().find_map(|&volume| {
+        let fs: &SimpleFileSystemProtocol = bs.handle_protocol(volume).ok()?;
+        let root = fs.open_volume().ok()?;
+        let exists = |path| root.open_path(path, EFI_FILE_MODE_READ).map(|file| { file.close(); }).is_ok();
+        let found = if exists(WINDOWS_BOOT_MANAGER) {
+            Some(WindowsBootManager { volume, has_bcd: exists(WINDOWS_BCD) })
+        } else {
+            None
+        };
+        root.close();
+        found
+    })
+}
+
+fn hash_file(root: &FileProtocol, path: &str) -> Option<[u8; SHA256_LEN]> {
+    let file = root.open_path(path, EFI_FILE_MODE_READ).ok()?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 4096];
+    let result = loop {
+        match file.read(&mut buf) {
+            Ok(0) => break Some(()),
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(_) => break None,
+        }
+    };
+    file.close();
+    result.map(|()| hasher.finish())
+}
+
+fn read_volume_label(root: &FileProtocol, title: &mut Text<MAX_TITLE>) {
+    let mut buf = [0u8; 2 * MAX_TITLE];
+    let units = match root.volume_label(&mut buf) {
+        Ok(units) => units,
+        Err(_) => return,
+    };
+
+    for c in char::decode_utf16(units) {
+        title.push(c.unwrap_or(char::REPLACEMENT_CHARACTER).encode_utf8(&mut [0; 4]));
+    }
+}
+
+fn read_os_release_name(root: &FileProtocol, title: &mut Text<MAX_TITLE>) {
+    for path in &["\\etc\\os-release", "\\usr\\lib\\os-release"] {
+        let file = match root.open_path(path, EFI_FILE_MODE_READ) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        let mut buf = [0u8; MAX_OS_RELEASE_SIZE];
+        let mut len = 0;
+        while len < buf.len() {
+            match file.read(&mut buf[len..]) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => len += n,
+            }
+        }
+        file.close();
+
+        let text = match str::from_utf8(&buf[..len]) {
+            Ok(text) => text,
+            // A read cut off in the middle of a character.
+            Err(e) => unsafe { str::from_utf8_unchecked(&buf[..e.valid_up_to()]) },
+        };
+        if let Some(name) = os_release_name(text) {
+            title.push(name);
+            return;
+        }
+    }
+}
+
+/// The name of the OS in an os-release file: PRETTY_NAME, or NAME without it.
+pub fn os_release_name(os_release: &str) -> Option<&str> {
+    let value = |key: &str| {
+        os_release.lines().filter_map(|line| line.trim().strip_prefix(key)?.strip_prefix('=')).next_back().map(|v| {
+            let v = v.trim();
+            let quoted = v.len() >= 2 && (v.starts_with('"') && v.ends_with('"') || v.starts_with('\'') && v.ends_with('\''));
+            if quoted { &v[1..v.len() - 1] } else { v }
+        })
+    };
+    value("PRETTY_NAME").or_else(|| value("NAME")).filter(|v| !v.is_empty())
+}
+
+/// Where a load option or a volume's device path points: the partition, and the file on it,
+/// with separators as backslashes and in lowercase.
+struct LoadTarget {
+    partition: Option<[u8; 16]>,
+    path: Text<MAX_LOADER_PATH>,
+    has_path: bool,
+}
+
+impl LoadTarget {
+    fn from_device_path(path: DevicePath) -> LoadTarget {
+        let mut target = LoadTarget { partition: None, path: Text::EMPTY, has_path: false };
+
+        for node in path.nodes() {
+            // Only the first instance is used to boot.
+            if node.is_end() {
+                break;
+            }
+            if node.node_type() != DevicePathTypes::Media as u8 {
+                continue;
+            }
+
+            let data = node.data();
+            if node.sub_type() == MediaSubTypes::HardDrive as u8 {
+                // The signature type is 1 for MBR and 2 for GPT, and 0 for none.
+                if data.len() >= 38 && data[37] != 0 {
+                    let mut signature = [0u8; 16];
+                    signature.copy_from_slice(&data[20..36]);
+                    target.partition = Some(signature);
+                }
+            } else if node.sub_type() == MediaSubTypes::FilePath as u8 {
+                // A path may be split over several nodes, each with its own terminator.
+                let units = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).take_while(|&c| c != 0);
+                if target.has_path && !target.path.as_str().ends_with('\\') {
+                    target.path.push("\\");
+                }
+                for c in char::decode_utf16(units) {
+                    let c = match c.unwrap_or(char::REPLACEMENT_CHARACTER) {
+                        '/' => '\\',
+                        c => c.to_ascii_lowercase(),
+                    };
+                    target.path.push(c.encode_utf8(&mut [0; 4]));
+                }
+                target.has_path = true;
+            }
+        }
+        target
+    }
+
+    /// The target of an EFI_LOAD_OPTION, as stored in a Boot#### variable.
+    fn from_load_option(option: &[u8]) -> Option<LoadTarget> {
+        LoadOption::parse(option).ok().map(|option| LoadTarget::from_device_path(option.file_path()))
+    }
+
+    /// Whether booting this target starts `candidate`: the same partition, and the same file,
+    /// or no file for the fallback loader, which the firmware then starts.
+    fn starts(&self, candidate: &BootCandidate) -> bool {
+        if self.partition != candidate.partition {
+            return false;
+        }
+        if self.has_path {
+            self.path.as_str().eq_ignore_ascii_case(candidate.path())
+        } else {
+            candidate.kind == LoaderKind::Fallback
+        }
+    }
+}
+
+/// Whether `path` is of a file in the directory `dir`, or below it.
+fn in_directory(path: &str, dir: &str) -> bool {
+    path.len() > dir.len() && path.starts_with(dir) && path.as_bytes()[dir.len()] == b'\\'
+}
+
+#[test]
+fn loader_directories() {
+    assert!(in_directory("\\EFI\\Boot\\bootx64.efi", "\\EFI\\Boot"));
+    assert!(!in_directory("\\EFI\\Boot2\\bootx64.efi", "\\EFI\\Boot"));
+    assert!(!in_directory("\\EFI\\Boot", "\\EFI\\Boot"));
+}
+
+#[test]
+fn bootscan_parsing() {
+    assert_eq!(os_release_name("NAME=Fedora\nPRETTY_NAME=\"Fedora Linux 40\"\n"), Some("Fedora Linux 40"));
+    assert_eq!(os_release_name("NAME='Arch Linux'\n"), Some("Arch Linux"));
+    assert_eq!(os_release_name("ID=debian\n"), None);
+
+    // Attributes, file path list length, "A", then HD(1,GPT,...)/\EFI\fedora\shimx64.efi.
+    let mut option = [0u8; 160];
+    option[4] = 42 + 52 + 4;
+    option[6] = b'A';
+    let mut offset = 10;
+    option[offset..offset + 4].copy_from_slice(&[4, 1, 42, 0]);
+    option[offset + 4 + 20..offset + 4 + 36].copy_from_slice(&[0xAB; 16]);
+    option[offset + 4 + 36..offset + 4 + 38].copy_from_slice(&[2, 2]);
+    offset += 42;
+    option[offset..offset + 4].copy_from_slice(&[4, 4, 52, 0]);
+    for (i, c) in "\\EFI\\fedora\\shimx64.efi".bytes().enumerate() {
+        option[offset + 4 + 2 * i] = c;
+    }
+    offset += 52;
+    option[offset..offset + 4].copy_from_slice(&[0x7F, 0xFF, 4, 0]);
+
+    let target = LoadTarget::from_load_option(&option).unwrap();
+    assert_eq!(target.partition, Some([0xAB; 16]));
+    assert_eq!(target.path.as_str(), "\\efi\\fedora\\shimx64.efi");
+
+    let mut candidate = BootCandidate::EMPTY;
+    candidate.kind = LoaderKind::Linux;
+    candidate.path.push("\\EFI\\fedora\\shimx64.efi");
+    assert!(!target.starts(&candidate));
+    candidate.partition = Some([0xAB; 16]);
+    assert!(target.starts(&candidate));
+}
@@ -1,14 +1,72 @@
 use core::ptr;
+use core::slice;
 use core::mem;
+use core::marker::PhantomData;
+use core::ops::Deref;
 
 use void::{NotYetDef, CVoid};
-use base::{Event, Handle, Handles, MemoryType, MemoryDescriptor, Status};
-use event::{EventType, EventNotify, TimerDelay};
+use base::{Event, Handle, Handles, MemoryType, MemoryDescriptor, PhysicalAddress, Status, status_to_result};
+use event::{EventType, EventNotify, TimerDelay, EVT_TIMER, EVT_TIMER_NOTIFY_SIGNAL};
 use task::TPL;
-use protocol::{DevicePathProtocol, Protocol, get_current_image};
+use protocol::{DevicePathProtocol, Interface, Protocol, get_current_image};
 use guid;
+#[cfg(feature = "heap-stats")]
+use heapstats;
+use runtimeservices;
 use table;
 
+/// Most GUID and interface pairs `BootServices::install_multiple_protocol_interfaces` and
+/// `uninstall_multiple_protocol_interfaces` take at once.
+pub const MAX_MULTIPLE_PROTOCOL_INTERFACES: usize = 8;
+
+/// Attributes of `BootServices::open_protocol`, saying who opens the protocol and for what.
+/// `BY_HANDLE_PROTOCOL` and `GET_PROTOCOL` only get the interface, as applications do;
+/// `BY_DRIVER`, optionally with `EXCLUSIVE`, is how a driver's `start` takes over a controller,
+/// and `BY_CHILD_CONTROLLER` records that a child handle uses its parent's protocol.
+/// `TEST_PROTOCOL` is for `BootServices::test_protocol`.
+pub const EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL: u32 = 0x00000001;
+pub const EFI_OPEN_PROTOCOL_GET_PROTOCOL: u32 = 0x00000002;
+pub const EFI_OPEN_PROTOCOL_TEST_PROTOCOL: u32 = 0x00000004;
+pub const EFI_OPEN_PROTOCOL_BY_CHILD_CONTROLLER: u32 = 0x00000008;
+pub const EFI_OPEN_PROTOCOL_BY_DRIVER: u32 = 0x00000010;
+pub const EFI_OPEN_PROTOCOL_EXCLUSIVE: u32 = 0x00000020;
+
+/// A parameter of the variadic multiple protocol interface services. Every one, the handle
+/// included, is pointer-sized, so with the win64 convention a call with a fixed number of
+/// them passes them exactly as the variadic call would.
+macro_rules! variadic_parameter {
+    ($index:tt) => { *const CVoid };
+}
+
+/// Call the variadic service `$function` with `$first`, then the GUID and interface pairs of
+/// `$interfaces` at each of the indices given, then the terminating null pointer.
+macro_rules! call_multiple_protocol_interfaces {
+    ($function:expr, $first:expr, $interfaces:expr, $($index:tt)*) => {{
+        type Function = unsafe extern "win64" fn(*const CVoid, $(variadic_parameter!($index), variadic_parameter!($index),)* *const CVoid) -> Status;
+        let function = mem::transmute::<*const CVoid, Function>($function);
+        function($first, $($interfaces[$index].0 as *const guid::Guid as *const CVoid, $interfaces[$index].1,)* ptr::null())
+    }};
+}
+
+/// Call the variadic service `$function` with `$first` and every pair of `$interfaces`, or
+/// give `Status::InvalidParameter` if there are none or more than
+/// `MAX_MULTIPLE_PROTOCOL_INTERFACES`.
+macro_rules! call_multiple_protocol_interfaces_len {
+    ($function:expr, $first:expr, $interfaces:expr) => {
+        match $interfaces.len() {
+            1 => call_multiple_protocol_interfaces!($function, $first, $interfaces, 0),
+            2 => call_multiple_protocol_interfaces!($function, $first, $interfaces, 0 1),
+            3 => call_multiple_protocol_interfaces!($function, $first, $interfaces, 0 1 2),
+            4 => call_multiple_protocol_interfaces!($function, $first, $interfaces, 0 1 2 3),
+            5 => call_multiple_protocol_interfaces!($function, $first, $interfaces, 0 1 2 3 4),
+            6 => call_multiple_protocol_interfaces!($function, $first, $interfaces, 0 1 2 3 4 5),
+            7 => call_multiple_protocol_interfaces!($function, $first, $interfaces, 0 1 2 3 4 5 6),
+            8 => call_multiple_protocol_interfaces!($function, $first, $interfaces, 0 1 2 3 4 5 6 7),
+            _ => Status::InvalidParameter,
+        }
+    };
+}
+
 #[repr(C)]
 pub enum LocateSearchType {
     AllHandles = 0,
@@ -16,30 +74,48 @@ pub enum LocateSearchType {
     ByProtocol = 2,
 }
 
+#[repr(C)]
+enum RawAllocateType {
+    AnyPages = 0,
+    MaxAddress = 1,
+    Address = 2,
+}
+
+/// Where `allocate_pages` may place an allocation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocateType {
+    /// Anywhere.
+    AnyPages,
+    /// Anywhere ending at or below the given address.
+    MaxAddress(PhysicalAddress),
+    /// Exactly at the given page-aligned address.
+    Address(PhysicalAddress),
+}
+
 /// See http://wiki.phoenix.com/wiki/index.php/EFI_BOOT_SERVICES
 #[repr(C)]
 pub struct BootServices {
     header: table::TableHeader,
-    raise_tpl: *const NotYetDef,
-    restore_tpl: *const NotYetDef,
-    allocate_pages: *const NotYetDef,
-    free_pages: *const NotYetDef,
+    raise_tpl: unsafe extern "win64" fn(new_tpl: usize) -> usize,
+    restore_tpl: unsafe extern "win64" fn(old_tpl: usize),
+    allocate_pages: unsafe extern "win64" fn(allocate_type: RawAllocateType, memory_type: MemoryType, pages: usize, memory: *mut PhysicalAddress) -> Status,
+    free_pages: unsafe extern "win64" fn(memory: PhysicalAddress, pages: usize) -> Status,
     get_memory_map: unsafe extern "win64" fn(memory_map_size: *mut usize, memory_map: *mut MemoryDescriptor, *mut usize, descriptor_size: *mut usize, descriptor_version: *mut u32) -> Status,
     allocate_pool: unsafe extern "win64" fn(pool_type: MemoryType, size: usize, out: *mut *mut u8) -> Status,
     free_pool: unsafe extern "win64" fn(*mut CVoid),
-    create_event: unsafe extern "win64" fn(event_type: EventType, notify_tpl: TPL, notify_function: Option<EventNotify>, notify_context: *const CVoid, event: *mut Event) -> Status,
+    create_event: unsafe extern "win64" fn(event_type: u32, notify_tpl: TPL, notify_function: Option<EventNotify>, notify_context: *const CVoid, event: *mut Event) -> Status,
     set_timer: unsafe extern "win64" fn(event: Event, delay_type: TimerDelay, delay: u64) -> Status,
     // typedef EFI_STATUS (EFIAPI *EFI_WAIT_FOR_EVENT) (IN UINTN NumberOfEvents, IN EFI_EVENT *Event, OUT UINTN *Index);
     wait_for_event: unsafe extern "win64" fn(usize, *const Event, *mut usize) -> Status,
     signal_event: *const NotYetDef,
-    close_event: *const NotYetDef,
-    check_event: *const NotYetDef,
+    close_event: unsafe extern "win64" fn(event: Event) -> Status,
+    check_event: unsafe extern "win64" fn(event: Event) -> Status,
     install_protocol_interface: *const NotYetDef,
     reinstall_protocol_interface: *const NotYetDef,
     uninstall_protocol_interface: *const NotYetDef,
     handle_protocol: unsafe extern "win64" fn(Handle, &guid::Guid, &mut *mut CVoid) -> Status,
     __reserved: *const NotYetDef,
-    register_protocol_notify: *const NotYetDef,
+    register_protocol_notify: unsafe extern "win64" fn(protocol: &guid::Guid, event: Event, registration: *mut *const CVoid) -> Status,
     locate_handle: *const NotYetDef,
     locate_device_path: *const NotYetDef,
     install_configuration_table: *const NotYetDef,
@@ -48,20 +124,22 @@ pub struct BootServices {
     exit: *const NotYetDef,
     unload_image: *const NotYetDef,
     exit_boot_services: unsafe extern "win64" fn(image_handle: Handle, map_key: usize) -> Status,
-    get_next_monotonic_count: *const NotYetDef,
+    get_next_monotonic_count: unsafe extern "win64" fn(count: *mut u64) -> Status,
     stall: unsafe extern "win64" fn(usize) -> Status,
     set_watchdog_timer: unsafe extern "win64" fn(timeout: usize, code: u64, data_size: usize, data: *const u16) -> Status,
-    connect_controller: *const NotYetDef,
-    disconnect_controller: *const NotYetDef,
-    open_protocol: *const NotYetDef,
+    connect_controller: unsafe extern "win64" fn(controller_handle: Handle, driver_image_handle: *const Handle, remaining_device_path: *const DevicePathProtocol, recursive: bool) -> Status,
+    disconnect_controller: unsafe extern "win64" fn(controller_handle: Handle, driver_image_handle: Handle, child_handle: Handle) -> Status,
+    open_protocol: unsafe extern "win64" fn(handle: Handle, protocol: &guid::Guid, interface: *mut *mut CVoid, agent_handle: Handle, controller_handle: Handle, attributes: u32) -> Status,
     close_protocol: unsafe extern "win64" fn(handle: Handle, protocol: &guid::Guid, agent_handle: Handle, controller_handle: Handle) -> Status,
     open_protocol_information: *const NotYetDef,
-    protocols_per_handle: *const NotYetDef,
+    protocols_per_handle: unsafe extern "win64" fn(handle: Handle, protocol_buffer: *mut *mut *const guid::Guid, protocol_buffer_count: *mut usize) -> Status,
     locate_handle_buffer: unsafe extern "win64" fn(search_type: LocateSearchType, protocol: &guid::Guid, search_key: *const CVoid, nhandles: *mut usize, handles: *mut *mut CVoid) -> Status,
     locate_protocol: unsafe extern "win64" fn(protocol: &guid::Guid, registration: *const CVoid, interface: &mut *mut CVoid) -> Status,
-    install_multiple_protocol_interfaces: *const NotYetDef,
-    uninstall_multiple_protocol_interfaces: *const NotYetDef,
-    calculate_crc32: *const NotYetDef,
+    // Both take a variable number of GUID and interface pairs, ended by a null pointer, so they
+    // are called through `call_multiple_protocol_interfaces!` at a fixed arity.
+    install_multiple_protocol_interfaces: *const CVoid,
+    uninstall_multiple_protocol_interfaces: *const CVoid,
+    calculate_crc32: unsafe extern "win64" fn(data: *const CVoid, data_size: usize, crc32: *mut u32) -> Status,
     copy_mem: unsafe extern "win64" fn(*mut CVoid, *mut CVoid, usize),
     set_mem: unsafe extern "win64" fn(*mut CVoid, usize, u8),
     create_event_ex: *const NotYetDef,
@@ -87,27 +165,116 @@ impl BootServices {
         }
     }
 
+    /// Raise the task priority level to `tpl`, and return the previous level for
+    /// `restore_tpl`. The previous level may be between the named `TPL` levels. Fails with
+    /// `Status::InvalidParameter`, rather than tripping a firmware assertion, if `tpl` is below
+    /// the current level.
+    pub fn raise_tpl(&self, tpl: TPL) -> Result<usize, Status> {
+        // There is no service to read the level, so raise to the highest to learn it, then
+        // come down to the one asked for.
+        let old_tpl = unsafe { (self.raise_tpl)(TPL::HighLevel as usize) };
+        if (tpl as usize) < old_tpl {
+            self.restore_tpl(old_tpl);
+            return Err(Status::InvalidParameter);
+        }
+        self.restore_tpl(tpl as usize);
+        Ok(old_tpl)
+    }
+
+    /// The current task priority level, which may be between the named `TPL` levels.
+    pub fn current_tpl(&self) -> usize {
+        let tpl = unsafe { (self.raise_tpl)(TPL::HighLevel as usize) };
+        self.restore_tpl(tpl);
+        tpl
+    }
+
+    /// Lower the task priority level back to `old_tpl`, as returned by `raise_tpl`.
+    pub fn restore_tpl(&self, old_tpl: usize) {
+        unsafe { (self.restore_tpl)(old_tpl) }
+    }
+
+    /// Read the memory map into `buf` without allocating, as needed right before
+    /// ExitBootServices, returning (map size, map key, descriptor size, descriptor version).
+    /// `buf` must be aligned for `MemoryDescriptor`. If it is too small this fails with
+    /// `Status::BufferTooSmall` and sets `needed` to the size the map takes.
+    pub fn get_memory_map_into(&self, buf: &mut [u8], needed: &mut usize) -> Result<(usize, usize, usize, u32), Status> {
+        let mut size = buf.len();
+        let mut map_key: usize = 0;
+        let mut descriptor_size: usize = 0;
+        let mut descriptor_version: u32 = 0;
+
+        let status = unsafe {
+            (self.get_memory_map)(&mut size, buf.as_mut_ptr() as *mut MemoryDescriptor, &mut map_key,
+                                  &mut descriptor_size, &mut descriptor_version)
+        };
+        *needed = size;
+        match status {
+            Status::Success => Ok((size, map_key, descriptor_size, descriptor_version)),
+            e => Err(e),
+        }
+    }
+
+    /// Allocate `pages` contiguous 4KiB pages of type `memory_type`, placed according to
+    /// `allocate_type`, and return the physical address of the first one.
+    pub fn allocate_pages(&self, allocate_type: AllocateType, memory_type: MemoryType, pages: usize) -> Result<PhysicalAddress, Status> {
+        let (raw_type, mut address) = match allocate_type {
+            AllocateType::AnyPages => (RawAllocateType::AnyPages, 0),
+            AllocateType::MaxAddress(address) => (RawAllocateType::MaxAddress, address),
+            AllocateType::Address(address) => (RawAllocateType::Address, address),
+        };
+
+        let status = unsafe { (self.allocate_pages)(raw_type, memory_type, pages, &mut address) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        #[cfg(feature = "heap-stats")]
+        heapstats::record_page_allocation(address, pages, memory_type);
+        Ok(address)
+    }
+
+    /// Free pages allocated with `allocate_pages`.
+    pub fn free_pages(&self, address: PhysicalAddress, pages: usize) -> Status {
+        #[cfg(feature = "heap-stats")]
+        heapstats::record_page_free(address);
+        unsafe {
+            (self.free_pages)(address, pages)
+        }
+    }
+
     /// Allocate `size` bytes of memory using type `T`.
     pub fn allocate_pool<T>(&self, size: usize) -> Result<*mut T, Status> {
         let mut ptr: *mut u8 = 0 as *mut u8;
 
-        let result = unsafe { (self.allocate_pool)(get_current_image().image_data_type, size, &mut ptr) };
+        let memory_type = get_current_image().image_data_type;
+        let result = unsafe { (self.allocate_pool)(memory_type, size, &mut ptr) };
         if result != Status::Success {
             return Err(result);
         }
+
+        #[cfg(feature = "heap-stats")]
+        heapstats::record_pool_allocation(ptr as usize, size, memory_type);
         Ok(ptr as *mut T)
     }
 
     pub fn free_pool<T>(&self, p: *const T) {
+        #[cfg(feature = "heap-stats")]
+        heapstats::record_pool_free(p as usize);
         unsafe {
             (self.free_pool)(p as *mut CVoid);
         }
     }
 
+    /// Create an event of `event_type`, calling `notify_func` with `notify_context` at
+    /// `notify_tpl` if the type includes `EVT_NOTIFY_WAIT` or `EVT_NOTIFY_SIGNAL`. Combinations
+    /// the firmware would reject, as `EventType::validate` checks, fail with
+    /// `Status::InvalidParameter` without calling it.
     pub fn create_event(&self, event_type: EventType, notify_tpl: TPL, notify_func: Option<EventNotify>, notify_context: *const CVoid) -> Result<Event, Status> {
+        event_type.validate(notify_tpl, notify_func.is_some())?;
+
         let mut event: Event = Event(0 as *mut CVoid);
 
-        let result = unsafe { (self.create_event)(event_type, notify_tpl, notify_func, notify_context, &mut event) };
+        let result = unsafe { (self.create_event)(event_type.bits(), notify_tpl, notify_func, notify_context, &mut event) };
         if result != Status::Success {
             return Err(result);
         }
@@ -115,6 +282,18 @@ impl BootServices {
         Ok(event)
     }
 
+    /// Create a timer event, to be set with `set_timer` and waited for with `wait_for_event`
+    /// or `check_event`.
+    pub fn create_timer_event(&self) -> Result<Event, Status> {
+        self.create_event(EVT_TIMER, TPL::Application, None, ptr::null())
+    }
+
+    /// Create a timer event that calls `notify_func` with `notify_context` at `notify_tpl`,
+    /// `TPL::Callback` or `TPL::Notify`, each time it expires.
+    pub fn create_timer_event_with_notify(&self, notify_tpl: TPL, notify_func: EventNotify, notify_context: *const CVoid) -> Result<Event, Status> {
+        self.create_event(EVT_TIMER_NOTIFY_SIGNAL, notify_tpl, Some(notify_func), notify_context)
+    }
+
     pub fn set_timer(&self, event: Event, delay_type: TimerDelay, delay: u64) -> Status {
         unsafe {
             (self.set_timer)(event, delay_type, delay)
@@ -138,6 +317,21 @@ impl BootServices {
         Ok(index)
     }
 
+    /// Whether `event` is signalled, without waiting: `Success` if it was, clearing it, and
+    /// `NotReady` if not. Events of type `NotifySignal` give `InvalidParameter`.
+    pub fn check_event(&self, event: Event) -> Status {
+        unsafe {
+            (self.check_event)(event)
+        }
+    }
+
+    /// Close an event created with `create_event`, cancelling any timer set on it.
+    pub fn close_event(&self, event: Event) -> Status {
+        unsafe {
+            (self.close_event)(event)
+        }
+    }
+
     pub fn handle_protocol<T: Protocol>(&self, handle: Handle) -> Result<&'static T, Status> {
         let mut ptr : *mut CVoid = 0 as *mut CVoid;
         let guid = T::guid();
@@ -154,20 +348,130 @@ impl BootServices {
         Ok(r)
     }
 
-    // TODO: for the love of types, fix me
-    pub fn close_protocol<T: Protocol>(&self, handle: Handle, agent_handle: Handle, controller_handle: Handle) -> Status {
-        let guid = T::guid();
+    /// Open protocol `T` on `handle` on behalf of `agent_handle`, the image or driver, and for
+    /// drivers, `controller_handle`, with the `EFI_OPEN_PROTOCOL_*` flags in `attributes`. The
+    /// firmware records the open, so that it knows which drivers to stop before the protocol
+    /// is uninstalled; the returned guard closes it again when dropped.
+    ///
+    /// Opening `BY_DRIVER` fails with `Status::AccessDenied` if another driver already has
+    /// the protocol open that way, and `Status::AlreadyStarted` if this one does. Use
+    /// `test_protocol` rather than `EFI_OPEN_PROTOCOL_TEST_PROTOCOL`, which gets no interface.
+    ///
+    /// ```rust,ignore
+    /// fn supported(controller: Handle, _: Option<&DevicePathProtocol>) -> Result<(), Status> {
+    ///     let bs = uefi::get_system_table().boot_services();
+    ///     let io = bs.open_protocol::<PciIoProtocol>(controller, BINDING.driver_binding_handle(), controller,
+    ///                                                EFI_OPEN_PROTOCOL_BY_DRIVER)?;
+    ///     check_device(&io)
+    /// }
+    /// ```
+    pub fn open_protocol<T: Protocol>(&self, handle: Handle, agent_handle: Handle, controller_handle: Handle, attributes: u32)
+                                      -> Result<ScopedProtocol<T>, Status> {
+        if attributes & EFI_OPEN_PROTOCOL_TEST_PROTOCOL != 0 {
+            return Err(Status::InvalidParameter);
+        }
 
-        unsafe {
-            (self.close_protocol)(handle, guid, agent_handle, controller_handle)
+        let mut ptr: *mut CVoid = ptr::null_mut();
+        let status = unsafe { (self.open_protocol)(handle, T::guid(), &mut ptr, agent_handle, controller_handle, attributes) };
+        if status != Status::Success {
+            return Err(status);
         }
+
+        Ok(ScopedProtocol {
+            interface: unsafe { &*(ptr as *const T) },
+            handle,
+            agent_handle,
+            controller_handle,
+        })
+    }
+
+    /// Check whether `handle` supports protocol `T`, without opening it.
+    pub fn test_protocol<T: Protocol>(&self, handle: Handle, agent_handle: Handle, controller_handle: Handle) -> Result<(), Status> {
+        status_to_result(unsafe {
+            (self.open_protocol)(handle, T::guid(), ptr::null_mut(), agent_handle, controller_handle, EFI_OPEN_PROTOCOL_TEST_PROTOCOL)
+        })
+    }
+
+    /// Close protocol `T` on `handle`, opened by `agent_handle` for `controller_handle` with
+    /// `open_protocol`. Fails with `Status::NotFound` if it isn't open that way.
+    pub fn close_protocol<T: Protocol>(&self, handle: Handle, agent_handle: Handle, controller_handle: Handle) -> Result<(), Status> {
+        status_to_result(unsafe { (self.close_protocol)(handle, T::guid(), agent_handle, controller_handle) })
     }
 
     /// Retrives a slice of handles by protocol GUID.
     pub fn locate_handle_by_protocol<T: Protocol>(&self) -> Result<Handles, Status> {
+        self.locate_handle_by_guid(T::guid())
+    }
+
+    /// Find the handles supporting protocol `T`. Unlike `locate_handle_by_protocol`, finding
+    /// none is not an error: the result is empty, and `Err` is left for real failures.
+    pub fn find_handles<T: Protocol>(&self) -> Result<Handles, Status> {
+        self.find_handles_by_guid(T::guid())
+    }
+
+    /// Find the handles supporting the protocol identified by `guid`, as `find_handles`.
+    pub fn find_handles_by_guid(&self, guid: &guid::Guid) -> Result<Handles, Status> {
+        match self.locate_handle_by_guid(guid) {
+            Err(Status::NotFound) => Ok(Handles::empty()),
+            result => result,
+        }
+    }
+
+    /// Whether any handle supports protocol `T`.
+    pub fn exists<T: Protocol>(&self) -> bool {
+        self.exists_by_guid(T::guid())
+    }
+
+    /// Whether any handle supports the protocol identified by `guid`.
+    pub fn exists_by_guid(&self, guid: &guid::Guid) -> bool {
+        self.locate_protocol_by_guid(guid).is_ok()
+    }
+
+    /// Retrieve every handle in the handle database.
+    pub fn locate_all_handles(&self) -> Result<Handles, Status> {
+        let mut nhandles: usize = 0;
+        let mut handles: *mut CVoid = ptr::null_mut();
+
+        // The protocol is ignored for AllHandles searches.
+        let res = unsafe { (self.locate_handle_buffer)(LocateSearchType::AllHandles, &runtimeservices::EFI_GLOBAL_VARIABLE_GUID, ptr::null(), &mut nhandles, &mut handles) };
+        if res != Status::Success {
+            return Err(res);
+        }
+
+        Ok(Handles::new(handles as *mut Handle, nhandles))
+    }
+
+    /// The GUIDs of the protocols installed on `handle`.
+    pub fn protocols_per_handle(&self, handle: Handle) -> Result<ProtocolGuids, Status> {
+        let mut guids: *mut *const guid::Guid = ptr::null_mut();
+        let mut count: usize = 0;
+
+        let res = unsafe { (self.protocols_per_handle)(handle, &mut guids, &mut count) };
+        if res != Status::Success {
+            return Err(res);
+        }
+
+        Ok(ProtocolGuids { guids, count })
+    }
+
+    /// Have `event` signalled whenever an interface for the protocol `guid` is installed or
+    /// reinstalled, returning the registration key for `LocateSearchType::ByRegisterNotify`
+    /// searches. The registration lasts until the event is closed.
+    pub fn register_protocol_notify(&self, guid: &guid::Guid, event: Event) -> Result<*const CVoid, Status> {
+        let mut registration: *const CVoid = ptr::null();
+
+        let res = unsafe { (self.register_protocol_notify)(guid, event, &mut registration) };
+        if res != Status::Success {
+            return Err(res);
+        }
+
+        Ok(registration)
+    }
+
+    /// Retrieve the handles supporting the protocol identified by `guid`.
+    pub fn locate_handle_by_guid(&self, guid: &guid::Guid) -> Result<Handles, Status> {
         let mut nhandles : usize = 0;
         let mut handles : *mut CVoid = ptr::null_mut();
-        let guid = T::guid();
 
         let res = unsafe { (self.locate_handle_buffer)(LocateSearchType::ByProtocol, guid, ptr::null(), &mut nhandles as *mut usize, &mut handles) };
 
@@ -178,6 +482,44 @@ impl BootServices {
         return Ok(Handles::new(handles as *mut Handle, nhandles));
     }
 
+    /// Get the interface of the protocol identified by `guid` on `handle`, for protocols without
+    /// a `Protocol` type.
+    pub fn handle_protocol_by_guid(&self, handle: Handle, guid: &guid::Guid) -> Result<Interface, Status> {
+        let mut ptr: *mut CVoid = ptr::null_mut();
+
+        let status = unsafe { (self.handle_protocol)(handle, guid, &mut ptr) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(Interface::new(*guid, ptr))
+    }
+
+    /// Find the first interface of the protocol identified by `guid`, for protocols without a
+    /// `Protocol` type.
+    pub fn locate_protocol_by_guid(&self, guid: &guid::Guid) -> Result<Interface, Status> {
+        let mut ptr: *mut CVoid = ptr::null_mut();
+
+        let status = unsafe { (self.locate_protocol)(guid, ptr::null(), &mut ptr) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(Interface::new(*guid, ptr))
+    }
+
+    /// Find every handle supporting protocol `T`, and iterate over the handles together with
+    /// their `T` interface. The handle buffer is freed when the iterator is dropped.
+    pub fn locate_all_protocols<T: Protocol>(&self) -> Result<ProtocolInstances<T>, Status> {
+        let handles = self.locate_handle_by_protocol::<T>()?;
+
+        Ok(ProtocolInstances {
+            handles,
+            index: 0,
+            _marker: PhantomData,
+        })
+    }
+
     /// Load an image by device path and return its handle.
     pub fn load_image(&self, boot_policy: bool, parent_image_handle: Handle, device_path: *const DevicePathProtocol) -> Result<Handle, Status> {
         self.load_image_buffer(boot_policy, parent_image_handle, device_path, 0 as *const CVoid, 0)
@@ -219,11 +561,38 @@ impl BootServices {
     }
 
     pub fn exit_boot_services(&self, image_handle: &Handle, map_key: &usize) -> Status {
-        unsafe {
-            (self.exit_boot_services)(*image_handle, *map_key)
+        // The RT properties table has to be read while the configuration table is still in its
+        // boot-time mapping.
+        let supported = runtimeservices::runtime_services_supported();
+
+        let status = unsafe { (self.exit_boot_services)(*image_handle, *map_key) };
+        if status == Status::Success {
+            runtimeservices::enter_runtime(supported);
+        }
+        status
+    }
+
+    /// The next value of the platform's monotonic counter, which increases on every call.
+    pub fn next_monotonic_count(&self) -> Result<u64, Status> {
+        let mut count = 0;
+        match unsafe { (self.get_next_monotonic_count)(&mut count) } {
+            Status::Success => Ok(count),
+            e => Err(e),
         }
     }
 
+    /// Connect the drivers that support `controller` to it, and with `recursive`, to the child
+    /// handles they create, as for a partition driver and then a file system driver on a new
+    /// disk. Fails with `Status::NotFound` if no driver was connected.
+    pub fn connect_controller(&self, controller: Handle, recursive: bool) -> Result<(), Status> {
+        status_to_result(unsafe { (self.connect_controller)(controller, ptr::null(), ptr::null(), recursive) })
+    }
+
+    /// Disconnect every driver managing `controller`, destroying the child handles they made.
+    pub fn disconnect_controller(&self, controller: Handle) -> Result<(), Status> {
+        status_to_result(unsafe { (self.disconnect_controller)(controller, Handle::NULL, Handle::NULL) })
+    }
+
     /// Sleep for a number of microseconds.
     pub fn stall(&self, microseconds: usize) {
         unsafe {
@@ -255,6 +624,60 @@ impl BootServices {
         }
     }
 
+    /// Install the protocol interfaces in `interfaces`, given as GUID and interface pairs, on
+    /// `handle`, or on a new handle if it is `Handle::NULL`, and return the handle. Either all
+    /// are installed or none are: this fails with `Status::AlreadyStarted` if the handle's
+    /// device path is already on another handle, and `Status::InvalidParameter` if there are
+    /// no pairs or more than `MAX_MULTIPLE_PROTOCOL_INTERFACES`.
+    ///
+    /// ```rust,ignore
+    /// let handle = unsafe {
+    ///     bs.install_multiple_protocol_interfaces(Handle::NULL, &[
+    ///         (&EFI_DEVICE_PATH_PROTOCOL_GUID, device_path as *const _ as *const CVoid),
+    ///         (&EFI_LOAD_FILE2_PROTOCOL_GUID, &LOAD_FILE2 as *const _ as *const CVoid),
+    ///     ])?
+    /// };
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Each interface must be of the type its GUID names and stay valid, at the same address,
+    /// until it is uninstalled; other drivers and applications will use it.
+    pub unsafe fn install_multiple_protocol_interfaces(&self, handle: Handle, interfaces: &[(&guid::Guid, *const CVoid)])
+                                                       -> Result<Handle, Status> {
+        let mut handle = handle;
+        let handle_ptr = &mut handle as *mut Handle as *const CVoid;
+        match call_multiple_protocol_interfaces_len!(self.install_multiple_protocol_interfaces, handle_ptr, interfaces) {
+            Status::Success => Ok(handle),
+            e => Err(e),
+        }
+    }
+
+    /// Uninstall the protocol interfaces in `interfaces`, given as GUID and interface pairs,
+    /// from `handle`. Either all are uninstalled or none are: if any is not installed, or a
+    /// driver refuses to stop using one, this fails and leaves them all installed. Once the
+    /// last protocol is uninstalled the firmware frees the handle.
+    ///
+    /// # Safety
+    ///
+    /// The interfaces must have been installed by the caller, which must not let anything else
+    /// rely on them being installed.
+    pub unsafe fn uninstall_multiple_protocol_interfaces(&self, handle: Handle, interfaces: &[(&guid::Guid, *const CVoid)])
+                                                         -> Result<(), Status> {
+        let handle = mem::transmute::<Handle, *const CVoid>(handle);
+        status_to_result(call_multiple_protocol_interfaces_len!(self.uninstall_multiple_protocol_interfaces, handle, interfaces))
+    }
+
+    /// The CRC32 of `data`, as used in table headers and GPT. `util::crc32` computes the same
+    /// without boot services.
+    pub fn calculate_crc32(&self, data: &[u8]) -> Result<u32, Status> {
+        let mut crc = 0;
+        match unsafe { (self.calculate_crc32)(data.as_ptr() as *const CVoid, data.len(), &mut crc) } {
+            Status::Success => Ok(crc),
+            e => Err(e),
+        }
+    }
+
     /// Copy memory, similar to memcpy.
     pub fn copy_mem(&self, dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
         unsafe {
@@ -274,3 +697,120 @@ impl BootServices {
     }
 }
 
+
+/// The protocols on a handle, returned by `BootServices::protocols_per_handle`. The list is in
+/// pool memory, which is freed when this is dropped.
+pub struct ProtocolGuids {
+    guids: *mut *const guid::Guid,
+    count: usize,
+}
+
+impl ProtocolGuids {
+    pub fn as_slice(&self) -> &[&guid::Guid] {
+        if self.guids.is_null() {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.guids as *const &guid::Guid, self.count) }
+    }
+}
+
+impl Drop for ProtocolGuids {
+    fn drop(&mut self) {
+        if !self.guids.is_null() {
+            ::get_system_table().boot_services().free_pool(self.guids);
+        }
+    }
+}
+
+/// A protocol opened with `BootServices::open_protocol`, which is closed again when this is
+/// dropped. Dereferences to the interface.
+pub struct ScopedProtocol<T: Protocol + 'static> {
+    interface: &'static T,
+    handle: Handle,
+    agent_handle: Handle,
+    controller_handle: Handle,
+}
+
+impl<T: Protocol + 'static> ScopedProtocol<T> {
+    /// The handle the protocol is open on.
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// Keep the protocol open, returning the interface. A driver does this in `start` for a
+    /// protocol opened `BY_DRIVER`, and closes it with `BootServices::close_protocol` in `stop`.
+    pub fn leak(self) -> &'static T {
+        let interface = self.interface;
+        mem::forget(self);
+        interface
+    }
+}
+
+impl<T: Protocol + 'static> Deref for ScopedProtocol<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.interface
+    }
+}
+
+impl<T: Protocol + 'static> Drop for ScopedProtocol<T> {
+    fn drop(&mut self) {
+        // Only fails if the protocol was closed behind the guard's back.
+        let _ = ::get_system_table().boot_services()
+            .close_protocol::<T>(self.handle, self.agent_handle, self.controller_handle);
+    }
+}
+
+/// Iterator over all instances of protocol `T`, returned by `BootServices::locate_all_protocols`.
+pub struct ProtocolInstances<T: Protocol + 'static> {
+    handles: Handles,
+    index: usize,
+    _marker: PhantomData<&'static T>,
+}
+
+impl<T: Protocol + 'static> ProtocolInstances<T> {
+    /// The handles found, including any whose interface could not be retrieved.
+    pub fn handles(&self) -> &Handles {
+        &self.handles
+    }
+}
+
+impl<T: Protocol + 'static> Iterator for ProtocolInstances<T> {
+    type Item = (Handle, &'static T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bs = ::get_system_table().boot_services();
+
+        while let Some(&handle) = self.handles.as_slice().get(self.index) {
+            self.index += 1;
+            // The protocol may have been uninstalled since the handles were located.
+            if let Ok(interface) = bs.handle_protocol::<T>(handle) {
+                return Some((handle, interface));
+            }
+        }
+
+        None
+    }
+}
+
+#[test]
+fn multiple_protocol_interfaces_call() {
+    static mut ARGUMENTS: [usize; 6] = [usize::MAX; 6];
+
+    unsafe extern "win64" fn service(handle: *const CVoid, guid0: *const CVoid, interface0: *const CVoid,
+                                     guid1: *const CVoid, interface1: *const CVoid, end: *const CVoid) -> Status {
+        let arguments = [handle, guid0, interface0, guid1, interface1, end].map(|p| p as usize);
+        *ptr::addr_of_mut!(ARGUMENTS) = arguments;
+        Status::Success
+    }
+
+    let (a, b) = (guid::Guid(1, 2, 3, [0; 8]), guid::Guid(4, 5, 6, [0; 8]));
+    let interfaces = [(&a, 0x1000 as *const CVoid), (&b, 0x2000 as *const CVoid)];
+    let status = unsafe { call_multiple_protocol_interfaces_len!(service as *const CVoid, 0x10 as *const CVoid, &interfaces[..]) };
+    assert_eq!(status, Status::Success);
+    assert_eq!(unsafe { *ptr::addr_of!(ARGUMENTS) }, [0x10, &a as *const _ as usize, 0x1000, &b as *const _ as usize, 0x2000, 0]);
+
+    let none: [(&guid::Guid, *const CVoid); 0] = [];
+    assert_eq!(unsafe { call_multiple_protocol_interfaces_len!(service as *const CVoid, ptr::null(), &none[..]) }, Status::InvalidParameter);
+}
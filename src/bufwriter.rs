@@ -0,0 +1,113 @@
+use core::cell::RefCell;
+use core::fmt;
+
+use base::Status;
+use console::{Attribute, SimpleTextOutput};
+
+/// Number of UCS-2 characters `BufWriter` collects before writing them out.
+pub const BUF_WRITER_CAPACITY: usize = 256;
+
+struct Buffer {
+    chars: [u16; BUF_WRITER_CAPACITY + 1],
+    len: usize,
+}
+
+/// Batches output to a `SimpleTextOutput`, so that many small writes become a single
+/// OutputString call. Some firmware consoles redraw on every call, which makes verbose logging
+/// very slow when written piecemeal.
+///
+/// The buffer is written out when it fills up, at the end of each line, before the attribute is
+/// changed, on `flush`, and when the writer is dropped.
+pub struct BufWriter<T: SimpleTextOutput> {
+    inner: T,
+    buffer: RefCell<Buffer>,
+}
+
+impl<T: SimpleTextOutput> BufWriter<T> {
+    pub fn new(inner: T) -> BufWriter<T> {
+        BufWriter {
+            inner,
+            buffer: RefCell::new(Buffer {
+                chars: [0; BUF_WRITER_CAPACITY + 1],
+                len: 0,
+            }),
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Write out anything buffered.
+    pub fn flush(&self) -> Status {
+        let mut buffer = self.buffer.borrow_mut();
+        if buffer.len == 0 {
+            return Status::Success;
+        }
+
+        let len = buffer.len;
+        buffer.chars[len] = 0;
+        buffer.len = 0;
+        self.inner.write_raw(buffer.chars.as_ptr())
+    }
+
+    /// Flush the buffer and return the wrapped output.
+    pub fn into_inner(self) -> T {
+        self.flush();
+        let this = ::core::mem::ManuallyDrop::new(self);
+        unsafe { ::core::ptr::read(&this.inner) }
+    }
+}
+
+impl<T: SimpleTextOutput> SimpleTextOutput for BufWriter<T> {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn write_raw(&self, str: *const u16) -> Status {
+        let mut i = 0;
+        loop {
+            let c = unsafe { *str.add(i) };
+            if c == 0 {
+                return Status::Success;
+            }
+            i += 1;
+
+            let full = {
+                let mut buffer = self.buffer.borrow_mut();
+                let len = buffer.len;
+                buffer.chars[len] = c;
+                buffer.len += 1;
+                buffer.len == BUF_WRITER_CAPACITY
+            };
+
+            if full || c == b'\n' as u16 {
+                let status = self.flush();
+                if status != Status::Success {
+                    return status;
+                }
+            }
+        }
+    }
+
+    fn set_attribute(&self, attribute: Attribute) -> Status {
+        // Text already written has to come out in the old colours.
+        let status = self.flush();
+        if status != Status::Success {
+            return status;
+        }
+        self.inner.set_attribute(attribute)
+    }
+}
+
+impl<T: SimpleTextOutput> fmt::Write for BufWriter<T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.write(s) == Status::Success {
+            return Ok(());
+        }
+        Err(fmt::Error)
+    }
+}
+
+impl<T: SimpleTextOutput> Drop for BufWriter<T> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,144 @@
+//! Detection of the firmware implementation and the services it offers, so portable programs can
+//! leave out features instead of failing on firmware that only implements part of UEFI, such as
+//! U-Boot's.
+
+use core::slice;
+
+use base::Status;
+use protocol::{
+    BlockIo2Protocol, DevicePathToTextProtocol, DevicePathUtilitiesProtocol, GraphicsOutputProtocol, HiiStringProtocol,
+    SimpleFileSystemProtocol, EFI_SERIAL_IO_PROTOCOL_GUID,
+};
+use runtimeservices::{runtime_services_supported, RuntimeServicesSupported, EFI_GLOBAL_VARIABLE_GUID};
+use util::utf16_strlen;
+
+/// The firmware implementation, recognised from the vendor string of the system table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FirmwareImplementation {
+    /// EDK2 and firmware built directly from it, such as OVMF.
+    Edk2,
+    /// U-Boot's UEFI implementation.
+    UBoot,
+    Apple,
+    Ami,
+    Insyde,
+    Phoenix,
+    Unknown,
+}
+
+/// Vendor string prefixes of the implementations, in the order they are tried.
+const VENDORS: [(&str, FirmwareImplementation); 7] = [
+    ("EDK II", FirmwareImplementation::Edk2),
+    ("EDK2", FirmwareImplementation::Edk2),
+    ("Das U-Boot", FirmwareImplementation::UBoot),
+    ("Apple", FirmwareImplementation::Apple),
+    ("American Megatrends", FirmwareImplementation::Ami),
+    ("INSYDE", FirmwareImplementation::Insyde),
+    ("Phoenix", FirmwareImplementation::Phoenix),
+];
+
+impl FirmwareImplementation {
+    /// Recognise the implementation from `vendor`, the UCS-2 vendor string.
+    pub fn from_vendor(vendor: &[u16]) -> FirmwareImplementation {
+        VENDORS.iter()
+            .find(|&&(prefix, _)| {
+                vendor.len() >= prefix.len()
+                    && prefix.bytes().zip(vendor).all(|(p, &v)| v < 0x80 && (v as u8).eq_ignore_ascii_case(&p))
+            })
+            .map(|&(_, implementation)| implementation)
+            .unwrap_or(FirmwareImplementation::Unknown)
+    }
+}
+
+/// What the running firmware provides, from `Capabilities::probe`.
+///
+/// ```rust,ignore
+/// let caps = Capabilities::probe();
+/// if !caps.persistent_variables {
+///     println!("Settings will not be kept after a reboot");
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Capabilities {
+    pub implementation: FirmwareImplementation,
+    /// The UEFI revision the firmware claims, as in `SystemTable::uefi_revision`.
+    pub uefi_revision: u32,
+    pub runtime_services: RuntimeServicesSupported,
+    /// Whether variables can be read. U-Boot may be built without variable support.
+    pub variables: bool,
+    /// Whether variables set at boot time are likely to be kept across a reset. U-Boot keeps
+    /// them in a file on the ESP, or not at all, unless built for a secure variable store, so
+    /// this is false there.
+    pub persistent_variables: bool,
+    pub graphics_output: bool,
+    pub simple_file_system: bool,
+    pub block_io2: bool,
+    pub serial_io: bool,
+    pub device_path_to_text: bool,
+    pub device_path_utilities: bool,
+    pub hii: bool,
+}
+
+impl Capabilities {
+    /// Probe the firmware. This looks for protocols and reads a variable, so it needs boot
+    /// services; it changes nothing.
+    pub fn probe() -> Capabilities {
+        let st = ::get_system_table();
+        let bs = st.boot_services();
+        let vendor = st.vendor();
+        let implementation = if vendor.is_null() {
+            FirmwareImplementation::Unknown
+        } else {
+            FirmwareImplementation::from_vendor(unsafe { slice::from_raw_parts(vendor, utf16_strlen(vendor)) })
+        };
+
+        // Reading any variable tells whether the service works; it doesn't matter if it exists.
+        let mut buf = [0u8; 64];
+        let variables = match st.runtime_services().get_variable("PlatformLang", &EFI_GLOBAL_VARIABLE_GUID, &mut buf) {
+            Ok(_) => true,
+            Err(status) => status != Status::Unsupported && status != Status::DeviceError,
+        };
+
+        Capabilities {
+            implementation,
+            uefi_revision: st.uefi_revision(),
+            runtime_services: runtime_services_supported(),
+            variables,
+            persistent_variables: variables && implementation != FirmwareImplementation::UBoot,
+            graphics_output: bs.exists::<GraphicsOutputProtocol>(),
+            simple_file_system: bs.exists::<SimpleFileSystemProtocol>(),
+            block_io2: bs.exists::<BlockIo2Protocol>(),
+            // The serial I/O protocol's type wraps the interface, so look it up by GUID.
+            serial_io: bs.exists_by_guid(&EFI_SERIAL_IO_PROTOCOL_GUID),
+            device_path_to_text: bs.exists::<DevicePathToTextProtocol>(),
+            device_path_utilities: bs.exists::<DevicePathUtilitiesProtocol>(),
+            hii: bs.exists::<HiiStringProtocol>(),
+        }
+    }
+
+    /// Whether this is U-Boot or another implementation known to leave out much of UEFI, where
+    /// anything beyond the basics should be probed for before use.
+    pub fn is_reduced(&self) -> bool {
+        self.implementation == FirmwareImplementation::UBoot
+            || !self.hii
+            || !self.device_path_utilities
+    }
+}
+
+#[test]
+fn firmware_implementation_from_vendor() {
+    let ucs2 = |s: &str, buf: &mut [u16; 32]| -> usize {
+        for (b, c) in buf.iter_mut().zip(s.bytes()) {
+            *b = c as u16;
+        }
+        s.len()
+    };
+    let mut buf = [0u16; 32];
+
+    let len = ucs2("EDK II", &mut buf);
+    assert_eq!(FirmwareImplementation::from_vendor(&buf[..len]), FirmwareImplementation::Edk2);
+    let len = ucs2("Das U-Boot", &mut buf);
+    assert_eq!(FirmwareImplementation::from_vendor(&buf[..len]), FirmwareImplementation::UBoot);
+    let len = ucs2("Das U", &mut buf);
+    assert_eq!(FirmwareImplementation::from_vendor(&buf[..len]), FirmwareImplementation::Unknown);
+}
@@ -1,8 +1,12 @@
 use core::fmt;
 
-use void::*;
+use core::ptr;
+
 use base::{Event, Status};
+use event::TimerDelay;
+use protocol::{ConsoleControlProtocol, ConsoleControlScreenMode, GraphicsOutputProtocol};
 use systemtable;
+use util::char_to_ucs2;
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -71,13 +75,98 @@ pub struct SimpleTextOutputProtocol {
     reset: unsafe extern "win64" fn(*const SimpleTextOutputProtocol, u8) -> Status,
     output_string: unsafe extern "win64" fn(*const SimpleTextOutputProtocol, *const u16) -> Status,
     test_string: unsafe extern "win64" fn(*const SimpleTextOutputProtocol, *const u16) -> Status,
-    query_mode: *const NotYetDef,
-    set_mode: *const NotYetDef,
+    query_mode: unsafe extern "win64" fn(*const SimpleTextOutputProtocol, usize, *mut usize, *mut usize) -> Status,
+    set_mode: unsafe extern "win64" fn(*const SimpleTextOutputProtocol, usize) -> Status,
     set_attribute: unsafe extern "win64" fn(*const SimpleTextOutputProtocol, usize) -> Status,
     clear_screen: unsafe extern "win64" fn(*const SimpleTextOutputProtocol) -> Status,
-    set_cursor_position: *const NotYetDef,
-    enable_cursor: *const NotYetDef,
-    mode: *const NotYetDef,
+    set_cursor_position: unsafe extern "win64" fn(*const SimpleTextOutputProtocol, usize, usize) -> Status,
+    enable_cursor: unsafe extern "win64" fn(*const SimpleTextOutputProtocol, u8) -> Status,
+    mode: *const SimpleTextOutputMode,
+}
+
+/// Type for SIMPLE_TEXT_OUTPUT_MODE, the current state of a text output device.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct SimpleTextOutputMode {
+    pub max_mode: i32,
+    pub mode: i32,
+    pub attribute: i32,
+    pub cursor_column: i32,
+    pub cursor_row: i32,
+    /// A BOOLEAN, which firmware may set to any nonzero value for true.
+    cursor_visible: u8,
+}
+
+impl SimpleTextOutputMode {
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible != 0
+    }
+}
+
+/// A snapshot of the console output state, taken with `Console::save_state`.
+#[derive(Clone, Copy, Debug)]
+pub struct ConsoleState {
+    mode: usize,
+    attribute: usize,
+    cursor_column: usize,
+    cursor_row: usize,
+    cursor_visible: bool,
+}
+
+/// The text mode a console is in and its size, from `Console::current_mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConsoleMode {
+    pub mode: usize,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+/// Notices console mode changes that the code using it didn't make, such as `SetMode` calls
+/// from a chainloaded application that has returned, or a resolution change through GOP, so
+/// full-screen layouts can be recomputed.
+///
+/// ```rust,ignore
+/// let mut watcher = ModeWatcher::new(&console);
+/// loop {
+///     if let Some(mode) = watcher.mode_changed(&console) {
+///         menu.layout(mode.columns, mode.rows);
+///         menu.redraw(&console);
+///     }
+///     // ...
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct ModeWatcher {
+    output: *const SimpleTextOutputProtocol,
+    mode: Option<ConsoleMode>,
+}
+
+impl ModeWatcher {
+    /// Watch `console` from its current mode.
+    pub fn new(console: &Console) -> ModeWatcher {
+        ModeWatcher { output: console.output, mode: console.current_mode().ok() }
+    }
+
+    /// Record that the console is now as `output` and `mode` say, returning `mode` if that
+    /// differs from what was recorded last.
+    fn update(&mut self, output: *const SimpleTextOutputProtocol, mode: Option<ConsoleMode>) -> Option<ConsoleMode> {
+        let changed = output != self.output || mode != self.mode;
+        self.output = output;
+        self.mode = mode;
+        if changed { mode } else { None }
+    }
+
+    /// The new mode if the console has changed mode, or size, since the watcher was made or
+    /// last called. A console whose output device was replaced in the system table counts as
+    /// changed too.
+    pub fn mode_changed(&mut self, console: &Console) -> Option<ConsoleMode> {
+        self.update(console.output, console.current_mode().ok())
+    }
+
+    /// Record the console's current mode as seen, for after changing it deliberately.
+    pub fn acknowledge(&mut self, console: &Console) {
+        self.mode_changed(console);
+    }
 }
 
 pub trait SimpleTextOutput {
@@ -85,6 +174,8 @@ pub trait SimpleTextOutput {
 
     fn set_attribute(&self, attribute: Attribute) -> Status;
 
+    /// Write `s`, converted to UCS-2 in small chunks. Characters UCS-2 cannot represent are
+    /// written as U+FFFD.
     fn write(&self, s: &str) -> Status {
         let mut buf = [0u16; 64];
         let mut i = 0;
@@ -95,7 +186,7 @@ pub trait SimpleTextOutput {
         }
 
         for c in s.chars() {
-            buf[i] = c as u16;
+            buf[i] = char_to_ucs2(c);
             i += 1;
 
             // if we hit the end of buf, send output
@@ -122,6 +213,23 @@ pub trait SimpleTextOutput {
     }
 }
 
+/// Result of `Console::countdown`.
+#[derive(Clone, Copy, Debug)]
+pub enum Countdown {
+    /// The countdown ran to zero without a key being pressed.
+    Expired,
+    /// A key was pressed before the countdown ran out.
+    Interrupted(InputKey),
+}
+
+/// Whether the screen shows the text console or is left to graphics drawn through GOP. Used with
+/// `Console::set_display_mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayMode {
+    Text,
+    Graphics,
+}
+
 pub trait SimpleTextInput {
     fn read_key_async(&self) -> Result<InputKey, Status>;
     fn read_key(&self) -> Result<InputKey, Status>;
@@ -155,6 +263,200 @@ impl Console {
     pub fn wait_for_key(&self) -> Event {
         self.input.wait_for_key
     }
+
+    /// Count down `seconds` seconds, calling `on_tick` with the number of seconds remaining at
+    /// the start and after every second, until either the time runs out or a key is pressed.
+    /// This is the building block for boot menu timeouts.
+    ///
+    /// The countdown waits on a periodic timer and the keyboard together rather than stalling,
+    /// so a keypress is noticed immediately.
+    pub fn countdown<F: FnMut(usize)>(&self, seconds: usize, mut on_tick: F) -> Result<Countdown, Status> {
+        let bs = self.system_table.boot_services();
+
+        on_tick(seconds);
+        if seconds == 0 {
+            return Ok(Countdown::Expired);
+        }
+
+        let timer = bs.create_timer_event()?;
+        // The timer period is in units of 100ns.
+        let status = bs.set_timer(timer, TimerDelay::Periodic, 10_000_000);
+        if status != Status::Success {
+            bs.close_event(timer);
+            return Err(status);
+        }
+
+        let events = [timer, self.input.wait_for_key];
+        let mut remaining = seconds;
+        let result = loop {
+            match bs.wait_for_event(&events) {
+                Ok(0) => {
+                    remaining -= 1;
+                    on_tick(remaining);
+                    if remaining == 0 {
+                        break Ok(Countdown::Expired);
+                    }
+                }
+                Ok(_) => match self.read_key_async() {
+                    Ok(key) => break Ok(Countdown::Interrupted(key)),
+                    Err(Status::NotReady) => continue,
+                    Err(e) => break Err(e),
+                },
+                Err(e) => break Err(e),
+            }
+        };
+
+        bs.close_event(timer);
+        result
+    }
+
+    /// Get the current output mode information.
+    pub fn mode(&self) -> SimpleTextOutputMode {
+        unsafe { *self.output.mode }
+    }
+
+    /// Return the number of (columns, rows) of text mode `mode`.
+    pub fn query_mode(&self, mode: usize) -> Result<(usize, usize), Status> {
+        let mut columns: usize = 0;
+        let mut rows: usize = 0;
+
+        let status = unsafe { (self.output.query_mode)(self.output, mode, &mut columns, &mut rows) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok((columns, rows))
+    }
+
+    /// The text mode in use with its number of columns and rows.
+    pub fn current_mode(&self) -> Result<ConsoleMode, Status> {
+        let mode = self.mode().mode as usize;
+        let (columns, rows) = self.query_mode(mode)?;
+        Ok(ConsoleMode { mode, columns, rows })
+    }
+
+    /// Switch to text mode `mode`. This also clears the screen.
+    pub fn set_mode(&self, mode: usize) -> Status {
+        unsafe {
+            (self.output.set_mode)(self.output, mode)
+        }
+    }
+
+    pub fn clear_screen(&self) -> Status {
+        unsafe {
+            (self.output.clear_screen)(self.output)
+        }
+    }
+
+    pub fn set_cursor_position(&self, column: usize, row: usize) -> Status {
+        unsafe {
+            (self.output.set_cursor_position)(self.output, column, row)
+        }
+    }
+
+    /// Check whether the output device can display every character of `s`. Returns
+    /// `Status::WarnUnknownGlyph` if the font lacks a glyph for one of them.
+    pub fn test_string(&self, s: &str) -> Status {
+        let mut buf = [0u16; 64];
+        let mut i = 0;
+
+        for c in s.chars() {
+            buf[i] = char_to_ucs2(c);
+            i += 1;
+
+            if i == buf.len() - 1 {
+                buf[i] = 0;
+                let status = unsafe { (self.output.test_string)(self.output, buf.as_ptr()) };
+                if status != Status::Success {
+                    return status;
+                }
+                i = 0;
+            }
+        }
+
+        if i > 0 {
+            buf[i] = 0;
+            return unsafe { (self.output.test_string)(self.output, buf.as_ptr()) };
+        }
+
+        Status::Success
+    }
+
+    /// Make the cursor visible or invisible. Not all devices support an invisible cursor.
+    pub fn enable_cursor(&self, visible: bool) -> Status {
+        unsafe {
+            (self.output.enable_cursor)(self.output, visible as u8)
+        }
+    }
+
+    /// Switch the screen between text and graphics. Where the firmware has the console control
+    /// protocol, as Apple's does, this goes through it, since text output is otherwise drawn
+    /// over graphics or not shown at all. Elsewhere the text console and GOP share the screen,
+    /// so switching only clears it and shows or hides the cursor; switching to graphics fails
+    /// with `Status::Unsupported` if there is no GOP.
+    pub fn set_display_mode(&self, mode: DisplayMode) -> Status {
+        let bs = self.system_table.boot_services();
+
+        if let Ok(control) = bs.locate_protocol::<ConsoleControlProtocol>(ptr::null()) {
+            return control.set_mode(match mode {
+                DisplayMode::Text => ConsoleControlScreenMode::Text,
+                DisplayMode::Graphics => ConsoleControlScreenMode::Graphics,
+            });
+        }
+
+        if mode == DisplayMode::Graphics && bs.locate_protocol::<GraphicsOutputProtocol>(ptr::null()).is_err() {
+            return Status::Unsupported;
+        }
+        let status = self.clear_screen();
+        if status != Status::Success {
+            return status;
+        }
+        match self.enable_cursor(mode == DisplayMode::Text) {
+            Status::Unsupported => Status::Success,
+            s => s,
+        }
+    }
+
+    /// Capture the mode, attribute and cursor state of the console, so it can be put back with
+    /// `restore_state` before handing the console back to the firmware or shell.
+    pub fn save_state(&self) -> ConsoleState {
+        let mode = self.mode();
+
+        ConsoleState {
+            mode: mode.mode as usize,
+            attribute: mode.attribute as usize,
+            cursor_column: mode.cursor_column as usize,
+            cursor_row: mode.cursor_row as usize,
+            cursor_visible: mode.cursor_visible(),
+        }
+    }
+
+    /// Restore a state previously captured with `save_state`. The text mode is only changed (and
+    /// the screen cleared) if it differs from the current one.
+    pub fn restore_state(&self, state: &ConsoleState) -> Status {
+        if self.mode().mode as usize != state.mode {
+            let status = self.set_mode(state.mode);
+            if status != Status::Success {
+                return status;
+            }
+        }
+
+        let status = unsafe { (self.output.set_attribute)(self.output, state.attribute) };
+        if status != Status::Success {
+            return status;
+        }
+
+        let status = self.set_cursor_position(state.cursor_column, state.cursor_row);
+        if status != Status::Success {
+            return status;
+        }
+
+        // Devices which cannot hide the cursor return Unsupported; that is not worth failing for.
+        match self.enable_cursor(state.cursor_visible) {
+            Status::Unsupported => Status::Success,
+            s => s,
+        }
+    }
 }
 
 impl SimpleTextOutput for Console {
@@ -217,3 +519,19 @@ impl fmt::Write for Console {
     }
 }
 
+
+#[test]
+fn mode_watcher_changes() {
+    let output = 0x1000 as *const SimpleTextOutputProtocol;
+    let standard = ConsoleMode { mode: 0, columns: 80, rows: 25 };
+    let mut watcher = ModeWatcher { output, mode: Some(standard) };
+
+    assert_eq!(watcher.update(output, Some(standard)), None);
+
+    // The same mode number at a new resolution is a change.
+    let larger = ConsoleMode { mode: 0, columns: 128, rows: 40 };
+    assert_eq!(watcher.update(output, Some(larger)), Some(larger));
+    assert_eq!(watcher.update(output, Some(larger)), None);
+
+    assert_eq!(watcher.update(0x2000 as *const SimpleTextOutputProtocol, Some(larger)), Some(larger));
+}
@@ -0,0 +1,291 @@
+//! The coreboot tables, which coreboot leaves in memory for its payload. When the UEFI firmware
+//! is a payload of coreboot (EDK2's UefiPayloadPkg, for instance), they describe what the UEFI
+//! interfaces do not: the serial console coreboot used, the board and its IDs.
+//!
+//! Coreboot does not publish the tables as a configuration table. Like Linux and coreboot's own
+//! payloads, `CorebootTable::find` looks for the "LBIO" header in the legacy BIOS area, and
+//! follows the forward record there to the full table. Coreboot also leaves a copy in the first
+//! 4 KiB of memory, which `CorebootTable::find_including_low_memory` searches as well.
+
+use core::{slice, str};
+
+use base::Status;
+use util::wire;
+
+/// Size of the header before the records.
+pub const COREBOOT_HEADER_SIZE: usize = 24;
+
+pub const LB_TAG_MAINBOARD: u32 = 0x0003;
+pub const LB_TAG_VERSION: u32 = 0x0004;
+pub const LB_TAG_SERIAL: u32 = 0x000F;
+pub const LB_TAG_FORWARD: u32 = 0x0011;
+pub const LB_TAG_CBMEM_CONSOLE: u32 = 0x0017;
+pub const LB_TAG_BOARD_ID: u32 = 0x0025;
+pub const LB_TAG_RAM_CODE: u32 = 0x0028;
+pub const LB_TAG_SKU_ID: u32 = 0x002D;
+
+/// The legacy BIOS area, searched for the header as (start, end).
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const BIOS_RANGE: (usize, usize) = (0xF0000, 0x100000);
+
+/// The first page of memory, searched only on request. It starts past address 0, which cannot be
+/// dereferenced; the header is 16-byte aligned so nothing is missed.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const LOW_RANGE: (usize, usize) = (0x10, 0x1000);
+
+/// The 16-bit ones' complement checksum of the internet protocols, which coreboot uses.
+fn ip_checksum(bytes: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for (i, &b) in bytes.iter().enumerate() {
+        sum += if i % 2 == 0 { b as u32 } else { (b as u32) << 8 };
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// How a coreboot serial console is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorebootSerialKind {
+    /// In I/O port space.
+    Io,
+    /// Memory-mapped.
+    Mmio,
+}
+
+/// The serial console coreboot used, from its `LB_TAG_SERIAL` record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CorebootSerial {
+    pub kind: CorebootSerialKind,
+    /// The I/O port or physical address of the UART.
+    pub base: u32,
+    pub baud: u32,
+    /// Distance between registers in bytes.
+    pub reg_width: u32,
+    /// The UART's input clock in Hz, 0 if unknown.
+    pub input_hertz: u32,
+}
+
+/// A validated coreboot table.
+///
+/// ```rust,ignore
+/// if let Some(serial) = CorebootTable::find().and_then(|cb| cb.serial()) {
+///     open_uart(serial.base, serial.baud);
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CorebootTable<'a> {
+    records: &'a [u8],
+    entries: u32,
+}
+
+impl<'a> CorebootTable<'a> {
+    /// Parse the table whose header starts `buf`, checking the checksums of the header and the
+    /// records. The table is returned as is, even if it only holds a forward record.
+    pub fn parse(buf: &'a [u8]) -> Result<CorebootTable<'a>, Status> {
+        if buf.len() < COREBOOT_HEADER_SIZE || &buf[..4] != b"LBIO" {
+            return Err(Status::NotFound);
+        }
+        let header_bytes = wire::read_u32(buf, 4)? as usize;
+        let table_bytes = wire::read_u32(buf, 12)? as usize;
+        let table_checksum = wire::read_u32(buf, 16)?;
+        if header_bytes != COREBOOT_HEADER_SIZE || ip_checksum(&buf[..COREBOOT_HEADER_SIZE]) != 0 {
+            return Err(Status::InvalidParameter);
+        }
+
+        let records = buf.get(header_bytes..header_bytes + table_bytes).ok_or(Status::BufferTooSmall)?;
+        if ip_checksum(records) as u32 != table_checksum {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(CorebootTable { records, entries: wire::read_u32(buf, 20)? })
+    }
+
+    /// The records, as (tag, record) pairs. Each record includes its tag and size fields, as
+    /// offsets in coreboot's structures count from them.
+    pub fn records(&self) -> CorebootRecords<'a> {
+        CorebootRecords { buf: self.records, remaining: self.entries }
+    }
+
+    fn record(&self, tag: u32) -> Option<&'a [u8]> {
+        self.records().find(|&(t, _)| t == tag).map(|(_, record)| record)
+    }
+
+    fn record_u32(&self, tag: u32) -> Option<u32> {
+        self.record(tag).and_then(|r| wire::read_u32(r, 8).ok())
+    }
+
+    fn record_u64(&self, tag: u32) -> Option<u64> {
+        self.record(tag).and_then(|r| wire::read_u64(r, 8).ok())
+    }
+
+    /// The serial console, if coreboot had one.
+    pub fn serial(&self) -> Option<CorebootSerial> {
+        let r = self.record(LB_TAG_SERIAL)?;
+        let kind = match wire::read_u32(r, 8).ok()? {
+            1 => CorebootSerialKind::Io,
+            2 => CorebootSerialKind::Mmio,
+            _ => return None,
+        };
+
+        Some(CorebootSerial {
+            kind,
+            base: wire::read_u32(r, 12).ok()?,
+            baud: wire::read_u32(r, 16).ok()?,
+            reg_width: wire::read_u32(r, 20).ok()?,
+            // Older coreboot versions end the record before this field.
+            input_hertz: wire::read_u32(r, 24).unwrap_or(0),
+        })
+    }
+
+    /// The mainboard's (vendor, part number), such as ("Google", "Brya").
+    pub fn mainboard(&self) -> Option<(&'a str, &'a str)> {
+        let r = self.record(LB_TAG_MAINBOARD)?;
+        let strings = r.get(10..)?;
+        let string = |index: u8| -> Option<&'a str> {
+            let s = strings.get(index as usize..)?;
+            str::from_utf8(&s[..s.iter().position(|&b| b == 0)?]).ok()
+        };
+        Some((string(r[8])?, string(r[9])?))
+    }
+
+    /// The coreboot version string.
+    pub fn version(&self) -> Option<&'a str> {
+        let s = self.record(LB_TAG_VERSION)?.get(8..)?;
+        str::from_utf8(&s[..s.iter().position(|&b| b == 0).unwrap_or(s.len())]).ok()
+    }
+
+    /// The board revision, from the board's strapping pins.
+    pub fn board_id(&self) -> Option<u32> {
+        self.record_u32(LB_TAG_BOARD_ID)
+    }
+
+    /// The strapping identifying the memory fitted.
+    pub fn ram_code(&self) -> Option<u32> {
+        self.record_u32(LB_TAG_RAM_CODE)
+    }
+
+    pub fn sku_id(&self) -> Option<u32> {
+        self.record_u32(LB_TAG_SKU_ID)
+    }
+
+    /// The physical address of coreboot's in-memory console log.
+    pub fn cbmem_console(&self) -> Option<u64> {
+        self.record_u64(LB_TAG_CBMEM_CONSOLE)
+    }
+}
+
+impl CorebootTable<'static> {
+    /// Find the coreboot table in memory, if the firmware was started by coreboot. Only x86
+    /// keeps the table at a known place; elsewhere this returns `None`.
+    pub fn find() -> Option<CorebootTable<'static>> {
+        Self::find_in(false)
+    }
+
+    /// Like `find`, but also search the first 4 KiB of memory, where coreboot keeps another
+    /// copy of the header. Firmware that leaves page 0 unmapped to catch NULL pointers faults
+    /// on this, so only call it where page 0 is known to be mapped.
+    pub fn find_including_low_memory() -> Option<CorebootTable<'static>> {
+        Self::find_in(true)
+    }
+
+    fn find_in(low_memory: bool) -> Option<CorebootTable<'static>> {
+        let table = Self::search(low_memory)?;
+        // The low table usually only forwards to the full one in high memory.
+        match table.record_u64(LB_TAG_FORWARD) {
+            Some(forward) => unsafe { Self::at(forward as usize) },
+            None => Some(table),
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn search(low_memory: bool) -> Option<CorebootTable<'static>> {
+        let ranges = if low_memory { &[LOW_RANGE, BIOS_RANGE][..] } else { &[BIOS_RANGE][..] };
+        ranges.iter()
+            .flat_map(|&(start, end)| (start..end).step_by(16))
+            .filter_map(|address| unsafe { Self::at(address) })
+            .next()
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    fn search(_low_memory: bool) -> Option<CorebootTable<'static>> {
+        None
+    }
+
+    /// View the table at `address`, checking it.
+    unsafe fn at(address: usize) -> Option<CorebootTable<'static>> {
+        if address == 0 {
+            return None;
+        }
+
+        let header = slice::from_raw_parts(address as *const u8, COREBOOT_HEADER_SIZE);
+        if &header[..4] != b"LBIO" {
+            return None;
+        }
+        let len = COREBOOT_HEADER_SIZE + wire::read_u32(header, 12).ok()? as usize;
+        CorebootTable::parse(slice::from_raw_parts(address as *const u8, len)).ok()
+    }
+}
+
+/// Iterator returned by `CorebootTable::records`.
+#[derive(Clone, Debug)]
+pub struct CorebootRecords<'a> {
+    buf: &'a [u8],
+    remaining: u32,
+}
+
+impl<'a> Iterator for CorebootRecords<'a> {
+    type Item = (u32, &'a [u8]);
+
+    fn next(&mut self) -> Option<(u32, &'a [u8])> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let tag = wire::read_u32(self.buf, 0).ok()?;
+        let size = wire::read_u32(self.buf, 4).ok()? as usize;
+        if size < 8 || size > self.buf.len() {
+            return None;
+        }
+
+        let (record, rest) = self.buf.split_at(size);
+        self.buf = rest;
+        self.remaining -= 1;
+        Some((tag, record))
+    }
+}
+
+#[test]
+fn coreboot_table_parse() {
+    let mut buf = [0u8; COREBOOT_HEADER_SIZE + 68];
+    {
+        let records = &mut buf[COREBOOT_HEADER_SIZE..];
+        // Serial: I/O port 0x3F8 at 115200 baud, without the input clock field.
+        for (i, v) in [LB_TAG_SERIAL, 24, 1, 0x3F8, 115200, 1].iter().enumerate() {
+            wire::write_u32(records, i * 4, *v).unwrap();
+        }
+        for (i, v) in [LB_TAG_BOARD_ID, 12, 3].iter().enumerate() {
+            wire::write_u32(records, 24 + i * 4, *v).unwrap();
+        }
+        wire::write_u32(records, 36, LB_TAG_MAINBOARD).unwrap();
+        wire::write_u32(records, 40, 32).unwrap();
+        records[44..52].copy_from_slice(&[0, 7, b'G', b'o', b'o', b'g', b'l', b'e']);
+        records[52..58].copy_from_slice(b"\0Brya\0");
+    }
+    let checksum = ip_checksum(&buf[COREBOOT_HEADER_SIZE..]);
+    buf[..4].copy_from_slice(b"LBIO");
+    for (i, v) in [COREBOOT_HEADER_SIZE as u32, 0, 68, checksum as u32, 3].iter().enumerate() {
+        wire::write_u32(&mut buf, 4 + i * 4, *v).unwrap();
+    }
+    let header_checksum = ip_checksum(&buf[..COREBOOT_HEADER_SIZE]);
+    wire::write_u32(&mut buf, 8, header_checksum as u32).unwrap();
+
+    let table = CorebootTable::parse(&buf).unwrap();
+    assert_eq!(table.records().count(), 3);
+    assert_eq!(table.serial(), Some(CorebootSerial {
+        kind: CorebootSerialKind::Io, base: 0x3F8, baud: 115200, reg_width: 1, input_hertz: 0,
+    }));
+    assert_eq!(table.board_id(), Some(3));
+    assert_eq!(table.mainboard(), Some(("Google", "Brya")));
+    assert_eq!(table.sku_id(), None);
+
+    buf[30] ^= 1;
+    assert_eq!(CorebootTable::parse(&buf).unwrap_err(), Status::InvalidParameter);
+}
@@ -0,0 +1,131 @@
+//! Guards that turn concurrent use of a device, from nested calls or event callbacks, into an
+//! error instead of interleaved commands.
+
+use base::Status;
+use task::{TplCell, TPL};
+
+/// Most devices that can be locked at once.
+pub const MAX_LOCKED_DEVICES: usize = 16;
+
+/// A lock taken by `lock_device`, with the TPL to go back to once it is released.
+#[derive(Clone, Copy)]
+struct Lock {
+    key: usize,
+    old_tpl: usize,
+    released: bool,
+}
+
+/// The locks held, in the order taken. A lock released before ones taken after it stays until
+/// they are released too, so the TPL only comes down once nothing above it holds a lock.
+struct Locks {
+    locks: [Lock; MAX_LOCKED_DEVICES],
+    len: usize,
+}
+
+impl Locks {
+    /// Lock the device `key`, taken at `old_tpl`, returning where the lock is kept.
+    fn lock(&mut self, key: usize, old_tpl: usize) -> Result<usize, Status> {
+        if self.locks[..self.len].iter().any(|lock| lock.key == key && !lock.released) {
+            return Err(Status::AccessDenied);
+        }
+        if self.len == MAX_LOCKED_DEVICES {
+            return Err(Status::OutOfResources);
+        }
+        self.locks[self.len] = Lock { key, old_tpl, released: false };
+        self.len += 1;
+        Ok(self.len - 1)
+    }
+
+    /// Release the lock at `index`, returning the TPL to restore if that can be done now: the
+    /// lowest that the locks released since the last still held were taken at.
+    fn unlock(&mut self, index: usize) -> Option<usize> {
+        self.locks[index].released = true;
+        let mut tpl: Option<usize> = None;
+        while self.len > 0 && self.locks[self.len - 1].released {
+            self.len -= 1;
+            let old_tpl = self.locks[self.len].old_tpl;
+            tpl = Some(tpl.map_or(old_tpl, |tpl| tpl.min(old_tpl)));
+        }
+        tpl
+    }
+}
+
+const NO_LOCK: Lock = Lock { key: 0, old_tpl: 0, released: true };
+
+static LOCKS: TplCell<Locks> = TplCell::new(Locks { locks: [NO_LOCK; MAX_LOCKED_DEVICES], len: 0 });
+
+/// Holds a device locked by `lock_device` until it is dropped.
+pub struct DeviceGuard {
+    index: usize,
+}
+
+/// Lock the device whose protocol interface is `device` for the life of the returned guard.
+/// Wrappers keeping state on a device, such as `SerialIOProtocol`, take this around each
+/// operation.
+///
+/// Callbacks at `TPL::Callback` and below are held off while the guard is held, so they run
+/// after the operation rather than in the middle of it. Anything that still tries to use the
+/// device meanwhile, from a higher TPL or a nested call, fails with `Status::AccessDenied`.
+/// Fails with `Status::OutOfResources` if `MAX_LOCKED_DEVICES` devices are already locked.
+///
+/// Guards dropped out of the order they were taken in leave the TPL raised until the ones
+/// taken after them are dropped as well.
+pub fn lock_device<T>(device: &T) -> Result<DeviceGuard, Status> {
+    let bs = ::get_system_table().boot_services();
+    let key = device as *const T as usize;
+
+    let old_tpl = bs.raise_tpl(TPL::HighLevel)?;
+    match LOCKS.with(|locks| locks.lock(key, old_tpl)) {
+        Ok(index) => {
+            bs.restore_tpl(old_tpl.max(TPL::Callback as usize));
+            Ok(DeviceGuard { index })
+        }
+        Err(e) => {
+            bs.restore_tpl(old_tpl);
+            Err(e)
+        }
+    }
+}
+
+impl Drop for DeviceGuard {
+    fn drop(&mut self) {
+        let bs = ::get_system_table().boot_services();
+        // Raising to `TPL::HighLevel` can't fail.
+        let tpl = bs.raise_tpl(TPL::HighLevel).unwrap_or(TPL::HighLevel as usize);
+        let restore = LOCKS.with(|locks| locks.unlock(self.index));
+        bs.restore_tpl(restore.unwrap_or(tpl));
+    }
+}
+
+#[test]
+fn device_locks_nest() {
+    let mut locks = Locks { locks: [NO_LOCK; MAX_LOCKED_DEVICES], len: 0 };
+    let a = locks.lock(0x1000, TPL::Application as usize).unwrap();
+    assert_eq!(locks.lock(0x1000, TPL::Callback as usize), Err(Status::AccessDenied));
+    let b = locks.lock(0x2000, TPL::Callback as usize).unwrap();
+    assert_eq!(locks.unlock(b), Some(TPL::Callback as usize));
+    assert_eq!(locks.unlock(a), Some(TPL::Application as usize));
+    assert_eq!(locks.len, 0);
+}
+
+#[test]
+fn device_locks_released_out_of_order() {
+    let mut locks = Locks { locks: [NO_LOCK; MAX_LOCKED_DEVICES], len: 0 };
+    let a = locks.lock(0x1000, TPL::Application as usize).unwrap();
+    let b = locks.lock(0x2000, TPL::Callback as usize).unwrap();
+    // The TPL stays up for `b`, but `a`'s device can be locked again meanwhile.
+    assert_eq!(locks.unlock(a), None);
+    let c = locks.lock(0x1000, TPL::Callback as usize).unwrap();
+    assert_eq!(locks.unlock(c), Some(TPL::Callback as usize));
+    assert_eq!(locks.unlock(b), Some(TPL::Application as usize));
+    assert_eq!(locks.len, 0);
+}
+
+#[test]
+fn device_locks_full() {
+    let mut locks = Locks { locks: [NO_LOCK; MAX_LOCKED_DEVICES], len: 0 };
+    for key in 1..=MAX_LOCKED_DEVICES {
+        locks.lock(key, TPL::Callback as usize).unwrap();
+    }
+    assert_eq!(locks.lock(0x1000, TPL::Callback as usize), Err(Status::OutOfResources));
+}
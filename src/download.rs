@@ -0,0 +1,176 @@
+//! Streaming a download to a file while hashing it, for provisioning tools writing images too
+//! large to hold in memory.
+
+use core::{slice, str};
+
+use base::Status;
+use path::EfiPath;
+use protocol::{FileProtocol, HttpProtocol, ServiceBindingProtocol, EFI_HTTP_SERVICE_BINDING_PROTOCOL_GUID,
+               EFI_FILE_MODE_READ, EFI_FILE_MODE_WRITE, MAX_FILE_PATH};
+use util::{Sha256, SHA256_LEN};
+
+/// Size of the buffer data is streamed through.
+pub const DOWNLOAD_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Timeout for each HTTP request and response, in milliseconds.
+pub const HTTP_TIMEOUT_MS: u32 = 30_000;
+
+/// Appended to the name of the file a download is written to until its hash is checked.
+const PARTIAL_SUFFIX: &str = ".part";
+
+/// `path` with `PARTIAL_SUFFIX` appended.
+fn partial_path(path: &EfiPath) -> Result<EfiPath, Status> {
+    let path = path.as_str();
+    let mut buf = [0u8; MAX_FILE_PATH * 3 + PARTIAL_SUFFIX.len()];
+    let len = path.len() + PARTIAL_SUFFIX.len();
+    buf[..path.len()].copy_from_slice(path.as_bytes());
+    buf[path.len()..len].copy_from_slice(PARTIAL_SUFFIX.as_bytes());
+    // Both parts are whole strings.
+    Ok(EfiPath::new(unsafe { str::from_utf8_unchecked(&buf[..len]) })?)
+}
+
+/// Write everything `read` produces to `path` under `dir`, hashing it as it goes, and check the
+/// SHA-256 against `expected`. `read` fills the buffer it is given and returns the number of
+/// bytes, with 0 at the end of the data. `progress` is called with the bytes written so far and
+/// `total`, if known.
+///
+/// This is the pipeline behind `fetch_verify_store`, for sources other than HTTP such as TFTP.
+/// The data is written to `path` with ".part" appended, which only replaces the file at `path`
+/// once the hash matches; if anything fails before then, it is deleted and the old file is
+/// left alone. A hash mismatch fails with `Status::SecurityViolation`. If the verified file
+/// cannot be renamed into place, it is kept under the ".part" name. Returns the number of
+/// bytes stored.
+pub fn stream_verify_store<R, F, P>(mut read: R, expected: &[u8; SHA256_LEN], dir: &FileProtocol, path: F,
+                                    total: Option<u64>, mut progress: P) -> Result<u64, Status>
+    where R: FnMut(&mut [u8]) -> Result<usize, Status>,
+          F: AsRef<str>,
+          P: FnMut(u64, Option<u64>)
+{
+    let bs = ::get_system_table().boot_services();
+    let buf = bs.allocate_pool::<u8>(DOWNLOAD_BUFFER_SIZE)?;
+    let buf = unsafe { slice::from_raw_parts_mut(buf, DOWNLOAD_BUFFER_SIZE) };
+
+    let result = (|| {
+        let target = EfiPath::new(path.as_ref())?;
+        let name = target.file_name().ok_or(Status::InvalidParameter)?;
+        let file = dir.create_path(partial_path(&target)?)?;
+        let mut hasher = Sha256::new();
+        let mut written = 0u64;
+        let copied = loop {
+            let n = match read(buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => n,
+                Err(e) => break Err(e),
+            };
+            hasher.update(&buf[..n]);
+            if let Err(e) = file.write_all(&buf[..n]) {
+                break Err(e);
+            }
+            written += n as u64;
+            progress(written, total);
+        };
+
+        let verified = copied.and_then(|()| {
+            if total.is_some_and(|t| t != written) {
+                Err(Status::EndOfFile)
+            } else if hasher.finish() != *expected {
+                Err(Status::SecurityViolation)
+            } else {
+                Ok(written)
+            }
+        });
+        match verified {
+            Ok(written) => match file.flush() {
+                Status::Success => {
+                    if let Ok(old) = dir.open_path(target, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE) {
+                        old.delete();
+                    }
+                    let renamed = file.rename(name);
+                    file.close();
+                    renamed.map(|()| written)
+                }
+                e => {
+                    file.delete();
+                    Err(e)
+                }
+            },
+            Err(e) => {
+                file.delete();
+                Err(e)
+            }
+        }
+    })();
+
+    bs.free_pool(buf.as_ptr());
+    result
+}
+
+/// Download `url` over HTTP with the first network interface that supports it, store it as
+/// `path` under `dir` and check it against the SHA-256 `expected`, without holding more than
+/// `DOWNLOAD_BUFFER_SIZE` bytes of it in memory at once.
+///
+/// ```rust,ignore
+/// let root = esp.open_volume()?;
+/// fetch_verify_store("http://10.0.0.1/os.img", &OS_IMAGE_SHA256, root, "\\os.img", |done, total| {
+///     show_progress(done, total);
+/// })?;
+/// ```
+///
+/// Fails with `Status::SecurityViolation` if the hash does not match and `Status::NotFound` if
+/// the server does not answer 200 OK; as with `stream_verify_store`, the file at `path` is only
+/// replaced once the hash matches. Without a Content-Length, the body ends when the server
+/// closes the connection.
+pub fn fetch_verify_store<F, P>(url: &str, expected: &[u8; SHA256_LEN], dir: &FileProtocol, path: F, progress: P) -> Result<u64, Status>
+    where F: AsRef<str>,
+          P: FnMut(u64, Option<u64>)
+{
+    let bs = ::get_system_table().boot_services();
+    let handles = bs.locate_handle_by_guid(&EFI_HTTP_SERVICE_BINDING_PROTOCOL_GUID)?;
+    let &nic = handles.as_slice().first().ok_or(Status::NotFound)?;
+    let binding = bs.handle_protocol_by_guid(nic, &EFI_HTTP_SERVICE_BINDING_PROTOCOL_GUID)?;
+    let binding: &ServiceBindingProtocol = unsafe { binding.cast() };
+
+    let child = binding.create_child()?;
+    let result = (|| {
+        let http: &HttpProtocol = bs.handle_protocol(child)?;
+        http.configure_ipv4(HTTP_TIMEOUT_MS)?;
+
+        // The first part of the body arrives with the headers.
+        let mut first = [0u8; 4096];
+        let response = http.get(url, &mut first)?;
+        let total = response.content_length();
+        let mut pending = &first[..response.first_body_length()];
+        let mut received = pending.len() as u64;
+
+        let read = |buf: &mut [u8]| {
+            if !pending.is_empty() {
+                let n = pending.len().min(buf.len());
+                buf[..n].copy_from_slice(&pending[..n]);
+                pending = &pending[n..];
+                return Ok(n);
+            }
+            if total.is_some_and(|t| received >= t) {
+                return Ok(0);
+            }
+            let n = response.read_body(buf)?;
+            received += n as u64;
+            Ok(n)
+        };
+        stream_verify_store(read, expected, dir, path, total, progress)
+    })();
+    binding.destroy_child(child);
+    result
+}
+
+#[test]
+fn download_partial_path() {
+    let path = EfiPath::new("/images/os.img").unwrap();
+    assert_eq!(partial_path(&path).unwrap().as_str(), "\\images\\os.img.part");
+    assert_eq!(partial_path(&EfiPath::new("os").unwrap()).unwrap().as_str(), "os.part");
+
+    let mut long = [b'a'; MAX_FILE_PATH - 1];
+    long[0] = b'\\';
+    long[100] = b'\\';
+    let path = EfiPath::new(str::from_utf8(&long).unwrap()).unwrap();
+    assert_eq!(partial_path(&path).map(|_| ()), Err(Status::InvalidParameter));
+}
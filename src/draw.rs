@@ -0,0 +1,312 @@
+use core::{cmp, fmt, str};
+
+use base::Status;
+use console::{Console, SimpleTextOutput};
+use util::char_to_ucs2;
+
+/// The characters used to draw frames and table borders.
+#[derive(Clone, Copy, Debug)]
+pub struct BoxChars {
+    pub horizontal: char,
+    pub vertical: char,
+    pub top_left: char,
+    pub top_right: char,
+    pub bottom_left: char,
+    pub bottom_right: char,
+    pub tee_down: char,
+    pub tee_up: char,
+    pub tee_right: char,
+    pub tee_left: char,
+    pub cross: char,
+}
+
+/// Single-line Unicode box drawing characters.
+pub const UNICODE_BOX_CHARS: BoxChars = BoxChars {
+    horizontal: '\u{2500}',
+    vertical: '\u{2502}',
+    top_left: '\u{250C}',
+    top_right: '\u{2510}',
+    bottom_left: '\u{2514}',
+    bottom_right: '\u{2518}',
+    tee_down: '\u{252C}',
+    tee_up: '\u{2534}',
+    tee_right: '\u{251C}',
+    tee_left: '\u{2524}',
+    cross: '\u{253C}',
+};
+
+/// ASCII replacements for consoles whose font has no box drawing glyphs.
+pub const ASCII_BOX_CHARS: BoxChars = BoxChars {
+    horizontal: '-',
+    vertical: '|',
+    top_left: '+',
+    top_right: '+',
+    bottom_left: '+',
+    bottom_right: '+',
+    tee_down: '+',
+    tee_up: '+',
+    tee_right: '+',
+    tee_left: '+',
+    cross: '+',
+};
+
+impl BoxChars {
+    /// The Unicode box characters if `console` can display them, otherwise the ASCII fallback.
+    /// Fonts without the glyphs make `test_string` return `Status::WarnUnknownGlyph`.
+    pub fn for_console(console: &Console) -> &'static BoxChars {
+        let c = UNICODE_BOX_CHARS;
+        let mut buf = [0u8; 11 * 3];
+        let mut len = 0;
+        for ch in &[c.horizontal, c.vertical, c.top_left, c.top_right, c.bottom_left, c.bottom_right,
+                    c.tee_down, c.tee_up, c.tee_right, c.tee_left, c.cross] {
+            len += ch.encode_utf8(&mut buf[len..]).len();
+        }
+        let all = unsafe { str::from_utf8_unchecked(&buf[..len]) };
+
+        match console.test_string(all) {
+            Status::Success => &UNICODE_BOX_CHARS,
+            _ => &ASCII_BOX_CHARS,
+        }
+    }
+}
+
+/// Write `c` to `out` `count` times.
+fn write_repeat<T: SimpleTextOutput + ?Sized>(out: &T, c: char, mut count: usize) -> Status {
+    let mut buf = [0u16; 64];
+
+    while count > 0 {
+        let n = cmp::min(count, buf.len() - 1);
+        for b in buf[..n].iter_mut() {
+            *b = char_to_ucs2(c);
+        }
+        buf[n] = 0;
+
+        let status = out.write_raw(buf.as_ptr());
+        if status != Status::Success {
+            return status;
+        }
+        count -= n;
+    }
+
+    Status::Success
+}
+
+fn write_char<T: SimpleTextOutput + ?Sized>(out: &T, c: char) -> Status {
+    write_repeat(out, c, 1)
+}
+
+/// Draw a frame with its top left corner at (`column`, `row`), `width` columns wide and
+/// `height` rows high, including the border. The inside of the frame is left untouched.
+pub fn draw_box(console: &Console, column: usize, row: usize, width: usize, height: usize,
+                chars: &BoxChars) -> Status {
+    if width < 2 || height < 2 {
+        return Status::InvalidParameter;
+    }
+
+    for r in 0..height {
+        let (left, fill, right) = if r == 0 {
+            (chars.top_left, Some(chars.horizontal), chars.top_right)
+        } else if r == height - 1 {
+            (chars.bottom_left, Some(chars.horizontal), chars.bottom_right)
+        } else {
+            (chars.vertical, None, chars.vertical)
+        };
+
+        let status = console.set_cursor_position(column, row + r);
+        if status != Status::Success {
+            return status;
+        }
+        let status = write_char(console, left);
+        if status != Status::Success {
+            return status;
+        }
+
+        let status = match fill {
+            Some(c) => write_repeat(console, c, width - 2),
+            None => console.set_cursor_position(column + width - 1, row + r),
+        };
+        if status != Status::Success {
+            return status;
+        }
+
+        let status = write_char(console, right);
+        if status != Status::Success {
+            return status;
+        }
+    }
+
+    Status::Success
+}
+
+/// Horizontal alignment of a table column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// A column of a `Table`.
+#[derive(Clone, Copy, Debug)]
+pub struct Column<'a> {
+    pub title: &'a str,
+    /// Width of the cell contents, in characters. Longer values are truncated.
+    pub width: usize,
+    pub align: Align,
+}
+
+impl<'a> Column<'a> {
+    pub fn new(title: &'a str, width: usize, align: Align) -> Column<'a> {
+        Column { title, width, align }
+    }
+}
+
+/// Longest cell value `Table::print_row` can format, in bytes.
+const CELL_MAX: usize = 128;
+
+/// A stack buffer to format a cell into before padding it.
+struct CellBuf {
+    buf: [u8; CELL_MAX],
+    len: usize,
+}
+
+impl fmt::Write for CellBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Cut at a character boundary rather than failing, so long values are truncated.
+        for c in s.chars() {
+            let n = c.len_utf8();
+            if self.len + n > CELL_MAX {
+                break;
+            }
+            c.encode_utf8(&mut self.buf[self.len..]);
+            self.len += n;
+        }
+        Ok(())
+    }
+}
+
+/// Prints rows of values aligned in bordered columns, e.g. for handle or memory map dumps.
+///
+/// ```rust,ignore
+/// let columns = [Column::new("Type", 20, Align::Left), Column::new("Pages", 8, Align::Right)];
+/// let table = Table::new(&columns, BoxChars::for_console(&console));
+/// table.print_header(&console);
+/// table.print_row(&console, &[&"LoaderCode", &12]);
+/// table.print_footer(&console);
+/// ```
+pub struct Table<'a> {
+    columns: &'a [Column<'a>],
+    chars: &'a BoxChars,
+}
+
+impl<'a> Table<'a> {
+    pub fn new(columns: &'a [Column<'a>], chars: &'a BoxChars) -> Table<'a> {
+        Table { columns, chars }
+    }
+
+    fn print_rule<T: SimpleTextOutput + ?Sized>(&self, out: &T, left: char, middle: char, right: char) -> Status {
+        for (i, column) in self.columns.iter().enumerate() {
+            let status = write_char(out, if i == 0 { left } else { middle });
+            if status != Status::Success {
+                return status;
+            }
+            let status = write_repeat(out, self.chars.horizontal, column.width + 2);
+            if status != Status::Success {
+                return status;
+            }
+        }
+
+        let status = write_char(out, right);
+        if status != Status::Success {
+            return status;
+        }
+        out.write("\r\n")
+    }
+
+    fn print_cell<T: SimpleTextOutput + ?Sized>(&self, out: &T, column: &Column, value: &str) -> Status {
+        let width = value.chars().count();
+        let (value, pad) = if width > column.width {
+            let end = value.char_indices().nth(column.width).map_or(value.len(), |(i, _)| i);
+            (&value[..end], 0)
+        } else {
+            (value, column.width - width)
+        };
+
+        let status = write_char(out, self.chars.vertical);
+        if status != Status::Success {
+            return status;
+        }
+        let status = match column.align {
+            Align::Left => write_char(out, ' '),
+            Align::Right => write_repeat(out, ' ', pad + 1),
+        };
+        if status != Status::Success {
+            return status;
+        }
+        let status = out.write(value);
+        if status != Status::Success {
+            return status;
+        }
+        match column.align {
+            Align::Left => write_repeat(out, ' ', pad + 1),
+            Align::Right => write_char(out, ' '),
+        }
+    }
+
+    /// Print the top border and the column titles.
+    pub fn print_header<T: SimpleTextOutput + ?Sized>(&self, out: &T) -> Status {
+        let status = self.print_rule(out, self.chars.top_left, self.chars.tee_down, self.chars.top_right);
+        if status != Status::Success {
+            return status;
+        }
+
+        for column in self.columns {
+            let status = self.print_cell(out, column, column.title);
+            if status != Status::Success {
+                return status;
+            }
+        }
+        let status = write_char(out, self.chars.vertical);
+        if status != Status::Success {
+            return status;
+        }
+        let status = out.write("\r\n");
+        if status != Status::Success {
+            return status;
+        }
+
+        self.print_separator(out)
+    }
+
+    /// Print a horizontal line between rows.
+    pub fn print_separator<T: SimpleTextOutput + ?Sized>(&self, out: &T) -> Status {
+        self.print_rule(out, self.chars.tee_right, self.chars.cross, self.chars.tee_left)
+    }
+
+    /// Print one row. `cells` holds one value per column; missing values are left blank and extra
+    /// ones are ignored.
+    pub fn print_row<T: SimpleTextOutput + ?Sized>(&self, out: &T, cells: &[&dyn fmt::Display]) -> Status {
+        for (i, column) in self.columns.iter().enumerate() {
+            let mut cell = CellBuf { buf: [0; CELL_MAX], len: 0 };
+            if let Some(value) = cells.get(i) {
+                let _ = fmt::write(&mut cell, format_args!("{}", value));
+            }
+            let value = unsafe { str::from_utf8_unchecked(&cell.buf[..cell.len]) };
+
+            let status = self.print_cell(out, column, value);
+            if status != Status::Success {
+                return status;
+            }
+        }
+
+        let status = write_char(out, self.chars.vertical);
+        if status != Status::Success {
+            return status;
+        }
+        out.write("\r\n")
+    }
+
+    /// Print the bottom border.
+    pub fn print_footer<T: SimpleTextOutput + ?Sized>(&self, out: &T) -> Status {
+        self.print_rule(out, self.chars.bottom_left, self.chars.tee_up, self.chars.bottom_right)
+    }
+}
@@ -0,0 +1,176 @@
+//! Access to the embedded controller through the ACPI EC interface on I/O ports 0x62 and 0x66,
+//! for hardware diagnostics reading battery, thermal and fan data before an OS is running.
+//! Built with the `ec` feature on x86.
+//!
+//! What lives at which EC address is up to the board vendor; the ACPI tables of the machine,
+//! or its coreboot or vendor sources, say where the battery and thermal registers are.
+//!
+//! ```rust,ignore
+//! let ec = EmbeddedController::new();
+//! let temperature = ec.read(0x78)?;
+//! let remaining_capacity = ec.read_u16(0xA2)?;
+//! ```
+
+use base::Status;
+use portio::{inb, outb};
+
+/// The EC data port of the ACPI specification's default interface.
+pub const EC_DATA_PORT: u16 = 0x62;
+
+/// The EC command and status port of the ACPI specification's default interface.
+pub const EC_COMMAND_PORT: u16 = 0x66;
+
+/// How long to wait for the EC at each step of a command, by default.
+pub const EC_DEFAULT_TIMEOUT_US: usize = 100_000;
+
+/// Bits of the EC status register.
+pub const EC_STATUS_OBF: u8 = 0x01;
+pub const EC_STATUS_IBF: u8 = 0x02;
+pub const EC_STATUS_CMD: u8 = 0x08;
+pub const EC_STATUS_BURST: u8 = 0x10;
+pub const EC_STATUS_SCI_EVT: u8 = 0x20;
+pub const EC_STATUS_SMI_EVT: u8 = 0x40;
+
+const EC_CMD_READ: u8 = 0x80;
+const EC_CMD_WRITE: u8 = 0x81;
+const EC_CMD_QUERY: u8 = 0x84;
+
+/// Microseconds between polls of the status register.
+const POLL_INTERVAL_US: usize = 10;
+
+/// Poll `status` until `mask` is set, if `set`, or clear, stalling between polls with `stall`.
+/// Fails with `Status::Timeout` after `timeout_us` microseconds.
+fn wait<F, S>(mut status: F, mut stall: S, mask: u8, set: bool, timeout_us: usize) -> Result<(), Status>
+    where F: FnMut() -> u8, S: FnMut(usize)
+{
+    let mut waited = 0;
+    loop {
+        if (status() & mask != 0) == set {
+            return Ok(());
+        }
+        if waited >= timeout_us {
+            return Err(Status::Timeout);
+        }
+        stall(POLL_INTERVAL_US);
+        waited += POLL_INTERVAL_US;
+    }
+}
+
+/// An embedded controller speaking the ACPI EC protocol. Each operation waits for the EC
+/// with a timeout, so a missing or wedged EC gives `Status::Timeout` rather than a hang.
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedController {
+    data_port: u16,
+    command_port: u16,
+    timeout_us: usize,
+}
+
+impl Default for EmbeddedController {
+    fn default() -> EmbeddedController {
+        EmbeddedController::new()
+    }
+}
+
+impl EmbeddedController {
+    /// The EC at the standard ports 0x62 and 0x66.
+    pub fn new() -> EmbeddedController {
+        EmbeddedController::with_ports(EC_DATA_PORT, EC_COMMAND_PORT)
+    }
+
+    /// An EC at other ports, as the ACPI ECDT or the EC's `_CRS` give for some boards.
+    pub fn with_ports(data_port: u16, command_port: u16) -> EmbeddedController {
+        EmbeddedController { data_port, command_port, timeout_us: EC_DEFAULT_TIMEOUT_US }
+    }
+
+    /// Wait at most `timeout_us` microseconds at each step of a command.
+    pub fn set_timeout(&mut self, timeout_us: usize) {
+        self.timeout_us = timeout_us;
+    }
+
+    /// The status register, made of the `EC_STATUS_` bits.
+    pub fn status(&self) -> u8 {
+        // Reading the status register has no side effects.
+        unsafe { inb(self.command_port) }
+    }
+
+    fn wait(&self, mask: u8, set: bool) -> Result<(), Status> {
+        let bs = ::get_system_table().boot_services();
+        wait(|| self.status(), |us| bs.stall(us), mask, set, self.timeout_us)
+    }
+
+    fn command(&self, command: u8) -> Result<(), Status> {
+        self.wait(EC_STATUS_IBF, false)?;
+        // The EC protocol: commands go to the command port once the input buffer is empty.
+        unsafe { outb(self.command_port, command) };
+        Ok(())
+    }
+
+    fn write_data(&self, value: u8) -> Result<(), Status> {
+        self.wait(EC_STATUS_IBF, false)?;
+        unsafe { outb(self.data_port, value) };
+        Ok(())
+    }
+
+    fn read_data(&self) -> Result<u8, Status> {
+        self.wait(EC_STATUS_OBF, true)?;
+        Ok(unsafe { inb(self.data_port) })
+    }
+
+    /// Read the byte at `address` of the EC's register space.
+    pub fn read(&self, address: u8) -> Result<u8, Status> {
+        self.command(EC_CMD_READ)?;
+        self.write_data(address)?;
+        self.read_data()
+    }
+
+    /// Read a little-endian 16-bit value at `address`, as battery registers usually are.
+    pub fn read_u16(&self, address: u8) -> Result<u16, Status> {
+        let low = self.read(address)?;
+        let high = self.read(address.wrapping_add(1))?;
+        Ok(u16::from_le_bytes([low, high]))
+    }
+
+    /// Fill `buf` from consecutive addresses starting at `address`.
+    pub fn read_bytes(&self, address: u8, buf: &mut [u8]) -> Result<(), Status> {
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = self.read(address.wrapping_add(i as u8))?;
+        }
+        Ok(())
+    }
+
+    /// Write `value` at `address`. EC registers can control fans, charging and power, so only
+    /// write ones the board's documentation describes.
+    pub fn write(&self, address: u8, value: u8) -> Result<(), Status> {
+        self.command(EC_CMD_WRITE)?;
+        self.write_data(address)?;
+        self.write_data(value)?;
+        // Let the EC take the value before the next command.
+        self.wait(EC_STATUS_IBF, false)
+    }
+
+    /// Take the pending event the EC has signalled with `EC_STATUS_SCI_EVT`, returning its
+    /// query number, or `None` if there is none.
+    pub fn query(&self) -> Result<Option<u8>, Status> {
+        if self.status() & EC_STATUS_SCI_EVT == 0 {
+            return Ok(None);
+        }
+        self.command(EC_CMD_QUERY)?;
+        match self.read_data()? {
+            0 => Ok(None),
+            event => Ok(Some(event)),
+        }
+    }
+}
+
+#[test]
+fn ec_wait_timeout() {
+    let mut polls = 0;
+    let mut stalled = 0;
+    let ready = wait(|| { polls += 1; if polls < 5 { EC_STATUS_IBF } else { 0 } }, |us| stalled += us, EC_STATUS_IBF, false, 1000);
+    assert_eq!(ready, Ok(()));
+    assert_eq!(stalled, 4 * POLL_INTERVAL_US);
+
+    let mut stalled = 0;
+    assert_eq!(wait(|| 0, |us| stalled += us, EC_STATUS_OBF, true, 100), Err(Status::Timeout));
+    assert_eq!(stalled, 100);
+}
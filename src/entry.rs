@@ -0,0 +1,157 @@
+use base::Status;
+use error::EfiError;
+
+/// Types an application entry function can return, mapped to the EFI_STATUS handed back to the
+/// firmware. Similar to `std::process::Termination`.
+pub trait Termination {
+    fn report(self) -> Status;
+}
+
+impl Termination for () {
+    fn report(self) -> Status {
+        Status::Success
+    }
+}
+
+impl Termination for Status {
+    fn report(self) -> Status {
+        self
+    }
+}
+
+impl<T: Termination> Termination for Result<T, Status> {
+    fn report(self) -> Status {
+        match self {
+            Ok(value) => value.report(),
+            Err(status) => status,
+        }
+    }
+}
+
+impl<T: Termination> Termination for Result<T, EfiError> {
+    fn report(self) -> Status {
+        match self {
+            Ok(value) => value.report(),
+            Err(e) => e.status,
+        }
+    }
+}
+
+/// The value to return from `efi_entry` for `result`. EFI_STATUS is an unsigned native-width
+/// integer with the error flag in the top bit; this keeps that bit pattern in the `isize` the
+/// entry point is declared with.
+pub fn exit_code<T: Termination>(result: T) -> isize {
+    isize::from(result.report())
+}
+
+/// Work done when `$main` returns in `efi_main!`: write the boot transcript, if one was started,
+/// and with the `heap-stats` feature, print the allocation report to stderr.
+#[doc(hidden)]
+pub fn at_exit() {
+    let _ = ::transcript::flush_transcript();
+
+    #[cfg(feature = "heap-stats")]
+    {
+        let _ = ::heapstats::heap_stats().report(&mut ::stdio::stderr());
+    }
+}
+
+/// Define the `efi_entry` function of an application: initialise the crate with
+/// `set_system_table` and `set_current_image`, call `$main`, and return its result to the
+/// firmware. `$main` takes no arguments and returns `()`, `Status`, `Result<T, Status>` or
+/// `Result<T, EfiError>`, with errors becoming the exit status.
+///
+/// ```rust,ignore
+/// #[macro_use] extern crate uefi;
+///
+/// fn main() -> Result<(), uefi::Status> {
+///     let rs = uefi::get_system_table().runtime_services();
+///     let time = rs.get_time()?;
+///     // ...
+///     Ok(())
+/// }
+///
+/// efi_main!(main);
+/// ```
+#[macro_export]
+macro_rules! efi_main {
+    ($main:path) => {
+        #[no_mangle]
+        pub extern "win64" fn efi_entry(image_handle: $crate::Handle,
+                                        system_table: *const $crate::SystemTable)
+                                        -> isize {
+            $crate::set_system_table(system_table);
+            if let Err(status) = $crate::protocol::set_current_image(image_handle) {
+                return $crate::exit_code(status);
+            }
+
+            let code = $crate::exit_code($main());
+            $crate::at_exit();
+            code
+        }
+    };
+}
+
+/// Define the `efi_entry` function of a driver following the UEFI driver model: initialise the
+/// crate, run `$init` if given, and install the `DriverBindingProtocol` and
+/// `ComponentName2Protocol` of `$driver`, a type implementing `protocol::Driver`. The entry point
+/// then returns `Success`, which leaves the image resident for the firmware to call the driver
+/// through its protocols. `$init` takes no arguments and returns `Result<(), Status>`; an error
+/// from it is returned without installing the driver, and the firmware unloads the image.
+///
+/// The image must be linked as a boot service driver, with `/subsystem:efi_boot_service_driver`,
+/// and loaded with the shell's `load` command, a `Driver####` variable or from an option ROM.
+///
+/// ```rust,ignore
+/// #[macro_use] extern crate uefi;
+///
+/// struct RamDisk;
+///
+/// impl uefi::protocol::Driver for RamDisk {
+///     const NAME: &'static [u16] = ucs2!("RAM Disk Driver");
+///     // supported, start and stop ...
+/// }
+///
+/// driver_entry!(RamDisk);
+/// ```
+#[macro_export]
+macro_rules! driver_entry {
+    ($driver:ty) => {
+        driver_entry!($driver, $crate::driver_init);
+    };
+    ($driver:ty, $init:path) => {
+        static DRIVER_BINDING: $crate::protocol::DriverBindingProtocol = $crate::protocol::DriverBindingProtocol::new::<$driver>();
+        static COMPONENT_NAME: $crate::protocol::ComponentName2Protocol = $crate::protocol::ComponentName2Protocol::new::<$driver>();
+
+        #[no_mangle]
+        pub extern "win64" fn efi_entry(image_handle: $crate::Handle,
+                                        system_table: *const $crate::SystemTable)
+                                        -> isize {
+            $crate::set_system_table(system_table);
+            if let Err(status) = $crate::protocol::set_current_image(image_handle) {
+                return $crate::exit_code(status);
+            }
+
+            let result: Result<(), $crate::Status> = $init();
+            if let Err(status) = result {
+                return $crate::exit_code(status);
+            }
+            $crate::exit_code($crate::protocol::install_driver(image_handle, &DRIVER_BINDING, &COMPONENT_NAME))
+        }
+    };
+}
+
+/// The initialisation `driver_entry!` does for drivers without their own: none.
+#[doc(hidden)]
+pub fn driver_init() -> Result<(), Status> {
+    Ok(())
+}
+
+#[test]
+fn exit_codes() {
+    assert_eq!(exit_code(()), 0);
+    assert_eq!(exit_code(Ok::<(), Status>(())), 0);
+    assert_eq!(exit_code(Err::<(), Status>(Status::NotFound)), Status::NotFound as usize as isize);
+    assert!(exit_code(Status::NotFound) < 0);
+    assert_eq!(exit_code(Status::WarnStaleData), 5);
+}
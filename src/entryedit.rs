@@ -0,0 +1,350 @@
+//! An "edit entry" screen like GRUB's `e`: the lines of a boot entry, most usefully the kernel
+//! command line, can be changed for a single boot before it is started.
+
+use core::{char, cmp, fmt};
+
+use base::Status;
+use bls::{BlsEntry, MAX_BLS_INITRDS};
+use console::{Console, InputKey, SimpleTextInput, SimpleTextOutput};
+use util::char_to_ucs2;
+
+/// Longest line that can be edited, in UCS-2 units.
+pub const MAX_EDIT_LINE_LEN: usize = 1024;
+
+/// Most lines an `EntryEditor` holds: the kernel or program, the initrds, a devicetree and the
+/// options.
+pub const MAX_EDIT_LINES: usize = MAX_BLS_INITRDS + 3;
+
+const SCAN_UP: u16 = 0x01;
+const SCAN_DOWN: u16 = 0x02;
+const SCAN_RIGHT: u16 = 0x03;
+const SCAN_LEFT: u16 = 0x04;
+const SCAN_HOME: u16 = 0x05;
+const SCAN_END: u16 = 0x06;
+const SCAN_DELETE: u16 = 0x08;
+const SCAN_F10: u16 = 0x14;
+const SCAN_ESC: u16 = 0x17;
+
+const CHAR_BACKSPACE: u16 = 0x08;
+const CHAR_CARRIAGE_RETURN: u16 = 0x0D;
+
+/// Columns before the text of each line: the scroll marker and the key.
+const LABEL_WIDTH: usize = 12;
+
+/// Widest screen row drawn; wider modes leave the rest blank.
+const MAX_ROW_WIDTH: usize = 256;
+
+const HELP_LINE: &str = "Enter/F10: boot, Esc: discard changes, Up/Down: select line";
+
+/// One line of editable text, scrolled horizontally to keep the cursor in view when it is wider
+/// than the screen.
+pub struct EditLine {
+    text: [u16; MAX_EDIT_LINE_LEN],
+    len: usize,
+    cursor: usize,
+    scroll: usize,
+}
+
+impl EditLine {
+    const EMPTY: EditLine = EditLine { text: [0; MAX_EDIT_LINE_LEN], len: 0, cursor: 0, scroll: 0 };
+
+    /// A line holding `s`, with the cursor at its end. Fails with `Status::BufferTooSmall` if `s`
+    /// is longer than `MAX_EDIT_LINE_LEN`.
+    pub fn new(s: &str) -> Result<EditLine, Status> {
+        let mut line = EditLine::EMPTY;
+        fmt::Write::write_str(&mut line, s).map_err(|_| Status::BufferTooSmall)?;
+        Ok(line)
+    }
+
+    /// The text, as UCS-2 without a terminator, ready to be passed as load options.
+    pub fn as_ucs2(&self) -> &[u16] {
+        &self.text[..self.len]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Insert `c` at the cursor. Returns false, leaving the line alone, if it is full.
+    pub fn insert(&mut self, c: u16) -> bool {
+        if self.len == MAX_EDIT_LINE_LEN {
+            return false;
+        }
+        self.text.copy_within(self.cursor..self.len, self.cursor + 1);
+        self.text[self.cursor] = c;
+        self.len += 1;
+        self.cursor += 1;
+        true
+    }
+
+    /// Remove the character before the cursor.
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.delete();
+        }
+    }
+
+    /// Remove the character under the cursor.
+    pub fn delete(&mut self) {
+        if self.cursor < self.len {
+            self.text.copy_within(self.cursor + 1..self.len, self.cursor);
+            self.len -= 1;
+        }
+    }
+
+    /// Apply an editing key: the arrows, Home and End move the cursor, Backspace and Delete
+    /// remove text and printable characters are inserted. Returns false for any other key.
+    pub fn handle_key(&mut self, key: InputKey) -> bool {
+        match (key.scan_code, key.unicode_char) {
+            (SCAN_LEFT, _) => self.cursor = self.cursor.saturating_sub(1),
+            (SCAN_RIGHT, _) => self.cursor = cmp::min(self.cursor + 1, self.len),
+            (SCAN_HOME, _) => self.cursor = 0,
+            (SCAN_END, _) => self.cursor = self.len,
+            (SCAN_DELETE, _) => self.delete(),
+            (_, CHAR_BACKSPACE) => self.backspace(),
+            (_, c) if c >= 0x20 && c != 0x7F => {
+                self.insert(c);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Scroll so the cursor is within `width` columns, and return the offset of the first
+    /// column shown and the text shown in them.
+    pub fn view(&mut self, width: usize) -> (usize, &[u16]) {
+        let width = cmp::max(width, 1);
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + width {
+            self.scroll = self.cursor + 1 - width;
+        }
+        // Don't leave the screen half empty after text at the end is deleted.
+        self.scroll = cmp::min(self.scroll, (self.len + 1).saturating_sub(width));
+
+        let end = cmp::min(self.scroll + width, self.len);
+        (self.scroll, &self.text[self.scroll..end])
+    }
+}
+
+impl fmt::Write for EditLine {
+    /// Append `s`, failing if it does not fit.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.cursor = self.len;
+            if !self.insert(char_to_ucs2(c)) {
+                return Err(fmt::Error);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EditLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in char::decode_utf16(self.as_ucs2().iter().cloned()) {
+            fmt::Write::write_char(f, c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
+/// What the user chose on the `EntryEditor` screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorAction {
+    /// Boot the entry as edited.
+    Boot,
+    /// Return to the menu, discarding the changes.
+    Cancel,
+}
+
+/// The lines of a boot entry as a full-screen editor, one per row, each prefixed by its key.
+///
+/// ```rust,ignore
+/// let mut editor = EntryEditor::from_bls(&entry.entry)?;
+/// if editor.run(&console, entry.entry.display_title())? == EditorAction::Boot {
+///     let cmdline = editor.line("options").map(|l| l.as_ucs2()).unwrap_or(&[]);
+///     boot_linux(editor.line("linux"), cmdline)?;
+/// }
+/// ```
+pub struct EntryEditor {
+    keys: [&'static str; MAX_EDIT_LINES],
+    lines: [EditLine; MAX_EDIT_LINES],
+    count: usize,
+    current: usize,
+}
+
+impl EntryEditor {
+    pub fn new() -> EntryEditor {
+        EntryEditor { keys: [""; MAX_EDIT_LINES], lines: [EditLine::EMPTY; MAX_EDIT_LINES], count: 0, current: 0 }
+    }
+
+    /// Add a line. Fails with `Status::OutOfResources` if there are already `MAX_EDIT_LINES`.
+    pub fn push(&mut self, key: &'static str, line: EditLine) -> Result<(), Status> {
+        if self.count == MAX_EDIT_LINES {
+            return Err(Status::OutOfResources);
+        }
+        self.keys[self.count] = key;
+        self.lines[self.count] = line;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// An editor for `entry`: its `linux` or `efi` line, each `initrd`, its `devicetree` and
+    /// its `options` as a single command line. The options line is there even if the entry has
+    /// none, so some can be added.
+    pub fn from_bls(entry: &BlsEntry) -> Result<EntryEditor, Status> {
+        let mut editor = EntryEditor::new();
+        if let Some(linux) = entry.linux {
+            editor.push("linux", EditLine::new(linux)?)?;
+        }
+        if let Some(efi) = entry.efi {
+            editor.push("efi", EditLine::new(efi)?)?;
+        }
+        for initrd in entry.initrds() {
+            editor.push("initrd", EditLine::new(initrd)?)?;
+        }
+        if let Some(devicetree) = entry.devicetree {
+            editor.push("devicetree", EditLine::new(devicetree)?)?;
+        }
+
+        let mut options = EditLine::EMPTY;
+        fmt::write(&mut options, format_args!("{}", entry.cmdline())).map_err(|_| Status::BufferTooSmall)?;
+        editor.push("options", options)?;
+        // Start on the command line, which is what people usually came to change.
+        editor.current = editor.count - 1;
+        Ok(editor)
+    }
+
+    /// The keys of the lines, in order.
+    pub fn keys(&self) -> &[&'static str] {
+        &self.keys[..self.count]
+    }
+
+    /// The lines, in order.
+    pub fn lines(&self) -> &[EditLine] {
+        &self.lines[..self.count]
+    }
+
+    /// The first line with `key`.
+    pub fn line(&self, key: &str) -> Option<&EditLine> {
+        self.keys().iter().position(|&k| k == key).map(|i| &self.lines[i])
+    }
+
+    /// Show the editor full-screen, under `title`, until the user boots with Enter or F10 or
+    /// gives up with Esc. Up and Down move between lines. The console state is restored
+    /// afterwards.
+    pub fn run(&mut self, console: &Console, title: &str) -> Result<EditorAction, Status> {
+        let state = console.save_state();
+        console.clear_screen();
+        let result = self.edit(console, title);
+        console.clear_screen();
+        console.restore_state(&state);
+        result
+    }
+
+    fn edit(&mut self, console: &Console, title: &str) -> Result<EditorAction, Status> {
+        let (columns, rows) = console.query_mode(console.mode().mode as usize)?;
+        // Leave the last column empty so the firmware does not wrap for us.
+        let columns = cmp::min(cmp::max(columns, LABEL_WIDTH + 4) - 1, MAX_ROW_WIDTH);
+        let width = columns - LABEL_WIDTH - 1;
+
+        console.set_cursor_position(0, 0);
+        console.write(title);
+        console.set_cursor_position(0, rows.saturating_sub(1));
+        console.write(HELP_LINE);
+        let _ = console.enable_cursor(true);
+
+        // Rows 0 and 1 hold the title and a gap, the last the help line.
+        let shown = cmp::min(self.count, rows.saturating_sub(4));
+        loop {
+            let mut cursor = (0, 0);
+            for i in 0..shown {
+                let (scroll, text) = self.lines[i].view(width);
+                let mut row = [b' ' as u16; MAX_ROW_WIDTH + 1];
+                if scroll > 0 {
+                    row[0] = b'<' as u16;
+                }
+                for (j, b) in self.keys[i].bytes().take(LABEL_WIDTH - 2).enumerate() {
+                    row[1 + j] = b as u16;
+                }
+                row[LABEL_WIDTH..LABEL_WIDTH + text.len()].copy_from_slice(text);
+                if scroll + text.len() < self.lines[i].len() {
+                    row[columns - 1] = b'>' as u16;
+                }
+                row[columns] = 0;
+
+                console.set_cursor_position(0, 2 + i);
+                let status = console.write_raw(row.as_ptr());
+                if status != Status::Success {
+                    return Err(status);
+                }
+                if i == self.current {
+                    cursor = (LABEL_WIDTH + self.lines[i].cursor() - scroll, 2 + i);
+                }
+            }
+            console.set_cursor_position(cursor.0, cursor.1);
+
+            let key = console.read_key()?;
+            match (key.scan_code, key.unicode_char) {
+                (SCAN_ESC, _) => return Ok(EditorAction::Cancel),
+                (SCAN_F10, _) | (_, CHAR_CARRIAGE_RETURN) => return Ok(EditorAction::Boot),
+                (SCAN_UP, _) => self.current = self.current.saturating_sub(1),
+                (SCAN_DOWN, _) => self.current = cmp::min(self.current + 1, shown.saturating_sub(1)),
+                _ => {
+                    if self.current < shown {
+                        self.lines[self.current].handle_key(key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for EntryEditor {
+    fn default() -> EntryEditor {
+        EntryEditor::new()
+    }
+}
+
+#[test]
+fn entry_editor_lines() {
+    fn text(line: &EditLine) -> [u8; 64] {
+        let mut out = [0u8; 64];
+        for (o, &c) in out.iter_mut().zip(line.as_ucs2()) {
+            *o = c as u8;
+        }
+        out
+    }
+    let key = |scan_code, unicode_char| InputKey { scan_code, unicode_char };
+
+    let entry = BlsEntry::parse("linux /vmlinuz\ninitrd /initrd.img\noptions root=/dev/sda1 ro\noptions quiet\n");
+    let mut editor = EntryEditor::from_bls(&entry).unwrap();
+    assert_eq!(editor.keys(), &["linux", "initrd", "options"]);
+    assert_eq!(&text(editor.line("options").unwrap())[..22], b"root=/dev/sda1 ro quie");
+
+    let line = &mut editor.lines[2];
+    line.handle_key(key(SCAN_HOME, 0));
+    for &c in b"init=/bin/sh " {
+        line.handle_key(key(0, c as u16));
+    }
+    line.handle_key(key(SCAN_END, 0));
+    line.handle_key(key(0, CHAR_BACKSPACE));
+    assert_eq!(&text(line)[..line.len()], b"init=/bin/sh root=/dev/sda1 ro quie");
+
+    // The view follows the cursor in both directions.
+    let (scroll, shown) = line.view(10);
+    assert_eq!((scroll, shown.len()), (line.len() - 9, 9));
+    line.handle_key(key(SCAN_HOME, 0));
+    assert_eq!(line.view(10).0, 0);
+    assert!(!line.handle_key(key(SCAN_F10, 0)));
+}
@@ -0,0 +1,113 @@
+use core::fmt;
+
+use base::Status;
+
+/// Most bytes of context an `EfiError` keeps; longer context is truncated. This is kept small so
+/// that `Result<T, EfiError>` stays cheap to return.
+pub const ERROR_CONTEXT_SIZE: usize = 80;
+
+/// A failed operation: the status, the firmware call or step that failed, and optionally what it
+/// was applied to, such as a file path. Displays as
+/// `LoadImage(\EFI\foo.efi) failed: not found`.
+///
+/// Higher-level helpers return this so that failures can be reported to the user as they are;
+/// thin protocol bindings return a bare `Status`. Convert with `ResultExt::context`.
+#[derive(Clone, Copy)]
+pub struct EfiError {
+    pub status: Status,
+    pub operation: &'static str,
+    context: [u8; ERROR_CONTEXT_SIZE],
+    context_len: Option<u8>,
+}
+
+impl EfiError {
+    pub fn new(status: Status, operation: &'static str) -> EfiError {
+        EfiError {
+            status,
+            operation,
+            context: [0; ERROR_CONTEXT_SIZE],
+            context_len: None,
+        }
+    }
+
+    /// Set the context, truncated to `ERROR_CONTEXT_SIZE` bytes.
+    pub fn with_context(self, context: &str) -> EfiError {
+        self.with_context_fmt(format_args!("{}", context))
+    }
+
+    /// Set the context from format arguments, truncated to `ERROR_CONTEXT_SIZE` bytes.
+    pub fn with_context_fmt(mut self, args: fmt::Arguments) -> EfiError {
+        let mut writer = ContextWriter { buf: &mut self.context, len: 0 };
+        // Truncation is not an error worth reporting.
+        let _ = fmt::write(&mut writer, args);
+        self.context_len = Some(writer.len as u8);
+        self
+    }
+
+    pub fn context(&self) -> Option<&str> {
+        // Only whole characters are ever written to the buffer.
+        self.context_len.map(|len| unsafe { ::core::str::from_utf8_unchecked(&self.context[..len as usize]) })
+    }
+}
+
+struct ContextWriter<'a> {
+    buf: &'a mut [u8; ERROR_CONTEXT_SIZE],
+    len: usize,
+}
+
+impl<'a> fmt::Write for ContextWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.len + c.len_utf8() > ERROR_CONTEXT_SIZE {
+                return Err(fmt::Error);
+            }
+            self.len += c.encode_utf8(&mut self.buf[self.len..]).len();
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EfiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.context() {
+            Some(context) => write!(f, "{}({}) failed: {}", self.operation, context, self.status),
+            None => write!(f, "{} failed: {}", self.operation, self.status),
+        }
+    }
+}
+
+impl fmt::Debug for EfiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EfiError")
+            .field("status", &self.status)
+            .field("operation", &self.operation)
+            .field("context", &self.context())
+            .finish()
+    }
+}
+
+impl From<EfiError> for Status {
+    fn from(e: EfiError) -> Status {
+        e.status
+    }
+}
+
+/// Attach operation info to the `Status` of a failed binding call.
+///
+/// ```rust,ignore
+/// let image = bs.load_image(false, parent, path.as_ptr()).context_with("LoadImage", path_str)?;
+/// ```
+pub trait ResultExt<T> {
+    fn context(self, operation: &'static str) -> Result<T, EfiError>;
+    fn context_with(self, operation: &'static str, context: &str) -> Result<T, EfiError>;
+}
+
+impl<T> ResultExt<T> for Result<T, Status> {
+    fn context(self, operation: &'static str) -> Result<T, EfiError> {
+        self.map_err(|status| EfiError::new(status, operation))
+    }
+
+    fn context_with(self, operation: &'static str, context: &str) -> Result<T, EfiError> {
+        self.map_err(|status| EfiError::new(status, operation).with_context(context))
+    }
+}
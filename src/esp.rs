@@ -0,0 +1,43 @@
+//! Finding the EFI System Partition, where loaders, their configuration and firmware updates
+//! live, which is where most bootloaders and updaters start.
+
+use base::{Handle, Handles, Status};
+use protocol::{get_current_image, get_current_image_handle, PartitionInfoProtocol, SimpleFileSystemProtocol};
+
+/// Whether the volume on `handle` is an EFI System Partition, as the partition driver reports
+/// with the partition info protocol.
+pub fn is_esp(handle: Handle) -> bool {
+    ::get_system_table().boot_services()
+        .handle_protocol::<PartitionInfoProtocol>(handle)
+        .map(|info| info.is_esp())
+        .unwrap_or(false)
+}
+
+/// Find the file system handles of the EFI System Partitions, by their partition type. The one
+/// the current image was loaded from comes first if it is one, followed by the others in the
+/// order the firmware lists them. Fails with `Status::NotFound` if there are none.
+///
+/// Partitions are recognized from the partition info protocol of UEFI 2.7, so none are found
+/// on older firmware.
+///
+/// ```rust,ignore
+/// let esps = uefi::find_esp()?;
+/// let fs: &SimpleFileSystemProtocol = bs.handle_protocol(esps.as_slice()[0])?;
+/// let root = fs.open_volume()?;
+/// ```
+pub fn find_esp() -> Result<Handles, Status> {
+    let mut handles = ::get_system_table().boot_services().find_handles::<SimpleFileSystemProtocol>()?;
+    handles.retain(is_esp);
+    if handles.is_empty() {
+        return Err(Status::NotFound);
+    }
+
+    // The current image is only known once `set_current_image` has been called.
+    if !get_current_image_handle().is_null() {
+        let device = get_current_image().device_handle;
+        if let Some(i) = handles.as_slice().iter().position(|&handle| handle == device) {
+            handles.as_mut_slice()[..=i].rotate_right(1);
+        }
+    }
+    Ok(handles)
+}
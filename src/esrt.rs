@@ -0,0 +1,48 @@
+use core::slice;
+
+use guid::Guid;
+
+/// GUID of the EFI System Resource Table in the system configuration table
+pub static EFI_SYSTEM_RESOURCE_TABLE_GUID: Guid = Guid(0xB122A263, 0x3661, 0x4F68, [0x99, 0x29, 0x78, 0xF8, 0xB0, 0xD6, 0x21, 0x80]);
+
+/// Type for EFI_SYSTEM_RESOURCE_TABLE, without the trailing entries.
+#[derive(Debug)]
+#[repr(C)]
+pub struct SystemResourceTable {
+    pub fw_resource_count: u32,
+    pub fw_resource_count_max: u32,
+    pub fw_resource_version: u64,
+}
+
+/// Type for EFI_SYSTEM_RESOURCE_ENTRY, describing one updatable firmware component.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct SystemResourceEntry {
+    pub fw_class: Guid,
+    pub fw_type: u32,
+    pub fw_version: u32,
+    pub lowest_supported_fw_version: u32,
+    pub capsule_flags: u32,
+    pub last_attempt_version: u32,
+    pub last_attempt_status: u32,
+}
+
+/// Values of `SystemResourceEntry::fw_type`.
+pub const ESRT_FW_TYPE_UNKNOWN: u32 = 0;
+pub const ESRT_FW_TYPE_SYSTEM_FIRMWARE: u32 = 1;
+pub const ESRT_FW_TYPE_DEVICE_FIRMWARE: u32 = 2;
+pub const ESRT_FW_TYPE_UEFI_DRIVER: u32 = 3;
+
+/// The firmware components listed in the ESRT, or an empty slice if the firmware does not
+/// publish one.
+pub fn esrt_entries() -> &'static [SystemResourceEntry] {
+    let table = match ::get_system_table().configuration_table(&EFI_SYSTEM_RESOURCE_TABLE_GUID) {
+        Some(table) if !table.is_null() => table as *const SystemResourceTable,
+        _ => return &[],
+    };
+
+    unsafe {
+        let entries = table.offset(1) as *const SystemResourceEntry;
+        slice::from_raw_parts(entries, (*table).fw_resource_count as usize)
+    }
+}
@@ -1,14 +1,83 @@
 use void::CVoid;
-use base::Event;
-
-#[repr(u32)]
-pub enum EventType {
-    Timer = 0x80000000,
-    Runtime = 0x40000000,
-    NotifyWait = 0x00000100,
-    NotifySignal = 0x00000200,
-    SignalExitBootServices = 0x00000201,
-    SignalVirtualAddressChange = 0x60000202
+use base::{Event, Status};
+use task::TPL;
+
+// bitflags 0.9 expands to the deprecated `try!`, so the flags get a module of their own.
+#[allow(deprecated)]
+mod event_type {
+    bitflags! {
+        /// Type for the event type of `BootServices::create_event`: the flags below, or one of the
+        /// named combinations.
+        pub struct EventType: u32 {
+            const EVT_TIMER = 0x80000000;
+            const EVT_RUNTIME = 0x40000000;
+            const EVT_NOTIFY_WAIT = 0x00000100;
+            const EVT_NOTIFY_SIGNAL = 0x00000200;
+            const EVT_SIGNAL_EXIT_BOOT_SERVICES = 0x00000201;
+            const EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE = 0x60000202;
+
+            /// A timer calling its notify function when it expires.
+            const EVT_TIMER_NOTIFY_SIGNAL = EVT_TIMER.bits | EVT_NOTIFY_SIGNAL.bits;
+            /// A timer calling its notify function while it is waited for or checked and not yet
+            /// signalled.
+            const EVT_TIMER_NOTIFY_WAIT = EVT_TIMER.bits | EVT_NOTIFY_WAIT.bits;
+        }
+    }
+}
+pub use self::event_type::*;
+
+/// The bits that make `EVT_SIGNAL_EXIT_BOOT_SERVICES` and `EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE`
+/// differ from `EVT_NOTIFY_SIGNAL` and `EVT_RUNTIME`.
+const EVT_SIGNAL_GROUP_BITS: u32 = 0x20000003;
+
+impl EventType {
+    /// Check the type is one the firmware accepts, for an event created with `notify_tpl`, and
+    /// a notify function if `notify` is set. Firmware is not consistent in rejecting bad
+    /// combinations, so `create_event` checks with this before asking it.
+    pub fn validate(&self, notify_tpl: TPL, notify: bool) -> Result<(), Status> {
+        let waits = self.contains(EVT_NOTIFY_WAIT);
+        let signals = self.contains(EVT_NOTIFY_SIGNAL);
+
+        // At most one notification type, and only with a function to notify.
+        if (waits && signals) || (waits || signals) != notify {
+            return Err(Status::InvalidParameter);
+        }
+        // Notify functions run at a raised TPL, below the one with interrupts disabled.
+        if notify && (notify_tpl == TPL::Application || notify_tpl == TPL::HighLevel) {
+            return Err(Status::InvalidParameter);
+        }
+
+        // The exit boot services and virtual address change events are notify-signal events
+        // of their own, which can't be timers or combined with each other.
+        let group = self.bits() & EVT_SIGNAL_GROUP_BITS;
+        if group != 0 {
+            let exit_boot_services = EVT_SIGNAL_EXIT_BOOT_SERVICES.bits() & EVT_SIGNAL_GROUP_BITS;
+            let virtual_address_change = EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE.bits() & EVT_SIGNAL_GROUP_BITS;
+            if (group != exit_boot_services && group != virtual_address_change) || self.contains(EVT_TIMER) {
+                return Err(Status::InvalidParameter);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The names the event types had when `EventType` was an enum, for code written against it.
+/// They can still be passed and matched on; where a variant was cast with `as u32`, use
+/// `bits()`.
+#[allow(non_upper_case_globals)]
+impl EventType {
+    #[deprecated(note = "use `EVT_TIMER`")]
+    pub const Timer: EventType = EVT_TIMER;
+    #[deprecated(note = "use `EVT_RUNTIME`")]
+    pub const Runtime: EventType = EVT_RUNTIME;
+    #[deprecated(note = "use `EVT_NOTIFY_WAIT`")]
+    pub const NotifyWait: EventType = EVT_NOTIFY_WAIT;
+    #[deprecated(note = "use `EVT_NOTIFY_SIGNAL`")]
+    pub const NotifySignal: EventType = EVT_NOTIFY_SIGNAL;
+    #[deprecated(note = "use `EVT_SIGNAL_EXIT_BOOT_SERVICES`")]
+    pub const SignalExitBootServices: EventType = EVT_SIGNAL_EXIT_BOOT_SERVICES;
+    #[deprecated(note = "use `EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE`")]
+    pub const SignalVirtualAddressChange: EventType = EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE;
 }
 
 #[repr(C)]
@@ -19,3 +88,33 @@ pub enum TimerDelay {
 }
 
 pub type EventNotify = extern "win64" fn(event: Event, context: *const CVoid);
+
+#[test]
+fn event_type_validation() {
+    assert_eq!(EVT_TIMER.validate(TPL::Application, false), Ok(()));
+    assert_eq!(EVT_TIMER_NOTIFY_SIGNAL.validate(TPL::Callback, true), Ok(()));
+    assert_eq!(EVT_SIGNAL_EXIT_BOOT_SERVICES.validate(TPL::Notify, true), Ok(()));
+    assert_eq!(EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE.validate(TPL::Notify, true), Ok(()));
+
+    // A notification type needs a function, at a TPL it can run at, and the reverse.
+    assert_eq!(EVT_TIMER_NOTIFY_SIGNAL.validate(TPL::Callback, false), Err(Status::InvalidParameter));
+    assert_eq!(EVT_TIMER.validate(TPL::Callback, true), Err(Status::InvalidParameter));
+    assert_eq!(EVT_NOTIFY_SIGNAL.validate(TPL::Application, true), Err(Status::InvalidParameter));
+    assert_eq!((EVT_NOTIFY_WAIT | EVT_NOTIFY_SIGNAL).validate(TPL::Callback, true), Err(Status::InvalidParameter));
+
+    assert_eq!((EVT_TIMER | EVT_SIGNAL_EXIT_BOOT_SERVICES).validate(TPL::Notify, true), Err(Status::InvalidParameter));
+    assert_eq!((EVT_SIGNAL_EXIT_BOOT_SERVICES | EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE).validate(TPL::Notify, true),
+               Err(Status::InvalidParameter));
+}
+
+#[test]
+#[allow(deprecated)]
+fn event_type_enum_names() {
+    assert_eq!(EventType::SignalExitBootServices.bits(), 0x00000201);
+    let kind = match EVT_NOTIFY_SIGNAL {
+        EventType::Timer => "timer",
+        EventType::NotifySignal => "notify signal",
+        _ => "other",
+    };
+    assert_eq!(kind, "notify signal");
+}
@@ -0,0 +1,123 @@
+use core::{cmp, ptr};
+
+use base::Status;
+use protocol::SpiNorFlashProtocol;
+use util::{Sha256, SHA256_LEN};
+
+/// Read access to a flash device, such as the one holding the platform firmware.
+pub trait Flash {
+    /// Size of the device in bytes.
+    fn size(&self) -> u64;
+
+    /// Read `buf.len()` bytes from `offset`. Reads past the end of the device fail with
+    /// `Status::InvalidParameter`.
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<(), Status>;
+}
+
+impl Flash for SpiNorFlashProtocol {
+    fn size(&self) -> u64 {
+        self.flash_size() as u64
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<(), Status> {
+        if offset > u32::MAX as u64 {
+            return Err(Status::InvalidParameter);
+        }
+        SpiNorFlashProtocol::read(self, offset as u32, buf)
+    }
+}
+
+/// Flash which the chipset maps into the physical address space, as x86 platforms map the BIOS
+/// region just below 4GB.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryMappedFlash {
+    base: u64,
+    size: u64,
+}
+
+impl MemoryMappedFlash {
+    /// Flash of `size` bytes mapped at physical address `base`.
+    ///
+    /// # Safety
+    ///
+    /// `[base, base + size)` must be a mapping of flash, which can be read at any time without
+    /// side effects.
+    pub unsafe fn new(base: u64, size: u64) -> MemoryMappedFlash {
+        MemoryMappedFlash { base, size }
+    }
+
+    /// Flash of `size` bytes mapped so that it ends at 4GB.
+    ///
+    /// # Safety
+    ///
+    /// As for `new`.
+    pub unsafe fn below_4g(size: u64) -> MemoryMappedFlash {
+        MemoryMappedFlash::new((1 << 32) - size, size)
+    }
+}
+
+impl Flash for MemoryMappedFlash {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<(), Status> {
+        match offset.checked_add(buf.len() as u64) {
+            Some(end) if end <= self.size => {}
+            _ => return Err(Status::InvalidParameter),
+        }
+
+        // Use volatile reads, since the mapping is of a device rather than RAM.
+        let src = (self.base + offset) as usize as *const u8;
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = unsafe { ptr::read_volatile(src.add(i)) };
+        }
+        Ok(())
+    }
+}
+
+/// The first SPI NOR flash device the firmware exposes, which on most platforms holds the
+/// firmware itself.
+pub fn spi_flash() -> Result<&'static SpiNorFlashProtocol, Status> {
+    ::get_system_table()
+        .boot_services()
+        .locate_all_protocols::<SpiNorFlashProtocol>()?
+        .next()
+        .map(|(_, flash)| flash)
+        .ok_or(Status::NotFound)
+}
+
+/// Size of the chunks `dump_region` and `hash_region` read at a time.
+const FLASH_CHUNK_SIZE: usize = 4096;
+
+/// Read `len` bytes of `flash` from `offset` in chunks, passing each chunk and its offset to
+/// `f`, e.g. to write a firmware image out to a file.
+pub fn dump_region<F>(flash: &dyn Flash, offset: u64, len: u64, mut f: F) -> Result<(), Status>
+    where F: FnMut(u64, &[u8]) -> Result<(), Status>
+{
+    match offset.checked_add(len) {
+        Some(end) if end <= flash.size() => {}
+        _ => return Err(Status::InvalidParameter),
+    }
+
+    let mut buf = [0u8; FLASH_CHUNK_SIZE];
+    let mut done = 0;
+    while done < len {
+        let n = cmp::min(len - done, FLASH_CHUNK_SIZE as u64) as usize;
+        flash.read(offset + done, &mut buf[..n])?;
+        f(offset + done, &buf[..n])?;
+        done += n as u64;
+    }
+    Ok(())
+}
+
+/// SHA-256 of `len` bytes of `flash` from `offset`, for comparing the firmware against a known
+/// good image.
+pub fn hash_region(flash: &dyn Flash, offset: u64, len: u64) -> Result<[u8; SHA256_LEN], Status> {
+    let mut hasher = Sha256::new();
+    dump_region(flash, offset, len, |_, chunk| {
+        hasher.update(chunk);
+        Ok(())
+    })?;
+    Ok(hasher.finish())
+}
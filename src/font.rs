@@ -0,0 +1,435 @@
+//! Text drawn on the graphics output with PC Screen Fonts, the PSF1 and PSF2 bitmap fonts of
+//! the Linux console, loaded at run time from the ESP or embedded assets. With the font's
+//! Unicode table, text in any script the font covers can be drawn, where the firmware's own
+//! console usually only has Latin glyphs. On high-resolution panels, where an 8x16 font is
+//! too small to read, text can be drawn at an integer scale picked from the screen size.
+//!
+//! ```rust,ignore
+//! let mut buf = [0u8; 16 * 1024];
+//! let font = PsfFont::load(&overlay, "\\fonts\\ter-u16n.psf", &mut buf)?;
+//! let scale = font.auto_scale(gop);
+//! let x = (gop.resolution().0 - font.measure("Загрузка…", scale)) / 2;
+//! font.draw_str_scaled(gop, x, 16, "Загрузка…", WHITE, BLACK, scale)?;
+//! ```
+
+use core::char;
+use core::ops::Range;
+
+use base::Status;
+use overlay::FileSource;
+use protocol::{BltPixel, GraphicsOutputProtocol};
+use util::wire;
+
+/// Widest and tallest glyphs drawn, in pixels.
+pub const MAX_GLYPH_WIDTH: usize = 64;
+pub const MAX_GLYPH_HEIGHT: usize = 64;
+
+/// Largest integer scale text is drawn at.
+pub const MAX_FONT_SCALE: usize = 4;
+
+/// Characters a screen should hold across and down at the scale `PsfFont::auto_scale` picks.
+pub const MIN_TEXT_COLUMNS: usize = 80;
+pub const MIN_TEXT_ROWS: usize = 45;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+const PSF1_MODE512: u8 = 0x01;
+const PSF1_MODEHASTAB: u8 = 0x02;
+const PSF1_MODESEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_STARTSEQ: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_STARTSEQ: u8 = 0xFE;
+
+/// The two versions of the format, which differ in how the Unicode table is encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PsfVersion {
+    Psf1,
+    Psf2,
+}
+
+/// A PC Screen Font, borrowing the file it was parsed from.
+#[derive(Clone, Copy, Debug)]
+pub struct PsfFont<'a> {
+    version: PsfVersion,
+    glyphs: &'a [u8],
+    glyph_count: usize,
+    glyph_size: usize,
+    width: usize,
+    height: usize,
+    unicode_table: Option<&'a [u8]>,
+}
+
+/// The bitmap of one glyph: rows of `(width + 7) / 8` bytes, most significant bit leftmost.
+#[derive(Clone, Copy, Debug)]
+pub struct Glyph<'a> {
+    bitmap: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Glyph<'a> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether the pixel at (`x`, `y`) is set.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let row = y * ((self.width + 7) / 8);
+        self.bitmap[row + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    /// Draw the glyph into `buf`, `width` by `height` pixels stored row by row, in `fg` on
+    /// `bg`. Fails with `Status::BufferTooSmall` if `buf` can't hold it.
+    pub fn render(&self, fg: BltPixel, bg: BltPixel, buf: &mut [BltPixel]) -> Result<(), Status> {
+        self.render_rows(0..self.height, 1, fg, bg, buf)
+    }
+
+    /// Draw the glyph's `rows` into `buf` with each pixel made a `scale` by `scale` square, so
+    /// `width * scale` pixels a row and `scale` rows for each of the glyph's.
+    pub fn render_rows(&self, rows: Range<usize>, scale: usize, fg: BltPixel, bg: BltPixel, buf: &mut [BltPixel])
+                       -> Result<(), Status> {
+        if scale == 0 || rows.end > self.height {
+            return Err(Status::InvalidParameter);
+        }
+        let line = self.width * scale;
+        let buf = buf.get_mut(..line * scale * rows.len()).ok_or(Status::BufferTooSmall)?;
+        for (i, pixel) in buf.iter_mut().enumerate() {
+            let (x, y) = (i % line / scale, rows.start + i / line / scale);
+            *pixel = if self.pixel(x, y) { fg } else { bg };
+        }
+        Ok(())
+    }
+}
+
+impl<'a> PsfFont<'a> {
+    /// Parse a PSF1 or PSF2 font, failing with `Status::Unsupported` for anything else, and
+    /// `Status::InvalidParameter` if there are no glyphs, they run past the end, or they are
+    /// larger than `MAX_GLYPH_WIDTH` by `MAX_GLYPH_HEIGHT`.
+    pub fn parse(font: &'a [u8]) -> Result<PsfFont<'a>, Status> {
+        let bad = |_| Status::InvalidParameter;
+        let (version, header_size, glyph_count, glyph_size, width, height, has_table) = if font.starts_with(&PSF1_MAGIC) {
+            let mode = wire::read_u8(font, 2).map_err(bad)?;
+            let height = wire::read_u8(font, 3).map_err(bad)? as usize;
+            let count = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+            (PsfVersion::Psf1, PSF1_HEADER_SIZE, count, height, 8, height, mode & (PSF1_MODEHASTAB | PSF1_MODESEQ) != 0)
+        } else if font.starts_with(&PSF2_MAGIC) {
+            let header_size = wire::read_u32(font, 8).map_err(bad)? as usize;
+            let flags = wire::read_u32(font, 12).map_err(bad)?;
+            let count = wire::read_u32(font, 16).map_err(bad)? as usize;
+            let glyph_size = wire::read_u32(font, 20).map_err(bad)? as usize;
+            let height = wire::read_u32(font, 24).map_err(bad)? as usize;
+            let width = wire::read_u32(font, 28).map_err(bad)? as usize;
+            (PsfVersion::Psf2, header_size.max(PSF2_HEADER_SIZE), count, glyph_size, width, height,
+             flags & PSF2_HAS_UNICODE_TABLE != 0)
+        } else {
+            return Err(Status::Unsupported);
+        };
+
+        if glyph_count == 0 || width == 0 || height == 0 || width > MAX_GLYPH_WIDTH || height > MAX_GLYPH_HEIGHT
+            || glyph_size < (width + 7) / 8 * height {
+            return Err(Status::InvalidParameter);
+        }
+        let end = glyph_count.checked_mul(glyph_size).and_then(|size| size.checked_add(header_size));
+        let glyphs = end.and_then(|end| font.get(header_size..end)).ok_or(Status::InvalidParameter)?;
+        let unicode_table = if has_table { Some(&font[header_size + glyphs.len()..]) } else { None };
+
+        Ok(PsfFont { version, glyphs, glyph_count, glyph_size, width, height, unicode_table })
+    }
+
+    /// Read the font at `path` from `source`, such as an `Overlay` of the ESP and embedded
+    /// assets, into `buf` and parse it.
+    pub fn load(source: &dyn FileSource, path: &str, buf: &'a mut [u8]) -> Result<PsfFont<'a>, Status> {
+        let len = source.read_file(path, buf)?;
+        PsfFont::parse(&buf[..len])
+    }
+
+    pub fn version(&self) -> PsfVersion {
+        self.version
+    }
+
+    /// Width of every glyph, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of every glyph, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    pub fn has_unicode_table(&self) -> bool {
+        self.unicode_table.is_some()
+    }
+
+    /// The glyph at `index` in the font.
+    pub fn glyph_at(&self, index: usize) -> Option<Glyph<'a>> {
+        if index >= self.glyph_count {
+            return None;
+        }
+        let start = index * self.glyph_size;
+        Some(Glyph { bitmap: &self.glyphs[start..start + self.glyph_size], width: self.width, height: self.height })
+    }
+
+    /// The index of the glyph for `c`: looked up in the Unicode table if the font has one, and
+    /// otherwise the character's code, as fonts without a table are in Latin-1 order.
+    /// Sequences of several characters drawn as one glyph are not matched.
+    pub fn glyph_index(&self, c: char) -> Option<usize> {
+        let table = match self.unicode_table {
+            Some(table) => table,
+            None => return Some(c as usize).filter(|&i| i < self.glyph_count),
+        };
+        match self.version {
+            PsfVersion::Psf1 => psf1_lookup(table, c),
+            PsfVersion::Psf2 => psf2_lookup(table, c),
+        }.filter(|&i| i < self.glyph_count)
+    }
+
+    /// The glyph for `c`, if the font has one.
+    pub fn glyph(&self, c: char) -> Option<Glyph<'a>> {
+        self.glyph_index(c).and_then(|i| self.glyph_at(i))
+    }
+
+    /// The glyph drawn for `c`: its own, or else the font's replacement character or `?`, or
+    /// failing those its first glyph.
+    pub fn glyph_or_replacement(&self, c: char) -> Glyph<'a> {
+        self.glyph(c)
+            .or_else(|| self.glyph(char::REPLACEMENT_CHARACTER))
+            .or_else(|| self.glyph('?'))
+            .unwrap_or_else(|| self.glyph_at(0).unwrap())
+    }
+
+    /// Width of `s` in pixels when drawn at `scale`, for laying out text before drawing it.
+    pub fn measure(&self, s: &str, scale: usize) -> usize {
+        s.chars().count() * self.width * scale
+    }
+
+    /// The largest scale, up to `MAX_FONT_SCALE`, at which a screen of `width` by `height`
+    /// pixels still holds `MIN_TEXT_COLUMNS` by `MIN_TEXT_ROWS` characters: 1 at 1080p with an
+    /// 8x16 font, 2 at 1440p and 3 on a 4K panel. Never less than 1.
+    pub fn scale_for_resolution(&self, width: usize, height: usize) -> usize {
+        let fit = (width / (self.width * MIN_TEXT_COLUMNS)).min(height / (self.height * MIN_TEXT_ROWS));
+        fit.clamp(1, MAX_FONT_SCALE)
+    }
+
+    /// `scale_for_resolution` for the current mode of `gop`.
+    pub fn auto_scale(&self, gop: &GraphicsOutputProtocol) -> usize {
+        let (width, height) = gop.resolution();
+        self.scale_for_resolution(width, height)
+    }
+
+    /// Draw `s` on one line from (`x`, `y`), in `fg` on `bg`, returning the x coordinate after
+    /// the last glyph. Text past the right edge of the screen is left out.
+    pub fn draw_str(&self, gop: &GraphicsOutputProtocol, x: usize, y: usize, s: &str, fg: BltPixel, bg: BltPixel)
+                    -> Result<usize, Status> {
+        self.draw_str_scaled(gop, x, y, s, fg, bg, 1)
+    }
+
+    /// Draw `s` as `draw_str` does, with each pixel of the font made a `scale` by `scale`
+    /// square, up to `MAX_FONT_SCALE`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_str_scaled(&self, gop: &GraphicsOutputProtocol, x: usize, y: usize, s: &str, fg: BltPixel, bg: BltPixel,
+                           scale: usize) -> Result<usize, Status> {
+        if scale == 0 || scale > MAX_FONT_SCALE {
+            return Err(Status::InvalidParameter);
+        }
+        let (glyph_width, glyph_height) = (self.width * scale, self.height * scale);
+        let (screen_width, screen_height) = gop.resolution();
+        if y + glyph_height > screen_height {
+            return Err(Status::InvalidParameter);
+        }
+
+        // Glyphs too large for the buffer once scaled are drawn in bands of rows.
+        let mut buf = [BltPixel::default(); MAX_GLYPH_WIDTH * MAX_GLYPH_HEIGHT];
+        let band = (buf.len() / (glyph_width * scale)).min(self.height);
+        let mut x = x;
+        for c in s.chars() {
+            if x + glyph_width > screen_width {
+                break;
+            }
+            let glyph = self.glyph_or_replacement(c);
+            let mut row = 0;
+            while row < self.height {
+                let rows = row..(row + band).min(self.height);
+                glyph.render_rows(rows.clone(), scale, fg, bg, &mut buf)?;
+                gop.write_rect(x, y + row * scale, glyph_width, rows.len() * scale, &buf)?;
+                row = rows.end;
+            }
+            x += glyph_width;
+        }
+        Ok(x)
+    }
+}
+
+/// Find `c` in a PSF1 Unicode table: for each glyph, UCS-2 characters, then sequences each
+/// introduced by `PSF1_STARTSEQ`, ended by `PSF1_SEPARATOR`.
+fn psf1_lookup(table: &[u8], c: char) -> Option<usize> {
+    let c = c as u32;
+    let mut glyph = 0;
+    let mut in_sequence = false;
+    for unit in table.chunks_exact(2).map(|u| u16::from_le_bytes([u[0], u[1]])) {
+        match unit {
+            PSF1_SEPARATOR => {
+                glyph += 1;
+                in_sequence = false;
+            }
+            PSF1_STARTSEQ => in_sequence = true,
+            unit if !in_sequence && unit as u32 == c => return Some(glyph),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find `c` in a PSF2 Unicode table: for each glyph, UTF-8 characters, then sequences each
+/// introduced by `PSF2_STARTSEQ`, ended by `PSF2_SEPARATOR`.
+fn psf2_lookup(table: &[u8], c: char) -> Option<usize> {
+    let mut encoded = [0u8; 4];
+    let encoded = c.encode_utf8(&mut encoded).as_bytes();
+    let mut glyph = 0;
+    let mut i = 0;
+    let mut in_sequence = false;
+    while i < table.len() {
+        match table[i] {
+            PSF2_SEPARATOR => {
+                glyph += 1;
+                in_sequence = false;
+                i += 1;
+            }
+            PSF2_STARTSEQ => {
+                in_sequence = true;
+                i += 1;
+            }
+            lead => {
+                // The length of a UTF-8 character from its lead byte; stray bytes count as one.
+                let len = match lead {
+                    0xF0..=0xF7 => 4,
+                    0xE0..=0xEF => 3,
+                    0xC0..=0xDF => 2,
+                    _ => 1,
+                };
+                if !in_sequence && table.get(i..i + len) == Some(encoded) {
+                    return Some(glyph);
+                }
+                i += len;
+            }
+        }
+    }
+    None
+}
+
+/// PSF1: 256 8x2 glyphs with a table mapping glyph 1 to 'A' and U+00C4 to the sequence
+/// A + U+0308, and glyph 2 to U+0416.
+#[cfg(test)]
+fn test_psf1() -> [u8; PSF1_HEADER_SIZE + 256 * 2 + 32] {
+    let mut psf1 = [0u8; PSF1_HEADER_SIZE + 256 * 2 + 32];
+    psf1[..4].copy_from_slice(&[0x36, 0x04, PSF1_MODEHASTAB, 2]);
+    psf1[4 + 2..4 + 4].copy_from_slice(&[0x81, 0x80]);
+    let table: [u16; 9] = [0xFFFF, 0x41, 0xC4, 0xFFFE, 0x41, 0x308, 0xFFFF, 0x416, 0xFFFF];
+    for (i, unit) in table.iter().enumerate() {
+        psf1[4 + 512 + 2 * i..4 + 512 + 2 * i + 2].copy_from_slice(&unit.to_le_bytes());
+    }
+    psf1
+}
+
+/// PSF2: two 12x2 glyphs, the second for 'Ж', with no replacement character or '?'.
+#[cfg(test)]
+fn test_psf2() -> [u8; PSF2_HEADER_SIZE + 2 * 4 + 4] {
+    let mut psf2 = [0u8; PSF2_HEADER_SIZE + 2 * 4 + 4];
+    for (i, field) in [0x864AB572, 0, 32, PSF2_HAS_UNICODE_TABLE, 2, 4, 2, 12].iter().enumerate() {
+        psf2[4 * i..4 * i + 4].copy_from_slice(&field.to_le_bytes());
+    }
+    psf2[PSF2_HEADER_SIZE + 4..PSF2_HEADER_SIZE + 6].copy_from_slice(&[0x00, 0x10]);
+    psf2[PSF2_HEADER_SIZE + 8..].copy_from_slice(&[0xFF, 0xD0, 0x96, 0xFF]);
+    psf2
+}
+
+#[test]
+fn psf1_fonts() {
+    let psf1 = test_psf1();
+    let font = PsfFont::parse(&psf1).unwrap();
+    assert_eq!((font.version(), font.width(), font.height(), font.glyph_count()), (PsfVersion::Psf1, 8, 2, 256));
+    assert_eq!(font.glyph_index('A'), Some(1));
+    assert_eq!(font.glyph_index('\u{C4}'), Some(1));
+    assert_eq!(font.glyph_index('\u{308}'), None);
+    assert_eq!(font.glyph_index('Ж'), Some(2));
+    let glyph = font.glyph('A').unwrap();
+    assert!(glyph.pixel(0, 0) && glyph.pixel(7, 0) && !glyph.pixel(1, 0));
+    assert!(glyph.pixel(0, 1) && !glyph.pixel(7, 1));
+    assert_eq!(font.measure("Ж!", 3), 48);
+}
+
+#[test]
+fn psf2_fonts() {
+    let psf2 = test_psf2();
+    let font = PsfFont::parse(&psf2).unwrap();
+    assert_eq!((font.version(), font.width(), font.height(), font.glyph_count()), (PsfVersion::Psf2, 12, 2, 2));
+    assert_eq!(font.glyph_index('Ж'), Some(1));
+    assert!(font.glyph('Ж').unwrap().pixel(11, 0));
+    assert_eq!(font.glyph_index('A'), None);
+    assert!(font.glyph('A').is_none());
+    assert!(!font.glyph_or_replacement('A').pixel(11, 0));
+}
+
+#[test]
+fn glyph_rendering() {
+    let psf1 = test_psf1();
+    let font = PsfFont::parse(&psf1).unwrap();
+    let glyph = font.glyph('A').unwrap();
+
+    let (fg, bg) = (BltPixel { red: 0xFF, ..BltPixel::default() }, BltPixel::default());
+    let mut buf = [BltPixel::default(); 16];
+    glyph.render(fg, bg, &mut buf).unwrap();
+    assert_eq!(&buf[..3], &[fg, bg, bg]);
+    assert_eq!(&buf[7..10], &[fg, fg, bg]);
+    assert_eq!(glyph.render(fg, bg, &mut buf[..15]), Err(Status::BufferTooSmall));
+
+    let mut scaled = [BltPixel::default(); 64];
+    glyph.render_rows(1..2, 2, fg, bg, &mut scaled).unwrap();
+    assert_eq!(&scaled[..4], &[fg, fg, bg, bg]);
+    assert_eq!(&scaled[16..19], &[fg, fg, bg]);
+    assert_eq!(glyph.render_rows(0..2, 2, fg, bg, &mut scaled[..63]), Err(Status::BufferTooSmall));
+}
+
+#[test]
+fn font_scaling() {
+    let mut vga = [0u8; PSF1_HEADER_SIZE + 256 * 16];
+    vga[..4].copy_from_slice(&[0x36, 0x04, 0, 16]);
+    let font = PsfFont::parse(&vga).unwrap();
+    assert_eq!(font.scale_for_resolution(640, 480), 1);
+    assert_eq!(font.scale_for_resolution(1920, 1080), 1);
+    assert_eq!(font.scale_for_resolution(2560, 1440), 2);
+    assert_eq!(font.scale_for_resolution(3840, 2160), 3);
+}
+
+#[test]
+fn psf_parse_errors() {
+    let psf1 = test_psf1();
+    let psf2 = test_psf2();
+    assert_eq!(PsfFont::parse(b"BM").map(|_| ()), Err(Status::Unsupported));
+    // Cut off in the header, and in the glyphs.
+    assert_eq!(PsfFont::parse(&psf1[..3]).map(|_| ()), Err(Status::InvalidParameter));
+    assert_eq!(PsfFont::parse(&psf2[..20]).map(|_| ()), Err(Status::InvalidParameter));
+    assert_eq!(PsfFont::parse(&psf1[..PSF1_HEADER_SIZE + 256 * 2 - 1]).map(|_| ()), Err(Status::InvalidParameter));
+    assert_eq!(PsfFont::parse(&psf2[..39]).map(|_| ()), Err(Status::InvalidParameter));
+
+    // Glyphs too small for their size, and no glyphs at all.
+    let mut small = psf2;
+    small[20..24].copy_from_slice(&2u32.to_le_bytes());
+    assert_eq!(PsfFont::parse(&small).map(|_| ()), Err(Status::InvalidParameter));
+    let mut empty = psf2;
+    empty[16..20].copy_from_slice(&0u32.to_le_bytes());
+    assert_eq!(PsfFont::parse(&empty).map(|_| ()), Err(Status::InvalidParameter));
+}
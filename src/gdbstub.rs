@@ -0,0 +1,525 @@
+//! A minimal GDB remote serial protocol stub, so gdb can be attached to an application on real
+//! hardware over a serial line (`target remote /dev/ttyUSB0`).
+//!
+//! The stub hooks processor exceptions through the debug support protocol. When one is taken,
+//! it reports the stop to gdb and serves its requests (registers, memory, software breakpoints
+//! and single-stepping) until gdb resumes execution. Only x64 is supported, and only the
+//! general-purpose registers are exposed.
+//!
+//! ```rust,ignore
+//! static mut SERIAL: Option<SerialIOProtocol> = None;
+//!
+//! let debug = bs.locate_protocol::<DebugSupportProtocol>(ptr::null())?;
+//! let serial = unsafe { SERIAL.insert(SerialIOProtocol::new()?) };
+//! unsafe { install_gdbstub(debug, serial)? };
+//! // Stop here and wait for gdb to attach.
+//! gdb_breakpoint();
+//! ```
+
+use core::arch::asm;
+use core::ptr;
+use core::str;
+
+use base::Status;
+use protocol::{DebugSupportProtocol, InstructionSetArchitecture, SerialIOProtocol, SystemContextX64,
+               EXCEPT_X64_BREAKPOINT, EXCEPT_X64_DEBUG, EXCEPT_X64_DIVIDE_ERROR, EXCEPT_X64_GP_FAULT,
+               EXCEPT_X64_INVALID_OPCODE, EXCEPT_X64_PAGE_FAULT};
+
+/// Largest packet the stub accepts or sends, including the framing.
+pub const GDB_PACKET_SIZE: usize = 1024;
+/// Most software breakpoints that can be set at once.
+pub const MAX_BREAKPOINTS: usize = 16;
+
+const INT3: u8 = 0xCC;
+const TRAP_FLAG: u64 = 0x100;
+
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGFPE: u8 = 8;
+const SIGSEGV: u8 = 11;
+
+/// A byte stream to a debugger.
+pub trait GdbConnection {
+    /// Wait for and return the next byte.
+    fn read_byte(&mut self) -> Result<u8, Status>;
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Status>;
+}
+
+impl GdbConnection for SerialIOProtocol {
+    fn read_byte(&mut self) -> Result<u8, Status> {
+        let mut b = [0];
+        while self.try_read(&mut b)? == 0 {}
+        Ok(b[0])
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Status> {
+        // Packets are always ASCII.
+        let mut data = str::from_utf8(data).map_err(|_| Status::InvalidParameter)?;
+        while !data.is_empty() {
+            let written = self.write(data)?;
+            data = &data[written..];
+        }
+        Ok(())
+    }
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Parse a hex number, such as an address or length.
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    s.iter().try_fold(0u64, |v, &c| Some(v << 4 | hex_digit(c)? as u64))
+}
+
+/// Parse `addr,len`, as used by the memory and breakpoint packets.
+fn parse_addr_len(s: &[u8]) -> Option<(u64, u64)> {
+    let comma = s.iter().position(|&c| c == b',')?;
+    Some((parse_hex(&s[..comma])?, parse_hex(&s[comma + 1..])?))
+}
+
+/// Hex-encoded bytes, as in memory and register packets.
+fn hex_bytes(s: &[u8]) -> impl Iterator<Item = Option<u8>> + '_ {
+    s.chunks(2).map(|c| match *c {
+        [hi, lo] => Some(hex_digit(hi)? << 4 | hex_digit(lo)?),
+        _ => None,
+    })
+}
+
+/// The registers of gdb's x86-64 `g` packet, with their sizes in bytes.
+fn register(context: &mut SystemContextX64, index: usize) -> Option<(&mut u64, usize)> {
+    let r = match index {
+        0 => &mut context.rax,
+        1 => &mut context.rbx,
+        2 => &mut context.rcx,
+        3 => &mut context.rdx,
+        4 => &mut context.rsi,
+        5 => &mut context.rdi,
+        6 => &mut context.rbp,
+        7 => &mut context.rsp,
+        8 => &mut context.r8,
+        9 => &mut context.r9,
+        10 => &mut context.r10,
+        11 => &mut context.r11,
+        12 => &mut context.r12,
+        13 => &mut context.r13,
+        14 => &mut context.r14,
+        15 => &mut context.r15,
+        16 => &mut context.rip,
+        17 => return Some((&mut context.rflags, 4)),
+        18 => return Some((&mut context.cs, 4)),
+        19 => return Some((&mut context.ss, 4)),
+        20 => return Some((&mut context.ds, 4)),
+        21 => return Some((&mut context.es, 4)),
+        22 => return Some((&mut context.fs, 4)),
+        23 => return Some((&mut context.gs, 4)),
+        _ => return None,
+    };
+    Some((r, 8))
+}
+
+/// The registers of a `G` packet applied to a copy of `context`, or `None` if the packet is too
+/// short or not all hex. gdb may send more registers than the stub exposes; those are ignored.
+fn decode_registers(context: &SystemContextX64, data: &[u8]) -> Option<SystemContextX64> {
+    let mut decoded = *context;
+    let mut bytes = hex_bytes(data);
+    let mut i = 0;
+    while let Some((value, size)) = register(&mut decoded, i) {
+        let mut b = [0u8; 8];
+        for byte in b.iter_mut().take(size) {
+            *byte = bytes.next()??;
+        }
+        *value = u64::from_le_bytes(b);
+        i += 1;
+    }
+
+    if bytes.all(|b| b.is_some()) {
+        Some(decoded)
+    } else {
+        None
+    }
+}
+
+/// A reply packet being built.
+struct Reply {
+    buf: [u8; GDB_PACKET_SIZE],
+    len: usize,
+}
+
+impl Reply {
+    fn push(&mut self, data: &[u8]) {
+        // Commands limit their replies to fit, leaving room for the framing.
+        let n = data.len().min(GDB_PACKET_SIZE - 4 - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+    }
+
+    fn push_hex(&mut self, data: &[u8]) {
+        for &b in data {
+            self.push(&[HEX_DIGITS[(b >> 4) as usize], HEX_DIGITS[(b & 0xF) as usize]]);
+        }
+    }
+}
+
+enum Resume {
+    Continue,
+    Step,
+}
+
+/// The protocol state of a connection to gdb.
+pub struct GdbStub<'a> {
+    conn: &'a mut dyn GdbConnection,
+    packet: [u8; GDB_PACKET_SIZE],
+    /// Inserted breakpoints, with the instruction bytes they replaced.
+    breakpoints: [Option<(u64, u8)>; MAX_BREAKPOINTS],
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(conn: &'a mut dyn GdbConnection) -> GdbStub<'a> {
+        GdbStub {
+            conn,
+            packet: [0; GDB_PACKET_SIZE],
+            breakpoints: [None; MAX_BREAKPOINTS],
+        }
+    }
+
+    /// Read the next well-formed packet into `self.packet`, acknowledging it, and return its
+    /// length.
+    fn receive_packet(&mut self) -> Result<usize, Status> {
+        loop {
+            // Anything outside a packet, such as an interrupt request, is ignored while stopped.
+            while self.conn.read_byte()? != b'$' {}
+
+            let mut len = 0;
+            let mut sum = 0u8;
+            let mut overflow = false;
+            loop {
+                match self.conn.read_byte()? {
+                    b'#' => break,
+                    c if len < GDB_PACKET_SIZE => {
+                        self.packet[len] = c;
+                        len += 1;
+                        sum = sum.wrapping_add(c);
+                    }
+                    _ => overflow = true,
+                }
+            }
+
+            let checksum = [self.conn.read_byte()?, self.conn.read_byte()?];
+            if !overflow && hex_bytes(&checksum).next().flatten() == Some(sum) {
+                self.conn.write_all(b"+")?;
+                return Ok(len);
+            }
+            self.conn.write_all(b"-")?;
+        }
+    }
+
+    fn send_packet(&mut self, data: &[u8]) -> Result<(), Status> {
+        let sum = data.iter().fold(0u8, |s, &c| s.wrapping_add(c));
+        let trailer = [b'#', HEX_DIGITS[(sum >> 4) as usize], HEX_DIGITS[(sum & 0xF) as usize]];
+        loop {
+            self.conn.write_all(b"$")?;
+            self.conn.write_all(data)?;
+            self.conn.write_all(&trailer)?;
+            // Resend until gdb acknowledges it.
+            match self.conn.read_byte()? {
+                b'-' => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn is_breakpoint(&self, addr: u64) -> bool {
+        self.breakpoints.iter().any(|b| matches!(*b, Some((a, _)) if a == addr))
+    }
+
+    fn insert_breakpoint(&mut self, addr: u64) -> bool {
+        if self.is_breakpoint(addr) {
+            return true;
+        }
+        match self.breakpoints.iter_mut().find(|b| b.is_none()) {
+            Some(slot) => unsafe {
+                let p = addr as *mut u8;
+                *slot = Some((addr, ptr::read_volatile(p)));
+                ptr::write_volatile(p, INT3);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn remove_breakpoint(&mut self, addr: u64) {
+        for slot in self.breakpoints.iter_mut() {
+            if let Some((a, original)) = *slot {
+                if a == addr {
+                    unsafe { ptr::write_volatile(a as *mut u8, original) };
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    fn remove_all_breakpoints(&mut self) {
+        for i in 0..MAX_BREAKPOINTS {
+            if let Some((addr, _)) = self.breakpoints[i] {
+                self.remove_breakpoint(addr);
+            }
+        }
+    }
+
+    /// Report a stop with `signal` to gdb and serve its requests on `context` until it resumes
+    /// execution. On a single-step request the trap flag is set in `context`.
+    pub fn handle_stop(&mut self, context: &mut SystemContextX64, signal: u8) -> Result<(), Status> {
+        let mut reply = Reply { buf: [0; GDB_PACKET_SIZE], len: 0 };
+        reply.push(b"S");
+        reply.push_hex(&[signal]);
+        self.send_packet(&reply.buf[..reply.len])?;
+
+        loop {
+            let len = self.receive_packet()?;
+            let packet = self.packet;
+            reply.len = 0;
+
+            match self.dispatch(&packet[..len], context, signal, &mut reply) {
+                Some(resume) => {
+                    if let Resume::Step = resume {
+                        context.rflags |= TRAP_FLAG;
+                    }
+                    return Ok(());
+                }
+                None => self.send_packet(&reply.buf[..reply.len])?,
+            }
+        }
+    }
+
+    /// Handle one packet, filling in `reply`, or return how to resume. An empty reply tells gdb
+    /// the packet is not supported.
+    fn dispatch(&mut self, packet: &[u8], context: &mut SystemContextX64, signal: u8, reply: &mut Reply)
+                -> Option<Resume> {
+        let (&command, args) = packet.split_first()?;
+
+        match command {
+            b'?' => {
+                reply.push(b"S");
+                reply.push_hex(&[signal]);
+            }
+            b'g' => {
+                let mut i = 0;
+                while let Some((value, size)) = register(context, i) {
+                    reply.push_hex(&value.to_le_bytes()[..size]);
+                    i += 1;
+                }
+            }
+            // Registers and memory are only written once the whole packet has decoded, so a
+            // corrupt packet can't leave them half updated.
+            b'G' => match decode_registers(context, args) {
+                Some(decoded) => {
+                    *context = decoded;
+                    reply.push(b"OK");
+                }
+                None => reply.push(b"E01"),
+            },
+            b'm' => match parse_addr_len(args) {
+                Some((addr, len)) => {
+                    let len = (len as usize).min((GDB_PACKET_SIZE - 4) / 2);
+                    for i in 0..len {
+                        let b = unsafe { ptr::read_volatile((addr as usize + i) as *const u8) };
+                        reply.push_hex(&[b]);
+                    }
+                }
+                None => reply.push(b"E01"),
+            },
+            b'M' => {
+                let colon = args.iter().position(|&c| c == b':');
+                match colon.and_then(|colon| Some((parse_addr_len(&args[..colon])?, &args[colon + 1..]))) {
+                    Some(((addr, len), data)) if len.checked_mul(2) == Some(data.len() as u64)
+                                                 && hex_bytes(data).all(|b| b.is_some()) => {
+                        for (i, b) in hex_bytes(data).flatten().enumerate() {
+                            unsafe { ptr::write_volatile((addr as usize + i) as *mut u8, b) };
+                        }
+                        reply.push(b"OK");
+                    }
+                    _ => reply.push(b"E01"),
+                }
+            }
+            b'c' | b's' => {
+                if let Some(addr) = parse_hex(args) {
+                    context.rip = addr;
+                }
+                return Some(if command == b's' { Resume::Step } else { Resume::Continue });
+            }
+            b'Z' | b'z' if args.starts_with(b"0,") => match parse_addr_len(&args[2..]) {
+                Some((addr, _)) if command == b'Z' => {
+                    reply.push(if self.insert_breakpoint(addr) { b"OK" } else { b"E01" });
+                }
+                Some((addr, _)) => {
+                    self.remove_breakpoint(addr);
+                    reply.push(b"OK");
+                }
+                None => reply.push(b"E01"),
+            },
+            b'q' if args.starts_with(b"Supported") => {
+                reply.push(b"PacketSize=");
+                reply.push_hex(&(GDB_PACKET_SIZE as u16).to_be_bytes());
+            }
+            b'q' if args == b"Attached" => reply.push(b"1"),
+            b'D' | b'k' => {
+                self.remove_all_breakpoints();
+                if command == b'D' {
+                    // The reply must go out before execution resumes.
+                    let _ = self.send_packet(b"OK");
+                }
+                return Some(Resume::Continue);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+static mut STUB: Option<GdbStub<'static>> = None;
+
+extern "win64" fn exception_callback(exception_type: isize, context: *mut SystemContextX64) {
+    let stub = match unsafe { (*ptr::addr_of_mut!(STUB)).as_mut() } {
+        Some(stub) => stub,
+        None => return,
+    };
+    let context = unsafe { &mut *context };
+
+    // A single step is over once the exception is taken.
+    context.rflags &= !TRAP_FLAG;
+
+    // int3 leaves RIP after the breakpoint; point it back at the breakpoint so it is reported
+    // where gdb set it, and the original instruction runs once it is removed.
+    if exception_type == EXCEPT_X64_BREAKPOINT && stub.is_breakpoint(context.rip.wrapping_sub(1)) {
+        context.rip -= 1;
+    }
+
+    let signal = match exception_type {
+        EXCEPT_X64_DIVIDE_ERROR => SIGFPE,
+        EXCEPT_X64_INVALID_OPCODE => SIGILL,
+        EXCEPT_X64_GP_FAULT | EXCEPT_X64_PAGE_FAULT => SIGSEGV,
+        _ => SIGTRAP,
+    };
+
+    // If the connection fails there is nothing to do but carry on.
+    let _ = stub.handle_stop(context, signal);
+}
+
+/// Start the stub on `conn`, hooking breakpoint, debug and fault exceptions on the boot
+/// processor. Nothing is sent to gdb until an exception is taken, such as by `gdb_breakpoint`.
+///
+/// # Safety
+///
+/// The stub takes over exception handling, and lets gdb read and write any memory and
+/// registers. It must only be installed once, and `conn` must work from exception context.
+pub unsafe fn install_gdbstub(debug: &DebugSupportProtocol, conn: &'static mut dyn GdbConnection) -> Result<(), Status> {
+    if debug.isa != InstructionSetArchitecture::X64 {
+        return Err(Status::Unsupported);
+    }
+
+    *ptr::addr_of_mut!(STUB) = Some(GdbStub::new(conn));
+    for &exception in &[EXCEPT_X64_DIVIDE_ERROR, EXCEPT_X64_DEBUG, EXCEPT_X64_BREAKPOINT,
+                        EXCEPT_X64_INVALID_OPCODE, EXCEPT_X64_GP_FAULT, EXCEPT_X64_PAGE_FAULT] {
+        debug.register_exception_callback(0, Some(exception_callback), exception)?;
+    }
+    Ok(())
+}
+
+/// Stop in the debugger, as if a breakpoint had been hit here.
+pub fn gdb_breakpoint() {
+    unsafe { asm!("int3") };
+}
+
+#[cfg(test)]
+struct Script {
+    input: &'static [u8],
+    output: [u8; 512],
+    written: usize,
+}
+
+#[cfg(test)]
+impl GdbConnection for Script {
+    fn read_byte(&mut self) -> Result<u8, Status> {
+        let (&b, rest) = self.input.split_first().ok_or(Status::EndOfFile)?;
+        self.input = rest;
+        Ok(b)
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Status> {
+        self.output[self.written..self.written + data.len()].copy_from_slice(data);
+        self.written += data.len();
+        Ok(())
+    }
+}
+
+#[test]
+fn gdb_session() {
+    let mut script = Script { input: b"+$g#67+$s#73", output: [0; 512], written: 0 };
+    let mut context: SystemContextX64 = unsafe { ::core::mem::zeroed() };
+    context.rax = 0x1122;
+    context.rflags = 0x202;
+
+    GdbStub::new(&mut script).handle_stop(&mut context, SIGTRAP).unwrap();
+    assert_eq!(context.rflags, 0x302);
+
+    let out = str::from_utf8(&script.output[..script.written]).unwrap();
+    assert!(out.starts_with("$S05#b8+$2211000000000000"));
+}
+
+#[test]
+fn gdb_rejects_bad_writes() {
+    let mut script = Script { input: b"", output: [0; 512], written: 0 };
+    let mut stub = GdbStub::new(&mut script);
+    let mut context: SystemContextX64 = unsafe { ::core::mem::zeroed() };
+    let mut reply = Reply { buf: [0; GDB_PACKET_SIZE], len: 0 };
+
+    // 17 eight-byte and 7 four-byte registers, with rax set to 0x10 and a bad last digit.
+    let mut packet = [b'0'; 1 + 17 * 16 + 7 * 8];
+    packet[0] = b'G';
+    packet[1] = b'1';
+    let last = packet.len() - 1;
+    packet[last] = b'x';
+    assert!(stub.dispatch(&packet, &mut context, SIGTRAP, &mut reply).is_none());
+    assert_eq!(&reply.buf[..reply.len], b"E01");
+    assert_eq!(context.rax, 0);
+
+    reply.len = 0;
+    stub.dispatch(&packet[..last - 1], &mut context, SIGTRAP, &mut reply);
+    assert_eq!(&reply.buf[..reply.len], b"E01");
+    assert_eq!(context.rax, 0);
+
+    reply.len = 0;
+    packet[last] = b'0';
+    stub.dispatch(&packet, &mut context, SIGTRAP, &mut reply);
+    assert_eq!(&reply.buf[..reply.len], b"OK");
+    assert_eq!(context.rax, 0x10);
+
+    // Memory writes with bad hex or an overflowing length leave memory alone.
+    let mut memory = [0u8; 2];
+    let addr = memory.as_mut_ptr() as u64;
+    for &(args, expected, contents) in &[
+        (&b",2:11zz"[..], &b"E01"[..], [0, 0]),
+        (b",8000000000000000:", b"E01", [0, 0]),
+        (b",2:1122", b"OK", [0x11, 0x22]),
+    ] {
+        let mut packet = Reply { buf: [0; GDB_PACKET_SIZE], len: 0 };
+        packet.push(b"M");
+        packet.push_hex(&addr.to_be_bytes());
+        packet.push(args);
+        reply.len = 0;
+        stub.dispatch(&packet.buf[..packet.len], &mut context, SIGTRAP, &mut reply);
+        assert_eq!(&reply.buf[..reply.len], expected);
+        assert_eq!(unsafe { ptr::read_volatile(&memory) }, contents);
+    }
+}
@@ -1,10 +1,112 @@
-use core::fmt;
+use core::{fmt, str};
 
-/// Type for EFI_GUID.
-#[derive(Clone, Copy, Debug)]
+use validate::ValidationError;
+
+/// Type for EFI_GUID. Formats, and parses with `parse` or `str::parse`, in the canonical form
+/// `8BE4DF61-93CA-11D2-AA0D-00E098032B8C`. Declare one from a string with `guid!`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(C)]
 pub struct Guid(pub u32, pub u16, pub u16, pub [u8; 8]);
 
+/// A GUID from its canonical string form, checked at compile time.
+///
+/// ```rust,ignore
+/// pub static MY_VENDOR_GUID: Guid = guid!("5E0B9C4A-3D71-4F2E-9A16-7C48B2E50D93");
+/// ```
+#[macro_export]
+macro_rules! guid {
+    ($s:expr) => {{
+        const GUID: $crate::Guid = match $crate::Guid::parse($s) {
+            Ok(guid) => guid,
+            Err(_) => panic!("malformed GUID"),
+        };
+        GUID
+    }};
+}
+
+/// Length of the canonical form, without braces.
+const GUID_STRING_LEN: usize = 36;
+
+/// The value of hex digit `c`.
+const fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// The value of the `len` hex digits of `s` at `start`, or the position of the first that
+/// isn't one.
+const fn hex_field(s: &[u8], start: usize, len: usize) -> Result<u64, usize> {
+    let mut value = 0u64;
+    let mut i = start;
+    while i < start + len {
+        match hex_digit(s[i]) {
+            Some(digit) => value = value << 4 | digit as u64,
+            None => return Err(i),
+        }
+        i += 1;
+    }
+    Ok(value)
+}
+
+impl Guid {
+    /// Parse the canonical form of a GUID, in either case and optionally in braces as the
+    /// registry writes them: `{8BE4DF61-93CA-11D2-AA0D-00E098032B8C}`.
+    pub const fn parse(s: &str) -> Result<Guid, ValidationError> {
+        let bytes = s.as_bytes();
+        let (start, end) = if bytes.len() == GUID_STRING_LEN + 2 && bytes[0] == b'{' && bytes[GUID_STRING_LEN + 1] == b'}' {
+            (1, GUID_STRING_LEN + 1)
+        } else {
+            (0, bytes.len())
+        };
+        if end - start != GUID_STRING_LEN {
+            let position = if end - start > GUID_STRING_LEN { start + GUID_STRING_LEN } else { end };
+            return Err(ValidationError::MalformedGuid { position });
+        }
+
+        // Each field as its offset and number of digits, the hyphens falling between them.
+        const FIELDS: [(usize, usize); 11] = [(0, 8), (9, 4), (14, 4), (19, 2), (21, 2), (24, 2), (26, 2), (28, 2), (30, 2),
+                                              (32, 2), (34, 2)];
+        let mut values = [0u64; 11];
+        let mut i = 0;
+        while i < FIELDS.len() {
+            let (offset, len) = FIELDS[i];
+            values[i] = match hex_field(bytes, start + offset, len) {
+                Ok(value) => value,
+                Err(position) => return Err(ValidationError::MalformedGuid { position }),
+            };
+            i += 1;
+        }
+        let hyphens = [8, 13, 18, 23];
+        let mut i = 0;
+        while i < hyphens.len() {
+            if bytes[start + hyphens[i]] != b'-' {
+                return Err(ValidationError::MalformedGuid { position: start + hyphens[i] });
+            }
+            i += 1;
+        }
+
+        let mut node = [0u8; 8];
+        let mut i = 0;
+        while i < 8 {
+            node[i] = values[3 + i] as u8;
+            i += 1;
+        }
+        Ok(Guid(values[0] as u32, values[1] as u16, values[2] as u16, node))
+    }
+}
+
+impl str::FromStr for Guid {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Guid, ValidationError> {
+        Guid::parse(s)
+    }
+}
+
 impl fmt::Display for Guid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}", self.0,
@@ -15,3 +117,41 @@ impl fmt::Display for Guid {
     }
 }
 
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// GUIDs serialize as their canonical string form.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for Guid {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[test]
+fn guid_strings() {
+    let guid = Guid(0x8BE4DF61, 0x93CA, 0x11D2, [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C]);
+    assert_eq!(Guid::parse("8BE4DF61-93CA-11D2-AA0D-00E098032B8C"), Ok(guid));
+    assert_eq!("{8be4df61-93ca-11d2-aa0d-00e098032b8c}".parse(), Ok(guid));
+    assert_eq!(guid!("8BE4DF61-93CA-11D2-AA0D-00E098032B8C"), guid);
+
+    struct Buf([u8; 64], usize);
+    impl fmt::Write for Buf {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0[self.1..self.1 + s.len()].copy_from_slice(s.as_bytes());
+            self.1 += s.len();
+            Ok(())
+        }
+    }
+    let mut text = Buf([0; 64], 0);
+    fmt::write(&mut text, format_args!("{:?}", guid)).unwrap();
+    assert_eq!(&text.0[..text.1], b"8BE4DF61-93CA-11D2-AA0D-00E098032B8C");
+
+    assert_eq!(Guid::parse("8BE4DF61-93CA-11D2-AA0D-00E098032B8"), Err(ValidationError::MalformedGuid { position: 35 }));
+    assert_eq!(Guid::parse("8BE4DF61-93CA-11D2-AA0D-00E098032B8C0"), Err(ValidationError::MalformedGuid { position: 36 }));
+    assert_eq!(Guid::parse("8BE4DF61-93CA-11D2-AA0DX00E098032B8C"), Err(ValidationError::MalformedGuid { position: 23 }));
+    assert_eq!(Guid::parse("8BE4DF61-93CA-11G2-AA0D-00E098032B8C"), Err(ValidationError::MalformedGuid { position: 16 }));
+}
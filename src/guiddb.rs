@@ -0,0 +1,141 @@
+//! Names of well-known GUIDs, for diagnostic tools printing handle databases, configuration
+//! tables or variables. Enabled with the `guiddb` feature, since the table adds to binary size.
+
+use core::fmt;
+
+use bbs::EFI_LEGACY_BIOS_PROTOCOL_GUID;
+use acpi::{EFI_ACPI_20_TABLE_GUID, ACPI_TABLE_GUID};
+use esrt::EFI_SYSTEM_RESOURCE_TABLE_GUID;
+use guid::Guid;
+use mat::EFI_MEMORY_ATTRIBUTES_TABLE_GUID;
+use protocol::*;
+use smbios::{SMBIOS_TABLE_GUID, SMBIOS3_TABLE_GUID};
+use runtimeservices::{EFI_GLOBAL_VARIABLE_GUID, EFI_RT_PROPERTIES_TABLE_GUID};
+
+static GUIDS: &[(&Guid, &str)] = &[
+    // Protocols bound by this crate.
+    (&EFI_LOADED_IMAGE_PROTOCOL_GUID, "EFI_LOADED_IMAGE_PROTOCOL"),
+    (&EFI_DEVICE_PATH_PROTOCOL_GUID, "EFI_DEVICE_PATH_PROTOCOL"),
+    (&EFI_DEVICE_PATH_TO_TEXT_PROTOCOL_GUID, "EFI_DEVICE_PATH_TO_TEXT_PROTOCOL"),
+    (&EFI_DEVICE_PATH_FROM_TEXT_PROTOCOL_GUID, "EFI_DEVICE_PATH_FROM_TEXT_PROTOCOL"),
+    (&EFI_DEVICE_PATH_UTILITIES_PROTOCOL_GUID, "EFI_DEVICE_PATH_UTILITIES_PROTOCOL"),
+    (&EFI_SERIAL_IO_PROTOCOL_GUID, "EFI_SERIAL_IO_PROTOCOL"),
+    (&EFI_SHELL_PARAMETERS_PROTOCOL_GUID, "EFI_SHELL_PARAMETERS_PROTOCOL"),
+    (&EFI_HII_STRING_PROTOCOL_GUID, "EFI_HII_STRING_PROTOCOL"),
+    (&EFI_TCG2_PROTOCOL_GUID, "EFI_TCG2_PROTOCOL"),
+    (&EFI_MM_COMMUNICATION2_PROTOCOL_GUID, "EFI_MM_COMMUNICATION2_PROTOCOL"),
+    (&EFI_DEBUG_SUPPORT_PROTOCOL_GUID, "EFI_DEBUG_SUPPORT_PROTOCOL"),
+    (&EFI_DECOMPRESS_PROTOCOL_GUID, "EFI_DECOMPRESS_PROTOCOL"),
+    (&EFI_FIRMWARE_VOLUME2_PROTOCOL_GUID, "EFI_FIRMWARE_VOLUME2_PROTOCOL"),
+    (&EFI_FIRMWARE_VOLUME_BLOCK2_PROTOCOL_GUID, "EFI_FIRMWARE_VOLUME_BLOCK2_PROTOCOL"),
+    (&EFI_LEGACY_BIOS_PROTOCOL_GUID, "EFI_LEGACY_BIOS_PROTOCOL"),
+    (&EFI_SPI_NOR_FLASH_PROTOCOL_GUID, "EFI_SPI_NOR_FLASH_PROTOCOL"),
+    (&EFI_I2C_IO_PROTOCOL_GUID, "EFI_I2C_IO_PROTOCOL"),
+    (&EFI_I2C_MASTER_PROTOCOL_GUID, "EFI_I2C_MASTER_PROTOCOL"),
+    (&EMBEDDED_GPIO_PROTOCOL_GUID, "EMBEDDED_GPIO"),
+    (&EFI_SD_MMC_PASS_THRU_PROTOCOL_GUID, "EFI_SD_MMC_PASS_THRU_PROTOCOL"),
+    (&EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID, "EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL"),
+    (&EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID, "EFI_STORAGE_SECURITY_COMMAND_PROTOCOL"),
+    (&EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL_GUID, "EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL"),
+    (&EFI_SUPPLICANT_PROTOCOL_GUID, "EFI_SUPPLICANT_PROTOCOL"),
+    (&EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID, "EFI_GRAPHICS_OUTPUT_PROTOCOL"),
+    (&EFI_CONSOLE_CONTROL_PROTOCOL_GUID, "EFI_CONSOLE_CONTROL_PROTOCOL"),
+    (&EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID, "EFI_SIMPLE_FILE_SYSTEM_PROTOCOL"),
+    (&EFI_BLOCK_IO2_PROTOCOL_GUID, "EFI_BLOCK_IO2_PROTOCOL"),
+    (&EFI_HTTP_SERVICE_BINDING_PROTOCOL_GUID, "EFI_HTTP_SERVICE_BINDING_PROTOCOL"),
+    (&EFI_HTTP_PROTOCOL_GUID, "EFI_HTTP_PROTOCOL"),
+    (&EFI_USBFN_IO_PROTOCOL_GUID, "EFI_USBFN_IO_PROTOCOL"),
+    (&EFI_ADAPTER_INFORMATION_PROTOCOL_GUID, "EFI_ADAPTER_INFORMATION_PROTOCOL"),
+    (&EFI_ADAPTER_INFO_MEDIA_STATE_GUID, "EFI_ADAPTER_INFO_MEDIA_STATE"),
+    (&EFI_ADAPTER_INFO_NETWORK_BOOT_GUID, "EFI_ADAPTER_INFO_NETWORK_BOOT"),
+    (&EFI_ADAPTER_INFO_SAN_MAC_ADDRESS_GUID, "EFI_ADAPTER_INFO_SAN_MAC_ADDRESS"),
+    (&EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT_GUID, "EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT"),
+    (&EFI_ADAPTER_INFO_MEDIA_TYPE_GUID, "EFI_ADAPTER_INFO_MEDIA_TYPE"),
+    (&EFI_RNG_PROTOCOL_GUID, "EFI_RNG_PROTOCOL"),
+    (&EFI_RNG_ALGORITHM_RAW, "EFI_RNG_ALGORITHM_RAW"),
+    (&EFI_RNG_ALGORITHM_SP800_90_CTR_256_GUID, "EFI_RNG_ALGORITHM_SP800_90_CTR_256"),
+
+    // Other protocols.
+    (&Guid(0x387477C1, 0x69C7, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]), "EFI_SIMPLE_TEXT_INPUT_PROTOCOL"),
+    (&Guid(0xDD9E7534, 0x7762, 0x4698, [0x8C, 0x14, 0xF5, 0x85, 0x17, 0xA6, 0x25, 0xAA]), "EFI_SIMPLE_TEXT_INPUT_EX_PROTOCOL"),
+    (&Guid(0x387477C2, 0x69C7, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]), "EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL"),
+    (&EFI_BLOCK_IO_PROTOCOL_GUID, "EFI_BLOCK_IO_PROTOCOL"),
+    (&Guid(0xCE345171, 0xBA0B, 0x11D2, [0x8E, 0x4F, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]), "EFI_DISK_IO_PROTOCOL"),
+    (&EFI_PARTITION_INFO_PROTOCOL_GUID, "EFI_PARTITION_INFO_PROTOCOL"),
+    (&Guid(0xBC62157E, 0x3E33, 0x4FEC, [0x99, 0x20, 0x2D, 0x3B, 0x36, 0xD7, 0x50, 0xDF]), "EFI_LOADED_IMAGE_DEVICE_PATH_PROTOCOL"),
+    (&Guid(0x4CF5B200, 0x68B8, 0x4CA5, [0x9E, 0xEC, 0xB2, 0x3E, 0x3F, 0x50, 0x02, 0x9A]), "EFI_PCI_IO_PROTOCOL"),
+    (&Guid(0x2B2F68D6, 0x0CD2, 0x44CF, [0x8E, 0x8B, 0xBB, 0xA2, 0x0B, 0x1B, 0x5B, 0x75]), "EFI_USB_IO_PROTOCOL"),
+    (&Guid(0xA19832B9, 0xAC25, 0x11D3, [0x9A, 0x2D, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]), "EFI_SIMPLE_NETWORK_PROTOCOL"),
+    (&Guid(0x03C4E603, 0xAC28, 0x11D3, [0x9A, 0x2D, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]), "EFI_PXE_BASE_CODE_PROTOCOL"),
+    (&EFI_DRIVER_BINDING_PROTOCOL_GUID, "EFI_DRIVER_BINDING_PROTOCOL"),
+    (&EFI_COMPONENT_NAME2_PROTOCOL_GUID, "EFI_COMPONENT_NAME2_PROTOCOL"),
+    (&Guid(0xEF9FC172, 0xA1B2, 0x4693, [0xB3, 0x27, 0x6D, 0x32, 0xFC, 0x41, 0x60, 0x42]), "EFI_HII_DATABASE_PROTOCOL"),
+    (&Guid(0x6302D008, 0x7F9B, 0x4F30, [0x87, 0xAC, 0x60, 0xC9, 0xFE, 0xF5, 0xDA, 0x4E]), "EFI_SHELL_PROTOCOL"),
+    (&Guid(0x91BD12FE, 0xF6C3, 0x44FB, [0xA5, 0xB7, 0x51, 0x22, 0xAB, 0x30, 0x3A, 0xE0]), "APPLE_DEVICE_PROPERTIES_PROTOCOL"),
+    (&Guid(0xC5C5DA95, 0x7D5C, 0x45E6, [0xB2, 0xF1, 0x3F, 0xD5, 0x2B, 0xB1, 0x00, 0x77]), "APPLE_SET_OS_PROTOCOL"),
+
+    // Configuration tables.
+    (&EFI_SYSTEM_RESOURCE_TABLE_GUID, "EFI_SYSTEM_RESOURCE_TABLE"),
+    (&EFI_MEMORY_ATTRIBUTES_TABLE_GUID, "EFI_MEMORY_ATTRIBUTES_TABLE"),
+    (&EFI_RT_PROPERTIES_TABLE_GUID, "EFI_RT_PROPERTIES_TABLE"),
+    (&EFI_ACPI_20_TABLE_GUID, "EFI_ACPI_20_TABLE"),
+    (&ACPI_TABLE_GUID, "ACPI_TABLE"),
+    (&SMBIOS_TABLE_GUID, "SMBIOS_TABLE"),
+    (&SMBIOS3_TABLE_GUID, "SMBIOS3_TABLE"),
+    (&Guid(0x05AD34BA, 0x6F02, 0x4214, [0x95, 0x2E, 0x4D, 0xA0, 0x39, 0x8E, 0x2B, 0xB9]), "DXE_SERVICES_TABLE"),
+    (&Guid(0x7739F24C, 0x93D7, 0x11D4, [0x9A, 0x3A, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]), "HOB_LIST"),
+    (&Guid(0x49152E77, 0x1ADA, 0x4764, [0xB7, 0xA2, 0x7A, 0xFE, 0xFE, 0xD9, 0x5E, 0x8B]), "EFI_DEBUG_IMAGE_INFO_TABLE"),
+    (&Guid(0x1E2ED096, 0x30E2, 0x4254, [0xBD, 0x89, 0x86, 0x3B, 0xBE, 0xF8, 0x23, 0x25]), "EFI_TCG2_FINAL_EVENTS_TABLE"),
+
+    // Variable namespaces and partition types.
+    (&EFI_GLOBAL_VARIABLE_GUID, "EFI_GLOBAL_VARIABLE"),
+    (&Guid(0xD719B2CB, 0x3D3A, 0x4596, [0xA3, 0xBC, 0xDA, 0xD0, 0x0E, 0x67, 0x65, 0x6F]), "EFI_IMAGE_SECURITY_DATABASE"),
+    (&EFI_SYSTEM_PARTITION_GUID, "EFI_SYSTEM_PARTITION"),
+    (&Guid(0x7C436110, 0xAB2A, 0x4BBB, [0xA8, 0x80, 0xFE, 0x41, 0x99, 0x5C, 0x9F, 0x82]), "APPLE_BOOT_VARIABLE"),
+    (&Guid(0x4D1EDE05, 0x38C7, 0x4A6A, [0x9C, 0xC6, 0x4B, 0xCC, 0xA8, 0xB3, 0x8C, 0x14]), "APPLE_VENDOR_VARIABLE"),
+    (&Guid(0x7C3457EF, 0x0000, 0x11AA, [0xAA, 0x11, 0x00, 0x30, 0x65, 0x43, 0xEC, 0xAC]), "APFS_PARTITION"),
+    (&Guid(0x48465300, 0x0000, 0x11AA, [0xAA, 0x11, 0x00, 0x30, 0x65, 0x43, 0xEC, 0xAC]), "HFS_PLUS_PARTITION"),
+    (&Guid(0x426F6F74, 0x0000, 0x11AA, [0xAA, 0x11, 0x00, 0x30, 0x65, 0x43, 0xEC, 0xAC]), "APPLE_BOOT_PARTITION"),
+
+    // File information types.
+    (&EFI_FILE_INFO_ID, "EFI_FILE_INFO"),
+    (&EFI_FILE_SYSTEM_INFO_ID, "EFI_FILE_SYSTEM_INFO"),
+    (&EFI_FILE_SYSTEM_VOLUME_LABEL_ID, "EFI_FILE_SYSTEM_VOLUME_LABEL"),
+];
+
+/// All known GUIDs with their names.
+pub fn all() -> &'static [(&'static Guid, &'static str)] {
+    GUIDS
+}
+
+/// The name of `guid`, such as "EFI_SIMPLE_FILE_SYSTEM_PROTOCOL".
+pub fn name_of(guid: &Guid) -> Option<&'static str> {
+    GUIDS.iter().find(|&&(g, _)| g == guid).map(|&(_, name)| name)
+}
+
+/// The GUID called `name`. A trailing `_GUID`, as in the specification's macro names, is
+/// accepted.
+pub fn guid_of(name: &str) -> Option<&'static Guid> {
+    let name = name.strip_suffix("_GUID").unwrap_or(name);
+    GUIDS.iter().find(|&&(_, n)| n == name).map(|&(guid, _)| guid)
+}
+
+/// Displays a GUID by name if it is known, or in its usual hex form otherwise.
+pub struct Named<'a>(pub &'a Guid);
+
+impl<'a> fmt::Display for Named<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match name_of(self.0) {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+#[test]
+fn lookup() {
+    let sfs = guid_of("EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID").unwrap();
+    assert_eq!(name_of(sfs), Some("EFI_SIMPLE_FILE_SYSTEM_PROTOCOL"));
+    assert_eq!(name_of(&EFI_TCG2_PROTOCOL_GUID), Some("EFI_TCG2_PROTOCOL"));
+    assert_eq!(guid_of("NO_SUCH_PROTOCOL"), None);
+}
@@ -0,0 +1,91 @@
+//! embedded-hal trait implementations, so drivers for I2C devices and GPIO-driven parts
+//! written against embedded-hal can be used from UEFI applications. Enabled with the
+//! `embedded-hal` feature.
+
+use core::array;
+
+use embedded_hal::{digital, i2c};
+
+use base::{Status, status_to_result};
+use devicelock::lock_device;
+use protocol::{GpioPin, I2cMasterProtocol, I2cOperation, MAX_I2C_OPERATIONS};
+
+impl i2c::Error for Status {
+    fn kind(&self) -> i2c::ErrorKind {
+        match *self {
+            Status::NoResponse => i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Unknown),
+            Status::DeviceError => i2c::ErrorKind::Bus,
+            _ => i2c::ErrorKind::Other,
+        }
+    }
+}
+
+impl digital::Error for Status {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+/// An I2C bus, as an embedded-hal `I2c` implementation over `I2cMasterProtocol`. Transactions
+/// are limited to `MAX_I2C_OPERATIONS` operations, and fail with `Status::AccessDenied` if
+/// another transaction on the same controller is in progress, as from an event callback.
+pub struct I2cBus<'a> {
+    master: &'a I2cMasterProtocol,
+}
+
+impl<'a> I2cBus<'a> {
+    pub fn new(master: &'a I2cMasterProtocol) -> I2cBus<'a> {
+        I2cBus { master }
+    }
+}
+
+impl<'a> i2c::ErrorType for I2cBus<'a> {
+    type Error = Status;
+}
+
+impl<'a> i2c::I2c for I2cBus<'a> {
+    fn transaction(&mut self, address: u8, operations: &mut [i2c::Operation<'_>]) -> Result<(), Status> {
+        if operations.len() > MAX_I2C_OPERATIONS {
+            return Err(Status::InvalidParameter);
+        }
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        let count = operations.len();
+        let mut ops: [I2cOperation; MAX_I2C_OPERATIONS] = array::from_fn(|_| I2cOperation::empty());
+        for (slot, op) in ops.iter_mut().zip(operations.iter_mut()) {
+            *slot = match *op {
+                i2c::Operation::Read(ref mut buf) => I2cOperation::read(buf),
+                i2c::Operation::Write(buf) => I2cOperation::write(buf),
+            };
+        }
+
+        let _guard = lock_device(self.master)?;
+        self.master.execute(address as u16, &mut ops[..count])
+    }
+}
+
+impl<'a> digital::ErrorType for GpioPin<'a> {
+    type Error = Status;
+}
+
+impl<'a> digital::OutputPin for GpioPin<'a> {
+    fn set_low(&mut self) -> Result<(), Status> {
+        status_to_result(GpioPin::set_low(self))
+    }
+
+    fn set_high(&mut self) -> Result<(), Status> {
+        status_to_result(GpioPin::set_high(self))
+    }
+}
+
+impl<'a> digital::InputPin for GpioPin<'a> {
+    fn is_high(&mut self) -> Result<bool, Status> {
+        GpioPin::is_high(self)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Status> {
+        GpioPin::is_high(self).map(|high| !high)
+    }
+}
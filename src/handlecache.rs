@@ -0,0 +1,183 @@
+use core::cell::Cell;
+use core::{mem, ptr, slice};
+
+use arena::Arena;
+use base::{Event, Handle, Handles, Status};
+use guid::Guid;
+
+/// Most distinct protocols a `HandleCache` watches for new installations.
+pub const MAX_WATCHED_PROTOCOLS: usize = 256;
+
+/// A snapshot of the handle database: every handle and the protocols installed on it, for
+/// tools that query it repeatedly. Each query on the live database is a locate or
+/// ProtocolsPerHandle call, which is slow on machines with hundreds of handles.
+///
+/// The cache registers for notification of every protocol in the snapshot and becomes stale
+/// when one is installed again, which is how drivers connecting or new devices show up.
+/// Uninstalls are not notified, so handles may have gone away even when it is not stale.
+///
+/// ```rust,ignore
+/// let mut cache = HandleCache::new()?;
+/// for handle in cache.handles_with(&EFI_BLOCK_IO_PROTOCOL_GUID) {
+///     show(handle, cache.protocols(handle));
+/// }
+/// cache.refresh_if_stale()?;
+/// ```
+pub struct HandleCache {
+    handles: Handles,
+    /// For each handle, the start and length of its protocols in the arena.
+    protocols: *mut (*const Guid, usize),
+    arena: Arena,
+    changed: Event,
+    stale: Cell<bool>,
+}
+
+impl HandleCache {
+    /// Take a snapshot of the handle database.
+    pub fn new() -> Result<HandleCache, Status> {
+        let bs = ::get_system_table().boot_services();
+        let changed = bs.create_timer_event()?;
+        let mut cache = HandleCache {
+            handles: Handles::new(ptr::null(), 0),
+            protocols: ptr::null_mut(),
+            arena: Arena::new(),
+            changed,
+            stale: Cell::new(false),
+        };
+        cache.snapshot()?;
+        Ok(cache)
+    }
+
+    /// Fill the empty cache from the handle database. On failure the cache is left empty, and
+    /// stale so that `refresh_if_stale` tries again.
+    fn snapshot(&mut self) -> Result<(), Status> {
+        let result = self.fill();
+        if result.is_err() {
+            self.clear();
+            self.stale.set(true);
+        }
+        result
+    }
+
+    fn fill(&mut self) -> Result<(), Status> {
+        let bs = ::get_system_table().boot_services();
+        let handles = bs.locate_all_handles()?;
+        let count = handles.as_slice().len();
+        self.protocols = bs.allocate_pool(count * mem::size_of::<(*const Guid, usize)>())?;
+        // Only now that every handle has an entry, even if filling them in fails.
+        self.handles = handles;
+        for i in 0..count {
+            unsafe { self.protocols.add(i).write((ptr::null(), 0)) };
+        }
+
+        let mut watched = [Guid(0, 0, 0, [0; 8]); MAX_WATCHED_PROTOCOLS];
+        let mut nwatched = 0;
+        for (i, &handle) in self.handles.as_slice().iter().enumerate() {
+            // A handle removed since it was located has no protocols.
+            let copied = match bs.protocols_per_handle(handle) {
+                Ok(guids) => {
+                    let guids = guids.as_slice();
+                    let bytes = self.arena.alloc_bytes(guids.len() * mem::size_of::<Guid>(), mem::align_of::<Guid>())?;
+                    let copied = unsafe { slice::from_raw_parts_mut(bytes.as_mut_ptr() as *mut Guid, guids.len()) };
+                    for (out, guid) in copied.iter_mut().zip(guids) {
+                        *out = **guid;
+                    }
+                    &*copied
+                }
+                Err(_) => &[],
+            };
+            unsafe { self.protocols.add(i).write((copied.as_ptr(), copied.len())) };
+
+            for guid in copied {
+                if nwatched < MAX_WATCHED_PROTOCOLS && !watched[..nwatched].contains(guid) {
+                    bs.register_protocol_notify(guid, self.changed)?;
+                    watched[nwatched] = *guid;
+                    nwatched += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a protocol has been installed since the snapshot was taken.
+    pub fn is_stale(&self) -> bool {
+        if !self.stale.get() && ::get_system_table().boot_services().check_event(self.changed) == Status::Success {
+            self.stale.set(true);
+        }
+        self.stale.get()
+    }
+
+    /// Take a new snapshot.
+    pub fn refresh(&mut self) -> Result<(), Status> {
+        let bs = ::get_system_table().boot_services();
+        // Closing the event drops its notify registrations.
+        bs.close_event(self.changed);
+        self.changed = bs.create_timer_event()?;
+        self.stale.set(false);
+        self.clear();
+        self.snapshot()
+    }
+
+    fn clear(&mut self) {
+        if !self.protocols.is_null() {
+            ::get_system_table().boot_services().free_pool(self.protocols);
+            self.protocols = ptr::null_mut();
+        }
+        self.handles = Handles::new(ptr::null(), 0);
+        self.arena.reset();
+    }
+
+    /// Take a new snapshot if the cache is stale, returning whether it was.
+    pub fn refresh_if_stale(&mut self) -> Result<bool, Status> {
+        if !self.is_stale() {
+            return Ok(false);
+        }
+        self.refresh()?;
+        Ok(true)
+    }
+
+    /// Every handle, in the order the firmware returned them.
+    pub fn handles(&self) -> &[Handle] {
+        self.handles.as_slice()
+    }
+
+    /// The protocols installed on `handle` when the snapshot was taken. Unknown handles have
+    /// none.
+    pub fn protocols(&self, handle: Handle) -> &[Guid] {
+        match self.handles().iter().position(|&h| h == handle) {
+            Some(i) => self.protocols_at(i),
+            None => &[],
+        }
+    }
+
+    fn protocols_at(&self, i: usize) -> &[Guid] {
+        unsafe {
+            let (p, len) = *self.protocols.add(i);
+            if len == 0 { &[] } else { slice::from_raw_parts(p, len) }
+        }
+    }
+
+    /// Whether `handle` had the protocol `guid` installed.
+    pub fn supports(&self, handle: Handle, guid: &Guid) -> bool {
+        self.protocols(handle).contains(guid)
+    }
+
+    /// The handles with the protocol `guid` installed.
+    pub fn handles_with<'a>(&'a self, guid: &'a Guid) -> impl Iterator<Item = Handle> + 'a {
+        self.handles()
+            .iter()
+            .enumerate()
+            .filter(move |&(i, _)| self.protocols_at(i).contains(guid))
+            .map(|(_, &h)| h)
+    }
+}
+
+impl Drop for HandleCache {
+    fn drop(&mut self) {
+        let bs = ::get_system_table().boot_services();
+        bs.close_event(self.changed);
+        if !self.protocols.is_null() {
+            bs.free_pool(self.protocols);
+        }
+    }
+}
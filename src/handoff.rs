@@ -0,0 +1,271 @@
+//! A snapshot of what a kernel needs from the firmware, taken as boot services are exited.
+//!
+//! `Handoff` is a `repr(C)` structure with a fixed layout, so a kernel written in any language
+//! can read it. All addresses are physical and all fields little-endian:
+//!
+//! | Offset | Size | Field |
+//! |--------|------|-------|
+//! | 0      | 8    | `magic`, `HANDOFF_MAGIC` |
+//! | 8      | 4    | `version`, `HANDOFF_VERSION` |
+//! | 12     | 4    | `size` of the structure in bytes |
+//! | 16     | 8    | `memory_map`, the final memory map |
+//! | 24     | 8    | `memory_map_size` in bytes |
+//! | 32     | 8    | `memory_descriptor_size` |
+//! | 40     | 4    | `memory_descriptor_version` |
+//! | 44     | 4    | reserved, zero |
+//! | 48     | 8    | `acpi_rsdp`, the ACPI 2.0 RSDP, or the 1.0 one, or 0 |
+//! | 56     | 8    | `smbios`, the SMBIOS 2.x entry point, or 0 |
+//! | 64     | 8    | `smbios3`, the SMBIOS 3.x entry point, or 0 |
+//! | 72     | 8    | `runtime_services`, the runtime services table |
+//! | 80     | 8    | `system_table`, the EFI system table |
+//! | 88     | 8    | `cmdline`, null-terminated UTF-8, or 0 |
+//! | 96     | 8    | `cmdline_len` in bytes, without the terminator |
+//! | 104    | 8    | `initrd_base`, or 0 |
+//! | 112    | 8    | `initrd_size` in bytes |
+//! | 120    | 48   | `framebuffer`, a `HandoffFramebuffer`; `base` is 0 if there is none |
+//!
+//! New fields are only added at the end, with a new version; `size` lets a kernel skip fields
+//! it does not know.
+
+use core::{mem, ptr, slice};
+
+use base::{Handle, MemoryDescriptor, MemoryType, Status};
+use bootservices::AllocateType;
+use guid::Guid;
+use memmap::{EFI_PAGE_SIZE, MEMORY_MAP_SLACK};
+use protocol::GraphicsOutputProtocol;
+use transcript::flush_transcript;
+use acpi::{ACPI_TABLE_GUID, EFI_ACPI_20_TABLE_GUID};
+use smbios::{SMBIOS_TABLE_GUID, SMBIOS3_TABLE_GUID};
+
+/// `Handoff::magic`, "EFIHNDOF" read as a little-endian integer.
+pub const HANDOFF_MAGIC: u64 = 0x464F_444E_4849_4645;
+
+/// `Handoff::version` of the layout described in this module.
+pub const HANDOFF_VERSION: u32 = 1;
+
+/// The framebuffer of the graphics output device, for a kernel to draw to directly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct HandoffFramebuffer {
+    pub base: u64,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Pixels, not bytes, from the start of one row to the next.
+    pub pixels_per_scan_line: u32,
+    /// A `PixelFormat`: 0 is RGB and 1 BGR with 8 bits each, 2 uses the masks.
+    pub pixel_format: u32,
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub reserved_mask: u32,
+}
+
+impl HandoffFramebuffer {
+    /// The framebuffer of `gop`'s current mode, or `None` if the mode has no framebuffer.
+    pub fn from_gop(gop: &GraphicsOutputProtocol) -> Option<HandoffFramebuffer> {
+        let framebuffer = gop.framebuffer()?;
+        let (width, height) = framebuffer.resolution();
+        let masks = framebuffer.pixel_information();
+
+        Some(HandoffFramebuffer {
+            base: framebuffer.base(),
+            size: framebuffer.size() as u64,
+            width: width as u32,
+            height: height as u32,
+            pixels_per_scan_line: framebuffer.pixels_per_scan_line() as u32,
+            pixel_format: framebuffer.pixel_format() as u32,
+            red_mask: masks.red_mask,
+            green_mask: masks.green_mask,
+            blue_mask: masks.blue_mask,
+            reserved_mask: masks.reserved_mask,
+        })
+    }
+}
+
+/// What a kernel needs from the firmware once boot services are gone. See the module
+/// documentation for the layout.
+#[derive(Debug)]
+#[repr(C)]
+pub struct Handoff {
+    pub magic: u64,
+    pub version: u32,
+    pub size: u32,
+    pub memory_map: u64,
+    pub memory_map_size: u64,
+    pub memory_descriptor_size: u64,
+    pub memory_descriptor_version: u32,
+    reserved: u32,
+    pub acpi_rsdp: u64,
+    pub smbios: u64,
+    pub smbios3: u64,
+    pub runtime_services: u64,
+    pub system_table: u64,
+    pub cmdline: u64,
+    pub cmdline_len: u64,
+    pub initrd_base: u64,
+    pub initrd_size: u64,
+    pub framebuffer: HandoffFramebuffer,
+}
+
+impl Handoff {
+    /// The memory map as the firmware left it when boot services were exited.
+    pub fn memory_map(&self) -> impl Iterator<Item = &MemoryDescriptor> {
+        let (base, size) = (self.memory_map as usize, self.memory_descriptor_size as usize);
+        let count = (self.memory_map_size as usize).checked_div(size).unwrap_or(0);
+        (0..count).map(move |i| unsafe { &*((base + i * size) as *const MemoryDescriptor) })
+    }
+
+    /// The command line, without its terminator.
+    pub fn cmdline(&self) -> &[u8] {
+        if self.cmdline == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.cmdline as usize as *const u8, self.cmdline_len as usize) }
+    }
+}
+
+/// Collects what goes into a `Handoff` and exits boot services, in that order.
+///
+/// ```rust,ignore
+/// let handoff = HandoffBuilder::new()
+///     .cmdline("console=ttyS0 root=/dev/nvme0n1p2")
+///     .initrd(initrd_base, initrd_size)
+///     .exit_boot_services(image_handle)?;
+/// jump_to_kernel(kernel_entry, handoff);
+/// ```
+pub struct HandoffBuilder<'a> {
+    cmdline: &'a str,
+    initrd: (u64, u64),
+    framebuffer: Option<HandoffFramebuffer>,
+}
+
+impl<'a> HandoffBuilder<'a> {
+    /// Start a handoff with the framebuffer of the first graphics output device, if any.
+    pub fn new() -> HandoffBuilder<'a> {
+        let bs = ::get_system_table().boot_services();
+        HandoffBuilder {
+            cmdline: "",
+            initrd: (0, 0),
+            framebuffer: bs.locate_protocol::<GraphicsOutputProtocol>(ptr::null()).ok()
+                .and_then(HandoffFramebuffer::from_gop),
+        }
+    }
+
+    pub fn cmdline(mut self, cmdline: &'a str) -> HandoffBuilder<'a> {
+        self.cmdline = cmdline;
+        self
+    }
+
+    /// The physical address and size of an initrd the kernel should use.
+    pub fn initrd(mut self, base: u64, size: u64) -> HandoffBuilder<'a> {
+        self.initrd = (base, size);
+        self
+    }
+
+    /// Use `gop`'s framebuffer instead of the first device's.
+    pub fn framebuffer(mut self, gop: &GraphicsOutputProtocol) -> HandoffBuilder<'a> {
+        self.framebuffer = HandoffFramebuffer::from_gop(gop);
+        self
+    }
+
+    /// Fill in the handoff, read the final memory map and exit boot services with it, retrying
+    /// with a fresh map if it changed in between.
+    ///
+    /// The handoff, command line and map are in one `LoaderData` allocation, which the memory
+    /// map describes, so the kernel knows not to reuse it before it is done with them. After an
+    /// error other than from ExitBootServices itself, boot services are still available.
+    pub fn exit_boot_services(self, image: Handle) -> Result<&'static Handoff, Status> {
+        let st = ::get_system_table();
+        let bs = st.boot_services();
+
+        // The file system is gone afterwards. A transcript that cannot be written is no reason
+        // not to boot.
+        let _ = flush_transcript();
+
+        let mut needed = 0;
+        match bs.get_memory_map_into(&mut [], &mut needed) {
+            Err(Status::BufferTooSmall) => {}
+            Err(e) => return Err(e),
+            Ok(_) => return Err(Status::DeviceError),
+        }
+
+        let header = mem::size_of::<Handoff>();
+        let map_offset = (header + self.cmdline.len() + 1 + 7) & !7;
+        // Allocating the pages below can split a region, so leave room for more descriptors.
+        let pages = (map_offset + needed + MEMORY_MAP_SLACK + EFI_PAGE_SIZE as usize - 1) / EFI_PAGE_SIZE as usize;
+        let base = bs.allocate_pages(AllocateType::AnyPages, MemoryType::LoaderData, pages)?;
+        let buf = unsafe { slice::from_raw_parts_mut(base as usize as *mut u8, pages * EFI_PAGE_SIZE as usize) };
+        buf.fill(0);
+
+        let cmdline = &mut buf[header..header + self.cmdline.len()];
+        cmdline.copy_from_slice(self.cmdline.as_bytes());
+        let table = |guid: &Guid| st.configuration_table(guid).map_or(0, |p| p as usize as u64);
+        let handoff = Handoff {
+            magic: HANDOFF_MAGIC,
+            version: HANDOFF_VERSION,
+            size: header as u32,
+            memory_map: base + map_offset as u64,
+            memory_map_size: 0,
+            memory_descriptor_size: 0,
+            memory_descriptor_version: 0,
+            reserved: 0,
+            acpi_rsdp: match table(&EFI_ACPI_20_TABLE_GUID) {
+                0 => table(&ACPI_TABLE_GUID),
+                rsdp => rsdp,
+            },
+            smbios: table(&SMBIOS_TABLE_GUID),
+            smbios3: table(&SMBIOS3_TABLE_GUID),
+            runtime_services: st.runtime_services() as *const _ as usize as u64,
+            system_table: st as *const _ as usize as u64,
+            cmdline: if self.cmdline.is_empty() { 0 } else { base + header as u64 },
+            cmdline_len: self.cmdline.len() as u64,
+            initrd_base: self.initrd.0,
+            initrd_size: self.initrd.1,
+            framebuffer: self.framebuffer.unwrap_or_default(),
+        };
+
+        let (head, map) = buf.split_at_mut(map_offset);
+        let handoff_ptr = head.as_mut_ptr() as *mut Handoff;
+        unsafe { ptr::write(handoff_ptr, handoff) };
+        let handoff = unsafe { &mut *handoff_ptr };
+
+        // The map key goes stale if anything allocates, including the firmware's own events, in
+        // which case ExitBootServices fails and only GetMemoryMap may be called before retrying.
+        let mut attempts = 0;
+        loop {
+            let (size, key, descriptor_size, descriptor_version) = match bs.get_memory_map_into(map, &mut needed) {
+                Ok(map) => map,
+                Err(e) => {
+                    if attempts == 0 {
+                        bs.free_pages(base, pages);
+                    }
+                    return Err(e);
+                }
+            };
+            handoff.memory_map_size = size as u64;
+            handoff.memory_descriptor_size = descriptor_size as u64;
+            handoff.memory_descriptor_version = descriptor_version;
+
+            match bs.exit_boot_services(&image, &key) {
+                Status::Success => return Ok(handoff),
+                Status::InvalidParameter if attempts < 3 => attempts += 1,
+                e => return Err(e),
+            }
+        }
+    }
+}
+
+impl<'a> Default for HandoffBuilder<'a> {
+    fn default() -> HandoffBuilder<'a> {
+        HandoffBuilder::new()
+    }
+}
+
+#[test]
+fn handoff_layout() {
+    assert_eq!(mem::size_of::<HandoffFramebuffer>(), 48);
+    assert_eq!(mem::size_of::<Handoff>(), 168);
+    assert_eq!(&HANDOFF_MAGIC.to_le_bytes(), b"EFIHNDOF");
+}
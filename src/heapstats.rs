@@ -0,0 +1,201 @@
+//! Allocation tracking for `BootServices::allocate_pool` and `allocate_pages`, to find leaks.
+//!
+//! Every pool and page allocation made through the crate is counted per memory type, and live
+//! allocations are remembered (up to `MAX_TRACKED_ALLOCATIONS`) so the ones never freed can
+//! be listed. Applications started with `efi_main!` print the leak report to stderr on exit.
+
+use core::fmt;
+
+use base::{MemoryType, PhysicalAddress};
+use memmap::EFI_PAGE_SIZE;
+use task::TplCell;
+
+/// Most live allocations whose size and address are remembered. Allocations beyond this are
+/// still counted, but cannot be listed as leaks and their size is not known when freed.
+pub const MAX_TRACKED_ALLOCATIONS: usize = 256;
+
+/// Number of memory types statistics are kept for, indexed by `MemoryType as usize`.
+pub const MEMORY_TYPE_COUNT: usize = 14;
+
+/// Counters for one memory type.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TypeStats {
+    pub allocations: usize,
+    pub frees: usize,
+    /// Bytes currently allocated, counting pages as 4 KiB each.
+    pub live_bytes: usize,
+    /// Highest `live_bytes` seen.
+    pub peak_bytes: usize,
+}
+
+impl TypeStats {
+    fn allocated(&mut self, bytes: usize) {
+        self.allocations += 1;
+        self.live_bytes += bytes;
+        self.peak_bytes = self.peak_bytes.max(self.live_bytes);
+    }
+
+    fn freed(&mut self, bytes: usize) {
+        self.frees += 1;
+        self.live_bytes = self.live_bytes.saturating_sub(bytes);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Allocation {
+    address: usize,
+    size: usize,
+    memory_type: MemoryType,
+    pages: bool,
+}
+
+/// Allocation statistics, as returned by `heap_stats`.
+#[derive(Clone, Copy)]
+pub struct HeapStats {
+    pub pool: [TypeStats; MEMORY_TYPE_COUNT],
+    pub pages: [TypeStats; MEMORY_TYPE_COUNT],
+    /// Allocations missing from the tracking table, because it was full when they were made.
+    pub untracked: usize,
+    /// Frees of memory missing from the tracking table: allocated while it was full, or by
+    /// the firmware rather than through the crate. Their type and size are unknown.
+    pub unknown_frees: usize,
+    live: [Option<Allocation>; MAX_TRACKED_ALLOCATIONS],
+}
+
+const NO_STATS: HeapStats = HeapStats {
+    pool: [TypeStats { allocations: 0, frees: 0, live_bytes: 0, peak_bytes: 0 }; MEMORY_TYPE_COUNT],
+    pages: [TypeStats { allocations: 0, frees: 0, live_bytes: 0, peak_bytes: 0 }; MEMORY_TYPE_COUNT],
+    untracked: 0,
+    unknown_frees: 0,
+    live: [None; MAX_TRACKED_ALLOCATIONS],
+};
+
+/// Updated from whatever allocates, including event callbacks.
+static STATS: TplCell<HeapStats> = TplCell::new(NO_STATS);
+
+fn type_index(memory_type: MemoryType) -> usize {
+    (memory_type as usize).min(MEMORY_TYPE_COUNT - 1)
+}
+
+impl HeapStats {
+    fn record_allocation(&mut self, allocation: Allocation) {
+        let index = type_index(allocation.memory_type);
+        if allocation.pages {
+            self.pages[index].allocated(allocation.size);
+        } else {
+            self.pool[index].allocated(allocation.size);
+        }
+
+        match self.live.iter_mut().find(|a| a.is_none()) {
+            Some(slot) => *slot = Some(allocation),
+            None => self.untracked += 1,
+        }
+    }
+
+    fn record_free(&mut self, address: usize, pages: bool) {
+        let slot = self.live.iter_mut().find(|a| a.is_some_and(|a| a.address == address && a.pages == pages));
+        match slot.and_then(|slot| slot.take()) {
+            Some(allocation) if pages => self.pages[type_index(allocation.memory_type)].freed(allocation.size),
+            Some(allocation) => self.pool[type_index(allocation.memory_type)].freed(allocation.size),
+            None => self.unknown_frees += 1,
+        }
+    }
+}
+
+fn record_allocation(allocation: Allocation) {
+    STATS.with(|stats| stats.record_allocation(allocation));
+}
+
+fn record_free(address: usize, pages: bool) {
+    STATS.with(|stats| stats.record_free(address, pages));
+}
+
+pub(crate) fn record_pool_allocation(address: usize, size: usize, memory_type: MemoryType) {
+    record_allocation(Allocation { address, size, memory_type, pages: false });
+}
+
+pub(crate) fn record_pool_free(address: usize) {
+    record_free(address, false);
+}
+
+pub(crate) fn record_page_allocation(address: PhysicalAddress, pages: usize, memory_type: MemoryType) {
+    record_allocation(Allocation { address: address as usize, size: pages * EFI_PAGE_SIZE as usize, memory_type, pages: true });
+}
+
+pub(crate) fn record_page_free(address: PhysicalAddress) {
+    record_free(address as usize, true);
+}
+
+/// A snapshot of the allocation statistics.
+pub fn heap_stats() -> HeapStats {
+    STATS.with(|stats| *stats)
+}
+
+impl HeapStats {
+    /// Pool bytes currently allocated, over all memory types.
+    pub fn live_pool_bytes(&self) -> usize {
+        self.pool.iter().map(|t| t.live_bytes).sum()
+    }
+
+    /// The live tracked allocations, as (address, size in bytes, memory type, whether it is a
+    /// page allocation).
+    pub fn live_allocations(&self) -> impl Iterator<Item = (usize, usize, MemoryType, bool)> + '_ {
+        self.live.iter().flatten().map(|a| (a.address, a.size, a.memory_type, a.pages))
+    }
+
+    /// Write a summary per memory type, followed by every allocation still live.
+    pub fn report<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        writeln!(w, "heap: {} pool bytes live", self.live_pool_bytes())?;
+        for (i, (pool, pages)) in self.pool.iter().zip(self.pages.iter()).enumerate() {
+            if pool.allocations == 0 && pages.allocations == 0 {
+                continue;
+            }
+            writeln!(w, "  type {:2}: pool {}/{} freed, {} live, {} peak; pages {}/{} freed, {} live, {} peak",
+                     i, pool.frees, pool.allocations, pool.live_bytes, pool.peak_bytes,
+                     pages.frees, pages.allocations, pages.live_bytes, pages.peak_bytes)?;
+        }
+        for (address, size, memory_type, pages) in self.live_allocations() {
+            let kind = if pages { "pages" } else { "pool" };
+            writeln!(w, "  leak: {} {:#x}, {} bytes, {:?}", kind, address, size, memory_type)?;
+        }
+        if self.untracked > 0 {
+            writeln!(w, "  {} more allocations not tracked", self.untracked)?;
+        }
+        if self.unknown_frees > 0 {
+            writeln!(w, "  {} frees of untracked memory", self.unknown_frees)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn heap_stats_frees() {
+    let mut stats = NO_STATS;
+    stats.record_allocation(Allocation { address: 0x1000, size: 24, memory_type: MemoryType::LoaderData, pages: false });
+    stats.record_allocation(Allocation { address: 0x2000, size: 8192, memory_type: MemoryType::LoaderData, pages: true });
+    let index = type_index(MemoryType::LoaderData);
+    assert_eq!(stats.live_pool_bytes(), 24);
+
+    // Page and pool allocations at the same address are told apart.
+    stats.record_free(0x2000, false);
+    assert_eq!((stats.unknown_frees, stats.pages[index].frees), (1, 0));
+    stats.record_free(0x2000, true);
+    stats.record_free(0x1000, false);
+    assert_eq!((stats.pool[index].frees, stats.pool[index].live_bytes, stats.pool[index].peak_bytes), (1, 0, 24));
+    assert_eq!((stats.pages[index].frees, stats.pages[index].live_bytes), (1, 0));
+    assert_eq!(stats.live_allocations().count(), 0);
+}
+
+#[test]
+fn heap_stats_table_full() {
+    let mut stats = NO_STATS;
+    for i in 0..MAX_TRACKED_ALLOCATIONS + 2 {
+        stats.record_allocation(Allocation { address: i * 16, size: 16, memory_type: MemoryType::BootServicesData, pages: false });
+    }
+    assert_eq!(stats.untracked, 2);
+
+    // Memory the firmware allocated leaves the counts of the crate's own allocations alone.
+    stats.record_free(0xFFFF_0000, false);
+    assert_eq!((stats.untracked, stats.unknown_frees), (2, 1));
+    assert_eq!(stats.live_pool_bytes(), (MAX_TRACKED_ALLOCATIONS + 2) * 16);
+}
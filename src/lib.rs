@@ -75,6 +75,20 @@
 //!
 //! See [set_system_table] and [set_current_image].
 //!
+//! Alternatively, the `efi_main!` macro defines `efi_entry`, does this initialization, and calls a
+//! `main` function which may return `Result<(), Status>`:
+//!
+//! ```rust,ignore
+//! fn main() -> Result<(), uefi::Status> {
+//!     Ok(())
+//! }
+//!
+//! efi_main!(main);
+//! ```
+//!
+//! Drivers following the UEFI driver model implement `protocol::Driver` and use `driver_entry!`
+//! instead, which installs the driver's protocols and keeps the image resident.
+//!
 //! [set_system_table]: fn.set_system_table.html
 //! [set_current_image]: protocol/fn.set_current_image.html
 //!
@@ -83,34 +97,238 @@
 #![no_std]
 
 #[macro_use] extern crate bitflags;
+#[cfg(feature = "serde")]
+#[macro_use] extern crate serde;
+#[cfg(feature = "embedded-hal")]
+extern crate embedded_hal;
 
 pub mod protocol;
 mod void;
 mod base;
-mod guid;
+#[macro_use] mod guid;
 mod table;
 mod systemtable;
 mod bootservices;
 mod runtimeservices;
+#[cfg(all(feature = "psci", any(target_arch = "aarch64", target_arch = "arm")))]
+mod psci;
 mod console;
+mod scrollback;
+mod replay;
+mod draw;
+mod bufwriter;
+mod locale;
+mod stdio;
+mod args;
+mod abboot;
+mod bootscan;
+mod bootoptions;
+mod esp;
+mod loopback;
+mod bls;
+mod loaderconf;
+mod entryedit;
+mod esrt;
+mod acpi;
+mod bmp;
+mod font;
+mod download;
+mod qr;
+mod bbs;
+mod coreboot;
+mod capabilities;
+mod smbios;
+#[cfg(feature = "apple")]
+mod apple;
+mod flash;
+mod nvme;
+mod usbgadget;
+#[cfg(all(feature = "gdbstub", target_arch = "x86_64"))]
+mod gdbstub;
+#[cfg(all(feature = "port-io", any(target_arch = "x86", target_arch = "x86_64")))]
+mod portio;
+#[cfg(all(feature = "ec", any(target_arch = "x86", target_arch = "x86_64")))]
+mod ec;
+#[cfg(feature = "embedded-hal")]
+mod hal;
+mod mat;
+mod memmap;
+mod physmem;
+mod random;
+mod handoff;
+mod transcript;
+mod placement;
+mod arena;
+mod handlecache;
+mod devicelock;
+mod audit;
+mod testing;
+mod entry;
+#[cfg(feature = "heap-stats")]
+mod heapstats;
+#[cfg(feature = "panic-handler")]
+mod panic;
+mod error;
+mod validate;
+mod path;
+mod overlay;
+mod assets;
 mod task;
 mod event;
 pub mod util;
+#[cfg(feature = "guiddb")]
+pub mod guiddb;
 
 
-pub use base::{Handle, Handles, Event, MemoryType, MemoryDescriptor, Status, Time};
+pub use base::{Handle, Handles, Event, MemoryType, MemoryDescriptor, PhysicalAddress, VirtualAddress, Status, Time, status_to_result};
+pub use base::{MemoryAttribute, EFI_MEMORY_UC, EFI_MEMORY_WC, EFI_MEMORY_WT, EFI_MEMORY_WB, EFI_MEMORY_UCE, EFI_MEMORY_WP,
+               EFI_MEMORY_RP, EFI_MEMORY_XP, EFI_MEMORY_NV, EFI_MEMORY_MORE_RELIABLE, EFI_MEMORY_RO, EFI_MEMORY_SP,
+               EFI_MEMORY_CPU_CRYPTO, EFI_MEMORY_RUNTIME};
+pub use base::{EFI_UNSPECIFIED_TIMEZONE, EFI_TIME_ADJUST_DAYLIGHT, EFI_TIME_IN_DAYLIGHT};
 pub use guid::*;
 
+pub use table::ConfigurationTable;
+
 pub use systemtable::*;
 
-pub use bootservices::BootServices;
+pub use bootservices::{AllocateType, BootServices, ProtocolGuids, ProtocolInstances, ScopedProtocol, MAX_MULTIPLE_PROTOCOL_INTERFACES,
+                       EFI_OPEN_PROTOCOL_BY_HANDLE_PROTOCOL, EFI_OPEN_PROTOCOL_GET_PROTOCOL, EFI_OPEN_PROTOCOL_TEST_PROTOCOL,
+                       EFI_OPEN_PROTOCOL_BY_CHILD_CONTROLLER, EFI_OPEN_PROTOCOL_BY_DRIVER, EFI_OPEN_PROTOCOL_EXCLUSIVE};
 
-pub use runtimeservices::{ResetType, RuntimeServices};
+pub use runtimeservices::*;
 
-pub use console::{Attribute, ForegroundColor, BackgroundColor, InputKey, SimpleTextOutput, SimpleTextInput, Console};
+pub use console::{Attribute, ForegroundColor, BackgroundColor, InputKey, SimpleTextOutput, SimpleTextInput, SimpleTextOutputMode, SimpleTextInputProtocol, SimpleTextOutputProtocol, Console, ConsoleState, Countdown, DisplayMode, ConsoleMode, ModeWatcher};
 
 use core::mem;
 
+pub use scrollback::Scrollback;
+
+pub use replay::{InputRecorder, InputReplay, RecordedKey, encode_recording, decode_recording, MAX_RECORDED_KEYS,
+                 MAX_RECORDING_SIZE, RECORDING_TICK_MS};
+
+pub use bufwriter::{BufWriter, BUF_WRITER_CAPACITY};
+
+pub use draw::{BoxChars, Align, Column, Table, UNICODE_BOX_CHARS, ASCII_BOX_CHARS, draw_box};
+
+pub use args::{Args, GetOpt, Opt, OptError, OptSpec, MAX_ARGS, ARGS_BUFFER_SIZE};
+
+pub use stdio::{Stdin, Stdout, stdin, stdout, stderr};
+
+pub use abboot::{AbBoot, AbState, BootSlot, AB_STATE_VARIABLE};
+
+pub use bootscan::{BootCandidate, BootDiscovery, LoaderKind, WindowsBootManager, find_windows_boot_manager, os_release_name,
+                   EFI_ARCH, MAX_BOOT_CANDIDATES};
+
+pub use esp::{find_esp, is_esp};
+
+pub use loopback::{Loopback, FileBlockDevice, LOOPBACK_BLOCK_SIZE, LOOPBACK_VENDOR_GUID};
+
+pub use bootoptions::{BootOption, BootOptions, Description, LoadOption, add_boot_option, boot_option_name, boot_option_number,
+                      boot_options, delete_boot_option, move_boot_option, read_boot_option, write_boot_option, write_load_option,
+                      LOAD_OPTION_ACTIVE, LOAD_OPTION_CATEGORY_APP, LOAD_OPTION_FORCE_RECONNECT, LOAD_OPTION_HIDDEN,
+                      MAX_BOOT_ORDER, MAX_LOAD_OPTION_SIZE};
+
+pub use bls::{BlsEntry, BlsCmdline, MAX_BLS_INITRDS, MAX_BLS_OPTIONS};
+
+pub use loaderconf::{LoaderConf, LoaderConfig, LoaderEntry, LoaderTimeout, glob_match, version_cmp, MAX_LOADER_ENTRIES,
+                     LOADER_CONF_PATH, LOADER_ENTRIES_PATH};
+
+pub use entryedit::{EditLine, EditorAction, EntryEditor, MAX_EDIT_LINE_LEN, MAX_EDIT_LINES};
+
+pub use esrt::*;
+
+pub use acpi::{Bgrt, LogoOrientation, acpi_tables, find_acpi_table, EFI_ACPI_20_TABLE_GUID, ACPI_TABLE_GUID, ACPI_TABLE_HEADER_SIZE};
+
+pub use bmp::{BmpImage, bmp_size, write_bmp_header, BMP_HEADER_SIZE};
+
+pub use font::{Glyph, PsfFont, PsfVersion, MAX_FONT_SCALE, MAX_GLYPH_HEIGHT, MAX_GLYPH_WIDTH, MIN_TEXT_COLUMNS, MIN_TEXT_ROWS};
+
+pub use download::{fetch_verify_store, stream_verify_store, DOWNLOAD_BUFFER_SIZE, HTTP_TIMEOUT_MS};
+
+pub use qr::{QrCode, QrEcc, MAX_QR_VERSION, QR_QUIET_ZONE};
+
+pub use capabilities::{Capabilities, FirmwareImplementation};
+
+pub use smbios::{FirmwareInfo, FirmwareVendor, SmbiosTable, SmbiosStructure, SmbiosStructures, SMBIOS_TABLE_GUID, SMBIOS3_TABLE_GUID,
+                 SMBIOS_TYPE_BIOS_INFORMATION, SMBIOS_TYPE_SYSTEM_INFORMATION, SMBIOS_TYPE_END_OF_TABLE};
+
+pub use coreboot::{CorebootTable, CorebootRecords, CorebootSerial, CorebootSerialKind, COREBOOT_HEADER_SIZE, LB_TAG_MAINBOARD,
+                   LB_TAG_VERSION, LB_TAG_SERIAL, LB_TAG_FORWARD, LB_TAG_CBMEM_CONSOLE, LB_TAG_BOARD_ID, LB_TAG_RAM_CODE, LB_TAG_SKU_ID};
+
+#[cfg(feature = "apple")]
+pub use apple::{is_apple_partition_type, has_macos_loader, prepare_macos_boot, APPLE_BOOT_VARIABLE_GUID, APPLE_VENDOR_VARIABLE_GUID,
+                APFS_PARTITION_TYPE_GUID, HFS_PLUS_PARTITION_TYPE_GUID, APPLE_BOOT_PARTITION_TYPE_GUID, MACOS_BOOT_FILE,
+                MACOS_OS_VERSION, MACOS_OS_VENDOR};
+
+pub use bbs::{BbsDeviceType, BbsNode, LegacyDevOrder, LegacyDevOrderGroups, LegacyDevGroup, write_bbs_node, csm_present,
+              EFI_LEGACY_BIOS_PROTOCOL_GUID, EFI_LEGACY_DEV_ORDER_VARIABLE_GUID, LEGACY_DEV_ORDER_VARIABLE};
+
+pub use mat::*;
+
+#[cfg(feature = "embedded-hal")]
+pub use hal::I2cBus;
+
+pub use nvme::{NvmeController, IdentifyController, IdentifyNamespace, LbaFormat, SecureErase, SanitizeAction,
+               SanitizeState, NVME_IDENTIFY_SIZE};
+
+pub use usbgadget::{BulkGadget, BULK_BUFFER_SIZE, BULK_ENDPOINT};
+
+#[cfg(all(feature = "gdbstub", target_arch = "x86_64"))]
+pub use gdbstub::{GdbConnection, GdbStub, install_gdbstub, gdb_breakpoint, GDB_PACKET_SIZE, MAX_BREAKPOINTS};
+
+#[cfg(all(feature = "port-io", any(target_arch = "x86", target_arch = "x86_64")))]
+pub use portio::{inb, inw, inl, outb, outw, outl, post_code, post_code_history, POST_CODE_PORT, POST_CODE_HISTORY};
+
+#[cfg(all(feature = "ec", any(target_arch = "x86", target_arch = "x86_64")))]
+pub use ec::{EmbeddedController, EC_DATA_PORT, EC_COMMAND_PORT, EC_DEFAULT_TIMEOUT_US, EC_STATUS_OBF, EC_STATUS_IBF, EC_STATUS_CMD,
+             EC_STATUS_BURST, EC_STATUS_SCI_EVT, EC_STATUS_SMI_EVT};
+
+pub use flash::{Flash, MemoryMappedFlash, spi_flash, dump_region, hash_region};
+
+pub use memmap::{MemoryMap, MemoryMapIter, EFI_PAGE_SIZE, exit_boot_services};
+
+pub use physmem::PhysMem;
+
+pub use random::{fill_random, fill_random_firmware, random_u64, RandomQuality};
+
+pub use handoff::{Handoff, HandoffBuilder, HandoffFramebuffer, HANDOFF_MAGIC, HANDOFF_VERSION};
+
+pub use transcript::{start_transcript, transcript, flush_transcript, TRANSCRIPT_CAPACITY, TRANSCRIPT_FILE, TRANSCRIPT_KEEP};
+
+pub use placement::{PlacementAllocator, Reservation, random_placement, MAX_RESERVATIONS};
+
+pub use arena::{Arena, MAX_ARENA_CHUNKS, DEFAULT_ARENA_CHUNK_PAGES};
+
+pub use handlecache::{HandleCache, MAX_WATCHED_PROTOCOLS};
+
+pub use devicelock::{DeviceGuard, lock_device, MAX_LOCKED_DEVICES};
+
+pub use audit::{AuditReport, ImageRecord, AUDIT_MAX_IMAGES, AUDIT_MAX_FIRMWARE};
+
+pub use entry::{Termination, exit_code, at_exit, driver_init};
+
+#[cfg(feature = "heap-stats")]
+pub use heapstats::{HeapStats, TypeStats, heap_stats, MAX_TRACKED_ALLOCATIONS, MEMORY_TYPE_COUNT};
+
+#[cfg(feature = "panic-handler")]
+pub use panic::{PanicAction, panic_action, set_panic_action};
+
+pub use error::{EfiError, ResultExt, ERROR_CONTEXT_SIZE};
+
+pub use validate::{ValidationError, validate_variable_name, validate_path, normalize_path, validate_device_path_text,
+                   MAX_PATH_COMPONENT};
+
+pub use path::EfiPath;
+
+pub use overlay::{FileSource, DirSource, MemorySource, Overlay, MAX_OVERLAY_SOURCES};
+
+pub use assets::{Asset, AssetFile, Assets};
+
+pub use testing::{TestCase, TestReporter, TestSummary, run_tests, run_tests_with_timeout, assertion_failed, assertion_failure_count,
+                  TEST_TIMEOUT_SECONDS, TEST_WATCHDOG_CODE, TEST_PROGRESS_VARIABLE, TEST_PROGRESS_VARIABLE_GUID};
+
+pub use locale::{Language, Message, Translation, DEFAULT_LANGUAGE, hii_string};
+
 pub use event::*;
 
 pub use task::*;
@@ -0,0 +1,329 @@
+//! systemd-boot style configuration: `\loader\loader.conf` and the BLS entries in
+//! `\loader\entries`, read from the ESP so a boot menu built on this crate can take over an
+//! existing systemd-boot installation.
+
+use core::{cmp, str};
+
+use arena::Arena;
+use base::Status;
+use bls::BlsEntry;
+use bootscan::EFI_ARCH;
+use protocol::{FileProtocol, EFI_FILE_MODE_READ};
+
+/// Most entries `LoaderConfig::load` keeps.
+pub const MAX_LOADER_ENTRIES: usize = 64;
+
+/// Path of the main configuration file on the ESP.
+pub const LOADER_CONF_PATH: &str = "\\loader\\loader.conf";
+
+/// Directory of the entry files on the ESP.
+pub const LOADER_ENTRIES_PATH: &str = "\\loader\\entries";
+
+/// Largest configuration or entry file read.
+const MAX_CONF_FILE_SIZE: u64 = 64 * 1024;
+
+/// How long the menu waits before booting the default entry, from the `timeout` key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoaderTimeout {
+    /// Show the menu and boot the default entry after this many seconds.
+    Seconds(u32),
+    /// Show the menu and wait for a choice ("menu-force").
+    MenuForce,
+    /// Boot the default entry at once unless a key is held ("0" or "menu-hidden"). This is the
+    /// default.
+    MenuHidden,
+    /// Boot the default entry at once ("menu-disabled").
+    MenuDisabled,
+}
+
+impl LoaderTimeout {
+    fn parse(value: &str) -> Option<LoaderTimeout> {
+        match value {
+            "menu-force" => Some(LoaderTimeout::MenuForce),
+            "0" | "menu-hidden" => Some(LoaderTimeout::MenuHidden),
+            "menu-disabled" => Some(LoaderTimeout::MenuDisabled),
+            seconds => seconds.parse().ok().map(LoaderTimeout::Seconds),
+        }
+    }
+}
+
+/// The settings in `loader.conf`. Keys other than these are ignored.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoaderConf<'a> {
+    /// A glob pattern, with `*` and `?`, for the id of the entry to boot by default.
+    pub default: Option<&'a str>,
+    pub timeout: Option<LoaderTimeout>,
+    /// Whether the command line of an entry may be edited before booting.
+    pub editor: Option<bool>,
+    pub console_mode: Option<&'a str>,
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" | "yes" | "y" | "true" | "t" | "on" => Some(true),
+        "0" | "no" | "n" | "false" | "f" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl<'a> LoaderConf<'a> {
+    pub fn parse(text: &'a str) -> LoaderConf<'a> {
+        let mut conf = LoaderConf::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.find(char::is_whitespace) {
+                Some(i) => (&line[..i], line[i..].trim()),
+                None => (line, ""),
+            };
+
+            match key {
+                "default" => conf.default = Some(value),
+                "timeout" => conf.timeout = LoaderTimeout::parse(value).or(conf.timeout),
+                "editor" => conf.editor = parse_bool(value).or(conf.editor),
+                "console-mode" => conf.console_mode = Some(value),
+                _ => {}
+            }
+        }
+        conf
+    }
+}
+
+/// A BLS entry and its id, the file name without `.conf`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoaderEntry<'a> {
+    pub id: &'a str,
+    pub entry: BlsEntry<'a>,
+}
+
+/// `loader.conf` and the bootable entries for this architecture, with the text of every file
+/// held in an `Arena`. Entries are in menu order: those with a `sort-key` first, by sort key,
+/// machine id and then newest version first, followed by the rest by id, newest first.
+///
+/// ```rust,ignore
+/// let arena = Arena::new();
+/// let config = LoaderConfig::load(esp_root, &arena)?;
+/// let choice = menu.run(config.entries(), config.default_index(), config.timeout());
+/// ```
+pub struct LoaderConfig<'a> {
+    pub conf: LoaderConf<'a>,
+    entries: [LoaderEntry<'a>; MAX_LOADER_ENTRIES],
+    len: usize,
+}
+
+impl<'a> LoaderConfig<'a> {
+    /// Read the configuration from `root`, the root directory of the ESP. A missing
+    /// `loader.conf` leaves the defaults, and a missing entries directory no entries; entry
+    /// files which cannot be read or are not UTF-8 are skipped.
+    pub fn load(root: &FileProtocol, arena: &'a Arena) -> Result<LoaderConfig<'a>, Status> {
+        let conf = match read_text(root, LOADER_CONF_PATH, arena) {
+            Ok(text) => LoaderConf::parse(text),
+            Err(Status::NotFound) => LoaderConf::default(),
+            Err(e) => return Err(e),
+        };
+        let mut config = LoaderConfig { conf, entries: [LoaderEntry::default(); MAX_LOADER_ENTRIES], len: 0 };
+
+        let dir = match root.open_path(LOADER_ENTRIES_PATH, EFI_FILE_MODE_READ) {
+            Ok(dir) => dir,
+            Err(Status::NotFound) => return Ok(config),
+            Err(e) => return Err(e),
+        };
+        let result = config.read_entries(dir, arena);
+        dir.close();
+        result?;
+
+        config.entries[..config.len].sort_unstable_by(|a, b| menu_order(a, b));
+        Ok(config)
+    }
+
+    fn read_entries(&mut self, dir: &FileProtocol, arena: &'a Arena) -> Result<(), Status> {
+        let mut buf = [0u8; 1024];
+        while self.len < MAX_LOADER_ENTRIES {
+            let info = match dir.read_dir_entry(&mut buf)? {
+                Some(info) => info,
+                None => break,
+            };
+            if info.is_directory() {
+                continue;
+            }
+
+            // Names are UCS-2, so each unit is at most three bytes of UTF-8.
+            let name = arena.alloc_bytes(info.file_name().count() * 3, 1)?;
+            let mut len = 0;
+            for c in char::decode_utf16(info.file_name()) {
+                len += c.unwrap_or(char::REPLACEMENT_CHARACTER).encode_utf8(&mut name[len..]).len();
+            }
+            let name = unsafe { str::from_utf8_unchecked(&name[..len]) };
+
+            let id = match name.len().checked_sub(5) {
+                Some(stem) if name.is_char_boundary(stem) && name[stem..].eq_ignore_ascii_case(".conf") => &name[..stem],
+                _ => continue,
+            };
+            let entry = match read_text(dir, name, arena) {
+                Ok(text) => BlsEntry::parse(text),
+                Err(_) => continue,
+            };
+            if entry.is_bootable() && entry.is_for_architecture(EFI_ARCH) {
+                self.entries[self.len] = LoaderEntry { id, entry };
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[LoaderEntry<'a>] {
+        &self.entries[..self.len]
+    }
+
+    /// The index of the entry to boot by default: the first matching the `default` pattern,
+    /// or the first entry if it matches none or there is no pattern. `None` if there are no
+    /// entries.
+    pub fn default_index(&self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let pattern = self.conf.default.unwrap_or("*");
+        Some(self.entries().iter().position(|e| glob_match(pattern, e.id)).unwrap_or(0))
+    }
+
+    /// The `timeout` setting, `LoaderTimeout::MenuHidden` if there is none.
+    pub fn timeout(&self) -> LoaderTimeout {
+        self.conf.timeout.unwrap_or(LoaderTimeout::MenuHidden)
+    }
+}
+
+/// Read the whole of `path` under `dir` into `arena` as text.
+fn read_text<'a>(dir: &FileProtocol, path: &str, arena: &'a Arena) -> Result<&'a str, Status> {
+    let file = dir.open_path(path, EFI_FILE_MODE_READ)?;
+    let result = (|| {
+        let mut info = [0u8; 512];
+        let size = file.file_info(&mut info)?.file_size();
+        if size > MAX_CONF_FILE_SIZE {
+            return Err(Status::BadBufferSize);
+        }
+
+        let buf = arena.alloc_bytes(size as usize, 1)?;
+        let mut len = 0;
+        while len < buf.len() {
+            match file.read(&mut buf[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        str::from_utf8(&buf[..len]).map_err(|_| Status::InvalidParameter)
+    })();
+    file.close();
+    result
+}
+
+fn menu_order(a: &LoaderEntry, b: &LoaderEntry) -> cmp::Ordering {
+    let (x, y) = (&a.entry, &b.entry);
+    match (x.sort_key, y.sort_key) {
+        (Some(kx), Some(ky)) => kx.cmp(ky)
+            .then_with(|| x.machine_id.unwrap_or("").cmp(y.machine_id.unwrap_or("")))
+            .then_with(|| version_cmp(y.version.unwrap_or(""), x.version.unwrap_or("")))
+            .then_with(|| version_cmp(b.id, a.id)),
+        (Some(_), None) => cmp::Ordering::Less,
+        (None, Some(_)) => cmp::Ordering::Greater,
+        (None, None) => version_cmp(b.id, a.id),
+    }
+}
+
+/// Compare version strings as the UAPI version format specification does, as used for kernel
+/// versions in entry names: runs of digits compare as numbers and other runs as text, `~`
+/// sorts before anything, even the end of the string, and `-`, `^` and `.` separate parts.
+pub fn version_cmp(a: &str, b: &str) -> cmp::Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    'parts: loop {
+        // Characters which are neither alphanumeric nor separators are ignored.
+        let skip = |s: &[u8]| s.iter().position(|&c| c.is_ascii_alphanumeric() || b"~-^.".contains(&c)).unwrap_or(s.len());
+        a = &a[skip(a)..];
+        b = &b[skip(b)..];
+
+        // `~` sorts before the end of the string, which sorts before the other separators,
+        // and the side with a separator the other lacks is the older.
+        for &sep in b"~\0-^." {
+            let (sa, sb) = if sep == 0 { (a.is_empty(), b.is_empty()) } else { (a.first() == Some(&sep), b.first() == Some(&sep)) };
+            match (sa, sb) {
+                (true, true) if sep == 0 => return cmp::Ordering::Equal,
+                (true, true) => {
+                    a = &a[1..];
+                    b = &b[1..];
+                    continue 'parts;
+                }
+                (true, false) => return cmp::Ordering::Less,
+                (false, true) => return cmp::Ordering::Greater,
+                (false, false) => {}
+            }
+        }
+
+        let digits = a[0].is_ascii_digit();
+        if digits != b[0].is_ascii_digit() {
+            // Letters sort before digits.
+            return if digits { cmp::Ordering::Greater } else { cmp::Ordering::Less };
+        }
+        let run = |s: &[u8]| s.iter().position(|c| c.is_ascii_digit() != digits || !c.is_ascii_alphanumeric()).unwrap_or(s.len());
+        let (ra, rb) = (&a[..run(a)], &b[..run(b)]);
+        let order = if digits {
+            let (ta, tb) = (trim_zeros(ra), trim_zeros(rb));
+            ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb))
+        } else {
+            ra.cmp(rb)
+        };
+        if order != cmp::Ordering::Equal {
+            return order;
+        }
+        a = &a[ra.len()..];
+        b = &b[rb.len()..];
+    }
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    &digits[digits.iter().position(|&c| c != b'0').unwrap_or(digits.len())..]
+}
+
+/// Match `text` against a glob `pattern`, where `*` matches any run of characters and `?` any
+/// one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t) = (pattern.as_bytes(), text.as_bytes());
+    let (mut pi, mut ti) = (0, 0);
+    // The position after the last `*` and the text position it is currently matched up to.
+    let mut star = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == b'?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == b'*' {
+            star = Some((pi + 1, ti));
+            pi += 1;
+        } else if let Some((after, matched)) = star {
+            pi = after;
+            ti = matched + 1;
+            star = Some((after, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == b'*')
+}
+
+#[test]
+fn loaderconf_parse() {
+    let conf = LoaderConf::parse("# comment\ndefault fedora-*\ntimeout 5\neditor no\nconsole-mode max\n");
+    assert_eq!(conf.default, Some("fedora-*"));
+    assert_eq!(conf.timeout, Some(LoaderTimeout::Seconds(5)));
+    assert_eq!(conf.editor, Some(false));
+    assert_eq!(LoaderConf::parse("timeout menu-force").timeout, Some(LoaderTimeout::MenuForce));
+
+    assert!(glob_match("fedora-*", "fedora-6.8.9-300.fc40.x86_64"));
+    assert!(glob_match("*.fc4?.*", "fedora-6.8.9-300.fc40.x86_64"));
+    assert!(!glob_match("arch-*", "fedora-6.8.9"));
+
+    assert_eq!(version_cmp("6.10.1", "6.9.12"), cmp::Ordering::Greater);
+    assert_eq!(version_cmp("1.0~rc1", "1.0"), cmp::Ordering::Less);
+    assert_eq!(version_cmp("1.0", "1.0.1"), cmp::Ordering::Less);
+    assert_eq!(version_cmp("fc40-002", "fc40-2"), cmp::Ordering::Equal);
+}
@@ -0,0 +1,152 @@
+use core::{fmt, ptr, str};
+
+use base::Status;
+use protocol::{HiiHandle, HiiStringProtocol, StringId};
+use runtimeservices::EFI_GLOBAL_VARIABLE_GUID;
+
+/// Language used when PlatformLang is unset and as the last entry of every fallback chain.
+pub const DEFAULT_LANGUAGE: &str = "en-US";
+
+const MAX_LANGUAGE: usize = 32;
+
+/// An RFC 4646 language code, such as "en-US", as used by the PlatformLang variable.
+#[derive(Clone, Copy)]
+pub struct Language {
+    buf: [u8; MAX_LANGUAGE],
+    len: usize,
+}
+
+impl Language {
+    /// Create a language from its RFC 4646 code. Codes longer than 31 characters are truncated.
+    pub fn new(code: &str) -> Language {
+        let mut lang = Language { buf: [0; MAX_LANGUAGE], len: 0 };
+
+        for &c in code.as_bytes().iter().take(MAX_LANGUAGE - 1) {
+            if !c.is_ascii() || c == 0 {
+                break;
+            }
+            lang.buf[lang.len] = c;
+            lang.len += 1;
+        }
+
+        lang
+    }
+
+    /// The current platform language, read from the PlatformLang variable. Falls back to
+    /// `DEFAULT_LANGUAGE` if the variable is missing or malformed.
+    pub fn platform() -> Language {
+        let mut buf = [0u8; MAX_LANGUAGE];
+        let rs = ::get_system_table().runtime_services();
+
+        match rs.get_variable("PlatformLang", &EFI_GLOBAL_VARIABLE_GUID, &mut buf) {
+            Ok((size, _)) => {
+                let len = buf[..size].iter().position(|&c| c == 0).unwrap_or(size);
+                match str::from_utf8(&buf[..len]) {
+                    Ok(code) if !code.is_empty() => Language::new(code),
+                    _ => Language::new(DEFAULT_LANGUAGE),
+                }
+            }
+            Err(_) => Language::new(DEFAULT_LANGUAGE),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII is ever stored.
+        unsafe { str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    /// The primary language subtag, e.g. "en" for "en-US".
+    pub fn primary(&self) -> &str {
+        let code = self.as_str();
+        match code.find('-') {
+            Some(i) => &code[..i],
+            None => code,
+        }
+    }
+
+    /// The languages to try, in order, when looking up a message: the full code, the primary
+    /// subtag, then `DEFAULT_LANGUAGE` and its primary subtag.
+    pub fn fallbacks(&self) -> [&str; 4] {
+        [self.as_str(), self.primary(), DEFAULT_LANGUAGE, "en"]
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Compare RFC 4646 codes, which are case-insensitive.
+fn language_eq(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+/// One translation of a message.
+pub struct Translation {
+    pub lang: &'static str,
+    pub text: &'static str,
+}
+
+/// A translatable message. Declare these with the `message!` macro.
+pub struct Message {
+    pub translations: &'static [Translation],
+}
+
+impl Message {
+    /// Look up the text for `lang`, following its fallback chain. If no language in the chain
+    /// matches, the first translation is used.
+    pub fn get(&self, lang: &Language) -> &'static str {
+        for candidate in lang.fallbacks().iter() {
+            for t in self.translations {
+                if language_eq(t.lang, candidate) {
+                    return t.text;
+                }
+            }
+        }
+
+        self.translations.first().map(|t| t.text).unwrap_or("")
+    }
+
+    /// Look up the text for the current platform language.
+    pub fn localized(&self) -> &'static str {
+        self.get(&Language::platform())
+    }
+}
+
+/// Declare a translatable `Message`.
+///
+/// ```rust,ignore
+/// static BOOT: uefi::Message = message!("en" => "Boot", "de" => "Starten", "fr-FR" => "Démarrer");
+///
+/// console.write(BOOT.localized());
+/// ```
+#[macro_export]
+macro_rules! message {
+    ($($lang:expr => $text:expr),+ $(,)*) => {
+        $crate::Message {
+            translations: &[$($crate::Translation { lang: $lang, text: $text }),+],
+        }
+    };
+}
+
+/// Read string `string_id` from an HII package list into `buf`, trying each language in `lang`'s
+/// fallback chain. Returns the number of UCS-2 characters read. Fails with `Unsupported` if the
+/// HII string protocol is not present, so callers can fall back to a `Message`.
+pub fn hii_string(package_list: HiiHandle, string_id: StringId, lang: &Language, buf: &mut [u16]) -> Result<usize, Status> {
+    let hii = ::get_system_table()
+        .boot_services()
+        .locate_protocol::<HiiStringProtocol>(ptr::null())
+        .map_err(|_| Status::Unsupported)?;
+
+    for candidate in lang.fallbacks().iter() {
+        match hii.get_string(candidate, package_list, string_id, buf) {
+            Ok(len) => return Ok(len),
+            // Try the next language; anything else is a real failure.
+            Err(Status::InvalidParameter) | Err(Status::NotFound) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(Status::NotFound)
+}
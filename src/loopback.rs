@@ -0,0 +1,246 @@
+//! Disk images in files attached as block devices, so the firmware's own partition and FAT
+//! drivers mount them: an installer can ship its payload as one `.img` and copy files out of
+//! it as from any other volume.
+//!
+//! ```rust,ignore
+//! let image = root.open_path("\\payload.img", EFI_FILE_MODE_READ)?;
+//! let disk = Loopback::attach(image, true)?;
+//! let fs: &SimpleFileSystemProtocol = bs.handle_protocol(disk.volume()?)?;
+//! let payload = fs.open_volume()?;
+//! ```
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{mem, ptr};
+
+use base::{Handle, Status};
+use guid::Guid;
+use protocol::{BlockDevice, BlockIoDevice, BlockIoMedia, BlockIoProtocol, DevicePath, DevicePathProtocol, DevicePathTypes,
+               EndPathSubTypes, FileProtocol, HardwareSubTypes, SimpleFileSystemProtocol, EFI_BLOCK_IO_PROTOCOL_GUID,
+               EFI_DEVICE_PATH_PROTOCOL_GUID};
+use util::wire;
+use void::CVoid;
+
+/// Vendor GUID of the hardware device path node identifying a loopback disk.
+pub static LOOPBACK_VENDOR_GUID: Guid = guid!("5E0B9C4A-3D71-4F2E-9A16-7C48B2E50D93");
+
+/// Block size of loopback disks. Images are read as 512-byte sectors, as `dd` and `mkfs.fat`
+/// write them, and a trailing partial sector is left out.
+pub const LOOPBACK_BLOCK_SIZE: u32 = 512;
+
+/// A vendor hardware node with the GUID and an instance number, then the end node.
+const LOOPBACK_DEVICE_PATH_SIZE: usize = 24 + 4;
+
+/// Instance number of the next loopback disk, so each has a device path of its own.
+static NEXT_INSTANCE: AtomicUsize = AtomicUsize::new(0);
+
+/// A `BlockDevice` reading and writing the blocks of a disk image in a file.
+pub struct FileBlockDevice {
+    file: &'static FileProtocol,
+}
+
+impl FileBlockDevice {
+    pub fn new(file: &'static FileProtocol) -> FileBlockDevice {
+        FileBlockDevice { file }
+    }
+
+    pub fn file(&self) -> &'static FileProtocol {
+        self.file
+    }
+
+    fn seek(&self, lba: u64) -> Result<(), Status> {
+        match self.file.set_position(lba * LOOPBACK_BLOCK_SIZE as u64) {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+}
+
+impl BlockDevice for FileBlockDevice {
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), Status> {
+        self.seek(lba)?;
+        let mut len = 0;
+        while len < buf.len() {
+            match self.file.read(&mut buf[len..])? {
+                0 => return Err(Status::DeviceError),
+                n => len += n,
+            }
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), Status> {
+        self.seek(lba)?;
+        self.file.write_all(buf)
+    }
+
+    fn flush_blocks(&self) -> Result<(), Status> {
+        match self.file.flush() {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+}
+
+/// The pool allocation behind a `Loopback`, which the installed protocols point into.
+#[repr(C)]
+struct LoopbackDisk {
+    device: BlockIoDevice<FileBlockDevice>,
+    path: [u8; LOOPBACK_DEVICE_PATH_SIZE],
+}
+
+/// A disk image in a file, attached as a block device on a handle of its own. Dropping it
+/// detaches the disk, as `detach` does, ignoring errors.
+pub struct Loopback {
+    disk: *mut LoopbackDisk,
+    handle: Handle,
+}
+
+impl Loopback {
+    /// Attach the disk image in `file`, taking ownership of the file, and connect drivers to
+    /// it. The image may be a whole disk with a partition table or a single file system. Unless
+    /// `read_only` is set, the file must be open for writing. Fails with `Status::NotFound` if
+    /// no driver recognized what is in the image.
+    pub fn attach(file: &'static FileProtocol, read_only: bool) -> Result<Loopback, Status> {
+        let mut info = [0u8; 512];
+        let blocks = file.file_info(&mut info)?.file_size() / LOOPBACK_BLOCK_SIZE as u64;
+        if blocks == 0 {
+            return Err(Status::BadBufferSize);
+        }
+
+        let media = BlockIoMedia {
+            media_id: 0,
+            removable_media: false,
+            media_present: true,
+            logical_partition: false,
+            read_only,
+            write_caching: false,
+            block_size: LOOPBACK_BLOCK_SIZE,
+            io_align: 0,
+            last_block: blocks - 1,
+            lowest_aligned_lba: 0,
+            logical_blocks_per_physical_block: 1,
+            optimal_transfer_length_granularity: 0,
+        };
+
+        let bs = ::get_system_table().boot_services();
+        let disk = bs.allocate_pool::<LoopbackDisk>(mem::size_of::<LoopbackDisk>())?;
+        let mut path = [0u8; LOOPBACK_DEVICE_PATH_SIZE];
+        write_device_path(&mut path, NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed) as u32);
+        unsafe { ptr::write(disk, LoopbackDisk { device: BlockIoDevice::new(media, FileBlockDevice::new(file)), path }) };
+
+        let disk_ref = unsafe { &mut *disk };
+        let interfaces = [
+            (&EFI_BLOCK_IO_PROTOCOL_GUID, disk_ref.device.protocol() as *const BlockIoProtocol as *const CVoid),
+            (&EFI_DEVICE_PATH_PROTOCOL_GUID, disk_ref.path.as_ptr() as *const CVoid),
+        ];
+        // The pool allocation stays put until `detach` has uninstalled both.
+        let handle = match unsafe { bs.install_multiple_protocol_interfaces(Handle::NULL, &interfaces) } {
+            Ok(handle) => handle,
+            Err(e) => {
+                bs.free_pool(disk);
+                return Err(e);
+            }
+        };
+
+        let loopback = Loopback { disk, handle };
+        bs.connect_controller(handle, true)?;
+        Ok(loopback)
+    }
+
+    /// The handle the block device and its device path are installed on.
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// The block device, as the drivers connected to it see it.
+    pub fn block_io(&self) -> &BlockIoProtocol {
+        unsafe { &*(&(*self.disk).device as *const BlockIoDevice<FileBlockDevice> as *const BlockIoProtocol) }
+    }
+
+    /// The device path of the disk: a vendor node with `LOOPBACK_VENDOR_GUID`.
+    pub fn device_path(&self) -> DevicePath<'_> {
+        // Written by `write_device_path`, which makes a well-formed path.
+        DevicePath::from_bytes(unsafe { &(*self.disk).path }).unwrap()
+    }
+
+    /// The handle of the file system on the disk: on the disk itself for an image of a single
+    /// file system, or else on its first partition with one.
+    pub fn volume(&self) -> Result<Handle, Status> {
+        let bs = ::get_system_table().boot_services();
+        if bs.handle_protocol::<SimpleFileSystemProtocol>(self.handle).is_ok() {
+            return Ok(self.handle);
+        }
+
+        let disk_path = self.device_path().as_bytes();
+        let prefix = &disk_path[..disk_path.len() - 4];
+        let handles = bs.find_handles::<SimpleFileSystemProtocol>()?;
+        let volume = handles.as_slice().iter().cloned().find(|&handle| {
+            bs.handle_protocol::<DevicePathProtocol>(handle)
+                .and_then(|path| unsafe { path.path() })
+                .map(|path| path.as_bytes().starts_with(prefix))
+                .unwrap_or(false)
+        });
+        volume.ok_or(Status::NotFound)
+    }
+
+    /// Disconnect the drivers from the disk, remove it, and close the file. If a driver
+    /// refuses to let go, the disk stays attached and the error is returned along with it.
+    pub fn detach(self) -> Result<(), (Loopback, Status)> {
+        match self.remove() {
+            Ok(()) => {
+                mem::forget(self);
+                Ok(())
+            }
+            Err(e) => Err((self, e)),
+        }
+    }
+
+    fn remove(&self) -> Result<(), Status> {
+        let bs = ::get_system_table().boot_services();
+        let disk = unsafe { &mut *self.disk };
+        // Not connected is fine; anything still holding the disk shows up when uninstalling.
+        let _ = bs.disconnect_controller(self.handle);
+        let interfaces = [
+            (&EFI_BLOCK_IO_PROTOCOL_GUID, disk.device.protocol() as *const BlockIoProtocol as *const CVoid),
+            (&EFI_DEVICE_PATH_PROTOCOL_GUID, disk.path.as_ptr() as *const CVoid),
+        ];
+        unsafe { bs.uninstall_multiple_protocol_interfaces(self.handle, &interfaces)? };
+
+        let _ = disk.device.device().file().flush();
+        disk.device.device().file().close();
+        bs.free_pool(self.disk);
+        Ok(())
+    }
+}
+
+impl Drop for Loopback {
+    fn drop(&mut self) {
+        // If the disk can't be removed, it is left attached rather than freed under a driver.
+        let _ = self.remove();
+    }
+}
+
+/// Write the device path of loopback disk `instance` into `buf`.
+fn write_device_path(buf: &mut [u8; LOOPBACK_DEVICE_PATH_SIZE], instance: u32) {
+    buf[0] = DevicePathTypes::Hardware.into();
+    buf[1] = HardwareSubTypes::Vendor.into();
+    buf[2] = 24;
+    // The buffer is the size of both nodes.
+    wire::write_guid(buf, 4, &LOOPBACK_VENDOR_GUID).unwrap();
+    wire::write_u32(buf, 20, instance).unwrap();
+    buf[24] = DevicePathTypes::End.into();
+    buf[25] = EndPathSubTypes::EndEntirePath.into();
+    buf[26] = 4;
+}
+
+#[test]
+fn loopback_device_path() {
+    let mut buf = [0u8; LOOPBACK_DEVICE_PATH_SIZE];
+    write_device_path(&mut buf, 3);
+    let path = DevicePath::from_bytes(&buf).unwrap();
+    assert_eq!(path.total_len(), LOOPBACK_DEVICE_PATH_SIZE);
+    let node = path.nodes().next().unwrap();
+    assert_eq!((node.node_type(), node.sub_type()), (1, 4));
+    assert_eq!(wire::read_guid(node.data(), 0), Ok(LOOPBACK_VENDOR_GUID));
+    assert_eq!(wire::read_u32(node.data(), 16), Ok(3));
+}
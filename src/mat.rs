@@ -0,0 +1,172 @@
+use core::mem;
+
+use base::{MemoryAttribute, MemoryDescriptor, EFI_MEMORY_RO, EFI_MEMORY_XP};
+use guid::Guid;
+
+/// GUID of the Memory Attributes Table in the system configuration table
+pub static EFI_MEMORY_ATTRIBUTES_TABLE_GUID: Guid = Guid(0xDCFA911D, 0x26EB, 0x469F, [0xA2, 0x20, 0x38, 0xB7, 0xDC, 0x46, 0x12, 0x20]);
+
+/// Set in `MemoryAttributesTable::flags` when runtime code regions are compatible with forward
+/// control flow guards (version 2 and later).
+pub const EFI_MEMORY_ATTRIBUTES_FLAGS_RT_FORWARD_CONTROL_FLOW_GUARD: u32 = 0x1;
+
+/// Header of EFI_MEMORY_ATTRIBUTES_TABLE, which is followed by the descriptors.
+#[derive(Debug)]
+#[repr(C)]
+struct MemoryAttributesTableHeader {
+    version: u32,
+    number_of_entries: u32,
+    descriptor_size: u32,
+    flags: u32,
+}
+
+/// The Memory Attributes Table, which splits the runtime services regions of the memory map into
+/// code and data parts, with the RO/XP permissions each should be mapped with. Loaders use it to
+/// build page tables for runtime services that are W^X.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryAttributesTable {
+    header: &'static MemoryAttributesTableHeader,
+}
+
+impl MemoryAttributesTable {
+    /// Find the table in the system configuration table, if the firmware publishes a valid one.
+    pub fn get() -> Option<MemoryAttributesTable> {
+        match ::get_system_table().configuration_table(&EFI_MEMORY_ATTRIBUTES_TABLE_GUID) {
+            Some(table) if !table.is_null() => {
+                MemoryAttributesTable::from_header(unsafe { &*(table as *const MemoryAttributesTableHeader) })
+            }
+            _ => None,
+        }
+    }
+
+    /// Wrap `header` if it describes descriptors that can be walked safely: a version the spec
+    /// defines, and descriptors big enough and aligned for a `MemoryDescriptor`.
+    fn from_header(header: &'static MemoryAttributesTableHeader) -> Option<MemoryAttributesTable> {
+        let descriptor_size = header.descriptor_size as usize;
+        if header.version == 0
+            || descriptor_size < mem::size_of::<MemoryDescriptor>()
+            || descriptor_size % mem::align_of::<MemoryDescriptor>() != 0
+        {
+            return None;
+        }
+        Some(MemoryAttributesTable { header })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.header.version
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.header.flags
+    }
+
+    /// The runtime regions and their attributes. Entries are sorted by address and don't
+    /// overlap.
+    pub fn entries(&self) -> MemoryAttributesIter {
+        MemoryAttributesIter {
+            next: unsafe { (self.header as *const MemoryAttributesTableHeader).add(1) as *const u8 },
+            descriptor_size: self.header.descriptor_size as usize,
+            remaining: self.header.number_of_entries as usize,
+        }
+    }
+}
+
+/// Iterator over the descriptors of a `MemoryAttributesTable`.
+pub struct MemoryAttributesIter {
+    next: *const u8,
+    descriptor_size: usize,
+    remaining: usize,
+}
+
+impl Iterator for MemoryAttributesIter {
+    type Item = &'static MemoryDescriptor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        // Descriptors can be bigger than MemoryDescriptor in later spec versions, so step by the
+        // size the table gives.
+        let descriptor = unsafe { &*(self.next as *const MemoryDescriptor) };
+        self.next = unsafe { self.next.add(self.descriptor_size) };
+        self.remaining -= 1;
+        Some(descriptor)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for MemoryAttributesIter {}
+
+/// Page permissions for a region of the Memory Attributes Table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PagePermissions {
+    pub writable: bool,
+    pub executable: bool,
+}
+
+impl PagePermissions {
+    /// The permissions `attribute` asks for. Regions with neither RO nor XP set must stay
+    /// writable and executable.
+    pub fn from_attribute(attribute: MemoryAttribute) -> PagePermissions {
+        PagePermissions {
+            writable: !attribute.contains(EFI_MEMORY_RO),
+            executable: !attribute.contains(EFI_MEMORY_XP),
+        }
+    }
+}
+
+/// A table as firmware lays it out, with descriptors padded to 48 bytes.
+#[cfg(test)]
+#[repr(C)]
+struct TestTable {
+    header: MemoryAttributesTableHeader,
+    descriptors: [[u64; 6]; 2],
+}
+
+#[cfg(test)]
+static TEST_TABLE: TestTable = TestTable {
+    header: MemoryAttributesTableHeader { version: 2, number_of_entries: 2, descriptor_size: 48, flags: 1 },
+    descriptors: [
+        // Type, physical start, virtual start, pages, attribute, padding.
+        [5, 0x7F00_0000, 0, 4, 0x8000_0000_0002_0000, 0xFFFF_FFFF_FFFF_FFFF],
+        [6, 0x7F00_4000, 0, 2, 0x8000_0000_0000_4000, 0xFFFF_FFFF_FFFF_FFFF],
+    ],
+};
+
+#[cfg(test)]
+static BAD_HEADERS: [MemoryAttributesTableHeader; 3] = [
+    MemoryAttributesTableHeader { version: 0, number_of_entries: 0, descriptor_size: 48, flags: 0 },
+    MemoryAttributesTableHeader { version: 1, number_of_entries: 0, descriptor_size: 8, flags: 0 },
+    MemoryAttributesTableHeader { version: 1, number_of_entries: 0, descriptor_size: 44, flags: 0 },
+];
+
+#[test]
+fn memory_attributes_entries() {
+    use base::MemoryType;
+
+    let table = MemoryAttributesTable::from_header(&TEST_TABLE.header).unwrap();
+    assert_eq!(table.version(), 2);
+    assert_eq!(table.flags(), EFI_MEMORY_ATTRIBUTES_FLAGS_RT_FORWARD_CONTROL_FLOW_GUARD);
+
+    let mut entries = table.entries();
+    assert_eq!(entries.len(), 2);
+    let code = entries.next().unwrap();
+    assert_eq!(code.memory_type(), MemoryType::RuntimeServicesCode);
+    assert_eq!(code.physical_start(), 0x7F00_0000);
+    assert_eq!(code.number_of_pages(), 4);
+    assert_eq!(PagePermissions::from_attribute(code.attribute()), PagePermissions { writable: false, executable: true });
+    let data = entries.next().unwrap();
+    assert_eq!(data.memory_type(), MemoryType::RuntimeServicesData);
+    assert_eq!(data.physical_start(), 0x7F00_4000);
+    assert_eq!(data.number_of_pages(), 2);
+    assert_eq!(PagePermissions::from_attribute(data.attribute()), PagePermissions { writable: true, executable: false });
+    assert!(entries.next().is_none());
+
+    for header in &BAD_HEADERS {
+        assert!(MemoryAttributesTable::from_header(header).is_none());
+    }
+}
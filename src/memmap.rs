@@ -0,0 +1,195 @@
+use core::slice;
+
+use base::{Handle, MemoryDescriptor, MemoryType, Status};
+use bootservices::AllocateType;
+use transcript::flush_transcript;
+
+/// Size of a page as used by AllocatePages and the memory map.
+pub const EFI_PAGE_SIZE: u64 = 4096;
+
+/// Bytes of room left for the memory map to grow between sizing it and reading it.
+pub const MEMORY_MAP_SLACK: usize = 1024;
+
+/// A snapshot of the firmware memory map. Maps from `get` are in pool memory, which is freed on
+/// drop; the final map from `exit_boot_services` is in pages that are kept.
+pub struct MemoryMap {
+    buffer: *const u8,
+    size: usize,
+    key: usize,
+    descriptor_size: usize,
+    descriptor_version: u32,
+    pool: bool,
+}
+
+impl MemoryMap {
+    /// Get the current memory map, retrying with a larger buffer until it fits.
+    pub fn get() -> Result<MemoryMap, Status> {
+        let bs = ::get_system_table().boot_services();
+
+        let mut size: usize = 0;
+        loop {
+            match unsafe { bs.get_memory_map(&mut size) } {
+                Ok((map, key, size, descriptor_size, descriptor_version)) => {
+                    return Ok(MemoryMap {
+                        buffer: map as *const MemoryDescriptor as *const u8,
+                        size,
+                        key,
+                        descriptor_size,
+                        descriptor_version,
+                        pool: true,
+                    });
+                }
+                // The firmware updated `size` to what it needs; leave room for the descriptors
+                // our own allocation may add.
+                Err(Status::BufferTooSmall) => size += 512,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The key identifying this version of the map, as needed by ExitBootServices.
+    pub fn key(&self) -> usize {
+        self.key
+    }
+
+    pub fn descriptor_size(&self) -> usize {
+        self.descriptor_size
+    }
+
+    pub fn descriptor_version(&self) -> u32 {
+        self.descriptor_version
+    }
+
+    pub fn len(&self) -> usize {
+        if self.descriptor_size == 0 {
+            return 0;
+        }
+        self.size / self.descriptor_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The descriptor at `index`. Descriptors are `descriptor_size` bytes apart, which may be more
+    /// than the size of `MemoryDescriptor`.
+    pub fn get_descriptor(&self, index: usize) -> Option<&MemoryDescriptor> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(unsafe { &*(self.buffer.add(index * self.descriptor_size) as *const MemoryDescriptor) })
+    }
+
+    pub fn iter(&self) -> MemoryMapIter<'_> {
+        MemoryMapIter { map: self, index: 0 }
+    }
+}
+
+impl Drop for MemoryMap {
+    fn drop(&mut self) {
+        if self.pool {
+            ::get_system_table().boot_services().free_pool(self.buffer);
+        }
+    }
+}
+
+/// Read the final memory map and exit boot services with it, retrying with a fresh map if it
+/// changed in between, and return the map.
+///
+/// The map is kept in `LoaderData` pages, which it describes, so it stays usable afterwards and
+/// can be handed to a kernel; dropping it frees nothing. The boot transcript, if any, is
+/// written first, as the file system is gone afterwards. After an error other than from
+/// ExitBootServices itself, boot services are still available.
+///
+/// ```rust,ignore
+/// let map = uefi::exit_boot_services(image_handle)?;
+/// for descriptor in &map {
+///     // ...
+/// }
+/// ```
+pub fn exit_boot_services(image_handle: Handle) -> Result<MemoryMap, Status> {
+    let bs = ::get_system_table().boot_services();
+    let _ = flush_transcript();
+
+    let mut needed = 0;
+    loop {
+        match bs.get_memory_map_into(&mut [], &mut needed) {
+            Err(Status::BufferTooSmall) => {}
+            Err(e) => return Err(e),
+            Ok(_) => return Err(Status::DeviceError),
+        }
+
+        // Allocating the pages can split a region, so leave room for more descriptors.
+        let pages = (needed + MEMORY_MAP_SLACK + EFI_PAGE_SIZE as usize - 1) / EFI_PAGE_SIZE as usize;
+        let base = bs.allocate_pages(AllocateType::AnyPages, MemoryType::LoaderData, pages)?;
+        let buf = unsafe { slice::from_raw_parts_mut(base as usize as *mut u8, pages * EFI_PAGE_SIZE as usize) };
+
+        // The map key goes stale if anything allocates, including the firmware's own events, in
+        // which case ExitBootServices fails and only GetMemoryMap may be called before retrying.
+        let mut attempts = 0;
+        loop {
+            let (size, key, descriptor_size, descriptor_version) = match bs.get_memory_map_into(buf, &mut needed) {
+                Ok(map) => map,
+                // Grown past the slack before anything was tried: start again, larger.
+                Err(Status::BufferTooSmall) if attempts == 0 => {
+                    bs.free_pages(base, pages);
+                    break;
+                }
+                Err(e) => {
+                    if attempts == 0 {
+                        bs.free_pages(base, pages);
+                    }
+                    return Err(e);
+                }
+            };
+
+            match bs.exit_boot_services(&image_handle, &key) {
+                Status::Success => {
+                    return Ok(MemoryMap {
+                        buffer: buf.as_ptr(),
+                        size,
+                        key,
+                        descriptor_size,
+                        descriptor_version,
+                        pool: false,
+                    });
+                }
+                Status::InvalidParameter if attempts < 3 => attempts += 1,
+                e => return Err(e),
+            }
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a MemoryMap {
+    type Item = &'a MemoryDescriptor;
+    type IntoIter = MemoryMapIter<'a>;
+
+    fn into_iter(self) -> MemoryMapIter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the descriptors of a `MemoryMap`.
+#[derive(Clone)]
+pub struct MemoryMapIter<'a> {
+    map: &'a MemoryMap,
+    index: usize,
+}
+
+impl<'a> Iterator for MemoryMapIter<'a> {
+    type Item = &'a MemoryDescriptor;
+
+    fn next(&mut self) -> Option<&'a MemoryDescriptor> {
+        let descriptor = self.map.get_descriptor(self.index)?;
+        self.index += 1;
+        Some(descriptor)
+    }
+}
+
+impl MemoryDescriptor {
+    /// Physical address one past the end of the region.
+    pub fn physical_end(&self) -> u64 {
+        self.physical_start() + self.number_of_pages() * EFI_PAGE_SIZE
+    }
+}
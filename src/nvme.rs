@@ -0,0 +1,299 @@
+use core::str;
+
+use base::Status;
+use protocol::{NvmeCommand, NvmePassThruProtocol, NvmeQueue, NVME_BROADCAST_NSID};
+use util::wire;
+
+/// Size of the Identify and most log page data structures.
+pub const NVME_IDENTIFY_SIZE: usize = 4096;
+
+const OPCODE_GET_LOG_PAGE: u8 = 0x02;
+const OPCODE_IDENTIFY: u8 = 0x06;
+const OPCODE_FORMAT_NVM: u8 = 0x80;
+const OPCODE_SANITIZE: u8 = 0x84;
+
+const CNS_NAMESPACE: u32 = 0x00;
+const CNS_CONTROLLER: u32 = 0x01;
+const CNS_ACTIVE_NAMESPACES: u32 = 0x02;
+
+const LOG_SANITIZE_STATUS: u32 = 0x81;
+const SANITIZE_LOG_SIZE: usize = 512;
+
+/// Timeout for ordinary admin commands, in 100ns units.
+const ADMIN_TIMEOUT: u64 = 50_000_000;
+
+/// Page-aligned command data, which satisfies any IoAlign a controller asks for.
+#[repr(C, align(4096))]
+struct DataBuffer([u8; NVME_IDENTIFY_SIZE]);
+
+/// Take `bytes` as an ASCII string padded with spaces, as Identify strings are.
+fn ascii_field(bytes: &[u8]) -> &str {
+    str::from_utf8(bytes).unwrap_or("").trim_end_matches([' ', '\0'])
+}
+
+/// The Identify Controller data structure.
+pub struct IdentifyController {
+    data: [u8; NVME_IDENTIFY_SIZE],
+}
+
+impl IdentifyController {
+    pub fn from_bytes(data: [u8; NVME_IDENTIFY_SIZE]) -> IdentifyController {
+        IdentifyController { data }
+    }
+
+    /// PCI vendor ID.
+    pub fn vendor_id(&self) -> u16 {
+        wire::read_u16(&self.data, 0).unwrap()
+    }
+
+    pub fn serial_number(&self) -> &str {
+        ascii_field(&self.data[4..24])
+    }
+
+    pub fn model_number(&self) -> &str {
+        ascii_field(&self.data[24..64])
+    }
+
+    pub fn firmware_revision(&self) -> &str {
+        ascii_field(&self.data[64..72])
+    }
+
+    /// Whether the controller supports the Format NVM command.
+    pub fn supports_format(&self) -> bool {
+        wire::read_u16(&self.data, 256).unwrap() & 0x0002 != 0
+    }
+
+    /// The SANICAP field: bit 0 crypto erase, bit 1 block erase and bit 2 overwrite are
+    /// supported.
+    pub fn sanitize_capabilities(&self) -> u32 {
+        wire::read_u32(&self.data, 328).unwrap()
+    }
+
+    /// The largest namespace ID the controller supports.
+    pub fn number_of_namespaces(&self) -> u32 {
+        wire::read_u32(&self.data, 516).unwrap()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// A logical block format a namespace supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LbaFormat {
+    /// Metadata bytes per block.
+    pub metadata_size: u16,
+    /// Data bytes per block.
+    pub block_size: u64,
+    /// 0 for the best performance, up to 3 for degraded.
+    pub relative_performance: u8,
+}
+
+/// The Identify Namespace data structure.
+pub struct IdentifyNamespace {
+    data: [u8; NVME_IDENTIFY_SIZE],
+}
+
+impl IdentifyNamespace {
+    pub fn from_bytes(data: [u8; NVME_IDENTIFY_SIZE]) -> IdentifyNamespace {
+        IdentifyNamespace { data }
+    }
+
+    /// Size of the namespace in blocks.
+    pub fn size(&self) -> u64 {
+        wire::read_u64(&self.data, 0).unwrap()
+    }
+
+    /// Blocks that may be allocated.
+    pub fn capacity(&self) -> u64 {
+        wire::read_u64(&self.data, 8).unwrap()
+    }
+
+    /// Blocks currently allocated.
+    pub fn utilization(&self) -> u64 {
+        wire::read_u64(&self.data, 16).unwrap()
+    }
+
+    /// Index of the block format the namespace is formatted with.
+    pub fn current_lba_format(&self) -> usize {
+        (self.data[26] & 0x0F) as usize
+    }
+
+    /// The block formats the namespace can be formatted with, by index.
+    pub fn lba_formats(&self) -> impl Iterator<Item = LbaFormat> + '_ {
+        let count = self.data[25] as usize + 1;
+        (0..count).map(move |i| {
+            let v = wire::read_u32(&self.data, 128 + i * 4).unwrap();
+            LbaFormat {
+                metadata_size: v as u16,
+                block_size: 1 << ((v >> 16) & 0xFF),
+                relative_performance: ((v >> 24) & 0x03) as u8,
+            }
+        })
+    }
+
+    /// Size of the namespace in bytes, with its current block format.
+    pub fn size_bytes(&self) -> u64 {
+        let block_size = self.lba_formats().nth(self.current_lba_format()).map_or(0, |f| f.block_size);
+        self.size().saturating_mul(block_size)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Secure erase done by `NvmeController::format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecureErase {
+    None = 0,
+    UserData = 1,
+    Cryptographic = 2,
+}
+
+/// Sanitize operation done by `NvmeController::sanitize`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SanitizeAction {
+    /// Leave the failure state a failed sanitize put the controller in.
+    ExitFailureMode = 1,
+    BlockErase = 2,
+    Overwrite = 3,
+    CryptoErase = 4,
+}
+
+/// State reported by the Sanitize Status log page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SanitizeState {
+    NeverSanitized,
+    Completed,
+    /// In progress, with the fraction done out of 65536.
+    InProgress(u16),
+    Failed,
+}
+
+/// Typed admin commands over an `NvmePassThruProtocol`.
+pub struct NvmeController<'a> {
+    pass_thru: &'a NvmePassThruProtocol,
+}
+
+impl<'a> NvmeController<'a> {
+    pub fn new(pass_thru: &'a NvmePassThruProtocol) -> NvmeController<'a> {
+        NvmeController { pass_thru }
+    }
+
+    fn admin(&self, command: &NvmeCommand, buf: Option<&mut [u8]>, timeout: u64) -> Result<u32, Status> {
+        let completion = self.pass_thru.pass_thru(NvmeQueue::Admin, command, buf, timeout)?;
+        if completion.status() != 0 {
+            return Err(Status::DeviceError);
+        }
+        Ok(completion.dw0)
+    }
+
+    fn identify(&self, cns: u32, nsid: u32) -> Result<[u8; NVME_IDENTIFY_SIZE], Status> {
+        let mut buf = DataBuffer([0; NVME_IDENTIFY_SIZE]);
+        let command = NvmeCommand::new(OPCODE_IDENTIFY, nsid).cdw10(cns);
+        self.admin(&command, Some(&mut buf.0), ADMIN_TIMEOUT)?;
+        Ok(buf.0)
+    }
+
+    pub fn identify_controller(&self) -> Result<IdentifyController, Status> {
+        self.identify(CNS_CONTROLLER, 0).map(IdentifyController::from_bytes)
+    }
+
+    pub fn identify_namespace(&self, nsid: u32) -> Result<IdentifyNamespace, Status> {
+        self.identify(CNS_NAMESPACE, nsid).map(IdentifyNamespace::from_bytes)
+    }
+
+    /// The IDs of the active namespaces, as reported by the controller itself (up to 1024).
+    pub fn active_namespaces(&self) -> Result<impl Iterator<Item = u32>, Status> {
+        let list = self.identify(CNS_ACTIVE_NAMESPACES, 0)?;
+        Ok((0..NVME_IDENTIFY_SIZE / 4)
+            .map(move |i| wire::read_u32(&list, i * 4).unwrap())
+            .take_while(|&nsid| nsid != 0))
+    }
+
+    /// Low-level format namespace `nsid` (or all namespaces with `NVME_BROADCAST_NSID`) with
+    /// block format `lba_format`, an index into `IdentifyNamespace::lba_formats`. This destroys
+    /// all data in the namespace, and blocks until the format is done, which may take minutes.
+    pub fn format(&self, nsid: u32, lba_format: u8, erase: SecureErase) -> Result<(), Status> {
+        if lba_format > 0x0F {
+            return Err(Status::InvalidParameter);
+        }
+
+        let command = NvmeCommand::new(OPCODE_FORMAT_NVM, nsid)
+            .cdw10((lba_format as u32) | ((erase as u32) << 9));
+        self.admin(&command, None, 0).map(|_| ())
+    }
+
+    /// Read the Sanitize Status log page.
+    pub fn sanitize_status(&self) -> Result<SanitizeState, Status> {
+        let mut buf = DataBuffer([0; NVME_IDENTIFY_SIZE]);
+        let dwords = (SANITIZE_LOG_SIZE / 4 - 1) as u32;
+        let command = NvmeCommand::new(OPCODE_GET_LOG_PAGE, NVME_BROADCAST_NSID)
+            .cdw10(LOG_SANITIZE_STATUS | (dwords << 16));
+        self.admin(&command, Some(&mut buf.0[..SANITIZE_LOG_SIZE]), ADMIN_TIMEOUT)?;
+
+        let progress = wire::read_u16(&buf.0, 0)?;
+        match wire::read_u16(&buf.0, 2)? & 0x07 {
+            0 => Ok(SanitizeState::NeverSanitized),
+            1 | 4 => Ok(SanitizeState::Completed),
+            2 => Ok(SanitizeState::InProgress(progress)),
+            _ => Ok(SanitizeState::Failed),
+        }
+    }
+
+    /// Sanitize the whole controller, destroying all user data in every namespace, and wait for
+    /// it to finish, calling `progress` with the percentage done about once a second.
+    /// `overwrite_pattern` is only used by `SanitizeAction::Overwrite`. Returns
+    /// `Status::DeviceError` if the sanitize fails.
+    pub fn sanitize<F: FnMut(u8)>(&self, action: SanitizeAction, overwrite_pattern: u32, mut progress: F)
+                                  -> Result<(), Status> {
+        let command = NvmeCommand::new(OPCODE_SANITIZE, 0)
+            .cdw10(action as u32)
+            .cdw11(overwrite_pattern);
+        self.admin(&command, None, ADMIN_TIMEOUT)?;
+        if action == SanitizeAction::ExitFailureMode {
+            return Ok(());
+        }
+
+        let bs = ::get_system_table().boot_services();
+        let mut unstarted_polls = 0;
+        loop {
+            match self.sanitize_status()? {
+                SanitizeState::InProgress(done) => {
+                    progress(((done as u32 * 100) >> 16) as u8);
+                    bs.stall(1_000_000);
+                }
+                SanitizeState::Completed => {
+                    progress(100);
+                    return Ok(());
+                }
+                // The command has been accepted, so the log should report it shortly.
+                SanitizeState::NeverSanitized if unstarted_polls < 50 => {
+                    unstarted_polls += 1;
+                    bs.stall(100_000);
+                }
+                SanitizeState::NeverSanitized => return Err(Status::DeviceError),
+                SanitizeState::Failed => return Err(Status::DeviceError),
+            }
+        }
+    }
+}
+
+#[test]
+fn identify_namespace_formats() {
+    let mut data = [0u8; NVME_IDENTIFY_SIZE];
+    wire::write_u64(&mut data, 0, 1000).unwrap();
+    data[25] = 1;
+    data[26] = 1;
+    wire::write_u32(&mut data, 128, 9 << 16).unwrap();
+    wire::write_u32(&mut data, 132, (12 << 16) | 8).unwrap();
+
+    let ns = IdentifyNamespace::from_bytes(data);
+    let formats: [LbaFormat; 2] = [ns.lba_formats().next().unwrap(), ns.lba_formats().nth(1).unwrap()];
+    assert_eq!(formats[0].block_size, 512);
+    assert_eq!(formats[1].metadata_size, 8);
+    assert_eq!(ns.lba_formats().count(), 2);
+    assert_eq!(ns.size_bytes(), 4096 * 1000);
+}
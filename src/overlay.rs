@@ -0,0 +1,245 @@
+//! One namespace of read-only files stacked from several sources, such as a directory on the
+//! ESP over copies embedded in the image, so a recovery tool reads `\config.txt` from the disk
+//! when it is there and falls back to its own copy when it is not.
+//!
+//! ```rust,ignore
+//! static BUILTIN: MemorySource = MemorySource::new(&[("\\config.txt", include_bytes!("config.txt"))]);
+//!
+//! let disk = DirSource::new(esp_root, "\\EFI\\recovery")?;
+//! let mut overlay = Overlay::new();
+//! overlay.mount("\\", &disk, 10)?;
+//! overlay.mount("\\", &BUILTIN, 0)?;
+//! let len = overlay.read_file("\\config.txt", &mut buf)?;
+//! ```
+
+use base::Status;
+use path::EfiPath;
+use protocol::{FileProtocol, EFI_FILE_MODE_READ};
+
+/// Most sources an `Overlay` stacks.
+pub const MAX_OVERLAY_SOURCES: usize = 8;
+
+/// Somewhere read-only files can be read from by path. Paths are absolute and normalized, as
+/// `EfiPath` makes them, relative to where the source is mounted.
+pub trait FileSource {
+    /// The size of the file at `path`, failing with `Status::NotFound` if there is none.
+    fn file_size(&self, path: &str) -> Result<u64, Status>;
+
+    /// Read the whole file at `path` into `buf`, returning its size. Fails with
+    /// `Status::BufferTooSmall` if it doesn't fit, and `Status::NotFound` if there is none.
+    fn read_file(&self, path: &str, buf: &mut [u8]) -> Result<usize, Status>;
+}
+
+/// Files in a directory of a volume.
+pub struct DirSource<'a> {
+    root: &'a FileProtocol,
+    dir: EfiPath,
+}
+
+impl<'a> DirSource<'a> {
+    /// The files under `dir` in the volume whose root directory is `root`.
+    pub fn new(root: &'a FileProtocol, dir: &str) -> Result<DirSource<'a>, Status> {
+        Ok(DirSource { root, dir: EfiPath::new(dir)? })
+    }
+
+    fn open(&self, path: &str) -> Result<&'a FileProtocol, Status> {
+        let path = self.dir.join(path.trim_start_matches('\\'))?;
+        self.root.open_path(path, EFI_FILE_MODE_READ)
+    }
+}
+
+impl<'a> FileSource for DirSource<'a> {
+    fn file_size(&self, path: &str) -> Result<u64, Status> {
+        let file = self.open(path)?;
+        let mut info = [0u8; 512];
+        let size = file.file_info(&mut info).map(|info| info.file_size());
+        file.close();
+        size
+    }
+
+    fn read_file(&self, path: &str, buf: &mut [u8]) -> Result<usize, Status> {
+        let file = self.open(path)?;
+        let result = (|| {
+            let mut info = [0u8; 512];
+            let size = file.file_info(&mut info)?.file_size();
+            if size > buf.len() as u64 {
+                return Err(Status::BufferTooSmall);
+            }
+
+            let buf = &mut buf[..size as usize];
+            let mut len = 0;
+            while len < buf.len() {
+                match file.read(&mut buf[len..])? {
+                    0 => return Err(Status::EndOfFile),
+                    n => len += n,
+                }
+            }
+            Ok(len)
+        })();
+        file.close();
+        result
+    }
+}
+
+/// Files held in memory as (path, contents) pairs, such as a ramdisk or files built into the
+/// image. Paths are matched as `EfiPath`s are compared, so either slash may be used and case
+/// is ignored.
+pub struct MemorySource<'a> {
+    files: &'a [(&'a str, &'a [u8])],
+}
+
+impl<'a> MemorySource<'a> {
+    pub const fn new(files: &'a [(&'a str, &'a [u8])]) -> MemorySource<'a> {
+        MemorySource { files }
+    }
+
+    fn find(&self, path: &str) -> Result<&'a [u8], Status> {
+        let path = EfiPath::new(path)?;
+        self.files.iter().find(|&&(name, _)| path == name).map(|&(_, data)| data).ok_or(Status::NotFound)
+    }
+}
+
+impl<'a> FileSource for MemorySource<'a> {
+    fn file_size(&self, path: &str) -> Result<u64, Status> {
+        self.find(path).map(|data| data.len() as u64)
+    }
+
+    fn read_file(&self, path: &str, buf: &mut [u8]) -> Result<usize, Status> {
+        let data = self.find(path)?;
+        buf.get_mut(..data.len()).ok_or(Status::BufferTooSmall)?.copy_from_slice(data);
+        Ok(data.len())
+    }
+}
+
+/// A source mounted in an `Overlay`.
+struct Mount<'a> {
+    prefix: EfiPath,
+    source: &'a dyn FileSource,
+    priority: i32,
+}
+
+/// Sources stacked under one namespace. A path is looked up in each source mounted at or
+/// above it, highest priority first and in the order mounted among equals, and the first that
+/// has the file serves it. An overlay is itself a `FileSource`, so overlays can be stacked.
+///
+/// A source that fails, whether the file is missing or the disk can't be read, is passed over
+/// for the next. A source that has the file but whose copy doesn't fit the buffer ends the
+/// search with `Status::BufferTooSmall`, rather than quietly reading another copy.
+pub struct Overlay<'a> {
+    mounts: [Option<Mount<'a>>; MAX_OVERLAY_SOURCES],
+    len: usize,
+}
+
+impl<'a> Overlay<'a> {
+    const NO_MOUNT: Option<Mount<'a>> = None;
+
+    pub fn new() -> Overlay<'a> {
+        Overlay { mounts: [Self::NO_MOUNT; MAX_OVERLAY_SOURCES], len: 0 }
+    }
+
+    /// Mount `source` at `prefix`, an absolute path such as `\` or `\fonts`, so that
+    /// `\fonts\a.psf` is read from the source as `\a.psf`. Fails with
+    /// `Status::InvalidParameter` for a relative or invalid prefix, and
+    /// `Status::OutOfResources` if `MAX_OVERLAY_SOURCES` are already mounted.
+    pub fn mount(&mut self, prefix: &str, source: &'a dyn FileSource, priority: i32) -> Result<(), Status> {
+        let prefix = EfiPath::new(prefix)?;
+        if !prefix.is_absolute() {
+            return Err(Status::InvalidParameter);
+        }
+        if self.len == MAX_OVERLAY_SOURCES {
+            return Err(Status::OutOfResources);
+        }
+
+        let at = self.mounts[..self.len].iter().flatten().position(|m| m.priority < priority).unwrap_or(self.len);
+        self.mounts[at..=self.len].rotate_right(1);
+        self.mounts[at] = Some(Mount { prefix, source, priority });
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Whether any source has the file at `path`.
+    pub fn exists(&self, path: &str) -> bool {
+        self.file_size(path).is_ok()
+    }
+
+    /// Try `f` on each source mounted above `path`, with the path relative to the source,
+    /// until one succeeds.
+    fn find<R, F>(&self, path: &str, mut f: F) -> Result<R, Status>
+        where F: FnMut(&dyn FileSource, &str) -> Result<R, Status>
+    {
+        let path = EfiPath::new(path)?;
+        let mut error = Status::NotFound;
+        for mount in self.mounts[..self.len].iter().flatten() {
+            let rest = match strip_mount_prefix(path.as_str(), mount.prefix.as_str()) {
+                Some(rest) => rest,
+                None => continue,
+            };
+            match f(mount.source, rest) {
+                Ok(r) => return Ok(r),
+                Err(Status::BufferTooSmall) => return Err(Status::BufferTooSmall),
+                Err(Status::NotFound) => {}
+                Err(e) if error == Status::NotFound => error = e,
+                Err(_) => {}
+            }
+        }
+        Err(error)
+    }
+}
+
+impl<'a> Default for Overlay<'a> {
+    fn default() -> Overlay<'a> {
+        Overlay::new()
+    }
+}
+
+impl<'a> FileSource for Overlay<'a> {
+    fn file_size(&self, path: &str) -> Result<u64, Status> {
+        self.find(path, |source, path| source.file_size(path))
+    }
+
+    fn read_file(&self, path: &str, buf: &mut [u8]) -> Result<usize, Status> {
+        self.find(path, |source, path| source.read_file(path, buf))
+    }
+}
+
+/// `path` relative to the mount point `prefix`, as an absolute path, if it is under it.
+fn strip_mount_prefix<'p>(path: &'p str, prefix: &str) -> Option<&'p str> {
+    if prefix == "\\" {
+        return Some(path);
+    }
+    let head = path.get(..prefix.len())?;
+    if !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    match &path[prefix.len()..] {
+        "" => Some("\\"),
+        rest if rest.starts_with('\\') => Some(rest),
+        _ => None,
+    }
+}
+
+#[test]
+fn overlay_fallback() {
+    let disk = MemorySource::new(&[("\\config.txt", b"disk"), ("\\fonts\\big.psf", b"0123456789")]);
+    let builtin = MemorySource::new(&[("/CONFIG.TXT", b"builtin"), ("\\logo.bmp", b"BM")]);
+    let fonts = MemorySource::new(&[("\\small.psf", b"small")]);
+
+    let mut overlay = Overlay::new();
+    overlay.mount("\\", &builtin, 0).unwrap();
+    overlay.mount("\\", &disk, 10).unwrap();
+    overlay.mount("/Fonts", &fonts, 0).unwrap();
+    assert_eq!(overlay.mount("fonts", &fonts, 0), Err(Status::InvalidParameter));
+
+    let mut buf = [0u8; 8];
+    assert_eq!(overlay.read_file("\\config.txt", &mut buf), Ok(4));
+    assert_eq!(&buf[..4], b"disk");
+    assert_eq!(overlay.read_file("/logo.bmp", &mut buf), Ok(2));
+    assert_eq!(overlay.file_size("\\fonts\\small.psf"), Ok(5));
+    assert_eq!(overlay.read_file("\\fonts\\big.psf", &mut buf), Err(Status::BufferTooSmall));
+    assert!(!overlay.exists("\\fontsmall.psf"));
+    assert_eq!(overlay.file_size("\\missing"), Err(Status::NotFound));
+
+    assert_eq!(strip_mount_prefix("\\fonts", "\\FONTS"), Some("\\"));
+    assert_eq!(strip_mount_prefix("\\fonts\\a", "\\fonts"), Some("\\a"));
+    assert_eq!(strip_mount_prefix("\\fontsa", "\\fonts"), None);
+}
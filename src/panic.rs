@@ -0,0 +1,127 @@
+//! A `#[panic_handler]` for applications, with the `panic-handler` feature, so they don't each
+//! write their own. It prints the message and where it happened on the console and the first
+//! serial port, then does what `set_panic_action` chose: by default, halt with the message on
+//! screen.
+//!
+//! The handler is only defined when building for a UEFI target, so host-side tests keep the
+//! standard library's. The crate has no global allocator, so there is no allocation error
+//! handler: allocations fail with `Status::OutOfResources` instead.
+//!
+//! ```rust,ignore
+//! fn main() -> Result<(), Status> {
+//!     uefi::set_panic_action(PanicAction::Reset { after_seconds: 30 });
+//!     // ...
+//! }
+//! ```
+
+#[cfg(target_os = "uefi")]
+use core::fmt::{self, Write};
+#[cfg(target_os = "uefi")]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(target_os = "uefi")]
+use runtimeservices::ResetType;
+#[cfg(target_os = "uefi")]
+use base::Status;
+#[cfg(target_os = "uefi")]
+use systemtable::{boot_services_available, system_table_available};
+#[cfg(target_os = "uefi")]
+use testing::TestReporter;
+
+/// What the panic handler does once it has printed the message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicAction {
+    /// Stop, leaving the message on screen. The watchdog timer is disabled so the firmware
+    /// doesn't reset the machine meanwhile.
+    Halt,
+    /// Wait `after_seconds`, for the message to be read, then reset the machine.
+    Reset { after_seconds: usize },
+}
+
+/// `PanicAction::Halt` in `PANIC_ACTION`.
+const HALT: usize = usize::MAX;
+
+/// The panic action as one value, so a callback panicking while it is set sees either the old
+/// action or the new one: `HALT`, or else the seconds before a reset.
+static PANIC_ACTION: AtomicUsize = AtomicUsize::new(HALT);
+
+/// Choose what the panic handler does after printing the message.
+pub fn set_panic_action(action: PanicAction) {
+    let action = match action {
+        PanicAction::Halt => HALT,
+        // A wait of `HALT` seconds is over 500 billion years, so one less is as good.
+        PanicAction::Reset { after_seconds } => after_seconds.min(HALT - 1),
+    };
+    PANIC_ACTION.store(action, Ordering::Relaxed);
+}
+
+/// What the panic handler will do after printing the message.
+pub fn panic_action() -> PanicAction {
+    match PANIC_ACTION.load(Ordering::Relaxed) {
+        HALT => PanicAction::Halt,
+        after_seconds => PanicAction::Reset { after_seconds },
+    }
+}
+
+/// Writes to `W`, turning the bare newlines in a `PanicInfo`'s message into the CRLFs the
+/// console needs.
+#[cfg(target_os = "uefi")]
+struct CrLf<W>(W);
+
+#[cfg(target_os = "uefi")]
+impl<W: Write> Write for CrLf<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.0.write_str("\r\n")?;
+            }
+            self.0.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "uefi")]
+#[panic_handler]
+fn panic(info: &::core::panic::PanicInfo) -> ! {
+    static PANICKING: AtomicBool = AtomicBool::new(false);
+
+    // A panic while printing the message, such as in a console driver, goes straight to the
+    // action rather than recursing.
+    let first = !PANICKING.swap(true, Ordering::Relaxed);
+    let boot_services = boot_services_available();
+    if first && boot_services {
+        // `PanicInfo` displays as the standard library prints a panic.
+        let _ = write!(CrLf(TestReporter::new()), "\n{}\n", info);
+    }
+
+    match panic_action() {
+        PanicAction::Reset { after_seconds } if system_table_available() => {
+            let st = ::get_system_table();
+            if boot_services {
+                st.boot_services().stall(after_seconds.saturating_mul(1_000_000));
+            }
+            st.runtime_services().reset_system(ResetType::Cold, Status::Aborted)
+        }
+        _ => {
+            if boot_services {
+                let _ = ::get_system_table().boot_services().set_watchdog_timer(0, 0);
+            }
+            loop {
+                ::core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+#[test]
+fn panic_actions() {
+    assert_eq!(panic_action(), PanicAction::Halt);
+    set_panic_action(PanicAction::Reset { after_seconds: 30 });
+    assert_eq!(panic_action(), PanicAction::Reset { after_seconds: 30 });
+    set_panic_action(PanicAction::Reset { after_seconds: usize::MAX });
+    assert_eq!(panic_action(), PanicAction::Reset { after_seconds: usize::MAX - 1 });
+    set_panic_action(PanicAction::Halt);
+    assert_eq!(panic_action(), PanicAction::Halt);
+}
@@ -0,0 +1,177 @@
+//! File paths as the firmware's FAT driver sees them: backslashes between names, compared
+//! ignoring case. Mixing up `/` and `\` is the most common reason a file that is plainly there
+//! is not found, so an `EfiPath` is normalized when it is made.
+
+use core::{fmt, str};
+
+use protocol::MAX_FILE_PATH;
+use validate::{normalize_path, ValidationError};
+
+/// Bytes needed for the longest path as UTF-8, as every UCS-2 unit takes at most three.
+const MAX_PATH_BYTES: usize = MAX_FILE_PATH * 3;
+
+/// A validated, normalized file path such as `\EFI\BOOT\BOOTX64.EFI`, without allocating.
+/// Forward slashes are turned into backslashes, repeated ones are collapsed and a trailing one
+/// is dropped, as `normalize_path` does. Paths compare equal ignoring ASCII case, as FAT names
+/// do.
+///
+/// File helpers such as `FileProtocol::open_path` take either an `EfiPath` or a `&str`.
+///
+/// ```rust,ignore
+/// let dir = EfiPath::new("/EFI/Linux/")?;
+/// let kernel = dir.join("vmlinuz.efi")?;
+/// assert_eq!(kernel, "\\efi\\linux\\VMLINUZ.EFI");
+/// assert_eq!(kernel.extension(), Some("efi"));
+/// let file = root.open_path(&kernel, EFI_FILE_MODE_READ)?;
+/// ```
+#[derive(Clone, Copy)]
+pub struct EfiPath {
+    buf: [u8; MAX_PATH_BYTES],
+    len: usize,
+}
+
+impl EfiPath {
+    /// The root directory, `\`.
+    pub fn root() -> EfiPath {
+        let mut buf = [0u8; MAX_PATH_BYTES];
+        buf[0] = b'\\';
+        EfiPath { buf, len: 1 }
+    }
+
+    /// Validate and normalize `path`, failing for paths `validate_path` refuses.
+    pub fn new(path: &str) -> Result<EfiPath, ValidationError> {
+        let mut buf = [0u8; MAX_PATH_BYTES];
+        let len = normalize_path(path, &mut buf)?.len();
+        Ok(EfiPath { buf, len })
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only `normalize_path` writes the buffer, with whole characters.
+        unsafe { str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    /// Whether the path starts at the root of the volume rather than the directory it is
+    /// opened from.
+    pub fn is_absolute(&self) -> bool {
+        self.as_str().starts_with('\\')
+    }
+
+    /// `path` relative to this one, or `path` itself if it is absolute. Either slash may be
+    /// used in `path`.
+    pub fn join(&self, path: &str) -> Result<EfiPath, ValidationError> {
+        if path.starts_with('\\') || path.starts_with('/') {
+            return EfiPath::new(path);
+        }
+
+        let mut joined = [0u8; MAX_PATH_BYTES * 2 + 1];
+        let base = self.as_str().as_bytes();
+        if base.len() + 1 + path.len() > joined.len() {
+            return Err(ValidationError::TooLong { max: MAX_FILE_PATH - 1 });
+        }
+        joined[..base.len()].copy_from_slice(base);
+        joined[base.len()] = b'\\';
+        joined[base.len() + 1..base.len() + 1 + path.len()].copy_from_slice(path.as_bytes());
+
+        // Both parts are whole strings and the separator is ASCII.
+        EfiPath::new(unsafe { str::from_utf8_unchecked(&joined[..base.len() + 1 + path.len()]) })
+    }
+
+    /// The directory the path is in: `\EFI` for `\EFI\BOOT`, and the root for `\EFI`. `None`
+    /// for the root and for a single relative name.
+    pub fn parent(&self) -> Option<EfiPath> {
+        let path = self.as_str();
+        match path.rfind('\\') {
+            _ if path == "\\" => None,
+            Some(0) => Some(EfiPath::root()),
+            Some(end) => {
+                let mut parent = *self;
+                parent.len = end;
+                Some(parent)
+            }
+            None => None,
+        }
+    }
+
+    /// The last name in the path, or `None` for the root.
+    pub fn file_name(&self) -> Option<&str> {
+        self.as_str().rsplit('\\').next().filter(|name| !name.is_empty())
+    }
+
+    /// What follows the last `.` of the file name, unless the name starts with its only `.`.
+    pub fn extension(&self) -> Option<&str> {
+        let name = self.file_name()?;
+        match name.rfind('.') {
+            Some(0) | None => None,
+            Some(dot) => Some(&name[dot + 1..]),
+        }
+    }
+}
+
+impl AsRef<str> for EfiPath {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for EfiPath {
+    fn eq(&self, other: &EfiPath) -> bool {
+        self.as_str().eq_ignore_ascii_case(other.as_str())
+    }
+}
+
+impl Eq for EfiPath {}
+
+/// Compare with `other` as a path, normalized. A string that isn't a valid path is not equal to
+/// any `EfiPath`.
+impl PartialEq<str> for EfiPath {
+    fn eq(&self, other: &str) -> bool {
+        EfiPath::new(other).map(|other| *self == other).unwrap_or(false)
+    }
+}
+
+impl<'a> PartialEq<&'a str> for EfiPath {
+    fn eq(&self, other: &&'a str) -> bool {
+        *self == **other
+    }
+}
+
+impl fmt::Display for EfiPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for EfiPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EfiPath({:?})", self.as_str())
+    }
+}
+
+#[test]
+fn efi_paths() {
+    let dir = EfiPath::new("/EFI//Linux/").unwrap();
+    assert_eq!(dir.as_str(), "\\EFI\\Linux");
+    assert!(dir.is_absolute());
+
+    let kernel = dir.join("vmlinuz.efi").unwrap();
+    assert_eq!(kernel.as_str(), "\\EFI\\Linux\\vmlinuz.efi");
+    assert_eq!(kernel, "\\efi\\linux\\VMLINUZ.EFI");
+    assert_eq!(kernel, "/EFI/Linux/vmlinuz.efi");
+    assert!(kernel != "\\EFI\\vmlinuz.efi");
+    assert_eq!(kernel.file_name(), Some("vmlinuz.efi"));
+    assert_eq!(kernel.extension(), Some("efi"));
+    assert_eq!(kernel.parent(), Some(dir));
+    assert_eq!(dir.join("/loader/loader.conf").unwrap().as_str(), "\\loader\\loader.conf");
+
+    assert_eq!(dir.parent().unwrap().parent(), Some(EfiPath::root()));
+    assert_eq!(EfiPath::root().parent(), None);
+    assert_eq!(EfiPath::root().file_name(), None);
+    assert_eq!(EfiPath::new("grub.cfg").unwrap().parent(), None);
+    assert_eq!(EfiPath::new("\\.hidden").unwrap().extension(), None);
+    assert_eq!(EfiPath::new("a.tar.gz").unwrap().extension(), Some("gz"));
+    assert_eq!(EfiPath::new("\\EFI\\a?b").map(|_| ()), Err(ValidationError::ForbiddenCharacter { character: '?', position: 6 }));
+
+    let name = [b'n'; 250];
+    let name = str::from_utf8(&name).unwrap();
+    assert_eq!(dir.join(name).map(|_| ()), Err(ValidationError::TooLong { max: MAX_FILE_PATH - 1 }));
+}
@@ -0,0 +1,136 @@
+//! Checked volatile access to device registers by physical address, for tools that poke at
+//! platform registers before boot.
+
+use core::{mem, ptr};
+
+use base::{MemoryType, PhysicalAddress, Status};
+use memmap::MemoryMap;
+
+/// A window onto a range of physical addresses which the memory map does not describe as RAM,
+/// with volatile accessors bounds- and alignment-checked against it.
+///
+/// The range may only overlap regions the memory map describes as memory-mapped I/O or reserved.
+/// Addresses the map leaves out are also accepted, as firmware commonly omits device MMIO that
+/// no runtime service needs.
+///
+/// ```rust,ignore
+/// let hpet = PhysMem::new(0xFED0_0000, 0x400)?;
+/// let capabilities = hpet.read64(0)?;
+/// hpet.write64(0x10, 1)?;
+/// ```
+#[derive(Debug)]
+pub struct PhysMem {
+    base: PhysicalAddress,
+    len: usize,
+}
+
+/// Check that `base..base + len` is a non-empty range that only overlaps device or reserved
+/// regions among `regions`, given as (type, start, end).
+fn check_range<I>(regions: I, base: PhysicalAddress, len: usize) -> Result<(), Status>
+    where I: IntoIterator<Item = (MemoryType, PhysicalAddress, PhysicalAddress)>
+{
+    let end = base.checked_add(len as u64).ok_or(Status::InvalidParameter)?;
+    if base == 0 || len == 0 || end > usize::MAX as u64 {
+        return Err(Status::InvalidParameter);
+    }
+
+    for (memory_type, start, region_end) in regions {
+        if start >= end || region_end <= base {
+            continue;
+        }
+        match memory_type {
+            MemoryType::MemoryMappedIo | MemoryType::MemoryMappedIoPortSpace | MemoryType::Reserved => {}
+            _ => return Err(Status::AccessDenied),
+        }
+    }
+    Ok(())
+}
+
+impl PhysMem {
+    /// Open `len` bytes at `base`, checking the range against the current memory map. Fails with
+    /// `Status::AccessDenied` if it overlaps RAM of any kind, and `Status::InvalidParameter` if
+    /// it is empty, starts at 0 or is not addressable.
+    pub fn new(base: PhysicalAddress, len: usize) -> Result<PhysMem, Status> {
+        let map = MemoryMap::get()?;
+        check_range(map.iter().map(|d| (d.memory_type(), d.physical_start(), d.physical_end())), base, len)?;
+        Ok(PhysMem { base, len })
+    }
+
+    pub fn base(&self) -> PhysicalAddress {
+        self.base
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The address of a `T` at `offset`, which must be inside the window and aligned.
+    fn register<T>(&self, offset: usize) -> Result<*mut T, Status> {
+        let size = mem::size_of::<T>();
+        if offset.checked_add(size).map_or(true, |end| end > self.len) {
+            return Err(Status::InvalidParameter);
+        }
+        let address = self.base as usize + offset;
+        if address % size != 0 {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(address as *mut T)
+    }
+
+    pub fn read8(&self, offset: usize) -> Result<u8, Status> {
+        self.register(offset).map(|r| unsafe { ptr::read_volatile(r) })
+    }
+
+    pub fn read16(&self, offset: usize) -> Result<u16, Status> {
+        self.register(offset).map(|r| unsafe { ptr::read_volatile(r) })
+    }
+
+    pub fn read32(&self, offset: usize) -> Result<u32, Status> {
+        self.register(offset).map(|r| unsafe { ptr::read_volatile(r) })
+    }
+
+    pub fn read64(&self, offset: usize) -> Result<u64, Status> {
+        self.register(offset).map(|r| unsafe { ptr::read_volatile(r) })
+    }
+
+    pub fn write8(&self, offset: usize, value: u8) -> Result<(), Status> {
+        self.register(offset).map(|r| unsafe { ptr::write_volatile(r, value) })
+    }
+
+    pub fn write16(&self, offset: usize, value: u16) -> Result<(), Status> {
+        self.register(offset).map(|r| unsafe { ptr::write_volatile(r, value) })
+    }
+
+    pub fn write32(&self, offset: usize, value: u32) -> Result<(), Status> {
+        self.register(offset).map(|r| unsafe { ptr::write_volatile(r, value) })
+    }
+
+    pub fn write64(&self, offset: usize, value: u64) -> Result<(), Status> {
+        self.register(offset).map(|r| unsafe { ptr::write_volatile(r, value) })
+    }
+}
+
+#[test]
+fn physmem_range_checks() {
+    let map = [
+        (MemoryType::Conventional, 0x0, 0xA0000),
+        (MemoryType::Reserved, 0xA0000, 0x100000),
+        (MemoryType::MemoryMappedIo, 0xFED00000, 0xFED01000),
+    ];
+    assert_eq!(check_range(map.iter().cloned(), 0xFED00000, 0x400), Ok(()));
+    assert_eq!(check_range(map.iter().cloned(), 0xB8000, 0x1000), Ok(()));
+    // Not in the map at all.
+    assert_eq!(check_range(map.iter().cloned(), 0xFEC00000, 0x1000), Ok(()));
+    assert_eq!(check_range(map.iter().cloned(), 0x9F000, 0x2000), Err(Status::AccessDenied));
+    assert_eq!(check_range(map.iter().cloned(), 0, 0x10), Err(Status::InvalidParameter));
+    assert_eq!(check_range(map.iter().cloned(), 0xFED00000, 0), Err(Status::InvalidParameter));
+
+    let window = PhysMem { base: 0xFED00000, len: 0x400 };
+    assert_eq!(window.register::<u32>(0x3FC).map(|r| r as usize), Ok(0xFED003FC));
+    assert_eq!(window.register::<u32>(0x3FE).unwrap_err(), Status::InvalidParameter);
+    assert_eq!(window.register::<u64>(0x404).unwrap_err(), Status::InvalidParameter);
+}
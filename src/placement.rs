@@ -0,0 +1,264 @@
+use base::{MemoryType, PhysicalAddress, Status};
+use bootservices::AllocateType;
+use memmap::{MemoryMap, EFI_PAGE_SIZE};
+use random::{random_u64, RandomQuality};
+
+/// Most reservations a `PlacementAllocator` keeps track of.
+pub const MAX_RESERVATIONS: usize = 32;
+
+/// Most free regions `PlacementAllocator::allocate` considers.
+const MAX_CANDIDATES: usize = 64;
+
+/// Random addresses `PlacementAllocator::allocate_random` tries before giving up, in case
+/// others allocate from the map between reading it and claiming the address.
+const RANDOM_ATTEMPTS: usize = 4;
+
+/// A region claimed by a `PlacementAllocator`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reservation {
+    pub address: PhysicalAddress,
+    pub pages: usize,
+    pub memory_type: MemoryType,
+}
+
+impl Reservation {
+    pub fn size(&self) -> u64 {
+        self.pages as u64 * EFI_PAGE_SIZE
+    }
+
+    pub fn end(&self) -> PhysicalAddress {
+        self.address + self.size()
+    }
+}
+
+fn pages_for(size: u64) -> u64 {
+    size / EFI_PAGE_SIZE + (size % EFI_PAGE_SIZE != 0) as u64
+}
+
+fn align_up(value: u64, align: u64) -> Option<u64> {
+    Some(value.checked_add(align - 1)? & !(align - 1))
+}
+
+/// The aligned start addresses at which `size` bytes fit in `start..end`, as (first, count).
+fn slots(start: u64, end: u64, size: u64, align: u64) -> (u64, u64) {
+    let first = match align_up(start, align) {
+        Some(first) => first,
+        None => return (0, 0),
+    };
+    match end.checked_sub(size) {
+        Some(last) if last >= first => (first, (last - first) / align + 1),
+        _ => (0, 0),
+    }
+}
+
+/// Pick a uniformly random address among every `align`-aligned place where `size` bytes fit
+/// inside one of the free `regions`, given as (start, end), within `window`. `random` chooses
+/// the address; each place is equally likely, up to a bias of one part in 2^64 over the number
+/// of places. Returns `None` if nothing fits.
+///
+/// This is the choice behind `PlacementAllocator::allocate_random`, for loaders that place a
+/// kernel themselves, e.g. from the final memory map after boot services are exited.
+pub fn random_placement<I>(regions: I, size: u64, align: u64, window: (PhysicalAddress, PhysicalAddress), random: u64) -> Option<PhysicalAddress>
+    where I: IntoIterator<Item = (PhysicalAddress, PhysicalAddress)> + Clone
+{
+    if size == 0 || !align.is_power_of_two() {
+        return None;
+    }
+    let clamp = |(start, end): (u64, u64)| (start.max(window.0), end.min(window.1));
+
+    let total: u64 = regions.clone().into_iter().map(|r| {
+        let (start, end) = clamp(r);
+        slots(start, end, size, align).1
+    }).sum();
+    if total == 0 {
+        return None;
+    }
+
+    // Scale rather than take the remainder, which would favour low slots.
+    let mut index = ((random as u128 * total as u128) >> 64) as u64;
+    for r in regions {
+        let (start, end) = clamp(r);
+        let (first, count) = slots(start, end, size, align);
+        if index < count {
+            return Some(first + index * align);
+        }
+        index -= count;
+    }
+    None
+}
+
+/// Places kernels and other payloads in conventional memory under constraints AllocatePages
+/// can't express directly, such as 2MiB or 1GiB alignment, and keeps a record of everything it
+/// claimed so the handoff memory map can describe it.
+///
+/// Candidates are found in the memory map, then claimed with AllocatePages at their exact
+/// address, so the firmware stays the authority on what is free.
+pub struct PlacementAllocator {
+    memory_type: MemoryType,
+    reservations: [Option<Reservation>; MAX_RESERVATIONS],
+}
+
+impl PlacementAllocator {
+    /// Claim memory as `memory_type`, e.g. `LoaderData`, or an OEM type to make the regions easy
+    /// to find in the final memory map.
+    pub fn new(memory_type: MemoryType) -> PlacementAllocator {
+        PlacementAllocator {
+            memory_type,
+            reservations: [None; MAX_RESERVATIONS],
+        }
+    }
+
+    /// The regions claimed so far.
+    pub fn reservations(&self) -> impl Iterator<Item = &Reservation> {
+        self.reservations.iter().filter_map(|r| r.as_ref())
+    }
+
+    fn record(&mut self, reservation: Reservation) -> Result<Reservation, Status> {
+        match self.reservations.iter_mut().find(|r| r.is_none()) {
+            Some(slot) => {
+                *slot = Some(reservation);
+                Ok(reservation)
+            }
+            None => {
+                ::get_system_table().boot_services().free_pages(reservation.address, reservation.pages);
+                Err(Status::OutOfResources)
+            }
+        }
+    }
+
+    /// The largest free region of conventional memory, as (address, size in bytes).
+    pub fn largest_free_region(&self) -> Result<(PhysicalAddress, u64), Status> {
+        let map = MemoryMap::get()?;
+
+        map.iter()
+            .filter(|d| d.memory_type() == MemoryType::Conventional)
+            .map(|d| (d.physical_start(), d.number_of_pages() * EFI_PAGE_SIZE))
+            .max_by_key(|&(_, size)| size)
+            .ok_or(Status::OutOfResources)
+    }
+
+    /// Claim the whole of the largest free region.
+    pub fn claim_largest(&mut self) -> Result<Reservation, Status> {
+        let (address, size) = self.largest_free_region()?;
+        self.allocate_at(address, size)
+    }
+
+    /// Claim `size` bytes at exactly `address`, which must be page aligned.
+    pub fn allocate_at(&mut self, address: PhysicalAddress, size: u64) -> Result<Reservation, Status> {
+        if address % EFI_PAGE_SIZE != 0 || size == 0 {
+            return Err(Status::InvalidParameter);
+        }
+
+        let pages = pages_for(size) as usize;
+        let bs = ::get_system_table().boot_services();
+        let address = bs.allocate_pages(AllocateType::Address(address), self.memory_type, pages)?;
+
+        self.record(Reservation { address, pages, memory_type: self.memory_type })
+    }
+
+    /// Claim `size` bytes aligned to `align` (a power of two, at least a page), ending at or below
+    /// `limit` if given. The lowest suitable address is used.
+    pub fn allocate(&mut self, size: u64, align: u64, limit: Option<PhysicalAddress>) -> Result<Reservation, Status> {
+        if size == 0 || !align.is_power_of_two() || align < EFI_PAGE_SIZE {
+            return Err(Status::InvalidParameter);
+        }
+
+        let size = pages_for(size) * EFI_PAGE_SIZE;
+        let limit = limit.unwrap_or(u64::MAX);
+
+        // There is no allocator for a Vec, so collect candidates into a fixed array.
+        let mut candidates = [0u64; MAX_CANDIDATES];
+        let mut count = 0;
+        {
+            let map = MemoryMap::get()?;
+            for d in map.iter().filter(|d| d.memory_type() == MemoryType::Conventional) {
+                if count == MAX_CANDIDATES {
+                    break;
+                }
+
+                let start = match align_up(d.physical_start(), align) {
+                    Some(start) => start,
+                    None => continue,
+                };
+                match start.checked_add(size) {
+                    Some(end) if end <= d.physical_end() && end <= limit => {
+                        candidates[count] = start;
+                        count += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        candidates[..count].sort_unstable();
+        for &address in &candidates[..count] {
+            // Another allocation may have taken the region in the meantime; try the next one.
+            if let Ok(reservation) = self.allocate_at(address, size) {
+                return Ok(reservation);
+            }
+        }
+
+        Err(Status::OutOfResources)
+    }
+
+    /// Claim `size` bytes aligned to `align` (a power of two, at least a page) at a random
+    /// address between `window.0` and `window.1`, as for a kernel with address space layout
+    /// randomisation. Only conventional memory is considered, so reserved and runtime regions
+    /// are never chosen. The quality of the randomness used is returned along with the
+    /// reservation; see `fill_random`.
+    ///
+    /// ```rust,ignore
+    /// // A relocatable kernel, 2MiB aligned, anywhere between 16MiB and 4GiB.
+    /// let (kernel, quality) = placement.allocate_random(kernel_size, 0x20_0000, (0x100_0000, 0x1_0000_0000))?;
+    /// ```
+    pub fn allocate_random(&mut self, size: u64, align: u64, window: (PhysicalAddress, PhysicalAddress)) -> Result<(Reservation, RandomQuality), Status> {
+        if size == 0 || !align.is_power_of_two() || align < EFI_PAGE_SIZE {
+            return Err(Status::InvalidParameter);
+        }
+        let size = pages_for(size) * EFI_PAGE_SIZE;
+
+        for _ in 0..RANDOM_ATTEMPTS {
+            let (random, quality) = random_u64();
+            let address = {
+                let map = MemoryMap::get()?;
+                let free = map.iter()
+                    .filter(|d| d.memory_type() == MemoryType::Conventional)
+                    .map(|d| (d.physical_start(), d.physical_end()));
+                random_placement(free, size, align, window, random).ok_or(Status::OutOfResources)?
+            };
+            if let Ok(reservation) = self.allocate_at(address, size) {
+                return Ok((reservation, quality));
+            }
+        }
+
+        Err(Status::OutOfResources)
+    }
+
+    /// Free the reservation starting at `address`.
+    pub fn free(&mut self, address: PhysicalAddress) -> Status {
+        let slot = self.reservations.iter_mut().find(|r| r.map(|r| r.address) == Some(address));
+
+        match slot {
+            Some(slot) => {
+                let reservation = slot.take().unwrap();
+                ::get_system_table().boot_services().free_pages(reservation.address, reservation.pages)
+            }
+            None => Status::NotFound,
+        }
+    }
+}
+
+#[test]
+fn random_placement_slots() {
+    let regions = [(0x10_0000, 0x50_0000), (0x100_0000, 0x180_0000)];
+    let place = |random| random_placement(regions.iter().cloned(), 0x20_0000, 0x20_0000, (0, u64::MAX), random);
+
+    // One place fits in the first region and four in the second.
+    assert_eq!(place(0), Some(0x20_0000));
+    assert_eq!(place(u64::MAX / 5 + 1), Some(0x100_0000));
+    assert_eq!(place(u64::MAX), Some(0x160_0000));
+
+    let windowed = random_placement(regions.iter().cloned(), 0x20_0000, 0x20_0000, (0x110_0000, 0x140_0000), 0);
+    assert_eq!(windowed, Some(0x120_0000));
+    assert_eq!(random_placement(regions.iter().cloned(), 0x100_0000, 0x20_0000, (0, u64::MAX), 0), None);
+}
@@ -0,0 +1,114 @@
+//! x86 I/O port access, for diagnostics talking to legacy devices such as the PS/2 controller,
+//! the RTC and CMOS, or the POST code port. Built with the `port-io` feature on x86.
+//!
+//! Ports are not checked against anything: writing to the wrong one can hang or damage the
+//! machine, which is why the accessors are unsafe.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// The port POST codes are written to, shown on debug cards and some boards' displays.
+pub const POST_CODE_PORT: u16 = 0x80;
+
+/// POST codes remembered by `post_code`.
+pub const POST_CODE_HISTORY: usize = 32;
+
+/// Read a byte from `port`.
+///
+/// # Safety
+///
+/// Reading a port can have side effects on the device behind it, such as acknowledging an
+/// interrupt or consuming a byte of input; the caller must own the device.
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
+
+/// Read a 16-bit word from `port`.
+///
+/// # Safety
+///
+/// As for `inb`.
+pub unsafe fn inw(port: u16) -> u16 {
+    let value: u16;
+    asm!("in ax, dx", out("ax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
+
+/// Read a 32-bit doubleword from `port`.
+///
+/// # Safety
+///
+/// As for `inb`.
+pub unsafe fn inl(port: u16) -> u32 {
+    let value: u32;
+    asm!("in eax, dx", out("eax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
+
+/// Write a byte to `port`.
+///
+/// # Safety
+///
+/// The caller must own the device behind `port` and know what writing `value` to it does;
+/// some ports control reset, power or the memory controller.
+pub unsafe fn outb(port: u16, value: u8) {
+    asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Write a 16-bit word to `port`.
+///
+/// # Safety
+///
+/// As for `outb`.
+pub unsafe fn outw(port: u16, value: u16) {
+    asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Write a 32-bit doubleword to `port`.
+///
+/// # Safety
+///
+/// As for `outb`.
+pub unsafe fn outl(port: u16, value: u32) {
+    asm!("out dx, eax", in("dx") port, in("eax") value, options(nomem, nostack, preserves_flags));
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_POST_CODE: AtomicU8 = AtomicU8::new(0);
+
+/// The codes given to `post_code`, the latest at `(POST_CODE_COUNT - 1) % POST_CODE_HISTORY`.
+/// Kept in atomics since callbacks can report codes too, preempting one in progress.
+static POST_CODES: [AtomicU8; POST_CODE_HISTORY] = [NO_POST_CODE; POST_CODE_HISTORY];
+
+/// Codes given to `post_code` so far.
+static POST_CODE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Show `code` on the POST code port, marking progress where a debug card or the board's own
+/// display can see it even if the screen is dead, and remember it for `post_code_history`.
+pub fn post_code(code: u8) {
+    // Port 0x80 is reserved for POST codes on PCs; writing it has no other effect.
+    unsafe { outb(POST_CODE_PORT, code) };
+
+    let n = POST_CODE_COUNT.fetch_add(1, Ordering::Relaxed);
+    POST_CODES[n % POST_CODE_HISTORY].store(code, Ordering::Relaxed);
+}
+
+/// The last `POST_CODE_HISTORY` codes given to `post_code`, oldest first, copied into `out`.
+/// Returns how many there were.
+///
+/// ```rust,ignore
+/// let mut codes = [0; POST_CODE_HISTORY];
+/// let n = post_code_history(&mut codes);
+/// println!("last POST codes: {:02X?}", &codes[..n]);
+/// ```
+pub fn post_code_history(out: &mut [u8; POST_CODE_HISTORY]) -> usize {
+    // A code reported by a callback while this runs may show up in place of the oldest.
+    let total = POST_CODE_COUNT.load(Ordering::Relaxed);
+    let count = total.min(POST_CODE_HISTORY);
+    for (i, code) in out.iter_mut().take(count).enumerate() {
+        *code = POST_CODES[(total - count + i) % POST_CODE_HISTORY].load(Ordering::Relaxed);
+    }
+    count
+}
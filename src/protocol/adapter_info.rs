@@ -0,0 +1,285 @@
+use core::{mem, ptr, slice};
+
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+use util::wire;
+use void::CVoid;
+
+/// GUID for the adapter information protocol
+pub static EFI_ADAPTER_INFORMATION_PROTOCOL_GUID: Guid = Guid(0xE5DD1403, 0xD622, 0xC24E, [0x84, 0x88, 0xC7, 0x1B, 0x17, 0xF5, 0xE8, 0x02]);
+
+/// Information type GUIDs defined by the UEFI specification.
+pub static EFI_ADAPTER_INFO_MEDIA_STATE_GUID: Guid = Guid(0xD7C74207, 0xA831, 0x4A26, [0xB1, 0xF5, 0xD1, 0x93, 0x06, 0x5C, 0xE8, 0xB6]);
+pub static EFI_ADAPTER_INFO_NETWORK_BOOT_GUID: Guid = Guid(0x1FBD2960, 0x4130, 0x41E5, [0x94, 0xAC, 0xD2, 0xCF, 0x03, 0x7F, 0xB3, 0x7C]);
+pub static EFI_ADAPTER_INFO_SAN_MAC_ADDRESS_GUID: Guid = Guid(0x114DA5EF, 0x2CF1, 0x4E12, [0x9B, 0xBB, 0xC4, 0x70, 0xB5, 0x52, 0x05, 0xD9]);
+pub static EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT_GUID: Guid = Guid(0x4BD56BE3, 0x4975, 0x4D8A, [0xA0, 0xAD, 0xC4, 0x91, 0x20, 0x4B, 0x5D, 0x4D]);
+pub static EFI_ADAPTER_INFO_MEDIA_TYPE_GUID: Guid = Guid(0x8484472F, 0x71EC, 0x411A, [0xB3, 0x9C, 0x62, 0xCD, 0x94, 0xD9, 0x91, 0x6E]);
+
+/// A type of adapter information, identified by a GUID, and how to decode its block. The
+/// standard types implement this; vendor types can be added by implementing it too.
+///
+/// ```rust,ignore
+/// struct AcmeBatteryInfo { millivolts: u16 }
+///
+/// impl AdapterInfoType for AcmeBatteryInfo {
+///     fn guid() -> &'static Guid { &ACME_BATTERY_INFO_GUID }
+///     fn parse(block: &[u8]) -> Result<Self, Status> {
+///         Ok(AcmeBatteryInfo { millivolts: wire::read_u16(block, 0)? })
+///     }
+/// }
+///
+/// let battery: AcmeBatteryInfo = adapter.get()?;
+/// ```
+pub trait AdapterInfoType: Sized {
+    fn guid() -> &'static Guid;
+
+    /// Decode an information block, failing with `Status::BadBufferSize` or another error if
+    /// it is malformed.
+    fn parse(block: &[u8]) -> Result<Self, Status>;
+}
+
+/// EFI_ADAPTER_INFO_MEDIA_STATE: whether media, such as a network cable, is attached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdapterMediaState {
+    /// Media is present.
+    Present,
+    /// Whether media is present can't be told yet, for example during autonegotiation.
+    NotReady,
+    NoMedia,
+    /// Another status the driver reported, as a raw EFI_STATUS.
+    Other(u64),
+}
+
+impl AdapterInfoType for AdapterMediaState {
+    fn guid() -> &'static Guid {
+        &EFI_ADAPTER_INFO_MEDIA_STATE_GUID
+    }
+
+    fn parse(block: &[u8]) -> Result<AdapterMediaState, Status> {
+        let raw = match mem::size_of::<usize>() {
+            4 => wire::read_u32(block, 0)? as u64,
+            _ => wire::read_u64(block, 0)?,
+        };
+        Ok(match raw {
+            r if r == Status::Success as u64 => AdapterMediaState::Present,
+            r if r == Status::NotReady as u64 => AdapterMediaState::NotReady,
+            r if r == Status::NoMedia as u64 => AdapterMediaState::NoMedia,
+            r => AdapterMediaState::Other(r),
+        })
+    }
+}
+
+/// EFI_ADAPTER_INFO_NETWORK_BOOT: the SAN boot capabilities of a network adapter, and which
+/// are enabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AdapterNetworkBoot {
+    pub iscsi_ipv4_boot_capable: bool,
+    pub iscsi_ipv6_boot_capable: bool,
+    pub fcoe_boot_capable: bool,
+    pub offload_capable: bool,
+    pub iscsi_mpio_capable: bool,
+    pub iscsi_ipv4_boot: bool,
+    pub iscsi_ipv6_boot: bool,
+    pub fcoe_boot: bool,
+}
+
+impl AdapterInfoType for AdapterNetworkBoot {
+    fn guid() -> &'static Guid {
+        &EFI_ADAPTER_INFO_NETWORK_BOOT_GUID
+    }
+
+    fn parse(block: &[u8]) -> Result<AdapterNetworkBoot, Status> {
+        let flag = |i| wire::read_u8(block, i).map(|b| b != 0);
+        Ok(AdapterNetworkBoot {
+            iscsi_ipv4_boot_capable: flag(0)?,
+            iscsi_ipv6_boot_capable: flag(1)?,
+            fcoe_boot_capable: flag(2)?,
+            offload_capable: flag(3)?,
+            iscsi_mpio_capable: flag(4)?,
+            iscsi_ipv4_boot: flag(5)?,
+            iscsi_ipv6_boot: flag(6)?,
+            fcoe_boot: flag(7)?,
+        })
+    }
+}
+
+/// EFI_ADAPTER_INFO_SAN_MAC_ADDRESS: the MAC address the adapter uses for SAN boot, as an
+/// EFI_MAC_ADDRESS, padded with zeroes to 32 bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdapterSanMacAddress(pub [u8; 32]);
+
+impl AdapterSanMacAddress {
+    /// The address of an Ethernet adapter.
+    pub fn ethernet(&self) -> [u8; 6] {
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&self.0[..6]);
+        mac
+    }
+}
+
+impl AdapterInfoType for AdapterSanMacAddress {
+    fn guid() -> &'static Guid {
+        &EFI_ADAPTER_INFO_SAN_MAC_ADDRESS_GUID
+    }
+
+    fn parse(block: &[u8]) -> Result<AdapterSanMacAddress, Status> {
+        let mut mac = [0; 32];
+        mac.copy_from_slice(block.get(..32).ok_or(Status::BadBufferSize)?);
+        Ok(AdapterSanMacAddress(mac))
+    }
+}
+
+/// EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT: whether the adapter's UNDI driver supports IPv6.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdapterUndiIpv6Support(pub bool);
+
+impl AdapterInfoType for AdapterUndiIpv6Support {
+    fn guid() -> &'static Guid {
+        &EFI_ADAPTER_INFO_UNDI_IPV6_SUPPORT_GUID
+    }
+
+    fn parse(block: &[u8]) -> Result<AdapterUndiIpv6Support, Status> {
+        Ok(AdapterUndiIpv6Support(wire::read_u8(block, 0)? != 0))
+    }
+}
+
+/// EFI_ADAPTER_INFO_MEDIA_TYPE: the kind of network the adapter attaches to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdapterMediaType {
+    Ethernet,
+    WirelessEthernet,
+    /// A type the specification reserves or defines after this crate.
+    Other(u8),
+}
+
+impl AdapterInfoType for AdapterMediaType {
+    fn guid() -> &'static Guid {
+        &EFI_ADAPTER_INFO_MEDIA_TYPE_GUID
+    }
+
+    fn parse(block: &[u8]) -> Result<AdapterMediaType, Status> {
+        Ok(match wire::read_u8(block, 0)? {
+            1 => AdapterMediaType::Ethernet,
+            2 => AdapterMediaType::WirelessEthernet,
+            t => AdapterMediaType::Other(t),
+        })
+    }
+}
+
+/// An information block returned by `AdapterInformationProtocol::get_raw`, in pool memory
+/// allocated by the firmware, which is freed when this is dropped.
+pub struct AdapterInfoBlock {
+    buffer: *mut u8,
+    len: usize,
+}
+
+impl AdapterInfoBlock {
+    pub fn as_slice(&self) -> &[u8] {
+        if self.buffer.is_null() {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.buffer, self.len) }
+    }
+}
+
+impl Drop for AdapterInfoBlock {
+    fn drop(&mut self) {
+        if !self.buffer.is_null() {
+            ::get_system_table().boot_services().free_pool(self.buffer);
+        }
+    }
+}
+
+/// EFI_ADAPTER_INFORMATION_PROTOCOL, found on network and storage adapters, giving typed
+/// blocks of information about the adapter.
+///
+/// ```rust,ignore
+/// let adapter: &AdapterInformationProtocol = bs.handle_protocol(nic)?;
+/// if adapter.get::<AdapterMediaState>()? == AdapterMediaState::NoMedia {
+///     println!("cable unplugged");
+/// }
+/// ```
+#[repr(C)]
+pub struct AdapterInformationProtocol {
+    get_information: unsafe extern "win64" fn(this: *const AdapterInformationProtocol, information_type: *const Guid, information_block: *mut *mut CVoid, information_block_size: *mut usize) -> Status,
+    set_information: unsafe extern "win64" fn(this: *const AdapterInformationProtocol, information_type: *const Guid, information_block: *const CVoid, information_block_size: usize) -> Status,
+    get_supported_types: unsafe extern "win64" fn(this: *const AdapterInformationProtocol, info_types_buffer: *mut *mut Guid, info_types_buffer_count: *mut usize) -> Status,
+}
+
+impl Protocol for AdapterInformationProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_ADAPTER_INFORMATION_PROTOCOL_GUID
+    }
+}
+
+impl AdapterInformationProtocol {
+    /// The information block of type `information_type`. Fails with `Status::Unsupported` if
+    /// the adapter does not have it.
+    pub fn get_raw(&self, information_type: &Guid) -> Result<AdapterInfoBlock, Status> {
+        let mut buffer: *mut CVoid = ptr::null_mut();
+        let mut len = 0;
+        match unsafe { (self.get_information)(self, information_type, &mut buffer, &mut len) } {
+            Status::Success => Ok(AdapterInfoBlock { buffer: buffer as *mut u8, len }),
+            e => Err(e),
+        }
+    }
+
+    /// The information of type `T`, decoded.
+    pub fn get<T: AdapterInfoType>(&self) -> Result<T, Status> {
+        T::parse(self.get_raw(T::guid())?.as_slice())
+    }
+
+    /// Set the information block of type `information_type`. Most types are read-only, and
+    /// fail with `Status::WriteProtected`.
+    pub fn set_raw(&self, information_type: &Guid, block: &[u8]) -> Result<(), Status> {
+        match unsafe { (self.set_information)(self, information_type, block.as_ptr() as *const CVoid, block.len()) } {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Copy the information types the adapter supports into `types`, returning how many there
+    /// are. If `types` is too small, it is filled and the full count is still returned.
+    pub fn supported_types(&self, types: &mut [Guid]) -> Result<usize, Status> {
+        let mut buffer: *mut Guid = ptr::null_mut();
+        let mut count = 0;
+        match unsafe { (self.get_supported_types)(self, &mut buffer, &mut count) } {
+            Status::Success => {}
+            e => return Err(e),
+        }
+        if !buffer.is_null() {
+            let supported = unsafe { slice::from_raw_parts(buffer, count) };
+            for (slot, guid) in types.iter_mut().zip(supported) {
+                *slot = *guid;
+            }
+            ::get_system_table().boot_services().free_pool(buffer);
+        }
+        Ok(count)
+    }
+
+    /// Whether the adapter has information of type `T`.
+    pub fn supports<T: AdapterInfoType>(&self) -> bool {
+        let mut buffer: *mut Guid = ptr::null_mut();
+        let mut count = 0;
+        if unsafe { (self.get_supported_types)(self, &mut buffer, &mut count) } != Status::Success || buffer.is_null() {
+            return false;
+        }
+        let found = unsafe { slice::from_raw_parts(buffer, count) }.contains(T::guid());
+        ::get_system_table().boot_services().free_pool(buffer);
+        found
+    }
+}
+
+#[test]
+fn adapter_info_parse() {
+    let mut state = [0u8; 8];
+    assert_eq!(AdapterMediaState::parse(&state[..mem::size_of::<usize>()]), Ok(AdapterMediaState::Present));
+    state[..mem::size_of::<usize>()].copy_from_slice(&(Status::NoMedia as u64).to_le_bytes()[..mem::size_of::<usize>()]);
+    assert_eq!(AdapterMediaState::parse(&state[..mem::size_of::<usize>()]), Ok(AdapterMediaState::NoMedia));
+    assert!(AdapterMediaState::parse(&state[..2]).is_err());
+
+    let boot = AdapterNetworkBoot::parse(&[1, 0, 0, 1, 0, 1, 0, 0]).unwrap();
+    assert!(boot.iscsi_ipv4_boot_capable && boot.offload_capable && boot.iscsi_ipv4_boot && !boot.fcoe_boot);
+    assert_eq!(AdapterMediaType::parse(&[2]), Ok(AdapterMediaType::WirelessEthernet));
+}
@@ -0,0 +1,125 @@
+//! Protocols only found on Apple firmware: the device properties database, which macOS reads
+//! its graphics and audio configuration from, and "set OS", through which a loader tells the
+//! firmware macOS is about to start. Built with the `apple` feature.
+
+use base::Status;
+use guid::Guid;
+use protocol::{DevicePathProtocol, Protocol};
+use util::ucs2;
+use void::{CVoid, NotYetDef};
+
+/// GUID for the Apple device properties protocol (EFI_DEVICE_PATH_PROPERTY_DATABASE_PROTOCOL)
+pub static APPLE_DEVICE_PROPERTIES_PROTOCOL_GUID: Guid = Guid(0x91BD12FE, 0xF6C3, 0x44FB, [0xA5, 0xB7, 0x51, 0x22, 0xAB, 0x30, 0x3A, 0xE0]);
+
+/// GUID for the Apple set OS protocol
+pub static APPLE_SET_OS_PROTOCOL_GUID: Guid = Guid(0xC5C5DA95, 0x7D5C, 0x45E6, [0xB2, 0xF1, 0x3F, 0xD5, 0x2B, 0xB1, 0x00, 0x77]);
+
+/// Longest property name, in UCS-2 units without the terminator.
+const MAX_PROPERTY_NAME: usize = 63;
+
+/// Longest string passed to `AppleSetOsProtocol`, without the terminator.
+const MAX_SET_OS_STRING: usize = 63;
+
+/// The properties, such as "AAPL,ig-platform-id" or "layout-id", which Apple firmware keeps per
+/// device path and hands to macOS. Setting them before starting `boot.efi` is how device
+/// configuration is injected.
+#[repr(C)]
+pub struct AppleDevicePropertiesProtocol {
+    pub revision: u64,
+    get_property: unsafe extern "win64" fn(this: *const AppleDevicePropertiesProtocol, device: *const DevicePathProtocol, name: *const u16, value: *mut CVoid, size: *mut usize) -> Status,
+    set_property: unsafe extern "win64" fn(this: *const AppleDevicePropertiesProtocol, device: *const DevicePathProtocol, name: *const u16, value: *const CVoid, size: usize) -> Status,
+    remove_property: unsafe extern "win64" fn(this: *const AppleDevicePropertiesProtocol, device: *const DevicePathProtocol, name: *const u16) -> Status,
+    get_property_buffer: *const NotYetDef,
+}
+
+impl Protocol for AppleDevicePropertiesProtocol {
+    fn guid() -> &'static Guid {
+        &APPLE_DEVICE_PROPERTIES_PROTOCOL_GUID
+    }
+}
+
+fn property_name<'a>(name: &str, buf: &'a mut [u16; MAX_PROPERTY_NAME + 1]) -> Result<&'a [u16], Status> {
+    let len = ucs2::write_fmt(buf, format_args!("{}", name)).map_err(|_| Status::InvalidParameter)?;
+    Ok(&buf[..len + 1])
+}
+
+impl AppleDevicePropertiesProtocol {
+    /// Read property `name` of `device` into `value`, returning its size. If `value` is too
+    /// small, `Status::BufferTooSmall` is returned and nothing is read.
+    pub fn get_property(&self, device: &DevicePathProtocol, name: &str, value: &mut [u8]) -> Result<usize, Status> {
+        let mut name_buf = [0u16; MAX_PROPERTY_NAME + 1];
+        let name = property_name(name, &mut name_buf)?;
+        let mut size = value.len();
+
+        match unsafe { (self.get_property)(self, device, name.as_ptr(), value.as_mut_ptr() as *mut CVoid, &mut size) } {
+            Status::Success => Ok(size),
+            status => Err(status),
+        }
+    }
+
+    /// Create or replace property `name` of `device`.
+    pub fn set_property(&self, device: &DevicePathProtocol, name: &str, value: &[u8]) -> Status {
+        let mut name_buf = [0u16; MAX_PROPERTY_NAME + 1];
+        let name = match property_name(name, &mut name_buf) {
+            Ok(name) => name,
+            Err(e) => return e,
+        };
+
+        unsafe { (self.set_property)(self, device, name.as_ptr(), value.as_ptr() as *const CVoid, value.len()) }
+    }
+
+    pub fn remove_property(&self, device: &DevicePathProtocol, name: &str) -> Status {
+        let mut name_buf = [0u16; MAX_PROPERTY_NAME + 1];
+        let name = match property_name(name, &mut name_buf) {
+            Ok(name) => name,
+            Err(e) => return e,
+        };
+
+        unsafe { (self.remove_property)(self, device, name.as_ptr()) }
+    }
+}
+
+/// Apple's "set OS" protocol. Firmware on Macs with two GPUs powers off the integrated one
+/// unless it is told, before `ExitBootServices`, that macOS is starting.
+#[repr(C)]
+pub struct AppleSetOsProtocol {
+    pub version: u64,
+    set_os_version: unsafe extern "win64" fn(version: *const u8) -> Status,
+    set_os_vendor: unsafe extern "win64" fn(vendor: *const u8) -> Status,
+}
+
+impl Protocol for AppleSetOsProtocol {
+    fn guid() -> &'static Guid {
+        &APPLE_SET_OS_PROTOCOL_GUID
+    }
+}
+
+/// Copy `s` into `buf` as a nul-terminated ASCII string.
+fn set_os_string(s: &str, buf: &mut [u8; MAX_SET_OS_STRING + 1]) -> Result<(), Status> {
+    if s.len() > MAX_SET_OS_STRING || !s.is_ascii() || s.contains('\0') {
+        return Err(Status::InvalidParameter);
+    }
+    buf[..s.len()].copy_from_slice(s.as_bytes());
+    buf[s.len()] = 0;
+    Ok(())
+}
+
+impl AppleSetOsProtocol {
+    /// Tell the firmware which OS version is starting, e.g. "Mac OS X 10.15".
+    pub fn set_os_version(&self, version: &str) -> Status {
+        let mut buf = [0u8; MAX_SET_OS_STRING + 1];
+        if let Err(e) = set_os_string(version, &mut buf) {
+            return e;
+        }
+        unsafe { (self.set_os_version)(buf.as_ptr()) }
+    }
+
+    /// Tell the firmware whose OS is starting, e.g. "Apple Inc.".
+    pub fn set_os_vendor(&self, vendor: &str) -> Status {
+        let mut buf = [0u8; MAX_SET_OS_STRING + 1];
+        if let Err(e) = set_os_string(vendor, &mut buf) {
+            return e;
+        }
+        unsafe { (self.set_os_vendor)(buf.as_ptr()) }
+    }
+}
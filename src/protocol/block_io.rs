@@ -0,0 +1,491 @@
+use core::{ptr, slice};
+
+use base::{status_to_result, Event, Status};
+use entry::Termination;
+use guid::Guid;
+use protocol::Protocol;
+use void::CVoid;
+
+/// GUID for the block I/O protocol
+pub static EFI_BLOCK_IO_PROTOCOL_GUID: Guid = Guid(0x964E5B21, 0x6459, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+
+/// GUID for the asynchronous block I/O protocol
+pub static EFI_BLOCK_IO2_PROTOCOL_GUID: Guid = Guid(0xA77B2472, 0xE282, 0x4E9F, [0xA2, 0x45, 0xC2, 0xC0, 0xE2, 0x7B, 0xBC, 0xC1]);
+
+/// Most reads `BlockIo2Protocol::read_pipelined` keeps in flight.
+pub const MAX_PIPELINE_DEPTH: usize = 8;
+
+/// Bytes per read in `BlockIo2Protocol::read_pipelined` when the device does not report an
+/// optimal transfer length.
+pub const DEFAULT_PIPELINE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Revisions of `BlockIoProtocol`. The media fields after `last_block` are only valid from
+/// `EFI_BLOCK_IO_PROTOCOL_REVISION2` and `EFI_BLOCK_IO_PROTOCOL_REVISION3` respectively.
+pub const EFI_BLOCK_IO_PROTOCOL_REVISION2: u64 = 0x00020001;
+pub const EFI_BLOCK_IO_PROTOCOL_REVISION3: u64 = 0x0002001F;
+
+/// Type for EFI_BLOCK_IO_MEDIA. The fields after `last_block` are only valid for revision 2
+/// and 3 protocols respectively.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct BlockIoMedia {
+    pub media_id: u32,
+    pub removable_media: bool,
+    pub media_present: bool,
+    pub logical_partition: bool,
+    pub read_only: bool,
+    pub write_caching: bool,
+    pub block_size: u32,
+    /// Required alignment of I/O buffers in bytes; 0 or 1 for none.
+    pub io_align: u32,
+    pub last_block: u64,
+    pub lowest_aligned_lba: u64,
+    pub logical_blocks_per_physical_block: u32,
+    /// Preferred transfer length, in blocks; 0 if not reported.
+    pub optimal_transfer_length_granularity: u32,
+}
+
+impl BlockIoMedia {
+    /// Size of the media in bytes.
+    pub fn size(&self) -> u64 {
+        (self.last_block + 1) * self.block_size as u64
+    }
+
+    /// Check a transfer of `len` bytes into or out of `buf` suits the media: whole blocks, from
+    /// a buffer aligned as `io_align` requires, so the firmware isn't left to refuse it.
+    fn check_transfer(&self, buf: *const u8, len: usize) -> Result<(), Status> {
+        if !self.media_present {
+            return Err(Status::NoMedia);
+        }
+        if self.block_size == 0 || len % self.block_size as usize != 0 {
+            return Err(Status::BadBufferSize);
+        }
+        if buf as usize % (self.io_align as usize).max(1) != 0 {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(())
+    }
+}
+
+/// Type for EFI_BLOCK_IO_PROTOCOL, giving whole-block access to a disk, or to a partition of
+/// one where `BlockIoMedia::logical_partition` is set. Disks and partitions each have a
+/// handle with this protocol; reading LBA 1 of a disk gives its GPT header.
+///
+/// ```rust,ignore
+/// for &handle in bs.locate_handle_by_guid(&EFI_BLOCK_IO_PROTOCOL_GUID)?.as_slice() {
+///     let disk: &BlockIoProtocol = bs.handle_protocol(handle)?;
+///     let media = disk.media();
+///     if media.media_present && !media.logical_partition {
+///         let mut header = [0u8; 4096];
+///         disk.read_blocks(1, &mut header[..media.block_size as usize])?;
+///     }
+/// }
+/// ```
+#[repr(C)]
+pub struct BlockIoProtocol {
+    revision: u64,
+    media: *const BlockIoMedia,
+    reset: unsafe extern "win64" fn(this: *const BlockIoProtocol, extended_verification: bool) -> Status,
+    read_blocks: unsafe extern "win64" fn(this: *const BlockIoProtocol, media_id: u32, lba: u64, buffer_size: usize, buffer: *mut CVoid) -> Status,
+    write_blocks: unsafe extern "win64" fn(this: *const BlockIoProtocol, media_id: u32, lba: u64, buffer_size: usize, buffer: *const CVoid) -> Status,
+    flush_blocks: unsafe extern "win64" fn(this: *const BlockIoProtocol) -> Status,
+}
+
+impl Protocol for BlockIoProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_BLOCK_IO_PROTOCOL_GUID
+    }
+}
+
+impl BlockIoProtocol {
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// The media currently in the device. The firmware updates it when the media changes,
+    /// which a read or write reports with `Status::MediaChanged`.
+    pub fn media(&self) -> &BlockIoMedia {
+        unsafe { &*self.media }
+    }
+
+    pub fn reset(&self, extended_verification: bool) -> Status {
+        unsafe { (self.reset)(self, extended_verification) }
+    }
+
+    /// Read blocks starting at `lba` into `buf`, which must be a whole number of blocks,
+    /// failing with `Status::BadBufferSize` otherwise.
+    pub fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), Status> {
+        let media = self.media();
+        media.check_transfer(buf.as_ptr(), buf.len())?;
+        status_to_result(unsafe { (self.read_blocks)(self, media.media_id, lba, buf.len(), buf.as_mut_ptr() as *mut CVoid) })
+    }
+
+    /// Write `buf`, a whole number of blocks, to blocks starting at `lba`. Fails with
+    /// `Status::WriteProtected` on read-only media without asking the device.
+    pub fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), Status> {
+        let media = self.media();
+        if media.read_only {
+            return Err(Status::WriteProtected);
+        }
+        media.check_transfer(buf.as_ptr(), buf.len())?;
+        status_to_result(unsafe { (self.write_blocks)(self, media.media_id, lba, buf.len(), buf.as_ptr() as *const CVoid) })
+    }
+
+    /// Flush written blocks to the device.
+    pub fn flush_blocks(&self) -> Status {
+        unsafe { (self.flush_blocks)(self) }
+    }
+}
+
+/// A block device implemented in Rust, published to the firmware as a `BlockIoProtocol` by
+/// wrapping it in a `BlockIoDevice`. Transfers are checked against the media before they reach
+/// the device: they are whole blocks, inside the media, and writes are not to read-only media.
+pub trait BlockDevice {
+    /// Read blocks starting at `lba` into `buf`, a whole number of blocks.
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), Status>;
+
+    /// Write `buf`, a whole number of blocks, to blocks starting at `lba`.
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), Status>;
+
+    /// Flush written blocks to where they are stored. By default, there is nothing to flush.
+    fn flush_blocks(&self) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Reset the device. By default, there is nothing to reset.
+    fn reset(&self, _extended_verification: bool) -> Result<(), Status> {
+        Ok(())
+    }
+}
+
+/// A `BlockDevice` with its media and the `BlockIoProtocol` through which the firmware and
+/// other drivers use it, which `protocol` gives for installing on a handle.
+#[repr(C)]
+pub struct BlockIoDevice<D> {
+    // First, so the protocol pointer the firmware passes back is a pointer to the whole device.
+    protocol: BlockIoProtocol,
+    media: BlockIoMedia,
+    device: D,
+}
+
+unsafe extern "win64" fn device_reset<D: BlockDevice>(this: *const BlockIoProtocol, extended_verification: bool) -> Status {
+    let this = &*(this as *const BlockIoDevice<D>);
+    this.device.reset(extended_verification).report()
+}
+
+unsafe extern "win64" fn device_read_blocks<D: BlockDevice>(this: *const BlockIoProtocol, media_id: u32, lba: u64,
+                                                            buffer_size: usize, buffer: *mut CVoid) -> Status {
+    let this = &*(this as *const BlockIoDevice<D>);
+    let result = this.check_request(media_id, lba, buffer as *const u8, buffer_size).and_then(|_| {
+        this.device.read_blocks(lba, slice::from_raw_parts_mut(buffer as *mut u8, buffer_size))
+    });
+    result.report()
+}
+
+unsafe extern "win64" fn device_write_blocks<D: BlockDevice>(this: *const BlockIoProtocol, media_id: u32, lba: u64,
+                                                             buffer_size: usize, buffer: *const CVoid) -> Status {
+    let this = &*(this as *const BlockIoDevice<D>);
+    if this.media.read_only {
+        return Status::WriteProtected;
+    }
+    let result = this.check_request(media_id, lba, buffer as *const u8, buffer_size).and_then(|_| {
+        this.device.write_blocks(lba, slice::from_raw_parts(buffer as *const u8, buffer_size))
+    });
+    result.report()
+}
+
+unsafe extern "win64" fn device_flush_blocks<D: BlockDevice>(this: *const BlockIoProtocol) -> Status {
+    let this = &*(this as *const BlockIoDevice<D>);
+    this.device.flush_blocks().report()
+}
+
+impl<D: BlockDevice> BlockIoDevice<D> {
+    /// Wrap `device`, whose media is described by `media`.
+    pub fn new(media: BlockIoMedia, device: D) -> BlockIoDevice<D> {
+        BlockIoDevice {
+            protocol: BlockIoProtocol {
+                revision: EFI_BLOCK_IO_PROTOCOL_REVISION3,
+                media: ptr::null(),
+                reset: device_reset::<D>,
+                read_blocks: device_read_blocks::<D>,
+                write_blocks: device_write_blocks::<D>,
+                flush_blocks: device_flush_blocks::<D>,
+            },
+            media,
+            device,
+        }
+    }
+
+    /// The protocol to install on the device's handle. The device must then stay where it is
+    /// until the protocol is uninstalled, as the protocol points into it.
+    pub fn protocol(&mut self) -> &BlockIoProtocol {
+        self.protocol.media = &self.media;
+        &self.protocol
+    }
+
+    pub fn media(&self) -> &BlockIoMedia {
+        &self.media
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Check a request from the firmware: for the current media, of whole blocks, and inside
+    /// the media.
+    fn check_request(&self, media_id: u32, lba: u64, buf: *const u8, len: usize) -> Result<(), Status> {
+        if media_id != self.media.media_id {
+            return Err(Status::MediaChanged);
+        }
+        if buf.is_null() {
+            return Err(Status::InvalidParameter);
+        }
+        self.media.check_transfer(buf, len)?;
+        let blocks = (len / self.media.block_size as usize) as u64;
+        match lba.checked_add(blocks) {
+            Some(end) if end <= self.media.last_block + 1 => Ok(()),
+            _ => Err(Status::InvalidParameter),
+        }
+    }
+}
+
+/// Type for EFI_BLOCK_IO2_TOKEN.
+#[repr(C)]
+pub struct BlockIo2Token {
+    pub event: Event,
+    pub transaction_status: Status,
+}
+
+#[repr(C)]
+pub struct BlockIo2Protocol {
+    media: *const BlockIoMedia,
+    reset: unsafe extern "win64" fn(this: *const BlockIo2Protocol, extended_verification: bool) -> Status,
+    read_blocks_ex: unsafe extern "win64" fn(this: *const BlockIo2Protocol, media_id: u32, lba: u64, token: *mut BlockIo2Token, buffer_size: usize, buffer: *mut CVoid) -> Status,
+    write_blocks_ex: unsafe extern "win64" fn(this: *const BlockIo2Protocol, media_id: u32, lba: u64, token: *mut BlockIo2Token, buffer_size: usize, buffer: *const CVoid) -> Status,
+    flush_blocks_ex: unsafe extern "win64" fn(this: *const BlockIo2Protocol, token: *mut BlockIo2Token) -> Status,
+}
+
+impl Protocol for BlockIo2Protocol {
+    fn guid() -> &'static Guid {
+        &EFI_BLOCK_IO2_PROTOCOL_GUID
+    }
+}
+
+impl BlockIo2Protocol {
+    pub fn media(&self) -> &BlockIoMedia {
+        unsafe { &*self.media }
+    }
+
+    pub fn reset(&self, extended_verification: bool) -> Status {
+        unsafe { (self.reset)(self, extended_verification) }
+    }
+
+    /// Read blocks starting at `lba` into `buf`, waiting for the transfer to complete.
+    pub fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), Status> {
+        let status = unsafe {
+            (self.read_blocks_ex)(self, self.media().media_id, lba, ptr::null_mut(), buf.len(), buf.as_mut_ptr() as *mut CVoid)
+        };
+        match status {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Write `buf` to blocks starting at `lba`, waiting for the transfer to complete.
+    pub fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), Status> {
+        let status = unsafe {
+            (self.write_blocks_ex)(self, self.media().media_id, lba, ptr::null_mut(), buf.len(), buf.as_ptr() as *const CVoid)
+        };
+        match status {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Flush written blocks to the device, waiting for it to complete.
+    pub fn flush_blocks(&self) -> Status {
+        unsafe { (self.flush_blocks_ex)(self, ptr::null_mut()) }
+    }
+
+    /// Read blocks starting at `lba` into `buf` with up to `depth` reads queued at once, for
+    /// loading large images faster than one synchronous read at a time. `progress` is called
+    /// with the number of bytes read so far as each read completes.
+    ///
+    /// `buf` must be a multiple of the block size and aligned as `BlockIoMedia::io_align`
+    /// requires; otherwise this fails with `Status::InvalidParameter`. Reads are
+    /// `optimal_transfer_length_granularity` blocks when the device reports a plausible one, or
+    /// `DEFAULT_PIPELINE_CHUNK_SIZE` bytes rounded down to whole blocks. On failure every
+    /// queued read is still waited for, so `buf` is not written to after this returns.
+    ///
+    /// ```rust,ignore
+    /// let block_io: &BlockIo2Protocol = bs.handle_protocol(handle)?;
+    /// block_io.read_pipelined(0, image, 4, |done| progress.update(done))?;
+    /// ```
+    pub fn read_pipelined<F: FnMut(usize)>(&self, lba: u64, buf: &mut [u8], depth: usize, mut progress: F) -> Result<(), Status> {
+        let media = *self.media();
+        let block_size = media.block_size as usize;
+        let align = (media.io_align as usize).max(1);
+        if block_size == 0 || buf.len() % block_size != 0 || buf.as_ptr() as usize % align != 0 {
+            return Err(Status::InvalidParameter);
+        }
+
+        // Media from older protocol revisions has no transfer length, so ignore implausible ones.
+        let chunk = match media.optimal_transfer_length_granularity as usize * block_size {
+            bytes if bytes > 0 && bytes <= DEFAULT_PIPELINE_CHUNK_SIZE * 16 => bytes,
+            _ => (DEFAULT_PIPELINE_CHUNK_SIZE / block_size).max(1) * block_size,
+        };
+        let depth = depth.clamp(1, MAX_PIPELINE_DEPTH);
+
+        let bs = ::get_system_table().boot_services();
+        let mut tokens: [BlockIo2Token; MAX_PIPELINE_DEPTH] = ::core::array::from_fn(|_| BlockIo2Token {
+            event: Event(ptr::null_mut()),
+            transaction_status: Status::Success,
+        });
+        for token in tokens.iter_mut().take(depth) {
+            match bs.create_timer_event() {
+                Ok(event) => token.event = event,
+                Err(e) => {
+                    close_tokens(&tokens);
+                    return Err(e);
+                }
+            }
+        }
+
+        // A ring of (offset, length) of the queued reads, indexed like `tokens`. Reads complete
+        // in any order, but are waited for oldest first.
+        let mut queue = [(0usize, 0usize); MAX_PIPELINE_DEPTH];
+        let (mut head, mut queued) = (0, 0);
+        let mut next = 0;
+        let mut done = 0;
+        let mut result = Ok(());
+        loop {
+            while result.is_ok() && queued < depth && next < buf.len() {
+                let slot = (head + queued) % depth;
+                let len = chunk.min(buf.len() - next);
+                tokens[slot].transaction_status = Status::Success;
+                let status = unsafe {
+                    (self.read_blocks_ex)(self, media.media_id, lba + (next / block_size) as u64, &mut tokens[slot], len,
+                                          buf.as_mut_ptr().add(next) as *mut CVoid)
+                };
+                if status != Status::Success {
+                    result = Err(status);
+                    break;
+                }
+                queue[slot] = (next, len);
+                queued += 1;
+                next += len;
+            }
+
+            if queued == 0 {
+                break;
+            }
+
+            let (slot, (offset, len)) = (head, queue[head]);
+            let event = tokens[slot].event;
+            if bs.wait_for_event(&[event]).is_err() {
+                // The read may still be running, so it must finish before `buf` is released.
+                while bs.check_event(event) == Status::NotReady {}
+            }
+            head = (head + 1) % depth;
+            queued -= 1;
+
+            match tokens[slot].transaction_status {
+                Status::Success if result.is_ok() => {
+                    done = done.max(offset + len);
+                    progress(done);
+                }
+                Status::Success => {}
+                e => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        close_tokens(&tokens);
+        result
+    }
+}
+
+fn close_tokens(tokens: &[BlockIo2Token]) {
+    let bs = ::get_system_table().boot_services();
+    for token in tokens.iter().filter(|t| !t.event.0.is_null()) {
+        bs.close_event(token.event);
+    }
+}
+
+#[test]
+fn block_io_media() {
+    let mut media = BlockIoMedia {
+        media_id: 1,
+        removable_media: false,
+        media_present: true,
+        logical_partition: false,
+        read_only: false,
+        write_caching: false,
+        block_size: 512,
+        io_align: 8,
+        last_block: 2047,
+        lowest_aligned_lba: 0,
+        logical_blocks_per_physical_block: 1,
+        optimal_transfer_length_granularity: 0,
+    };
+    assert_eq!(media.size(), 1024 * 1024);
+
+    let buf = [0u64; 128];
+    let buf = buf.as_ptr() as *const u8;
+    assert_eq!(media.check_transfer(buf, 1024), Ok(()));
+    assert_eq!(media.check_transfer(buf, 1000), Err(Status::BadBufferSize));
+    assert_eq!(media.check_transfer(unsafe { buf.add(4) }, 512), Err(Status::InvalidParameter));
+    media.media_present = false;
+    assert_eq!(media.check_transfer(buf, 512), Err(Status::NoMedia));
+}
+
+#[test]
+fn block_io_device() {
+    use core::cell::RefCell;
+
+    struct RamDisk(RefCell<[u8; 2048]>);
+
+    impl BlockDevice for RamDisk {
+        fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), Status> {
+            let start = lba as usize * 512;
+            buf.copy_from_slice(&self.0.borrow()[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), Status> {
+            let start = lba as usize * 512;
+            self.0.borrow_mut()[start..start + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    let media = BlockIoMedia {
+        media_id: 7,
+        removable_media: false,
+        media_present: true,
+        logical_partition: false,
+        read_only: false,
+        write_caching: false,
+        block_size: 512,
+        io_align: 0,
+        last_block: 3,
+        lowest_aligned_lba: 0,
+        logical_blocks_per_physical_block: 1,
+        optimal_transfer_length_granularity: 0,
+    };
+    let mut device = BlockIoDevice::new(media, RamDisk(RefCell::new([0; 2048])));
+    let disk = device.protocol();
+    assert_eq!(disk.media().size(), 2048);
+
+    let mut buf = [0xA5u8; 1024];
+    assert_eq!(disk.write_blocks(2, &buf), Ok(()));
+    assert_eq!(disk.write_blocks(3, &buf), Err(Status::InvalidParameter));
+    buf = [0; 1024];
+    assert_eq!(disk.read_blocks(1, &mut buf), Ok(()));
+    assert!(buf[..512].iter().all(|&b| b == 0) && buf[512..].iter().all(|&b| b == 0xA5));
+    assert_eq!(disk.read_blocks(0, &mut buf[..100]), Err(Status::BadBufferSize));
+    assert_eq!(disk.flush_blocks(), Status::Success);
+}
@@ -0,0 +1,117 @@
+use core::ptr;
+
+use base::{Handle, Status};
+use guid::Guid;
+use protocol::{Driver, Protocol};
+
+/// GUID for the component name 2 protocol
+pub static EFI_COMPONENT_NAME2_PROTOCOL_GUID: Guid = Guid(0x6A7A5CFF, 0xE8D9, 0x4F70, [0xBA, 0xDA, 0x75, 0xAB, 0x30, 0x25, 0xCE, 0x14]);
+
+/// The languages a `ComponentName2Protocol` made with `new` names things in, as a list of
+/// RFC 4646 codes.
+const SUPPORTED_LANGUAGES: &[u8] = b"en\0";
+
+/// EFI_COMPONENT_NAME2_PROTOCOL, giving the names of a driver and the controllers it manages,
+/// as shown by the shell's `drivers` and `devices` commands.
+#[repr(C)]
+pub struct ComponentName2Protocol {
+    get_driver_name: unsafe extern "win64" fn(this: *const ComponentName2Protocol, language: *const u8, driver_name: *mut *const u16) -> Status,
+    get_controller_name: unsafe extern "win64" fn(this: *const ComponentName2Protocol, controller: Handle, child: Handle, language: *const u8, controller_name: *mut *const u16) -> Status,
+    supported_languages: *const u8,
+}
+
+// The languages point at a static string.
+unsafe impl Sync for ComponentName2Protocol {}
+
+impl Protocol for ComponentName2Protocol {
+    fn guid() -> &'static Guid {
+        &EFI_COMPONENT_NAME2_PROTOCOL_GUID
+    }
+}
+
+/// Whether the null-terminated language code `language` is English, the only language names
+/// are given in.
+unsafe fn is_english(language: *const u8) -> bool {
+    *language == b'e' && *language.add(1) == b'n' && (*language.add(2) == 0 || *language.add(2) == b'-')
+}
+
+unsafe extern "win64" fn get_driver_name<D: Driver>(_this: *const ComponentName2Protocol, language: *const u8,
+                                                    driver_name: *mut *const u16) -> Status {
+    if language.is_null() || driver_name.is_null() {
+        return Status::InvalidParameter;
+    }
+    if !is_english(language) {
+        return Status::Unsupported;
+    }
+    *driver_name = D::NAME.as_ptr();
+    Status::Success
+}
+
+unsafe extern "win64" fn get_controller_name<D: Driver>(_this: *const ComponentName2Protocol, controller: Handle, child: Handle,
+                                                        language: *const u8, controller_name: *mut *const u16) -> Status {
+    if controller.is_null() || language.is_null() || controller_name.is_null() {
+        return Status::InvalidParameter;
+    }
+    if !is_english(language) {
+        return Status::Unsupported;
+    }
+    let child = if child.is_null() { None } else { Some(child) };
+    match D::controller_name(controller, child) {
+        Some(name) => {
+            *controller_name = name.as_ptr();
+            Status::Success
+        }
+        None => Status::Unsupported,
+    }
+}
+
+impl ComponentName2Protocol {
+    /// The component name protocol of driver `D`, naming it and its controllers in English.
+    pub const fn new<D: Driver>() -> ComponentName2Protocol {
+        ComponentName2Protocol {
+            get_driver_name: get_driver_name::<D>,
+            get_controller_name: get_controller_name::<D>,
+            supported_languages: SUPPORTED_LANGUAGES.as_ptr(),
+        }
+    }
+
+    /// The name of the driver in `language`, an RFC 4646 code such as "en", as a
+    /// null-terminated UCS-2 string.
+    pub fn driver_name(&self, language: &str) -> Result<*const u16, Status> {
+        let mut lang = [0u8; 16];
+        if language.len() >= lang.len() {
+            return Err(Status::InvalidParameter);
+        }
+        lang[..language.len()].copy_from_slice(language.as_bytes());
+
+        let mut name = ptr::null();
+        match unsafe { (self.get_driver_name)(self, lang.as_ptr(), &mut name) } {
+            Status::Success => Ok(name),
+            e => Err(e),
+        }
+    }
+}
+
+#[test]
+fn component_names() {
+    struct Test;
+    impl Driver for Test {
+        const NAME: &'static [u16] = &[0x54, 0x65, 0x73, 0x74, 0];
+        fn supported(_: Handle, _: Option<&::protocol::DevicePathProtocol>) -> Result<(), Status> {
+            Ok(())
+        }
+        fn start(_: Handle, _: Option<&::protocol::DevicePathProtocol>) -> Result<(), Status> {
+            Ok(())
+        }
+        fn stop(_: Handle, _: &[Handle]) -> Result<(), Status> {
+            Ok(())
+        }
+    }
+
+    let component_name = ComponentName2Protocol::new::<Test>();
+    let name = |language| component_name.driver_name(language).map(|name| unsafe { ::core::slice::from_raw_parts(name, 5) });
+    assert_eq!(name("en"), Ok(Test::NAME));
+    assert_eq!(name("en-US"), Ok(Test::NAME));
+    assert_eq!(component_name.driver_name("fr"), Err(Status::Unsupported));
+    assert_eq!(component_name.driver_name("e"), Err(Status::Unsupported));
+}
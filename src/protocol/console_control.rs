@@ -0,0 +1,57 @@
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+use void::NotYetDef;
+
+/// GUID for the console control protocol
+pub static EFI_CONSOLE_CONTROL_PROTOCOL_GUID: Guid = Guid(0xF42F7782, 0x012E, 0x4C12, [0x99, 0x56, 0x49, 0xF9, 0x43, 0x04, 0xF7, 0x21]);
+
+/// Type for EFI_CONSOLE_CONTROL_SCREEN_MODE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ConsoleControlScreenMode {
+    Text = 0,
+    Graphics = 1,
+}
+
+/// EFI_CONSOLE_CONTROL_PROTOCOL, from the Intel framework specifications that came before UEFI.
+/// It is gone from current EDK2, but Apple firmware still draws text output over graphics, or
+/// hides it, until the console is switched to the right mode through it.
+#[repr(C)]
+pub struct ConsoleControlProtocol {
+    get_mode: unsafe extern "win64" fn(this: *const ConsoleControlProtocol, mode: *mut u32, gop_uga_exists: *mut bool, std_in_locked: *mut bool) -> Status,
+    set_mode: unsafe extern "win64" fn(this: *const ConsoleControlProtocol, mode: ConsoleControlScreenMode) -> Status,
+    lock_std_in: *const NotYetDef,
+}
+
+impl Protocol for ConsoleControlProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_CONSOLE_CONTROL_PROTOCOL_GUID
+    }
+}
+
+impl ConsoleControlProtocol {
+    /// Return the current screen mode, whether a graphics device is present and whether input is
+    /// locked behind a password.
+    pub fn get_mode(&self) -> Result<(ConsoleControlScreenMode, bool, bool), Status> {
+        let mut mode = 0;
+        let mut gop_uga_exists = false;
+        let mut std_in_locked = false;
+
+        let status = unsafe { (self.get_mode)(self, &mut mode, &mut gop_uga_exists, &mut std_in_locked) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        let mode = match mode {
+            0 => ConsoleControlScreenMode::Text,
+            1 => ConsoleControlScreenMode::Graphics,
+            _ => return Err(Status::DeviceError),
+        };
+        Ok((mode, gop_uga_exists, std_in_locked))
+    }
+
+    pub fn set_mode(&self, mode: ConsoleControlScreenMode) -> Status {
+        unsafe { (self.set_mode)(self, mode) }
+    }
+}
@@ -0,0 +1,141 @@
+use base::{Status, status_to_result};
+use guid::Guid;
+use protocol::Protocol;
+
+/// GUID for the debug support protocol
+pub static EFI_DEBUG_SUPPORT_PROTOCOL_GUID: Guid = Guid(0x2755590C, 0x6F3C, 0x42FA, [0x9E, 0xA4, 0xA3, 0xBA, 0x54, 0x3C, 0xDA, 0x25]);
+
+/// Instruction set of a debug support protocol, from the PE machine types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum InstructionSetArchitecture {
+    Ia32 = 0x014C,
+    X64 = 0x8664,
+    Ipf = 0x0200,
+    Ebc = 0x0EBC,
+    Arm = 0x01C2,
+    AArch64 = 0xAA64,
+    RiscV32 = 0x5032,
+    RiscV64 = 0x5064,
+    RiscV128 = 0x5128,
+    LoongArch32 = 0x6232,
+    LoongArch64 = 0x6264,
+}
+
+pub const EXCEPT_X64_DIVIDE_ERROR: isize = 0;
+pub const EXCEPT_X64_DEBUG: isize = 1;
+pub const EXCEPT_X64_NMI: isize = 2;
+pub const EXCEPT_X64_BREAKPOINT: isize = 3;
+pub const EXCEPT_X64_OVERFLOW: isize = 4;
+pub const EXCEPT_X64_BOUND: isize = 5;
+pub const EXCEPT_X64_INVALID_OPCODE: isize = 6;
+pub const EXCEPT_X64_DOUBLE_FAULT: isize = 8;
+pub const EXCEPT_X64_INVALID_TSS: isize = 10;
+pub const EXCEPT_X64_SEG_NOT_PRESENT: isize = 11;
+pub const EXCEPT_X64_STACK_FAULT: isize = 12;
+pub const EXCEPT_X64_GP_FAULT: isize = 13;
+pub const EXCEPT_X64_PAGE_FAULT: isize = 14;
+pub const EXCEPT_X64_FP_ERROR: isize = 16;
+pub const EXCEPT_X64_ALIGNMENT_CHECK: isize = 17;
+pub const EXCEPT_X64_MACHINE_CHECK: isize = 18;
+pub const EXCEPT_X64_SIMD: isize = 19;
+
+/// Type for EFI_SYSTEM_CONTEXT_X64, the processor state saved when an exception is taken.
+/// Changes made by a callback are restored to the processor when it returns.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct SystemContextX64 {
+    pub exception_data: u64,
+    pub fx_save_state: [u8; 512],
+    pub dr0: u64,
+    pub dr1: u64,
+    pub dr2: u64,
+    pub dr3: u64,
+    pub dr6: u64,
+    pub dr7: u64,
+    pub cr0: u64,
+    pub cr1: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    pub cr8: u64,
+    pub rflags: u64,
+    pub ldtr: u64,
+    pub tr: u64,
+    pub gdtr: [u64; 2],
+    pub idtr: [u64; 2],
+    pub rip: u64,
+    pub gs: u64,
+    pub fs: u64,
+    pub es: u64,
+    pub ds: u64,
+    pub cs: u64,
+    pub ss: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub rbx: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rax: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+}
+
+/// Function called on an exception, with the exception type (one of the `EXCEPT_*` values for
+/// the architecture) and the saved processor state. EFI_SYSTEM_CONTEXT is a union of pointers
+/// to the per-architecture contexts, so it is passed as the pointer for the architecture in use.
+pub type ExceptionCallback = extern "win64" fn(exception_type: isize, context: *mut SystemContextX64);
+
+/// Function called periodically from the timer interrupt, with the saved processor state.
+pub type PeriodicCallback = extern "win64" fn(context: *mut SystemContextX64);
+
+/// EFI_DEBUG_SUPPORT_PROTOCOL, which lets a debug agent hook processor exceptions and the
+/// timer interrupt.
+#[repr(C)]
+pub struct DebugSupportProtocol {
+    pub isa: InstructionSetArchitecture,
+    get_maximum_processor_index: unsafe extern "win64" fn(this: *const DebugSupportProtocol, max_processor_index: *mut usize) -> Status,
+    register_periodic_callback: unsafe extern "win64" fn(this: *const DebugSupportProtocol, processor_index: usize, callback: Option<PeriodicCallback>) -> Status,
+    register_exception_callback: unsafe extern "win64" fn(this: *const DebugSupportProtocol, processor_index: usize, callback: Option<ExceptionCallback>, exception_type: isize) -> Status,
+    invalidate_instruction_cache: unsafe extern "win64" fn(this: *const DebugSupportProtocol, processor_index: usize, start: *mut u8, length: u64) -> Status,
+}
+
+impl Protocol for DebugSupportProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_DEBUG_SUPPORT_PROTOCOL_GUID
+    }
+}
+
+impl DebugSupportProtocol {
+    pub fn maximum_processor_index(&self) -> Result<usize, Status> {
+        let mut index = 0;
+        status_to_result(unsafe { (self.get_maximum_processor_index)(self, &mut index) })?;
+        Ok(index)
+    }
+
+    /// Register `callback` to be called from the timer interrupt on `processor`, or unregister
+    /// the current one with `None`. Fails with `Status::AlreadyStarted` if one is registered.
+    pub fn register_periodic_callback(&self, processor: usize, callback: Option<PeriodicCallback>) -> Result<(), Status> {
+        status_to_result(unsafe { (self.register_periodic_callback)(self, processor, callback) })
+    }
+
+    /// Register `callback` for `exception_type` on `processor`, or unregister the current one
+    /// with `None`. Fails with `Status::AlreadyStarted` if one is registered.
+    pub fn register_exception_callback(&self, processor: usize, callback: Option<ExceptionCallback>, exception_type: isize)
+                                       -> Result<(), Status> {
+        status_to_result(unsafe { (self.register_exception_callback)(self, processor, callback, exception_type) })
+    }
+
+    /// Make `processor` see code modified in `code`, such as an inserted breakpoint.
+    pub fn invalidate_instruction_cache(&self, processor: usize, code: &mut [u8]) -> Result<(), Status> {
+        status_to_result(unsafe { (self.invalidate_instruction_cache)(self, processor, code.as_mut_ptr(), code.len() as u64) })
+    }
+}
@@ -0,0 +1,76 @@
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+use void::CVoid;
+
+/// GUID for the EFI decompression protocol
+pub static EFI_DECOMPRESS_PROTOCOL_GUID: Guid = Guid(0xD8117CFE, 0x94A6, 0x11D4, [0x9A, 0x3A, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]);
+
+/// Buffer sizes needed to decompress a buffer, as returned by `DecompressProtocol::get_info`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecompressInfo {
+    /// Size of the decompressed data.
+    pub destination_size: usize,
+    /// Size of the scratch buffer the decompressor needs.
+    pub scratch_size: usize,
+}
+
+/// EFI_DECOMPRESS_PROTOCOL, which decompresses data in the EFI 1.1 (Tiano) compression format
+/// used in firmware volumes and option ROMs.
+#[repr(C)]
+pub struct DecompressProtocol {
+    get_info: unsafe extern "win64" fn(this: *const DecompressProtocol, source: *const CVoid, source_size: u32, destination_size: *mut u32, scratch_size: *mut u32) -> Status,
+    decompress: unsafe extern "win64" fn(this: *const DecompressProtocol, source: *const CVoid, source_size: u32, destination: *mut CVoid, destination_size: u32, scratch: *mut CVoid, scratch_size: u32) -> Status,
+}
+
+impl Protocol for DecompressProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_DECOMPRESS_PROTOCOL_GUID
+    }
+}
+
+impl DecompressProtocol {
+    /// Read the header of the compressed data in `source` to find the buffer sizes needed to
+    /// decompress it. Fails with `Status::InvalidParameter` if `source` is not compressed data.
+    pub fn get_info(&self, source: &[u8]) -> Result<DecompressInfo, Status> {
+        if source.len() > u32::MAX as usize {
+            return Err(Status::InvalidParameter);
+        }
+
+        let mut destination_size = 0;
+        let mut scratch_size = 0;
+        let status = unsafe {
+            (self.get_info)(self, source.as_ptr() as *const CVoid, source.len() as u32,
+                            &mut destination_size, &mut scratch_size)
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(DecompressInfo {
+            destination_size: destination_size as usize,
+            scratch_size: scratch_size as usize,
+        })
+    }
+
+    /// Decompress `source` into `destination`, using `scratch` as working memory, and return the
+    /// decompressed size. The buffers must be at least the sizes given by `get_info`, or
+    /// `Status::BufferTooSmall` is returned.
+    pub fn decompress(&self, source: &[u8], destination: &mut [u8], scratch: &mut [u8]) -> Result<usize, Status> {
+        let info = self.get_info(source)?;
+        if destination.len() < info.destination_size || scratch.len() < info.scratch_size {
+            return Err(Status::BufferTooSmall);
+        }
+
+        let status = unsafe {
+            (self.decompress)(self, source.as_ptr() as *const CVoid, source.len() as u32,
+                              destination.as_mut_ptr() as *mut CVoid, info.destination_size as u32,
+                              scratch.as_mut_ptr() as *mut CVoid, info.scratch_size as u32)
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(info.destination_size)
+    }
+}
@@ -12,15 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::mem;
+use core::slice;
 
 use base::Status;
 use console::SimpleTextOutput;
 use guid::Guid;
 use protocol::Protocol;
 use void::CVoid;
-use util::{utf16_ptr_to_str, str_to_utf16_ptr};
+use util::{utf16_ptr_to_str, str_to_utf16_ptr, wire};
+use validate::validate_device_path_text;
 
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u8)]
 pub enum DevicePathTypes {
     Hardware = 0x01,
@@ -37,6 +39,7 @@ impl Into<u8> for DevicePathTypes {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u8)]
 pub enum HardwareSubTypes {
     PCI = 0x01,
@@ -53,6 +56,7 @@ impl Into<u8> for HardwareSubTypes {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u8)]
 pub enum ACPISubTypes {
     ACPIDevicePath = 0x01,
@@ -66,6 +70,7 @@ impl Into<u8> for ACPISubTypes {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[allow(non_camel_case_types)]
 #[repr(u8)]
 pub enum MessagingSubTypes {
@@ -106,6 +111,7 @@ impl Into<u8> for MessagingSubTypes {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u8)]
 pub enum MediaSubTypes {
     HardDrive = 0x1,
@@ -125,6 +131,7 @@ impl Into<u8> for MediaSubTypes {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u8)]
 pub enum BIOSSubTypes {
     BIOSBootSpecification = 0x1
@@ -136,6 +143,7 @@ impl Into<u8> for BIOSSubTypes {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u8)]
 pub enum EndPathSubTypes {
     EndInstance = 0x01,
@@ -175,22 +183,200 @@ impl Protocol for DevicePathProtocol {
 }
 
 impl DevicePathProtocol {
-    fn data<T>(&self) -> *const T {
-        unsafe {
-            let self_u8: *const u8 = mem::transmute(self);
-            mem::transmute(self_u8.offset(4))
+    /// Length of this node in bytes, as stored in its header. Firmware paths are not trusted, so
+    /// use `node` to get the node with its length checked.
+    pub fn len(&self) -> u16 {
+        u16::from_le_bytes(self.length)
+    }
+
+    /// This node as bytes, with its length checked.
+    ///
+    /// # Safety
+    ///
+    /// `self` must be the header of a device path node, followed in memory by the rest of the
+    /// node as described by its length field.
+    pub unsafe fn node(&self) -> Result<DevicePathNode<'_>, Status> {
+        DevicePathNode::from_ptr(self)
+    }
+
+    /// The device path starting at this node, with every node length checked.
+    ///
+    /// # Safety
+    ///
+    /// As for `DevicePath::from_ptr`.
+    pub unsafe fn path(&self) -> Result<DevicePath<'_>, Status> {
+        DevicePath::from_ptr(self)
+    }
+}
+
+/// Size of the type, sub-type and length header at the start of every device path node.
+pub const DEVICE_PATH_NODE_HEADER_SIZE: usize = 4;
+
+/// A device path node viewed as bytes, whose length field has been checked to be at least the
+/// header size and to lie within the buffer it was read from. Node fields are read with the
+/// `util::wire` helpers, as nodes are packed and their fields often misaligned.
+#[derive(Clone, Copy, Debug)]
+pub struct DevicePathNode<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> DevicePathNode<'a> {
+    /// The node at the start of `buf`. Fails with `Status::InvalidParameter` if `buf` is too short
+    /// for the header, or the node length is smaller than the header or runs past the end of
+    /// `buf`.
+    pub fn from_bytes(buf: &'a [u8]) -> Result<DevicePathNode<'a>, Status> {
+        let len = wire::read_u16(buf, 2).map_err(|_| Status::InvalidParameter)? as usize;
+        if len < DEVICE_PATH_NODE_HEADER_SIZE || len > buf.len() {
+            return Err(Status::InvalidParameter);
         }
+        Ok(DevicePathNode { bytes: &buf[..len] })
     }
 
-    pub fn len(&self) -> u16 {
-        (self.length[0] as u16) | ((self.length[1] as u16) << 8)
+    /// The node at `node`, as found in a device path passed in by the firmware, whose extent is
+    /// only known from the node's own length field.
+    ///
+    /// # Safety
+    ///
+    /// `node` must point to a readable device path node header, followed by the rest of the node
+    /// if its length field is at least the header size.
+    pub unsafe fn from_ptr(node: *const DevicePathProtocol) -> Result<DevicePathNode<'a>, Status> {
+        if node.is_null() {
+            return Err(Status::InvalidParameter);
+        }
+
+        let header = slice::from_raw_parts(node as *const u8, DEVICE_PATH_NODE_HEADER_SIZE);
+        let len = wire::read_u16(header, 2)? as usize;
+        if len < DEVICE_PATH_NODE_HEADER_SIZE {
+            return Err(Status::InvalidParameter);
+        }
+        DevicePathNode::from_bytes(slice::from_raw_parts(node as *const u8, len))
+    }
+
+    pub fn node_type(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    pub fn sub_type(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    /// Length of the node in bytes, including the header.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Whether this node ends a device path instance or the whole device path.
+    pub fn is_end(&self) -> bool {
+        self.node_type() == DevicePathTypes::End.into()
+    }
+
+    /// The node, including its header.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The node-specific data after the header.
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[DEVICE_PATH_NODE_HEADER_SIZE..]
+    }
+
+    pub fn as_ptr(&self) -> *const DevicePathProtocol {
+        self.bytes.as_ptr() as *const DevicePathProtocol
+    }
+
+    fn is_end_entire_path(&self) -> bool {
+        self.is_end() && self.sub_type() == EndPathSubTypes::EndEntirePath.into()
+    }
+}
+
+/// Largest device path `DevicePath::from_ptr` accepts, so that a path missing its end node is
+/// rejected before running off into unrelated memory.
+pub const MAX_DEVICE_PATH_SIZE: usize = 64 * 1024;
+
+/// A complete device path: a sequence of nodes terminated by an End Entire Device Path node. Node
+/// lengths are checked when the path is created, so a malformed path is refused with
+/// `Status::InvalidParameter` up front and traversal never reads outside the path.
+#[derive(Clone, Copy, Debug)]
+pub struct DevicePath<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> DevicePath<'a> {
+    /// The device path at the start of `buf`, which may continue past the end node.
+    pub fn from_bytes(buf: &'a [u8]) -> Result<DevicePath<'a>, Status> {
+        let mut offset = 0;
+        loop {
+            let node = DevicePathNode::from_bytes(&buf[offset..])?;
+            offset += node.len();
+            if node.is_end_entire_path() {
+                return Ok(DevicePath { bytes: &buf[..offset] });
+            }
+        }
+    }
+
+    /// The device path at `path`, such as one returned by the firmware, whose extent is only
+    /// known by walking its nodes. Paths longer than `MAX_DEVICE_PATH_SIZE` are refused.
+    ///
+    /// # Safety
+    ///
+    /// `path` must point to readable memory holding a device path, up to its end node or up to a
+    /// node with a malformed length.
+    pub unsafe fn from_ptr(path: *const DevicePathProtocol) -> Result<DevicePath<'a>, Status> {
+        let base = path as *const u8;
+        let mut offset = 0;
+        loop {
+            let node = DevicePathNode::from_ptr(base.add(offset) as *const DevicePathProtocol)?;
+            offset += node.len();
+            if offset > MAX_DEVICE_PATH_SIZE {
+                return Err(Status::InvalidParameter);
+            }
+            if node.is_end_entire_path() {
+                return Ok(DevicePath { bytes: slice::from_raw_parts(base, offset) });
+            }
+        }
+    }
+
+    /// The nodes of the path, not including the final end node. End Instance nodes separating
+    /// the instances of a multi-instance path are included.
+    pub fn nodes(&self) -> DevicePathNodes<'a> {
+        DevicePathNodes {
+            bytes: &self.bytes[..self.bytes.len() - DEVICE_PATH_NODE_HEADER_SIZE],
+        }
+    }
+
+    /// Size of the path in bytes, including the end node.
+    pub fn total_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
     }
 
-    // Get a pointer to the DevicePathProtocol after this one, accounting for however big this one
-    // might be. This is very unsafe.
-    pub unsafe fn next(&self) -> &mut DevicePathProtocol {
-        let this_u8 = self as *const DevicePathProtocol as *const u8;
-        &mut *(this_u8.offset(self.len() as isize) as *mut DevicePathProtocol)
+    pub fn as_ptr(&self) -> *const DevicePathProtocol {
+        self.bytes.as_ptr() as *const DevicePathProtocol
+    }
+
+    pub fn as_protocol(&self) -> &'a DevicePathProtocol {
+        unsafe { &*self.as_ptr() }
+    }
+}
+
+/// Iterator over the nodes of a `DevicePath`.
+#[derive(Clone, Debug)]
+pub struct DevicePathNodes<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for DevicePathNodes<'a> {
+    type Item = DevicePathNode<'a>;
+
+    fn next(&mut self) -> Option<DevicePathNode<'a>> {
+        // The lengths were all checked when the path was created.
+        let node = DevicePathNode::from_bytes(self.bytes).ok()?;
+        self.bytes = &self.bytes[node.len()..];
+        Some(node)
     }
 }
 
@@ -266,31 +452,33 @@ impl Protocol for DevicePathFromTextProtocol {
 
 impl DevicePathFromTextProtocol {
     pub fn text_to_device_path_node(&self, path: &str) -> Result<&DevicePathProtocol, Status> {
+        validate_device_path_text(path)?;
         str_to_utf16_ptr(path)
-            .map(|utf16_str| {
-                let out = unsafe { &*((self.text_to_device_path_node)(utf16_str)) };
+            .and_then(|utf16_str| {
+                let out = unsafe { (self.text_to_device_path_node)(utf16_str).as_ref().ok_or(Status::InvalidParameter)? };
                 // FIXME(csssuf)
                 // Ideally, at this point, we'd free utf16_str. However, free_pool(utf16_str) seems
                 // to hang here for unknown reasons. So we leak it.
-                out
+                Ok(out)
             })
     }
 
     pub fn text_to_device_path(&self, path: &str) -> Result<&DevicePathProtocol, Status> {
+        validate_device_path_text(path)?;
         str_to_utf16_ptr(path)
-            .map(|utf16_str| {
-                let out = unsafe { &*((self.text_to_device_path)(utf16_str)) };
+            .and_then(|utf16_str| {
+                let out = unsafe { (self.text_to_device_path)(utf16_str).as_ref().ok_or(Status::InvalidParameter)? };
                 // FIXME(csssuf)
                 // Ideally, at this point, we'd free utf16_str. However, free_pool(utf16_str) seems
                 // to hang here for unknown reasons. So we leak it.
-                out
+                Ok(out)
             })
     }
 }
 
 #[repr(C)]
 pub struct DevicePathUtilitiesProtocol {
-    get_device_path_size: *const CVoid,
+    get_device_path_size: unsafe extern "win64" fn(path: *const DevicePathProtocol) -> usize,
     duplicate_device_path:
         unsafe extern "win64" fn(src: *const DevicePathProtocol) -> *mut DevicePathProtocol,
     append_device_path: unsafe extern "win64" fn(src1: *const DevicePathProtocol, src2: *const DevicePathProtocol) -> *const DevicePathProtocol,
@@ -308,6 +496,14 @@ impl Protocol for DevicePathUtilitiesProtocol {
 }
 
 impl DevicePathUtilitiesProtocol {
+    /// Size of the device path `path` in bytes, including the end node.
+    pub fn get_device_path_size(&self, path: &DevicePathProtocol) -> Result<usize, Status> {
+        match unsafe { (self.get_device_path_size)(path) } {
+            0 => Err(Status::InvalidParameter),
+            size => Ok(size),
+        }
+    }
+
     pub fn duplicate_device_path(&self, src: &DevicePathProtocol) -> Result<&mut DevicePathProtocol, Status> {
         unsafe {
             let out = (self.duplicate_device_path)(src);
@@ -318,10 +514,12 @@ impl DevicePathUtilitiesProtocol {
         }
     }
 
-    pub fn append_device_path(&self, src1: *const DevicePathProtocol, src2: *const DevicePathProtocol) -> Result<*const DevicePathProtocol, Status> {
+    /// A new path made of `src1` followed by `src2`, allocated from pool memory, which the caller
+    /// must free.
+    pub fn append_device_path(&self, src1: &DevicePath, src2: &DevicePath) -> Result<DevicePath<'static>, Status> {
         unsafe {
-            let out = (self.append_device_path)(src1, src2);
-            if out == 0 as *const DevicePathProtocol {
+            let out = (self.append_device_path)(src1.as_ptr(), src2.as_ptr());
+            if out.is_null() {
                 // `out` being a null pointer indicates, according to the spec, that "memory could
                 // not be allocate[sic]." Whether that's due to memory conditions, bad parameters
                 // being passed in, or another reason is unspecified. Unless the caller passes in
@@ -329,18 +527,20 @@ impl DevicePathUtilitiesProtocol {
                 // parameters, so error here is represented as OutOfResources.
                 return Err(Status::OutOfResources);
             }
-            Ok(out)
+            DevicePath::from_ptr(out)
         }
     }
 
-    pub fn append_device_node(&self, path: *const DevicePathProtocol, node: *const DevicePathProtocol) -> Result<*const DevicePathProtocol, Status> {
+    /// A new path made of `path` with `node` added to its end, allocated from pool memory, which
+    /// the caller must free.
+    pub fn append_device_node(&self, path: &DevicePath, node: &DevicePathNode) -> Result<DevicePath<'static>, Status> {
         unsafe {
-            let out = (self.append_device_node)(path, node);
-            if out == 0 as *const DevicePathProtocol {
+            let out = (self.append_device_node)(path.as_ptr(), node.as_ptr());
+            if out.is_null() {
                 // See comment in append_device_path.
                 return Err(Status::OutOfResources);
             }
-            Ok(out)
+            DevicePath::from_ptr(out)
         }
     }
 
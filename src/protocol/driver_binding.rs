@@ -0,0 +1,133 @@
+use core::slice;
+
+use base::{AtomicHandle, Handle, Status};
+use entry::Termination;
+use guid::Guid;
+use protocol::{ComponentName2Protocol, DevicePathProtocol, Protocol, EFI_COMPONENT_NAME2_PROTOCOL_GUID};
+use void::CVoid;
+
+/// GUID for the driver binding protocol
+pub static EFI_DRIVER_BINDING_PROTOCOL_GUID: Guid = Guid(0x18A031AB, 0xB443, 0x4D1A, [0xA5, 0xC0, 0x0C, 0x09, 0x26, 0x1E, 0x9F, 0x71]);
+
+/// A driver following the UEFI driver model, managing controllers the firmware hands it with
+/// `ConnectController`. Implemented by a type naming the driver, usually a unit struct; the
+/// functions are associated functions, so state the driver keeps goes in statics.
+///
+/// `driver_entry!` publishes a driver's `DriverBindingProtocol` and `ComponentName2Protocol`.
+pub trait Driver {
+    /// The version of the driver. Where several drivers support a controller, the one with the
+    /// highest version is started first. Versions 0 to 0xF are reserved for platform drivers.
+    const VERSION: u32 = 0x10;
+
+    /// The name of the driver in English, as a null-terminated UCS-2 string from `ucs2!`.
+    const NAME: &'static [u16];
+
+    /// Whether the driver can manage `controller`, succeeding if it can. This is called often
+    /// and must be quick, leaving the controller as it was: protocols opened to check must be
+    /// closed again. `remaining_device_path` is the part of a device path below `controller`
+    /// a bus driver is asked to create a child for.
+    fn supported(controller: Handle, remaining_device_path: Option<&DevicePathProtocol>) -> Result<(), Status>;
+
+    /// Start managing `controller`, which `supported` accepted.
+    fn start(controller: Handle, remaining_device_path: Option<&DevicePathProtocol>) -> Result<(), Status>;
+
+    /// Stop managing `controller`, or if `children` isn't empty, only stop the given children
+    /// created by `start`.
+    fn stop(controller: Handle, children: &[Handle]) -> Result<(), Status>;
+
+    /// The name in English of `controller`, or of the child `child` of it, if the driver is
+    /// managing it and names its controllers. By default, none are named.
+    fn controller_name(_controller: Handle, _child: Option<Handle>) -> Option<&'static [u16]> {
+        None
+    }
+}
+
+/// EFI_DRIVER_BINDING_PROTOCOL, through which the firmware asks a driver to manage controllers.
+/// A driver makes one with `DriverBindingProtocol::new`, as `driver_entry!` does.
+#[repr(C)]
+pub struct DriverBindingProtocol {
+    supported: unsafe extern "win64" fn(this: *const DriverBindingProtocol, controller: Handle, remaining_device_path: *const DevicePathProtocol) -> Status,
+    start: unsafe extern "win64" fn(this: *const DriverBindingProtocol, controller: Handle, remaining_device_path: *const DevicePathProtocol) -> Status,
+    stop: unsafe extern "win64" fn(this: *const DriverBindingProtocol, controller: Handle, number_of_children: usize, child_handle_buffer: *const Handle) -> Status,
+    version: u32,
+    image_handle: AtomicHandle,
+    driver_binding_handle: AtomicHandle,
+}
+
+impl Protocol for DriverBindingProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_DRIVER_BINDING_PROTOCOL_GUID
+    }
+}
+
+unsafe extern "win64" fn driver_supported<D: Driver>(_this: *const DriverBindingProtocol, controller: Handle,
+                                                     remaining_device_path: *const DevicePathProtocol) -> Status {
+    D::supported(controller, remaining_device_path.as_ref()).report()
+}
+
+unsafe extern "win64" fn driver_start<D: Driver>(_this: *const DriverBindingProtocol, controller: Handle,
+                                                 remaining_device_path: *const DevicePathProtocol) -> Status {
+    D::start(controller, remaining_device_path.as_ref()).report()
+}
+
+unsafe extern "win64" fn driver_stop<D: Driver>(_this: *const DriverBindingProtocol, controller: Handle,
+                                                number_of_children: usize, child_handle_buffer: *const Handle) -> Status {
+    let children = if number_of_children == 0 || child_handle_buffer.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(child_handle_buffer, number_of_children)
+    };
+    D::stop(controller, children).report()
+}
+
+impl DriverBindingProtocol {
+    /// The driver binding of driver `D`, for a static installed with `install_driver`.
+    pub const fn new<D: Driver>() -> DriverBindingProtocol {
+        DriverBindingProtocol {
+            supported: driver_supported::<D>,
+            start: driver_start::<D>,
+            stop: driver_stop::<D>,
+            version: D::VERSION,
+            image_handle: AtomicHandle::new(Handle::NULL),
+            driver_binding_handle: AtomicHandle::new(Handle::NULL),
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The handle of the driver's image.
+    pub fn image_handle(&self) -> Handle {
+        self.image_handle.load()
+    }
+
+    /// The handle the driver binding is installed on.
+    pub fn driver_binding_handle(&self) -> Handle {
+        self.driver_binding_handle.load()
+    }
+}
+
+/// Install `binding` and `component_name` on the driver's image handle, `image_handle`, so the
+/// firmware connects the driver to the controllers it supports. The image has to stay resident
+/// afterwards: its entry point returns `Success`, and it is linked as a boot service driver.
+pub fn install_driver(image_handle: Handle, binding: &'static DriverBindingProtocol,
+                      component_name: &'static ComponentName2Protocol) -> Result<(), Status> {
+    binding.image_handle.store(image_handle);
+    binding.driver_binding_handle.store(image_handle);
+
+    let bs = ::get_system_table().boot_services();
+    let interfaces = [
+        (&EFI_DRIVER_BINDING_PROTOCOL_GUID, binding as *const DriverBindingProtocol as *const CVoid),
+        (&EFI_COMPONENT_NAME2_PROTOCOL_GUID, component_name as *const ComponentName2Protocol as *const CVoid),
+    ];
+    // Both are statics, which live as long as the resident image.
+    match unsafe { bs.install_multiple_protocol_interfaces(image_handle, &interfaces) } {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            binding.image_handle.store(Handle::NULL);
+            binding.driver_binding_handle.store(Handle::NULL);
+            Err(e)
+        }
+    }
+}
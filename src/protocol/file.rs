@@ -0,0 +1,634 @@
+use core::{char, fmt, ptr};
+
+use base::{Handle, Handles, Status};
+use guid::Guid;
+use protocol::Protocol;
+use util::{ucs2, wire};
+use validate::normalize_path;
+use void::CVoid;
+
+/// GUID for the simple file system protocol
+pub static EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID: Guid = Guid(0x964E5B22, 0x6459, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+
+/// Information type for `FileProtocol::get_info`: an EFI_FILE_INFO, as `FileInfo` reads
+pub static EFI_FILE_INFO_ID: Guid = Guid(0x09576E92, 0x6D3F, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+
+/// Information type for `FileProtocol::get_info` on a volume's root: an EFI_FILE_SYSTEM_INFO,
+/// as `FileSystemInfo` reads
+pub static EFI_FILE_SYSTEM_INFO_ID: Guid = Guid(0x09576E93, 0x6D3F, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+
+/// Information type for `FileProtocol::get_info` on a volume's root: the volume label as a
+/// null-terminated UCS-2 string
+pub static EFI_FILE_SYSTEM_VOLUME_LABEL_ID: Guid = Guid(0xDB47D7D3, 0xFE81, 0x11D3, [0x9A, 0x35, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]);
+
+/// Open modes for `FileProtocol::open`. `EFI_FILE_MODE_CREATE` must be combined with read and
+/// write.
+pub const EFI_FILE_MODE_READ: u64 = 0x0000000000000001;
+pub const EFI_FILE_MODE_WRITE: u64 = 0x0000000000000002;
+pub const EFI_FILE_MODE_CREATE: u64 = 0x8000000000000000;
+
+/// Attribute bit of a `FileInfo` for a directory.
+pub const EFI_FILE_DIRECTORY: u64 = 0x0000000000000010;
+
+/// Size of an EFI_FILE_INFO before the file name.
+const FILE_INFO_HEADER_SIZE: usize = 80;
+
+/// Longest path, in UCS-2 units with the terminator, that `FileProtocol::open_path` accepts.
+pub const MAX_FILE_PATH: usize = 256;
+
+/// Size of an EFI_FILE_SYSTEM_INFO before the volume label.
+const FILE_SYSTEM_INFO_HEADER_SIZE: usize = 36;
+
+/// Most UCS-2 units of a volume label kept in a `VolumeInfo`; longer labels are cut short.
+pub const MAX_VOLUME_LABEL: usize = 64;
+
+/// Type for EFI_SIMPLE_FILE_SYSTEM_PROTOCOL, installed on the handle of each FAT volume.
+#[repr(C)]
+pub struct SimpleFileSystemProtocol {
+    revision: u64,
+    open_volume: unsafe extern "win64" fn(this: *const SimpleFileSystemProtocol, root: *mut *const FileProtocol) -> Status,
+}
+
+impl Protocol for SimpleFileSystemProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID
+    }
+}
+
+impl SimpleFileSystemProtocol {
+    /// Open the root directory of the volume. It must be closed with `FileProtocol::close`.
+    pub fn open_volume(&self) -> Result<&FileProtocol, Status> {
+        let mut root = ptr::null();
+        match unsafe { (self.open_volume)(self, &mut root) } {
+            Status::Success => Ok(unsafe { &*root }),
+            e => Err(e),
+        }
+    }
+}
+
+/// An EFI_FILE_SYSTEM_INFO, as returned by `FileProtocol::file_system_info`.
+#[derive(Clone, Copy, Debug)]
+pub struct FileSystemInfo<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> FileSystemInfo<'a> {
+    /// The record at the start of `buf`, whose size field must lie within it.
+    pub fn from_bytes(buf: &'a [u8]) -> Result<FileSystemInfo<'a>, Status> {
+        let size = wire::read_u64(buf, 0)? as usize;
+        if size < FILE_SYSTEM_INFO_HEADER_SIZE || size > buf.len() {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(FileSystemInfo { bytes: &buf[..size] })
+    }
+
+    pub fn read_only(&self) -> bool {
+        wire::read_u8(self.bytes, 8).unwrap_or(0) != 0
+    }
+
+    /// Size of the volume in bytes.
+    pub fn volume_size(&self) -> u64 {
+        wire::read_u64(self.bytes, 16).unwrap_or(0)
+    }
+
+    /// Bytes free on the volume.
+    pub fn free_space(&self) -> u64 {
+        wire::read_u64(self.bytes, 24).unwrap_or(0)
+    }
+
+    /// Size of the volume's allocation unit, the cluster on FAT, in bytes.
+    pub fn block_size(&self) -> u32 {
+        wire::read_u32(self.bytes, 32).unwrap_or(0)
+    }
+
+    /// The volume label as UCS-2 units, empty if the volume has none.
+    pub fn volume_label(&self) -> wire::Ucs2Units<'a> {
+        wire::read_ucs2(self.bytes, FILE_SYSTEM_INFO_HEADER_SIZE)
+    }
+}
+
+/// What a disk picker shows about a volume, copied out of its EFI_FILE_SYSTEM_INFO by
+/// `FileProtocol::volume_info` or `volumes`.
+#[derive(Clone, Copy)]
+pub struct VolumeInfo {
+    label: [u16; MAX_VOLUME_LABEL],
+    label_len: usize,
+    volume_size: u64,
+    free_space: u64,
+    block_size: u32,
+    read_only: bool,
+}
+
+impl VolumeInfo {
+    /// Copy `info`, cutting the label short after `MAX_VOLUME_LABEL` units.
+    fn from_info(info: &FileSystemInfo) -> VolumeInfo {
+        let mut volume = VolumeInfo {
+            label: [0; MAX_VOLUME_LABEL],
+            label_len: 0,
+            volume_size: info.volume_size(),
+            free_space: info.free_space(),
+            block_size: info.block_size(),
+            read_only: info.read_only(),
+        };
+        for (unit, c) in volume.label.iter_mut().zip(info.volume_label()) {
+            *unit = c;
+            volume.label_len += 1;
+        }
+        volume
+    }
+
+    /// The volume label, without a terminator, as UCS-2 units. Empty if the volume has none.
+    pub fn label(&self) -> &[u16] {
+        &self.label[..self.label_len]
+    }
+
+    /// Size of the volume in bytes.
+    pub fn volume_size(&self) -> u64 {
+        self.volume_size
+    }
+
+    /// Bytes free on the volume.
+    pub fn free_space(&self) -> u64 {
+        self.free_space
+    }
+
+    /// Size of the volume's allocation unit in bytes.
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+}
+
+/// Shows the label.
+impl fmt::Display for VolumeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in char::decode_utf16(self.label().iter().cloned()) {
+            write!(f, "{}", c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for VolumeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VolumeInfo {{ label: \"{}\", volume_size: {}, free_space: {}, block_size: {}, read_only: {} }}",
+               self, self.volume_size, self.free_space, self.block_size, self.read_only)
+    }
+}
+
+/// The information of the volume on `handle`, which has the simple file system protocol.
+pub fn volume_info(handle: Handle) -> Result<VolumeInfo, Status> {
+    let fs: &SimpleFileSystemProtocol = ::get_system_table().boot_services().handle_protocol(handle)?;
+    let root = fs.open_volume()?;
+    let info = root.volume_info();
+    root.close();
+    info
+}
+
+/// Iterate over every volume the firmware has a file system driver for, with its handle, for
+/// showing a list of disks to choose from. Volumes whose information can't be read, such as
+/// drives without media, are yielded with the error.
+///
+/// ```rust,ignore
+/// for (handle, volume) in volumes()? {
+///     if let Ok(volume) = volume {
+///         println!("{} {} MiB free", volume, volume.free_space() >> 20);
+///     }
+/// }
+/// ```
+pub fn volumes() -> Result<Volumes, Status> {
+    let handles = ::get_system_table().boot_services().find_handles::<SimpleFileSystemProtocol>()?;
+    Ok(Volumes { handles, next: 0 })
+}
+
+/// Iterator returned by `volumes`.
+pub struct Volumes {
+    handles: Handles,
+    next: usize,
+}
+
+impl Iterator for Volumes {
+    type Item = (Handle, Result<VolumeInfo, Status>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let &handle = self.handles.as_slice().get(self.next)?;
+        self.next += 1;
+        Some((handle, volume_info(handle)))
+    }
+}
+
+/// An EFI_FILE_INFO, as returned by `FileProtocol::file_info` or read from a directory with
+/// `FileProtocol::read_dir_entry`.
+#[derive(Clone, Copy, Debug)]
+pub struct FileInfo<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> FileInfo<'a> {
+    /// The record at the start of `buf`, whose size field must lie within it.
+    pub fn from_bytes(buf: &'a [u8]) -> Result<FileInfo<'a>, Status> {
+        let size = wire::read_u64(buf, 0)? as usize;
+        if size < FILE_INFO_HEADER_SIZE || size > buf.len() {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(FileInfo { bytes: &buf[..size] })
+    }
+
+    /// Size of the file's contents in bytes.
+    pub fn file_size(&self) -> u64 {
+        wire::read_u64(self.bytes, 8).unwrap_or(0)
+    }
+
+    /// Bytes the file takes on the volume.
+    pub fn physical_size(&self) -> u64 {
+        wire::read_u64(self.bytes, 16).unwrap_or(0)
+    }
+
+    /// The `EFI_FILE_*` attribute bits.
+    pub fn attribute(&self) -> u64 {
+        wire::read_u64(self.bytes, 72).unwrap_or(0)
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attribute() & EFI_FILE_DIRECTORY != 0
+    }
+
+    /// The file's name, without its directory, as UCS-2 units.
+    pub fn file_name(&self) -> wire::Ucs2Units<'a> {
+        wire::read_ucs2(self.bytes, FILE_INFO_HEADER_SIZE)
+    }
+}
+
+/// A directory entry yielded by `DirEntries`, copied out of the EFI_FILE_INFO so it outlives
+/// the read buffer.
+#[derive(Clone, Copy)]
+pub struct DirEntry {
+    file_size: u64,
+    physical_size: u64,
+    attribute: u64,
+    name: [u16; MAX_FILE_PATH],
+    name_len: usize,
+}
+
+impl DirEntry {
+    /// Copy `info`, failing with `Status::BufferTooSmall` if its name is longer than
+    /// `MAX_FILE_PATH` units.
+    fn from_info(info: &FileInfo) -> Result<DirEntry, Status> {
+        let mut entry = DirEntry {
+            file_size: info.file_size(),
+            physical_size: info.physical_size(),
+            attribute: info.attribute(),
+            name: [0; MAX_FILE_PATH],
+            name_len: 0,
+        };
+        for c in info.file_name() {
+            *entry.name.get_mut(entry.name_len).ok_or(Status::BufferTooSmall)? = c;
+            entry.name_len += 1;
+        }
+        Ok(entry)
+    }
+
+    /// Size of the file's contents in bytes.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Bytes the file takes on the volume.
+    pub fn physical_size(&self) -> u64 {
+        self.physical_size
+    }
+
+    /// The `EFI_FILE_*` attribute bits.
+    pub fn attribute(&self) -> u64 {
+        self.attribute
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attribute & EFI_FILE_DIRECTORY != 0
+    }
+
+    /// The file's name, without its directory or a terminator, as UCS-2 units.
+    pub fn name(&self) -> &[u16] {
+        &self.name[..self.name_len]
+    }
+
+    /// Whether the name is `name`, ignoring ASCII case as FAT does.
+    pub fn name_eq(&self, name: &str) -> bool {
+        let mut units = self.name().iter();
+        name.encode_utf16().all(|c| units.next().is_some_and(|&u| {
+            char::from_u32(u as u32).zip(char::from_u32(c as u32)).is_some_and(|(a, b)| a.eq_ignore_ascii_case(&b))
+        })) && units.next().is_none()
+    }
+}
+
+impl fmt::Display for DirEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in char::decode_utf16(self.name().iter().cloned()) {
+            write!(f, "{}", c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DirEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DirEntry {{ name: \"{}\", file_size: {}, attribute: {:#x} }}", self, self.file_size, self.attribute)
+    }
+}
+
+/// Iterator over the entries of a directory, returned by `FileProtocol::entries`. The `.` and
+/// `..` entries are skipped. After an error, iteration ends.
+pub struct DirEntries<'a> {
+    dir: &'a FileProtocol,
+    done: bool,
+}
+
+impl<'a> Iterator for DirEntries<'a> {
+    type Item = Result<DirEntry, Status>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = [0u8; FILE_INFO_HEADER_SIZE + MAX_FILE_PATH * 2];
+        while !self.done {
+            let entry = match self.dir.read_dir_entry(&mut buf) {
+                Ok(Some(info)) => DirEntry::from_info(&info),
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => Err(e),
+            };
+            match entry {
+                Ok(ref entry) if entry.name() == [b'.' as u16] || entry.name() == [b'.' as u16, b'.' as u16] => continue,
+                Ok(entry) => return Some(Ok(entry)),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Type for EFI_FILE_PROTOCOL. Unlike most protocols this is not located through a handle, but
+/// returned by the file system (or the shell) for each open file.
+#[repr(C)]
+pub struct FileProtocol {
+    revision: u64,
+    open: unsafe extern "win64" fn(this: *const FileProtocol, new_handle: *mut *const FileProtocol, file_name: *const u16, open_mode: u64, attributes: u64) -> Status,
+    close: unsafe extern "win64" fn(this: *const FileProtocol) -> Status,
+    delete: unsafe extern "win64" fn(this: *const FileProtocol) -> Status,
+    read: unsafe extern "win64" fn(this: *const FileProtocol, buffer_size: *mut usize, buffer: *mut CVoid) -> Status,
+    write: unsafe extern "win64" fn(this: *const FileProtocol, buffer_size: *mut usize, buffer: *const CVoid) -> Status,
+    get_position: unsafe extern "win64" fn(this: *const FileProtocol, position: *mut u64) -> Status,
+    set_position: unsafe extern "win64" fn(this: *const FileProtocol, position: u64) -> Status,
+    get_info: unsafe extern "win64" fn(this: *const FileProtocol, information_type: &Guid, buffer_size: *mut usize, buffer: *mut CVoid) -> Status,
+    set_info: unsafe extern "win64" fn(this: *const FileProtocol, information_type: &Guid, buffer_size: usize, buffer: *const CVoid) -> Status,
+    flush: unsafe extern "win64" fn(this: *const FileProtocol) -> Status,
+}
+
+impl FileProtocol {
+    /// Open `file_name`, a null-terminated UCS-2 path relative to this directory, with the
+    /// `EFI_FILE_MODE_*` flags in `open_mode`. The file must be closed with `close`.
+    pub fn open(&self, file_name: &[u16], open_mode: u64, attributes: u64) -> Result<&FileProtocol, Status> {
+        if file_name.last() != Some(&0) {
+            return Err(Status::InvalidParameter);
+        }
+
+        let mut file = ptr::null();
+        match unsafe { (self.open)(self, &mut file, file_name.as_ptr(), open_mode, attributes) } {
+            Status::Success => Ok(unsafe { &*file }),
+            e => Err(e),
+        }
+    }
+
+    /// Open `path`, an `EfiPath` or a string such as `"\\EFI\\BOOT\\BOOTX64.EFI"`, relative to
+    /// this directory. Forward slashes may be used too; paths `validate_path` refuses fail with
+    /// `Status::InvalidParameter` without asking the file system.
+    pub fn open_path<P: AsRef<str>>(&self, path: P, open_mode: u64) -> Result<&FileProtocol, Status> {
+        let mut normalized = [0u8; MAX_FILE_PATH * 3];
+        let path = normalize_path(path.as_ref(), &mut normalized)?;
+
+        let mut name = [0u16; MAX_FILE_PATH];
+        ucs2::write_fmt(&mut name, format_args!("{}", path))?;
+        self.open(&name, open_mode, 0)
+    }
+
+    /// Create `path` relative to this directory for writing, replacing any existing file.
+    pub fn create_path<P: AsRef<str>>(&self, path: P) -> Result<&FileProtocol, Status> {
+        let path = path.as_ref();
+        // Delete the old file first, since writing over it would leave its tail if it is longer.
+        if let Ok(old) = self.open_path(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE) {
+            old.delete();
+        }
+        self.open_path(path, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE | EFI_FILE_MODE_CREATE)
+    }
+
+    /// Write all of `buf` at the current position.
+    pub fn write_all(&self, mut buf: &[u8]) -> Result<(), Status> {
+        while !buf.is_empty() {
+            let written = self.write(buf)?;
+            if written == 0 {
+                return Err(Status::DeviceError);
+            }
+            buf = &buf[written..];
+        }
+
+        Ok(())
+    }
+
+    /// Delete the file and close it. The FileProtocol must not be used afterwards. Returns
+    /// `Status::WarnDeleteFailure` if the file was closed but could not be deleted.
+    pub fn delete(&self) -> Status {
+        unsafe {
+            (self.delete)(self)
+        }
+    }
+
+    /// Read up to `buf.len()` bytes from the current position, returning the number of bytes
+    /// read. Zero bytes are returned at end of file.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, Status> {
+        let mut size = buf.len();
+        let status = unsafe { (self.read)(self, &mut size, buf.as_mut_ptr() as *mut CVoid) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(size)
+    }
+
+    /// Write `buf` at the current position, returning the number of bytes written.
+    pub fn write(&self, buf: &[u8]) -> Result<usize, Status> {
+        let mut size = buf.len();
+        let status = unsafe { (self.write)(self, &mut size, buf.as_ptr() as *const CVoid) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(size)
+    }
+
+    /// Get the current position in the file.
+    pub fn get_position(&self) -> Result<u64, Status> {
+        let mut position: u64 = 0;
+        let status = unsafe { (self.get_position)(self, &mut position) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(position)
+    }
+
+    /// Set the current position in the file. Setting it to `0xFFFFFFFFFFFFFFFF` moves to the end
+    /// of the file.
+    pub fn set_position(&self, position: u64) -> Status {
+        unsafe {
+            (self.set_position)(self, position)
+        }
+    }
+
+    /// Read the information of type `information_type` into `buf`, returning its size. Fails
+    /// with `Status::BufferTooSmall` if it does not fit.
+    pub fn get_info(&self, information_type: &Guid, buf: &mut [u8]) -> Result<usize, Status> {
+        let mut size = buf.len();
+        let status = unsafe { (self.get_info)(self, information_type, &mut size, buf.as_mut_ptr() as *mut CVoid) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(size)
+    }
+
+    /// Set the information of type `information_type` from `buf`.
+    pub fn set_info(&self, information_type: &Guid, buf: &[u8]) -> Status {
+        unsafe { (self.set_info)(self, information_type, buf.len(), buf.as_ptr() as *const CVoid) }
+    }
+
+    /// Rename the file to `new_name`, relative to the directory it is in unless it starts with
+    /// a backslash.
+    pub fn rename(&self, new_name: &str) -> Result<(), Status> {
+        let mut buf = [0u8; FILE_INFO_HEADER_SIZE + MAX_FILE_PATH * 2];
+        self.get_info(&EFI_FILE_INFO_ID, &mut buf)?;
+
+        let mut name = [0u16; MAX_FILE_PATH];
+        let len = ucs2::write_fmt(&mut name, format_args!("{}", new_name))? + 1;
+        let size = FILE_INFO_HEADER_SIZE + len * 2;
+        wire::write_u64(&mut buf, 0, size as u64)?;
+        for (i, &c) in name[..len].iter().enumerate() {
+            wire::write_u16(&mut buf, FILE_INFO_HEADER_SIZE + i * 2, c)?;
+        }
+
+        match self.set_info(&EFI_FILE_INFO_ID, &buf[..size]) {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// The file's EFI_FILE_INFO, read into `buf`.
+    pub fn file_info<'b>(&self, buf: &'b mut [u8]) -> Result<FileInfo<'b>, Status> {
+        let size = self.get_info(&EFI_FILE_INFO_ID, buf)?;
+        FileInfo::from_bytes(&buf[..size])
+    }
+
+    /// The EFI_FILE_SYSTEM_INFO of the volume, read into `buf`. Any open file of the volume
+    /// will do.
+    pub fn file_system_info<'b>(&self, buf: &'b mut [u8]) -> Result<FileSystemInfo<'b>, Status> {
+        let size = self.get_info(&EFI_FILE_SYSTEM_INFO_ID, buf)?;
+        FileSystemInfo::from_bytes(&buf[..size])
+    }
+
+    /// The size, free space and label of the volume.
+    pub fn volume_info(&self) -> Result<VolumeInfo, Status> {
+        let mut buf = [0u8; FILE_SYSTEM_INFO_HEADER_SIZE + MAX_FILE_PATH * 2];
+        let info = self.file_system_info(&mut buf)?;
+        Ok(VolumeInfo::from_info(&info))
+    }
+
+    /// The volume label on its own, read into `buf` from the EFI_FILE_SYSTEM_VOLUME_LABEL
+    /// information.
+    pub fn volume_label<'b>(&self, buf: &'b mut [u8]) -> Result<wire::Ucs2Units<'b>, Status> {
+        let size = self.get_info(&EFI_FILE_SYSTEM_VOLUME_LABEL_ID, buf)?;
+        Ok(wire::read_ucs2(&buf[..size], 0))
+    }
+
+    /// Read the next entry of a directory into `buf`, or `None` after the last one. The `.` and
+    /// `..` entries are included. Fails with `Status::BufferTooSmall` if the entry does not
+    /// fit, without moving on to the next one.
+    pub fn read_dir_entry<'b>(&self, buf: &'b mut [u8]) -> Result<Option<FileInfo<'b>>, Status> {
+        match self.read(buf)? {
+            0 => Ok(None),
+            size => FileInfo::from_bytes(&buf[..size]).map(Some),
+        }
+    }
+
+    /// Iterate over the entries of this directory from the start.
+    ///
+    /// ```rust,ignore
+    /// for entry in dir.entries()? {
+    ///     let entry = entry?;
+    ///     println!("{} {}", entry, entry.file_size());
+    /// }
+    /// ```
+    pub fn entries(&self) -> Result<DirEntries<'_>, Status> {
+        // Setting the position of a directory to 0 restarts reading its entries.
+        match self.set_position(0) {
+            Status::Success => Ok(DirEntries { dir: self, done: false }),
+            e => Err(e),
+        }
+    }
+
+    /// Flush all modified data to the device.
+    pub fn flush(&self) -> Status {
+        unsafe {
+            (self.flush)(self)
+        }
+    }
+
+    /// Close the file. The FileProtocol must not be used afterwards.
+    pub fn close(&self) -> Status {
+        unsafe {
+            (self.close)(self)
+        }
+    }
+}
+
+#[test]
+fn dir_entry_from_info() {
+    let mut buf = [0u8; FILE_INFO_HEADER_SIZE + 12];
+    wire::write_u64(&mut buf, 0, (FILE_INFO_HEADER_SIZE + 12) as u64).unwrap();
+    wire::write_u64(&mut buf, 8, 1234).unwrap();
+    wire::write_u64(&mut buf, 72, EFI_FILE_DIRECTORY).unwrap();
+    for (i, c) in "Linux".encode_utf16().enumerate() {
+        wire::write_u16(&mut buf, FILE_INFO_HEADER_SIZE + i * 2, c).unwrap();
+    }
+
+    let entry = DirEntry::from_info(&FileInfo::from_bytes(&buf).unwrap()).unwrap();
+    assert_eq!(entry.file_size(), 1234);
+    assert!(entry.is_directory());
+    assert_eq!(entry.name().len(), 5);
+    assert!(entry.name_eq("LINUX"));
+    assert!(!entry.name_eq("Linu"));
+    assert!(!entry.name_eq("Linux2"));
+}
+
+#[test]
+fn volume_info_from_file_system_info() {
+    let mut buf = [0u8; FILE_SYSTEM_INFO_HEADER_SIZE + 12];
+    wire::write_u64(&mut buf, 0, (FILE_SYSTEM_INFO_HEADER_SIZE + 12) as u64).unwrap();
+    wire::write_u8(&mut buf, 8, 1).unwrap();
+    wire::write_u64(&mut buf, 16, 512 << 20).unwrap();
+    wire::write_u64(&mut buf, 24, 100 << 20).unwrap();
+    wire::write_u32(&mut buf, 32, 4096).unwrap();
+    wire::write_ucs2(&mut buf, FILE_SYSTEM_INFO_HEADER_SIZE, "ESP".encode_utf16()).unwrap();
+
+    let volume = VolumeInfo::from_info(&FileSystemInfo::from_bytes(&buf).unwrap());
+    assert_eq!(volume.label(), &[b'E' as u16, b'S' as u16, b'P' as u16]);
+    assert_eq!(volume.volume_size(), 512 << 20);
+    assert_eq!(volume.free_space(), 100 << 20);
+    assert_eq!(volume.block_size(), 4096);
+    assert!(volume.read_only());
+    assert!(FileSystemInfo::from_bytes(&buf[..20]).is_err());
+}
@@ -0,0 +1,349 @@
+use core::{ptr, slice};
+
+use base::{Handle, Status};
+use guid::Guid;
+use protocol::Protocol;
+use util::wire;
+use void::{CVoid, NotYetDef};
+
+/// GUID for the PI firmware volume protocol
+pub static EFI_FIRMWARE_VOLUME2_PROTOCOL_GUID: Guid = Guid(0x220E73B6, 0x6BDB, 0x4413, [0x84, 0x05, 0xB9, 0x74, 0xB1, 0x08, 0x61, 0x9A]);
+
+/// GUID for the PI firmware volume block protocol
+pub static EFI_FIRMWARE_VOLUME_BLOCK2_PROTOCOL_GUID: Guid = Guid(0x8F644FA9, 0xE850, 0x4DB1, [0x9C, 0xE2, 0x0B, 0x44, 0x69, 0x8E, 0x8D, 0xA4]);
+
+/// FFS file types, as passed to `FirmwareVolume2Protocol::files`.
+pub const EFI_FV_FILETYPE_ALL: u8 = 0x00;
+pub const EFI_FV_FILETYPE_RAW: u8 = 0x01;
+pub const EFI_FV_FILETYPE_FREEFORM: u8 = 0x02;
+pub const EFI_FV_FILETYPE_SECURITY_CORE: u8 = 0x03;
+pub const EFI_FV_FILETYPE_PEI_CORE: u8 = 0x04;
+pub const EFI_FV_FILETYPE_DXE_CORE: u8 = 0x05;
+pub const EFI_FV_FILETYPE_PEIM: u8 = 0x06;
+pub const EFI_FV_FILETYPE_DRIVER: u8 = 0x07;
+pub const EFI_FV_FILETYPE_COMBINED_PEIM_DRIVER: u8 = 0x08;
+pub const EFI_FV_FILETYPE_APPLICATION: u8 = 0x09;
+pub const EFI_FV_FILETYPE_MM: u8 = 0x0A;
+pub const EFI_FV_FILETYPE_FIRMWARE_VOLUME_IMAGE: u8 = 0x0B;
+pub const EFI_FV_FILETYPE_COMBINED_MM_DXE: u8 = 0x0C;
+pub const EFI_FV_FILETYPE_MM_CORE: u8 = 0x0D;
+
+/// FFS section types, as passed to `FirmwareVolume2Protocol::read_section`.
+pub const EFI_SECTION_ALL: u8 = 0x00;
+pub const EFI_SECTION_COMPRESSION: u8 = 0x01;
+pub const EFI_SECTION_GUID_DEFINED: u8 = 0x02;
+pub const EFI_SECTION_DISPOSABLE: u8 = 0x03;
+pub const EFI_SECTION_PE32: u8 = 0x10;
+pub const EFI_SECTION_PIC: u8 = 0x11;
+pub const EFI_SECTION_TE: u8 = 0x12;
+pub const EFI_SECTION_DXE_DEPEX: u8 = 0x13;
+pub const EFI_SECTION_VERSION: u8 = 0x14;
+pub const EFI_SECTION_USER_INTERFACE: u8 = 0x15;
+pub const EFI_SECTION_COMPATIBILITY16: u8 = 0x16;
+pub const EFI_SECTION_FIRMWARE_VOLUME_IMAGE: u8 = 0x17;
+pub const EFI_SECTION_FREEFORM_SUBTYPE_GUID: u8 = 0x18;
+pub const EFI_SECTION_RAW: u8 = 0x19;
+pub const EFI_SECTION_PEI_DEPEX: u8 = 0x1B;
+pub const EFI_SECTION_MM_DEPEX: u8 = 0x1C;
+
+/// Data read from a firmware volume into pool memory allocated by the firmware, which is freed
+/// when this is dropped.
+pub struct FvBuffer {
+    buffer: *mut u8,
+    len: usize,
+}
+
+impl FvBuffer {
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.buffer, self.len) }
+    }
+}
+
+impl Drop for FvBuffer {
+    fn drop(&mut self) {
+        ::get_system_table().boot_services().free_pool(self.buffer);
+    }
+}
+
+/// A file read with `FirmwareVolume2Protocol::read_file`.
+pub struct FvFile {
+    pub file_type: u8,
+    pub attributes: u32,
+    pub data: FvBuffer,
+}
+
+impl FvFile {
+    /// The sections of the file, for files of types that are made of sections (everything but
+    /// `EFI_FV_FILETYPE_RAW`).
+    pub fn sections(&self) -> FfsSections<'_> {
+        FfsSections::new(self.data.as_slice())
+    }
+}
+
+/// A file found by `FirmwareVolume2Protocol::files`.
+#[derive(Clone, Copy, Debug)]
+pub struct FvFileInfo {
+    pub name: Guid,
+    pub file_type: u8,
+    pub attributes: u32,
+    pub size: usize,
+}
+
+#[repr(C)]
+pub struct FirmwareVolume2Protocol {
+    get_volume_attributes: unsafe extern "win64" fn(this: *const FirmwareVolume2Protocol, attributes: *mut u64) -> Status,
+    set_volume_attributes: *const NotYetDef,
+    read_file: unsafe extern "win64" fn(this: *const FirmwareVolume2Protocol, name: *const Guid, buffer: *mut *mut CVoid, buffer_size: *mut usize, found_type: *mut u8, file_attributes: *mut u32, authentication_status: *mut u32) -> Status,
+    read_section: unsafe extern "win64" fn(this: *const FirmwareVolume2Protocol, name: *const Guid, section_type: u8, section_instance: usize, buffer: *mut *mut CVoid, buffer_size: *mut usize, authentication_status: *mut u32) -> Status,
+    write_file: *const NotYetDef,
+    get_next_file: unsafe extern "win64" fn(this: *const FirmwareVolume2Protocol, key: *mut CVoid, file_type: *mut u8, name: *mut Guid, attributes: *mut u32, size: *mut usize) -> Status,
+    key_size: u32,
+    parent_handle: Handle,
+    get_info: *const NotYetDef,
+    set_info: *const NotYetDef,
+}
+
+impl Protocol for FirmwareVolume2Protocol {
+    fn guid() -> &'static Guid {
+        &EFI_FIRMWARE_VOLUME2_PROTOCOL_GUID
+    }
+}
+
+/// Largest `key_size` `FirmwareVolume2Protocol::files` supports.
+const MAX_FV_KEY_SIZE: usize = 64;
+
+impl FirmwareVolume2Protocol {
+    /// The EFI_FV_ATTRIBUTES of the volume.
+    pub fn attributes(&self) -> Result<u64, Status> {
+        let mut attributes = 0;
+        let status = unsafe { (self.get_volume_attributes)(self, &mut attributes) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(attributes)
+    }
+
+    /// The handle of the firmware volume block protocol this volume is read through.
+    pub fn parent_handle(&self) -> Handle {
+        self.parent_handle
+    }
+
+    /// Read the body of file `name`, without the FFS file header. For sectioned files this is the
+    /// sections with their headers, which `FvFile::sections` walks.
+    pub fn read_file(&self, name: &Guid) -> Result<FvFile, Status> {
+        let mut buffer: *mut CVoid = ptr::null_mut();
+        let mut size = 0;
+        let mut file_type = 0;
+        let mut attributes = 0;
+        let mut authentication_status = 0;
+
+        let status = unsafe {
+            (self.read_file)(self, name, &mut buffer, &mut size, &mut file_type, &mut attributes,
+                             &mut authentication_status)
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(FvFile {
+            file_type,
+            attributes,
+            data: FvBuffer { buffer: buffer as *mut u8, len: size },
+        })
+    }
+
+    /// Read the contents of instance `instance` of the sections of type `section_type` in the
+    /// file `name`, without the section header. Encapsulation sections (compressed or GUID
+    /// defined) are searched through by the firmware.
+    pub fn read_section(&self, name: &Guid, section_type: u8, instance: usize) -> Result<FvBuffer, Status> {
+        let mut buffer: *mut CVoid = ptr::null_mut();
+        let mut size = 0;
+        let mut authentication_status = 0;
+
+        let status = unsafe {
+            (self.read_section)(self, name, section_type, instance, &mut buffer, &mut size,
+                                &mut authentication_status)
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(FvBuffer { buffer: buffer as *mut u8, len: size })
+    }
+
+    /// Iterate over the files of type `file_type` in the volume, or all files if it is
+    /// `EFI_FV_FILETYPE_ALL`.
+    pub fn files(&self, file_type: u8) -> Result<FvFiles<'_>, Status> {
+        if self.key_size as usize > MAX_FV_KEY_SIZE {
+            return Err(Status::Unsupported);
+        }
+
+        Ok(FvFiles {
+            fv: self,
+            // An all-zero key starts the search.
+            key: [0; MAX_FV_KEY_SIZE],
+            file_type,
+            done: false,
+        })
+    }
+}
+
+/// Iterator returned by `FirmwareVolume2Protocol::files`.
+pub struct FvFiles<'a> {
+    fv: &'a FirmwareVolume2Protocol,
+    key: [u8; MAX_FV_KEY_SIZE],
+    file_type: u8,
+    done: bool,
+}
+
+impl<'a> Iterator for FvFiles<'a> {
+    type Item = FvFileInfo;
+
+    fn next(&mut self) -> Option<FvFileInfo> {
+        if self.done {
+            return None;
+        }
+
+        let mut file_type = self.file_type;
+        let mut name = Guid(0, 0, 0, [0; 8]);
+        let mut attributes = 0;
+        let mut size = 0;
+
+        let status = unsafe {
+            (self.fv.get_next_file)(self.fv, self.key.as_mut_ptr() as *mut CVoid, &mut file_type,
+                                    &mut name, &mut attributes, &mut size)
+        };
+        if status != Status::Success {
+            // NotFound marks the end of the volume.
+            self.done = true;
+            return None;
+        }
+
+        Some(FvFileInfo { name, file_type, attributes, size })
+    }
+}
+
+/// A section of an FFS file.
+#[derive(Clone, Copy, Debug)]
+pub struct FfsSection<'a> {
+    pub section_type: u8,
+    /// The section contents, after the header.
+    pub data: &'a [u8],
+}
+
+/// Iterator over the sections of an FFS file body, as returned by `FvFile::sections`.
+/// Encapsulation sections are returned as they are, not searched through. Iteration stops at a
+/// malformed section.
+pub struct FfsSections<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> FfsSections<'a> {
+    pub fn new(data: &'a [u8]) -> FfsSections<'a> {
+        FfsSections { data }
+    }
+}
+
+impl<'a> Iterator for FfsSections<'a> {
+    type Item = FfsSection<'a>;
+
+    fn next(&mut self) -> Option<FfsSection<'a>> {
+        // The header is a 24-bit size and a type. Sections of 16MB or more have a size of
+        // 0xFFFFFF, followed by the real size as a u32.
+        let size = wire::read_u32(self.data, 0).ok()?;
+        let section_type = (size >> 24) as u8;
+        let (size, header) = match size & 0xFF_FFFF {
+            0xFF_FFFF => (wire::read_u32(self.data, 4).ok()? as usize, 8),
+            size => (size as usize, 4),
+        };
+        if size < header || size > self.data.len() {
+            self.data = &[];
+            return None;
+        }
+
+        let section = FfsSection { section_type, data: &self.data[header..size] };
+        // Sections are 4-byte aligned.
+        let next = (size + 3) / 4 * 4;
+        self.data = self.data.get(next..).unwrap_or(&[]);
+        Some(section)
+    }
+}
+
+#[repr(C)]
+pub struct FirmwareVolumeBlockProtocol {
+    get_attributes: unsafe extern "win64" fn(this: *const FirmwareVolumeBlockProtocol, attributes: *mut u32) -> Status,
+    set_attributes: *const NotYetDef,
+    get_physical_address: unsafe extern "win64" fn(this: *const FirmwareVolumeBlockProtocol, address: *mut u64) -> Status,
+    get_block_size: unsafe extern "win64" fn(this: *const FirmwareVolumeBlockProtocol, lba: u64, block_size: *mut usize, number_of_blocks: *mut usize) -> Status,
+    read: unsafe extern "win64" fn(this: *const FirmwareVolumeBlockProtocol, lba: u64, offset: usize, num_bytes: *mut usize, buffer: *mut u8) -> Status,
+    write: *const NotYetDef,
+    erase_blocks: *const NotYetDef,
+    parent_handle: Handle,
+}
+
+impl Protocol for FirmwareVolumeBlockProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_FIRMWARE_VOLUME_BLOCK2_PROTOCOL_GUID
+    }
+}
+
+impl FirmwareVolumeBlockProtocol {
+    /// The EFI_FVB_ATTRIBUTES_2 of the volume.
+    pub fn attributes(&self) -> Result<u32, Status> {
+        let mut attributes = 0;
+        let status = unsafe { (self.get_attributes)(self, &mut attributes) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(attributes)
+    }
+
+    /// The address at which the volume is memory mapped, for volumes that are.
+    pub fn physical_address(&self) -> Result<u64, Status> {
+        let mut address = 0;
+        let status = unsafe { (self.get_physical_address)(self, &mut address) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(address)
+    }
+
+    /// The size of block `lba`, and the number of consecutive blocks of that size starting at
+    /// it.
+    pub fn block_size(&self, lba: u64) -> Result<(usize, usize), Status> {
+        let mut block_size = 0;
+        let mut number_of_blocks = 0;
+        let status = unsafe { (self.get_block_size)(self, lba, &mut block_size, &mut number_of_blocks) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok((block_size, number_of_blocks))
+    }
+
+    /// Read from block `lba` at `offset` into `buf`, returning the number of bytes read, which
+    /// is less than `buf.len()` if the read would cross the end of the block.
+    pub fn read(&self, lba: u64, offset: usize, buf: &mut [u8]) -> Result<usize, Status> {
+        let mut len = buf.len();
+        match unsafe { (self.read)(self, lba, offset, &mut len, buf.as_mut_ptr()) } {
+            Status::Success | Status::BadBufferSize => Ok(len),
+            status => Err(status),
+        }
+    }
+
+    pub fn parent_handle(&self) -> Handle {
+        self.parent_handle
+    }
+}
+
+#[test]
+fn ffs_sections() {
+    // A user interface section holding "A", padded to 4 bytes, then a raw section.
+    let data = [0x08, 0x00, 0x00, 0x15, b'A', 0, 0, 0, 0x06, 0x00, 0x00, 0x19, 0xAA, 0xBB];
+    let mut sections = FfsSections::new(&data);
+
+    let ui = sections.next().unwrap();
+    assert_eq!((ui.section_type, ui.data), (EFI_SECTION_USER_INTERFACE, &[b'A', 0, 0, 0][..]));
+    let raw = sections.next().unwrap();
+    assert_eq!((raw.section_type, raw.data), (EFI_SECTION_RAW, &[0xAA, 0xBB][..]));
+    assert!(sections.next().is_none());
+}
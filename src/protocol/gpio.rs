@@ -0,0 +1,118 @@
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+
+/// GUID for the EDK2 embedded GPIO protocol
+pub static EMBEDDED_GPIO_PROTOCOL_GUID: Guid = Guid(0x17A0A3D7, 0xC0A5, 0x4635, [0xBB, 0xD5, 0x07, 0x21, 0x87, 0xDF, 0xF2, 0xEE]);
+
+/// Type for EMBEDDED_GPIO_MODE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum GpioMode {
+    Input = 0x00,
+    OutputLow = 0x0E,
+    OutputHigh = 0x0F,
+    SpecialFunction2 = 0x02,
+    SpecialFunction3 = 0x03,
+    SpecialFunction4 = 0x04,
+    SpecialFunction5 = 0x05,
+    SpecialFunction6 = 0x06,
+    SpecialFunction7 = 0x07,
+}
+
+/// Type for EMBEDDED_GPIO_PULL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum GpioPull {
+    None = 0,
+    Up = 1,
+    Down = 2,
+}
+
+/// The pin number of `pin` on GPIO port `port`, as the protocol numbers them.
+pub fn gpio_pin(port: usize, pin: usize) -> usize {
+    (port << 8) | pin
+}
+
+/// EMBEDDED_GPIO, the GPIO controller protocol of EDK2's EmbeddedPkg, which ARM and RISC-V
+/// boards commonly provide. There is no GPIO protocol in the UEFI or PI specifications.
+#[repr(C)]
+pub struct EmbeddedGpioProtocol {
+    get: unsafe extern "win64" fn(this: *const EmbeddedGpioProtocol, gpio: usize, value: *mut usize) -> Status,
+    set: unsafe extern "win64" fn(this: *const EmbeddedGpioProtocol, gpio: usize, mode: GpioMode) -> Status,
+    get_mode: unsafe extern "win64" fn(this: *const EmbeddedGpioProtocol, gpio: usize, mode: *mut u32) -> Status,
+    set_pull: unsafe extern "win64" fn(this: *const EmbeddedGpioProtocol, gpio: usize, direction: GpioPull) -> Status,
+}
+
+impl Protocol for EmbeddedGpioProtocol {
+    fn guid() -> &'static Guid {
+        &EMBEDDED_GPIO_PROTOCOL_GUID
+    }
+}
+
+impl EmbeddedGpioProtocol {
+    /// Read the level of input `pin`.
+    pub fn get(&self, pin: usize) -> Result<bool, Status> {
+        let mut value = 0;
+        match unsafe { (self.get)(self, pin, &mut value) } {
+            Status::Success => Ok(value != 0),
+            status => Err(status),
+        }
+    }
+
+    /// Set the mode of `pin`, which for outputs also sets the level.
+    pub fn set(&self, pin: usize, mode: GpioMode) -> Status {
+        unsafe { (self.set)(self, pin, mode) }
+    }
+
+    pub fn mode(&self, pin: usize) -> Result<GpioMode, Status> {
+        let mut mode = 0;
+        let status = unsafe { (self.get_mode)(self, pin, &mut mode) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        match mode {
+            0x00 => Ok(GpioMode::Input),
+            0x0E => Ok(GpioMode::OutputLow),
+            0x0F => Ok(GpioMode::OutputHigh),
+            0x02 => Ok(GpioMode::SpecialFunction2),
+            0x03 => Ok(GpioMode::SpecialFunction3),
+            0x04 => Ok(GpioMode::SpecialFunction4),
+            0x05 => Ok(GpioMode::SpecialFunction5),
+            0x06 => Ok(GpioMode::SpecialFunction6),
+            0x07 => Ok(GpioMode::SpecialFunction7),
+            _ => Err(Status::DeviceError),
+        }
+    }
+
+    pub fn set_pull(&self, pin: usize, pull: GpioPull) -> Status {
+        unsafe { (self.set_pull)(self, pin, pull) }
+    }
+
+    /// A handle to a single pin, e.g. to pass to code written against embedded-hal.
+    pub fn pin(&self, pin: usize) -> GpioPin<'_> {
+        GpioPin { gpio: self, pin }
+    }
+}
+
+/// A single pin of an `EmbeddedGpioProtocol` controller.
+#[derive(Clone, Copy)]
+pub struct GpioPin<'a> {
+    gpio: &'a EmbeddedGpioProtocol,
+    pin: usize,
+}
+
+impl<'a> GpioPin<'a> {
+    pub fn set_high(&self) -> Status {
+        self.gpio.set(self.pin, GpioMode::OutputHigh)
+    }
+
+    pub fn set_low(&self) -> Status {
+        self.gpio.set(self.pin, GpioMode::OutputLow)
+    }
+
+    pub fn is_high(&self) -> Result<bool, Status> {
+        self.gpio.get(self.pin)
+    }
+}
@@ -0,0 +1,393 @@
+use core::marker::PhantomData;
+use core::{mem, ptr, slice};
+
+use base::{PhysicalAddress, Status};
+use bmp::BmpImage;
+use guid::Guid;
+use protocol::Protocol;
+
+/// GUID for the graphics output protocol
+pub static EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID: Guid = Guid(0x9042A9DE, 0x23DC, 0x4A38, [0x96, 0xFB, 0x7A, 0xDE, 0xD0, 0x80, 0x51, 0x6A]);
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    RedGreenBlueReserved8BitPerColor = 0,
+    BlueGreenRedReserved8BitPerColor = 1,
+    BitMask = 2,
+    BltOnly = 3,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelBitmask {
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub reserved_mask: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GraphicsOutputModeInformation {
+    pub version: u32,
+    pub horizontal_resolution: u32,
+    pub vertical_resolution: u32,
+    pub pixel_format: PixelFormat,
+    pub pixel_information: PixelBitmask,
+    pub pixels_per_scan_line: u32,
+}
+
+#[repr(C)]
+pub struct GraphicsOutputMode {
+    pub max_mode: u32,
+    pub mode: u32,
+    info: *const GraphicsOutputModeInformation,
+    size_of_info: usize,
+    pub frame_buffer_base: PhysicalAddress,
+    pub frame_buffer_size: usize,
+}
+
+impl GraphicsOutputMode {
+    pub fn info(&self) -> &GraphicsOutputModeInformation {
+        unsafe { &*self.info }
+    }
+}
+
+impl PixelBitmask {
+    /// `pixel` as a framebuffer value with these masks, scaling each 8-bit channel to the
+    /// width of its mask.
+    pub fn encode(&self, pixel: BltPixel) -> u32 {
+        fn channel(value: u8, mask: u32) -> u32 {
+            if mask == 0 {
+                return 0;
+            }
+            let shift = mask.trailing_zeros();
+            let max = mask >> shift;
+            ((value as u32 * max + 127) / 255) << shift & mask
+        }
+        channel(pixel.red, self.red_mask) | channel(pixel.green, self.green_mask) | channel(pixel.blue, self.blue_mask)
+    }
+}
+
+/// The linear framebuffer of a graphics mode, from `GraphicsOutputProtocol::framebuffer`, for
+/// drawing without Blt or handing over to a kernel. It is only valid until the mode changes.
+#[derive(Clone, Copy, Debug)]
+pub struct Framebuffer<'a> {
+    base: PhysicalAddress,
+    size: usize,
+    width: usize,
+    height: usize,
+    pixels_per_scan_line: usize,
+    pixel_format: PixelFormat,
+    pixel_information: PixelBitmask,
+    _gop: PhantomData<&'a GraphicsOutputProtocol>,
+}
+
+impl<'a> Framebuffer<'a> {
+    /// Physical address of the first pixel, which boot services map one to one.
+    pub fn base(&self) -> PhysicalAddress {
+        self.base
+    }
+
+    /// Size of the framebuffer in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Width and height in pixels.
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    /// The masks of each channel when the format is `PixelFormat::BitMask`.
+    pub fn pixel_information(&self) -> PixelBitmask {
+        self.pixel_information
+    }
+
+    /// Pixels from the start of one row to the next, which may be more than the width.
+    pub fn pixels_per_scan_line(&self) -> usize {
+        self.pixels_per_scan_line
+    }
+
+    /// Bytes from the start of one row to the next. Every format GOP defines has 32-bit pixels.
+    pub fn stride(&self) -> usize {
+        self.pixels_per_scan_line * mem::size_of::<u32>()
+    }
+
+    /// `pixel` in the framebuffer's format.
+    pub fn encode(&self, pixel: BltPixel) -> u32 {
+        let (r, g, b) = (pixel.red as u32, pixel.green as u32, pixel.blue as u32);
+        match self.pixel_format {
+            PixelFormat::RedGreenBlueReserved8BitPerColor => r | g << 8 | b << 16,
+            PixelFormat::BlueGreenRedReserved8BitPerColor => b | g << 8 | r << 16,
+            PixelFormat::BitMask => self.pixel_information.encode(pixel),
+            PixelFormat::BltOnly => 0,
+        }
+    }
+
+    /// Set the pixel at (`x`, `y`). Pixels outside the screen are ignored.
+    pub fn write_pixel(&self, x: usize, y: usize, pixel: BltPixel) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let offset = (y * self.pixels_per_scan_line + x) * mem::size_of::<u32>();
+        if offset + mem::size_of::<u32>() > self.size {
+            return;
+        }
+        // The pixel lies within the framebuffer the firmware reported for the current mode.
+        unsafe { ptr::write_volatile((self.base as usize + offset) as *mut u32, self.encode(pixel)) }
+    }
+
+    /// The framebuffer as bytes, for copying a prepared image in.
+    ///
+    /// # Safety
+    ///
+    /// The mode must not change, and nothing else, such as Blt, may draw, while the slice is
+    /// in use.
+    pub unsafe fn as_mut_slice(&self) -> &'a mut [u8] {
+        slice::from_raw_parts_mut(self.base as usize as *mut u8, self.size)
+    }
+}
+
+/// Iterator over the modes of a graphics output device, returned by
+/// `GraphicsOutputProtocol::modes`.
+pub struct GraphicsModes<'a> {
+    gop: &'a GraphicsOutputProtocol,
+    next: u32,
+}
+
+impl<'a> Iterator for GraphicsModes<'a> {
+    type Item = (u32, GraphicsOutputModeInformation);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.gop.mode().max_mode {
+            let mode = self.next;
+            self.next += 1;
+            // Some firmware fails to describe modes it lists; skip them.
+            if let Ok(info) = self.gop.query_mode(mode) {
+                return Some((mode, info));
+            }
+        }
+        None
+    }
+}
+
+/// A pixel as Blt reads and writes them, whatever the framebuffer format.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BltPixel {
+    pub blue: u8,
+    pub green: u8,
+    pub red: u8,
+    pub reserved: u8,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BltOperation {
+    VideoFill = 0,
+    VideoToBltBuffer = 1,
+    BufferToVideo = 2,
+    VideoToVideo = 3,
+}
+
+#[repr(C)]
+pub struct GraphicsOutputProtocol {
+    query_mode: unsafe extern "win64" fn(this: *const GraphicsOutputProtocol, mode_number: u32, size_of_info: *mut usize, info: *mut *const GraphicsOutputModeInformation) -> Status,
+    set_mode: unsafe extern "win64" fn(this: *const GraphicsOutputProtocol, mode_number: u32) -> Status,
+    blt: unsafe extern "win64" fn(this: *const GraphicsOutputProtocol,
+                                  blt_buffer: *mut BltPixel,
+                                  blt_operation: BltOperation,
+                                  source_x: usize,
+                                  source_y: usize,
+                                  destination_x: usize,
+                                  destination_y: usize,
+                                  width: usize,
+                                  height: usize,
+                                  delta: usize)
+                                  -> Status,
+    mode: *const GraphicsOutputMode,
+}
+
+impl Protocol for GraphicsOutputProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID
+    }
+}
+
+impl GraphicsOutputProtocol {
+    pub fn mode(&self) -> &GraphicsOutputMode {
+        unsafe { &*self.mode }
+    }
+
+    /// Describe mode `mode_number`, from 0 up to `mode().max_mode`.
+    pub fn query_mode(&self, mode_number: u32) -> Result<GraphicsOutputModeInformation, Status> {
+        let mut size = 0;
+        let mut info = ptr::null();
+        match unsafe { (self.query_mode)(self, mode_number, &mut size, &mut info) } {
+            Status::Success => {}
+            e => return Err(e),
+        }
+
+        // The information is in pool memory the caller frees. Later versions may add fields.
+        let result = if size >= mem::size_of::<GraphicsOutputModeInformation>() {
+            Ok(unsafe { *info })
+        } else {
+            Err(Status::IncompatibleVersion)
+        };
+        ::get_system_table().boot_services().free_pool(info);
+        result
+    }
+
+    /// The modes of the device with their descriptions.
+    pub fn modes(&self) -> GraphicsModes<'_> {
+        GraphicsModes { gop: self, next: 0 }
+    }
+
+    /// Switch to mode `mode_number`, which clears the screen to black. The text console
+    /// changes size with it.
+    pub fn set_mode(&self, mode_number: u32) -> Result<(), Status> {
+        match unsafe { (self.set_mode)(self, mode_number) } {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// The linear framebuffer of the current mode, or `None` if it has none, as on devices
+    /// that only support Blt.
+    pub fn framebuffer(&self) -> Option<Framebuffer<'_>> {
+        let mode = self.mode();
+        let info = mode.info();
+        if info.pixel_format == PixelFormat::BltOnly || mode.frame_buffer_base == 0 {
+            return None;
+        }
+
+        Some(Framebuffer {
+            base: mode.frame_buffer_base,
+            size: mode.frame_buffer_size,
+            width: info.horizontal_resolution as usize,
+            height: info.vertical_resolution as usize,
+            pixels_per_scan_line: info.pixels_per_scan_line as usize,
+            pixel_format: info.pixel_format,
+            pixel_information: info.pixel_information,
+            _gop: PhantomData,
+        })
+    }
+
+    /// Width and height of the current mode in pixels.
+    pub fn resolution(&self) -> (usize, usize) {
+        let info = self.mode().info();
+        (info.horizontal_resolution as usize, info.vertical_resolution as usize)
+    }
+
+    /// Copy the `width` by `height` rectangle at (`x`, `y`) on screen into `buf`, row by row.
+    pub fn read_rect(&self, x: usize, y: usize, width: usize, height: usize, buf: &mut [BltPixel]) -> Result<(), Status> {
+        if width.checked_mul(height).map_or(true, |n| n > buf.len()) {
+            return Err(Status::BufferTooSmall);
+        }
+
+        let status = unsafe {
+            (self.blt)(self, buf.as_mut_ptr(), BltOperation::VideoToBltBuffer, x, y, 0, 0, width, height,
+                       width * mem::size_of::<BltPixel>())
+        };
+        match status {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Fill the `width` by `height` rectangle at (`x`, `y`) on screen with `color`.
+    pub fn fill_rect(&self, x: usize, y: usize, width: usize, height: usize, color: BltPixel) -> Result<(), Status> {
+        let mut color = color;
+        let status = unsafe { (self.blt)(self, &mut color, BltOperation::VideoFill, 0, 0, x, y, width, height, 0) };
+        match status {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Draw `buf`, a `width` by `height` image stored row by row, at (`x`, `y`) on screen.
+    pub fn write_rect(&self, x: usize, y: usize, width: usize, height: usize, buf: &[BltPixel]) -> Result<(), Status> {
+        if width.checked_mul(height).map_or(true, |n| n > buf.len()) {
+            return Err(Status::BufferTooSmall);
+        }
+
+        // The buffer is only read by this operation.
+        let status = unsafe {
+            (self.blt)(self, buf.as_ptr() as *mut BltPixel, BltOperation::BufferToVideo, 0, 0, x, y, width, height,
+                       width * mem::size_of::<BltPixel>())
+        };
+        match status {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Copy the `width` by `height` rectangle at (`source_x`, `source_y`) on screen to
+    /// (`destination_x`, `destination_y`), as for scrolling. The rectangles may overlap.
+    pub fn copy_rect(&self, source_x: usize, source_y: usize, destination_x: usize, destination_y: usize,
+                     width: usize, height: usize) -> Result<(), Status> {
+        let status = unsafe {
+            (self.blt)(self, ptr::null_mut(), BltOperation::VideoToVideo, source_x, source_y, destination_x, destination_y,
+                       width, height, 0)
+        };
+        match status {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Capture the whole screen as a 24-bit BMP in pool memory.
+    ///
+    /// ```rust,ignore
+    /// gop.capture_to_bmp()?.save("\\screenshot.bmp")?;
+    /// ```
+    pub fn capture_to_bmp(&self) -> Result<BmpImage, Status> {
+        let (width, height) = self.resolution();
+        let mut image = BmpImage::new(width, height)?;
+
+        // Read one row at a time so only a row of BltPixels is needed alongside the image.
+        let bs = ::get_system_table().boot_services();
+        let row = bs.allocate_pool::<BltPixel>(width * mem::size_of::<BltPixel>())?;
+        let pixels = unsafe {
+            ptr::write_bytes(row, 0, width);
+            slice::from_raw_parts_mut(row, width)
+        };
+        let result = (0..height).try_for_each(|y| {
+            self.read_rect(0, y, width, 1, pixels)?;
+            image.set_row(y, pixels);
+            Ok(())
+        });
+        bs.free_pool(row);
+
+        result.map(|()| image)
+    }
+}
+
+#[test]
+fn framebuffer_pixel_encoding() {
+    let pixel = BltPixel { red: 0xFF, green: 0x80, blue: 0x00, reserved: 0 };
+    let mut framebuffer = Framebuffer {
+        base: 0,
+        size: 0,
+        width: 0,
+        height: 0,
+        pixels_per_scan_line: 0,
+        pixel_format: PixelFormat::BlueGreenRedReserved8BitPerColor,
+        pixel_information: PixelBitmask { red_mask: 0, green_mask: 0, blue_mask: 0, reserved_mask: 0 },
+        _gop: PhantomData,
+    };
+    assert_eq!(framebuffer.encode(pixel), 0x00FF8000);
+    framebuffer.pixel_format = PixelFormat::RedGreenBlueReserved8BitPerColor;
+    assert_eq!(framebuffer.encode(pixel), 0x000080FF);
+
+    // RGB565.
+    framebuffer.pixel_format = PixelFormat::BitMask;
+    framebuffer.pixel_information = PixelBitmask { red_mask: 0xF800, green_mask: 0x07E0, blue_mask: 0x001F, reserved_mask: 0 };
+    assert_eq!(framebuffer.encode(pixel), 0xF800 | 32 << 5);
+}
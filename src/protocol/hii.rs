@@ -0,0 +1,70 @@
+use core::ptr;
+
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+use void::{CVoid, NotYetDef};
+
+/// GUID for the HII string protocol
+pub static EFI_HII_STRING_PROTOCOL_GUID: Guid = Guid(0x0FD96974, 0x23AA, 0x4CDC, [0xB9, 0xCB, 0x98, 0xD1, 0x77, 0x50, 0x32, 0x2A]);
+
+/// Type for EFI_HII_HANDLE, identifying a package list in the HII database.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct HiiHandle(pub *mut CVoid);
+
+/// Type for EFI_STRING_ID.
+pub type StringId = u16;
+
+/// Longest RFC 4646 language code, including the null terminator, passed to HII.
+const MAX_LANGUAGE: usize = 32;
+
+#[repr(C)]
+pub struct HiiStringProtocol {
+    new_string: *const NotYetDef,
+    get_string: unsafe extern "win64" fn(this: *const HiiStringProtocol, language: *const u8, package_list: HiiHandle, string_id: StringId, string: *mut u16, string_size: *mut usize, string_font_info: *mut *mut CVoid) -> Status,
+    set_string: *const NotYetDef,
+    get_languages: unsafe extern "win64" fn(this: *const HiiStringProtocol, package_list: HiiHandle, languages: *mut u8, languages_size: *mut usize) -> Status,
+    get_secondary_languages: *const NotYetDef,
+}
+
+impl Protocol for HiiStringProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_HII_STRING_PROTOCOL_GUID
+    }
+}
+
+impl HiiStringProtocol {
+    /// Read string `string_id` from `package_list` in `language` into `buf` as a null-terminated
+    /// UCS-2 string, returning the number of characters (excluding the terminator).
+    pub fn get_string(&self, language: &str, package_list: HiiHandle, string_id: StringId, buf: &mut [u16]) -> Result<usize, Status> {
+        let mut lang = [0u8; MAX_LANGUAGE];
+        if language.len() >= lang.len() {
+            return Err(Status::InvalidParameter);
+        }
+        lang[..language.len()].copy_from_slice(language.as_bytes());
+
+        let mut size = buf.len() * 2;
+        let status = unsafe {
+            (self.get_string)(self, lang.as_ptr(), package_list, string_id, buf.as_mut_ptr(), &mut size, ptr::null_mut())
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok((size / 2).saturating_sub(1))
+    }
+
+    /// Read the semicolon-separated list of languages supported by `package_list` into `buf`,
+    /// returning the list without its null terminator.
+    pub fn get_languages<'a>(&self, package_list: HiiHandle, buf: &'a mut [u8]) -> Result<&'a [u8], Status> {
+        let mut size = buf.len();
+        let status = unsafe { (self.get_languages)(self, package_list, buf.as_mut_ptr(), &mut size) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        let len = buf[..size].iter().position(|&c| c == 0).unwrap_or(size);
+        Ok(&buf[..len])
+    }
+}
@@ -0,0 +1,346 @@
+use core::{ptr, slice, str};
+
+use base::{Event, Handle, Status, ERR_FLAG};
+use guid::Guid;
+use protocol::Protocol;
+use util::ucs2;
+use void::{CVoid, NotYetDef};
+
+/// GUID for the HTTP service binding protocol, installed on each network interface with HTTP
+pub static EFI_HTTP_SERVICE_BINDING_PROTOCOL_GUID: Guid = Guid(0xBDC8E6AF, 0xD9BC, 0x4379, [0xA7, 0x2A, 0xE0, 0xC4, 0xE7, 0x5D, 0xAE, 0x1C]);
+
+/// GUID for the HTTP protocol
+pub static EFI_HTTP_PROTOCOL_GUID: Guid = Guid(0x7A59B29B, 0x910B, 0x4171, [0x82, 0x42, 0xA8, 0x5A, 0x0D, 0xF2, 0x5B, 0x5B]);
+
+/// Longest URL, in UCS-2 units with the terminator, that `HttpProtocol::get` accepts.
+pub const MAX_URL_LENGTH: usize = 512;
+
+/// Type for EFI_SERVICE_BINDING_PROTOCOL. Network drivers install one per interface, under a
+/// GUID naming the protocol its children provide.
+#[repr(C)]
+pub struct ServiceBindingProtocol {
+    create_child: unsafe extern "win64" fn(this: *const ServiceBindingProtocol, child_handle: *mut Handle) -> usize,
+    destroy_child: unsafe extern "win64" fn(this: *const ServiceBindingProtocol, child_handle: Handle) -> usize,
+}
+
+/// The status with the EFI_STATUS value `raw`. Network drivers return codes the `Status` enum
+/// may not have, such as OEM errors, so nothing here reads one as a `Status` directly; those
+/// become `Status::DeviceError`.
+fn status(raw: usize) -> Status {
+    Status::from_usize(raw).unwrap_or(Status::DeviceError)
+}
+
+impl ServiceBindingProtocol {
+    /// Create a child handle with the protocol this service binding provides installed on it.
+    pub fn create_child(&self) -> Result<Handle, Status> {
+        let mut child = Handle::NULL;
+        match status(unsafe { (self.create_child)(self, &mut child) }) {
+            Status::Success => Ok(child),
+            e => Err(e),
+        }
+    }
+
+    pub fn destroy_child(&self, child: Handle) -> Status {
+        status(unsafe { (self.destroy_child)(self, child) })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum HttpVersion {
+    Http10 = 0,
+    Http11 = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum HttpMethod {
+    Get = 0,
+    Post = 1,
+    Patch = 2,
+    Options = 3,
+    Connect = 4,
+    Head = 5,
+    Put = 6,
+    Delete = 7,
+    Trace = 8,
+}
+
+/// Type for EFI_HTTPv4_ACCESS_POINT.
+#[repr(C)]
+struct Httpv4AccessPoint {
+    use_default_address: bool,
+    local_address: [u8; 4],
+    local_subnet: [u8; 4],
+    local_port: u16,
+}
+
+/// Type for EFI_HTTP_CONFIG_DATA.
+#[repr(C)]
+struct HttpConfigData {
+    http_version: HttpVersion,
+    time_out_millisec: u32,
+    local_address_is_ipv6: bool,
+    access_point: *const Httpv4AccessPoint,
+}
+
+#[repr(C)]
+struct HttpRequestData {
+    method: HttpMethod,
+    url: *const u16,
+}
+
+#[repr(C)]
+struct HttpResponseData {
+    /// An EFI_HTTP_STATUS_CODE, which numbers the HTTP status codes in order.
+    status_code: u32,
+}
+
+/// Type for EFI_HTTP_HEADER.
+#[repr(C)]
+pub struct HttpHeader {
+    field_name: *const u8,
+    field_value: *const u8,
+}
+
+fn c_str<'a>(p: *const u8) -> &'a [u8] {
+    if p.is_null() {
+        return &[];
+    }
+    unsafe {
+        let mut len = 0;
+        while *p.add(len) != 0 {
+            len += 1;
+        }
+        slice::from_raw_parts(p, len)
+    }
+}
+
+impl HttpHeader {
+    pub fn name(&self) -> &str {
+        str::from_utf8(c_str(self.field_name)).unwrap_or("")
+    }
+
+    pub fn value(&self) -> &str {
+        str::from_utf8(c_str(self.field_value)).unwrap_or("")
+    }
+}
+
+/// Type for EFI_HTTP_MESSAGE. `data` points at the request or the response data.
+#[repr(C)]
+struct HttpMessage {
+    data: *mut CVoid,
+    header_count: usize,
+    headers: *mut HttpHeader,
+    body_length: usize,
+    body: *mut CVoid,
+}
+
+#[repr(C)]
+struct HttpToken {
+    event: Event,
+    status: usize,
+    message: *mut HttpMessage,
+}
+
+/// EFI_CONNECTION_FIN, which the driver returns once the server has closed the connection.
+const CONNECTION_FIN: usize = ERR_FLAG as usize | 104;
+
+/// EFI_HTTP_STATUS_CODE of 200 OK.
+const HTTP_STATUS_200_OK: u32 = 3;
+
+#[repr(C)]
+pub struct HttpProtocol {
+    get_mode_data: *const NotYetDef,
+    configure: unsafe extern "win64" fn(this: *const HttpProtocol, config: *const HttpConfigData) -> usize,
+    request: unsafe extern "win64" fn(this: *const HttpProtocol, token: *mut HttpToken) -> usize,
+    cancel: *const NotYetDef,
+    response: unsafe extern "win64" fn(this: *const HttpProtocol, token: *mut HttpToken) -> usize,
+    poll: unsafe extern "win64" fn(this: *const HttpProtocol) -> usize,
+}
+
+impl Protocol for HttpProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_HTTP_PROTOCOL_GUID
+    }
+}
+
+/// The host part of an `http://` or `https://` URL, for the Host header.
+fn url_host(url: &str) -> Option<&str> {
+    let rest = url.strip_prefix("http://").or_else(|| url.strip_prefix("https://"))?;
+    let authority = rest.split('/').next()?;
+    let host = authority.rsplit('@').next()?;
+    if host.is_empty() { None } else { Some(host) }
+}
+
+impl HttpProtocol {
+    /// Configure the instance for HTTP/1.1 over IPv4 with the interface's default address.
+    pub fn configure_ipv4(&self, timeout_ms: u32) -> Result<(), Status> {
+        let access_point = Httpv4AccessPoint {
+            use_default_address: true,
+            local_address: [0; 4],
+            local_subnet: [0; 4],
+            local_port: 0,
+        };
+        let config = HttpConfigData {
+            http_version: HttpVersion::Http11,
+            time_out_millisec: timeout_ms,
+            local_address_is_ipv6: false,
+            access_point: &access_point,
+        };
+        match status(unsafe { (self.configure)(self, &config) }) {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Start sending or receiving `message` with `start` and wait for it, polling the driver so
+    /// it makes progress as fast as it can. Fails with the raw EFI_STATUS, so callers can tell
+    /// codes `Status` doesn't have apart.
+    fn run(&self, start: unsafe extern "win64" fn(*const HttpProtocol, *mut HttpToken) -> usize,
+           message: &mut HttpMessage) -> Result<(), usize> {
+        let bs = ::get_system_table().boot_services();
+        let event = bs.create_timer_event().map_err(Status::as_usize)?;
+        let mut token = HttpToken { event, status: Status::Success.as_usize(), message };
+
+        let mut result = unsafe { start(self, &mut token) };
+        if result == Status::Success.as_usize() {
+            while bs.check_event(event) == Status::NotReady {
+                unsafe { (self.poll)(self) };
+            }
+            result = token.status;
+        }
+        bs.close_event(event);
+        if result == Status::Success.as_usize() { Ok(()) } else { Err(result) }
+    }
+
+    /// Send a GET request for `url` and read the response headers and the start of the body
+    /// into `buf`, returning a response to read the rest of the body from. Fails with
+    /// `Status::NotFound` unless the server answers 200 OK.
+    pub fn get<'a>(&'a self, url: &str, buf: &mut [u8]) -> Result<HttpResponse<'a>, Status> {
+        let host = url_host(url).ok_or(Status::InvalidParameter)?;
+        let mut url_buf = [0u16; MAX_URL_LENGTH];
+        ucs2::write_fmt(&mut url_buf, format_args!("{}", url))?;
+
+        // Header fields are null-terminated ASCII.
+        let mut host_buf = [0u8; 256];
+        let host_value = host_buf.get_mut(..host.len() + 1).ok_or(Status::InvalidParameter)?;
+        host_value[..host.len()].copy_from_slice(host.as_bytes());
+        let mut headers = [
+            HttpHeader { field_name: b"Host\0".as_ptr(), field_value: host_value.as_ptr() },
+            HttpHeader { field_name: b"Accept\0".as_ptr(), field_value: b"*/*\0".as_ptr() },
+        ];
+
+        let mut request = HttpRequestData { method: HttpMethod::Get, url: url_buf.as_ptr() };
+        let mut message = HttpMessage {
+            data: &mut request as *mut HttpRequestData as *mut CVoid,
+            header_count: headers.len(),
+            headers: headers.as_mut_ptr(),
+            body_length: 0,
+            body: ptr::null_mut(),
+        };
+        self.run(self.request, &mut message).map_err(status)?;
+
+        let mut response = HttpResponseData { status_code: 0 };
+        let mut message = HttpMessage {
+            data: &mut response as *mut HttpResponseData as *mut CVoid,
+            header_count: 0,
+            headers: ptr::null_mut(),
+            body_length: buf.len(),
+            body: buf.as_mut_ptr() as *mut CVoid,
+        };
+        self.run(self.response, &mut message).map_err(status)?;
+
+        let response = HttpResponse {
+            http: self,
+            headers: message.headers,
+            header_count: message.header_count,
+            first_body_length: message.body_length,
+            status_code: response.status_code,
+        };
+        if response.status_code != HTTP_STATUS_200_OK {
+            return Err(Status::NotFound);
+        }
+        Ok(response)
+    }
+}
+
+/// A response started by `HttpProtocol::get`. The headers are in pool memory allocated by the
+/// driver, which is freed when this is dropped.
+pub struct HttpResponse<'a> {
+    http: &'a HttpProtocol,
+    headers: *mut HttpHeader,
+    header_count: usize,
+    first_body_length: usize,
+    status_code: u32,
+}
+
+impl<'a> HttpResponse<'a> {
+    pub fn headers(&self) -> &[HttpHeader] {
+        if self.headers.is_null() {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.headers, self.header_count) }
+    }
+
+    /// The value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers().iter().find(|h| h.name().eq_ignore_ascii_case(name)).map(|h| h.value())
+    }
+
+    /// The body length from the Content-Length header, if the server sent one.
+    pub fn content_length(&self) -> Option<u64> {
+        self.header("Content-Length")?.trim().parse().ok()
+    }
+
+    /// Bytes of the body read into the buffer passed to `HttpProtocol::get`.
+    pub fn first_body_length(&self) -> usize {
+        self.first_body_length
+    }
+
+    /// Read more of the body into `buf`, returning the number of bytes read. Returns 0 once the
+    /// server has closed the connection, which ends a body sent without a Content-Length.
+    pub fn read_body(&self, buf: &mut [u8]) -> Result<usize, Status> {
+        let mut message = HttpMessage {
+            data: ptr::null_mut(),
+            header_count: 0,
+            headers: ptr::null_mut(),
+            body_length: buf.len(),
+            body: buf.as_mut_ptr() as *mut CVoid,
+        };
+        match self.http.run(self.http.response, &mut message) {
+            Ok(()) => Ok(message.body_length),
+            Err(CONNECTION_FIN) => Ok(0),
+            Err(e) => Err(status(e)),
+        }
+    }
+}
+
+impl<'a> Drop for HttpResponse<'a> {
+    fn drop(&mut self) {
+        let bs = ::get_system_table().boot_services();
+        for header in self.headers() {
+            bs.free_pool(header.field_name);
+            bs.free_pool(header.field_value);
+        }
+        if !self.headers.is_null() {
+            bs.free_pool(self.headers);
+        }
+    }
+}
+
+#[test]
+fn http_url_host() {
+    assert_eq!(url_host("http://example.com/image.img"), Some("example.com"));
+    assert_eq!(url_host("https://user@10.0.0.1:8080"), Some("10.0.0.1:8080"));
+    assert_eq!(url_host("tftp://example.com/x"), None);
+}
+
+#[test]
+fn http_statuses() {
+    assert_eq!(status(0), Status::Success);
+    assert_eq!(status(Status::HttpError.as_usize()), Status::HttpError);
+    assert_eq!(status(Status::IpAddressConflict.as_usize()), Status::IpAddressConflict);
+    // An OEM error, with the top two bits set.
+    assert_eq!(status(Status::HttpError.as_usize() | (Status::HttpError.as_usize() >> 1)), Status::DeviceError);
+}
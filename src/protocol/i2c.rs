@@ -0,0 +1,204 @@
+use core::marker::PhantomData;
+use core::{array, ptr};
+
+use base::{Event, Status};
+use guid::Guid;
+use protocol::Protocol;
+
+/// GUID for the PI I2C I/O protocol
+pub static EFI_I2C_IO_PROTOCOL_GUID: Guid = Guid(0xB60A3E6B, 0x18C4, 0x46E5, [0xA2, 0x9A, 0xC9, 0xA1, 0x06, 0x65, 0xA2, 0x8E]);
+
+/// GUID for the PI I2C master protocol
+pub static EFI_I2C_MASTER_PROTOCOL_GUID: Guid = Guid(0xCD72881F, 0x45B5, 0x4FEB, [0x98, 0xC8, 0x31, 0x3D, 0xA8, 0x11, 0x74, 0x62]);
+
+/// Flags of an `I2cOperation`.
+pub const I2C_FLAG_READ: u32 = 0x0000_0001;
+pub const I2C_FLAG_SMBUS_OPERATION: u32 = 0x0001_0000;
+pub const I2C_FLAG_SMBUS_BLOCK: u32 = 0x0002_0000;
+pub const I2C_FLAG_SMBUS_PROCESS_CALL: u32 = 0x0004_0000;
+pub const I2C_FLAG_SMBUS_PEC: u32 = 0x0008_0000;
+
+/// Most operations one I2C request can hold.
+pub const MAX_I2C_OPERATIONS: usize = 8;
+
+/// Type for EFI_I2C_OPERATION, one read or write of an I2C request. Operations of a request
+/// are separated by repeated starts, without a stop in between.
+#[repr(C)]
+pub struct I2cOperation<'a> {
+    flags: u32,
+    length: u32,
+    buffer: *mut u8,
+    _buffer: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> I2cOperation<'a> {
+    /// An operation transferring nothing, to fill unused slots of a request.
+    pub(crate) fn empty() -> I2cOperation<'a> {
+        I2cOperation {
+            flags: 0,
+            length: 0,
+            buffer: ptr::null_mut(),
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Read `buf.len()` bytes from the device into `buf`.
+    pub fn read(buf: &'a mut [u8]) -> I2cOperation<'a> {
+        I2cOperation {
+            flags: I2C_FLAG_READ,
+            length: buf.len() as u32,
+            buffer: buf.as_mut_ptr(),
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Write `data` to the device.
+    pub fn write(data: &'a [u8]) -> I2cOperation<'a> {
+        I2cOperation {
+            flags: 0,
+            length: data.len() as u32,
+            // The firmware only reads the buffer of a write.
+            buffer: data.as_ptr() as *mut u8,
+            _buffer: PhantomData,
+        }
+    }
+}
+
+/// Type for EFI_I2C_REQUEST_PACKET, with room for `MAX_I2C_OPERATIONS` operations.
+#[repr(C)]
+struct I2cRequestPacket {
+    operation_count: usize,
+    operations: [I2cOperation<'static>; MAX_I2C_OPERATIONS],
+}
+
+impl I2cRequestPacket {
+    fn new(operations: &mut [I2cOperation]) -> Result<I2cRequestPacket, Status> {
+        if operations.is_empty() || operations.len() > MAX_I2C_OPERATIONS {
+            return Err(Status::InvalidParameter);
+        }
+
+        let mut packet = I2cRequestPacket {
+            operation_count: operations.len(),
+            operations: array::from_fn(|_| I2cOperation::empty()),
+        };
+        for (slot, op) in packet.operations.iter_mut().zip(operations.iter()) {
+            slot.flags = op.flags;
+            slot.length = op.length;
+            slot.buffer = op.buffer;
+        }
+        Ok(packet)
+    }
+}
+
+/// Type for EFI_I2C_CONTROLLER_CAPABILITIES.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct I2cControllerCapabilities {
+    pub structure_size_in_bytes: u32,
+    pub maximum_receive_bytes: u32,
+    pub maximum_transmit_bytes: u32,
+    pub maximum_total_bytes: u32,
+}
+
+/// EFI_I2C_IO_PROTOCOL, installed on the handle of each I2C device the platform describes.
+/// Devices may answer at several addresses, which are selected by index rather than by address.
+#[repr(C)]
+pub struct I2cIoProtocol {
+    queue_request: unsafe extern "win64" fn(this: *const I2cIoProtocol, slave_address_index: usize, event: Event, request_packet: *mut I2cRequestPacket, i2c_status: *mut Status) -> Status,
+    device_guid: *const Guid,
+    device_index: u32,
+    hardware_revision: u32,
+    i2c_controller_capabilities: *const I2cControllerCapabilities,
+}
+
+impl Protocol for I2cIoProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_I2C_IO_PROTOCOL_GUID
+    }
+}
+
+impl I2cIoProtocol {
+    /// The GUID identifying the kind of device, as chosen by the platform.
+    pub fn device_guid(&self) -> &Guid {
+        unsafe { &*self.device_guid }
+    }
+
+    /// Index distinguishing several devices with the same `device_guid`.
+    pub fn device_index(&self) -> u32 {
+        self.device_index
+    }
+
+    pub fn hardware_revision(&self) -> u32 {
+        self.hardware_revision
+    }
+
+    pub fn capabilities(&self) -> &I2cControllerCapabilities {
+        unsafe { &*self.i2c_controller_capabilities }
+    }
+
+    /// Perform `operations` on the device at its `address_index`th address, waiting for them to
+    /// complete.
+    pub fn execute(&self, address_index: usize, operations: &mut [I2cOperation]) -> Result<(), Status> {
+        let mut packet = I2cRequestPacket::new(operations)?;
+        let status = unsafe {
+            (self.queue_request)(self, address_index, Event(ptr::null_mut()), &mut packet, ptr::null_mut())
+        };
+        match status {
+            Status::Success => Ok(()),
+            status => Err(status),
+        }
+    }
+
+    /// Write `data` and then read `buf.len()` bytes, as for reading a register.
+    pub fn write_read(&self, address_index: usize, data: &[u8], buf: &mut [u8]) -> Result<(), Status> {
+        self.execute(address_index, &mut [I2cOperation::write(data), I2cOperation::read(buf)])
+    }
+}
+
+/// EFI_I2C_MASTER_PROTOCOL, the I2C host controller, which addresses devices by their
+/// address on the bus. Devices described by the platform should be used through their
+/// `I2cIoProtocol` instead, which serializes access with other drivers.
+#[repr(C)]
+pub struct I2cMasterProtocol {
+    set_bus_frequency: unsafe extern "win64" fn(this: *const I2cMasterProtocol, bus_clock_hertz: *mut usize) -> Status,
+    reset: unsafe extern "win64" fn(this: *const I2cMasterProtocol) -> Status,
+    start_request: unsafe extern "win64" fn(this: *const I2cMasterProtocol, slave_address: usize, request_packet: *mut I2cRequestPacket, event: Event, i2c_status: *mut Status) -> Status,
+    i2c_controller_capabilities: *const I2cControllerCapabilities,
+}
+
+impl Protocol for I2cMasterProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_I2C_MASTER_PROTOCOL_GUID
+    }
+}
+
+impl I2cMasterProtocol {
+    /// Set the bus clock to at most `hertz`, returning the frequency actually used.
+    pub fn set_bus_frequency(&self, hertz: usize) -> Result<usize, Status> {
+        let mut hertz = hertz;
+        match unsafe { (self.set_bus_frequency)(self, &mut hertz) } {
+            Status::Success => Ok(hertz),
+            status => Err(status),
+        }
+    }
+
+    pub fn reset(&self) -> Status {
+        unsafe { (self.reset)(self) }
+    }
+
+    pub fn capabilities(&self) -> &I2cControllerCapabilities {
+        unsafe { &*self.i2c_controller_capabilities }
+    }
+
+    /// Perform `operations` on the device at 7-bit `address`, waiting for them to complete.
+    pub fn execute(&self, address: u16, operations: &mut [I2cOperation]) -> Result<(), Status> {
+        let mut packet = I2cRequestPacket::new(operations)?;
+        let status = unsafe {
+            (self.start_request)(self, address as usize, &mut packet, Event(ptr::null_mut()), ptr::null_mut())
+        };
+        match status {
+            Status::Success => Ok(()),
+            status => Err(status),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use core::mem;
+
+use guid::Guid;
+use void::CVoid;
+
+/// A protocol interface found by a GUID only known at runtime, for instance one read from a
+/// configuration file. Since there is no Rust type describing it, the interface is kept as a raw
+/// pointer, and functions in it are called through signatures supplied by the caller.
+#[derive(Clone, Copy, Debug)]
+pub struct Interface {
+    guid: Guid,
+    ptr: *mut CVoid,
+}
+
+impl Interface {
+    pub fn new(guid: Guid, ptr: *mut CVoid) -> Interface {
+        Interface { guid, ptr }
+    }
+
+    /// The protocol GUID the interface was looked up with.
+    pub fn guid(&self) -> &Guid {
+        &self.guid
+    }
+
+    /// The interface pointer, which is passed as the `This` argument of protocol functions.
+    pub fn as_ptr(&self) -> *mut CVoid {
+        self.ptr
+    }
+
+    /// View the interface as a protocol struct `T`.
+    ///
+    /// # Safety
+    ///
+    /// Nothing checks that `T` matches the layout of the interface.
+    pub unsafe fn cast<T>(&self) -> &'static T {
+        &*(self.ptr as *const T)
+    }
+
+    /// Read the function pointer at pointer-sized slot `index` of the interface, as type `F`,
+    /// which should be an `unsafe extern "win64" fn(...)` type. Most protocols are a table of
+    /// function pointers, where slot 0 is the first member.
+    ///
+    /// ```rust,ignore
+    /// type GetValue = unsafe extern "win64" fn(this: *mut CVoid, value: *mut u32) -> Status;
+    /// let get_value: GetValue = unsafe { interface.function(1) };
+    /// let status = unsafe { get_value(interface.as_ptr(), &mut value) };
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Nothing checks that slot `index` exists or holds a function of type `F`.
+    pub unsafe fn function<F: Copy>(&self, index: usize) -> F {
+        assert_eq!(mem::size_of::<F>(), mem::size_of::<usize>());
+        *((self.ptr as *const usize).add(index) as *const F)
+    }
+
+    /// Read a data member of type `V` at byte `offset` into the interface.
+    ///
+    /// # Safety
+    ///
+    /// Nothing checks that the interface has such a member.
+    pub unsafe fn read<V: Copy>(&self, offset: usize) -> V {
+        *((self.ptr as *const u8).add(offset) as *const V)
+    }
+}
@@ -0,0 +1,86 @@
+//! EFI_LEGACY_BIOS_PROTOCOL, from the Compatibility Support Module specification. Only the calls
+//! needed to boot a legacy OS, and to make BIOS interrupt calls, are bound.
+//!
+//! This protocol only exists on firmware with a CSM, and calling into the BIOS is inherently
+//! fragile, so it is only built with the `legacy-bios` feature.
+
+use base::Status;
+use bbs::{BbsNode, EFI_LEGACY_BIOS_PROTOCOL_GUID};
+use guid::Guid;
+use protocol::{DevicePathNode, Protocol};
+use void::{CVoid, NotYetDef};
+
+/// Type for EFI_IA32_REGISTER_SET, in its 32-bit register view. Registers are passed to and
+/// returned from real mode BIOS calls with `LegacyBiosProtocol::int86`. 16 and 8-bit registers
+/// are the low bits of the 32-bit ones, e.g. AH is `(eax >> 8) as u8`.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Ia32RegisterSet {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+    pub esi: u32,
+    pub edi: u32,
+    pub eflags: u32,
+    pub es: u16,
+    pub cs: u16,
+    pub ss: u16,
+    pub ds: u16,
+    pub fs: u16,
+    pub gs: u16,
+    pub ebp: u32,
+    pub esp: u32,
+}
+
+#[repr(C)]
+pub struct LegacyBiosProtocol {
+    int86: unsafe extern "win64" fn(this: *const LegacyBiosProtocol, bios_int: u8, regs: *mut Ia32RegisterSet) -> u8,
+    far_call86: *const NotYetDef,
+    check_pci_rom: *const NotYetDef,
+    install_pci_rom: *const NotYetDef,
+    legacy_boot: unsafe extern "win64" fn(this: *const LegacyBiosProtocol, boot_option: *const u8, load_options_size: u32, load_options: *const CVoid) -> Status,
+    update_keyboard_led_status: *const NotYetDef,
+    get_bbs_info: *const NotYetDef,
+    shadow_all_legacy_oproms: *const NotYetDef,
+    prepare_to_boot_efi: *const NotYetDef,
+    get_legacy_region: *const NotYetDef,
+    copy_legacy_region: *const NotYetDef,
+    boot_unconventional_device: *const NotYetDef,
+}
+
+impl Protocol for LegacyBiosProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_LEGACY_BIOS_PROTOCOL_GUID
+    }
+}
+
+impl LegacyBiosProtocol {
+    /// Boot the legacy device `boot_option`, a BBS device path node such as one from a `Boot####`
+    /// variable. This only returns if the boot fails.
+    pub fn legacy_boot(&self, boot_option: &DevicePathNode, load_options: &[u8]) -> Status {
+        if BbsNode::parse(boot_option).is_err() || load_options.len() > u32::MAX as usize {
+            return Status::InvalidParameter;
+        }
+
+        unsafe {
+            (self.legacy_boot)(self, boot_option.as_bytes().as_ptr(), load_options.len() as u32,
+                               load_options.as_ptr() as *const CVoid)
+        }
+    }
+
+    /// Issue BIOS interrupt `interrupt` in real mode with the registers in `regs`, which are
+    /// updated with the values the BIOS returned. A set carry flag, which BIOS services use to
+    /// report failure, is returned as `Status::DeviceError`.
+    ///
+    /// # Safety
+    ///
+    /// BIOS services can do anything to the machine, including to memory and devices owned by
+    /// the firmware. This is a last resort for hardware that has no UEFI driver.
+    pub unsafe fn int86(&self, interrupt: u8, regs: &mut Ia32RegisterSet) -> Result<(), Status> {
+        if (self.int86)(self, interrupt, regs) != 0 {
+            return Err(Status::DeviceError);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use core::{cmp, mem, ptr, slice};
+
+use base::{MemoryType, Status};
+use bootservices::AllocateType;
+use guid::Guid;
+use memmap::EFI_PAGE_SIZE;
+use protocol::Protocol;
+use void::CVoid;
+
+/// GUID for the MM Communication 2 protocol, used to send messages to MM (SMM) handlers
+pub static EFI_MM_COMMUNICATION2_PROTOCOL_GUID: Guid = Guid(0x378DAEDC, 0xF06B, 0x4446, [0x83, 0x14, 0x40, 0xAB, 0x93, 0x3C, 0x87, 0xA3]);
+
+/// Type for EFI_MM_COMMUNICATE_HEADER, which starts every communication buffer and is followed by
+/// `message_length` bytes of message.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MmCommunicateHeader {
+    /// GUID of the MM handler the message is for.
+    pub header_guid: Guid,
+    pub message_length: usize,
+}
+
+/// Size of `MmCommunicateHeader`, where the message starts.
+pub const MM_COMMUNICATE_HEADER_SIZE: usize = mem::size_of::<MmCommunicateHeader>();
+
+#[repr(C)]
+pub struct MmCommunication2Protocol {
+    communicate: unsafe extern "win64" fn(this: *const MmCommunication2Protocol, comm_buffer_physical: *mut CVoid, comm_buffer_virtual: *mut CVoid, comm_size: *mut usize) -> Status,
+}
+
+impl Protocol for MmCommunication2Protocol {
+    fn guid() -> &'static Guid {
+        &EFI_MM_COMMUNICATION2_PROTOCOL_GUID
+    }
+}
+
+/// A communication buffer in runtime memory, which MM handlers can access. It is freed on drop.
+pub struct MmCommBuffer {
+    address: u64,
+    pages: usize,
+}
+
+impl MmCommBuffer {
+    /// Allocate a buffer of at least `size` bytes, including the header.
+    pub fn new(size: usize) -> Result<MmCommBuffer, Status> {
+        let size = size as u64;
+        let pages = cmp::max(1, (size / EFI_PAGE_SIZE + (size % EFI_PAGE_SIZE != 0) as u64) as usize);
+        let address = ::get_system_table()
+            .boot_services()
+            .allocate_pages(AllocateType::AnyPages, MemoryType::RuntimeServicesData, pages)?;
+
+        Ok(MmCommBuffer { address, pages })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.pages * EFI_PAGE_SIZE as usize
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.address as usize as *mut u8
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.capacity()) }
+    }
+}
+
+impl Drop for MmCommBuffer {
+    fn drop(&mut self) {
+        ::get_system_table().boot_services().free_pages(self.address, self.pages);
+    }
+}
+
+impl MmCommunication2Protocol {
+    /// Pass a buffer starting with an `MmCommunicateHeader` to MM. `comm_size` is the size of the
+    /// buffer on input, and on `BadBufferSize` the size the handler needs.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must point to a communication buffer of `comm_size` bytes in memory MM can access,
+    /// identity mapped as during boot services.
+    pub unsafe fn communicate_raw(&self, buffer: *mut u8, comm_size: &mut usize) -> Status {
+        (self.communicate)(self, buffer as *mut CVoid, buffer as *mut CVoid, comm_size)
+    }
+
+    /// Send `request` to the MM handler identified by `handler`, and copy its reply into
+    /// `response`, returning the reply length.
+    ///
+    /// If the handler reports the buffer is too small, a buffer of the size it asks for is
+    /// allocated and the request sent again. A reply longer than `response` fails with
+    /// `BufferTooSmall`.
+    pub fn communicate(&self, handler: &Guid, request: &[u8], response: &mut [u8]) -> Result<usize, Status> {
+        let mut size = MM_COMMUNICATE_HEADER_SIZE + cmp::max(request.len(), response.len());
+
+        // One retry, if the handler asks for a bigger buffer.
+        for _ in 0..2 {
+            let mut buffer = MmCommBuffer::new(size)?;
+            let mut comm_size = buffer.capacity();
+
+            unsafe {
+                let header = MmCommunicateHeader { header_guid: *handler, message_length: request.len() };
+                ptr::write_unaligned(buffer.as_ptr() as *mut MmCommunicateHeader, header);
+            }
+            buffer.as_mut_slice()[MM_COMMUNICATE_HEADER_SIZE..MM_COMMUNICATE_HEADER_SIZE + request.len()]
+                .copy_from_slice(request);
+
+            match unsafe { self.communicate_raw(buffer.as_ptr(), &mut comm_size) } {
+                Status::Success => {}
+                Status::BadBufferSize | Status::BufferTooSmall if comm_size > size => {
+                    size = comm_size;
+                    continue;
+                }
+                e => return Err(e),
+            }
+
+            let header = unsafe { ptr::read_unaligned(buffer.as_ptr() as *const MmCommunicateHeader) };
+            let len = header.message_length;
+            if len > buffer.capacity() - MM_COMMUNICATE_HEADER_SIZE {
+                return Err(Status::ProtocolError);
+            }
+            if len > response.len() {
+                return Err(Status::BufferTooSmall);
+            }
+
+            response[..len].copy_from_slice(&buffer.as_mut_slice()[MM_COMMUNICATE_HEADER_SIZE..MM_COMMUNICATE_HEADER_SIZE + len]);
+            return Ok(len);
+        }
+
+        Err(Status::BadBufferSize)
+    }
+}
@@ -1,12 +1,75 @@
+use core::{char, slice, str};
+
 use base::{Handle, MemoryType, Status};
 use guid::Guid;
+use path::EfiPath;
 use void::NotYetDef;
 
+mod adapter_info;
+#[cfg(feature = "apple")]
+mod apple;
+mod block_io;
+mod component_name;
+mod console_control;
+mod debug_support;
+mod decompress;
 mod device_path;
+mod driver_binding;
+mod file;
+mod fv;
+mod gpio;
+mod graphics;
+mod hii;
+mod http;
+mod i2c;
+mod interface;
+#[cfg(feature = "legacy-bios")]
+mod legacy_bios;
+mod mm;
+mod nvme;
+mod partition_info;
+mod rng;
+mod sd_mmc;
 mod serial;
+mod shell;
+mod spi_nor;
+mod storage_security;
+mod usb_fn;
+mod wifi;
+mod tcg2;
 
+pub use self::adapter_info::*;
+#[cfg(feature = "apple")]
+pub use self::apple::*;
+pub use self::block_io::*;
+pub use self::component_name::*;
+pub use self::console_control::*;
+pub use self::debug_support::*;
+pub use self::decompress::*;
 pub use self::device_path::*;
+pub use self::driver_binding::*;
+pub use self::file::*;
+pub use self::fv::*;
+pub use self::gpio::*;
+pub use self::graphics::*;
+pub use self::hii::*;
+pub use self::http::*;
+pub use self::i2c::*;
+pub use self::interface::*;
+#[cfg(feature = "legacy-bios")]
+pub use self::legacy_bios::*;
+pub use self::mm::*;
+pub use self::nvme::*;
+pub use self::partition_info::*;
+pub use self::rng::*;
+pub use self::sd_mmc::*;
 pub use self::serial::*;
+pub use self::shell::*;
+pub use self::spi_nor::*;
+pub use self::storage_security::*;
+pub use self::usb_fn::*;
+pub use self::wifi::*;
+pub use self::tcg2::*;
 
 pub trait Protocol {
     fn guid() -> &'static Guid;
@@ -16,6 +79,7 @@ pub trait Protocol {
 pub static EFI_LOADED_IMAGE_PROTOCOL_GUID: Guid = Guid(0x5B1B31A1, 0x9562, 0x11d2, [0x8E,0x3F,0x00,0xA0,0xC9,0x69,0x72,0x3B]);
 
 static mut THIS_LOADED_IMAGE: *const LoadedImageProtocol = 0 as *const LoadedImageProtocol;
+static mut THIS_IMAGE_HANDLE: Handle = Handle::NULL;
 
 #[derive(Debug)]
 #[repr(C)]
@@ -27,7 +91,7 @@ pub struct LoadedImageProtocol {
     pub file_path: *const DevicePathProtocol,
     __reserved: *const NotYetDef,
     load_options_size: u32,
-    load_options: *const NotYetDef,
+    load_options: *const u8,
     pub image_base: usize,
     pub image_size: u64,
     image_code_type: MemoryType,
@@ -43,6 +107,82 @@ impl Protocol for LoadedImageProtocol {
     }
 }
 
+impl LoadedImageProtocol {
+    /// The load options the image was started with. For boot entries and the shell, these are
+    /// usually a UCS-2 command line.
+    pub fn load_options(&self) -> &[u8] {
+        if self.load_options.is_null() {
+            return &[];
+        }
+
+        unsafe { slice::from_raw_parts(self.load_options, self.load_options_size as usize) }
+    }
+
+    /// The file system the image was loaded from. Fails with `Status::Unsupported` if it was
+    /// not loaded from one, as when loaded from a buffer or over the network.
+    pub fn volume(&self) -> Result<&'static SimpleFileSystemProtocol, Status> {
+        ::get_system_table().boot_services().handle_protocol(self.device_handle)
+    }
+
+    /// The path of the image file on its volume, such as `\EFI\myapp\myapp.efi`, from the
+    /// file path nodes of `file_path`. Fails with `Status::NotFound` if there are none.
+    pub fn path(&self) -> Result<EfiPath, Status> {
+        let path = unsafe { DevicePath::from_ptr(self.file_path)? };
+
+        // A path may be split over several nodes, each with its own terminator; `EfiPath`
+        // collapses the separators doubled by joining them.
+        let mut buf = [0u8; MAX_FILE_PATH * 3];
+        let mut len = 0;
+        let mut push = |c: char| {
+            if len + c.len_utf8() > buf.len() {
+                return Err(Status::BufferTooSmall);
+            }
+            len += c.encode_utf8(&mut buf[len..]).len();
+            Ok(())
+        };
+        let mut found = false;
+        for node in path.nodes() {
+            if node.node_type() != DevicePathTypes::Media as u8 || node.sub_type() != MediaSubTypes::FilePath as u8 {
+                continue;
+            }
+            if found {
+                push('\\')?;
+            }
+            let units = node.data().chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).take_while(|&c| c != 0);
+            for c in char::decode_utf16(units) {
+                push(c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+            }
+            found = true;
+        }
+        if !found {
+            return Err(Status::NotFound);
+        }
+
+        // Only whole characters were copied in.
+        Ok(EfiPath::new(unsafe { str::from_utf8_unchecked(&buf[..len]) })?)
+    }
+
+    /// The directory the image file is in, such as `\EFI\myapp`, for finding files installed
+    /// next to it on `volume`.
+    pub fn app_dir(&self) -> Result<EfiPath, Status> {
+        Ok(self.path()?.parent().unwrap_or_else(EfiPath::root))
+    }
+
+    /// Open `name` relative to the directory the image file is in, with the `EFI_FILE_MODE_*`
+    /// flags in `open_mode`. The file must be closed with `FileProtocol::close`.
+    ///
+    /// ```rust,ignore
+    /// let config = get_current_image().open_sibling("myapp.conf", EFI_FILE_MODE_READ)?;
+    /// ```
+    pub fn open_sibling<P: AsRef<str>>(&self, name: P, open_mode: u64) -> Result<&'static FileProtocol, Status> {
+        let path = self.app_dir()?.join(name.as_ref())?;
+        let root = self.volume()?.open_volume()?;
+        let file = root.open_path(path, open_mode);
+        root.close();
+        file
+    }
+}
+
 pub fn set_current_image(handle: Handle) -> Result<&'static LoadedImageProtocol, Status> {
     let st = ::get_system_table();
 
@@ -50,6 +190,7 @@ pub fn set_current_image(handle: Handle) -> Result<&'static LoadedImageProtocol,
     if let Ok(image) = loaded_image_proto {
         unsafe {
             THIS_LOADED_IMAGE = image;
+            THIS_IMAGE_HANDLE = handle;
         }
     }
 
@@ -62,3 +203,46 @@ pub fn get_current_image() -> &'static LoadedImageProtocol {
     }
 }
 
+
+/// Get the handle passed to `set_current_image`.
+pub fn get_current_image_handle() -> Handle {
+    unsafe {
+        THIS_IMAGE_HANDLE
+    }
+}
+
+#[test]
+fn loaded_image_path() {
+    use core::ptr;
+    use util::wire;
+
+    // `\EFI\myapp` and `myapp.efi` in two file path nodes, then the end node.
+    let mut path = [0u8; 64];
+    let first = wire::write_ucs2(&mut path, 4, "\\EFI\\myapp".encode_utf16()).unwrap() + 4;
+    let second = wire::write_ucs2(&mut path, first + 4, "myapp.efi".encode_utf16()).unwrap() + 4;
+    for &(offset, len) in &[(0, first), (first, second)] {
+        path[offset] = DevicePathTypes::Media as u8;
+        path[offset + 1] = MediaSubTypes::FilePath as u8;
+        wire::write_u16(&mut path, offset + 2, len as u16).unwrap();
+    }
+    let end = first + second;
+    path[end..end + 4].copy_from_slice(&[0x7F, 0xFF, 4, 0]);
+
+    let image = LoadedImageProtocol {
+        revision: 0x1000,
+        parent_handle: Handle::NULL,
+        system_table: ptr::null(),
+        device_handle: Handle::NULL,
+        file_path: path.as_ptr() as *const DevicePathProtocol,
+        __reserved: ptr::null(),
+        load_options_size: 0,
+        load_options: ptr::null(),
+        image_base: 0,
+        image_size: 0,
+        image_code_type: MemoryType::LoaderCode,
+        image_data_type: MemoryType::LoaderData,
+        unload: ptr::null(),
+    };
+    assert_eq!(image.path().unwrap(), "\\EFI\\myapp\\myapp.efi");
+    assert_eq!(image.app_dir().unwrap(), "\\EFI\\myapp");
+}
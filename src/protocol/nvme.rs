@@ -0,0 +1,220 @@
+use core::ptr;
+
+use base::{Event, Status};
+use guid::Guid;
+use protocol::Protocol;
+use void::{CVoid, NotYetDef};
+
+/// GUID for the NVM Express pass-through protocol
+pub static EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID: Guid = Guid(0x52C78312, 0x8EDC, 0x4233, [0x98, 0xF2, 0x1A, 0x1A, 0xA5, 0xE3, 0x88, 0xA5]);
+
+/// Namespace ID addressing all namespaces, or none for commands that take no namespace.
+pub const NVME_BROADCAST_NSID: u32 = 0xFFFF_FFFF;
+
+/// Type for EFI_NVM_EXPRESS_PASS_THRU_MODE.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct NvmePassThruMode {
+    pub attributes: u32,
+    pub io_align: u32,
+    pub nvme_version: u32,
+}
+
+/// Which queue a command is submitted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum NvmeQueue {
+    Admin = 0,
+    Io = 1,
+}
+
+/// Type for EFI_NVM_EXPRESS_COMMAND. Build one with `NvmeCommand::new` and the `cdw*` setters,
+/// which also mark the dwords as valid.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct NvmeCommand {
+    cdw0: u32,
+    flags: u8,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+}
+
+impl NvmeCommand {
+    pub fn new(opcode: u8, nsid: u32) -> NvmeCommand {
+        NvmeCommand {
+            cdw0: opcode as u32,
+            flags: 0,
+            nsid,
+            cdw2: 0,
+            cdw3: 0,
+            cdw10: 0,
+            cdw11: 0,
+            cdw12: 0,
+            cdw13: 0,
+            cdw14: 0,
+            cdw15: 0,
+        }
+    }
+
+    pub fn opcode(&self) -> u8 {
+        self.cdw0 as u8
+    }
+
+    pub fn cdw10(mut self, v: u32) -> NvmeCommand {
+        self.cdw10 = v;
+        self.flags |= 0x04;
+        self
+    }
+
+    pub fn cdw11(mut self, v: u32) -> NvmeCommand {
+        self.cdw11 = v;
+        self.flags |= 0x08;
+        self
+    }
+
+    pub fn cdw12(mut self, v: u32) -> NvmeCommand {
+        self.cdw12 = v;
+        self.flags |= 0x10;
+        self
+    }
+
+    pub fn cdw13(mut self, v: u32) -> NvmeCommand {
+        self.cdw13 = v;
+        self.flags |= 0x20;
+        self
+    }
+
+    pub fn cdw14(mut self, v: u32) -> NvmeCommand {
+        self.cdw14 = v;
+        self.flags |= 0x40;
+        self
+    }
+
+    pub fn cdw15(mut self, v: u32) -> NvmeCommand {
+        self.cdw15 = v;
+        self.flags |= 0x80;
+        self
+    }
+}
+
+/// Type for EFI_NVM_EXPRESS_COMPLETION, the completion queue entry of a command.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct NvmeCompletion {
+    pub dw0: u32,
+    pub dw1: u32,
+    pub dw2: u32,
+    pub dw3: u32,
+}
+
+impl NvmeCompletion {
+    /// The status field: status code type in bits 10:8 and status code in bits 7:0.
+    pub fn status(&self) -> u16 {
+        ((self.dw3 >> 17) & 0x7FF) as u16
+    }
+}
+
+#[repr(C)]
+struct NvmeCommandPacket {
+    command_timeout: u64,
+    transfer_buffer: *mut CVoid,
+    transfer_length: u32,
+    metadata_buffer: *mut CVoid,
+    metadata_length: u32,
+    queue_type: NvmeQueue,
+    command: *const NvmeCommand,
+    completion: *mut NvmeCompletion,
+}
+
+/// EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL, for sending raw NVMe commands to a controller.
+#[repr(C)]
+pub struct NvmePassThruProtocol {
+    mode: *const NvmePassThruMode,
+    pass_thru: unsafe extern "win64" fn(this: *const NvmePassThruProtocol, namespace_id: u32, packet: *mut NvmeCommandPacket, event: Event) -> Status,
+    get_next_namespace: unsafe extern "win64" fn(this: *const NvmePassThruProtocol, namespace_id: *mut u32) -> Status,
+    build_device_path: *const NotYetDef,
+    get_namespace: *const NotYetDef,
+}
+
+impl Protocol for NvmePassThruProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_NVM_EXPRESS_PASS_THRU_PROTOCOL_GUID
+    }
+}
+
+impl NvmePassThruProtocol {
+    pub fn mode(&self) -> &NvmePassThruMode {
+        unsafe { &*self.mode }
+    }
+
+    /// The IDs of the namespaces of the controller.
+    pub fn namespaces(&self) -> NvmeNamespaces<'_> {
+        NvmeNamespaces { protocol: self, nsid: NVME_BROADCAST_NSID, done: false }
+    }
+
+    /// Submit `command` to `queue` and wait up to `timeout` (in 100ns units, or 0 to wait
+    /// forever) for it to complete. `buffer` is the data buffer for commands that transfer data;
+    /// its direction is implied by the command. Returns the completion queue entry.
+    pub fn pass_thru(&self, queue: NvmeQueue, command: &NvmeCommand, buffer: Option<&mut [u8]>, timeout: u64)
+                     -> Result<NvmeCompletion, Status> {
+        let (transfer_buffer, transfer_length) = match buffer {
+            Some(buf) => {
+                let align = self.mode().io_align as usize;
+                if align > 1 && buf.as_ptr() as usize % align != 0 {
+                    return Err(Status::InvalidParameter);
+                }
+                (buf.as_mut_ptr() as *mut CVoid, buf.len() as u32)
+            }
+            None => (ptr::null_mut(), 0),
+        };
+
+        let mut completion = NvmeCompletion::default();
+        let mut packet = NvmeCommandPacket {
+            command_timeout: timeout,
+            transfer_buffer,
+            transfer_length,
+            metadata_buffer: ptr::null_mut(),
+            metadata_length: 0,
+            queue_type: queue,
+            command,
+            completion: &mut completion,
+        };
+
+        let status = unsafe { (self.pass_thru)(self, command.nsid, &mut packet, Event(ptr::null_mut())) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(completion)
+    }
+}
+
+/// Iterator returned by `NvmePassThruProtocol::namespaces`.
+pub struct NvmeNamespaces<'a> {
+    protocol: &'a NvmePassThruProtocol,
+    nsid: u32,
+    done: bool,
+}
+
+impl<'a> Iterator for NvmeNamespaces<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.done {
+            return None;
+        }
+
+        // Starting from the broadcast ID returns the first namespace; NotFound marks the end.
+        if unsafe { (self.protocol.get_next_namespace)(self.protocol, &mut self.nsid) } != Status::Success {
+            self.done = true;
+            return None;
+        }
+        Some(self.nsid)
+    }
+}
@@ -0,0 +1,113 @@
+use guid::Guid;
+use protocol::Protocol;
+use util::wire;
+
+/// GUID for the partition info protocol
+pub static EFI_PARTITION_INFO_PROTOCOL_GUID: Guid = Guid(0x8CF2F62C, 0xBC9B, 0x4821, [0x80, 0x8D, 0xEC, 0x9E, 0xC4, 0x21, 0xA1, 0xA0]);
+
+/// GPT partition type of an EFI System Partition.
+pub static EFI_SYSTEM_PARTITION_GUID: Guid = Guid(0xC12A7328, 0xF81F, 0x11D2, [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B]);
+
+/// MBR partition type of an EFI System Partition.
+pub const MBR_TYPE_EFI_SYSTEM_PARTITION: u8 = 0xEF;
+
+/// Size of the partition record, the MBR entry or GPT entry, after the header of an
+/// EFI_PARTITION_INFO_PROTOCOL.
+const PARTITION_RECORD_SIZE: usize = 128;
+
+/// The partitioning scheme of a partition with `PartitionInfoProtocol`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionType {
+    Other,
+    Mbr,
+    Gpt,
+}
+
+/// Type for EFI_PARTITION_INFO_PROTOCOL, installed by the partition driver of UEFI 2.7 and
+/// later on the handle of each partition, with the partition's entry in the partition table.
+#[repr(C)]
+pub struct PartitionInfoProtocol {
+    revision: u32,
+    partition_type: u32,
+    system: u8,
+    reserved: [u8; 7],
+    record: [u8; PARTITION_RECORD_SIZE],
+}
+
+impl Protocol for PartitionInfoProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_PARTITION_INFO_PROTOCOL_GUID
+    }
+}
+
+impl PartitionInfoProtocol {
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    pub fn partition_type(&self) -> PartitionType {
+        match self.partition_type {
+            1 => PartitionType::Mbr,
+            2 => PartitionType::Gpt,
+            _ => PartitionType::Other,
+        }
+    }
+
+    /// Whether the partition driver took the partition to be an EFI System Partition.
+    pub fn is_system(&self) -> bool {
+        self.system == 1
+    }
+
+    /// The partition type GUID of a GPT partition.
+    pub fn gpt_partition_type(&self) -> Option<Guid> {
+        self.gpt_guid(0)
+    }
+
+    /// The unique GUID of a GPT partition, which HD nodes of device paths refer to it by.
+    pub fn unique_partition_guid(&self) -> Option<Guid> {
+        self.gpt_guid(16)
+    }
+
+    /// The OS type byte of an MBR partition.
+    pub fn mbr_os_type(&self) -> Option<u8> {
+        match self.partition_type() {
+            PartitionType::Mbr => wire::read_u8(&self.record, 4).ok(),
+            _ => None,
+        }
+    }
+
+    /// Whether this is an EFI System Partition, by its flag or its type.
+    pub fn is_esp(&self) -> bool {
+        self.is_system()
+            || self.gpt_partition_type() == Some(EFI_SYSTEM_PARTITION_GUID)
+            || self.mbr_os_type() == Some(MBR_TYPE_EFI_SYSTEM_PARTITION)
+    }
+
+    fn gpt_guid(&self, offset: usize) -> Option<Guid> {
+        match self.partition_type() {
+            PartitionType::Gpt => wire::read_guid(&self.record, offset).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn esp_partition_types() {
+    let mut esp = PartitionInfoProtocol {
+        revision: 0x1000,
+        partition_type: 2,
+        system: 0,
+        reserved: [0; 7],
+        record: [0; PARTITION_RECORD_SIZE],
+    };
+    wire::write_guid(&mut esp.record, 0, &EFI_SYSTEM_PARTITION_GUID).unwrap();
+    assert!(esp.is_esp());
+    assert_eq!(esp.mbr_os_type(), None);
+
+    let mut mbr = PartitionInfoProtocol { partition_type: 1, record: [0; PARTITION_RECORD_SIZE], ..esp };
+    mbr.record[4] = MBR_TYPE_EFI_SYSTEM_PARTITION;
+    assert!(mbr.is_esp());
+    mbr.record[4] = 0x83;
+    assert!(!mbr.is_esp());
+    assert_eq!(mbr.gpt_partition_type(), None);
+}
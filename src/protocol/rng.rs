@@ -0,0 +1,56 @@
+use core::{mem, ptr};
+
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+
+/// GUID for the random number generator protocol
+pub static EFI_RNG_PROTOCOL_GUID: Guid = Guid(0x3152BCA5, 0xEADE, 0x433D, [0x86, 0x2E, 0xC0, 0x1C, 0xDC, 0x29, 0x1F, 0x44]);
+
+/// Algorithms of `RngProtocol::get_rng_with`: raw entropy, and the NIST SP 800-90 CTR DRBG
+/// with AES-256.
+pub static EFI_RNG_ALGORITHM_RAW: Guid = Guid(0xE43176D7, 0xB6E8, 0x4827, [0xB7, 0x84, 0x7F, 0xFD, 0xC4, 0xB6, 0x85, 0x61]);
+pub static EFI_RNG_ALGORITHM_SP800_90_CTR_256_GUID: Guid = Guid(0x44F0DE6E, 0x4D8C, 0x4045, [0xA8, 0xC7, 0x4D, 0xD1, 0x68, 0x85, 0x6B, 0x9E]);
+
+/// EFI_RNG_PROTOCOL, the firmware's random number generator. Use `fill_random` rather than
+/// this directly to fall back to a software generator where firmware has none.
+#[repr(C)]
+pub struct RngProtocol {
+    get_info: unsafe extern "win64" fn(this: *const RngProtocol, algorithm_list_size: *mut usize, algorithm_list: *mut Guid) -> Status,
+    get_rng: unsafe extern "win64" fn(this: *const RngProtocol, algorithm: *const Guid, value_length: usize, value: *mut u8) -> Status,
+}
+
+impl Protocol for RngProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_RNG_PROTOCOL_GUID
+    }
+}
+
+impl RngProtocol {
+    /// Copy the algorithms the generator supports into `algorithms`, returning how many it
+    /// supports. Fails with `Status::BufferTooSmall` if they don't all fit.
+    pub fn algorithms(&self, algorithms: &mut [Guid]) -> Result<usize, Status> {
+        let mut size = mem::size_of_val(algorithms);
+        match unsafe { (self.get_info)(self, &mut size, algorithms.as_mut_ptr()) } {
+            Status::Success => Ok(size / mem::size_of::<Guid>()),
+            e => Err(e),
+        }
+    }
+
+    /// Fill `buf` with random bytes from the generator's default algorithm.
+    pub fn get_rng(&self, buf: &mut [u8]) -> Result<(), Status> {
+        match unsafe { (self.get_rng)(self, ptr::null(), buf.len(), buf.as_mut_ptr()) } {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// Fill `buf` with random bytes from `algorithm`, failing with `Status::Unsupported` if the
+    /// generator does not implement it.
+    pub fn get_rng_with(&self, algorithm: &Guid, buf: &mut [u8]) -> Result<(), Status> {
+        match unsafe { (self.get_rng)(self, algorithm, buf.len(), buf.as_mut_ptr()) } {
+            Status::Success => Ok(()),
+            e => Err(e),
+        }
+    }
+}
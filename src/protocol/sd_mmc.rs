@@ -0,0 +1,227 @@
+use core::ptr;
+
+use base::{Event, Status};
+use guid::Guid;
+use protocol::Protocol;
+use void::{CVoid, NotYetDef};
+
+/// GUID for the SD/MMC pass-through protocol
+pub static EFI_SD_MMC_PASS_THRU_PROTOCOL_GUID: Guid = Guid(0x716EF0D9, 0xFF83, 0x4F69, [0x81, 0xE9, 0x51, 0x8B, 0xD3, 0x9A, 0x8E, 0x70]);
+
+/// Type for EFI_SD_MMC_COMMAND_TYPE, the kind of bus transaction a command is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SdMmcCommandType {
+    /// Broadcast, no response.
+    Bc = 0,
+    /// Broadcast with response.
+    Bcr = 1,
+    /// Addressed, no data transfer.
+    Ac = 2,
+    /// Addressed, with data transfer.
+    Adtc = 3,
+}
+
+/// Type for EFI_SD_MMC_RESPONSE_TYPE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SdMmcResponseType {
+    R1 = 0,
+    R1b = 1,
+    R2 = 2,
+    R3 = 3,
+    R4 = 4,
+    R5 = 5,
+    R5b = 6,
+    R6 = 7,
+    R7 = 8,
+}
+
+/// Type for EFI_SD_MMC_COMMAND_BLOCK.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct SdMmcCommand {
+    pub index: u16,
+    pub argument: u32,
+    pub command_type: SdMmcCommandType,
+    pub response_type: SdMmcResponseType,
+}
+
+impl SdMmcCommand {
+    pub fn new(index: u16, argument: u32, command_type: SdMmcCommandType, response_type: SdMmcResponseType) -> SdMmcCommand {
+        SdMmcCommand { index, argument, command_type, response_type }
+    }
+}
+
+/// Data phase of a command.
+pub enum SdMmcData<'a> {
+    None,
+    /// Read from the device into the buffer.
+    In(&'a mut [u8]),
+    /// Write the buffer to the device.
+    Out(&'a [u8]),
+}
+
+#[repr(C)]
+struct SdMmcCommandPacket {
+    command: *const SdMmcCommand,
+    status: *mut [u32; 4],
+    timeout: u64,
+    in_data_buffer: *mut CVoid,
+    out_data_buffer: *const CVoid,
+    in_transfer_length: u32,
+    out_transfer_length: u32,
+    transaction_status: Status,
+}
+
+/// eMMC hardware partitions, selected with `SdMmcPassThruProtocol::switch_partition`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmmcPartition {
+    User = 0,
+    Boot0 = 1,
+    Boot1 = 2,
+    Rpmb = 3,
+    Gp1 = 4,
+    Gp2 = 5,
+    Gp3 = 6,
+    Gp4 = 7,
+}
+
+/// Size of the eMMC EXT_CSD register.
+pub const EMMC_EXT_CSD_SIZE: usize = 512;
+
+/// Index of the PARTITION_CONFIG byte in EXT_CSD.
+const EXT_CSD_PARTITION_CONFIG: usize = 179;
+
+/// Timeout for commands, in units of 100ns.
+const SD_MMC_TIMEOUT: u64 = 10_000_000;
+
+/// EFI_SD_MMC_PASS_THRU_PROTOCOL, for sending raw commands to SD cards and eMMC devices, e.g.
+/// to read their registers or switch eMMC partitions.
+#[repr(C)]
+pub struct SdMmcPassThruProtocol {
+    io_align: usize,
+    pass_thru: unsafe extern "win64" fn(this: *const SdMmcPassThruProtocol, slot: u8, packet: *mut SdMmcCommandPacket, event: Event) -> Status,
+    get_next_slot: unsafe extern "win64" fn(this: *const SdMmcPassThruProtocol, slot: *mut u8) -> Status,
+    build_device_path: *const NotYetDef,
+    get_slot_number: *const NotYetDef,
+    reset_device: unsafe extern "win64" fn(this: *const SdMmcPassThruProtocol, slot: u8) -> Status,
+}
+
+impl Protocol for SdMmcPassThruProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_SD_MMC_PASS_THRU_PROTOCOL_GUID
+    }
+}
+
+impl SdMmcPassThruProtocol {
+    /// Required alignment of data buffers, in bytes; 0 or 1 if there is none.
+    pub fn io_align(&self) -> usize {
+        self.io_align
+    }
+
+    /// The slots with a device present.
+    pub fn slots(&self) -> SdMmcSlots<'_> {
+        SdMmcSlots { protocol: self, slot: 0xFF, done: false }
+    }
+
+    pub fn reset_device(&self, slot: u8) -> Status {
+        unsafe { (self.reset_device)(self, slot) }
+    }
+
+    /// Send `command` to the device in `slot`, with data phase `data`, and return the response
+    /// words. For R2 responses all four words are used, least significant first.
+    pub fn send_command(&self, slot: u8, command: &SdMmcCommand, data: SdMmcData) -> Result<[u32; 4], Status> {
+        let mut response = [0u32; 4];
+        let mut packet = SdMmcCommandPacket {
+            command,
+            status: &mut response,
+            timeout: SD_MMC_TIMEOUT,
+            in_data_buffer: ptr::null_mut(),
+            out_data_buffer: ptr::null(),
+            in_transfer_length: 0,
+            out_transfer_length: 0,
+            transaction_status: Status::Success,
+        };
+        match data {
+            SdMmcData::None => {}
+            SdMmcData::In(buf) => {
+                packet.in_data_buffer = buf.as_mut_ptr() as *mut CVoid;
+                packet.in_transfer_length = buf.len() as u32;
+            }
+            SdMmcData::Out(buf) => {
+                packet.out_data_buffer = buf.as_ptr() as *const CVoid;
+                packet.out_transfer_length = buf.len() as u32;
+            }
+        }
+
+        let status = unsafe { (self.pass_thru)(self, slot, &mut packet, Event(ptr::null_mut())) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        if packet.transaction_status != Status::Success {
+            return Err(packet.transaction_status);
+        }
+        Ok(response)
+    }
+
+    /// Read the 128-bit CID register of the card with relative address `rca` (SEND_CID, CMD10).
+    /// The card must be in standby state, i.e. deselected with CMD7.
+    pub fn read_cid(&self, slot: u8, rca: u16) -> Result<[u32; 4], Status> {
+        let command = SdMmcCommand::new(10, (rca as u32) << 16, SdMmcCommandType::Ac, SdMmcResponseType::R2);
+        self.send_command(slot, &command, SdMmcData::None)
+    }
+
+    /// Read the 128-bit CSD register of the card with relative address `rca` (SEND_CSD, CMD9).
+    /// The card must be in standby state, as for `read_cid`.
+    pub fn read_csd(&self, slot: u8, rca: u16) -> Result<[u32; 4], Status> {
+        let command = SdMmcCommand::new(9, (rca as u32) << 16, SdMmcCommandType::Ac, SdMmcResponseType::R2);
+        self.send_command(slot, &command, SdMmcData::None)
+    }
+
+    /// Read the EXT_CSD register of the eMMC device in `slot` (SEND_EXT_CSD, CMD8). The device
+    /// must be in transfer state, as it is while the firmware is using it.
+    pub fn read_ext_csd(&self, slot: u8) -> Result<[u8; EMMC_EXT_CSD_SIZE], Status> {
+        let mut ext_csd = [0u8; EMMC_EXT_CSD_SIZE];
+        let command = SdMmcCommand::new(8, 0, SdMmcCommandType::Adtc, SdMmcResponseType::R1);
+        self.send_command(slot, &command, SdMmcData::In(&mut ext_csd))?;
+        Ok(ext_csd)
+    }
+
+    /// Select which eMMC hardware partition reads and writes go to (SWITCH, CMD6), keeping the
+    /// boot partition configuration. The firmware's block I/O on the device will then see the
+    /// selected partition until it is switched back.
+    pub fn switch_partition(&self, slot: u8, partition: EmmcPartition) -> Result<(), Status> {
+        let ext_csd = self.read_ext_csd(slot)?;
+        let config = (ext_csd[EXT_CSD_PARTITION_CONFIG] & !0x07) | partition as u8;
+
+        // Access mode 3 writes the byte at the index.
+        let argument = (3 << 24) | ((EXT_CSD_PARTITION_CONFIG as u32) << 16) | ((config as u32) << 8);
+        let command = SdMmcCommand::new(6, argument, SdMmcCommandType::Ac, SdMmcResponseType::R1b);
+        self.send_command(slot, &command, SdMmcData::None).map(|_| ())
+    }
+}
+
+/// Iterator returned by `SdMmcPassThruProtocol::slots`.
+pub struct SdMmcSlots<'a> {
+    protocol: &'a SdMmcPassThruProtocol,
+    slot: u8,
+    done: bool,
+}
+
+impl<'a> Iterator for SdMmcSlots<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.done {
+            return None;
+        }
+
+        // Starting from 0xFF returns the first slot; NotFound marks the end.
+        if unsafe { (self.protocol.get_next_slot)(self.protocol, &mut self.slot) } != Status::Success {
+            self.done = true;
+            return None;
+        }
+        Some(self.slot)
+    }
+}
@@ -12,12 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "async")]
+use core::future::Future;
+#[cfg(feature = "async")]
+use core::marker::PhantomPinned;
+#[cfg(feature = "async")]
+use core::pin::Pin;
 use core::slice;
 use core::str;
+#[cfg(feature = "async")]
+use core::task::{Context, Poll, Waker};
 
-use base::Status;
+use base::{Event, Status};
+use devicelock::lock_device;
+use event::TimerDelay;
 use guid::Guid;
 use protocol::Protocol;
+#[cfg(feature = "async")]
+use task::{TplCell, TPL};
 use void::CVoid;
 
 #[repr(C)]
@@ -198,6 +210,35 @@ impl RawSerialIOProtocol {
         }
     }
 
+    /// Read whatever input is waiting, up to `buf.len()` bytes, without waiting for more.
+    /// Returns 0 if there is none.
+    ///
+    /// Bytes are read one at a time, checking the INPUT_BUFFER_EMPTY control bit before each,
+    /// so this never blocks for the device timeout. Devices which do not report the bit are
+    /// read one byte per call, which blocks for the timeout when there is no input.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, Status> {
+        let reports_empty = unsafe { (*self.mode).control_mask } & INPUT_BUFFER_EMPTY.bits() != 0;
+        let mut len = 0;
+        while len < buf.len() {
+            if reports_empty && self.get_control_bits()?.contains(INPUT_BUFFER_EMPTY) {
+                break;
+            }
+
+            let mut length = 1;
+            match unsafe { (self.read)(self, &mut length, buf[len..].as_mut_ptr() as *mut CVoid) } {
+                Status::Success | Status::Timeout => {}
+                // Keep what was read; the error shows up again on the next call.
+                _ if len > 0 => break,
+                e => return Err(e),
+            }
+            len += length;
+            if length == 0 || !reports_empty {
+                break;
+            }
+        }
+        Ok(len)
+    }
+
     /// Read `length` bytes from the serial device.
     /// Note: The returned pointer is allocated with `allocate_pool`, and it is the caller's
     /// responsibility to free at some point.
@@ -220,6 +261,10 @@ impl RawSerialIOProtocol {
     }
 }
 
+/// A serial device with cached attributes, which are applied before each read or write. Each
+/// operation locks the device with `lock_device`, so using it from an event callback while
+/// another part of the application is in the middle of a read or write fails with
+/// `Status::AccessDenied` rather than interleaving with it.
 pub struct SerialIOProtocol {
     raw_protocol: &'static RawSerialIOProtocol,
     baud_rate: Option<u64>,
@@ -285,6 +330,7 @@ impl SerialIOProtocol {
         self.data_bits = data_bits.or(self.data_bits);
         self.stop_bits = stop_bits.or(self.stop_bits);
 
+        let _guard = lock_device(self.raw_protocol)?;
         self.set_attributes()
     }
 
@@ -293,19 +339,155 @@ impl SerialIOProtocol {
         // GRUB sets the attributes on the serial device on each read or write, so we will too.
         // This ensures that the cached attributes present in this wrapper structure will be
         // reflected by the serial device.
+        let _guard = lock_device(self.raw_protocol)?;
         self.set_attributes().and_then(|_| {
             self.raw_protocol.write(data)
         })
     }
 
+    /// Read whatever input is waiting into `buf` without blocking, returning the number of bytes
+    /// read, which is 0 if there is none. The INPUT_BUFFER_EMPTY control bit is checked before
+    /// each byte, so this does not wait for the device timeout when the input runs out.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, Status> {
+        let _guard = lock_device(self.raw_protocol)?;
+        self.raw_protocol.try_read(buf)
+    }
+
+    /// A periodic timer to poll the device with `try_read`. Wait on its event together with
+    /// others, such as the keyboard's, so an interactive session services both promptly instead
+    /// of blocking in serial reads.
+    ///
+    /// ```rust,ignore
+    /// let poll = serial.poll_timer(100_000)?;
+    /// loop {
+    ///     match bs.wait_for_event(&[poll.event(), console.wait_for_key()])? {
+    ///         0 => {
+    ///             let n = serial.try_read(&mut buf)?;
+    ///             handle(&buf[..n]);
+    ///         }
+    ///         _ => handle_key(console.read_key()?),
+    ///     }
+    /// }
+    /// ```
+    pub fn poll_timer(&self, period: u64) -> Result<SerialPollTimer, Status> {
+        SerialPollTimer::new(period)
+    }
+
+    /// Read into `buf` once input arrives, as a `Future` for an async executor. The device is
+    /// polled with `try_read` every `period`, in 100ns units, until there is some.
+    #[cfg(feature = "async")]
+    pub fn read_async<'a>(&'a self, buf: &'a mut [u8], period: u64) -> SerialRead<'a> {
+        SerialRead {
+            serial: self,
+            buf,
+            period,
+            timer: None,
+            waker: TplCell::new(None),
+            _pinned: PhantomPinned,
+        }
+    }
+
     /// Read a slice of bytes from the serial device. The resulting slice is allocated with
     /// `allocate_pool` and is the caller's responsibility to free.
     pub fn read_bytes(&self, length: usize) -> Result<Option<&[u8]>, Status> {
         // GRUB sets the attributes on the serial device on each read or write, so we will too.
         // This ensures that the cached attributes present in this wrapper structure will be
         // reflected by the serial device.
+        let _guard = lock_device(self.raw_protocol)?;
         self.set_attributes().and_then(|_| {
             self.raw_protocol.read_bytes(length)
         })
     }
 }
+
+/// A periodic timer event for polling a serial device, closed when dropped. Returned by
+/// `SerialIOProtocol::poll_timer`.
+pub struct SerialPollTimer {
+    event: Event,
+}
+
+impl SerialPollTimer {
+    /// Create a timer that signals every `period`, in 100ns units.
+    pub fn new(period: u64) -> Result<SerialPollTimer, Status> {
+        let bs = ::get_system_table().boot_services();
+        let event = bs.create_timer_event()?;
+        let status = bs.set_timer(event, TimerDelay::Periodic, period);
+        if status != Status::Success {
+            bs.close_event(event);
+            return Err(status);
+        }
+        Ok(SerialPollTimer { event })
+    }
+
+    /// The event to pass to `BootServices::wait_for_event`.
+    pub fn event(&self) -> Event {
+        self.event
+    }
+}
+
+impl Drop for SerialPollTimer {
+    fn drop(&mut self) {
+        ::get_system_table().boot_services().close_event(self.event);
+    }
+}
+
+/// A read completing once input arrives, returned by `SerialIOProtocol::read_async`. While
+/// there is none, a timer wakes the task to poll again after the poll period.
+#[cfg(feature = "async")]
+pub struct SerialRead<'a> {
+    serial: &'a SerialIOProtocol,
+    buf: &'a mut [u8],
+    period: u64,
+    timer: Option<Event>,
+    /// Taken and woken by the timer's notify function, which holds its address.
+    waker: TplCell<Option<Waker>>,
+    _pinned: PhantomPinned,
+}
+
+#[cfg(feature = "async")]
+impl<'a> Future for SerialRead<'a> {
+    type Output = Result<usize, Status>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<usize, Status>> {
+        // Nothing is moved out; pinning keeps `waker` where the timer expects it.
+        let this = unsafe { self.get_unchecked_mut() };
+        match this.serial.try_read(this.buf) {
+            Ok(0) if !this.buf.is_empty() => {}
+            result => return Poll::Ready(result),
+        }
+
+        let bs = ::get_system_table().boot_services();
+        let event = match this.timer {
+            Some(event) => event,
+            None => {
+                let context = &this.waker as *const TplCell<Option<Waker>> as *const CVoid;
+                match bs.create_timer_event_with_notify(TPL::Callback, wake_serial_read, context) {
+                    Ok(event) => *this.timer.insert(event),
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            }
+        };
+        this.waker.with(|waker| *waker = Some(cx.waker().clone()));
+        match bs.set_timer(event, TimerDelay::Relative, this.period) {
+            Status::Success => Poll::Pending,
+            e => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> Drop for SerialRead<'a> {
+    fn drop(&mut self) {
+        if let Some(event) = self.timer {
+            ::get_system_table().boot_services().close_event(event);
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+extern "win64" fn wake_serial_read(_event: Event, context: *const CVoid) {
+    let waker = unsafe { &*(context as *const TplCell<Option<Waker>>) };
+    if let Some(waker) = waker.with(Option::take) {
+        waker.wake();
+    }
+}
@@ -0,0 +1,50 @@
+use core::slice;
+
+use guid::Guid;
+use protocol::{FileProtocol, Protocol};
+
+/// GUID for the shell parameters protocol, installed by the UEFI shell on the image handle of
+/// applications it starts.
+pub static EFI_SHELL_PARAMETERS_PROTOCOL_GUID: Guid = Guid(0x752F3136, 0x4E16, 0x4FDC, [0xA2, 0x2A, 0xE5, 0xF4, 0x68, 0x12, 0xF4, 0xCA]);
+
+#[repr(C)]
+pub struct ShellParametersProtocol {
+    argv: *const *const u16,
+    argc: usize,
+    std_in: *const FileProtocol,
+    std_out: *const FileProtocol,
+    std_err: *const FileProtocol,
+}
+
+impl Protocol for ShellParametersProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_SHELL_PARAMETERS_PROTOCOL_GUID
+    }
+}
+
+impl ShellParametersProtocol {
+    /// The command line arguments, as null-terminated UCS-2 strings. The first argument is the
+    /// application name.
+    pub fn argv(&self) -> &[*const u16] {
+        if self.argv.is_null() {
+            return &[];
+        }
+
+        unsafe { slice::from_raw_parts(self.argv, self.argc) }
+    }
+
+    /// Standard input, which may be the console or a redirected file.
+    pub fn stdin(&self) -> Option<&FileProtocol> {
+        unsafe { self.std_in.as_ref() }
+    }
+
+    /// Standard output, which may be the console or a redirected file.
+    pub fn stdout(&self) -> Option<&FileProtocol> {
+        unsafe { self.std_out.as_ref() }
+    }
+
+    /// Standard error, which may be the console or a redirected file.
+    pub fn stderr(&self) -> Option<&FileProtocol> {
+        unsafe { self.std_err.as_ref() }
+    }
+}
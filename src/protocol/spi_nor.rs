@@ -0,0 +1,115 @@
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+use void::NotYetDef;
+
+/// GUID for the PI SPI NOR flash protocol
+pub static EFI_SPI_NOR_FLASH_PROTOCOL_GUID: Guid = Guid(0xB57EC3FE, 0xF833, 0x4BA6, [0x85, 0x78, 0x2A, 0x7D, 0x6A, 0x87, 0x44, 0x4B]);
+
+/// EFI_SPI_NOR_FLASH_PROTOCOL, which gives access to a SPI NOR flash part such as the one
+/// holding the platform firmware. Only reads are available unless the `flash-write` feature is
+/// enabled.
+#[repr(C)]
+pub struct SpiNorFlashProtocol {
+    spi_peripheral: *const NotYetDef,
+    flash_size: u32,
+    device_id: [u8; 3],
+    erase_block_bytes: u32,
+    get_flash_id: unsafe extern "win64" fn(this: *const SpiNorFlashProtocol, buffer: *mut u8) -> Status,
+    read_data: unsafe extern "win64" fn(this: *const SpiNorFlashProtocol, flash_address: u32, length: u32, buffer: *mut u8) -> Status,
+    lf_read_data: unsafe extern "win64" fn(this: *const SpiNorFlashProtocol, flash_address: u32, length: u32, buffer: *mut u8) -> Status,
+    read_status: unsafe extern "win64" fn(this: *const SpiNorFlashProtocol, length: u32, flash_status: *mut u8) -> Status,
+    write_status: unsafe extern "win64" fn(this: *const SpiNorFlashProtocol, length: u32, flash_status: *mut u8) -> Status,
+    write_data: unsafe extern "win64" fn(this: *const SpiNorFlashProtocol, flash_address: u32, length: u32, buffer: *const u8) -> Status,
+    erase: unsafe extern "win64" fn(this: *const SpiNorFlashProtocol, flash_address: u32, block_count: u32) -> Status,
+}
+
+impl Protocol for SpiNorFlashProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_SPI_NOR_FLASH_PROTOCOL_GUID
+    }
+}
+
+impl SpiNorFlashProtocol {
+    /// Size of the flash part in bytes.
+    pub fn flash_size(&self) -> u32 {
+        self.flash_size
+    }
+
+    /// The JEDEC manufacturer and device ID the flash part was identified with.
+    pub fn device_id(&self) -> [u8; 3] {
+        self.device_id
+    }
+
+    /// Size of an erase block in bytes.
+    pub fn erase_block_bytes(&self) -> u32 {
+        self.erase_block_bytes
+    }
+
+    /// Read the JEDEC ID from the flash part itself.
+    pub fn flash_id(&self) -> Result<[u8; 3], Status> {
+        let mut id = [0; 3];
+        let status = unsafe { (self.get_flash_id)(self, id.as_mut_ptr()) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(id)
+    }
+
+    /// Read `buf.len()` bytes from `address`.
+    pub fn read(&self, address: u32, buf: &mut [u8]) -> Result<(), Status> {
+        self.check_range(address, buf.len())?;
+        match unsafe { (self.read_data)(self, address, buf.len() as u32, buf.as_mut_ptr()) } {
+            Status::Success => Ok(()),
+            status => Err(status),
+        }
+    }
+
+    /// Read the flash status register into `buf`, which is usually 1 byte.
+    pub fn read_status(&self, buf: &mut [u8]) -> Result<(), Status> {
+        match unsafe { (self.read_status)(self, buf.len() as u32, buf.as_mut_ptr()) } {
+            Status::Success => Ok(()),
+            status => Err(status),
+        }
+    }
+
+    fn check_range(&self, address: u32, len: usize) -> Result<(), Status> {
+        match (address as u64).checked_add(len as u64) {
+            Some(end) if end <= self.flash_size as u64 => Ok(()),
+            _ => Err(Status::InvalidParameter),
+        }
+    }
+
+    /// Write `data` at `address`. The bytes written must have been erased first.
+    ///
+    /// # Safety
+    ///
+    /// This changes the contents of the flash, which may hold the firmware the machine boots
+    /// from; a bad write can leave it unbootable.
+    #[cfg(feature = "flash-write")]
+    pub unsafe fn write(&self, address: u32, data: &[u8]) -> Result<(), Status> {
+        self.check_range(address, data.len())?;
+        match (self.write_data)(self, address, data.len() as u32, data.as_ptr()) {
+            Status::Success => Ok(()),
+            status => Err(status),
+        }
+    }
+
+    /// Erase `blocks` erase blocks starting at `address`, which must be block aligned.
+    ///
+    /// # Safety
+    ///
+    /// As for `write`.
+    #[cfg(feature = "flash-write")]
+    pub unsafe fn erase(&self, address: u32, blocks: u32) -> Result<(), Status> {
+        let len = blocks as usize * self.erase_block_bytes as usize;
+        if self.erase_block_bytes == 0 || address % self.erase_block_bytes != 0 {
+            return Err(Status::InvalidParameter);
+        }
+        self.check_range(address, len)?;
+        match (self.erase)(self, address, blocks) {
+            Status::Success => Ok(()),
+            status => Err(status),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+use void::CVoid;
+
+/// GUID for the storage security command protocol
+pub static EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID: Guid = Guid(0xC88B0B6D, 0x0DFC, 0x49A7, [0x9C, 0xB4, 0x49, 0x07, 0x4B, 0x4C, 0x3A, 0x78]);
+
+/// Security protocol which lists the security protocols a device supports.
+pub const SECURITY_PROTOCOL_INFORMATION: u8 = 0x00;
+/// TCG security protocol 1, which carries Level 0 Discovery and Opal sessions.
+pub const SECURITY_PROTOCOL_TCG: u8 = 0x01;
+/// TCG security protocol 2, which carries ComID management.
+pub const SECURITY_PROTOCOL_TCG_COMID: u8 = 0x02;
+/// IEEE 1667 silo commands.
+pub const SECURITY_PROTOCOL_IEEE1667: u8 = 0xEE;
+
+/// EFI_STORAGE_SECURITY_COMMAND_PROTOCOL, for exchanging SECURITY PROTOCOL IN/OUT payloads
+/// (TCG Opal, IEEE 1667) with a drive, to unlock or manage self-encrypting drives.
+///
+/// Payloads of the SCSI-defined protocols are big-endian; the protocol passes them through
+/// unchanged.
+#[repr(C)]
+pub struct StorageSecurityCommandProtocol {
+    receive_data: unsafe extern "win64" fn(this: *const StorageSecurityCommandProtocol, media_id: u32, timeout: u64, security_protocol_id: u8, security_protocol_specific_data: u16, payload_buffer_size: usize, payload_buffer: *mut CVoid, payload_transfer_size: *mut usize) -> Status,
+    send_data: unsafe extern "win64" fn(this: *const StorageSecurityCommandProtocol, media_id: u32, timeout: u64, security_protocol_id: u8, security_protocol_specific_data: u16, payload_buffer_size: usize, payload_buffer: *const CVoid) -> Status,
+}
+
+impl Protocol for StorageSecurityCommandProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_STORAGE_SECURITY_COMMAND_PROTOCOL_GUID
+    }
+}
+
+impl StorageSecurityCommandProtocol {
+    /// Send SECURITY PROTOCOL IN to the media `media_id` (from the block I/O protocol on the
+    /// same handle) and return the number of bytes received into `buf`. `timeout` is in 100ns
+    /// units, or 0 to wait forever. `protocol_specific` is the ComID for the TCG protocols.
+    pub fn receive_data(&self, media_id: u32, timeout: u64, protocol_id: u8, protocol_specific: u16,
+                        buf: &mut [u8]) -> Result<usize, Status> {
+        let mut transferred = 0;
+        let status = unsafe {
+            (self.receive_data)(self, media_id, timeout, protocol_id, protocol_specific, buf.len(),
+                                buf.as_mut_ptr() as *mut CVoid, &mut transferred)
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(transferred)
+    }
+
+    /// Send SECURITY PROTOCOL OUT with the payload `buf`.
+    pub fn send_data(&self, media_id: u32, timeout: u64, protocol_id: u8, protocol_specific: u16,
+                     buf: &[u8]) -> Result<(), Status> {
+        let status = unsafe {
+            (self.send_data)(self, media_id, timeout, protocol_id, protocol_specific, buf.len(),
+                             buf.as_ptr() as *const CVoid)
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Whether the drive supports `protocol_id`, according to its supported protocol list.
+    pub fn supports(&self, media_id: u32, protocol_id: u8) -> Result<bool, Status> {
+        let mut buf = [0u8; 512];
+        let len = self.receive_data(media_id, 0, SECURITY_PROTOCOL_INFORMATION, 0, &mut buf)?;
+
+        // Six reserved bytes, a big-endian u16 count, then one byte per protocol.
+        let list = buf.get(..len).and_then(|b| b.get(8..)).unwrap_or(&[]);
+        let count = if len >= 8 { u16::from_be_bytes([buf[6], buf[7]]) as usize } else { 0 };
+        Ok(list.iter().take(count).any(|&id| id == protocol_id))
+    }
+
+    /// Run TCG Level 0 Discovery, which needs no session, into `buf` (at least 512 bytes is
+    /// usual).
+    pub fn level0_discovery<'a>(&self, media_id: u32, buf: &'a mut [u8]) -> Result<Level0Discovery<'a>, Status> {
+        let len = self.receive_data(media_id, 0, SECURITY_PROTOCOL_TCG, TCG_LEVEL0_DISCOVERY_COMID, buf)?;
+        Level0Discovery::from_bytes(&buf[..len])
+    }
+}
+
+/// ComID of TCG Level 0 Discovery.
+pub const TCG_LEVEL0_DISCOVERY_COMID: u16 = 0x0001;
+
+/// Level 0 feature code of the Locking feature.
+pub const TCG_FEATURE_LOCKING: u16 = 0x0002;
+/// Level 0 feature code of the Opal SSC v2 feature.
+pub const TCG_FEATURE_OPAL_V2: u16 = 0x0203;
+
+/// A TCG Level 0 Discovery response, which lists the security features of a drive.
+pub struct Level0Discovery<'a> {
+    data: &'a [u8],
+}
+
+/// The Locking feature of a Level 0 Discovery response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockingFeature {
+    pub supported: bool,
+    pub enabled: bool,
+    pub locked: bool,
+    pub mbr_enabled: bool,
+    pub mbr_done: bool,
+}
+
+impl<'a> Level0Discovery<'a> {
+    /// Parse a response, failing with `Status::InvalidParameter` if its header is truncated.
+    pub fn from_bytes(data: &'a [u8]) -> Result<Level0Discovery<'a>, Status> {
+        if data.len() < 48 {
+            return Err(Status::InvalidParameter);
+        }
+
+        // The length field does not count itself.
+        let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let end = len.saturating_add(4).min(data.len());
+        Ok(Level0Discovery { data: &data[..end] })
+    }
+
+    /// The features, as (feature code, version, feature data) triples.
+    pub fn features(&self) -> impl Iterator<Item = (u16, u8, &'a [u8])> + 'a {
+        let mut rest = &self.data[48..];
+        ::core::iter::from_fn(move || {
+            if rest.len() < 4 {
+                return None;
+            }
+            let code = u16::from_be_bytes([rest[0], rest[1]]);
+            let version = rest[2] >> 4;
+            let len = rest[3] as usize;
+            let data = rest.get(4..4 + len)?;
+            rest = &rest[4 + len..];
+            Some((code, version, data))
+        })
+    }
+
+    /// Find the feature with `code`, returning its data.
+    pub fn feature(&self, code: u16) -> Option<&'a [u8]> {
+        self.features().find(|&(c, _, _)| c == code).map(|(_, _, data)| data)
+    }
+
+    pub fn locking(&self) -> Option<LockingFeature> {
+        let flags = *self.feature(TCG_FEATURE_LOCKING)?.first()?;
+        Some(LockingFeature {
+            supported: flags & 0x01 != 0,
+            enabled: flags & 0x02 != 0,
+            locked: flags & 0x04 != 0,
+            mbr_enabled: flags & 0x10 != 0,
+            mbr_done: flags & 0x20 != 0,
+        })
+    }
+
+    /// The base ComID to open Opal sessions on, if the drive implements Opal SSC v2.
+    pub fn opal_v2_base_comid(&self) -> Option<u16> {
+        let data = self.feature(TCG_FEATURE_OPAL_V2)?;
+        Some(u16::from_be_bytes([*data.first()?, *data.get(1)?]))
+    }
+}
+
+#[test]
+fn level0_discovery() {
+    let mut buf = [0u8; 84];
+    buf[3] = 80;
+    // Locking: supported, enabled and locked.
+    buf[48..53].copy_from_slice(&[0x00, 0x02, 0x10, 0x0C, 0x07]);
+    // Opal v2 with base ComID 0x1000.
+    buf[64..70].copy_from_slice(&[0x02, 0x03, 0x10, 0x10, 0x10, 0x00]);
+
+    let d0 = Level0Discovery::from_bytes(&buf).unwrap();
+    let locking = d0.locking().unwrap();
+    assert!(locking.supported && locking.enabled && locking.locked && !locking.mbr_done);
+    assert_eq!(d0.opal_v2_base_comid(), Some(0x1000));
+    assert_eq!(d0.features().count(), 2);
+}
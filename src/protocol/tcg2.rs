@@ -0,0 +1,131 @@
+use base::Status;
+use guid::Guid;
+use protocol::Protocol;
+use void::NotYetDef;
+
+/// GUID for the TCG2 protocol, the firmware interface to a TPM 2.0
+pub static EFI_TCG2_PROTOCOL_GUID: Guid = Guid(0x607F766C, 0x7455, 0x42BE, [0x93, 0x0B, 0xE4, 0xD7, 0x6D, 0xB2, 0x72, 0x0F]);
+
+/// Number of PCRs in a PC client TPM.
+pub const TPM_PCR_COUNT: u32 = 24;
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_CC_PCR_READ: u32 = 0x0000017E;
+const TPM_ALG_SHA256: u16 = 0x000B;
+const TPM_RC_SUCCESS: u32 = 0;
+
+// bitflags 0.9 expands to the deprecated `try!`, so the flags get a module of their own.
+#[allow(deprecated)]
+mod hash_algorithms {
+    bitflags! {
+        /// Hash algorithm bitmap used for PCR banks and event log digests.
+        pub struct HashAlgorithms: u32 {
+            const EFI_TCG2_BOOT_HASH_ALG_SHA1 = 0x00000001;
+            const EFI_TCG2_BOOT_HASH_ALG_SHA256 = 0x00000002;
+            const EFI_TCG2_BOOT_HASH_ALG_SHA384 = 0x00000004;
+            const EFI_TCG2_BOOT_HASH_ALG_SHA512 = 0x00000008;
+            const EFI_TCG2_BOOT_HASH_ALG_SM3_256 = 0x00000010;
+        }
+    }
+}
+pub use self::hash_algorithms::*;
+
+#[repr(C)]
+pub struct Tcg2Protocol {
+    get_capability: *const NotYetDef,
+    get_event_log: *const NotYetDef,
+    hash_log_extend_event: *const NotYetDef,
+    submit_command: unsafe extern "win64" fn(this: *const Tcg2Protocol, input_size: u32, input: *const u8, output_size: u32, output: *mut u8) -> Status,
+    get_active_pcr_banks: unsafe extern "win64" fn(this: *const Tcg2Protocol, active_pcr_banks: *mut u32) -> Status,
+    set_active_pcr_banks: *const NotYetDef,
+    get_result_of_set_active_pcr_banks: *const NotYetDef,
+}
+
+impl Protocol for Tcg2Protocol {
+    fn guid() -> &'static Guid {
+        &EFI_TCG2_PROTOCOL_GUID
+    }
+}
+
+impl Tcg2Protocol {
+    /// Send a raw TPM 2.0 command and read the response into `output`. Returns the part of
+    /// `output` filled by the response, according to its header.
+    pub fn submit_command<'a>(&self, input: &[u8], output: &'a mut [u8]) -> Result<&'a [u8], Status> {
+        let status = unsafe {
+            (self.submit_command)(self, input.len() as u32, input.as_ptr(), output.len() as u32, output.as_mut_ptr())
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        if output.len() < 10 {
+            return Err(Status::BufferTooSmall);
+        }
+        let size = u32::from_be_bytes([output[2], output[3], output[4], output[5]]) as usize;
+        if size < 10 || size > output.len() {
+            return Err(Status::DeviceError);
+        }
+
+        Ok(&output[..size])
+    }
+
+    /// The PCR banks currently active in the TPM.
+    pub fn get_active_pcr_banks(&self) -> Result<HashAlgorithms, Status> {
+        let mut banks: u32 = 0;
+        let status = unsafe { (self.get_active_pcr_banks)(self, &mut banks) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(HashAlgorithms::from_bits_truncate(banks))
+    }
+
+    /// Read PCR `index` from the SHA-256 bank.
+    pub fn pcr_read_sha256(&self, index: u32) -> Result<[u8; 32], Status> {
+        if index >= TPM_PCR_COUNT {
+            return Err(Status::InvalidParameter);
+        }
+
+        let mut select = [0u8; 3];
+        select[(index / 8) as usize] = 1 << (index % 8);
+
+        let mut cmd = [0u8; 20];
+        cmd[0..2].copy_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        cmd[2..6].copy_from_slice(&20u32.to_be_bytes());
+        cmd[6..10].copy_from_slice(&TPM_CC_PCR_READ.to_be_bytes());
+        // TPML_PCR_SELECTION with a single SHA-256 selection.
+        cmd[10..14].copy_from_slice(&1u32.to_be_bytes());
+        cmd[14..16].copy_from_slice(&TPM_ALG_SHA256.to_be_bytes());
+        cmd[16] = select.len() as u8;
+        cmd[17..20].copy_from_slice(&select);
+
+        let mut buf = [0u8; 128];
+        let rsp = self.submit_command(&cmd, &mut buf)?;
+
+        if u32::from_be_bytes([rsp[6], rsp[7], rsp[8], rsp[9]]) != TPM_RC_SUCCESS {
+            return Err(Status::DeviceError);
+        }
+
+        // Skip the update counter and the returned selection, which has a variable-length
+        // bitmap, to reach the TPML_DIGEST.
+        let sel = 10 + 4;
+        if rsp.len() < sel + 7 {
+            return Err(Status::DeviceError);
+        }
+        let digests = sel + 4 + 2 + 1 + rsp[sel + 6] as usize;
+        if rsp.len() < digests + 6 || u32::from_be_bytes([rsp[digests], rsp[digests + 1], rsp[digests + 2], rsp[digests + 3]]) != 1 {
+            // The PCR is not allocated in the SHA-256 bank.
+            return Err(Status::NotFound);
+        }
+
+        let size = u16::from_be_bytes([rsp[digests + 4], rsp[digests + 5]]) as usize;
+        let start = digests + 6;
+        if size != 32 || rsp.len() < start + size {
+            return Err(Status::DeviceError);
+        }
+
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&rsp[start..start + size]);
+        Ok(digest)
+    }
+}
@@ -0,0 +1,492 @@
+use core::marker::PhantomData;
+use core::{mem, ptr, slice};
+
+use base::{Status, status_to_result};
+use guid::Guid;
+use protocol::Protocol;
+use void::{CVoid, NotYetDef};
+
+/// GUID for EFI_USBFN_IO_PROTOCOL, the USB device mode protocol of EDK2 and Windows platforms
+pub static EFI_USBFN_IO_PROTOCOL_GUID: Guid = Guid(0x32D2963A, 0xFE5D, 0x4F30, [0xB6, 0x33, 0x6E, 0x5D, 0xC5, 0x58, 0x03, 0xCC]);
+
+pub const USB_DESC_TYPE_DEVICE: u8 = 0x01;
+pub const USB_DESC_TYPE_CONFIG: u8 = 0x02;
+pub const USB_DESC_TYPE_INTERFACE: u8 = 0x04;
+pub const USB_DESC_TYPE_ENDPOINT: u8 = 0x05;
+
+/// Bit of an endpoint address set for IN (device to host) endpoints.
+pub const USB_ENDPOINT_DIR_IN: u8 = 0x80;
+
+/// Type for EFI_USBFN_PORT_TYPE, what the port is attached to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsbFnPortType {
+    Undefined,
+    StandardDownstream,
+    ChargingDownstream,
+    DedicatedCharging,
+    InvalidDedicatedCharging,
+}
+
+impl UsbFnPortType {
+    fn from_raw(raw: u32) -> UsbFnPortType {
+        match raw {
+            1 => UsbFnPortType::StandardDownstream,
+            2 => UsbFnPortType::ChargingDownstream,
+            3 => UsbFnPortType::DedicatedCharging,
+            4 => UsbFnPortType::InvalidDedicatedCharging,
+            _ => UsbFnPortType::Undefined,
+        }
+    }
+}
+
+/// Type for EFI_USB_ENDPOINT_TYPE.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsbEndpointType {
+    Control = 0,
+    Isochronous = 1,
+    Bulk = 2,
+    Interrupt = 3,
+}
+
+/// Type for EFI_USB_BUS_SPEED.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsbBusSpeed {
+    Unknown = 0,
+    Low = 1,
+    Full = 2,
+    High = 3,
+    Super = 4,
+}
+
+impl UsbBusSpeed {
+    fn from_raw(raw: u32) -> UsbBusSpeed {
+        match raw {
+            1 => UsbBusSpeed::Low,
+            2 => UsbBusSpeed::Full,
+            3 => UsbBusSpeed::High,
+            4 => UsbBusSpeed::Super,
+            _ => UsbBusSpeed::Unknown,
+        }
+    }
+}
+
+/// Type for EFI_USBFN_DEVICE_INFO_ID, the strings `UsbFnIoProtocol::device_info` returns.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsbFnDeviceInfoId {
+    SerialNumber = 1,
+    Manufacturer = 2,
+    Product = 3,
+}
+
+/// Type for EFI_USBFN_ENDPOINT_DIRECTION, named from the host's point of view.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsbEndpointDirection {
+    /// Host to device: the device receives.
+    HostOut = 0,
+    /// Device to host: the device sends.
+    HostIn = 1,
+}
+
+/// Type for EFI_USBFN_TRANSFER_STATUS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsbFnTransferStatus {
+    Unknown,
+    Complete,
+    Aborted,
+    Active,
+    None,
+}
+
+/// Type for USB_DEVICE_DESCRIPTOR.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct UsbDeviceDescriptor {
+    pub length: u8,
+    pub descriptor_type: u8,
+    pub bcd_usb: u16,
+    pub device_class: u8,
+    pub device_sub_class: u8,
+    pub device_protocol: u8,
+    pub max_packet_size0: u8,
+    pub id_vendor: u16,
+    pub id_product: u16,
+    pub bcd_device: u16,
+    pub str_manufacturer: u8,
+    pub str_product: u8,
+    pub str_serial_number: u8,
+    pub num_configurations: u8,
+}
+
+/// Type for USB_CONFIG_DESCRIPTOR. `total_length` covers the interface and endpoint
+/// descriptors of the configuration as well.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct UsbConfigDescriptor {
+    pub length: u8,
+    pub descriptor_type: u8,
+    pub total_length: u16,
+    pub num_interfaces: u8,
+    pub configuration_value: u8,
+    pub configuration: u8,
+    pub attributes: u8,
+    /// In units of 2 mA.
+    pub max_power: u8,
+}
+
+/// Type for USB_INTERFACE_DESCRIPTOR.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct UsbInterfaceDescriptor {
+    pub length: u8,
+    pub descriptor_type: u8,
+    pub interface_number: u8,
+    pub alternate_setting: u8,
+    pub num_endpoints: u8,
+    pub interface_class: u8,
+    pub interface_sub_class: u8,
+    pub interface_protocol: u8,
+    pub interface: u8,
+}
+
+/// Type for USB_ENDPOINT_DESCRIPTOR.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct UsbEndpointDescriptor {
+    pub length: u8,
+    pub descriptor_type: u8,
+    /// The endpoint number, with `USB_ENDPOINT_DIR_IN` set for IN endpoints.
+    pub endpoint_address: u8,
+    pub attributes: u8,
+    pub max_packet_size: u16,
+    pub interval: u8,
+}
+
+/// Type for EFI_USB_DEVICE_REQUEST, a SETUP packet from the host.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct UsbDeviceRequest {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+/// Type for EFI_USB_INTERFACE_INFO: an interface descriptor and its endpoints.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct UsbInterfaceInfo<'a> {
+    interface_descriptor: *const UsbInterfaceDescriptor,
+    endpoint_descriptor_table: *const &'a UsbEndpointDescriptor,
+    _descriptors: PhantomData<&'a UsbInterfaceDescriptor>,
+}
+
+impl<'a> UsbInterfaceInfo<'a> {
+    /// Fails with `Status::InvalidParameter` unless there are as many endpoints as the
+    /// descriptor says.
+    pub fn new(descriptor: &'a UsbInterfaceDescriptor, endpoints: &'a [&'a UsbEndpointDescriptor]) -> Result<UsbInterfaceInfo<'a>, Status> {
+        if descriptor.num_endpoints as usize != endpoints.len() {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(UsbInterfaceInfo {
+            interface_descriptor: descriptor,
+            endpoint_descriptor_table: endpoints.as_ptr(),
+            _descriptors: PhantomData,
+        })
+    }
+}
+
+/// Type for EFI_USB_CONFIG_INFO: a configuration descriptor and its interfaces.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct UsbConfigInfo<'a> {
+    config_descriptor: *const UsbConfigDescriptor,
+    interface_info_table: *const &'a UsbInterfaceInfo<'a>,
+    _descriptors: PhantomData<&'a UsbConfigDescriptor>,
+}
+
+impl<'a> UsbConfigInfo<'a> {
+    /// Fails with `Status::InvalidParameter` unless there are as many interfaces as the
+    /// descriptor says.
+    pub fn new(descriptor: &'a UsbConfigDescriptor, interfaces: &'a [&'a UsbInterfaceInfo<'a>]) -> Result<UsbConfigInfo<'a>, Status> {
+        if descriptor.num_interfaces as usize != interfaces.len() {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(UsbConfigInfo {
+            config_descriptor: descriptor,
+            interface_info_table: interfaces.as_ptr(),
+            _descriptors: PhantomData,
+        })
+    }
+}
+
+/// Type for EFI_USB_DEVICE_INFO, the descriptors given to
+/// `UsbFnIoProtocol::configure_enable_endpoints`.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct UsbDeviceInfo<'a> {
+    device_descriptor: *const UsbDeviceDescriptor,
+    config_info_table: *const &'a UsbConfigInfo<'a>,
+    _descriptors: PhantomData<&'a UsbDeviceDescriptor>,
+}
+
+impl<'a> UsbDeviceInfo<'a> {
+    /// Fails with `Status::InvalidParameter` unless there are as many configurations as the
+    /// descriptor says.
+    pub fn new(descriptor: &'a UsbDeviceDescriptor, configs: &'a [&'a UsbConfigInfo<'a>]) -> Result<UsbDeviceInfo<'a>, Status> {
+        if descriptor.num_configurations as usize != configs.len() {
+            return Err(Status::InvalidParameter);
+        }
+        Ok(UsbDeviceInfo {
+            device_descriptor: descriptor,
+            config_info_table: configs.as_ptr(),
+            _descriptors: PhantomData,
+        })
+    }
+}
+
+/// Type for EFI_USBFN_TRANSFER_RESULT, reported when a transfer finishes.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct UsbFnTransferResult {
+    bytes_transferred: usize,
+    transfer_status: u32,
+    endpoint_index: u8,
+    direction: u32,
+    buffer: *mut CVoid,
+}
+
+impl UsbFnTransferResult {
+    pub fn bytes_transferred(&self) -> usize {
+        self.bytes_transferred
+    }
+
+    pub fn status(&self) -> UsbFnTransferStatus {
+        match self.transfer_status {
+            1 => UsbFnTransferStatus::Complete,
+            2 => UsbFnTransferStatus::Aborted,
+            3 => UsbFnTransferStatus::Active,
+            4 => UsbFnTransferStatus::None,
+            _ => UsbFnTransferStatus::Unknown,
+        }
+    }
+
+    pub fn endpoint_index(&self) -> u8 {
+        self.endpoint_index
+    }
+
+    pub fn direction(&self) -> UsbEndpointDirection {
+        if self.direction == UsbEndpointDirection::HostIn as u32 {
+            UsbEndpointDirection::HostIn
+        } else {
+            UsbEndpointDirection::HostOut
+        }
+    }
+
+    /// The buffer given to `UsbFnIoProtocol::transfer`.
+    pub fn buffer(&self) -> *mut u8 {
+        self.buffer as *mut u8
+    }
+}
+
+/// Type for EFI_USBFN_MESSAGE_PAYLOAD.
+#[derive(Clone, Copy)]
+#[repr(C)]
+union UsbFnMessagePayload {
+    udr: UsbDeviceRequest,
+    utr: UsbFnTransferResult,
+    ubs: u32,
+}
+
+/// What happened on the bus, from `UsbFnIoProtocol::event_handler`.
+#[derive(Clone, Copy, Debug)]
+pub enum UsbFnEvent {
+    None,
+    /// A SETUP packet the driver did not handle itself, such as a class or vendor request.
+    /// The application must answer it on endpoint 0, or stall it.
+    SetupPacket(UsbDeviceRequest),
+    /// A transfer from the host finished.
+    EndpointStatusChangedRx(UsbFnTransferResult),
+    /// A transfer to the host finished.
+    EndpointStatusChangedTx(UsbFnTransferResult),
+    DetachedFromHost,
+    AttachedToHost,
+    Suspend,
+    Resume,
+    /// The host has enumerated the device at this speed.
+    BusEventSpeed(UsbBusSpeed),
+}
+
+/// EFI_USBFN_IO_PROTOCOL, found on platforms with a USB device controller, for presenting the
+/// machine to a host as a USB device.
+///
+/// The driver answers the host's standard requests from the descriptors given to
+/// `configure_enable_endpoints`. Everything else arrives through `event_handler`, which must be
+/// polled; transfers are queued with `transfer` and reported finished by it.
+#[repr(C)]
+pub struct UsbFnIoProtocol {
+    revision: u32,
+    detect_port: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, port_type: *mut u32) -> Status,
+    configure_enable_endpoints: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, device_info: *const UsbDeviceInfo) -> Status,
+    get_endpoint_max_packet_size: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, endpoint_type: UsbEndpointType, bus_speed: UsbBusSpeed, max_packet_size: *mut u16) -> Status,
+    get_device_info: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, id: UsbFnDeviceInfoId, buffer_size: *mut usize, buffer: *mut CVoid) -> Status,
+    get_vendor_id_product_id: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, vid: *mut u16, pid: *mut u16) -> Status,
+    abort_transfer: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, endpoint_index: u8, direction: UsbEndpointDirection) -> Status,
+    get_endpoint_stall_state: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, endpoint_index: u8, direction: UsbEndpointDirection, state: *mut bool) -> Status,
+    set_endpoint_stall_state: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, endpoint_index: u8, direction: UsbEndpointDirection, state: bool) -> Status,
+    event_handler: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, message: *mut u32, payload_size: *mut usize, payload: *mut UsbFnMessagePayload) -> Status,
+    transfer: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, endpoint_index: u8, direction: UsbEndpointDirection, buffer_size: *mut usize, buffer: *mut CVoid) -> Status,
+    get_max_transfer_size: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, max_transfer_size: *mut usize) -> Status,
+    allocate_transfer_buffer: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, size: usize, buffer: *mut *mut CVoid) -> Status,
+    free_transfer_buffer: unsafe extern "win64" fn(this: *const UsbFnIoProtocol, buffer: *mut CVoid) -> Status,
+    start_controller: unsafe extern "win64" fn(this: *const UsbFnIoProtocol) -> Status,
+    stop_controller: unsafe extern "win64" fn(this: *const UsbFnIoProtocol) -> Status,
+    set_endpoint_policy: *const NotYetDef,
+    get_endpoint_policy: *const NotYetDef,
+}
+
+impl Protocol for UsbFnIoProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_USBFN_IO_PROTOCOL_GUID
+    }
+}
+
+impl UsbFnIoProtocol {
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// What kind of port the device controller is attached to. Only meaningful after
+    /// `start_controller`.
+    pub fn detect_port(&self) -> Result<UsbFnPortType, Status> {
+        let mut port_type = 0;
+        status_to_result(unsafe { (self.detect_port)(self, &mut port_type) })?;
+        Ok(UsbFnPortType::from_raw(port_type))
+    }
+
+    /// Give the driver the descriptors to present to the host, and enable the endpoints they
+    /// describe. The driver may keep pointers to `device_info` until the controller is stopped.
+    pub fn configure_enable_endpoints(&self, device_info: &UsbDeviceInfo) -> Result<(), Status> {
+        status_to_result(unsafe { (self.configure_enable_endpoints)(self, device_info) })
+    }
+
+    /// The largest packet the controller supports on endpoints of `endpoint_type` at `speed`.
+    pub fn endpoint_max_packet_size(&self, endpoint_type: UsbEndpointType, speed: UsbBusSpeed) -> Result<u16, Status> {
+        let mut size = 0;
+        status_to_result(unsafe { (self.get_endpoint_max_packet_size)(self, endpoint_type, speed, &mut size) })?;
+        Ok(size)
+    }
+
+    /// Read the platform's `id` string, such as its serial number, into `buf` as null-terminated
+    /// UCS-2. Returns the number of units written, including the null; if `buf` is too small,
+    /// fails with `Status::BufferTooSmall`.
+    pub fn device_info(&self, id: UsbFnDeviceInfoId, buf: &mut [u16]) -> Result<usize, Status> {
+        let mut size = mem::size_of_val(buf);
+        status_to_result(unsafe { (self.get_device_info)(self, id, &mut size, buf.as_mut_ptr() as *mut CVoid) })?;
+        Ok(size / 2)
+    }
+
+    /// The USB vendor and product IDs the platform is assigned.
+    pub fn vendor_id_product_id(&self) -> Result<(u16, u16), Status> {
+        let (mut vid, mut pid) = (0, 0);
+        status_to_result(unsafe { (self.get_vendor_id_product_id)(self, &mut vid, &mut pid) })?;
+        Ok((vid, pid))
+    }
+
+    pub fn abort_transfer(&self, endpoint_index: u8, direction: UsbEndpointDirection) -> Result<(), Status> {
+        status_to_result(unsafe { (self.abort_transfer)(self, endpoint_index, direction) })
+    }
+
+    pub fn endpoint_stall_state(&self, endpoint_index: u8, direction: UsbEndpointDirection) -> Result<bool, Status> {
+        let mut state = false;
+        status_to_result(unsafe { (self.get_endpoint_stall_state)(self, endpoint_index, direction, &mut state) })?;
+        Ok(state)
+    }
+
+    /// Stall the endpoint, or clear a stall. Stalling endpoint 0 refuses a SETUP packet.
+    pub fn set_endpoint_stall_state(&self, endpoint_index: u8, direction: UsbEndpointDirection, state: bool) -> Result<(), Status> {
+        status_to_result(unsafe { (self.set_endpoint_stall_state)(self, endpoint_index, direction, state) })
+    }
+
+    /// Poll the controller for the next thing that happened on the bus.
+    pub fn event_handler(&self) -> Result<UsbFnEvent, Status> {
+        let mut message = 0;
+        let mut payload: UsbFnMessagePayload = unsafe { mem::zeroed() };
+        let mut size = mem::size_of::<UsbFnMessagePayload>();
+        status_to_result(unsafe { (self.event_handler)(self, &mut message, &mut size, &mut payload) })?;
+
+        // The union field read is the one the message says the driver filled in.
+        Ok(unsafe {
+            match message {
+                1 => UsbFnEvent::SetupPacket(payload.udr),
+                2 => UsbFnEvent::EndpointStatusChangedRx(payload.utr),
+                3 => UsbFnEvent::EndpointStatusChangedTx(payload.utr),
+                4 => UsbFnEvent::DetachedFromHost,
+                5 => UsbFnEvent::AttachedToHost,
+                6 => UsbFnEvent::Suspend,
+                7 => UsbFnEvent::Resume,
+                8 => UsbFnEvent::BusEventSpeed(UsbBusSpeed::from_raw(payload.ubs)),
+                _ => UsbFnEvent::None,
+            }
+        })
+    }
+
+    /// Queue a transfer of `len` bytes at `buffer` on the endpoint. Its completion is reported
+    /// by `event_handler`.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must come from `allocate_transfer_buffer` and stay allocated, and for
+    /// `HostOut` not otherwise accessed, until the transfer is reported finished or aborted.
+    pub unsafe fn transfer(&self, endpoint_index: u8, direction: UsbEndpointDirection, buffer: *mut u8, len: usize) -> Result<(), Status> {
+        let mut size = len;
+        status_to_result((self.transfer)(self, endpoint_index, direction, &mut size, buffer as *mut CVoid))
+    }
+
+    /// The largest transfer `transfer` accepts.
+    pub fn max_transfer_size(&self) -> Result<usize, Status> {
+        let mut size = 0;
+        status_to_result(unsafe { (self.get_max_transfer_size)(self, &mut size) })?;
+        Ok(size)
+    }
+
+    /// Allocate a buffer for `transfer`, in memory the controller can reach. Free it with
+    /// `free_transfer_buffer`.
+    pub fn allocate_transfer_buffer(&self, size: usize) -> Result<&'static mut [u8], Status> {
+        let mut buffer = ptr::null_mut();
+        status_to_result(unsafe { (self.allocate_transfer_buffer)(self, size, &mut buffer) })?;
+        Ok(unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size) })
+    }
+
+    /// Free a buffer from `allocate_transfer_buffer`.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must not be used afterwards, nor be part of an unfinished transfer.
+    pub unsafe fn free_transfer_buffer(&self, buffer: *mut u8) -> Result<(), Status> {
+        status_to_result((self.free_transfer_buffer)(self, buffer as *mut CVoid))
+    }
+
+    /// Start the device controller, making the device visible to a host once configured.
+    pub fn start_controller(&self) -> Result<(), Status> {
+        status_to_result(unsafe { (self.start_controller)(self) })
+    }
+
+    /// Stop the device controller, disconnecting from the host.
+    pub fn stop_controller(&self) -> Result<(), Status> {
+        status_to_result(unsafe { (self.stop_controller)(self) })
+    }
+}
+
+#[test]
+fn usb_descriptor_sizes() {
+    assert_eq!(mem::size_of::<UsbDeviceDescriptor>(), 18);
+    assert_eq!(mem::size_of::<UsbConfigDescriptor>(), 9);
+    assert_eq!(mem::size_of::<UsbInterfaceDescriptor>(), 9);
+    assert_eq!(mem::size_of::<UsbEndpointDescriptor>(), 7);
+    assert_eq!(mem::size_of::<UsbDeviceRequest>(), 8);
+}
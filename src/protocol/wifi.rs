@@ -0,0 +1,384 @@
+use core::{mem, ptr, slice};
+
+use base::{Event, Status};
+use guid::Guid;
+use protocol::Protocol;
+use void::CVoid;
+
+/// GUID for the wireless MAC connection II protocol
+pub static EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL_GUID: Guid = Guid(0x1B0FB9BF, 0x699D, 0x4FDD, [0xA7, 0xC3, 0x25, 0x46, 0x68, 0x1B, 0xF6, 0x3B]);
+
+/// GUID for the supplicant protocol
+pub static EFI_SUPPLICANT_PROTOCOL_GUID: Guid = Guid(0x54FCC43E, 0xAA89, 0x4333, [0x9A, 0x85, 0xCD, 0xEA, 0x24, 0x05, 0x1E, 0x9E]);
+
+/// Longest SSID, in bytes.
+pub const MAX_SSID_LEN: usize = 32;
+
+/// Type for EFI_80211_SSID.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Ssid {
+    len: u8,
+    bytes: [u8; MAX_SSID_LEN],
+}
+
+impl Ssid {
+    /// Fails with `Status::InvalidParameter` if `ssid` is longer than `MAX_SSID_LEN` bytes.
+    pub fn new(ssid: &[u8]) -> Result<Ssid, Status> {
+        if ssid.len() > MAX_SSID_LEN {
+            return Err(Status::InvalidParameter);
+        }
+
+        let mut bytes = [0; MAX_SSID_LEN];
+        bytes[..ssid.len()].copy_from_slice(ssid);
+        Ok(Ssid { len: ssid.len() as u8, bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..(self.len as usize).min(MAX_SSID_LEN)]
+    }
+}
+
+/// Type for EFI_80211_BSS_TYPE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum BssType {
+    Infrastructure = 0,
+    Independent = 1,
+    Mesh = 2,
+    Any = 3,
+}
+
+/// Type for EFI_80211_SUITE_SELECTOR, an OUI and suite type such as 00-0F-AC:2 (PSK).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct SuiteSelector {
+    pub oui: [u8; 3],
+    pub suite_type: u8,
+}
+
+/// The WPA2-Personal (PSK) key management suite.
+pub const AKM_SUITE_PSK: SuiteSelector = SuiteSelector { oui: [0x00, 0x0F, 0xAC], suite_type: 2 };
+/// The CCMP (AES) cipher suite.
+pub const CIPHER_SUITE_CCMP: SuiteSelector = SuiteSelector { oui: [0x00, 0x0F, 0xAC], suite_type: 4 };
+
+/// Type for EFI_80211_AKM_SUITE_SELECTOR and EFI_80211_CIPHER_SUITE_SELECTOR, a count followed
+/// by that many selectors.
+#[repr(C)]
+pub struct SuiteList {
+    count: u16,
+    list: [SuiteSelector; 0],
+}
+
+impl SuiteList {
+    pub fn suites(&self) -> &[SuiteSelector] {
+        unsafe { slice::from_raw_parts(self.list.as_ptr(), self.count as usize) }
+    }
+}
+
+/// Type for EFI_80211_NETWORK.
+#[repr(C)]
+pub struct WifiNetwork {
+    pub bss_type: BssType,
+    pub ssid: Ssid,
+    akm_suite: *const SuiteList,
+    cipher_suite: *const SuiteList,
+}
+
+impl WifiNetwork {
+    pub fn akm_suites(&self) -> &[SuiteSelector] {
+        unsafe { self.akm_suite.as_ref() }.map_or(&[], |l| l.suites())
+    }
+
+    pub fn cipher_suites(&self) -> &[SuiteSelector] {
+        unsafe { self.cipher_suite.as_ref() }.map_or(&[], |l| l.suites())
+    }
+}
+
+/// Type for EFI_80211_NETWORK_DESCRIPTION.
+#[repr(C)]
+pub struct WifiNetworkDescription {
+    pub network: WifiNetwork,
+    /// Signal quality, from 0 to 100.
+    pub quality: u8,
+}
+
+/// Type for EFI_80211_CONNECT_NETWORK_RESULT_CODE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ConnectResult {
+    Success = 0,
+    InvalidParameter = 1,
+    AuthenticationFailure = 2,
+    AssociationFailure = 3,
+    OtherFailure = 4,
+}
+
+#[repr(C)]
+struct GetNetworksData {
+    num_of_ssid: u32,
+    ssid_list: [Ssid; 1],
+}
+
+#[repr(C)]
+struct GetNetworksResult {
+    num_of_network_desc: u8,
+    network_desc: [WifiNetworkDescription; 0],
+}
+
+#[repr(C)]
+struct GetNetworksToken {
+    event: Event,
+    status: Status,
+    data: *const GetNetworksData,
+    result: *mut GetNetworksResult,
+}
+
+#[repr(C)]
+struct ConnectNetworkData {
+    network: *const WifiNetwork,
+    failure_timeout: u32,
+}
+
+#[repr(C)]
+struct ConnectNetworkToken {
+    event: Event,
+    status: Status,
+    data: *const ConnectNetworkData,
+    result_code: ConnectResult,
+}
+
+#[repr(C)]
+struct DisconnectNetworkToken {
+    event: Event,
+    status: Status,
+}
+
+/// Networks found by `WirelessMacConnectionProtocol::scan`, in a pool buffer which is freed on
+/// drop.
+pub struct WifiNetworks {
+    result: *mut GetNetworksResult,
+}
+
+impl WifiNetworks {
+    pub fn as_slice(&self) -> &[WifiNetworkDescription] {
+        unsafe {
+            let result = &*self.result;
+            slice::from_raw_parts(result.network_desc.as_ptr(), result.num_of_network_desc as usize)
+        }
+    }
+
+    /// The network with the strongest signal among those named `ssid`.
+    pub fn find(&self, ssid: &[u8]) -> Option<&WifiNetworkDescription> {
+        self.as_slice()
+            .iter()
+            .filter(|d| d.network.ssid.as_bytes() == ssid)
+            .max_by_key(|d| d.quality)
+    }
+}
+
+impl Drop for WifiNetworks {
+    fn drop(&mut self) {
+        ::get_system_table().boot_services().free_pool(self.result);
+    }
+}
+
+/// Start an asynchronous token operation with a fresh event and wait for it to complete. The
+/// event is a timer-type event with no notification function, which the driver signals.
+fn run_token<F: FnOnce(Event) -> Status>(start: F) -> Result<(), Status> {
+    let bs = ::get_system_table().boot_services();
+    let event = bs.create_timer_event()?;
+
+    // The driver keeps pointers to the token until it signals the event, so wait for that
+    // however long it takes.
+    let status = start(event);
+    let result = if status == Status::Success {
+        bs.wait_for_event(&[event]).map(|_| ())
+    } else {
+        Err(status)
+    };
+
+    bs.close_event(event);
+    result
+}
+
+/// EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL, which scans for and joins Wi-Fi networks. The
+/// EFI_SUPPLICANT_PROTOCOL on the same handle holds the credentials for protected networks.
+#[repr(C)]
+pub struct WirelessMacConnectionProtocol {
+    get_networks: unsafe extern "win64" fn(this: *const WirelessMacConnectionProtocol, token: *mut GetNetworksToken) -> Status,
+    connect_network: unsafe extern "win64" fn(this: *const WirelessMacConnectionProtocol, token: *mut ConnectNetworkToken) -> Status,
+    disconnect_network: unsafe extern "win64" fn(this: *const WirelessMacConnectionProtocol, token: *mut DisconnectNetworkToken) -> Status,
+}
+
+impl Protocol for WirelessMacConnectionProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_WIRELESS_MAC_CONNECTION_II_PROTOCOL_GUID
+    }
+}
+
+impl WirelessMacConnectionProtocol {
+    /// Scan for networks, including the hidden network `ssid` if given, and wait for the
+    /// results.
+    pub fn scan(&self, ssid: Option<&Ssid>) -> Result<WifiNetworks, Status> {
+        let data = GetNetworksData {
+            num_of_ssid: ssid.is_some() as u32,
+            ssid_list: [ssid.cloned().unwrap_or(Ssid { len: 0, bytes: [0; MAX_SSID_LEN] })],
+        };
+        let mut token = GetNetworksToken {
+            event: Event(ptr::null_mut()),
+            status: Status::Success,
+            data: &data,
+            result: ptr::null_mut(),
+        };
+
+        run_token(|event| {
+            token.event = event;
+            unsafe { (self.get_networks)(self, &mut token) }
+        })?;
+        if token.status != Status::Success {
+            return Err(token.status);
+        }
+        if token.result.is_null() {
+            return Err(Status::NotFound);
+        }
+        Ok(WifiNetworks { result: token.result })
+    }
+
+    /// Join `network`, giving up after `failure_timeout` seconds. Protected networks need their
+    /// credentials set on the supplicant first. An association or authentication failure is
+    /// returned as `Err(Status::DeviceError)` together with the driver's result code through
+    /// `ConnectResult`.
+    pub fn connect(&self, network: &WifiNetwork, failure_timeout: u32) -> Result<(), (Status, ConnectResult)> {
+        let data = ConnectNetworkData { network, failure_timeout };
+        let mut token = ConnectNetworkToken {
+            event: Event(ptr::null_mut()),
+            status: Status::Success,
+            data: &data,
+            result_code: ConnectResult::Success,
+        };
+
+        run_token(|event| {
+            token.event = event;
+            unsafe { (self.connect_network)(self, &mut token) }
+        }).map_err(|e| (e, ConnectResult::OtherFailure))?;
+
+        match (token.status, token.result_code) {
+            (Status::Success, ConnectResult::Success) => Ok(()),
+            (Status::Success, code) => Err((Status::DeviceError, code)),
+            (status, code) => Err((status, code)),
+        }
+    }
+
+    /// Join the WPA2-Personal network `ssid` with `passphrase`: set the credentials on
+    /// `supplicant`, scan for the network and connect to the strongest access point, waiting up
+    /// to `failure_timeout` seconds. Fails with `Status::NotFound` if the network is not in
+    /// range.
+    pub fn join_psk(&self, supplicant: &SupplicantProtocol, ssid: &str, passphrase: &str, failure_timeout: u32)
+                    -> Result<(), (Status, ConnectResult)> {
+        let other = |e| (e, ConnectResult::OtherFailure);
+        let ssid = Ssid::new(ssid.as_bytes()).map_err(other)?;
+        supplicant.set_psk(&ssid, passphrase).map_err(other)?;
+
+        let networks = self.scan(Some(&ssid)).map_err(other)?;
+        let network = networks.find(ssid.as_bytes()).ok_or((Status::NotFound, ConnectResult::OtherFailure))?;
+        self.connect(&network.network, failure_timeout)
+    }
+
+    /// Leave the current network.
+    pub fn disconnect(&self) -> Result<(), Status> {
+        let mut token = DisconnectNetworkToken {
+            event: Event(ptr::null_mut()),
+            status: Status::Success,
+        };
+
+        run_token(|event| {
+            token.event = event;
+            unsafe { (self.disconnect_network)(self, &mut token) }
+        })?;
+        if token.status != Status::Success {
+            return Err(token.status);
+        }
+        Ok(())
+    }
+}
+
+/// Type for EFI_SUPPLICANT_DATA_TYPE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SupplicantDataType {
+    AkmSuite = 0,
+    GroupDataCipherSuite = 1,
+    PairwiseCipherSuite = 2,
+    PskPassword = 3,
+    TargetSsidName = 4,
+    StationMac = 5,
+    TargetSsidMac = 6,
+    Ptk = 7,
+    Gtk = 8,
+    State = 9,
+    LinkState = 10,
+    KeyRefresh = 11,
+    SupportedAkmSuites = 12,
+    SupportedSoftwareCipherSuites = 13,
+    SupportedHardwareCipherSuites = 14,
+    Igtk = 15,
+    Pmk = 16,
+}
+
+/// EFI_SUPPLICANT_PROTOCOL, the WPA supplicant the Wi-Fi driver uses for protected networks.
+/// Only its configuration functions are bound; the driver runs the key exchange itself.
+#[repr(C)]
+pub struct SupplicantProtocol {
+    build_response_packet: *const CVoid,
+    process_packet: *const CVoid,
+    set_data: unsafe extern "win64" fn(this: *const SupplicantProtocol, data_type: SupplicantDataType, data: *const CVoid, data_size: usize) -> Status,
+    get_data: unsafe extern "win64" fn(this: *const SupplicantProtocol, data_type: SupplicantDataType, data: *mut CVoid, data_size: *mut usize) -> Status,
+}
+
+impl Protocol for SupplicantProtocol {
+    fn guid() -> &'static Guid {
+        &EFI_SUPPLICANT_PROTOCOL_GUID
+    }
+}
+
+impl SupplicantProtocol {
+    pub fn set_data(&self, data_type: SupplicantDataType, data: &[u8]) -> Result<(), Status> {
+        let status = unsafe { (self.set_data)(self, data_type, data.as_ptr() as *const CVoid, data.len()) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Read `data_type` into `buf`, returning its size. Fails with `Status::BufferTooSmall` if
+    /// `buf` is too small.
+    pub fn get_data(&self, data_type: SupplicantDataType, buf: &mut [u8]) -> Result<usize, Status> {
+        let mut size = buf.len();
+        let status = unsafe { (self.get_data)(self, data_type, buf.as_mut_ptr() as *mut CVoid, &mut size) };
+        if status != Status::Success {
+            return Err(status);
+        }
+        Ok(size)
+    }
+
+    /// Set the WPA2-Personal credentials for `ssid`. The passphrase must be 8 to 63 ASCII
+    /// characters, and is passed to the supplicant null-terminated.
+    pub fn set_psk(&self, ssid: &Ssid, passphrase: &str) -> Result<(), Status> {
+        if !(8..=63).contains(&passphrase.len()) || !passphrase.is_ascii() {
+            return Err(Status::InvalidParameter);
+        }
+
+        let mut password = [0u8; 64];
+        password[..passphrase.len()].copy_from_slice(passphrase.as_bytes());
+        // The SSID is passed as the EFI_80211_SSID structure itself.
+        let ssid_bytes = unsafe { slice::from_raw_parts(ssid as *const Ssid as *const u8, mem::size_of::<Ssid>()) };
+        let result = self.set_data(SupplicantDataType::TargetSsidName, ssid_bytes)
+            .and_then(|_| self.set_data(SupplicantDataType::PskPassword, &password[..passphrase.len() + 1]));
+
+        for b in password.iter_mut() {
+            unsafe { ptr::write_volatile(b, 0) };
+        }
+        result
+    }
+}
@@ -0,0 +1,39 @@
+//! Power State Coordination Interface calls, used to reset or power off ARM systems whose
+//! ResetSystem runtime service returns instead of resetting.
+//!
+//! PSCI is reached through the SMC conduit, or HVC with the `psci-hvc` feature when running under
+//! a hypervisor that expects it.
+
+use core::arch::asm;
+
+use runtimeservices::ResetType;
+
+const PSCI_SYSTEM_OFF: u32 = 0x8400_0008;
+const PSCI_SYSTEM_RESET: u32 = 0x8400_0009;
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn call(function: u32) {
+    #[cfg(not(feature = "psci-hvc"))]
+    asm!("smc #0", in("x0") function as u64, clobber_abi("C"), options(nostack));
+    #[cfg(feature = "psci-hvc")]
+    asm!("hvc #0", in("x0") function as u64, clobber_abi("C"), options(nostack));
+}
+
+#[cfg(target_arch = "arm")]
+unsafe fn call(function: u32) {
+    #[cfg(not(feature = "psci-hvc"))]
+    asm!(".arch_extension sec", "smc #0", in("r0") function, clobber_abi("C"), options(nostack));
+    #[cfg(feature = "psci-hvc")]
+    asm!(".arch_extension virt", "hvc #0", in("r0") function, clobber_abi("C"), options(nostack));
+}
+
+/// Ask the PSCI firmware for the reset or power off `reset_type` stands for. Only returns if PSCI
+/// is not implemented or refuses the request.
+pub fn reset(reset_type: ResetType) {
+    let function = match reset_type {
+        ResetType::Shutdown => PSCI_SYSTEM_OFF,
+        ResetType::Cold | ResetType::Warm | ResetType::PlatformSpecific => PSCI_SYSTEM_RESET,
+    };
+
+    unsafe { call(function) }
+}
@@ -0,0 +1,556 @@
+//! A small QR code generator for showing recovery information, such as a panic message or a
+//! support URL, on error screens.
+//!
+//! Data is encoded in byte mode, in the smallest version from 1 to `MAX_QR_VERSION` it fits,
+//! and the result can be drawn with the graphics output protocol or as block characters on a
+//! text console.
+
+use core::fmt;
+
+use base::Status;
+use protocol::{BltPixel, GraphicsOutputProtocol};
+
+/// Largest QR version generated, 57 by 57 modules. At `QrEcc::Low` this holds 271 bytes.
+pub const MAX_QR_VERSION: u8 = 10;
+
+/// Width of the light border drawn around the code with the graphics output protocol, in
+/// modules. Text rendering uses half this to fit more codes on screen.
+pub const QR_QUIET_ZONE: usize = 4;
+
+const MAX_QR_SIZE: usize = MAX_QR_VERSION as usize * 4 + 17;
+const MAX_QR_MODULE_BYTES: usize = (MAX_QR_SIZE * MAX_QR_SIZE + 7) / 8;
+const MAX_QR_CODEWORDS: usize = 346;
+
+/// Error correction level, from recovering 7% of the code to 30%.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QrEcc {
+    Low,
+    Medium,
+    Quartile,
+    High,
+}
+
+impl QrEcc {
+    fn index(self) -> usize {
+        match self {
+            QrEcc::Low => 0,
+            QrEcc::Medium => 1,
+            QrEcc::Quartile => 2,
+            QrEcc::High => 3,
+        }
+    }
+
+    fn format_bits(self) -> u32 {
+        match self {
+            QrEcc::Low => 1,
+            QrEcc::Medium => 0,
+            QrEcc::Quartile => 3,
+            QrEcc::High => 2,
+        }
+    }
+}
+
+// Indexed by error correction level, then version.
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 11]; 4] = [
+    [0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18],
+    [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26],
+    [0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24],
+    [0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28],
+];
+
+const NUM_ERROR_CORRECTION_BLOCKS: [[u8; 11]; 4] = [
+    [0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4],
+    [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5],
+    [0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8],
+    [0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8],
+];
+
+/// Number of modules available for data and error correction codewords.
+fn raw_data_modules(version: u8) -> usize {
+    let v = version as usize;
+    let mut result = (16 * v + 128) * v + 64;
+    if v >= 2 {
+        let num_align = v / 7 + 2;
+        result -= (25 * num_align - 10) * num_align - 55;
+        if v >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn data_codewords(version: u8, ecc: QrEcc) -> usize {
+    let (e, v) = (ecc.index(), version as usize);
+    raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[e][v] as usize * NUM_ERROR_CORRECTION_BLOCKS[e][v] as usize
+}
+
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u32 >> i) & 1) * x as u32;
+    }
+    z as u8
+}
+
+/// The Reed-Solomon error correction codewords for `data`, written to `ecc`, whose length is
+/// the number of codewords.
+fn reed_solomon(data: &[u8], ecc: &mut [u8]) {
+    let degree = ecc.len();
+    let mut divisor = [0u8; 30];
+    divisor[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            divisor[j] = gf_multiply(divisor[j], root);
+            if j + 1 < degree {
+                divisor[j] ^= divisor[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+
+    ecc.fill(0);
+    for &b in data {
+        let factor = b ^ ecc[0];
+        ecc.copy_within(1.., 0);
+        ecc[degree - 1] = 0;
+        for (e, &d) in ecc.iter_mut().zip(divisor.iter()) {
+            *e ^= gf_multiply(d, factor);
+        }
+    }
+}
+
+struct BitBuffer {
+    bytes: [u8; MAX_QR_CODEWORDS],
+    len: usize,
+}
+
+impl BitBuffer {
+    fn push(&mut self, value: u32, bits: usize) {
+        for i in (0..bits).rev() {
+            if (value >> i) & 1 != 0 {
+                self.bytes[self.len / 8] |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+/// A QR code.
+///
+/// ```rust,ignore
+/// let qr = QrCode::encode(b"https://example.com/recovery", QrEcc::Medium)?;
+/// qr.draw(gop, 100, 100, 4)?;
+/// ```
+pub struct QrCode {
+    version: u8,
+    size: usize,
+    modules: [u8; MAX_QR_MODULE_BYTES],
+    function: [u8; MAX_QR_MODULE_BYTES],
+}
+
+impl QrCode {
+    /// Encode `data` at error correction level `ecc`. Fails with `Status::BadBufferSize` if it
+    /// does not fit in a version `MAX_QR_VERSION` code.
+    pub fn encode(data: &[u8], ecc: QrEcc) -> Result<QrCode, Status> {
+        let version = (1..=MAX_QR_VERSION)
+            .find(|&v| {
+                let count_bits = if v < 10 { 8 } else { 16 };
+                data.len() < 1 << count_bits && 4 + count_bits + data.len() * 8 <= data_codewords(v, ecc) * 8
+            })
+            .ok_or(Status::BadBufferSize)?;
+
+        let capacity = data_codewords(version, ecc);
+        let mut bits = BitBuffer { bytes: [0; MAX_QR_CODEWORDS], len: 0 };
+        bits.push(0b0100, 4);
+        bits.push(data.len() as u32, if version < 10 { 8 } else { 16 });
+        for &b in data {
+            bits.push(b as u32, 8);
+        }
+        bits.push(0, (capacity * 8 - bits.len).min(4));
+        bits.push(0, (8 - bits.len % 8) % 8);
+        for pad in [0xEC, 0x11].iter().cycle().take(capacity - bits.len / 8) {
+            bits.push(*pad, 8);
+        }
+
+        let mut qr = QrCode {
+            version,
+            size: version as usize * 4 + 17,
+            modules: [0; MAX_QR_MODULE_BYTES],
+            function: [0; MAX_QR_MODULE_BYTES],
+        };
+        qr.draw_function_patterns(ecc);
+        let mut codewords = [0u8; MAX_QR_CODEWORDS];
+        let len = qr.interleave(&bits.bytes[..capacity], ecc, &mut codewords);
+        qr.draw_codewords(&codewords[..len]);
+
+        // Use the mask with the lowest penalty, as decoders do best with it.
+        let mut best = (0, u32::MAX);
+        for mask in 0..8 {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(ecc, mask);
+            let penalty = qr.penalty();
+            if penalty < best.1 {
+                best = (mask, penalty);
+            }
+            qr.apply_mask(mask);
+        }
+        qr.apply_mask(best.0);
+        qr.draw_format_bits(ecc, best.0);
+        Ok(qr)
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Width and height of the code in modules, without a quiet zone.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module at (`x`, `y`) is dark. Modules outside the code are light.
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[(y * self.size + x) / 8] & (1 << ((y * self.size + x) % 8)) != 0
+    }
+
+    fn set(&mut self, x: usize, y: usize, dark: bool) {
+        let i = y * self.size + x;
+        if dark {
+            self.modules[i / 8] |= 1 << (i % 8);
+        } else {
+            self.modules[i / 8] &= !(1 << (i % 8));
+        }
+    }
+
+    fn is_function(&self, x: usize, y: usize) -> bool {
+        let i = y * self.size + x;
+        self.function[i / 8] & (1 << (i % 8)) != 0
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.set(x, y, dark);
+        let i = y * self.size + x;
+        self.function[i / 8] |= 1 << (i % 8);
+    }
+
+    fn draw_function_patterns(&mut self, ecc: QrEcc) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        for &(x, y) in &[(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4isize..=4 {
+                for dx in -4isize..=4 {
+                    let (xx, yy) = (x as isize + dx, y as isize + dy);
+                    if xx >= 0 && yy >= 0 && (xx as usize) < size && (yy as usize) < size {
+                        let dist = dx.abs().max(dy.abs());
+                        self.set_function(xx as usize, yy as usize, dist != 2 && dist != 4);
+                    }
+                }
+            }
+        }
+
+        let mut positions = [0usize; 7];
+        let num_align = if self.version == 1 { 0 } else { self.version as usize / 7 + 2 };
+        if num_align > 0 {
+            let v = self.version as usize;
+            let step = (v * 8 + num_align * 3 + 5) / (num_align * 4 - 4) * 2;
+            positions[0] = 6;
+            for (i, p) in positions.iter_mut().enumerate().take(num_align).skip(1) {
+                *p = size - 7 - (num_align - 1 - i) * step;
+            }
+        }
+        for i in 0..num_align {
+            for j in 0..num_align {
+                // The corners with finder patterns have no alignment pattern.
+                let last = num_align - 1;
+                if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                    continue;
+                }
+                for dy in -2isize..=2 {
+                    for dx in -2isize..=2 {
+                        let (x, y) = ((positions[i] as isize + dx) as usize, (positions[j] as isize + dy) as usize);
+                        self.set_function(x, y, dx.abs().max(dy.abs()) != 1);
+                    }
+                }
+            }
+        }
+
+        // Reserve the format areas; the real bits are drawn once the mask is chosen.
+        self.draw_format_bits(ecc, 0);
+
+        if self.version >= 7 {
+            let mut rem = self.version as u32;
+            for _ in 0..12 {
+                rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+            }
+            let bits = (self.version as u32) << 12 | rem;
+            for i in 0..18 {
+                let dark = (bits >> i) & 1 != 0;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set_function(a, b, dark);
+                self.set_function(b, a, dark);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, ecc: QrEcc, mask: u32) {
+        let data = ecc.format_bits() << 3 | mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = (data << 10 | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        let size = self.size;
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Split `data` into blocks, add error correction to each and interleave them into `out`,
+    /// returning the number of codewords.
+    fn interleave(&self, data: &[u8], ecc: QrEcc, out: &mut [u8; MAX_QR_CODEWORDS]) -> usize {
+        let (e, v) = (ecc.index(), self.version as usize);
+        let num_blocks = NUM_ERROR_CORRECTION_BLOCKS[e][v] as usize;
+        let ecc_len = ECC_CODEWORDS_PER_BLOCK[e][v] as usize;
+        let raw_codewords = raw_data_modules(self.version) / 8;
+        let num_short_blocks = num_blocks - raw_codewords % num_blocks;
+        let short_data_len = raw_codewords / num_blocks - ecc_len;
+
+        // Blocks after the short ones have one more data codeword.
+        let block_start = |i: usize| i * short_data_len + i.saturating_sub(num_short_blocks);
+        let block_len = |i: usize| short_data_len + if i < num_short_blocks { 0 } else { 1 };
+
+        let mut ecc_bytes = [0u8; MAX_QR_CODEWORDS];
+        for i in 0..num_blocks {
+            let start = block_start(i);
+            reed_solomon(&data[start..start + block_len(i)], &mut ecc_bytes[i * ecc_len..(i + 1) * ecc_len]);
+        }
+
+        let mut n = 0;
+        for j in 0..=short_data_len {
+            for i in 0..num_blocks {
+                if j < block_len(i) {
+                    out[n] = data[block_start(i) + j];
+                    n += 1;
+                }
+            }
+        }
+        for j in 0..ecc_len {
+            for i in 0..num_blocks {
+                out[n] = ecc_bytes[i * ecc_len + j];
+                n += 1;
+            }
+        }
+        n
+    }
+
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size as isize;
+        let mut i = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vert } else { vert } as usize;
+                    if !self.is_function(x, y) && i < codewords.len() * 8 {
+                        self.set(x, y, (codewords[i / 8] >> (7 - i % 8)) & 1 != 0);
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    /// Invert the data modules selected by `mask`. Applying a mask twice removes it.
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if invert && !self.is_function(x, y) {
+                    let dark = self.get(x, y);
+                    self.set(x, y, !dark);
+                }
+            }
+        }
+    }
+
+    /// The penalty score the standard uses to choose between masks.
+    fn penalty(&self) -> u32 {
+        let size = self.size;
+        let mut result = 0;
+
+        // Runs of five or more modules of one colour, and patterns that look like finders.
+        for transpose in [false, true] {
+            let at = |a: usize, b: usize| if transpose { self.get(b, a) } else { self.get(a, b) };
+            for b in 0..size {
+                let mut run = 0;
+                for a in 0..size {
+                    run = if a > 0 && at(a, b) == at(a - 1, b) { run + 1 } else { 1 };
+                    if run == 5 {
+                        result += 3;
+                    } else if run > 5 {
+                        result += 1;
+                    }
+                }
+
+                // Light modules beyond the edge count as part of the pattern.
+                for a in 0..size + 4 {
+                    let module = |i: usize| (a + i).checked_sub(4).is_some_and(|p| p < size && at(p, b));
+                    let pattern = (0..11).fold(0u32, |acc, i| acc << 1 | module(i) as u32);
+                    if pattern == 0b10111010000 || pattern == 0b00001011101 {
+                        result += 40;
+                    }
+                }
+            }
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = self.get(x, y);
+                if c == self.get(x + 1, y) && c == self.get(x, y + 1) && c == self.get(x + 1, y + 1) {
+                    result += 3;
+                }
+            }
+        }
+
+        let total = (size * size) as i64;
+        let dark = (0..size).flat_map(|y| (0..size).map(move |x| (x, y))).filter(|&(x, y)| self.get(x, y)).count() as i64;
+        let k = ((dark * 20 - total * 10).abs() + total - 1) / total - 1;
+        result + k as u32 * 10
+    }
+
+    /// Draw the code with its top left corner, including the quiet zone, at (`x`, `y`), each
+    /// module `scale` pixels square.
+    pub fn draw(&self, gop: &GraphicsOutputProtocol, x: usize, y: usize, scale: usize) -> Result<(), Status> {
+        let light = BltPixel { blue: 0xFF, green: 0xFF, red: 0xFF, reserved: 0 };
+        let dark = BltPixel::default();
+        let outer = (self.size + QR_QUIET_ZONE * 2) * scale;
+        gop.fill_rect(x, y, outer, outer, light)?;
+
+        let origin = QR_QUIET_ZONE * scale;
+        for my in 0..self.size {
+            for mx in 0..self.size {
+                if self.get(mx, my) {
+                    gop.fill_rect(x + origin + mx * scale, y + origin + my * scale, scale, scale, dark)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the code as text, two full block characters for each light module, for consoles
+    /// with light text on a dark background. Each line ends with `"\r\n"`.
+    pub fn render_text<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        let quiet = QR_QUIET_ZONE / 2;
+        for y in 0..self.size + quiet * 2 {
+            for x in 0..self.size + quiet * 2 {
+                let dark = x >= quiet && y >= quiet && self.get(x - quiet, y - quiet);
+                w.write_str(if dark { "  " } else { "\u{2588}\u{2588}" })?;
+            }
+            w.write_str("\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn qr_reed_solomon() {
+    // The error correction codewords of "HELLO WORLD" as a 1-M code in alphanumeric mode.
+    let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+    let mut ecc = [0u8; 10];
+    reed_solomon(&data, &mut ecc);
+    assert_eq!(ecc, [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+}
+
+#[test]
+fn qr_function_patterns() {
+    let qr = QrCode::encode(b"https://example.com", QrEcc::Medium).unwrap();
+    assert_eq!((qr.version(), qr.size()), (2, 25));
+    // Finder pattern corners, their separators and the dark module.
+    assert!(qr.get(0, 0) && qr.get(24, 0) && qr.get(0, 24) && qr.get(8, 17));
+    assert!(!qr.get(7, 7) && !qr.get(17, 7) && !qr.get(7, 17));
+    // The timing patterns alternate between the finders.
+    for i in 8..17 {
+        assert_eq!(qr.get(i, 6), i % 2 == 0);
+        assert_eq!(qr.get(6, i), i % 2 == 0);
+    }
+    assert!(!qr.get(25, 0) && !qr.get(0, 25));
+}
+
+#[test]
+fn qr_versions() {
+    // The most bytes a version 1 code holds at each level, and one more.
+    for &(ecc, most) in &[(QrEcc::Low, 17), (QrEcc::Medium, 14), (QrEcc::Quartile, 11), (QrEcc::High, 7)] {
+        assert_eq!(QrCode::encode(&[0; 32][..most], ecc).unwrap().version(), 1);
+        assert_eq!(QrCode::encode(&[0; 32][..most + 1], ecc).unwrap().version(), 2);
+    }
+}
+
+#[test]
+fn qr_too_long() {
+    for &(ecc, most) in &[(QrEcc::Low, 271), (QrEcc::Medium, 213), (QrEcc::Quartile, 151), (QrEcc::High, 119)] {
+        assert_eq!(QrCode::encode(&[0; 272][..most], ecc).unwrap().version(), MAX_QR_VERSION);
+        assert_eq!(QrCode::encode(&[0; 272][..most + 1], ecc).err(), Some(Status::BadBufferSize));
+    }
+}
+
+#[test]
+fn qr_render_text() {
+    struct Buf([u8; 8192], usize);
+    impl fmt::Write for Buf {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0[self.1..self.1 + s.len()].copy_from_slice(s.as_bytes());
+            self.1 += s.len();
+            Ok(())
+        }
+    }
+
+    let qr = QrCode::encode(b"A", QrEcc::Low).unwrap();
+    let mut text = Buf([0; 8192], 0);
+    qr.render_text(&mut text).unwrap();
+    let text = ::core::str::from_utf8(&text.0[..text.1]).unwrap();
+
+    // A version 1 code and half the quiet zone on each side.
+    let width = 21 + QR_QUIET_ZONE;
+    assert_eq!(text.lines().count(), width);
+    let mut lines = text.split_terminator("\r\n");
+    assert!(lines.next().unwrap().chars().eq(::core::iter::repeat('\u{2588}').take(width * 2)));
+    // The top left finder pattern starts dark after the quiet zone.
+    assert!(lines.nth(1).unwrap().starts_with("\u{2588}\u{2588}\u{2588}\u{2588}  "));
+}
@@ -0,0 +1,191 @@
+//! Random bytes from the firmware's RNG protocol, falling back to a software generator on
+//! firmware without one, so features such as KASLR degrade rather than fail.
+//!
+//! The fallback is SHA-256 in counter mode over a seed gathered from cycle counter jitter, the
+//! monotonic counter, the time and the memory map. That is hard to predict from outside the
+//! machine but is not a vetted entropy source: results from it are marked
+//! `RandomQuality::Fallback`, and anything producing long-term keys should refuse them.
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+use core::arch::asm;
+use core::{mem, ptr};
+
+use base::Status;
+use memmap::MemoryMap;
+use protocol::{self, RngProtocol};
+use task::TplCell;
+use util::{Sha256, SHA256_LEN};
+
+/// Where random bytes came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomQuality {
+    /// The firmware's RNG protocol.
+    Firmware,
+    /// The software fallback, seeded from timing and platform state. Good enough to randomise
+    /// layouts; not for keys.
+    Fallback,
+}
+
+/// Samples of cycle counter jitter taken for the fallback seed.
+const JITTER_SAMPLES: usize = 256;
+
+/// The fallback generator: SHA-256 of the seed and a block counter.
+struct FallbackRng {
+    seed: [u8; SHA256_LEN],
+    counter: u64,
+}
+
+impl FallbackRng {
+    fn from_seed(seed: [u8; SHA256_LEN]) -> FallbackRng {
+        FallbackRng { seed, counter: 0 }
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(SHA256_LEN) {
+            let mut hasher = Sha256::new();
+            hasher.update(&self.seed);
+            hasher.update(&self.counter.to_le_bytes());
+            self.counter += 1;
+            let block = hasher.finish();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+
+        // Move the seed on, so earlier output can't be recomputed from the state.
+        let mut hasher = Sha256::new();
+        hasher.update(b"reseed");
+        hasher.update(&self.seed);
+        hasher.update(&self.counter.to_le_bytes());
+        hasher.update(&cycle_counter().to_le_bytes());
+        self.seed = hasher.finish();
+    }
+}
+
+/// Shared with callbacks that want random bytes, which must not see the counter before an
+/// interrupted fill has moved it on, or they would get the same bytes.
+static FALLBACK: TplCell<Option<FallbackRng>> = TplCell::new(None);
+
+/// The processor's cycle counter where there is one to read, or 0.
+fn cycle_counter() -> u64 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    unsafe {
+        let (low, high): (u32, u32);
+        asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack));
+        (high as u64) << 32 | low as u64
+    }
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        let count: u64;
+        asm!("mrs {}, cntvct_el0", out(reg) count, options(nomem, nostack));
+        count
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    0
+}
+
+/// Gather the fallback seed from whatever varies between boots and machines.
+fn gather_seed() -> [u8; SHA256_LEN] {
+    let st = ::get_system_table();
+    let bs = st.boot_services();
+    let mut hasher = Sha256::new();
+
+    // The time a short stall takes, in cycles, varies with interrupts, caches and the timer.
+    for _ in 0..JITTER_SAMPLES {
+        let start = cycle_counter();
+        bs.stall(1);
+        hasher.update(&cycle_counter().wrapping_sub(start).to_le_bytes());
+    }
+
+    if let Ok(count) = bs.next_monotonic_count() {
+        hasher.update(&count.to_le_bytes());
+    }
+    if let Ok(time) = st.runtime_services().get_time() {
+        hasher.update(&[time.second, time.minute, time.hour, time.day, time.month]);
+        hasher.update(&time.year.to_le_bytes());
+        hasher.update(&time.nanosecond.to_le_bytes());
+    }
+
+    // The memory map differs with installed memory, devices and allocations made so far.
+    if let Ok(map) = MemoryMap::get() {
+        for descriptor in map.iter() {
+            hasher.update(&descriptor.physical_start().to_le_bytes());
+            hasher.update(&descriptor.physical_end().to_le_bytes());
+            hasher.update(&(descriptor.memory_type() as u32).to_le_bytes());
+        }
+    }
+
+    // Where the image and the stack ended up.
+    hasher.update(&protocol::get_current_image().image_base.to_le_bytes());
+    let local = 0u8;
+    hasher.update(&(&local as *const u8 as usize).to_le_bytes());
+    hasher.update(&cycle_counter().to_le_bytes());
+    hasher.finish()
+}
+
+/// Fill `buf` from the fallback generator, seeding it first if needed.
+fn fill_fallback(buf: &mut [u8]) {
+    // The seed takes the memory map, which can't be read with callbacks held off. A callback
+    // seeding it meanwhile only means this seed goes unused.
+    if FALLBACK.with(|rng| rng.is_none()) {
+        let seed = gather_seed();
+        FALLBACK.with(|rng| {
+            rng.get_or_insert_with(|| FallbackRng::from_seed(seed));
+        });
+    }
+    FALLBACK.with(|rng| {
+        if let Some(rng) = rng {
+            rng.fill(buf);
+        }
+    });
+}
+
+/// Fill `buf` with random bytes, from the firmware's RNG protocol if it has one and the
+/// software fallback otherwise. Which was used is returned, so callers can decide whether
+/// fallback bytes are good enough.
+///
+/// ```rust,ignore
+/// let mut slide = [0u8; 8];
+/// if fill_random(&mut slide) == RandomQuality::Fallback {
+///     println!("warning: no firmware RNG, KASLR is weaker");
+/// }
+/// ```
+pub fn fill_random(buf: &mut [u8]) -> RandomQuality {
+    let bs = ::get_system_table().boot_services();
+    if let Ok(rng) = bs.locate_protocol::<RngProtocol>(ptr::null()) {
+        if rng.get_rng(buf).is_ok() {
+            return RandomQuality::Firmware;
+        }
+    }
+    fill_fallback(buf);
+    RandomQuality::Fallback
+}
+
+/// Fill `buf` from the firmware's RNG protocol only, failing with `Status::NotFound` if the
+/// firmware has none, for callers that can't accept the fallback.
+pub fn fill_random_firmware(buf: &mut [u8]) -> Result<(), Status> {
+    let bs = ::get_system_table().boot_services();
+    bs.locate_protocol::<RngProtocol>(ptr::null())?.get_rng(buf)
+}
+
+/// A random `u64`, with where it came from.
+pub fn random_u64() -> (u64, RandomQuality) {
+    let mut bytes = [0u8; mem::size_of::<u64>()];
+    let quality = fill_random(&mut bytes);
+    (u64::from_le_bytes(bytes), quality)
+}
+
+#[test]
+fn fallback_rng_output() {
+    let mut a = FallbackRng::from_seed([7; SHA256_LEN]);
+    let mut b = FallbackRng::from_seed([7; SHA256_LEN]);
+
+    // The first output depends only on the seed; later ones also on the cycle counter.
+    let (mut x, mut y) = ([0u8; 40], [0u8; 40]);
+    a.fill(&mut x);
+    b.fill(&mut y);
+    assert_eq!(&x[..], &y[..]);
+    assert_ne!(&x[..SHA256_LEN], &x[8..SHA256_LEN + 8]);
+
+    let mut z = [0u8; 40];
+    a.fill(&mut z);
+    assert_ne!(&x[..], &z[..]);
+}
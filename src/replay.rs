@@ -0,0 +1,381 @@
+//! Recording and replaying keystrokes, so boot menus and other console UIs can be driven
+//! deterministically in QEMU tests.
+//!
+//! `InputRecorder` wraps any `SimpleTextInput` and remembers each key with the time since the
+//! previous one. The recording can be saved to a variable or a file, and `InputReplay` later
+//! plays it back through the same trait, with the same pauses.
+
+use core::cell::{Cell, RefCell};
+use core::ptr;
+
+use base::{Event, Status};
+use console::{InputKey, SimpleTextInput};
+use event::TimerDelay;
+use guid::Guid;
+use protocol::{FileProtocol, EFI_FILE_MODE_READ};
+use runtimeservices::{EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_NON_VOLATILE};
+use util::wire;
+
+/// Most keys a recording holds. Later keys are passed through but not recorded.
+pub const MAX_RECORDED_KEYS: usize = 512;
+
+/// Resolution of recorded delays, in milliseconds.
+pub const RECORDING_TICK_MS: u32 = 10;
+
+const RECORDING_MAGIC: &[u8; 4] = b"KREC";
+const RECORDING_HEADER_SIZE: usize = 8;
+const RECORDED_KEY_SIZE: usize = 8;
+
+/// Size of the encoding of a recording of `MAX_RECORDED_KEYS` keys.
+pub const MAX_RECORDING_SIZE: usize = RECORDING_HEADER_SIZE + MAX_RECORDED_KEYS * RECORDED_KEY_SIZE;
+
+/// A key, and how long after the previous key (or the start of the recording) it was read.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordedKey {
+    pub delay_ms: u32,
+    pub key: InputKey,
+}
+
+const NO_KEY: RecordedKey = RecordedKey { delay_ms: 0, key: InputKey { scan_code: 0, unicode_char: 0 } };
+
+/// Encode `keys` into `buf`, returning the number of bytes used. The format is the magic
+/// `"KREC"`, a little-endian u16 version (1) and key count, then for each key a u32 delay in
+/// milliseconds, the scan code and the character.
+pub fn encode_recording(keys: &[RecordedKey], buf: &mut [u8]) -> Result<usize, Status> {
+    if keys.len() > MAX_RECORDED_KEYS {
+        return Err(Status::InvalidParameter);
+    }
+    let len = RECORDING_HEADER_SIZE + keys.len() * RECORDED_KEY_SIZE;
+    if buf.len() < len {
+        return Err(Status::BufferTooSmall);
+    }
+
+    buf[..4].copy_from_slice(RECORDING_MAGIC);
+    wire::write_u16(buf, 4, 1)?;
+    wire::write_u16(buf, 6, keys.len() as u16)?;
+    for (i, k) in keys.iter().enumerate() {
+        let offset = RECORDING_HEADER_SIZE + i * RECORDED_KEY_SIZE;
+        wire::write_u32(buf, offset, k.delay_ms)?;
+        wire::write_u16(buf, offset + 4, k.key.scan_code)?;
+        wire::write_u16(buf, offset + 6, k.key.unicode_char)?;
+    }
+    Ok(len)
+}
+
+/// Decode a recording made by `encode_recording` into `keys`, returning the number of keys.
+/// Fails with `Status::InvalidParameter` if `data` is not a recording,
+/// `Status::IncompatibleVersion` if it is a newer one, and `Status::BufferTooSmall` if `keys`
+/// cannot hold it.
+pub fn decode_recording(data: &[u8], keys: &mut [RecordedKey]) -> Result<usize, Status> {
+    let bad = |_| Status::InvalidParameter;
+    if data.get(..4) != Some(&RECORDING_MAGIC[..]) {
+        return Err(Status::InvalidParameter);
+    }
+    if wire::read_u16(data, 4).map_err(bad)? != 1 {
+        return Err(Status::IncompatibleVersion);
+    }
+    let count = wire::read_u16(data, 6).map_err(bad)? as usize;
+    if count > keys.len() {
+        return Err(Status::BufferTooSmall);
+    }
+
+    for (i, k) in keys[..count].iter_mut().enumerate() {
+        let offset = RECORDING_HEADER_SIZE + i * RECORDED_KEY_SIZE;
+        *k = RecordedKey {
+            delay_ms: wire::read_u32(data, offset).map_err(bad)?,
+            key: InputKey {
+                scan_code: wire::read_u16(data, offset + 4).map_err(bad)?,
+                unicode_char: wire::read_u16(data, offset + 6).map_err(bad)?,
+            },
+        };
+    }
+    Ok(count)
+}
+
+fn timer_event(delay_type: TimerDelay, delay: u64) -> Result<Event, Status> {
+    let bs = ::get_system_table().boot_services();
+    let event = bs.create_timer_event()?;
+    let status = bs.set_timer(event, delay_type, delay);
+    if status != Status::Success {
+        bs.close_event(event);
+        return Err(status);
+    }
+    Ok(event)
+}
+
+/// Records the keys read from an input.
+///
+/// ```rust,ignore
+/// let recorder = InputRecorder::new(&console)?;
+/// run_menu(&recorder)?;
+/// recorder.save_variable("MenuKeys", &MY_VENDOR_GUID)?;
+/// ```
+///
+/// Delays are measured with a 10 ms periodic timer. A blocking `read_key` counts every tick;
+/// polling with `read_key_async` counts at most one tick per call, so it is only accurate when
+/// polled at least every 10 ms.
+pub struct InputRecorder<'a, I: SimpleTextInput> {
+    input: &'a I,
+    tick: Event,
+    ticks: Cell<u32>,
+    keys: RefCell<([RecordedKey; MAX_RECORDED_KEYS], usize)>,
+}
+
+impl<'a, I: SimpleTextInput> InputRecorder<'a, I> {
+    pub fn new(input: &'a I) -> Result<InputRecorder<'a, I>, Status> {
+        // Timer periods are in 100 ns units.
+        let tick = timer_event(TimerDelay::Periodic, RECORDING_TICK_MS as u64 * 10_000)?;
+        Ok(InputRecorder {
+            input,
+            tick,
+            ticks: Cell::new(0),
+            keys: RefCell::new(([NO_KEY; MAX_RECORDED_KEYS], 0)),
+        })
+    }
+
+    fn count_tick(&self) {
+        if ::get_system_table().boot_services().check_event(self.tick) == Status::Success {
+            self.ticks.set(self.ticks.get().saturating_add(1));
+        }
+    }
+
+    fn record(&self, key: InputKey) {
+        let mut keys = self.keys.borrow_mut();
+        let (keys, len) = &mut *keys;
+        if *len < MAX_RECORDED_KEYS {
+            keys[*len] = RecordedKey { delay_ms: self.ticks.get().saturating_mul(RECORDING_TICK_MS), key };
+            *len += 1;
+        }
+        self.ticks.set(0);
+    }
+
+    /// The keys recorded so far.
+    pub fn len(&self) -> usize {
+        self.keys.borrow().1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Encode the recording into `buf`, which needs at most `MAX_RECORDING_SIZE` bytes.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, Status> {
+        let keys = self.keys.borrow();
+        encode_recording(&keys.0[..keys.1], buf)
+    }
+
+    /// Save the recording as the non-volatile variable `name` under `vendor`.
+    pub fn save_variable(&self, name: &str, vendor: &Guid) -> Result<(), Status> {
+        let mut buf = [0u8; MAX_RECORDING_SIZE];
+        let len = self.encode(&mut buf)?;
+        ::get_system_table()
+            .runtime_services()
+            .set_variable(name, vendor, EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS, &buf[..len])
+    }
+
+    /// Save the recording as `path` relative to the directory `dir`, replacing any existing
+    /// file.
+    pub fn save_file<P: AsRef<str>>(&self, dir: &FileProtocol, path: P) -> Result<(), Status> {
+        let mut buf = [0u8; MAX_RECORDING_SIZE];
+        let len = self.encode(&mut buf)?;
+
+        let file = dir.create_path(path)?;
+        let result = file.write_all(&buf[..len]);
+        file.close();
+        result
+    }
+}
+
+impl<'a, I: SimpleTextInput> SimpleTextInput for InputRecorder<'a, I> {
+    fn read_key_async(&self) -> Result<InputKey, Status> {
+        self.count_tick();
+        let key = self.input.read_key_async()?;
+        self.record(key);
+        Ok(key)
+    }
+
+    fn read_key(&self) -> Result<InputKey, Status> {
+        let bs = ::get_system_table().boot_services();
+        loop {
+            match self.input.read_key_async() {
+                Ok(key) => {
+                    self.record(key);
+                    return Ok(key);
+                }
+                Err(Status::NotReady) => {}
+                Err(e) => return Err(e),
+            }
+            // Wait for the next tick rather than the key, so every tick is counted.
+            bs.wait_for_event(&[self.tick])?;
+            self.ticks.set(self.ticks.get().saturating_add(1));
+        }
+    }
+}
+
+impl<'a, I: SimpleTextInput> Drop for InputRecorder<'a, I> {
+    fn drop(&mut self) {
+        ::get_system_table().boot_services().close_event(self.tick);
+    }
+}
+
+/// Plays back a recording through `SimpleTextInput`, then reads from `fallback` once it is
+/// exhausted.
+///
+/// ```rust,ignore
+/// let replay = InputReplay::from_variable("MenuKeys", &MY_VENDOR_GUID, &console)?;
+/// run_menu(&replay)?;
+/// ```
+pub struct InputReplay<'a, I: SimpleTextInput> {
+    fallback: &'a I,
+    keys: [RecordedKey; MAX_RECORDED_KEYS],
+    len: usize,
+    next: Cell<usize>,
+    /// Signalled when the next key is due; null before the first read.
+    due: Cell<Event>,
+}
+
+impl<'a, I: SimpleTextInput> InputReplay<'a, I> {
+    /// Replay the recording `data`, as made by `InputRecorder::encode`.
+    pub fn new(data: &[u8], fallback: &'a I) -> Result<InputReplay<'a, I>, Status> {
+        let mut keys = [NO_KEY; MAX_RECORDED_KEYS];
+        let len = decode_recording(data, &mut keys)?;
+        Ok(InputReplay {
+            fallback,
+            keys,
+            len,
+            next: Cell::new(0),
+            due: Cell::new(Event(ptr::null_mut())),
+        })
+    }
+
+    /// Replay the recording saved with `InputRecorder::save_variable`.
+    pub fn from_variable(name: &str, vendor: &Guid, fallback: &'a I) -> Result<InputReplay<'a, I>, Status> {
+        let mut buf = [0u8; MAX_RECORDING_SIZE];
+        let (len, _) = ::get_system_table().runtime_services().get_variable(name, vendor, &mut buf)?;
+        InputReplay::new(&buf[..len], fallback)
+    }
+
+    /// Replay the recording saved with `InputRecorder::save_file`.
+    pub fn from_file<P: AsRef<str>>(dir: &FileProtocol, path: P, fallback: &'a I) -> Result<InputReplay<'a, I>, Status> {
+        let mut buf = [0u8; MAX_RECORDING_SIZE];
+        let file = dir.open_path(path, EFI_FILE_MODE_READ)?;
+        let mut len = 0;
+        let result = loop {
+            match file.read(&mut buf[len..]) {
+                Ok(0) => break Ok(()),
+                Ok(n) => len += n,
+                Err(e) => break Err(e),
+            }
+            if len == buf.len() {
+                break Ok(());
+            }
+        };
+        file.close();
+        result?;
+        InputReplay::new(&buf[..len], fallback)
+    }
+
+    /// Whether every recorded key has been read.
+    pub fn finished(&self) -> bool {
+        self.next.get() >= self.len
+    }
+
+    /// The event signalled when the next key is due, starting its timer on first use.
+    fn due_event(&self) -> Result<Event, Status> {
+        if self.due.get().0.is_null() {
+            let delay = self.keys[self.next.get()].delay_ms as u64 * 10_000;
+            self.due.set(timer_event(TimerDelay::Relative, delay.max(1))?);
+        }
+        Ok(self.due.get())
+    }
+
+    fn take_key(&self) -> InputKey {
+        let bs = ::get_system_table().boot_services();
+        bs.close_event(self.due.replace(Event(ptr::null_mut())));
+        let key = self.keys[self.next.get()].key;
+        self.next.set(self.next.get() + 1);
+        key
+    }
+}
+
+impl<'a, I: SimpleTextInput> SimpleTextInput for InputReplay<'a, I> {
+    fn read_key_async(&self) -> Result<InputKey, Status> {
+        if self.finished() {
+            return self.fallback.read_key_async();
+        }
+
+        match ::get_system_table().boot_services().check_event(self.due_event()?) {
+            Status::Success => Ok(self.take_key()),
+            Status::NotReady => Err(Status::NotReady),
+            e => Err(e),
+        }
+    }
+
+    fn read_key(&self) -> Result<InputKey, Status> {
+        if self.finished() {
+            return self.fallback.read_key();
+        }
+
+        ::get_system_table().boot_services().wait_for_event(&[self.due_event()?])?;
+        Ok(self.take_key())
+    }
+}
+
+impl<'a, I: SimpleTextInput> Drop for InputReplay<'a, I> {
+    fn drop(&mut self) {
+        let due = self.due.get();
+        if !due.0.is_null() {
+            ::get_system_table().boot_services().close_event(due);
+        }
+    }
+}
+
+#[cfg(test)]
+const TEST_KEYS: [RecordedKey; 2] = [
+    RecordedKey { delay_ms: 1500, key: InputKey { scan_code: 0x02, unicode_char: 0 } },
+    RecordedKey { delay_ms: 20, key: InputKey { scan_code: 0, unicode_char: 0x0D } },
+];
+
+#[test]
+fn recording_round_trip() {
+    let mut buf = [0u8; 64];
+    assert_eq!(encode_recording(&TEST_KEYS, &mut buf), Ok(24));
+    assert_eq!(&buf[..8], b"KREC\x01\x00\x02\x00");
+
+    let mut decoded = [NO_KEY; 4];
+    assert_eq!(decode_recording(&buf[..24], &mut decoded), Ok(2));
+    assert_eq!((decoded[0].delay_ms, decoded[0].key.scan_code, decoded[0].key.unicode_char), (1500, 0x02, 0));
+    assert_eq!((decoded[1].delay_ms, decoded[1].key.scan_code, decoded[1].key.unicode_char), (20, 0, 0x0D));
+    assert_eq!(encode_recording(&[], &mut buf), Ok(RECORDING_HEADER_SIZE));
+    assert_eq!(decode_recording(&buf[..RECORDING_HEADER_SIZE], &mut decoded), Ok(0));
+}
+
+#[test]
+fn recording_encode_errors() {
+    let mut buf = [0u8; 64];
+    assert_eq!(encode_recording(&TEST_KEYS, &mut buf[..23]), Err(Status::BufferTooSmall));
+    let keys = [NO_KEY; MAX_RECORDED_KEYS + 1];
+    let mut big = [0u8; MAX_RECORDING_SIZE + RECORDED_KEY_SIZE];
+    assert_eq!(encode_recording(&keys, &mut big), Err(Status::InvalidParameter));
+    assert_eq!(encode_recording(&keys[..MAX_RECORDED_KEYS], &mut big), Ok(MAX_RECORDING_SIZE));
+}
+
+#[test]
+fn recording_decode_errors() {
+    let mut buf = [0u8; 64];
+    encode_recording(&TEST_KEYS, &mut buf).unwrap();
+    let mut decoded = [NO_KEY; 4];
+
+    assert_eq!(decode_recording(&buf[..24], &mut decoded[..1]), Err(Status::BufferTooSmall));
+    // Cut off in the header, and in the last key.
+    assert_eq!(decode_recording(&buf[..3], &mut decoded), Err(Status::InvalidParameter));
+    assert_eq!(decode_recording(&buf[..6], &mut decoded), Err(Status::InvalidParameter));
+    assert_eq!(decode_recording(&buf[..20], &mut decoded), Err(Status::InvalidParameter));
+
+    let mut other = buf;
+    other[0] = b'X';
+    assert_eq!(decode_recording(&other[..24], &mut decoded), Err(Status::InvalidParameter));
+    let mut newer = buf;
+    newer[4] = 2;
+    assert_eq!(decode_recording(&newer[..24], &mut decoded), Err(Status::IncompatibleVersion));
+}
@@ -1,11 +1,133 @@
-use core::ptr;
+use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
+use core::{fmt, ptr, slice, str};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
 use void::NotYetDef;
 use base::{Status, Time, TimeCapabilities, MemoryDescriptor};
 use guid::Guid;
+use systemtable::boot_services_available;
 use table::TableHeader;
+use validate::validate_variable_name;
+
+/// GUID for the namespace of architecturally defined variables, such as BootOrder and
+/// PlatformLang.
+pub static EFI_GLOBAL_VARIABLE_GUID: Guid = Guid(0x8BE4DF61, 0x93CA, 0x11D2, [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C]);
+
+// bitflags 0.9 expands to the deprecated `try!`, so the flags get a module of their own.
+#[allow(deprecated)]
+mod variable_attributes {
+    bitflags! {
+        /// Attributes of a UEFI variable.
+        pub struct VariableAttributes: u32 {
+            const EFI_VARIABLE_NON_VOLATILE = 0x00000001;
+            const EFI_VARIABLE_BOOTSERVICE_ACCESS = 0x00000002;
+            const EFI_VARIABLE_RUNTIME_ACCESS = 0x00000004;
+            const EFI_VARIABLE_HARDWARE_ERROR_RECORD = 0x00000008;
+            const EFI_VARIABLE_AUTHENTICATED_WRITE_ACCESS = 0x00000010;
+            const EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS = 0x00000020;
+            const EFI_VARIABLE_APPEND_WRITE = 0x00000040;
+        }
+    }
+}
+pub use self::variable_attributes::*;
+
+/// GUID of the EFI_RT_PROPERTIES_TABLE in the system configuration table
+pub static EFI_RT_PROPERTIES_TABLE_GUID: Guid = Guid(0xEB66918A, 0x7EEF, 0x402A, [0x84, 0x2E, 0x93, 0x1D, 0x21, 0xC3, 0x8A, 0xE9]);
+
+#[allow(deprecated)]
+mod runtime_services_supported {
+    bitflags! {
+        /// Runtime services the firmware still supports after ExitBootServices, as published in the
+        /// EFI_RT_PROPERTIES_TABLE.
+        pub struct RuntimeServicesSupported: u32 {
+            const EFI_RT_SUPPORTED_GET_TIME = 0x0001;
+            const EFI_RT_SUPPORTED_SET_TIME = 0x0002;
+            const EFI_RT_SUPPORTED_GET_WAKEUP_TIME = 0x0004;
+            const EFI_RT_SUPPORTED_SET_WAKEUP_TIME = 0x0008;
+            const EFI_RT_SUPPORTED_GET_VARIABLE = 0x0010;
+            const EFI_RT_SUPPORTED_GET_NEXT_VARIABLE_NAME = 0x0020;
+            const EFI_RT_SUPPORTED_SET_VARIABLE = 0x0040;
+            const EFI_RT_SUPPORTED_SET_VIRTUAL_ADDRESS_MAP = 0x0080;
+            const EFI_RT_SUPPORTED_CONVERT_POINTER = 0x0100;
+            const EFI_RT_SUPPORTED_GET_NEXT_HIGH_MONOTONIC_COUNT = 0x0200;
+            const EFI_RT_SUPPORTED_RESET_SYSTEM = 0x0400;
+            const EFI_RT_SUPPORTED_UPDATE_CAPSULE = 0x0800;
+            const EFI_RT_SUPPORTED_QUERY_CAPSULE_CAPABILITIES = 0x1000;
+            const EFI_RT_SUPPORTED_QUERY_VARIABLE_INFO = 0x2000;
+        }
+    }
+}
+pub use self::runtime_services_supported::*;
+
+/// Type for EFI_RT_PROPERTIES_TABLE.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct RtPropertiesTable {
+    pub version: u16,
+    pub length: u16,
+    pub runtime_services_supported: u32,
+}
+
+/// Whether `enter_runtime` has been called, after which `RUNTIME_SUPPORTED` holds the support
+/// it recorded.
+static BOOT_SERVICES_EXITED: AtomicBool = AtomicBool::new(false);
+
+/// Bits of the `RuntimeServicesSupported` recorded by `enter_runtime`.
+static RUNTIME_SUPPORTED: AtomicU32 = AtomicU32::new(0);
+
+/// Support recorded by `enter_runtime`, once boot services are gone.
+fn recorded_support() -> Option<RuntimeServicesSupported> {
+    if !BOOT_SERVICES_EXITED.load(Ordering::Acquire) {
+        return None;
+    }
+    Some(RuntimeServicesSupported::from_bits_truncate(RUNTIME_SUPPORTED.load(Ordering::Relaxed)))
+}
+
+/// The runtime services the firmware supports after ExitBootServices. Firmware without an
+/// EFI_RT_PROPERTIES_TABLE supports all of them.
+///
+/// This can be called before or after ExitBootServices, as long as the exit went through
+/// `BootServices::exit_boot_services`.
+pub fn runtime_services_supported() -> RuntimeServicesSupported {
+    if let Some(supported) = recorded_support() {
+        return supported;
+    }
+
+    match ::get_system_table().configuration_table(&EFI_RT_PROPERTIES_TABLE_GUID) {
+        Some(table) if !table.is_null() => {
+            let table = unsafe { &*(table as *const RtPropertiesTable) };
+            RuntimeServicesSupported::from_bits_truncate(table.runtime_services_supported)
+        }
+        _ => RuntimeServicesSupported::all(),
+    }
+}
+
+/// Record that boot services have been exited, so the runtime wrappers start checking
+/// `supported` before calling into the firmware.
+pub(crate) fn enter_runtime(supported: RuntimeServicesSupported) {
+    RUNTIME_SUPPORTED.store(supported.bits(), Ordering::Relaxed);
+    BOOT_SERVICES_EXITED.store(true, Ordering::Release);
+}
+
+/// Whether `enter_runtime` has been called.
+pub(crate) fn boot_services_exited() -> bool {
+    BOOT_SERVICES_EXITED.load(Ordering::Acquire)
+}
+
+/// Fail with `Unsupported` if boot services have been exited and the firmware says `service` is
+/// no longer available.
+fn check_supported(service: RuntimeServicesSupported) -> Result<(), Status> {
+    match recorded_support() {
+        Some(supported) if !supported.contains(service) => Err(Status::Unsupported),
+        _ => Ok(()),
+    }
+}
+
+/// Longest variable name, in characters, accepted by the variable wrappers.
+pub const MAX_VARIABLE_NAME: usize = 128;
 
 /// Reset type passed to RuntimeServices.reset_system
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub enum ResetType {
     Cold = 0,
@@ -26,13 +148,24 @@ pub struct RuntimeServices {
     set_virtual_address_map: unsafe extern "win64" fn(memory_map_size: usize, descriptor_size: usize, descriptor_version: u32, efi_memory_descriptor: *const MemoryDescriptor) -> Status,
     convert_pointer: *const NotYetDef,
     get_variable: unsafe extern "win64" fn(name: *const u16, guid: &Guid, attributes: *mut u32, size: *mut usize, data: *mut u8) -> Status,
-    get_next_variable_name: *const NotYetDef,
-    set_variable: unsafe extern "win64" fn(name: *const u16, guid: &Guid, attributes: *const u32, size: *const usize, data: *const u8) -> Status,
+    get_next_variable_name: unsafe extern "win64" fn(size: *mut usize, name: *mut u16, guid: *mut Guid) -> Status,
+    set_variable: unsafe extern "win64" fn(name: *const u16, guid: &Guid, attributes: u32, size: usize, data: *const u8) -> Status,
     get_next_highest_monotonic_count: unsafe extern "win64" fn(count: *mut u32) -> Status,
-    reset_system: unsafe extern "win64" fn(resettype: ResetType, status: Status, datasize: usize, data: *const u8) -> !,
+    reset_system: unsafe extern "win64" fn(resettype: ResetType, status: Status, datasize: usize, data: *const u8),
     update_capsule: *const NotYetDef,
     query_capsule_capabilities: *const NotYetDef,
-    query_variable_info: *const NotYetDef,
+    query_variable_info: unsafe extern "win64" fn(attributes: u32, maximum_variable_storage_size: *mut u64, remaining_variable_storage_size: *mut u64, maximum_variable_size: *mut u64) -> Status,
+}
+
+/// Space for variables of some attributes, as `RuntimeServices::query_variable_info` reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VariableStorageInfo {
+    /// Bytes of storage for variables with the attributes.
+    pub maximum_variable_storage_size: u64,
+    /// Bytes of that storage left.
+    pub remaining_variable_storage_size: u64,
+    /// Largest variable, name and data, that can be stored.
+    pub maximum_variable_size: u64,
 }
 
 impl RuntimeServices {
@@ -42,6 +175,8 @@ impl RuntimeServices {
         }
     }
     pub fn get_time(&self) -> Result<Time, Status> {
+        check_supported(EFI_RT_SUPPORTED_GET_TIME)?;
+
         let mut t : Time = Time::default();
         let status = unsafe { (self.get_time)(&mut t, ptr::null_mut()) };
         if status != Status::Success {
@@ -51,10 +186,363 @@ impl RuntimeServices {
         Ok(t)
     }
 
+    /// Read the variable `name` in the `vendor` namespace into `data`, returning the size of the
+    /// variable and its attributes. If `data` is too small, `BufferTooSmall` is returned and
+    /// nothing is read.
+    pub fn get_variable(&self, name: &str, vendor: &Guid, data: &mut [u8]) -> Result<(usize, VariableAttributes), Status> {
+        check_supported(EFI_RT_SUPPORTED_GET_VARIABLE)?;
+
+        let mut name_buf = [0u16; MAX_VARIABLE_NAME + 1];
+        let name = variable_name(name, &mut name_buf)?;
+        let mut attributes: u32 = 0;
+        let mut size = data.len();
+
+        let status = unsafe { (self.get_variable)(name, vendor, &mut attributes, &mut size, data.as_mut_ptr()) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok((size, VariableAttributes::from_bits_truncate(attributes)))
+    }
+
+    /// Create or replace the variable `name` in the `vendor` namespace. Unless `APPEND_WRITE` is
+    /// given, writing an empty `data` deletes the variable.
+    pub fn set_variable(&self, name: &str, vendor: &Guid, attributes: VariableAttributes, data: &[u8]) -> Result<(), Status> {
+        check_supported(EFI_RT_SUPPORTED_SET_VARIABLE)?;
+
+        let mut name_buf = [0u16; MAX_VARIABLE_NAME + 1];
+        let name = variable_name(name, &mut name_buf)?;
+
+        let status = unsafe { (self.set_variable)(name, vendor, attributes.bits(), data.len(), data.as_ptr()) };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(())
+    }
+
+    /// All variables, as (name, vendor GUID) pairs, in firmware order. Narrow the listing with
+    /// `Variables::with_guid` and `Variables::with_prefix`:
+    ///
+    /// ```rust,ignore
+    /// for (name, _) in rs.variables().with_guid(&EFI_GLOBAL_VARIABLE_GUID).with_prefix("Boot") {
+    ///     let (size, _) = rs.get_variable(name.as_str(), &EFI_GLOBAL_VARIABLE_GUID, &mut buf)?;
+    /// }
+    /// ```
+    ///
+    /// Names longer than 128 characters, which the other variable wrappers cannot address, are
+    /// skipped. Iteration ends early if the firmware reports an error. Finding the names of
+    /// such long variables needs pool memory, so after ExitBootServices iteration stops at them.
+    pub fn variables(&self) -> Variables<'_> {
+        Variables {
+            runtime_services: self,
+            inline: [0; MAX_VARIABLE_NAME + 1],
+            pool: None,
+            vendor: Guid(0, 0, 0, [0; 8]),
+            guid: None,
+            prefix: None,
+            done: check_supported(EFI_RT_SUPPORTED_GET_NEXT_VARIABLE_NAME).is_err(),
+        }
+    }
+
+    /// How much space there is for variables with `attributes`, and how much is left, for
+    /// checking a large variable will fit before writing it.
+    pub fn query_variable_info(&self, attributes: VariableAttributes) -> Result<VariableStorageInfo, Status> {
+        check_supported(EFI_RT_SUPPORTED_QUERY_VARIABLE_INFO)?;
+
+        let mut info = VariableStorageInfo {
+            maximum_variable_storage_size: 0,
+            remaining_variable_storage_size: 0,
+            maximum_variable_size: 0,
+        };
+        let status = unsafe {
+            (self.query_variable_info)(attributes.bits(), &mut info.maximum_variable_storage_size,
+                                       &mut info.remaining_variable_storage_size, &mut info.maximum_variable_size)
+        };
+        if status != Status::Success {
+            return Err(status);
+        }
+
+        Ok(info)
+    }
+
+    /// Read the global variable `name`, a list of 16-bit values such as boot option numbers,
+    /// into `buf`.
+    fn get_u16_variable<'b>(&self, name: &str, buf: &'b mut [u16]) -> Result<&'b [u16], Status> {
+        let bytes = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 2) };
+        let (size, _) = self.get_variable(name, &EFI_GLOBAL_VARIABLE_GUID, bytes)?;
+        Ok(&buf[..size / 2])
+    }
+
+    /// Write the global variable `name` as the boot manager's variables are stored.
+    fn set_u16_variable(&self, name: &str, values: &[u16]) -> Result<(), Status> {
+        let bytes = unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, values.len() * 2) };
+        let attributes = EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS;
+        self.set_variable(name, &EFI_GLOBAL_VARIABLE_GUID, attributes, bytes)
+    }
+
+    /// The numbers of the Boot#### options in the order the boot manager tries them, read into
+    /// `buf`. Fails with `Status::BufferTooSmall` if they don't fit.
+    pub fn boot_order<'b>(&self, buf: &'b mut [u16]) -> Result<&'b [u16], Status> {
+        self.get_u16_variable("BootOrder", buf)
+    }
+
+    /// Replace the boot order with `order`, a list of Boot#### option numbers.
+    pub fn set_boot_order(&self, order: &[u16]) -> Result<(), Status> {
+        self.set_u16_variable("BootOrder", order)
+    }
+
+    /// The number of the Boot#### option the system was started from.
+    pub fn boot_current(&self) -> Result<u16, Status> {
+        let mut current = [0u16; 1];
+        self.get_u16_variable("BootCurrent", &mut current)?.first().cloned().ok_or(Status::NotFound)
+    }
+
+    /// The number of the Boot#### option to try first on the next boot only, if one is set.
+    pub fn boot_next(&self) -> Result<Option<u16>, Status> {
+        let mut next = [0u16; 1];
+        match self.get_u16_variable("BootNext", &mut next) {
+            Ok(next) => Ok(next.first().cloned()),
+            Err(Status::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Boot option `number` on the next boot only, as for a one-time "reboot into" choice.
+    pub fn set_boot_next(&self, number: u16) -> Result<(), Status> {
+        self.set_u16_variable("BootNext", &[number])
+    }
+
+    /// Delete the variable `name` in the `vendor` namespace.
+    pub fn delete_variable(&self, name: &str, vendor: &Guid) -> Result<(), Status> {
+        self.set_variable(name, vendor, VariableAttributes::empty(), &[])
+    }
+
+    /// Reset or power off the system.
+    ///
+    /// ResetSystem should never return, but on some ARM platforms it does. With the `psci`
+    /// feature, ARM builds then fall back to asking the PSCI firmware directly.
     pub fn reset_system(&self, reset_type: ResetType, status: Status) -> ! {
         unsafe {
             (self.reset_system)(reset_type, status, 0, ptr::null());
         }
+
+        #[cfg(all(feature = "psci", any(target_arch = "aarch64", target_arch = "arm")))]
+        ::psci::reset(reset_type);
+
+        loop {
+            ::core::hint::spin_loop();
+        }
+    }
+}
+
+
+/// Convert a variable name into a null-terminated UCS-2 string in `buf`, refusing names
+/// `validate_variable_name` refuses.
+fn variable_name(name: &str, buf: &mut [u16; MAX_VARIABLE_NAME + 1]) -> Result<*const u16, Status> {
+    validate_variable_name(name)?;
+
+    let mut len = 0;
+    for c in name.encode_utf16() {
+        buf[len] = c;
+        len += 1;
+    }
+    buf[len] = 0;
+
+    Ok(buf.as_ptr())
+}
+
+/// A variable name yielded by `RuntimeServices::variables`, in both UCS-2 and UTF-8.
+#[derive(Clone, Copy)]
+pub struct VariableName {
+    ucs2: [u16; MAX_VARIABLE_NAME + 1],
+    ucs2_len: usize,
+    utf8: [u8; MAX_VARIABLE_NAME * 3],
+    utf8_len: usize,
+}
+
+impl VariableName {
+    fn from_ucs2(name: &[u16]) -> Option<VariableName> {
+        if name.len() > MAX_VARIABLE_NAME {
+            return None;
+        }
+
+        let mut v = VariableName {
+            ucs2: [0; MAX_VARIABLE_NAME + 1],
+            ucs2_len: name.len(),
+            utf8: [0; MAX_VARIABLE_NAME * 3],
+            utf8_len: 0,
+        };
+        v.ucs2[..name.len()].copy_from_slice(name);
+        // Every UCS-2 unit encodes as at most three bytes, so this always fits.
+        for c in decode_utf16(name.iter().cloned()) {
+            let c = c.unwrap_or(REPLACEMENT_CHARACTER);
+            v.utf8_len += c.encode_utf8(&mut v.utf8[v.utf8_len..]).len();
+        }
+        Some(v)
+    }
+
+    /// The name, with unpaired surrogates replaced.
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are ever written to the buffer.
+        unsafe { str::from_utf8_unchecked(&self.utf8[..self.utf8_len]) }
+    }
+
+    /// The name as UCS-2, without a terminator.
+    pub fn as_ucs2(&self) -> &[u16] {
+        &self.ucs2[..self.ucs2_len]
+    }
+
+    /// The name as a null-terminated UCS-2 string.
+    pub fn as_ucs2_with_nul(&self) -> &[u16] {
+        &self.ucs2[..self.ucs2_len + 1]
+    }
+}
+
+impl fmt::Display for VariableName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for VariableName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
     }
 }
 
+/// Iterator returned by `RuntimeServices::variables`.
+pub struct Variables<'a> {
+    runtime_services: &'a RuntimeServices,
+    inline: [u16; MAX_VARIABLE_NAME + 1],
+    /// A pool buffer and its length in units, once a name too long for `inline` is met.
+    pool: Option<(*mut u16, usize)>,
+    vendor: Guid,
+    guid: Option<&'a Guid>,
+    prefix: Option<&'a str>,
+    done: bool,
+}
+
+impl<'a> Variables<'a> {
+    /// Only variables in the `guid` namespace.
+    pub fn with_guid(mut self, guid: &'a Guid) -> Variables<'a> {
+        self.guid = Some(guid);
+        self
+    }
+
+    /// Only variables whose names start with `prefix`, such as "Boot".
+    pub fn with_prefix(mut self, prefix: &'a str) -> Variables<'a> {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    fn buffer(&mut self) -> &mut [u16] {
+        match self.pool {
+            Some((p, len)) => unsafe { slice::from_raw_parts_mut(p, len) },
+            None => &mut self.inline,
+        }
+    }
+
+    /// Replace the buffer with a pool buffer of `bytes` bytes, keeping the current name. Fails
+    /// with `Status::BufferTooSmall`, ending the iteration, once boot services are gone.
+    fn grow(&mut self, bytes: usize) -> Result<(), Status> {
+        if !boot_services_available() {
+            return Err(Status::BufferTooSmall);
+        }
+        let bs = ::get_system_table().boot_services();
+        let len = bytes / 2 + bytes % 2;
+        let p = bs.allocate_pool::<u16>(len * 2)?;
+
+        let new = unsafe { slice::from_raw_parts_mut(p, len) };
+        let old = self.buffer();
+        let n = old.iter().position(|&c| c == 0).map_or(old.len(), |n| n + 1);
+        new[..n].copy_from_slice(&old[..n]);
+
+        if let Some((old, _)) = self.pool.replace((p, len)) {
+            bs.free_pool(old);
+        }
+        Ok(())
+    }
+
+    /// Advance the firmware's cursor to the next variable, returning its name.
+    fn advance(&mut self) -> Result<&[u16], Status> {
+        loop {
+            let get_next_variable_name = self.runtime_services.get_next_variable_name;
+            let mut vendor = self.vendor;
+            let buf = self.buffer();
+            let mut size = buf.len() * 2;
+            let status = unsafe { get_next_variable_name(&mut size, buf.as_mut_ptr(), &mut vendor) };
+
+            match status {
+                Status::Success => {
+                    self.vendor = vendor;
+                    let buf = self.buffer();
+                    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+                    return Ok(&buf[..len]);
+                }
+                Status::BufferTooSmall => self.grow(size)?,
+                e => return Err(e),
+            }
+        }
+    }
+}
+
+/// Whether the variable `name` in the `vendor` namespace passes the filters of `Variables`.
+fn variable_matches(name: &str, vendor: &Guid, guid: Option<&Guid>, prefix: Option<&str>) -> bool {
+    guid.map_or(true, |g| g == vendor) && prefix.map_or(true, |p| name.starts_with(p))
+}
+
+impl<'a> Iterator for Variables<'a> {
+    type Item = (VariableName, Guid);
+
+    fn next(&mut self) -> Option<(VariableName, Guid)> {
+        while !self.done {
+            let name = match self.advance() {
+                Ok(name) => VariableName::from_ucs2(name),
+                // NotFound marks the end of the list.
+                Err(_) => {
+                    self.done = true;
+                    return None;
+                }
+            };
+
+            let name = match name {
+                Some(name) => name,
+                None => continue,
+            };
+            if !variable_matches(name.as_str(), &self.vendor, self.guid, self.prefix) {
+                continue;
+            }
+            return Some((name, self.vendor));
+        }
+        None
+    }
+}
+
+impl<'a> Drop for Variables<'a> {
+    fn drop(&mut self) {
+        // Pool memory can't be freed once boot services are gone; it is no longer tracked then.
+        if let Some((p, _)) = self.pool {
+            if boot_services_available() {
+                ::get_system_table().boot_services().free_pool(p);
+            }
+        }
+    }
+}
+
+#[test]
+fn variable_filters() {
+    let other = Guid(0x964E5B22, 0x6459, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+    let global = &EFI_GLOBAL_VARIABLE_GUID;
+
+    assert!(variable_matches("Boot0001", global, None, None));
+    assert!(variable_matches("Boot0001", global, Some(global), Some("Boot")));
+    assert!(variable_matches("BootOrder", &other, None, Some("Boot")));
+    assert!(!variable_matches("Boot0001", &other, Some(global), None));
+    assert!(!variable_matches("Boot0001", &other, Some(global), Some("Boot")));
+    assert!(!variable_matches("Timeout", global, Some(global), Some("Boot")));
+    // Prefixes are matched case-sensitively, as the firmware compares names.
+    assert!(!variable_matches("boot0001", global, None, Some("Boot")));
+    assert!(variable_matches("Boot", global, None, Some("Boot")));
+    assert!(!variable_matches("Boo", global, None, Some("Boot")));
+}
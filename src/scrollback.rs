@@ -0,0 +1,358 @@
+use core::{cmp, fmt, mem, slice};
+use core::cell::Cell;
+
+use base::Status;
+use console::{Attribute, Console, SimpleTextInput, SimpleTextOutput};
+use util::utf16_strlen;
+
+const SCAN_UP: u16 = 0x01;
+const SCAN_DOWN: u16 = 0x02;
+const SCAN_PAGE_UP: u16 = 0x09;
+const SCAN_PAGE_DOWN: u16 = 0x0A;
+const SCAN_ESC: u16 = 0x17;
+
+const STATUS_LINE: &str = "-- Up/Down/PgUp/PgDn: scroll, Esc: return --";
+
+/// A console decorator which records everything written through it into a ring buffer, so it can
+/// be reviewed later with `show_scrollback`.
+///
+/// The buffer is allocated with `allocate_pool` and holds the most recent `capacity` characters;
+/// older output is discarded as new output arrives.
+pub struct Scrollback {
+    console: Console,
+    buffer: *mut u16,
+    capacity: usize,
+    start: Cell<usize>,
+    len: Cell<usize>,
+}
+
+impl Scrollback {
+    /// Wrap `console`, keeping the last `capacity` characters of output.
+    pub fn new(console: Console, capacity: usize) -> Result<Scrollback, Status> {
+        if capacity == 0 {
+            return Err(Status::InvalidParameter);
+        }
+
+        let size = capacity.checked_mul(mem::size_of::<u16>()).ok_or(Status::InvalidParameter)?;
+        let buffer = ::get_system_table()
+            .boot_services()
+            .allocate_pool::<u16>(size)?;
+
+        Ok(Scrollback {
+            console,
+            buffer,
+            capacity,
+            start: Cell::new(0),
+            len: Cell::new(0),
+        })
+    }
+
+    /// The wrapped console.
+    pub fn console(&self) -> &Console {
+        &self.console
+    }
+
+    /// Discard all recorded output.
+    pub fn clear(&self) {
+        self.start.set(0);
+        self.len.set(0);
+    }
+
+    fn ring(&self) -> &[u16] {
+        unsafe { slice::from_raw_parts(self.buffer, self.capacity) }
+    }
+
+    fn push(&self, c: u16) {
+        let (start, len) = (self.start.get(), self.len.get());
+
+        // The buffer is only reachable through this Scrollback, so writing through the raw
+        // pointer does not alias any outstanding slice.
+        unsafe {
+            if len < self.capacity {
+                *self.buffer.add((start + len) % self.capacity) = c;
+                self.len.set(len + 1);
+            } else {
+                *self.buffer.add(start) = c;
+                self.start.set((start + 1) % self.capacity);
+            }
+        }
+    }
+
+    fn chars(&self) -> RingChars<'_> {
+        RingChars {
+            ring: self.ring(),
+            start: self.start.get(),
+            len: self.len.get(),
+            offset: 0,
+        }
+    }
+
+    /// Count the screen lines the recorded output occupies when wrapped at `columns`.
+    fn line_count(&self, columns: usize) -> usize {
+        let mut lines = 1;
+        let mut column = 0;
+
+        for c in self.chars() {
+            match c {
+                0x0D => (),
+                0x0A => {
+                    lines += 1;
+                    column = 0;
+                }
+                _ => {
+                    if column == columns {
+                        lines += 1;
+                        column = 0;
+                    }
+                    column += 1;
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Draw `rows` screen lines starting at line `top`.
+    fn draw_page(&self, top: usize, rows: usize, columns: usize) -> Status {
+        let mut out = LineWriter::new(&self.console);
+        let mut line = 0;
+        let mut column = 0;
+
+        for c in self.chars() {
+            if line >= top + rows {
+                break;
+            }
+
+            match c {
+                0x0D => continue,
+                0x0A => {
+                    if line >= top {
+                        out.newline();
+                    }
+                    line += 1;
+                    column = 0;
+                    continue;
+                }
+                _ => (),
+            }
+
+            if column == columns {
+                if line >= top {
+                    out.newline();
+                }
+                line += 1;
+                column = 0;
+                if line >= top + rows {
+                    break;
+                }
+            }
+
+            if line >= top {
+                out.push(c);
+            }
+            column += 1;
+        }
+
+        out.finish()
+    }
+
+    /// Show the recorded output full-screen, starting at the most recent page, and let the user
+    /// scroll through it with the arrow and page keys until Esc is pressed. The console state is
+    /// restored afterwards.
+    pub fn show_scrollback(&self) -> Status {
+        let state = self.console.save_state();
+        let mode = self.console.mode();
+        let (columns, rows) = match self.console.query_mode(mode.mode as usize) {
+            Ok(size) => size,
+            Err(e) => return e,
+        };
+
+        // Leave the last row for the status line, and the last column empty so the firmware does
+        // not wrap for us.
+        let page = cmp::max(rows, 2) - 1;
+        let columns = cmp::max(columns, 2) - 1;
+        let total = self.line_count(columns);
+        let last_top = total.saturating_sub(page);
+        let mut top = last_top;
+
+        loop {
+            self.console.clear_screen();
+            let status = self.draw_page(top, page, columns);
+            if status != Status::Success {
+                self.console.restore_state(&state);
+                return status;
+            }
+            self.console.set_cursor_position(0, page);
+            self.console.write(STATUS_LINE);
+
+            let key = match self.console.read_key() {
+                Ok(key) => key,
+                Err(e) => {
+                    self.console.restore_state(&state);
+                    return e;
+                }
+            };
+
+            match key.scan_code {
+                SCAN_UP => top = top.saturating_sub(1),
+                SCAN_DOWN => top = cmp::min(top + 1, last_top),
+                SCAN_PAGE_UP => top = top.saturating_sub(page),
+                SCAN_PAGE_DOWN => top = cmp::min(top + page, last_top),
+                SCAN_ESC => break,
+                _ => (),
+            }
+        }
+
+        self.console.clear_screen();
+        self.console.restore_state(&state)
+    }
+}
+
+impl Drop for Scrollback {
+    fn drop(&mut self) {
+        ::get_system_table().boot_services().free_pool(self.buffer);
+    }
+}
+
+impl SimpleTextOutput for Scrollback {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn write_raw(&self, str: *const u16) -> Status {
+        let len = utf16_strlen(str);
+        for &c in unsafe { slice::from_raw_parts(str, len) } {
+            self.push(c);
+        }
+
+        self.console.write_raw(str)
+    }
+
+    fn set_attribute(&self, attribute: Attribute) -> Status {
+        self.console.set_attribute(attribute)
+    }
+}
+
+impl fmt::Write for Scrollback {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.write(s) == Status::Success {
+            return Ok(());
+        }
+        Err(fmt::Error)
+    }
+}
+
+struct RingChars<'a> {
+    ring: &'a [u16],
+    start: usize,
+    len: usize,
+    offset: usize,
+}
+
+impl<'a> Iterator for RingChars<'a> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if self.offset == self.len {
+            return None;
+        }
+
+        let c = self.ring[(self.start + self.offset) % self.ring.len()];
+        self.offset += 1;
+        Some(c)
+    }
+}
+
+/// Batches characters into null-terminated chunks for OutputString.
+struct LineWriter<'a> {
+    console: &'a dyn SimpleTextOutput,
+    buf: [u16; 64],
+    len: usize,
+    status: Status,
+}
+
+impl<'a> LineWriter<'a> {
+    fn new(console: &'a dyn SimpleTextOutput) -> LineWriter<'a> {
+        LineWriter {
+            console,
+            buf: [0u16; 64],
+            len: 0,
+            status: Status::Success,
+        }
+    }
+
+    /// Queue `c` for output. Once a write has failed, the rest of the output is dropped.
+    fn push(&mut self, c: u16) {
+        if self.status != Status::Success {
+            return;
+        }
+        self.buf[self.len] = c;
+        self.len += 1;
+
+        if self.len == self.buf.len() - 1 {
+            self.flush();
+        }
+    }
+
+    fn newline(&mut self) {
+        self.push(0x0D);
+        self.push(0x0A);
+    }
+
+    fn flush(&mut self) {
+        if self.len > 0 && self.status == Status::Success {
+            self.buf[self.len] = 0;
+            self.status = self.console.write_raw(self.buf.as_ptr());
+        }
+        self.len = 0;
+    }
+
+    fn finish(mut self) -> Status {
+        self.flush();
+        self.status
+    }
+}
+
+/// Counts the characters written to it, failing every write after the first `writes`.
+#[cfg(test)]
+struct FailingOutput {
+    writes: Cell<usize>,
+    written: Cell<usize>,
+}
+
+#[cfg(test)]
+impl SimpleTextOutput for FailingOutput {
+    fn write_raw(&self, str: *const u16) -> Status {
+        if self.writes.get() == 0 {
+            return Status::DeviceError;
+        }
+        self.writes.set(self.writes.get() - 1);
+        self.written.set(self.written.get() + utf16_strlen(str));
+        Status::Success
+    }
+
+    fn set_attribute(&self, _attribute: Attribute) -> Status {
+        Status::Success
+    }
+}
+
+#[test]
+fn line_writer_batches_output() {
+    let output = FailingOutput { writes: Cell::new(usize::MAX), written: Cell::new(0) };
+    let mut out = LineWriter::new(&output);
+    for _ in 0..100 {
+        out.push(u16::from(b'x'));
+    }
+    out.newline();
+    assert_eq!(out.finish(), Status::Success);
+    assert_eq!(output.written.get(), 102);
+    assert_eq!(output.writes.get(), usize::MAX - 2);
+}
+
+#[test]
+fn line_writer_drops_output_after_failure() {
+    let output = FailingOutput { writes: Cell::new(1), written: Cell::new(0) };
+    let mut out = LineWriter::new(&output);
+    for _ in 0..1000 {
+        out.push(u16::from(b'x'));
+    }
+    assert_eq!(out.finish(), Status::DeviceError);
+    assert_eq!(output.written.get(), 63);
+}
@@ -0,0 +1,243 @@
+//! SMBIOS tables, which describe the machine: its firmware, the system and board, processors,
+//! memory and so on. `FirmwareInfo` gathers the parts inventory tools and quirk tables usually
+//! want.
+
+use core::{char, fmt, slice, str};
+
+use guid::Guid;
+use util::{utf16_strlen, wire};
+
+/// GUID of the SMBIOS 2.x entry point in the system configuration table
+pub static SMBIOS_TABLE_GUID: Guid = Guid(0xEB9D2D31, 0x2D88, 0x11D3, [0x9A, 0x16, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D]);
+
+/// GUID of the SMBIOS 3.x entry point in the system configuration table
+pub static SMBIOS3_TABLE_GUID: Guid = Guid(0xF2FD1544, 0x9794, 0x4A2C, [0x99, 0x2E, 0xE5, 0xBB, 0xCF, 0x20, 0xE3, 0x94]);
+
+pub const SMBIOS_TYPE_BIOS_INFORMATION: u8 = 0;
+pub const SMBIOS_TYPE_SYSTEM_INFORMATION: u8 = 1;
+pub const SMBIOS_TYPE_END_OF_TABLE: u8 = 127;
+
+/// One structure of the SMBIOS table: the formatted area, starting with the 4-byte header, and
+/// the strings following it.
+#[derive(Clone, Copy, Debug)]
+pub struct SmbiosStructure<'a> {
+    formatted: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> SmbiosStructure<'a> {
+    pub fn structure_type(&self) -> u8 {
+        self.formatted[0]
+    }
+
+    pub fn handle(&self) -> u16 {
+        wire::read_u16(self.formatted, 2).unwrap_or(0)
+    }
+
+    /// The formatted area, header included, so offsets match the specification's tables.
+    pub fn formatted(&self) -> &'a [u8] {
+        self.formatted
+    }
+
+    /// The string whose number is the byte at `offset` of the formatted area. `None` if the
+    /// structure is too short for the field, the number is 0 (no string), or the string is
+    /// missing or not UTF-8.
+    pub fn string_at(&self, offset: usize) -> Option<&'a str> {
+        let number = *self.formatted.get(offset)? as usize;
+        if number == 0 {
+            return None;
+        }
+        let s = self.strings.split(|&b| b == 0).nth(number - 1)?;
+        str::from_utf8(s).ok().map(str::trim).filter(|s| !s.is_empty())
+    }
+}
+
+/// The SMBIOS structure table.
+#[derive(Clone, Copy, Debug)]
+pub struct SmbiosTable<'a> {
+    table: &'a [u8],
+}
+
+impl<'a> SmbiosTable<'a> {
+    /// The structures in `table`, the structure table itself without an entry point.
+    pub fn from_bytes(table: &'a [u8]) -> SmbiosTable<'a> {
+        SmbiosTable { table }
+    }
+
+    pub fn structures(&self) -> SmbiosStructures<'a> {
+        SmbiosStructures { buf: self.table }
+    }
+
+    /// The first structure of type `structure_type`.
+    pub fn find(&self, structure_type: u8) -> Option<SmbiosStructure<'a>> {
+        self.structures().find(|s| s.structure_type() == structure_type)
+    }
+}
+
+impl SmbiosTable<'static> {
+    /// The table the firmware publishes, through the SMBIOS 3.x entry point if there is one and
+    /// the 2.x one otherwise.
+    pub fn get() -> Option<SmbiosTable<'static>> {
+        let st = ::get_system_table();
+        unsafe {
+            if let Some(entry) = st.configuration_table(&SMBIOS3_TABLE_GUID).filter(|p| !p.is_null()) {
+                let entry = slice::from_raw_parts(entry as *const u8, 24);
+                if &entry[..5] == b"_SM3_" {
+                    let len = wire::read_u32(entry, 0x0C).ok()? as usize;
+                    let address = wire::read_u64(entry, 0x10).ok()? as usize;
+                    return Some(SmbiosTable::from_bytes(slice::from_raw_parts(address as *const u8, len)));
+                }
+            }
+
+            let entry = st.configuration_table(&SMBIOS_TABLE_GUID).filter(|p| !p.is_null())?;
+            let entry = slice::from_raw_parts(entry as *const u8, 0x1F);
+            if &entry[..4] != b"_SM_" {
+                return None;
+            }
+            let len = wire::read_u16(entry, 0x16).ok()? as usize;
+            let address = wire::read_u32(entry, 0x18).ok()? as usize;
+            Some(SmbiosTable::from_bytes(slice::from_raw_parts(address as *const u8, len)))
+        }
+    }
+}
+
+/// Iterator returned by `SmbiosTable::structures`. It stops at the end-of-table structure or
+/// at the first malformed one.
+#[derive(Clone, Debug)]
+pub struct SmbiosStructures<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for SmbiosStructures<'a> {
+    type Item = SmbiosStructure<'a>;
+
+    fn next(&mut self) -> Option<SmbiosStructure<'a>> {
+        let len = *self.buf.get(1)? as usize;
+        if len < 4 || len > self.buf.len() || self.buf[0] == SMBIOS_TYPE_END_OF_TABLE {
+            self.buf = &[];
+            return None;
+        }
+
+        // The strings end with two nuls; a structure without strings has just the two.
+        let rest = &self.buf[len..];
+        let end = match rest.windows(2).position(|w| w == [0, 0]) {
+            Some(end) => end,
+            None => {
+                self.buf = &[];
+                return None;
+            }
+        };
+
+        let structure = SmbiosStructure { formatted: &self.buf[..len], strings: &rest[..end] };
+        self.buf = &rest[end + 2..];
+        Some(structure)
+    }
+}
+
+/// What the firmware and SMBIOS say about the machine, from `FirmwareInfo::collect`. SMBIOS
+/// strings are `None` where the table has no such string or there is no table.
+#[derive(Clone, Copy, Debug)]
+pub struct FirmwareInfo {
+    /// The UEFI firmware vendor from the system table, as UCS-2.
+    pub firmware_vendor: &'static [u16],
+    pub firmware_revision: u32,
+    pub uefi_revision: u32,
+    pub bios_vendor: Option<&'static str>,
+    pub bios_version: Option<&'static str>,
+    pub bios_release_date: Option<&'static str>,
+    pub manufacturer: Option<&'static str>,
+    pub product_name: Option<&'static str>,
+    pub product_version: Option<&'static str>,
+    pub serial_number: Option<&'static str>,
+    pub sku_number: Option<&'static str>,
+    pub family: Option<&'static str>,
+    /// The system UUID, unless SMBIOS has none or says it is unset.
+    pub uuid: Option<Guid>,
+}
+
+impl FirmwareInfo {
+    /// Read the firmware fields of the system table and the BIOS and system information
+    /// structures of SMBIOS, if the firmware publishes it.
+    pub fn collect() -> FirmwareInfo {
+        let st = ::get_system_table();
+        let vendor = st.vendor();
+        let firmware_vendor = if vendor.is_null() {
+            &[][..]
+        } else {
+            unsafe { slice::from_raw_parts(vendor, utf16_strlen(vendor)) }
+        };
+
+        FirmwareInfo {
+            firmware_vendor,
+            firmware_revision: st.firmware_revision(),
+            uefi_revision: st.uefi_revision(),
+            ..FirmwareInfo::from_smbios(SmbiosTable::get().as_ref())
+        }
+    }
+
+    /// The SMBIOS fields from `table`, with the system table fields left empty.
+    fn from_smbios(table: Option<&SmbiosTable<'static>>) -> FirmwareInfo {
+        let bios = table.and_then(|t| t.find(SMBIOS_TYPE_BIOS_INFORMATION));
+        let system = table.and_then(|t| t.find(SMBIOS_TYPE_SYSTEM_INFORMATION));
+        let bios_string = |offset| bios.and_then(|s| s.string_at(offset));
+        let system_string = |offset| system.and_then(|s| s.string_at(offset));
+
+        FirmwareInfo {
+            firmware_vendor: &[],
+            firmware_revision: 0,
+            uefi_revision: 0,
+            bios_vendor: bios_string(0x04),
+            bios_version: bios_string(0x05),
+            bios_release_date: bios_string(0x08),
+            manufacturer: system_string(0x04),
+            product_name: system_string(0x05),
+            product_version: system_string(0x06),
+            serial_number: system_string(0x07),
+            sku_number: system_string(0x19),
+            family: system_string(0x1A),
+            uuid: system.and_then(|s| wire::read_guid(s.formatted(), 0x08).ok())
+                .filter(|g| *g != Guid(0, 0, 0, [0; 8]) && *g != Guid(!0, !0, !0, [0xFF; 8])),
+        }
+    }
+
+    /// The firmware vendor from the system table.
+    pub fn vendor(&self) -> FirmwareVendor {
+        FirmwareVendor(self.firmware_vendor)
+    }
+}
+
+/// Displays the UCS-2 firmware vendor of a `FirmwareInfo`.
+pub struct FirmwareVendor(&'static [u16]);
+
+impl fmt::Display for FirmwareVendor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in char::decode_utf16(self.0.iter().cloned()) {
+            fmt::Write::write_char(f, c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn smbios_structures() {
+    static TABLE: [u8; 70] = [
+        // Type 0, BIOS information.
+        0, 0x12, 0x00, 0x00, 1, 2, 0x00, 0xF0, 3, 0x10, 0, 0, 0, 0, 0, 0, 0, 0,
+        b'A', b'c', b'm', b'e', 0, b'1', b'.', b'2', 0, b'0', b'1', b'/', b'0', b'2', b'/', b'2', b'4', 0, 0,
+        // Type 1, system information, with a UUID but no strings.
+        1, 0x19, 0x01, 0x00, 0, 0, 0, 0,
+        0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF,
+        6, 0, 0,
+        // End of table.
+        127, 4, 0x02, 0x00, 0, 0,
+    ];
+    let table: SmbiosTable<'static> = SmbiosTable::from_bytes(&TABLE);
+    assert_eq!(table.structures().count(), 2);
+
+    let info = FirmwareInfo::from_smbios(Some(&table));
+    assert_eq!(info.bios_vendor, Some("Acme"));
+    assert_eq!(info.bios_version, Some("1.2"));
+    assert_eq!(info.bios_release_date, Some("01/02/24"));
+    assert_eq!(info.manufacturer, None);
+    assert_eq!(info.uuid, Some(Guid(0x00112233, 0x4455, 0x6677, [0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF])));
+}
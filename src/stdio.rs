@@ -0,0 +1,184 @@
+use core::{fmt, slice};
+
+use base::Status;
+use console::{Attribute, Console, SimpleTextInput, SimpleTextOutput};
+use protocol::{FileProtocol, ShellParametersProtocol, get_current_image_handle, EFI_FILE_INFO_ID};
+use util::utf16_strlen;
+
+/// Byte order mark the shell writes at the start of redirected Unicode files.
+const BOM: u16 = 0xFEFF;
+
+fn shell_parameters() -> Option<&'static ShellParametersProtocol> {
+    ::get_system_table()
+        .boot_services()
+        .handle_protocol::<ShellParametersProtocol>(get_current_image_handle())
+        .ok()
+}
+
+/// Whether a shell stream whose EFI_FILE_INFO query gave `info` is really redirected. The shell
+/// fills in all three streams, wrapping the console in file handles of its own when nothing is
+/// redirected, and those don't support `get_info`.
+fn redirected(info: Result<usize, Status>) -> bool {
+    info != Err(Status::Unsupported)
+}
+
+/// The shell's stream `file`, if it is redirected rather than the console.
+fn redirected_file(file: Option<&'static FileProtocol>) -> Option<&'static FileProtocol> {
+    // Only the answer matters, so no buffer is given for the information.
+    file.filter(|file| redirected(file.get_info(&EFI_FILE_INFO_ID, &mut [])))
+}
+
+/// An output stream which is either the firmware console or, when the application was started by
+/// the UEFI shell with its output redirected, the file the shell redirected it to.
+pub enum Stdout {
+    Console(Console),
+    File(&'static FileProtocol),
+}
+
+impl Stdout {
+    /// True if the shell redirected output to a file rather than the console.
+    pub fn is_file(&self) -> bool {
+        match *self {
+            Stdout::File(_) => true,
+            Stdout::Console(_) => false,
+        }
+    }
+}
+
+impl SimpleTextOutput for Stdout {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn write_raw(&self, str: *const u16) -> Status {
+        match *self {
+            Stdout::Console(ref console) => console.write_raw(str),
+            Stdout::File(file) => {
+                let len = utf16_strlen(str) * 2;
+                let bytes = unsafe { slice::from_raw_parts(str as *const u8, len) };
+                match file.write(bytes) {
+                    Ok(_) => Status::Success,
+                    Err(e) => e,
+                }
+            }
+        }
+    }
+
+    fn set_attribute(&self, attribute: Attribute) -> Status {
+        match *self {
+            Stdout::Console(ref console) => console.set_attribute(attribute),
+            // Colours make no sense in a file.
+            Stdout::File(_) => Status::Success,
+        }
+    }
+}
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.write(s) == Status::Success {
+            return Ok(());
+        }
+        Err(fmt::Error)
+    }
+}
+
+/// An input stream which is either the firmware console or, when the shell redirected it, the
+/// shell's input file.
+pub enum Stdin {
+    Console(Console),
+    File(&'static FileProtocol),
+}
+
+impl Stdin {
+    /// True if the shell redirected input from a file rather than the keyboard.
+    pub fn is_file(&self) -> bool {
+        match *self {
+            Stdin::File(_) => true,
+            Stdin::Console(_) => false,
+        }
+    }
+
+    /// Read one UCS-2 character, blocking until one is available. Returns `None` at the end of
+    /// a redirected file. Keys without a character (such as arrow keys) are skipped.
+    pub fn read_char(&self) -> Result<Option<u16>, Status> {
+        match *self {
+            Stdin::Console(ref console) => loop {
+                let key = console.read_key()?;
+                if key.unicode_char != 0 {
+                    return Ok(Some(key.unicode_char));
+                }
+            },
+            Stdin::File(file) => loop {
+                let mut c = [0u8; 2];
+                if file.read(&mut c)? < 2 {
+                    return Ok(None);
+                }
+
+                let c = (c[0] as u16) | ((c[1] as u16) << 8);
+                if c != BOM {
+                    return Ok(Some(c));
+                }
+            },
+        }
+    }
+
+    /// Read a line into `buf`, without its terminator, and return its length. Returns `None` if
+    /// the end of input was reached before anything was read. Characters beyond the size of
+    /// `buf` are discarded.
+    pub fn read_line(&self, buf: &mut [u16]) -> Result<Option<usize>, Status> {
+        let mut len = 0;
+        let mut any = false;
+
+        loop {
+            let c = match self.read_char()? {
+                Some(c) => c,
+                None if any => return Ok(Some(len)),
+                None => return Ok(None),
+            };
+            any = true;
+
+            match c {
+                // Enter on the console produces a carriage return, files contain CRLF or LF.
+                0x0D if !self.is_file() => return Ok(Some(len)),
+                0x0D => continue,
+                0x0A => return Ok(Some(len)),
+                _ => {
+                    if len < buf.len() {
+                        buf[len] = c;
+                        len += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Standard output, honoring shell redirection.
+pub fn stdout() -> Stdout {
+    match redirected_file(shell_parameters().and_then(|p| p.stdout())) {
+        Some(file) => Stdout::File(file),
+        None => Stdout::Console(::get_system_table().console()),
+    }
+}
+
+/// Standard error, honoring shell redirection.
+pub fn stderr() -> Stdout {
+    match redirected_file(shell_parameters().and_then(|p| p.stderr())) {
+        Some(file) => Stdout::File(file),
+        None => Stdout::Console(::get_system_table().error_console()),
+    }
+}
+
+/// Standard input, honoring shell redirection.
+pub fn stdin() -> Stdin {
+    match redirected_file(shell_parameters().and_then(|p| p.stdin())) {
+        Some(file) => Stdin::File(file),
+        None => Stdin::Console(::get_system_table().console()),
+    }
+}
+
+#[test]
+fn stdio_redirection() {
+    // The shell's console wrappers, and real files, which need a buffer for the information.
+    assert!(!redirected(Err(Status::Unsupported)));
+    assert!(redirected(Err(Status::BufferTooSmall)));
+    assert!(redirected(Ok(0)));
+    assert_eq!(redirected_file(None).map(|_| ()), None);
+}
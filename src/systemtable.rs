@@ -1,4 +1,8 @@
+use core::slice;
+
 use base;
+use guid::Guid;
+use void::CVoid;
 use table;
 use bootservices;
 use runtimeservices;
@@ -12,26 +16,63 @@ pub struct SystemTable {
     vendor: *const u16,
     revision: u32,
     con_in_handle: base::Handle,
-    con_in: &'static console::SimpleTextInputProtocol,
+    con_in: *const console::SimpleTextInputProtocol,
     con_out_handle: base::Handle,
-    con_out: &'static console::SimpleTextOutputProtocol,
+    con_out: *const console::SimpleTextOutputProtocol,
     std_err_handle: base::Handle,
-    std_err: &'static console::SimpleTextOutputProtocol,
+    std_err: *const console::SimpleTextOutputProtocol,
     runtime_services: &'static runtimeservices::RuntimeServices,
     boot_services: &'static bootservices::BootServices,
     configuration_table_entries: usize,
-    configuration_table: &'static table::ConfigurationTableInternal,
+    configuration_table: *const table::ConfigurationTable,
 }
 
 impl SystemTable {
     pub fn console(&'static self) -> console::Console {
-        console::Console::new(self, self.con_in, self.con_out)
+        unsafe { console::Console::new(self, &*self.con_in, &*self.con_out) }
+    }
+
+    /// A console which writes to the standard error device instead of the console output.
+    pub fn error_console(&'static self) -> console::Console {
+        unsafe { console::Console::new(self, &*self.con_in, &*self.std_err) }
+    }
+
+    /// The console input protocol, or `None` once boot services have been exited and the firmware
+    /// has cleared it.
+    pub fn stdin(&self) -> Option<&'static console::SimpleTextInputProtocol> {
+        unsafe { self.con_in.as_ref() }
+    }
+
+    /// The console output protocol, or `None` once boot services have been exited.
+    pub fn stdout(&self) -> Option<&'static console::SimpleTextOutputProtocol> {
+        unsafe { self.con_out.as_ref() }
+    }
+
+    /// The standard error protocol, or `None` once boot services have been exited.
+    pub fn stderr(&self) -> Option<&'static console::SimpleTextOutputProtocol> {
+        unsafe { self.std_err.as_ref() }
+    }
+
+    /// The handle of the active console input device.
+    pub fn stdin_handle(&self) -> base::Handle {
+        self.con_in_handle
+    }
+
+    /// The handle of the active console output device.
+    pub fn stdout_handle(&self) -> base::Handle {
+        self.con_out_handle
+    }
+
+    /// The handle of the active standard error device.
+    pub fn stderr_handle(&self) -> base::Handle {
+        self.std_err_handle
     }
 
     pub fn boot_services(&self) -> &'static bootservices::BootServices {
         return self.boot_services;
     }
 
+    /// The runtime services, which remain usable after boot services have been exited.
     pub fn runtime_services(&self) -> &'static runtimeservices::RuntimeServices {
         return self.runtime_services;
     }
@@ -39,6 +80,33 @@ impl SystemTable {
     pub fn vendor(&self) -> *const u16 {
         return self.vendor
     }
+
+    /// The UEFI specification revision the firmware conforms to, e.g. 0x0002_0046 for 2.70.
+    pub fn uefi_revision(&self) -> u32 {
+        self.header.revision()
+    }
+
+    /// The vendor-specific firmware revision.
+    pub fn firmware_revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// All entries of the system configuration table.
+    pub fn configuration_tables(&self) -> &'static [table::ConfigurationTable] {
+        if self.configuration_table.is_null() {
+            return &[];
+        }
+
+        unsafe { slice::from_raw_parts(self.configuration_table, self.configuration_table_entries) }
+    }
+
+    /// Find the vendor table with the given GUID in the system configuration table.
+    pub fn configuration_table(&self, guid: &Guid) -> Option<*const CVoid> {
+        self.configuration_tables()
+            .iter()
+            .find(|t| t.vendor_guid == *guid)
+            .map(|t| t.vendor_table)
+    }
 }
 
 static mut SYSTEM_TABLE : *const SystemTable = 0 as *const SystemTable;
@@ -52,6 +120,17 @@ pub fn set_system_table(table: *const SystemTable) -> &'static SystemTable {
     get_system_table()
 }
 
+/// Whether boot services can be called: the system table has been set, and boot services have
+/// not been exited through `BootServices::exit_boot_services`.
+pub fn boot_services_available() -> bool {
+    system_table_available() && !runtimeservices::boot_services_exited()
+}
+
+/// Whether `set_system_table` has been called, so runtime services at least can be used.
+pub(crate) fn system_table_available() -> bool {
+    unsafe { !SYSTEM_TABLE.is_null() }
+}
+
 /// Retreive System Table handle.
 pub fn get_system_table() -> &'static SystemTable {
     unsafe {
@@ -1,4 +1,4 @@
-use void::NotYetDef;
+use void::CVoid;
 use guid::Guid;
 
 #[repr(C)]
@@ -10,9 +10,20 @@ pub struct TableHeader {
     reserved: u32,
 }
 
+impl TableHeader {
+    /// The specification revision the table follows, major version in the upper 16 bits and
+    /// minor in the lower, e.g. 0x0002_0046 for 2.70.
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+}
+
+/// An entry of the system configuration table, pointing to a vendor table such as ACPI, SMBIOS
+/// or the ESRT.
+#[derive(Debug)]
 #[repr(C)]
-pub struct ConfigurationTableInternal {
-    vendor_guid: Guid,
-    vendor_table: *const NotYetDef,
+pub struct ConfigurationTable {
+    pub vendor_guid: Guid,
+    pub vendor_table: *const CVoid,
 }
 
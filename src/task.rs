@@ -1,3 +1,12 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use systemtable::boot_services_available;
+
+/// Type for EFI_TPL, the task priority level. Only the levels named here may be raised to;
+/// the firmware may run between them, so levels it reports, such as the one `raise_tpl`
+/// returns, are plain numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(usize)]
 pub enum TPL {
     Application = 4,
@@ -5,3 +14,80 @@ pub enum TPL {
     Notify = 16,
     HighLevel = 31
 }
+
+impl TPL {
+    /// The named level `level`, or `None` for the levels between them.
+    pub fn from_usize(level: usize) -> Option<TPL> {
+        match level {
+            4 => Some(TPL::Application),
+            8 => Some(TPL::Callback),
+            16 => Some(TPL::Notify),
+            31 => Some(TPL::HighLevel),
+            _ => None,
+        }
+    }
+}
+
+/// State shared between the application and event callbacks. Callbacks preempt the
+/// application whenever the TPL is below theirs, so the state is only reached through `with`,
+/// which raises the TPL to `TPL::HighLevel` for the duration. Before the system table is set
+/// and after boot services are exited there are no callbacks, and the TPL is left alone.
+pub(crate) struct TplCell<T> {
+    value: UnsafeCell<T>,
+    busy: AtomicBool,
+}
+
+// Only one `with` at a time reaches the value: callbacks are held off while it runs, and
+// `busy` catches a nested call, or another thread in host tests.
+unsafe impl<T: Send> Sync for TplCell<T> {}
+
+impl<T> TplCell<T> {
+    pub(crate) const fn new(value: T) -> TplCell<T> {
+        TplCell { value: UnsafeCell::new(value), busy: AtomicBool::new(false) }
+    }
+
+    /// Run `f` on the value with callbacks held off. Panics if `f` reaches the same cell.
+    pub(crate) fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        // Raising to `TPL::HighLevel` can't fail.
+        let old_tpl = if boot_services_available() {
+            ::get_system_table().boot_services().raise_tpl(TPL::HighLevel).ok()
+        } else {
+            None
+        };
+        let restore = || if let Some(old_tpl) = old_tpl {
+            ::get_system_table().boot_services().restore_tpl(old_tpl);
+        };
+        if self.busy.swap(true, Ordering::Acquire) {
+            restore();
+            panic!("TplCell reentered");
+        }
+
+        let r = f(unsafe { &mut *self.value.get() });
+        self.busy.store(false, Ordering::Release);
+        restore();
+        r
+    }
+}
+
+#[test]
+fn tpl_levels() {
+    assert!(TPL::Application < TPL::Callback);
+    assert!(TPL::Notify < TPL::HighLevel);
+    assert_eq!(TPL::from_usize(TPL::Notify as usize), Some(TPL::Notify));
+    assert_eq!(TPL::from_usize(5), None);
+    assert_eq!(TPL::from_usize(32), None);
+}
+
+#[test]
+fn tpl_cell() {
+    let cell = TplCell::new(1);
+    assert_eq!(cell.with(|v| { *v += 1; *v }), 2);
+    assert_eq!(cell.with(|v| *v), 2);
+}
+
+#[test]
+#[should_panic(expected = "TplCell reentered")]
+fn tpl_cell_reentered() {
+    let cell = TplCell::new(0);
+    cell.with(|_| cell.with(|_| ()));
+}
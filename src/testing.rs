@@ -0,0 +1,345 @@
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use base::Status;
+use console::{Console, SimpleTextOutput};
+use guid::Guid;
+use protocol::SerialIOProtocol;
+use runtimeservices::{EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_NON_VOLATILE};
+use util::wire;
+
+/// Seconds each test may run before the watchdog resets the machine, with `run_tests`.
+pub const TEST_TIMEOUT_SECONDS: usize = 120;
+
+/// Watchdog code the test runner arms the timer with, so firmware logs show a test timed out.
+/// Codes below 0x10000 are reserved for the firmware.
+pub const TEST_WATCHDOG_CODE: u64 = 0x5445_5354;
+
+/// Vendor GUID of the variable recording which test is running.
+pub static TEST_PROGRESS_VARIABLE_GUID: Guid = Guid(0x2D8A6C0E, 0x7F41, 0x4B9A, [0x9C, 0x3E, 0x51, 0xA4, 0x0B, 0x6F, 0xD2, 0x17]);
+
+/// Name of the variable recording which test is running.
+pub const TEST_PROGRESS_VARIABLE: &str = "TestProgress";
+
+/// Longest test name recorded in the progress variable; longer names are cut short.
+const MAX_RECORDED_NAME: usize = 96;
+
+/// A test run by `run_tests`. Tests fail by returning an error, usually through `efi_assert!`
+/// and the related macros, which also report what failed.
+pub struct TestCase {
+    pub name: &'static str,
+    pub run: fn() -> Result<(), Status>,
+}
+
+/// Outcome of `run_tests`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TestSummary {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl TestSummary {
+    /// `Success` if every test passed, `Aborted` otherwise. This is what the test application
+    /// returns to the firmware, so a shell script or host-side runner can check it.
+    pub fn status(&self) -> Status {
+        if self.failed == 0 {
+            Status::Success
+        } else {
+            Status::Aborted
+        }
+    }
+}
+
+/// Writes test output to the console and, if there is one, the first serial port, where a
+/// host-side runner driving QEMU can read it.
+pub struct TestReporter {
+    console: Console,
+    serial: Option<SerialIOProtocol>,
+}
+
+impl Default for TestReporter {
+    fn default() -> TestReporter {
+        TestReporter::new()
+    }
+}
+
+impl TestReporter {
+    pub fn new() -> TestReporter {
+        let st = ::get_system_table();
+
+        TestReporter {
+            console: st.console(),
+            serial: SerialIOProtocol::new().ok(),
+        }
+    }
+
+    /// Print one line of output.
+    pub fn note(&mut self, args: fmt::Arguments) {
+        let _ = self.write_fmt(args);
+        let _ = self.write_str("\r\n");
+    }
+}
+
+impl fmt::Write for TestReporter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // A broken serial port should not hide the results on screen, so errors are ignored here.
+        if let Some(ref serial) = self.serial {
+            let _ = serial.write(s);
+        }
+
+        if self.console.write(s) == Status::Success {
+            return Ok(());
+        }
+        Err(fmt::Error)
+    }
+}
+
+/// Assertions failed since the application started, from tests or the callbacks they set up.
+static ASSERTION_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Report a failed assertion at `file`:`line` on the console and serial port, and count it
+/// against the test running, which `run_tests` then fails even if the error is discarded.
+/// Normally called through `efi_assert!` and the related macros.
+pub fn assertion_failed(file: &str, line: u32, args: fmt::Arguments) {
+    ASSERTION_FAILURES.fetch_add(1, Ordering::Relaxed);
+    TestReporter::new().note(format_args!("\r\nassertion failed at {}:{}: {}", file, line, args));
+}
+
+/// How many assertions have failed since the application started.
+pub fn assertion_failure_count() -> usize {
+    ASSERTION_FAILURES.load(Ordering::Relaxed)
+}
+
+/// Where a run of tests had got to, kept in a non-volatile variable while each test runs so
+/// that after a watchdog reset the next run knows which test hung.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TestProgress {
+    index: usize,
+    passed: usize,
+    failed: usize,
+}
+
+impl TestProgress {
+    /// The variable's contents: the index and counts, then the name of the running test.
+    fn encode<'a>(&self, name: &str, buf: &'a mut [u8; 12 + MAX_RECORDED_NAME]) -> &'a [u8] {
+        let _ = wire::write_u32(buf, 0, self.index as u32);
+        let _ = wire::write_u32(buf, 4, self.passed as u32);
+        let _ = wire::write_u32(buf, 8, self.failed as u32);
+        let len = name.len().min(MAX_RECORDED_NAME);
+        buf[12..12 + len].copy_from_slice(&name.as_bytes()[..len]);
+        &buf[..12 + len]
+    }
+
+    /// The progress in `data`, if it was recorded for the test at its index in `tests`.
+    fn decode(data: &[u8], tests: &[TestCase]) -> Option<TestProgress> {
+        let progress = TestProgress {
+            index: wire::read_u32(data, 0).ok()? as usize,
+            passed: wire::read_u32(data, 4).ok()? as usize,
+            failed: wire::read_u32(data, 8).ok()? as usize,
+        };
+        let name = tests.get(progress.index)?.name.as_bytes();
+        let recorded = data.get(12..)?;
+        if recorded != &name[..name.len().min(MAX_RECORDED_NAME)] {
+            return None;
+        }
+        Some(progress)
+    }
+}
+
+fn save_progress(progress: &TestProgress, name: &str) {
+    let mut buf = [0; 12 + MAX_RECORDED_NAME];
+    // Without variables, a hang is still cut short by the watchdog, just not reported after.
+    let _ = ::get_system_table().runtime_services().set_variable(TEST_PROGRESS_VARIABLE, &TEST_PROGRESS_VARIABLE_GUID,
+                                                                 EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS,
+                                                                 progress.encode(name, &mut buf));
+}
+
+/// The progress recorded by a run of `tests` that the watchdog cut short, removing the record.
+fn take_progress(tests: &[TestCase]) -> Option<TestProgress> {
+    let rs = ::get_system_table().runtime_services();
+    let mut buf = [0; 12 + MAX_RECORDED_NAME];
+    let (len, _) = rs.get_variable(TEST_PROGRESS_VARIABLE, &TEST_PROGRESS_VARIABLE_GUID, &mut buf).ok()?;
+    let _ = rs.delete_variable(TEST_PROGRESS_VARIABLE, &TEST_PROGRESS_VARIABLE_GUID);
+    TestProgress::decode(&buf[..len], tests)
+}
+
+/// Run `tests` in order, printing a line per test and a final summary in a format similar to
+/// `cargo test`:
+///
+/// ```text
+/// running 2 tests
+/// test tests::variables ... ok
+/// test tests::block_io ... FAILED (not found)
+///
+/// test result: FAILED. 1 passed; 1 failed
+/// ```
+///
+/// A test that returns `Ok` after an assertion in it failed still counts as failed. Each test
+/// may run for `TEST_TIMEOUT_SECONDS`; see `run_tests_with_timeout`.
+pub fn run_tests(tests: &[TestCase]) -> TestSummary {
+    run_tests_with_timeout(tests, TEST_TIMEOUT_SECONDS)
+}
+
+/// Run `tests` as `run_tests` does, giving each `timeout` seconds before the firmware watchdog
+/// resets the machine.
+///
+/// The test running is recorded in a non-volatile variable, so when the same tests run again
+/// after such a reset the one that hung is reported as failed, and the run carries on with the
+/// next, keeping the counts of the earlier tests. A host-side runner driving QEMU therefore
+/// sees a complete result without intervention, as long as the machine boots back into the
+/// tests.
+pub fn run_tests_with_timeout(tests: &[TestCase], timeout: usize) -> TestSummary {
+    let bs = ::get_system_table().boot_services();
+    let mut reporter = TestReporter::new();
+    let mut summary = TestSummary { passed: 0, failed: 0 };
+    let mut start = 0;
+
+    reporter.note(format_args!("running {} tests", tests.len()));
+    if let Some(progress) = take_progress(tests) {
+        reporter.note(format_args!("test {} ... FAILED (timed out after {}s, watchdog reset)",
+                                   tests[progress.index].name, timeout));
+        summary = TestSummary { passed: progress.passed, failed: progress.failed + 1 };
+        start = progress.index + 1;
+    }
+
+    for (index, test) in tests.iter().enumerate().skip(start) {
+        save_progress(&TestProgress { index, passed: summary.passed, failed: summary.failed }, test.name);
+        bs.set_watchdog_timer(timeout, TEST_WATCHDOG_CODE);
+
+        let _ = write!(reporter, "test {} ... ", test.name);
+        let failures = assertion_failure_count();
+        match (test.run)() {
+            Ok(()) if assertion_failure_count() == failures => {
+                summary.passed += 1;
+                reporter.note(format_args!("ok"));
+            }
+            Ok(()) => {
+                summary.failed += 1;
+                reporter.note(format_args!("FAILED (assertion)"));
+            }
+            Err(status) => {
+                summary.failed += 1;
+                reporter.note(format_args!("FAILED ({})", status));
+            }
+        }
+    }
+
+    bs.set_watchdog_timer(0, 0);
+    let _ = ::get_system_table().runtime_services().delete_variable(TEST_PROGRESS_VARIABLE, &TEST_PROGRESS_VARIABLE_GUID);
+
+    reporter.note(format_args!(""));
+    reporter.note(format_args!("test result: {}. {} passed; {} failed",
+                               if summary.failed == 0 { "ok" } else { "FAILED" },
+                               summary.passed, summary.failed));
+    summary
+}
+
+/// Like `assert!`, for code running in firmware: unless `cond` holds, report the condition, or
+/// the message given, with its location on the console and serial port, and return
+/// `Err(Status::Aborted)` from the enclosing function. The failure is counted by `run_tests`.
+///
+/// ```rust,ignore
+/// efi_assert!(map.len() > 0);
+/// efi_assert!(time.is_valid(), "bad time {:?}", time);
+/// ```
+#[macro_export]
+macro_rules! efi_assert {
+    ($cond:expr) => {
+        $crate::efi_assert!($cond, "{}", stringify!($cond))
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::assertion_failed(file!(), line!(), format_args!($($arg)+));
+            return Err($crate::Status::Aborted);
+        }
+    };
+}
+
+/// Like `assert_eq!`, reporting both values as `efi_assert!` does.
+#[macro_export]
+macro_rules! efi_assert_eq {
+    ($left:expr, $right:expr) => {
+        $crate::efi_assert_eq!($left, $right, "{} == {}", stringify!($left), stringify!($right))
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left == *right) {
+                    $crate::assertion_failed(file!(), line!(), format_args!("{}\r\n  left: {:?}\r\n right: {:?}",
+                                                                            format_args!($($arg)+), left, right));
+                    return Err($crate::Status::Aborted);
+                }
+            }
+        }
+    };
+}
+
+/// Like `assert_ne!`, reporting both values as `efi_assert!` does.
+#[macro_export]
+macro_rules! efi_assert_ne {
+    ($left:expr, $right:expr) => {
+        $crate::efi_assert_ne!($left, $right, "{} != {}", stringify!($left), stringify!($right))
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if *left == *right {
+                    $crate::assertion_failed(file!(), line!(), format_args!("{}\r\n  left: {:?}\r\n right: {:?}",
+                                                                            format_args!($($arg)+), left, right));
+                    return Err($crate::Status::Aborted);
+                }
+            }
+        }
+    };
+}
+
+/// Define the `efi_entry` of a test application which runs the given test functions, each of type
+/// `fn() -> Result<(), uefi::Status>`, and returns `Success` only if all of them pass.
+///
+/// ```rust,ignore
+/// fn reads_time() -> Result<(), uefi::Status> {
+///     let time = uefi::get_system_table().runtime_services().get_time()?;
+///     efi_assert!(time.is_valid(), "invalid time {:?}", time);
+///     Ok(())
+/// }
+///
+/// efi_test_main!(reads_time, tests::variables);
+/// ```
+///
+/// Booted in QEMU with OVMF and `-serial stdio`, the results appear on the host's standard output.
+#[macro_export]
+macro_rules! efi_test_main {
+    ($($test:path),* $(,)*) => {
+        #[no_mangle]
+        pub extern "win64" fn efi_entry(image_handle: $crate::Handle,
+                                        system_table: *const $crate::SystemTable)
+                                        -> isize {
+            $crate::set_system_table(system_table);
+            if let Err(status) = $crate::protocol::set_current_image(image_handle) {
+                return $crate::exit_code(status);
+            }
+
+            let tests = [$($crate::TestCase { name: stringify!($test), run: $test }),*];
+            $crate::exit_code($crate::run_tests(&tests).status())
+        }
+    };
+}
+
+#[test]
+fn test_progress_roundtrip() {
+    fn pass() -> Result<(), Status> {
+        Ok(())
+    }
+    let tests = [TestCase { name: "first", run: pass }, TestCase { name: "second", run: pass }];
+
+    let mut buf = [0; 12 + MAX_RECORDED_NAME];
+    let progress = TestProgress { index: 1, passed: 1, failed: 0 };
+    let data = progress.encode("second", &mut buf);
+    assert_eq!(TestProgress::decode(data, &tests), Some(progress));
+
+    // A record from another set of tests is ignored.
+    let mut buf = [0; 12 + MAX_RECORDED_NAME];
+    let data = progress.encode("other", &mut buf);
+    assert_eq!(TestProgress::decode(data, &tests), None);
+    assert_eq!(TestProgress::decode(&[1, 0], &tests), None);
+}
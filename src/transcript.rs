@@ -0,0 +1,245 @@
+//! A boot transcript: a log of what the application did (files loaded, entries chosen, errors),
+//! kept in memory and written to `\EFI\<app>\lastboot.log` on the volume it was loaded from
+//! when it exits or hands over to a kernel, so a failed boot can be looked at afterwards.
+//!
+//! ```rust,ignore
+//! start_transcript("mybootloader");
+//! transcript!("loading {}", path);
+//! transcript!("start_image: {:?}", status);
+//! ```
+
+use core::{fmt, str};
+
+use base::{Status, Time};
+use protocol::{self, FileProtocol, SimpleFileSystemProtocol, EFI_FILE_MODE_READ, EFI_FILE_MODE_WRITE};
+use task::TplCell;
+
+/// Bytes of transcript kept. Lines after it is full are dropped, and a note saying so is
+/// added when it is written.
+pub const TRANSCRIPT_CAPACITY: usize = 16 * 1024;
+
+/// Name of the transcript file, in the application's directory under `\EFI`.
+pub const TRANSCRIPT_FILE: &str = "lastboot.log";
+
+/// Transcripts kept: the latest as `lastboot.log` and earlier ones as `lastboot.1.log` and so
+/// on.
+pub const TRANSCRIPT_KEEP: usize = 3;
+
+const TRUNCATED_NOTE: &str = "... transcript full, later lines dropped\r\n";
+
+#[derive(Clone, Copy)]
+struct Transcript {
+    app: Option<&'static str>,
+    buf: [u8; TRANSCRIPT_CAPACITY],
+    len: usize,
+    truncated: bool,
+    rotated: bool,
+}
+
+impl Transcript {
+    /// Append a line, with `time` in front if there is one.
+    fn append(&mut self, time: Option<Time>, args: fmt::Arguments) {
+        if self.app.is_none() || self.truncated {
+            return;
+        }
+
+        // Leave room for the note added when the transcript fills up.
+        let end = TRANSCRIPT_CAPACITY - TRUNCATED_NOTE.len();
+        let mut line = LineWriter { buf: &mut self.buf[..end], len: self.len };
+        let result = match time {
+            Some(time) => fmt::write(&mut line, format_args!("{:02}:{:02}:{:02} ", time.hour, time.minute, time.second)),
+            None => Ok(()),
+        };
+        match result.and_then(|_| fmt::write(&mut line, args)).and_then(|_| fmt::Write::write_str(&mut line, "\r\n")) {
+            Ok(()) => self.len = line.len,
+            Err(_) => self.truncated = true,
+        }
+    }
+}
+
+/// Appended to from console output, including output from event callbacks.
+static TRANSCRIPT: TplCell<Transcript> = TplCell::new(Transcript {
+    app: None,
+    buf: [0; TRANSCRIPT_CAPACITY],
+    len: 0,
+    truncated: false,
+    rotated: false,
+});
+
+/// Appends to the buffer, failing once a line does not fit.
+struct LineWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> fmt::Write for LineWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Start recording a transcript for the application `app`, which names its directory under
+/// `\EFI`. Anything recorded before is discarded.
+pub fn start_transcript(app: &'static str) {
+    TRANSCRIPT.with(|t| {
+        t.app = Some(app);
+        t.len = 0;
+        t.truncated = false;
+        t.rotated = false;
+    });
+}
+
+/// Append a line to the transcript, with the time in front. Does nothing unless
+/// `start_transcript` was called. Normally used through `transcript!`.
+pub fn transcript(args: fmt::Arguments) {
+    // Read before holding callbacks off, which GetTime can't be called with.
+    let time = ::get_system_table().runtime_services().get_time().ok();
+    TRANSCRIPT.with(|t| t.append(time, args));
+}
+
+/// Write the transcript recorded so far to `\EFI\<app>\lastboot.log` on the volume the
+/// application was loaded from. The first write of a run moves the transcripts of earlier runs
+/// along, keeping `TRANSCRIPT_KEEP`; later writes replace this run's file. Does nothing unless
+/// `start_transcript` was called.
+///
+/// This is done by `efi_main!` when the application returns and by
+/// `HandoffBuilder::exit_boot_services`, so it only needs calling before other ways of leaving,
+/// such as starting another image that does not return.
+pub fn flush_transcript() -> Result<(), Status> {
+    // Written from a copy, since file access can't be done with callbacks held off. Lines
+    // added meanwhile are in the next write. Rotating is claimed up front so that a callback
+    // flushing meanwhile doesn't rotate again.
+    let mut t = TRANSCRIPT.with(|t| {
+        let copy = *t;
+        t.rotated = t.app.is_some();
+        copy
+    });
+    let app = match t.app {
+        Some(app) => app,
+        None => return Ok(()),
+    };
+
+    let result = (|| {
+        let device = protocol::get_current_image().device_handle;
+        let fs: &SimpleFileSystemProtocol = ::get_system_table().boot_services().handle_protocol(device)?;
+        let root = fs.open_volume()?;
+        let result = write_transcript(root, app, &mut t);
+        root.close();
+        result
+    })();
+    if !t.rotated {
+        TRANSCRIPT.with(|live| live.rotated = false);
+    }
+    result
+}
+
+fn write_transcript(root: &FileProtocol, app: &str, t: &mut Transcript) -> Result<(), Status> {
+    let mut path = [0u8; 128];
+    let dir = {
+        let mut w = LineWriter { buf: &mut path, len: 0 };
+        fmt::write(&mut w, format_args!("\\EFI\\{}\\", app)).map_err(|_| Status::InvalidParameter)?;
+        w.len
+    };
+
+    if !t.rotated {
+        rotate(root, &mut path, dir)?;
+        t.rotated = true;
+    }
+
+    let file = root.create_path(path_str(&mut path, dir, TRANSCRIPT_FILE, None)?)?;
+    let mut result = file.write_all(&t.buf[..t.len]);
+    if t.truncated && result.is_ok() {
+        result = file.write_all(TRUNCATED_NOTE.as_bytes());
+    }
+    let flushed = file.flush();
+    file.close();
+    result?;
+    match flushed {
+        Status::Success => Ok(()),
+        e => Err(e),
+    }
+}
+
+/// `path` with `name` appended after the directory prefix of length `dir`, and `.n` before the
+/// extension if `n` is given.
+fn path_str<'a>(path: &'a mut [u8; 128], dir: usize, name: &str, n: Option<usize>) -> Result<&'a str, Status> {
+    let mut w = LineWriter { buf: &mut path[..], len: dir };
+    let written = match (n, name.rfind('.')) {
+        (Some(n), Some(dot)) => fmt::write(&mut w, format_args!("{}.{}{}", &name[..dot], n, &name[dot..])),
+        (Some(n), None) => fmt::write(&mut w, format_args!("{}.{}", name, n)),
+        (None, _) => fmt::Write::write_str(&mut w, name),
+    };
+    written.map_err(|_| Status::InvalidParameter)?;
+    let len = w.len;
+    str::from_utf8(&path[..len]).map_err(|_| Status::InvalidParameter)
+}
+
+/// Move `lastboot.log` to `lastboot.1.log` and so on, dropping the oldest.
+fn rotate(root: &FileProtocol, path: &mut [u8; 128], dir: usize) -> Result<(), Status> {
+    for n in (0..TRANSCRIPT_KEEP).rev() {
+        let from = if n == 0 { None } else { Some(n) };
+        let mut name = [0u8; 128];
+        let to = path_str(&mut name, 0, TRANSCRIPT_FILE, Some(n + 1))?;
+        let file = match root.open_path(path_str(path, dir, TRANSCRIPT_FILE, from)?, EFI_FILE_MODE_READ | EFI_FILE_MODE_WRITE) {
+            Ok(file) => file,
+            Err(Status::NotFound) => continue,
+            Err(e) => return Err(e),
+        };
+
+        if n + 1 == TRANSCRIPT_KEEP {
+            file.delete();
+            continue;
+        }
+        let result = file.rename(to);
+        file.close();
+        result?;
+    }
+    Ok(())
+}
+
+/// Append a line to the boot transcript, formatted like `format!`. See `start_transcript`.
+#[macro_export]
+macro_rules! transcript {
+    ($($arg:tt)*) => {
+        $crate::transcript(format_args!($($arg)*))
+    };
+}
+
+#[test]
+fn transcript_paths() {
+    let mut path = [0u8; 128];
+    path[..11].copy_from_slice(b"\\EFI\\myapp\\");
+    assert_eq!(path_str(&mut path, 11, TRANSCRIPT_FILE, None), Ok("\\EFI\\myapp\\lastboot.log"));
+    assert_eq!(path_str(&mut path, 11, TRANSCRIPT_FILE, Some(2)), Ok("\\EFI\\myapp\\lastboot.2.log"));
+    assert_eq!(path_str(&mut path, 0, "notes", Some(1)), Ok("notes.1"));
+}
+
+#[test]
+fn transcript_truncation() {
+    let mut t = Transcript { app: None, buf: [0; TRANSCRIPT_CAPACITY], len: 0, truncated: false, rotated: false };
+    t.append(None, format_args!("before start"));
+    assert_eq!(t.len, 0);
+
+    t.app = Some("myapp");
+    let time = Time::new(2020, 1, 2, 3, 4, 5, 0, 0).unwrap();
+    t.append(Some(time), format_args!("loading {}", "\\vmlinuz"));
+    assert_eq!(&t.buf[..t.len], b"03:04:05 loading \\vmlinuz\r\n");
+
+    let long = [b'x'; 1000];
+    let long = str::from_utf8(&long).unwrap();
+    while !t.truncated {
+        t.append(None, format_args!("{}", long));
+    }
+    // The line that didn't fit is left out whole, with room kept for the note.
+    assert_eq!((t.len - 27) % 1002, 0);
+    assert!(t.len <= TRANSCRIPT_CAPACITY - TRUNCATED_NOTE.len());
+    let len = t.len;
+    t.append(None, format_args!("after"));
+    assert_eq!(t.len, len);
+}
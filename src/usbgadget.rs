@@ -0,0 +1,280 @@
+//! A vendor-specific USB bulk device over `UsbFnIoProtocol`, for manufacturing and diagnostic
+//! tools that talk to a host through a pair of bulk endpoints: the host opens the device by its
+//! vendor and product IDs with libusb or WinUSB and exchanges raw data with it.
+//!
+//! ```rust,ignore
+//! let usbfn = bs.locate_protocol::<UsbFnIoProtocol>(ptr::null())?;
+//! let arena = Arena::new();
+//! let mut gadget = BulkGadget::start(usbfn, &arena, 0x1209, 0x0001)?;
+//! gadget.wait_connected()?;
+//! let n = gadget.read(&mut command)?;
+//! gadget.write(b"OK")?;
+//! ```
+
+use base::Status;
+use arena::Arena;
+use protocol::{
+    UsbBusSpeed, UsbConfigDescriptor, UsbConfigInfo, UsbDeviceDescriptor, UsbDeviceInfo, UsbEndpointDescriptor,
+    UsbEndpointDirection, UsbEndpointType, UsbFnEvent, UsbFnIoProtocol, UsbFnTransferStatus, UsbInterfaceDescriptor,
+    UsbInterfaceInfo, USB_DESC_TYPE_CONFIG, USB_DESC_TYPE_DEVICE, USB_DESC_TYPE_ENDPOINT, USB_DESC_TYPE_INTERFACE,
+    USB_ENDPOINT_DIR_IN,
+};
+
+/// Size of each of the gadget's transfer buffers, unless the controller's largest transfer is
+/// smaller.
+pub const BULK_BUFFER_SIZE: usize = 4096;
+
+/// The endpoint number of both bulk endpoints.
+pub const BULK_ENDPOINT: u8 = 1;
+
+/// Interface class for vendor-specific interfaces.
+const USB_CLASS_VENDOR_SPECIFIC: u8 = 0xFF;
+
+/// The descriptors of a device with one configuration, holding one vendor-specific interface
+/// with a bulk OUT and a bulk IN endpoint.
+fn bulk_descriptors(vendor_id: u16, product_id: u16, max_packet_size: u16)
+    -> (UsbDeviceDescriptor, UsbConfigDescriptor, UsbInterfaceDescriptor, [UsbEndpointDescriptor; 2])
+{
+    let device = UsbDeviceDescriptor {
+        length: 18,
+        descriptor_type: USB_DESC_TYPE_DEVICE,
+        bcd_usb: if max_packet_size > 512 { 0x0300 } else { 0x0200 },
+        device_class: 0,
+        device_sub_class: 0,
+        device_protocol: 0,
+        max_packet_size0: if max_packet_size > 512 { 9 } else { 64 },
+        id_vendor: vendor_id,
+        id_product: product_id,
+        bcd_device: 0x0100,
+        str_manufacturer: 0,
+        str_product: 0,
+        str_serial_number: 0,
+        num_configurations: 1,
+    };
+    let config = UsbConfigDescriptor {
+        length: 9,
+        descriptor_type: USB_DESC_TYPE_CONFIG,
+        total_length: 9 + 9 + 7 * 2,
+        num_interfaces: 1,
+        configuration_value: 1,
+        configuration: 0,
+        // Bus powered.
+        attributes: 0x80,
+        max_power: 250,
+    };
+    let interface = UsbInterfaceDescriptor {
+        length: 9,
+        descriptor_type: USB_DESC_TYPE_INTERFACE,
+        interface_number: 0,
+        alternate_setting: 0,
+        num_endpoints: 2,
+        interface_class: USB_CLASS_VENDOR_SPECIFIC,
+        interface_sub_class: 0,
+        interface_protocol: 0,
+        interface: 0,
+    };
+    let endpoint = |address| UsbEndpointDescriptor {
+        length: 7,
+        descriptor_type: USB_DESC_TYPE_ENDPOINT,
+        endpoint_address: address,
+        attributes: UsbEndpointType::Bulk as u8,
+        max_packet_size,
+        interval: 0,
+    };
+    (device, config, interface, [endpoint(BULK_ENDPOINT), endpoint(BULK_ENDPOINT | USB_ENDPOINT_DIR_IN)])
+}
+
+/// A started USB device with one bulk endpoint each way. Dropping it disconnects from the host.
+pub struct BulkGadget<'a> {
+    usbfn: &'a UsbFnIoProtocol,
+    rx: &'static mut [u8],
+    tx: &'static mut [u8],
+    connected: bool,
+    rx_pending: bool,
+    tx_pending: bool,
+    /// Received bytes not yet returned by `try_read`, at `rx[rx_start..rx_end]`.
+    rx_start: usize,
+    rx_end: usize,
+    tx_status: UsbFnTransferStatus,
+}
+
+impl<'a> BulkGadget<'a> {
+    /// Start the device controller and present a vendor-specific device with the given IDs.
+    /// The descriptors are kept in `arena`, as the driver refers to them for as long as the
+    /// controller runs.
+    pub fn start(usbfn: &'a UsbFnIoProtocol, arena: &'a Arena, vendor_id: u16, product_id: u16) -> Result<BulkGadget<'a>, Status> {
+        let max_packet_size = usbfn.endpoint_max_packet_size(UsbEndpointType::Bulk, UsbBusSpeed::High).unwrap_or(512);
+        let (device, config, interface, endpoints) = bulk_descriptors(vendor_id, product_id, max_packet_size);
+
+        let endpoints: &'a [UsbEndpointDescriptor; 2] = arena.alloc(endpoints)?;
+        let endpoint_table: &'a [&'a UsbEndpointDescriptor; 2] = arena.alloc([&endpoints[0], &endpoints[1]])?;
+        let interface_info: &'a UsbInterfaceInfo<'a> = arena.alloc(UsbInterfaceInfo::new(arena.alloc(interface)?, endpoint_table)?)?;
+        let interface_table: &'a [&'a UsbInterfaceInfo<'a>; 1] = arena.alloc([interface_info])?;
+        let config_info: &'a UsbConfigInfo<'a> = arena.alloc(UsbConfigInfo::new(arena.alloc(config)?, interface_table)?)?;
+        let config_table: &'a [&'a UsbConfigInfo<'a>; 1] = arena.alloc([config_info])?;
+        let device_info: &'a UsbDeviceInfo<'a> = arena.alloc(UsbDeviceInfo::new(arena.alloc(device)?, config_table)?)?;
+
+        let size = usbfn.max_transfer_size().map(|max| max.min(BULK_BUFFER_SIZE)).unwrap_or(BULK_BUFFER_SIZE);
+        let rx = usbfn.allocate_transfer_buffer(size)?;
+        let tx = match usbfn.allocate_transfer_buffer(size) {
+            Ok(tx) => tx,
+            Err(e) => {
+                let _ = unsafe { usbfn.free_transfer_buffer(rx.as_mut_ptr()) };
+                return Err(e);
+            }
+        };
+
+        // From here dropping the gadget undoes everything.
+        let gadget = BulkGadget {
+            usbfn,
+            rx,
+            tx,
+            connected: false,
+            rx_pending: false,
+            tx_pending: false,
+            rx_start: 0,
+            rx_end: 0,
+            tx_status: UsbFnTransferStatus::None,
+        };
+        usbfn.start_controller()?;
+        usbfn.configure_enable_endpoints(device_info)?;
+        Ok(gadget)
+    }
+
+    /// Whether a host has enumerated the device.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Handle the next event from the controller, and return it. Requests on endpoint 0 other
+    /// than the standard ones, which the driver answers itself, are refused.
+    pub fn poll(&mut self) -> Result<UsbFnEvent, Status> {
+        let event = self.usbfn.event_handler()?;
+        match event {
+            UsbFnEvent::SetupPacket(request) => {
+                let direction = if request.request_type & 0x80 != 0 {
+                    UsbEndpointDirection::HostIn
+                } else {
+                    UsbEndpointDirection::HostOut
+                };
+                self.usbfn.set_endpoint_stall_state(0, direction, true)?;
+            }
+            UsbFnEvent::EndpointStatusChangedRx(result) if result.endpoint_index() == BULK_ENDPOINT => {
+                self.rx_pending = false;
+                if result.status() == UsbFnTransferStatus::Complete {
+                    self.rx_start = 0;
+                    self.rx_end = result.bytes_transferred().min(self.rx.len());
+                }
+            }
+            UsbFnEvent::EndpointStatusChangedTx(result) if result.endpoint_index() == BULK_ENDPOINT => {
+                self.tx_pending = false;
+                self.tx_status = result.status();
+            }
+            UsbFnEvent::BusEventSpeed(_) => self.connected = true,
+            UsbFnEvent::DetachedFromHost => {
+                self.connected = false;
+                self.abort();
+            }
+            _ => {}
+        }
+        Ok(event)
+    }
+
+    /// Poll until a host has enumerated the device.
+    pub fn wait_connected(&mut self) -> Result<(), Status> {
+        while !self.connected {
+            self.poll()?;
+        }
+        Ok(())
+    }
+
+    fn abort(&mut self) {
+        if self.rx_pending {
+            let _ = self.usbfn.abort_transfer(BULK_ENDPOINT, UsbEndpointDirection::HostOut);
+            self.rx_pending = false;
+        }
+        if self.tx_pending {
+            let _ = self.usbfn.abort_transfer(BULK_ENDPOINT, UsbEndpointDirection::HostIn);
+            self.tx_pending = false;
+            self.tx_status = UsbFnTransferStatus::Aborted;
+        }
+    }
+
+    /// Copy data the host has sent into `buf`, returning how much. Returns 0 if nothing has
+    /// arrived yet; fails with `Status::NotReady` while no host is connected.
+    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<usize, Status> {
+        if self.rx_start == self.rx_end {
+            if !self.rx_pending {
+                if !self.connected {
+                    return Err(Status::NotReady);
+                }
+                // The buffer stays with the gadget, untouched, until the transfer is reported.
+                unsafe { self.usbfn.transfer(BULK_ENDPOINT, UsbEndpointDirection::HostOut, self.rx.as_mut_ptr(), self.rx.len())? };
+                self.rx_pending = true;
+            }
+            self.poll()?;
+        }
+
+        let n = buf.len().min(self.rx_end - self.rx_start);
+        buf[..n].copy_from_slice(&self.rx[self.rx_start..self.rx_start + n]);
+        self.rx_start += n;
+        Ok(n)
+    }
+
+    /// Wait for data from the host and copy it into `buf`, returning how much.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Status> {
+        loop {
+            match self.try_read(buf)? {
+                0 => continue,
+                n => return Ok(n),
+            }
+        }
+    }
+
+    /// Send `data` to the host, waiting until it has all been taken. Fails with
+    /// `Status::Aborted` if the host disconnects or the transfer is cancelled.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Status> {
+        for chunk in data.chunks(self.tx.len()) {
+            if !self.connected {
+                return Err(Status::NotReady);
+            }
+            self.tx[..chunk.len()].copy_from_slice(chunk);
+            unsafe { self.usbfn.transfer(BULK_ENDPOINT, UsbEndpointDirection::HostIn, self.tx.as_mut_ptr(), chunk.len())? };
+            self.tx_pending = true;
+            while self.tx_pending {
+                self.poll()?;
+            }
+            if self.tx_status != UsbFnTransferStatus::Complete {
+                return Err(Status::Aborted);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for BulkGadget<'a> {
+    fn drop(&mut self) {
+        self.abort();
+        let _ = self.usbfn.stop_controller();
+        unsafe {
+            let _ = self.usbfn.free_transfer_buffer(self.rx.as_mut_ptr());
+            let _ = self.usbfn.free_transfer_buffer(self.tx.as_mut_ptr());
+        }
+    }
+}
+
+#[test]
+fn bulk_gadget_descriptors() {
+    let (device, config, interface, endpoints) = bulk_descriptors(0x1209, 0x0001, 512);
+    assert_eq!({ device.bcd_usb }, 0x0200);
+    assert_eq!({ device.id_vendor }, 0x1209);
+    assert_eq!({ config.total_length } as usize, 9 + 9 + 7 * endpoints.len());
+    assert_eq!(interface.num_endpoints as usize, endpoints.len());
+    assert_eq!(endpoints[0].endpoint_address, 0x01);
+    assert_eq!(endpoints[1].endpoint_address, 0x81);
+    assert_eq!({ endpoints[1].max_packet_size }, 512);
+
+    let (device, _, _, _) = bulk_descriptors(0x1209, 0x0001, 1024);
+    assert_eq!({ device.bcd_usb }, 0x0300);
+    assert_eq!(device.max_packet_size0, 9);
+}
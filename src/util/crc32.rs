@@ -0,0 +1,44 @@
+//! Software CRC32, the IEEE 802.3 polynomial used by the firmware's CalculateCrc32, for
+//! checking GPT and table headers where boot services are not available.
+
+/// The reflected polynomial.
+const POLYNOMIAL: u32 = 0xEDB88320;
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [u32; 256] = make_table();
+
+/// Continue the CRC32 `crc` of earlier data over `data`. Start from 0.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in data {
+        crc = TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// The CRC32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32_update(crc32(b"1234"), b"56789"), 0xCBF43926);
+    assert_eq!(crc32(&[]), 0);
+}
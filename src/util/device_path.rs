@@ -12,72 +12,67 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::slice;
+
 use base::Status;
-use protocol::{DevicePathProtocol, DevicePathUtilitiesProtocol, DevicePathTypes, EndPathSubTypes,
-               MediaSubTypes};
+use protocol::{DevicePathProtocol, DevicePathUtilitiesProtocol, DevicePathTypes,
+               EndPathSubTypes, MediaSubTypes, DEVICE_PATH_NODE_HEADER_SIZE};
 use void::CVoid;
-use util::*;
+use util::{char_to_ucs2, wire};
 
-pub fn create_file_device_node(filename: &str) -> Result<&DevicePathProtocol, Status> {
-    str_to_utf16_ptr(filename).and_then(|filename_ptr| {
-        let filename_len = utf16_strlen(filename_ptr);
-        let node_size_bytes = 4 + (filename_len + 1) * 2;
+/// A file path device node for `filename`, an `EfiPath` or a string in which forward slashes
+/// are taken as backslashes.
+pub fn create_file_device_node<P: AsRef<str>>(filename: P) -> Result<&'static DevicePathProtocol, Status> {
+    let filename = filename.as_ref();
+    let filename_len = filename.chars().count();
+    let node_size_bytes = 4 + (filename_len + 1) * 2;
+    if node_size_bytes > u16::MAX as usize {
+        return Err(Status::InvalidParameter);
+    }
 
-        ::get_system_table()
-            .boot_services()
-            .locate_protocol::<DevicePathUtilitiesProtocol>(0 as *const CVoid)
-            .and_then(|utilities| {
-                utilities.create_device_node(DevicePathTypes::Media, MediaSubTypes::FilePath, node_size_bytes as u16)
-                    .map(|node_ptr| {
-                        let node_filename_ptr: *mut u16 = unsafe { (node_ptr as *const u8).offset(4) as *mut u16 };
+    let utilities = ::get_system_table()
+        .boot_services()
+        .locate_protocol::<DevicePathUtilitiesProtocol>(0 as *const CVoid)?;
+    let node_ptr = utilities.create_device_node(DevicePathTypes::Media, MediaSubTypes::FilePath, node_size_bytes as u16)?;
 
-                        for i in 0..filename_len as isize{
-                            unsafe {
-                                *node_filename_ptr.offset(i) = *filename_ptr.offset(i);
-                            }
-                        }
-                        unsafe { *node_filename_ptr.offset(filename_len as isize) = 0 };
+    // The path name follows the 4 byte header and need not be aligned.
+    let node = unsafe { slice::from_raw_parts_mut(node_ptr as *mut u8, node_size_bytes) };
+    wire::write_ucs2(node, 4, filename.chars().map(|c| if c == '/' { '\\' } else { c }).map(char_to_ucs2))?;
 
-                        unsafe { &*node_ptr }
-                    })
-            })
-    })
+    Ok(unsafe { &*node_ptr })
 }
 
 /// Get the "parent" of a given device path - i.e., take all but the last DevicePathProtocol
 /// instance in the entire device path. This function allocates memory with `allocate_pool`, and it
 /// is the caller's responsibility to free it.
+///
+/// Every node length is checked, so a malformed path fails with `Status::InvalidParameter`
+/// rather than being read past its end.
 pub fn parent_device_path(
     src_device_path: &DevicePathProtocol,
 ) -> Result<&mut DevicePathProtocol, Status> {
+    let path = unsafe { src_device_path.path()? };
+
     // If the device path we're given is already an end, there's nothing we can do.
-    if src_device_path.type_ ==  DevicePathTypes::End.into() {
-        return Err(Status::InvalidParameter);
+    let mut last = None;
+    let mut offset = 0;
+    for node in path.nodes() {
+        last = Some(offset);
+        offset += node.len();
     }
+    let last = last.ok_or(Status::InvalidParameter)?;
 
-    ::get_system_table()
+    let utilities = ::get_system_table()
         .boot_services()
-        .locate_protocol::<DevicePathUtilitiesProtocol>(0 as *const CVoid)
-        .and_then(|utilities| {
-            utilities.duplicate_device_path(src_device_path).map(
-                |device_path| {
-                    let mut this_device_path_ptr = device_path as *mut DevicePathProtocol;
-                    loop {
-                        let next_device_path_ptr = unsafe { (&*this_device_path_ptr).next() };
-
-                        unsafe {
-                            if (*next_device_path_ptr).type_ == DevicePathTypes::End.into() {
-                                (*this_device_path_ptr).type_ = DevicePathTypes::End.into();
-                                (*this_device_path_ptr).sub_type = EndPathSubTypes::EndEntirePath
-                                    .into();
-                                (*this_device_path_ptr).length = [4, 0];
-                                return device_path;
-                            }
-                        }
+        .locate_protocol::<DevicePathUtilitiesProtocol>(0 as *const CVoid)?;
+    let device_path = utilities.duplicate_device_path(src_device_path)?;
+    let bytes = unsafe {
+        slice::from_raw_parts_mut(device_path as *mut DevicePathProtocol as *mut u8, path.total_len())
+    };
 
-                        this_device_path_ptr = next_device_path_ptr;
-                    }
-                },
-            )
-        })
+    // Overwrite the last node with the end of the path.
+    bytes[last] = DevicePathTypes::End.into();
+    bytes[last + 1] = EndPathSubTypes::EndEntirePath.into();
+    wire::write_u16(bytes, last + 2, DEVICE_PATH_NODE_HEADER_SIZE as u16)?;
+    Ok(device_path)
 }
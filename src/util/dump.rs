@@ -0,0 +1,101 @@
+use core::{fmt, ptr};
+
+use base::{MemoryType, Status};
+use memmap::MemoryMap;
+
+const BYTES_PER_ROW: usize = 16;
+
+/// Write `data` to `out` in the canonical hex+ASCII layout (as `hexdump -C` prints it), with 16
+/// bytes per row. Row offsets are printed starting at `address`.
+pub fn hexdump<W: fmt::Write>(data: &[u8], address: u64, out: &mut W) -> fmt::Result {
+    for (row, chunk) in data.chunks(BYTES_PER_ROW).enumerate() {
+        write!(out, "{:08x} ", address.wrapping_add((row * BYTES_PER_ROW) as u64))?;
+
+        for i in 0..BYTES_PER_ROW {
+            if i % 8 == 0 {
+                out.write_char(' ')?;
+            }
+            match chunk.get(i) {
+                Some(b) => write!(out, "{:02x} ", b)?,
+                None => out.write_str("   ")?,
+            }
+        }
+
+        out.write_str(" |")?;
+        for &b in chunk {
+            out.write_char(if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })?;
+        }
+        out.write_str("|\r\n")?;
+    }
+
+    Ok(())
+}
+
+/// Whether memory of type `t` is RAM that can be read without side effects.
+fn is_readable(t: MemoryType) -> bool {
+    matches!(t, MemoryType::LoaderCode | MemoryType::LoaderData |
+                MemoryType::BootServicesCode | MemoryType::BootServicesData |
+                MemoryType::RuntimeServicesCode | MemoryType::RuntimeServicesData |
+                MemoryType::Conventional | MemoryType::AcpiReclaimed | MemoryType::AcpiNvs)
+}
+
+/// Check that every byte of `[address, address + len)` lies in readable RAM according to the
+/// firmware memory map.
+fn check_readable(address: u64, len: usize) -> Result<(), Status> {
+    let end = address.checked_add(len as u64).ok_or(Status::InvalidParameter)?;
+    let map = MemoryMap::get()?;
+
+    // Regions may span several adjacent descriptors, so walk forward from `address` until `end`
+    // is reached or a gap or unreadable region is found.
+    let mut current = address;
+    while current < end {
+        match map.iter().find(|d| current >= d.physical_start() && current < d.physical_end()) {
+            Some(d) if is_readable(d.memory_type()) => current = d.physical_end(),
+            _ => return Err(Status::AccessDenied),
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy physical memory at `address` into `buf`, after checking the memory map that the whole
+/// range is RAM. MMIO, reserved and unmapped ranges are refused with `Status::AccessDenied`, so
+/// debugging tools can't hang or fault the machine by reading from them.
+///
+/// Only valid while boot services are active, when memory is identity mapped.
+pub fn read_physical(address: u64, buf: &mut [u8]) -> Result<(), Status> {
+    check_readable(address, buf.len())?;
+
+    unsafe {
+        ptr::copy_nonoverlapping(address as usize as *const u8, buf.as_mut_ptr(), buf.len());
+    }
+    Ok(())
+}
+
+/// Hex dump `len` bytes of physical memory starting at `address`, with the same checks as
+/// `read_physical`.
+pub fn hexdump_physical<W: fmt::Write>(address: u64, len: usize, out: &mut W) -> Result<(), Status> {
+    check_readable(address, len)?;
+
+    let data = unsafe { ::core::slice::from_raw_parts(address as usize as *const u8, len) };
+    hexdump(data, address, out).map_err(|_| Status::DeviceError)
+}
+
+#[test]
+fn hexdump_layout() {
+    struct Out([u8; 256], usize);
+
+    impl fmt::Write for Out {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0[self.1..self.1 + s.len()].copy_from_slice(s.as_bytes());
+            self.1 += s.len();
+            Ok(())
+        }
+    }
+
+    let mut out = Out([0; 256], 0);
+    hexdump(b"Hello, world!\n\x00\x01\xff!", 0x1000, &mut out).unwrap();
+    assert_eq!(::core::str::from_utf8(&out.0[..out.1]).unwrap(),
+               "00001000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\r\n\
+                00001010  ff 21                                             |.!|\r\n");
+}
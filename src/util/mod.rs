@@ -12,8 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod crc32;
 mod device_path;
+mod dump;
+pub mod partition;
+mod sha256;
+pub mod ucs2;
+pub mod wire;
+pub use self::crc32::*;
 pub use self::device_path::*;
+pub use self::dump::*;
+pub use self::sha256::*;
 
 use core::slice;
 use core::str;
@@ -64,26 +73,37 @@ pub fn utf16_ptr_to_str(chars: *const u16) -> Result<&'static str, Status> {
     }
 }
 
-/// Convert a rust &str to a pointer to a UTF-16 string.
-/// Note: This function expects to receive a fully ASCII-compatible string. If it does not, it will
-/// fail.
+/// Character written in place of characters outside the Basic Multilingual Plane, which UCS-2
+/// cannot represent.
+pub const UCS2_REPLACEMENT_CHAR: u16 = 0xFFFD;
+
+/// Convert a character to UCS-2, as used by the firmware for strings. Characters which would need
+/// a surrogate pair in UTF-16 become `UCS2_REPLACEMENT_CHAR`.
+pub fn char_to_ucs2(c: char) -> u16 {
+    let c = c as u32;
+    if c > 0xFFFF {
+        UCS2_REPLACEMENT_CHAR
+    } else {
+        c as u16
+    }
+}
+
+/// Convert a rust &str to a pointer to a null-terminated UCS-2 string, allocated from pool memory.
+/// Characters outside the Basic Multilingual Plane are replaced with `UCS2_REPLACEMENT_CHAR`.
 pub fn str_to_utf16_ptr(chars: &str) -> Result<*const u16, Status> {
+    let len = chars.chars().count();
+
     ::get_system_table()
         .boot_services()
-        .allocate_pool(chars.len() + 1)
-        .and_then(|u16_ptr| {
+        .allocate_pool::<u16>((len + 1) * 2)
+        .map(|u16_ptr| {
             for (i, c) in chars.chars().enumerate() {
-                if c.len_utf8() > 1 {
-                    ::get_system_table().boot_services().free_pool(u16_ptr);
-                    return Err(Status::Unsupported);
-                }
-
                 unsafe {
-                    *(u16_ptr.offset(i as isize)) = c as u16;
+                    *u16_ptr.add(i) = char_to_ucs2(c);
                 }
             }
-            unsafe { *(u16_ptr.offset(chars.len() as isize)) = 0 };
+            unsafe { *u16_ptr.add(len) = 0 };
 
-            Ok(u16_ptr as *const u16)
+            u16_ptr as *const u16
         })
 }
@@ -0,0 +1,433 @@
+//! Reading partition tables from a disk's block I/O protocol: GPT, with its headers and entry
+//! array checked against their CRC32s and the backup used if the primary is damaged, and the
+//! legacy MBR.
+//!
+//! The firmware's partition driver already does this to make a handle for each partition; this
+//! is for tools that work on whole disks, such as installers and partitioners, and for
+//! firmware too old to report partition entries with `PartitionInfoProtocol`.
+//!
+//! ```rust,ignore
+//! let disk: &BlockIoProtocol = bs.handle_protocol(handle)?;
+//! let gpt = Gpt::read(disk)?;
+//! for partition in gpt.partitions() {
+//!     println!("{:?}", partition);
+//! }
+//! ```
+
+use core::{char, fmt, slice};
+
+use base::{MemoryType, Status};
+use bootservices::AllocateType;
+use guid::Guid;
+use memmap::EFI_PAGE_SIZE;
+use protocol::BlockIoProtocol;
+use systemtable::boot_services_available;
+use util::{crc32, wire};
+
+/// Signature at the start of a GPT header.
+pub const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// Size of the fields of a GPT header; the rest of its block is reserved.
+pub const GPT_HEADER_SIZE: usize = 92;
+
+/// Largest header size accepted. The header may fill its block, but no revision has yet made it
+/// longer than `GPT_HEADER_SIZE`.
+const MAX_GPT_HEADER_SIZE: usize = 512;
+
+/// Size of a GPT partition entry; entries may be larger, in multiples of it.
+pub const GPT_ENTRY_SIZE: usize = 128;
+
+/// Largest partition entry array read, in bytes. A standard table is 16 KiB.
+pub const MAX_GPT_ENTRIES_SIZE: usize = 1024 * 1024;
+
+/// UCS-2 units of a GPT partition name.
+pub const GPT_NAME_LEN: usize = 36;
+
+/// MBR partition type of the single partition covering a GPT disk.
+pub const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+/// Offset of the partition table in an MBR.
+const MBR_PARTITION_TABLE: usize = 446;
+
+/// The CRC32 of `data`, from the firmware while boot services are available, and in software
+/// otherwise.
+fn checksum(data: &[u8]) -> u32 {
+    if boot_services_available() {
+        if let Ok(crc) = ::get_system_table().boot_services().calculate_crc32(data) {
+            return crc;
+        }
+    }
+    crc32(data)
+}
+
+/// A GPT header, as found in the second block of a disk and, as a backup, in its last block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GptHeader {
+    pub revision: u32,
+    pub header_size: u32,
+    /// The block this header is in.
+    pub my_lba: u64,
+    /// The block the other copy of the header is in.
+    pub alternate_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: Guid,
+    pub partition_entry_lba: u64,
+    pub number_of_partition_entries: u32,
+    pub size_of_partition_entry: u32,
+    pub partition_entry_array_crc32: u32,
+}
+
+impl GptHeader {
+    /// Parse and check the header at the start of `block`, which was read from block `lba`.
+    /// Fails with `Status::CrcError` if its CRC doesn't match and `Status::VolumeCorrupted` if
+    /// it isn't a GPT header or doesn't describe a usable entry array.
+    pub fn parse(block: &[u8], lba: u64) -> Result<GptHeader, Status> {
+        if block.get(..8) != Some(&GPT_SIGNATURE[..]) {
+            return Err(Status::VolumeCorrupted);
+        }
+        let header_size = wire::read_u32(block, 12)? as usize;
+        if !(GPT_HEADER_SIZE..=MAX_GPT_HEADER_SIZE).contains(&header_size) || header_size > block.len() {
+            return Err(Status::VolumeCorrupted);
+        }
+
+        // The CRC is taken with its own field as zero.
+        let mut copy = [0u8; MAX_GPT_HEADER_SIZE];
+        copy[..header_size].copy_from_slice(&block[..header_size]);
+        wire::write_u32(&mut copy, 16, 0)?;
+        if checksum(&copy[..header_size]) != wire::read_u32(block, 16)? {
+            return Err(Status::CrcError);
+        }
+
+        let header = GptHeader {
+            revision: wire::read_u32(block, 8)?,
+            header_size: header_size as u32,
+            my_lba: wire::read_u64(block, 24)?,
+            alternate_lba: wire::read_u64(block, 32)?,
+            first_usable_lba: wire::read_u64(block, 40)?,
+            last_usable_lba: wire::read_u64(block, 48)?,
+            disk_guid: wire::read_guid(block, 56)?,
+            partition_entry_lba: wire::read_u64(block, 72)?,
+            number_of_partition_entries: wire::read_u32(block, 80)?,
+            size_of_partition_entry: wire::read_u32(block, 84)?,
+            partition_entry_array_crc32: wire::read_u32(block, 88)?,
+        };
+        let entry_size = header.size_of_partition_entry as usize;
+        if header.my_lba != lba
+            || header.first_usable_lba > header.last_usable_lba
+            || header.number_of_partition_entries == 0
+            || entry_size < GPT_ENTRY_SIZE
+            || entry_size % GPT_ENTRY_SIZE != 0
+            || header.entries_size() > MAX_GPT_ENTRIES_SIZE {
+            return Err(Status::VolumeCorrupted);
+        }
+        Ok(header)
+    }
+
+    /// Size of the partition entry array in bytes.
+    pub fn entries_size(&self) -> usize {
+        self.number_of_partition_entries as usize * self.size_of_partition_entry as usize
+    }
+
+    /// Check `entries`, the partition entry array, against the CRC32 in the header.
+    pub fn check_entries(&self, entries: &[u8]) -> Result<(), Status> {
+        let entries = entries.get(..self.entries_size()).ok_or(Status::BadBufferSize)?;
+        if checksum(entries) != self.partition_entry_array_crc32 {
+            return Err(Status::CrcError);
+        }
+        Ok(())
+    }
+
+    /// The used entries of `entries`, the partition entry array this header describes.
+    pub fn partitions<'a>(&self, entries: &'a [u8]) -> GptPartitions<'a> {
+        let len = self.entries_size().min(entries.len());
+        GptPartitions { entries: &entries[..len], entry_size: self.size_of_partition_entry as usize, next: 0 }
+    }
+}
+
+/// A used entry of a GPT partition entry array.
+#[derive(Clone, Copy)]
+pub struct GptPartition {
+    /// What the partition holds, such as `EFI_SYSTEM_PARTITION_GUID`.
+    pub partition_type: Guid,
+    /// The partition's own GUID, which device paths find it by.
+    pub unique_partition_guid: Guid,
+    pub starting_lba: u64,
+    /// The last block of the partition, which is in it.
+    pub ending_lba: u64,
+    pub attributes: u64,
+    name: [u16; GPT_NAME_LEN],
+}
+
+impl GptPartition {
+    /// Parse the entry at the start of `entry`, or `None` if it is unused.
+    pub fn parse(entry: &[u8]) -> Result<Option<GptPartition>, Status> {
+        let partition_type = wire::read_guid(entry, 0)?;
+        if partition_type == Guid(0, 0, 0, [0; 8]) {
+            return Ok(None);
+        }
+
+        let mut partition = GptPartition {
+            partition_type,
+            unique_partition_guid: wire::read_guid(entry, 16)?,
+            starting_lba: wire::read_u64(entry, 32)?,
+            ending_lba: wire::read_u64(entry, 40)?,
+            attributes: wire::read_u64(entry, 48)?,
+            name: [0; GPT_NAME_LEN],
+        };
+        for (i, c) in partition.name.iter_mut().enumerate() {
+            *c = wire::read_u16(entry, 56 + i * 2)?;
+        }
+        Ok(Some(partition))
+    }
+
+    /// The partition's name as UCS-2 units, without a terminator.
+    pub fn name(&self) -> &[u16] {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(GPT_NAME_LEN);
+        &self.name[..len]
+    }
+
+    /// Number of blocks in the partition.
+    pub fn blocks(&self) -> u64 {
+        (self.ending_lba + 1).saturating_sub(self.starting_lba)
+    }
+}
+
+impl fmt::Debug for GptPartition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GptPartition {{ name: \"")?;
+        for c in char::decode_utf16(self.name().iter().cloned()) {
+            write!(f, "{}", c.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        write!(f, "\", partition_type: {}, unique_partition_guid: {}, starting_lba: {}, ending_lba: {} }}",
+               self.partition_type, self.unique_partition_guid, self.starting_lba, self.ending_lba)
+    }
+}
+
+/// Iterator over the used entries of a partition entry array, from `GptHeader::partitions` or
+/// `Gpt::partitions`. Entries too short to parse end it.
+pub struct GptPartitions<'a> {
+    entries: &'a [u8],
+    entry_size: usize,
+    next: usize,
+}
+
+impl<'a> Iterator for GptPartitions<'a> {
+    type Item = GptPartition;
+
+    fn next(&mut self) -> Option<GptPartition> {
+        while let Some(entry) = self.entries.get(self.next..) {
+            if entry.is_empty() {
+                break;
+            }
+            self.next += self.entry_size;
+            match GptPartition::parse(entry) {
+                Ok(Some(partition)) => return Some(partition),
+                Ok(None) => continue,
+                Err(_) => break,
+            }
+        }
+        self.next = self.entries.len();
+        None
+    }
+}
+
+/// Pages read from a disk, freed on drop. Pages are aligned beyond what any block device asks.
+struct DiskBuffer {
+    address: u64,
+    pages: usize,
+}
+
+impl DiskBuffer {
+    /// Read `len` bytes, rounded up to whole blocks, from block `lba` on.
+    fn read(disk: &BlockIoProtocol, lba: u64, len: usize) -> Result<DiskBuffer, Status> {
+        let block_size = disk.media().block_size as usize;
+        let len = (len + block_size - 1) / block_size * block_size;
+        let pages = (len + EFI_PAGE_SIZE as usize - 1) / EFI_PAGE_SIZE as usize;
+        let bs = ::get_system_table().boot_services();
+        let address = bs.allocate_pages(AllocateType::AnyPages, MemoryType::LoaderData, pages)?;
+
+        let buffer = DiskBuffer { address, pages };
+        disk.read_blocks(lba, unsafe { slice::from_raw_parts_mut(address as usize as *mut u8, len) })?;
+        Ok(buffer)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.address as usize as *const u8, self.pages * EFI_PAGE_SIZE as usize) }
+    }
+}
+
+impl Drop for DiskBuffer {
+    fn drop(&mut self) {
+        ::get_system_table().boot_services().free_pages(self.address, self.pages);
+    }
+}
+
+/// A disk's GPT: a checked header and its checked partition entry array.
+pub struct Gpt {
+    header: GptHeader,
+    entries: DiskBuffer,
+    backup: bool,
+}
+
+impl Gpt {
+    /// Read the GPT of `disk`, which should be a whole disk rather than a partition. If the
+    /// primary header or entry array is damaged, the backup at the end of the disk is used.
+    /// Fails with the primary's error if both are unusable, `Status::VolumeCorrupted` for a disk
+    /// without a GPT.
+    pub fn read(disk: &BlockIoProtocol) -> Result<Gpt, Status> {
+        let media = disk.media();
+        if (media.block_size as usize) < GPT_HEADER_SIZE {
+            return Err(Status::Unsupported);
+        }
+        Gpt::read_at(disk, 1, false).or_else(|e| Gpt::read_at(disk, media.last_block, true).map_err(|_| e))
+    }
+
+    fn read_at(disk: &BlockIoProtocol, lba: u64, backup: bool) -> Result<Gpt, Status> {
+        let header = {
+            let block = DiskBuffer::read(disk, lba, disk.media().block_size as usize)?;
+            GptHeader::parse(block.as_slice(), lba)?
+        };
+        let entries = DiskBuffer::read(disk, header.partition_entry_lba, header.entries_size())?;
+        header.check_entries(entries.as_slice())?;
+        Ok(Gpt { header, entries, backup })
+    }
+
+    pub fn header(&self) -> &GptHeader {
+        &self.header
+    }
+
+    /// Whether the backup was read because the primary is damaged, which a partitioner should
+    /// offer to repair.
+    pub fn is_backup(&self) -> bool {
+        self.backup
+    }
+
+    /// The partition entry array.
+    pub fn entries(&self) -> &[u8] {
+        &self.entries.as_slice()[..self.header.entries_size()]
+    }
+
+    /// The used partition entries.
+    pub fn partitions(&self) -> GptPartitions<'_> {
+        self.header.partitions(self.entries())
+    }
+}
+
+/// An entry of the partition table of an MBR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MbrPartition {
+    pub bootable: bool,
+    /// The partition type, such as `MBR_TYPE_EFI_SYSTEM_PARTITION`.
+    pub os_type: u8,
+    pub starting_lba: u32,
+    pub size_in_lba: u32,
+}
+
+/// A legacy master boot record: the first block of a disk partitioned the PC BIOS way, and of a
+/// GPT disk, where it protects the GPT from tools that don't know it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mbr {
+    pub disk_signature: u32,
+    /// The four primary partitions, `None` where unused.
+    pub partitions: [Option<MbrPartition>; 4],
+}
+
+impl Mbr {
+    /// Parse the MBR at the start of `block`, failing with `Status::VolumeCorrupted` if it
+    /// doesn't end with the boot signature.
+    pub fn parse(block: &[u8]) -> Result<Mbr, Status> {
+        if block.get(510..512) != Some(&[0x55, 0xAA][..]) {
+            return Err(Status::VolumeCorrupted);
+        }
+
+        let mut mbr = Mbr { disk_signature: wire::read_u32(block, 440)?, partitions: [None; 4] };
+        for (i, partition) in mbr.partitions.iter_mut().enumerate() {
+            let entry = MBR_PARTITION_TABLE + i * 16;
+            let os_type = wire::read_u8(block, entry + 4)?;
+            let size_in_lba = wire::read_u32(block, entry + 12)?;
+            if os_type != 0 && size_in_lba != 0 {
+                *partition = Some(MbrPartition {
+                    bootable: wire::read_u8(block, entry)? == 0x80,
+                    os_type,
+                    starting_lba: wire::read_u32(block, entry + 8)?,
+                    size_in_lba,
+                });
+            }
+        }
+        Ok(mbr)
+    }
+
+    /// Read the MBR of `disk`.
+    pub fn read(disk: &BlockIoProtocol) -> Result<Mbr, Status> {
+        let block = DiskBuffer::read(disk, 0, 512)?;
+        Mbr::parse(block.as_slice())
+    }
+
+    /// Whether this is the protective MBR of a GPT disk, so the partitions are in the GPT.
+    pub fn is_protective(&self) -> bool {
+        self.partitions.iter().flatten().any(|p| p.os_type == MBR_TYPE_GPT_PROTECTIVE)
+    }
+}
+
+#[test]
+fn gpt_parsing() {
+    let esp = Guid(0xC12A7328, 0xF81F, 0x11D2, [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B]);
+    let unique = Guid(0x12345678, 0x9ABC, 0xDEF0, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+    let mut entries = [0u8; GPT_ENTRY_SIZE * 4];
+    wire::write_guid(&mut entries, GPT_ENTRY_SIZE, &esp).unwrap();
+    wire::write_guid(&mut entries, GPT_ENTRY_SIZE + 16, &unique).unwrap();
+    wire::write_u64(&mut entries, GPT_ENTRY_SIZE + 32, 2048).unwrap();
+    wire::write_u64(&mut entries, GPT_ENTRY_SIZE + 40, 206847).unwrap();
+    wire::write_ucs2(&mut entries, GPT_ENTRY_SIZE + 56, "EFI".encode_utf16()).unwrap();
+
+    let mut block = [0u8; 512];
+    block[..8].copy_from_slice(GPT_SIGNATURE);
+    wire::write_u32(&mut block, 8, 0x10000).unwrap();
+    wire::write_u32(&mut block, 12, GPT_HEADER_SIZE as u32).unwrap();
+    wire::write_u64(&mut block, 24, 1).unwrap();
+    wire::write_u64(&mut block, 32, 999).unwrap();
+    wire::write_u64(&mut block, 40, 34).unwrap();
+    wire::write_u64(&mut block, 48, 966).unwrap();
+    wire::write_u64(&mut block, 72, 2).unwrap();
+    wire::write_u32(&mut block, 80, 4).unwrap();
+    wire::write_u32(&mut block, 84, GPT_ENTRY_SIZE as u32).unwrap();
+    wire::write_u32(&mut block, 88, crc32(&entries)).unwrap();
+    let crc = crc32(&block[..GPT_HEADER_SIZE]);
+    wire::write_u32(&mut block, 16, crc).unwrap();
+
+    let header = GptHeader::parse(&block, 1).unwrap();
+    assert_eq!(header.alternate_lba, 999);
+    assert_eq!(header.check_entries(&entries), Ok(()));
+    assert_eq!(GptHeader::parse(&block, 999), Err(Status::VolumeCorrupted));
+
+    let mut partitions = header.partitions(&entries);
+    let partition = partitions.next().unwrap();
+    assert_eq!(partition.partition_type, esp);
+    assert_eq!(partition.unique_partition_guid, unique);
+    assert_eq!(partition.blocks(), 204800);
+    assert_eq!(partition.name(), &[b'E' as u16, b'F' as u16, b'I' as u16]);
+    assert!(partitions.next().is_none());
+
+    entries[GPT_ENTRY_SIZE + 32] ^= 1;
+    assert_eq!(header.check_entries(&entries), Err(Status::CrcError));
+    block[40] ^= 1;
+    assert_eq!(GptHeader::parse(&block, 1), Err(Status::CrcError));
+}
+
+#[test]
+fn mbr_parsing() {
+    let mut block = [0u8; 512];
+    assert_eq!(Mbr::parse(&block), Err(Status::VolumeCorrupted));
+
+    block[510] = 0x55;
+    block[511] = 0xAA;
+    block[MBR_PARTITION_TABLE + 4] = MBR_TYPE_GPT_PROTECTIVE;
+    wire::write_u32(&mut block, MBR_PARTITION_TABLE + 8, 1).unwrap();
+    wire::write_u32(&mut block, MBR_PARTITION_TABLE + 12, 0xFFFFFFFF).unwrap();
+    let mbr = Mbr::parse(&block).unwrap();
+    assert!(mbr.is_protective());
+    assert_eq!(mbr.partitions[0], Some(MbrPartition { bootable: false, os_type: MBR_TYPE_GPT_PROTECTIVE, starting_lba: 1, size_in_lba: 0xFFFFFFFF }));
+    assert_eq!(mbr.partitions[1], None);
+}
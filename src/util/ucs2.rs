@@ -0,0 +1,122 @@
+//! UCS-2 strings in fixed buffers, for the variable names, file paths and other strings that
+//! firmware interfaces take, without allocating pool memory for them.
+
+use core::fmt;
+
+use base::Status;
+use util::char_to_ucs2;
+
+struct Ucs2Writer<'a> {
+    buf: &'a mut [u16],
+    len: usize,
+}
+
+impl<'a> fmt::Write for Ucs2Writer<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            // Keep a unit free for the terminator.
+            if self.len + 1 >= self.buf.len() {
+                return Err(fmt::Error);
+            }
+            self.buf[self.len] = char_to_ucs2(c);
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Format `args` into `buf` as a null-terminated UCS-2 string, returning its length without the
+/// terminator. Characters outside the Basic Multilingual Plane are replaced. Fails with
+/// `Status::BufferTooSmall` if the string and terminator do not fit.
+///
+/// ```rust,ignore
+/// let mut name = [0u16; 16];
+/// ucs2::write_fmt(&mut name, format_args!("Boot{:04X}", 3))?;
+/// ```
+pub fn write_fmt(buf: &mut [u16], args: fmt::Arguments) -> Result<usize, Status> {
+    if buf.is_empty() {
+        return Err(Status::BufferTooSmall);
+    }
+
+    let mut writer = Ucs2Writer { buf, len: 0 };
+    let result = fmt::write(&mut writer, args);
+    let len = writer.len;
+    buf[len] = 0;
+    match result {
+        Ok(()) => Ok(len),
+        Err(_) => Err(Status::BufferTooSmall),
+    }
+}
+
+/// Number of UCS-2 units in `s`, without a terminator. Used by `ucs2!`.
+pub const fn len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    let mut n = 0;
+    while i < bytes.len() {
+        // Count every byte which is not a UTF-8 continuation byte.
+        if bytes[i] & 0xC0 != 0x80 {
+            n += 1;
+        }
+        i += 1;
+    }
+    n
+}
+
+/// Encode `s` as a null-terminated UCS-2 array of `N` units, which must be `len(s) + 1`. Used
+/// by `ucs2!`, where a string outside the Basic Multilingual Plane or with a nul in it is a
+/// compile error.
+pub const fn encode<const N: usize>(s: &str) -> [u16; N] {
+    let bytes = s.as_bytes();
+    let mut out = [0u16; N];
+    let mut i = 0;
+    let mut n = 0;
+    while i < bytes.len() {
+        let b = bytes[i] as u32;
+        let (c, width) = if b < 0x80 {
+            (b, 1)
+        } else if b < 0xE0 {
+            ((b & 0x1F) << 6 | (bytes[i + 1] & 0x3F) as u32, 2)
+        } else if b < 0xF0 {
+            ((b & 0x0F) << 12 | ((bytes[i + 1] & 0x3F) as u32) << 6 | (bytes[i + 2] & 0x3F) as u32, 3)
+        } else {
+            panic!("ucs2! strings must be in the Basic Multilingual Plane");
+        };
+        if c == 0 {
+            panic!("ucs2! strings must not contain nul");
+        }
+
+        out[n] = c as u16;
+        n += 1;
+        i += width;
+    }
+    if n + 1 != N {
+        panic!("ucs2::encode length must be the string length plus one");
+    }
+    out
+}
+
+/// A string literal as a static null-terminated UCS-2 array, encoded at compile time.
+///
+/// ```rust,ignore
+/// static BOOT_ORDER: &[u16] = ucs2!("BootOrder");
+/// ```
+#[macro_export]
+macro_rules! ucs2 {
+    ($s:expr) => {{
+        const S: &str = $s;
+        static UCS2: [u16; $crate::util::ucs2::len(S) + 1] = $crate::util::ucs2::encode(S);
+        &UCS2
+    }};
+}
+
+#[test]
+fn ucs2_strings() {
+    let name: &[u16] = ucs2!("Boot\u{e9}");
+    assert_eq!(name, &[0x42, 0x6F, 0x6F, 0x74, 0xE9, 0]);
+
+    let mut buf = [0xFFFFu16; 10];
+    assert_eq!(write_fmt(&mut buf, format_args!("Boot{:04X}", 0x1A)), Ok(8));
+    assert_eq!(&buf[..9], ucs2!("Boot001A"));
+    assert_eq!(write_fmt(&mut buf[..8], format_args!("Boot{:04X}", 0x1A)), Err(Status::BufferTooSmall));
+}
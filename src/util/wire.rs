@@ -0,0 +1,184 @@
+//! Little-endian readers and writers over byte slices, for the packed structures UEFI stores in
+//! memory and on disk (device path nodes, GPT entries, load options and so on).
+//!
+//! Fields of these structures are frequently misaligned, so they are never accessed through
+//! typed pointers; every access copies bytes and checks bounds, returning
+//! `Status::BufferTooSmall` if the field would run past the end of the slice.
+
+use base::Status;
+use guid::Guid;
+
+fn field(buf: &[u8], offset: usize, len: usize) -> Result<&[u8], Status> {
+    offset.checked_add(len)
+        .and_then(|end| buf.get(offset..end))
+        .ok_or(Status::BufferTooSmall)
+}
+
+fn field_mut(buf: &mut [u8], offset: usize, len: usize) -> Result<&mut [u8], Status> {
+    offset.checked_add(len)
+        .and_then(move |end| buf.get_mut(offset..end))
+        .ok_or(Status::BufferTooSmall)
+}
+
+pub fn read_u8(buf: &[u8], offset: usize) -> Result<u8, Status> {
+    buf.get(offset).cloned().ok_or(Status::BufferTooSmall)
+}
+
+pub fn read_u16(buf: &[u8], offset: usize) -> Result<u16, Status> {
+    let b = field(buf, offset, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+pub fn read_u32(buf: &[u8], offset: usize) -> Result<u32, Status> {
+    let mut b = [0; 4];
+    b.copy_from_slice(field(buf, offset, 4)?);
+    Ok(u32::from_le_bytes(b))
+}
+
+pub fn read_u64(buf: &[u8], offset: usize) -> Result<u64, Status> {
+    let mut b = [0; 8];
+    b.copy_from_slice(field(buf, offset, 8)?);
+    Ok(u64::from_le_bytes(b))
+}
+
+/// Read a GUID in its in-memory layout: the first three fields little-endian, then eight bytes.
+pub fn read_guid(buf: &[u8], offset: usize) -> Result<Guid, Status> {
+    let b = field(buf, offset, 16)?;
+    let mut d4 = [0; 8];
+    d4.copy_from_slice(&b[8..]);
+    Ok(Guid(read_u32(b, 0)?, read_u16(b, 4)?, read_u16(b, 6)?, d4))
+}
+
+/// The UCS-2 code units of a null-terminated string at `offset`, without the terminator. The
+/// iterator also stops at the end of `buf` if no terminator is found.
+pub fn read_ucs2(buf: &[u8], offset: usize) -> Ucs2Units<'_> {
+    Ucs2Units { buf, offset }
+}
+
+/// Iterator returned by `read_ucs2`.
+#[derive(Clone, Debug)]
+pub struct Ucs2Units<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for Ucs2Units<'a> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        match read_u16(self.buf, self.offset) {
+            Ok(0) | Err(_) => None,
+            Ok(c) => {
+                self.offset += 2;
+                Some(c)
+            }
+        }
+    }
+}
+
+pub fn write_u8(buf: &mut [u8], offset: usize, value: u8) -> Result<(), Status> {
+    *buf.get_mut(offset).ok_or(Status::BufferTooSmall)? = value;
+    Ok(())
+}
+
+pub fn write_u16(buf: &mut [u8], offset: usize, value: u16) -> Result<(), Status> {
+    field_mut(buf, offset, 2)?.copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+pub fn write_u32(buf: &mut [u8], offset: usize, value: u32) -> Result<(), Status> {
+    field_mut(buf, offset, 4)?.copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+pub fn write_u64(buf: &mut [u8], offset: usize, value: u64) -> Result<(), Status> {
+    field_mut(buf, offset, 8)?.copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+pub fn write_guid(buf: &mut [u8], offset: usize, guid: &Guid) -> Result<(), Status> {
+    let b = field_mut(buf, offset, 16)?;
+    b[0..4].copy_from_slice(&guid.0.to_le_bytes());
+    b[4..6].copy_from_slice(&guid.1.to_le_bytes());
+    b[6..8].copy_from_slice(&guid.2.to_le_bytes());
+    b[8..].copy_from_slice(&guid.3);
+    Ok(())
+}
+
+/// Write `units` followed by a null terminator at `offset`, returning the number of bytes
+/// written. Nothing is written if the string does not fit.
+pub fn write_ucs2<I>(buf: &mut [u8], offset: usize, units: I) -> Result<usize, Status>
+    where I: Iterator<Item = u16> + Clone
+{
+    let len = (units.clone().count() + 1) * 2;
+    let b = field_mut(buf, offset, len)?;
+    for (i, c) in units.chain(Some(0)).enumerate() {
+        b[i * 2..i * 2 + 2].copy_from_slice(&c.to_le_bytes());
+    }
+    Ok(len)
+}
+
+#[test]
+fn integers_round_trip() {
+    let mut buf = [0xAA; 16];
+    write_u8(&mut buf, 0, 0x12).unwrap();
+    write_u16(&mut buf, 1, 0x1234).unwrap();
+    write_u32(&mut buf, 3, 0x1234_5678).unwrap();
+    write_u64(&mut buf, 7, 0x0102_0304_0506_0708).unwrap();
+    assert_eq!(buf, [0x12, 0x34, 0x12, 0x78, 0x56, 0x34, 0x12, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0xAA]);
+
+    assert_eq!(read_u8(&buf, 0), Ok(0x12));
+    assert_eq!(read_u16(&buf, 1), Ok(0x1234));
+    assert_eq!(read_u32(&buf, 3), Ok(0x1234_5678));
+    assert_eq!(read_u64(&buf, 7), Ok(0x0102_0304_0506_0708));
+    // Fields ending exactly at the end of the buffer.
+    assert_eq!(read_u64(&buf, 8), Ok(0xAA01_0203_0405_0607));
+    assert_eq!(read_u8(&buf, 15), Ok(0xAA));
+}
+
+#[test]
+fn guid_round_trip() {
+    let guid = Guid(0x964E5B22, 0x6459, 0x11D2, [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+    let mut buf = [0xAA; 20];
+    write_guid(&mut buf, 3, &guid).unwrap();
+    assert_eq!(&buf[3..19], &[0x22, 0x5B, 0x4E, 0x96, 0x59, 0x64, 0xD2, 0x11,
+                              0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B]);
+    assert_eq!(read_guid(&buf, 3), Ok(guid));
+    assert_eq!(read_guid(&buf, 5), Err(Status::BufferTooSmall));
+    assert_eq!(write_guid(&mut buf, 5, &guid), Err(Status::BufferTooSmall));
+}
+
+#[test]
+fn ucs2_round_trip() {
+    let mut buf = [0xAA; 12];
+    assert_eq!(write_ucs2(&mut buf, 2, "EFI".encode_utf16()), Ok(8));
+    assert_eq!(&buf[2..10], b"E\0F\0I\0\0\0");
+    assert!(read_ucs2(&buf, 2).eq("EFI".encode_utf16()));
+    assert_eq!(write_ucs2(&mut buf, 10, "".encode_utf16()), Ok(2));
+    assert_eq!(read_ucs2(&buf, 10).count(), 0);
+
+    // Nothing is written if the terminator doesn't fit.
+    let mut short = [0xAA; 6];
+    assert_eq!(write_ucs2(&mut short, 0, "EFI".encode_utf16()), Err(Status::BufferTooSmall));
+    assert_eq!(short, [0xAA; 6]);
+    // Without a terminator, reading stops at the end of the buffer, ignoring an odd last byte.
+    assert!(read_ucs2(b"E\0F\0I", 0).eq("EF".encode_utf16()));
+    assert_eq!(read_ucs2(b"E\0", 4).count(), 0);
+}
+
+#[test]
+fn out_of_bounds() {
+    let mut buf = [0; 8];
+    assert_eq!(read_u8(&buf, 8), Err(Status::BufferTooSmall));
+    assert_eq!(read_u16(&buf, 7), Err(Status::BufferTooSmall));
+    assert_eq!(read_u32(&buf, 5), Err(Status::BufferTooSmall));
+    assert_eq!(read_u64(&buf, 1), Err(Status::BufferTooSmall));
+    assert_eq!(write_u8(&mut buf, 8, 0), Err(Status::BufferTooSmall));
+    assert_eq!(write_u16(&mut buf, 7, 0), Err(Status::BufferTooSmall));
+    assert_eq!(write_u64(&mut buf, 1, 0), Err(Status::BufferTooSmall));
+    // Offsets so large that the end of the field overflows.
+    assert_eq!(read_u32(&buf, usize::MAX), Err(Status::BufferTooSmall));
+    assert_eq!(write_u32(&mut buf, usize::MAX, 0), Err(Status::BufferTooSmall));
+    assert_eq!(read_guid(&buf, usize::MAX - 8), Err(Status::BufferTooSmall));
+    assert_eq!(buf, [0; 8]);
+}
@@ -0,0 +1,237 @@
+//! Checks on names and paths before they are handed to firmware, which answers most mistakes
+//! with a bare `InvalidParameter`, if it notices them at all. The errors here say what is wrong
+//! and where, and convert to the `Status` the firmware would have given.
+
+use core::{fmt, str};
+
+use base::Status;
+use protocol::MAX_FILE_PATH;
+use runtimeservices::MAX_VARIABLE_NAME;
+
+/// Longest name of one file or directory, in UCS-2 units, on FAT with long file names.
+pub const MAX_PATH_COMPONENT: usize = 255;
+
+/// Characters FAT file names cannot contain, besides control characters.
+const FORBIDDEN_PATH_CHARACTERS: &str = "\"*:<>?|";
+
+/// Why a name or path was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// Nothing was given.
+    Empty,
+    /// More than `max` UCS-2 units, without the terminator.
+    TooLong { max: usize },
+    /// A character at this byte offset is outside the Basic Multilingual Plane, so has no
+    /// UCS-2 encoding.
+    NotUcs2 { position: usize },
+    /// A nul at this byte offset, which would end the string early.
+    Nul { position: usize },
+    /// A character file names cannot contain, at this byte offset.
+    ForbiddenCharacter { character: char, position: usize },
+    /// The path component starting at this byte offset is longer than `MAX_PATH_COMPONENT`.
+    ComponentTooLong { position: usize },
+    /// Device path text that doesn't parse as nodes like `PciRoot(0x0)/Pci(0x1,0x0)`, going
+    /// wrong at this byte offset.
+    MalformedDevicePath { position: usize },
+    /// Text that isn't a GUID in canonical form, going wrong at this byte offset.
+    MalformedGuid { position: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::Empty => write!(f, "empty"),
+            ValidationError::TooLong { max } => write!(f, "longer than {} characters", max),
+            ValidationError::NotUcs2 { position } => write!(f, "character at {} cannot be encoded as UCS-2", position),
+            ValidationError::Nul { position } => write!(f, "nul character at {}", position),
+            ValidationError::ForbiddenCharacter { character, position } => {
+                write!(f, "character {:?} at {} is not allowed", character, position)
+            }
+            ValidationError::ComponentTooLong { position } => {
+                write!(f, "name at {} is longer than {} characters", position, MAX_PATH_COMPONENT)
+            }
+            ValidationError::MalformedDevicePath { position } => write!(f, "malformed device path at {}", position),
+            ValidationError::MalformedGuid { position } => write!(f, "malformed GUID at {}", position),
+        }
+    }
+}
+
+impl From<ValidationError> for Status {
+    fn from(_: ValidationError) -> Status {
+        Status::InvalidParameter
+    }
+}
+
+/// Check each character of `s` has a UCS-2 encoding and isn't nul, and that there are at most
+/// `max` of them.
+fn validate_ucs2(s: &str, max: usize) -> Result<(), ValidationError> {
+    if s.is_empty() {
+        return Err(ValidationError::Empty);
+    }
+    for (n, (position, c)) in s.char_indices().enumerate() {
+        if n == max {
+            return Err(ValidationError::TooLong { max });
+        }
+        if c == '\0' {
+            return Err(ValidationError::Nul { position });
+        }
+        if c as u32 > 0xFFFF {
+            return Err(ValidationError::NotUcs2 { position });
+        }
+    }
+    Ok(())
+}
+
+/// Check `name` can be the name of a UEFI variable, as `RuntimeServices::get_variable` and
+/// `set_variable` take it.
+pub fn validate_variable_name(name: &str) -> Result<(), ValidationError> {
+    validate_ucs2(name, MAX_VARIABLE_NAME)
+}
+
+/// Check `path` is a file path `FileProtocol::open_path` can open: at most `MAX_FILE_PATH` - 1
+/// characters, with names of at most `MAX_PATH_COMPONENT` characters that FAT allows. Either
+/// slash separates names, as `normalize_path` makes them backslashes.
+pub fn validate_path(path: &str) -> Result<(), ValidationError> {
+    validate_ucs2(path, usize::MAX)?;
+
+    let mut component_start = 0;
+    let mut component_len = 0;
+    for (position, c) in path.char_indices() {
+        if c == '\\' || c == '/' {
+            component_start = position + 1;
+            component_len = 0;
+            continue;
+        }
+        if (c as u32) < 0x20 || FORBIDDEN_PATH_CHARACTERS.contains(c) {
+            return Err(ValidationError::ForbiddenCharacter { character: c, position });
+        }
+        component_len += 1;
+        if component_len > MAX_PATH_COMPONENT {
+            return Err(ValidationError::ComponentTooLong { position: component_start });
+        }
+    }
+    validate_ucs2(path, MAX_FILE_PATH - 1)
+}
+
+/// Validate `path` and write it into `buf` as firmware expects it: with backslashes between
+/// names, none repeated, and none at the end unless the path is the root, `\`.
+///
+/// ```rust,ignore
+/// let mut buf = [0u8; 256];
+/// assert_eq!(normalize_path("/EFI//boot/", &mut buf)?, "\\EFI\\boot");
+/// ```
+pub fn normalize_path<'a>(path: &str, buf: &'a mut [u8]) -> Result<&'a str, ValidationError> {
+    validate_path(path)?;
+
+    let mut len = 0;
+    let mut previous = None;
+    for c in path.chars() {
+        let c = if c == '/' { '\\' } else { c };
+        if c == '\\' && previous == Some('\\') {
+            continue;
+        }
+        if len + c.len_utf8() > buf.len() {
+            return Err(ValidationError::TooLong { max: buf.len() });
+        }
+        len += c.encode_utf8(&mut buf[len..]).len();
+        previous = Some(c);
+    }
+    if len > 1 && previous == Some('\\') {
+        len -= 1;
+    }
+
+    // Only whole characters were copied in.
+    Ok(unsafe { str::from_utf8_unchecked(&buf[..len]) })
+}
+
+/// Check `text` has the shape of a device path in text form, as
+/// `DevicePathFromTextProtocol::text_to_device_path` takes: nodes like `Pci(0x1,0x0)` or
+/// `HD(1,GPT,...)` separated by slashes, optionally followed by a file path, with instances
+/// separated by commas at the top level. What each node says is left to the firmware.
+pub fn validate_device_path_text(text: &str) -> Result<(), ValidationError> {
+    validate_ucs2(text, usize::MAX)?;
+
+    let mut depth = 0usize;
+    let mut node_start = true;
+    for (position, c) in text.char_indices() {
+        match c {
+            '(' if node_start => return Err(ValidationError::MalformedDevicePath { position }),
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(ValidationError::MalformedDevicePath { position }),
+            ')' => depth -= 1,
+            '/' | ',' if depth == 0 => {
+                if node_start {
+                    return Err(ValidationError::MalformedDevicePath { position });
+                }
+                node_start = true;
+                continue;
+            }
+            _ => {}
+        }
+        node_start = false;
+    }
+    if depth != 0 || node_start {
+        return Err(ValidationError::MalformedDevicePath { position: text.len() });
+    }
+    Ok(())
+}
+
+#[test]
+fn variable_names() {
+    assert_eq!(validate_variable_name("BootOrder"), Ok(()));
+    assert_eq!(validate_variable_name(""), Err(ValidationError::Empty));
+    assert_eq!(validate_variable_name("Boot\u{1F600}"), Err(ValidationError::NotUcs2 { position: 4 }));
+    assert_eq!(validate_variable_name("a\0b"), Err(ValidationError::Nul { position: 1 }));
+
+    let long = [b'a'; MAX_VARIABLE_NAME + 1];
+    assert_eq!(validate_variable_name(str::from_utf8(&long[..MAX_VARIABLE_NAME]).unwrap()), Ok(()));
+    assert_eq!(validate_variable_name(str::from_utf8(&long).unwrap()), Err(ValidationError::TooLong { max: MAX_VARIABLE_NAME }));
+    assert_eq!(Status::from(ValidationError::Empty), Status::InvalidParameter);
+}
+
+#[test]
+fn paths() {
+    assert_eq!(validate_path("\\EFI\\BOOT\\BOOTX64.EFI"), Ok(()));
+    assert_eq!(validate_path("/EFI/Linux/\u{e9}t\u{e9}.efi"), Ok(()));
+    assert_eq!(validate_path(""), Err(ValidationError::Empty));
+    assert_eq!(validate_path("\\EFI\\a?b"), Err(ValidationError::ForbiddenCharacter { character: '?', position: 6 }));
+    assert_eq!(validate_path("\\EFI\\a\tb"), Err(ValidationError::ForbiddenCharacter { character: '\t', position: 6 }));
+
+    let mut long = [b'a'; 400];
+    assert_eq!(validate_path(str::from_utf8(&long[..MAX_PATH_COMPONENT]).unwrap()), Ok(()));
+    long[0] = b'\\';
+    let component = str::from_utf8(&long[..MAX_PATH_COMPONENT + 2]).unwrap();
+    assert_eq!(validate_path(component), Err(ValidationError::ComponentTooLong { position: 1 }));
+    // Short enough names, but too long a path.
+    long[200] = b'\\';
+    assert_eq!(validate_path(str::from_utf8(&long[..MAX_FILE_PATH - 1]).unwrap()), Ok(()));
+    assert_eq!(validate_path(str::from_utf8(&long[..MAX_FILE_PATH]).unwrap()), Err(ValidationError::TooLong { max: MAX_FILE_PATH - 1 }));
+}
+
+#[test]
+fn path_normalization() {
+    let mut buf = [0u8; 64];
+    assert_eq!(normalize_path("/EFI//boot/", &mut buf), Ok("\\EFI\\boot"));
+    assert_eq!(normalize_path("/", &mut buf), Ok("\\"));
+    assert_eq!(normalize_path("\\\\", &mut buf), Ok("\\"));
+    assert_eq!(normalize_path("grub.cfg", &mut buf), Ok("grub.cfg"));
+    assert_eq!(normalize_path("\\a*b", &mut buf), Err(ValidationError::ForbiddenCharacter { character: '*', position: 2 }));
+
+    // Stops at the end of the buffer rather than cutting a character in two.
+    let mut small = [0u8; 4];
+    assert_eq!(normalize_path("\\EFI", &mut small), Ok("\\EFI"));
+    assert_eq!(normalize_path("\\EF\u{e9}", &mut small), Err(ValidationError::TooLong { max: 4 }));
+}
+
+#[test]
+fn device_path_text() {
+    assert_eq!(validate_device_path_text("PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0)"), Ok(()));
+    assert_eq!(validate_device_path_text("HD(1,GPT,0C1A,0x800,0x100000)/\\EFI\\BOOT\\BOOTX64.EFI"), Ok(()));
+    assert_eq!(validate_device_path_text("PciRoot(0x0)/Pci(0x1,0x0),PciRoot(0x1)"), Ok(()));
+    assert_eq!(validate_device_path_text(""), Err(ValidationError::Empty));
+    assert_eq!(validate_device_path_text("PciRoot(0x0/Pci(0x1,0x0)"), Err(ValidationError::MalformedDevicePath { position: 24 }));
+    assert_eq!(validate_device_path_text("PciRoot(0x0)//Pci(0x1,0x0)"), Err(ValidationError::MalformedDevicePath { position: 13 }));
+    assert_eq!(validate_device_path_text("PciRoot(0x0))"), Err(ValidationError::MalformedDevicePath { position: 12 }));
+    assert_eq!(validate_device_path_text("PciRoot(0x0)/"), Err(ValidationError::MalformedDevicePath { position: 13 }));
+    assert_eq!(validate_device_path_text("(0x0)"), Err(ValidationError::MalformedDevicePath { position: 0 }));
+}
@@ -2,8 +2,17 @@ extern crate libc;
 use libc::{size_t, malloc, free};
 use std::mem;
 
+#[macro_use]
 extern crate uefi;
-use uefi::{Handle, Handles};
+use std::cell::RefCell;
+
+use uefi::{Handle, Handles, Language, Message, Status, Attribute, SimpleTextOutput};
+use uefi::{Align, Column, Table, ASCII_BOX_CHARS, BufWriter};
+use uefi::{Args, GetOpt, Opt, OptSpec};
+use uefi::{Flash, hash_region};
+use uefi::{EfiError, ResultExt, ERROR_CONTEXT_SIZE};
+use uefi::util::{sha256, Sha256};
+use uefi::protocol::{DevicePath, DevicePathNode};
 
 #[test]
 fn handle_iterator() {
@@ -31,3 +40,179 @@ fn handle_iterator() {
         }
 }
 
+
+static BOOT: Message = message!("en" => "Boot", "de" => "Starten", "fr-CA" => "Amorcer");
+
+#[test]
+fn message_fallback() {
+        assert_eq!(BOOT.get(&Language::new("de-AT")), "Starten");
+        assert_eq!(BOOT.get(&Language::new("FR-ca")), "Amorcer");
+        assert_eq!(BOOT.get(&Language::new("fr-FR")), "Boot");
+        assert_eq!(Language::new("de-AT").primary(), "de");
+}
+
+#[test]
+fn sha256_vectors() {
+        assert_eq!(sha256(b"abc")[..4], [0xba, 0x78, 0x16, 0xbf]);
+        assert_eq!(sha256(b"")[28..], [0x78, 0x52, 0xb8, 0x55]);
+
+        // Feeding in pieces across block boundaries must match the one-shot hash.
+        let data = [0x5au8; 200];
+        let mut hasher = Sha256::new();
+        hasher.update(&data[..63]);
+        hasher.update(&data[63..130]);
+        hasher.update(&data[130..]);
+        assert_eq!(hasher.finish(), sha256(&data));
+}
+
+struct Capture(RefCell<String>);
+
+impl SimpleTextOutput for Capture {
+        fn write_raw(&self, s: *const u16) -> Status {
+                let mut i = 0;
+                while unsafe { *s.add(i) } != 0 {
+                        self.0.borrow_mut().push(std::char::from_u32(unsafe { *s.add(i) } as u32).unwrap());
+                        i += 1;
+                }
+                Status::Success
+        }
+
+        fn set_attribute(&self, _: Attribute) -> Status {
+                Status::Success
+        }
+}
+
+#[test]
+fn table_layout() {
+        let out = Capture(RefCell::new(String::new()));
+        let columns = [Column::new("Type", 6, Align::Left), Column::new("Pages", 5, Align::Right)];
+        let table = Table::new(&columns, &ASCII_BOX_CHARS);
+
+        table.print_header(&out);
+        table.print_row(&out, &[&"LoaderCode", &12]);
+        table.print_footer(&out);
+
+        assert_eq!(*out.0.borrow(), "+--------+-------+\r\n\
+                                     | Type   | Pages |\r\n\
+                                     +--------+-------+\r\n\
+                                     | Loader |    12 |\r\n\
+                                     +--------+-------+\r\n");
+}
+
+#[test]
+fn buf_writer_flushes_on_newline() {
+        let out = BufWriter::new(Capture(RefCell::new(String::new())));
+
+        out.write("one, ");
+        out.write("two");
+        assert_eq!(*out.get_ref().0.borrow(), "");
+
+        out.write("\r\nthree");
+        assert_eq!(*out.get_ref().0.borrow(), "one, two\r\n");
+
+        let inner = out.into_inner();
+        assert_eq!(*inner.0.borrow(), "one, two\r\nthree");
+}
+
+static OPTS: [OptSpec; 3] = [
+        OptSpec { short: Some('v'), long: "verbose", takes_value: false },
+        OptSpec { short: Some('o'), long: "output", takes_value: true },
+        OptSpec { short: None, long: "timeout", takes_value: true },
+];
+
+#[test]
+fn getopt_parsing() {
+        let args = Args::parse(r#"-vofile --timeout=5 "two words" \EFI\boot -- -x"#).unwrap();
+        assert_eq!(args.len(), 6);
+        assert_eq!(args.get(2), Some("two words"));
+
+        let mut parsed = Vec::new();
+        for opt in GetOpt::new(&args, &OPTS) {
+                match opt.unwrap() {
+                        Opt::Option(spec, value) => parsed.push(format!("{}={:?}", spec.long, value)),
+                        Opt::Positional(arg) => parsed.push(arg.to_string()),
+                }
+        }
+        assert_eq!(parsed, ["verbose=None", "output=Some(\"file\")", "timeout=Some(\"5\")",
+                            "two words", "\\EFI\\boot", "-x"]);
+
+        let args = Args::parse("--output").unwrap();
+        assert!(GetOpt::new(&args, &OPTS).next().unwrap().is_err());
+}
+
+#[test]
+fn device_path_node_lengths() {
+        // A file path node for "a", followed by an end node.
+        let path = [0x04, 0x04, 0x08, 0x00, b'a', 0, 0, 0, 0x7F, 0xFF, 0x04, 0x00];
+
+        let node = DevicePathNode::from_bytes(&path).unwrap();
+        assert_eq!((node.node_type(), node.sub_type(), node.len()), (4, 4, 8));
+        assert_eq!(node.data(), &[b'a', 0, 0, 0]);
+        assert!(DevicePathNode::from_bytes(&path[8..]).unwrap().is_end());
+
+        // Lengths shorter than the header or longer than the buffer are refused.
+        assert_eq!(DevicePathNode::from_bytes(&[0x04, 0x04, 0x02, 0x00]).err(), Some(Status::InvalidParameter));
+        assert_eq!(DevicePathNode::from_bytes(&path[..6]).err(), Some(Status::InvalidParameter));
+        assert_eq!(DevicePathNode::from_bytes(&path[..3]).err(), Some(Status::InvalidParameter));
+}
+
+#[test]
+fn device_path_traversal() {
+        // Two file path nodes, then the end node and some trailing bytes.
+        let bytes = [0x04, 0x04, 0x06, 0x00, b'a', 0, 0x04, 0x04, 0x06, 0x00, b'b', 0, 0x7F, 0xFF, 0x04, 0x00, 0xAA];
+
+        let path = DevicePath::from_bytes(&bytes).unwrap();
+        assert_eq!(path.total_len(), 16);
+        let names: Vec<u8> = path.nodes().map(|node| node.data()[0]).collect();
+        assert_eq!(names, b"ab");
+
+        // A path whose second node runs past the buffer, or which has no end node, is refused.
+        assert_eq!(DevicePath::from_bytes(&bytes[..9]).err(), Some(Status::InvalidParameter));
+        assert_eq!(DevicePath::from_bytes(&bytes[..12]).err(), Some(Status::InvalidParameter));
+}
+
+#[test]
+fn flash_region_hash() {
+        struct Image(Vec<u8>);
+
+        impl Flash for Image {
+                fn size(&self) -> u64 {
+                        self.0.len() as u64
+                }
+
+                fn read(&self, offset: u64, buf: &mut [u8]) -> Result<(), Status> {
+                        let offset = offset as usize;
+                        buf.copy_from_slice(&self.0[offset..offset + buf.len()]);
+                        Ok(())
+                }
+        }
+
+        // Larger than one chunk, so the region is read in several pieces.
+        let image = Image((0..10000).map(|i| i as u8).collect());
+        assert_eq!(hash_region(&image, 100, 9000).unwrap(), sha256(&image.0[100..9100]));
+        assert_eq!(hash_region(&image, 100, 9901).err(), Some(Status::InvalidParameter));
+}
+
+#[test]
+fn efi_error_display() {
+        let err = Err::<(), Status>(Status::NotFound).context_with("LoadImage", "\\EFI\\foo.efi").unwrap_err();
+        assert_eq!(format!("{}", err), "LoadImage(\\EFI\\foo.efi) failed: not found");
+        assert_eq!(format!("{}", EfiError::new(Status::Timeout, "Connect")), "Connect failed: timeout");
+
+        let long = "x".repeat(300);
+        assert_eq!(EfiError::new(Status::NotFound, "Open").with_context(&long).context().map(str::len), Some(ERROR_CONTEXT_SIZE));
+}
+
+#[test]
+fn efi_assert_passing() {
+        // Only passing assertions, as reporting a failure needs the system table.
+        fn checks() -> Result<(), Status> {
+                efi_assert!(1 + 1 == 2);
+                efi_assert!(true, "message {}", 1);
+                efi_assert_eq!(Status::NotFound, Status::NotFound);
+                efi_assert_ne!(1, 2, "values differ");
+                Ok(())
+        }
+
+        assert_eq!(checks(), Ok(()));
+}